@@ -18,11 +18,14 @@
 #import "shaders/CaveMaterialExtension/types.wgsl"::VoxelMaterialOutput
 #import "shaders/CaveMaterialExtension/voxels.wgsl"::voxel_function_by_type
 #import "shaders/CaveMaterialExtension/utility.wgsl"::{
-    ease_in_out_sine_3d,
-    mix_two_voxels,
-    mix_three_voxels,
+    add_weighted_voxel,
+    ease_in_out_sine_4d,
+    flat_normal,
+    perturb_normal,
     reconstruct_pbr_vertex,
-    quantize_3d
+    quantize_3d,
+    quantize_4d,
+    triplanar_weights
 }
 
 #ifdef PREPASS_PIPELINE
@@ -40,56 +43,118 @@
 struct CaveMaterialExtension {
     render_voxel_size: f32,
     voxel_type_transition_steps: f32,
-
-#ifdef WEBGL2
-    _webgl2_padding: vec2<f32>,
-#endif
+    scan_radius: f32,
+    scan_intensity: f32,
+    scan_origin: vec3<f32>,
+    scan_materials: u32,
 }
 
 @group(2) @binding(100)
 var<uniform> cave_material: CaveMaterialExtension;
 
+const SCAN_COLOR: vec3<f32> = vec3(0.3, 1.0, 0.8);
+const SCAN_FRONT_WIDTH: f32 = 1.5;
+// Must match FIRST_SPECIAL_VOXEL_TYPE in cave.rs
+const FIRST_SPECIAL_VOXEL_TYPE: u32 = 252u;
+// Higher values narrow the blend between triplanar projections
+const TRIPLANAR_SHARPNESS: f32 = 4.0;
+// Offsets each projection so the planes don't mirror each other where they blend
+const TRIPLANAR_OFFSET: vec3<f32> = vec3(17.0, 53.0, 89.0);
+// Height of the bumps, from the brightness of the surface
+const BUMP_STRENGTH: f32 = 0.05;
+
+fn is_scan_material(voxel_type: u32) -> bool {
+    return voxel_type < 32u && (cave_material.scan_materials & (1u << voxel_type)) != 0u;
+}
+
+fn voxel_type_at(i: u32) -> u32 {
+    return select(FIRST_SPECIAL_VOXEL_TYPE + i - 4u, i, i < 4u);
+}
+
+// Each vertex has one voxel type, so at most three types are weighted
+fn blend_voxel_types(
+    pos: vec3<f32>,
+    fac: vec4<f32>,
+    special_fac: vec4<f32>,
+    total: f32,
+) -> VoxelMaterialOutput {
+    var voxel = VoxelMaterialOutput(vec3(0.0), 0.0, vec4(0.0));
+    for (var i = 0u; i < 8u; i++) {
+        let weight = select(special_fac[i % 4u], fac[i % 4u], i < 4u);
+        if weight > 0.0 {
+            let weighted = voxel_function_by_type(voxel_type_at(i), pos);
+            voxel = add_weighted_voxel(voxel, weighted, weight / total);
+        }
+    }
+    return voxel;
+}
+
 @fragment
 fn fragment(
     in: CaveVertexOutput,
     @builtin(front_facing) is_front: bool,
 ) -> FragmentOutput {
-    let quantized_pos = quantize_3d(in.world_position.xyz, cave_material.render_voxel_size);
-    let fac = quantize_3d(ease_in_out_sine_3d(in.voxel_ratio), cave_material.voxel_type_transition_steps);
-    var voxel: VoxelMaterialOutput;
-
-    if in.voxel_type[0] == in.voxel_type[1] {
-        let a = voxel_function_by_type(in.voxel_type[0], quantized_pos);
-
-        if in.voxel_type[0] == in.voxel_type[2] {
-            // Same voxel type for all vertices
-            voxel = a;
-        } else {
-            let b = voxel_function_by_type(in.voxel_type[2], quantized_pos);
-            voxel = mix_two_voxels(a, b, fac.z);
+    let steps = cave_material.voxel_type_transition_steps;
+    var fac = quantize_4d(ease_in_out_sine_4d(in.voxel_weights), steps);
+    var special_fac = quantize_4d(ease_in_out_sine_4d(in.special_voxel_weights), steps);
+    if dot(fac + special_fac, vec4(1.0)) <= 0.0 {
+        // Every weight was quantized away
+        fac = in.voxel_weights;
+        special_fac = in.special_voxel_weights;
+    }
+    let total = dot(fac + special_fac, vec4(1.0));
+
+    var valuable = false;
+    for (var i = 0u; i < 8u; i++) {
+        let raw_weight = select(in.special_voxel_weights[i % 4u], in.voxel_weights[i % 4u], i < 4u);
+        if raw_weight > 0.0 && is_scan_material(voxel_type_at(i)) {
+            valuable = true;
         }
-    } else if in.voxel_type[0] == in.voxel_type[2] {
-        let a = voxel_function_by_type(in.voxel_type[0], quantized_pos);
-        let b = voxel_function_by_type(in.voxel_type[1], quantized_pos);
-        voxel = mix_two_voxels(a, b, fac.y);
-    } else if in.voxel_type[1] == in.voxel_type[2] {
-        let a = voxel_function_by_type(in.voxel_type[1], quantized_pos);
-        let b = voxel_function_by_type(in.voxel_type[0], quantized_pos);
-        voxel = mix_two_voxels(a, b, fac.x);
-    } else {
-        // Different voxel type for all vertices
-        let a = voxel_function_by_type(in.voxel_type[0], quantized_pos);
-        let b = voxel_function_by_type(in.voxel_type[1], quantized_pos);
-        let c = voxel_function_by_type(in.voxel_type[2], quantized_pos);
-        voxel = mix_three_voxels(a, b, c, fac);
     }
 
-    let pbr_vertex = reconstruct_pbr_vertex(in);
+    let world_position = in.world_position.xyz;
+    var normal = flat_normal(world_position);
+#ifdef CAVE_TRIPLANAR
+    let size = cave_material.render_voxel_size;
+    let blend = triplanar_weights(normal, TRIPLANAR_SHARPNESS);
+    let x_pos = quantize_3d(vec3(TRIPLANAR_OFFSET.x, world_position.yz), size);
+    let y_pos = quantize_3d(vec3(world_position.x, TRIPLANAR_OFFSET.y, world_position.z), size);
+    let z_pos = quantize_3d(vec3(world_position.xy, TRIPLANAR_OFFSET.z), size);
+
+    var voxel = VoxelMaterialOutput(vec3(0.0), 0.0, vec4(0.0));
+    voxel = add_weighted_voxel(voxel, blend_voxel_types(x_pos, fac, special_fac, total), blend.x);
+    voxel = add_weighted_voxel(voxel, blend_voxel_types(y_pos, fac, special_fac, total), blend.y);
+    voxel = add_weighted_voxel(voxel, blend_voxel_types(z_pos, fac, special_fac, total), blend.z);
+
+    let height = dot(voxel.base_color, vec3(0.2126, 0.7152, 0.0722)) * BUMP_STRENGTH;
+    normal = perturb_normal(normal, world_position, height);
+#else
+    let quantized_pos = quantize_3d(world_position, cave_material.render_voxel_size);
+    let voxel = blend_voxel_types(quantized_pos, fac, special_fac, total);
+#endif
+
+    var flat_in = in;
+#ifdef PREPASS_PIPELINE
+#ifdef NORMAL_PREPASS_OR_DEFERRED_PREPASS
+    flat_in.world_normal = normal;
+#endif
+#else
+    flat_in.world_normal = normal;
+#endif
+    let pbr_vertex = reconstruct_pbr_vertex(flat_in);
     var pbr_input = pbr_input_from_standard_material(pbr_vertex, is_front);
     pbr_input.material.base_color = vec4(voxel.base_color, 1.0);
     pbr_input.material.reflectance = voxel.reflectance;
     pbr_input.material.emissive = voxel.emissive;
     pbr_input.material.perceptual_roughness = 0.0;
+    pbr_input.diffuse_occlusion *= in.voxel_occlusion;
+
+    // Prospecting scanner pulse, a faint front on every surface and a glow on valuable materials
+    let scan_distance = distance(in.world_position.xyz, cave_material.scan_origin);
+    let scan_front = 1.0 - smoothstep(0.0, SCAN_FRONT_WIDTH, abs(scan_distance - cave_material.scan_radius));
+    let scanned = select(0.0, 1.0, scan_distance <= cave_material.scan_radius);
+    let scan_highlight = select(scan_front * 0.15, scanned + scan_front, valuable);
+    pbr_input.material.emissive += vec4(SCAN_COLOR * scan_highlight * cave_material.scan_intensity, 0.0);
     //pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
 
 #ifdef PREPASS_PIPELINE
@@ -29,8 +29,9 @@ struct CaveVertex {
 #endif
 
     // My changes
-    @location(8) voxel_type: vec3u,
-    @location(9) voxel_ratio: vec3f,
+    @location(8) voxel_weights: vec4f,
+    @location(9) special_voxel_weights: vec4f,
+    @location(10) voxel_occlusion: f32,
 };
 
 struct CaveVertexOutput {
@@ -57,6 +58,7 @@ struct CaveVertexOutput {
 #endif
 
     // My changes
-    @location(8) voxel_type: vec3u,
-    @location(9) voxel_ratio: vec3f,
+    @location(8) voxel_weights: vec4f,
+    @location(9) special_voxel_weights: vec4f,
+    @location(10) voxel_occlusion: f32,
 }
\ No newline at end of file
@@ -12,12 +12,8 @@ fn ease_in_out_sine(x: f32) -> f32 {
     return -(cos(3.1415927 * x) - 1.0) / 2.0;
 }
 
-fn ease_in_out_sine_3d(v: vec3f) -> vec3f {
-    return vec3(
-        ease_in_out_sine(v.x),
-        ease_in_out_sine(v.y),
-        ease_in_out_sine(v.z),
-    );
+fn ease_in_out_sine_4d(v: vec4f) -> vec4f {
+    return -(cos(3.1415927 * v) - 1.0) / 2.0;
 }
 
 fn quantize(v: f32, steps: f32) -> f32 {
@@ -28,54 +24,48 @@ fn quantize_3d(v: vec3<f32>, steps: f32) -> vec3<f32> {
     return round(v * steps) / steps;
 }
 
-fn clamp_scaled(v: f32, min: f32, max: f32) -> f32 {
-    return v * (max - min) + min;
+fn quantize_4d(v: vec4<f32>, steps: f32) -> vec4<f32> {
+    return round(v * steps) / steps;
 }
 
-fn mix_three(a: f32, b: f32, c: f32, fac: vec3f) -> f32 {
-    return a * fac.x + b * fac.y + c * fac.z;
+fn clamp_scaled(v: f32, min: f32, max: f32) -> f32 {
+    return v * (max - min) + min;
 }
 
-fn mix_three_3d(a: vec3f, b: vec3f, c: vec3f, fac: vec3f) -> vec3f {
-    return vec3(
-        a.r * fac.x + b.r * fac.y + c.r * fac.z,
-        a.g * fac.x + b.g * fac.y + c.g * fac.z,
-        a.b * fac.x + b.b * fac.y + c.b * fac.z,
-    );
+fn add_weighted_voxel(
+    sum: VoxelMaterialOutput,
+    voxel: VoxelMaterialOutput,
+    weight: f32,
+) -> VoxelMaterialOutput {
+    var out: VoxelMaterialOutput;
+    out.base_color = sum.base_color + voxel.base_color * weight;
+    out.reflectance = sum.reflectance + voxel.reflectance * weight;
+    out.emissive = sum.emissive + voxel.emissive * weight;
+    return out;
 }
 
-fn mix_three_4d(a: vec4f, b: vec4f, c: vec4f, fac: vec3f) -> vec4f {
-    return vec4(
-        a.r * fac.x + b.r * fac.y + c.r * fac.z,
-        a.g * fac.x + b.g * fac.y + c.g * fac.z,
-        a.b * fac.x + b.b * fac.y + c.b * fac.z,
-        a.a * fac.x + b.a * fac.y + c.a * fac.z,
-    );
+// Normal of the triangle being rasterized, facing the camera. Vertices are shared between
+// triangles, so the interpolated vertex normal would smooth over every face.
+fn flat_normal(world_position: vec3<f32>) -> vec3<f32> {
+    return normalize(cross(dpdy(world_position), dpdx(world_position)));
 }
 
-fn mix_two_voxels(
-    a: VoxelMaterialOutput,
-    b: VoxelMaterialOutput,
-    fac: f32,
-) -> VoxelMaterialOutput {
-    var out: VoxelMaterialOutput;
-    out.base_color = mix(a.base_color, b.base_color, fac);
-    out.reflectance = mix(a.reflectance, b.reflectance, fac);
-    out.emissive = mix(a.emissive, b.emissive, fac);
-    return out;
+// Weights of the planes projected along each axis, from the surface normal. Steep walls take the
+// horizontal projections and gentle slopes take the vertical one, with a narrow blend between.
+fn triplanar_weights(normal: vec3<f32>, sharpness: f32) -> vec3<f32> {
+    let weights = pow(abs(normal), vec3(sharpness));
+    return weights / dot(weights, vec3(1.0));
 }
 
-fn mix_three_voxels(
-    a: VoxelMaterialOutput,
-    b: VoxelMaterialOutput,
-    c: VoxelMaterialOutput,
-    fac: vec3f,
-) -> VoxelMaterialOutput {
-    var out: VoxelMaterialOutput;
-    out.base_color = mix_three_3d(a.base_color, b.base_color, c.base_color, fac);
-    out.reflectance = mix_three(a.reflectance, b.reflectance, c.reflectance, fac);
-    out.emissive = mix_three_4d(a.emissive, b.emissive, c.emissive, fac);
-    return out;
+// Bumps the normal by the screen space slope of the height, so no tangents are needed.
+fn perturb_normal(normal: vec3<f32>, world_position: vec3<f32>, height: f32) -> vec3<f32> {
+    let dpx = dpdx(world_position);
+    let dpy = dpdy(world_position);
+    let r1 = cross(dpy, normal);
+    let r2 = cross(normal, dpx);
+    let det = dot(dpx, r1);
+    let gradient = sign(det) * (dpdx(height) * r1 + dpdy(height) * r2);
+    return normalize(abs(det) * normal - gradient);
 }
 
 fn reconstruct_pbr_vertex(in: CaveVertexOutput) -> VertexOutput {
@@ -76,8 +76,9 @@ fn vertex(vertex_no_morph: CaveVertex) -> CaveVertexOutput {
 #endif
 
     // My changes
-    out.voxel_type = vertex.voxel_type;
-    out.voxel_ratio = vertex.voxel_ratio;
+    out.voxel_weights = vertex.voxel_weights;
+    out.special_voxel_weights = vertex.special_voxel_weights;
+    out.voxel_occlusion = vertex.voxel_occlusion;
 
     return out;
 }
\ No newline at end of file
@@ -20,6 +20,7 @@ fn voxel_function_by_type(voxel_type: u32, pos: vec3<f32>) -> VoxelMaterialOutpu
         case 0u: { return voxel_0(pos); }
         case 1u: { return voxel_1(pos); }
         case 2u: { return voxel_2(pos); }
+        case 3u: { return voxel_3(pos); }
 
         case 255u: { return fallback(pos, vec3(0.0, 1.0, 0.0)); } // Unset
         case 254u: { return fallback(pos, vec3(1.0, 0.0, 0.0)); } // Invalid
@@ -136,4 +137,23 @@ fn voxel_2(pos: vec3<f32>) -> VoxelMaterialOutput {
     out.base_color = mix(vec3(0.0, 0.0, 0.0), color, noise);
     out.reflectance = vertical_noise;
     return out;
-}
\ No newline at end of file
+}
+fn voxel_3(pos: vec3<f32>) -> VoxelMaterialOutput {
+    const color1 = vec3(0.10, 0.25, 0.35);
+    const color2 = vec3(0.55, 0.85, 0.95);
+
+    // Faceted noise
+    var noise = 0.5;
+    noise = mix(noise, simplex_noise_3d(quantize_3d(pos, 1.0)), 0.5);
+    noise = mix(noise, simplex_noise_3d(pos / 6.0), 0.3);
+    noise = clamp_scaled(noise, 0.25, 1.0);
+    noise = quantize(noise, 5.0);
+
+    // Shimmer
+    let shimmer = abs(sin(globals.time * 0.5 + noise * PI_2));
+
+    var out = VoxelMaterialOutput_default();
+    out.base_color = mix(color1, color2, noise * shimmer);
+    out.reflectance = 1.0;
+    return out;
+}
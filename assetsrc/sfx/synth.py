@@ -0,0 +1,247 @@
+"""Synthesizes the footstep, impact and deflection sounds in assets/sfx.
+
+    python3 synth.py ../../assets impact
+    python3 synth.py ../../assets deflect
+    python3 synth.py ../../assets rock,gravel,crystal,boundary,metal
+
+Each sound is generated from a fixed seed, so rerunning this reproduces the committed files.
+"""
+import math, random, sys, os
+import vorbis
+
+RATE = 22050
+
+def n(secs): return int(secs * RATE)
+
+def noise(count, rng): return [rng.uniform(-1, 1) for _ in range(count)]
+
+def lowpass(x, hz):
+    a = 1 - math.exp(-2 * math.pi * hz / RATE); y = 0.0; out = []
+    for v in x: y += a * (v - y); out.append(y)
+    return out
+
+def highpass(x, hz):
+    lp = lowpass(x, hz)
+    return [a - b for a, b in zip(x, lp)]
+
+def bandpass(x, hz, q):
+    # Two pole resonator
+    w = 2 * math.pi * hz / RATE; r = math.exp(-w / (2 * q))
+    a1 = -2 * r * math.cos(w); a2 = r * r; g = 1 - r
+    y1 = y2 = 0.0; out = []
+    for v in x:
+        y = g * v - a1 * y1 - a2 * y2
+        out.append(y); y2, y1 = y1, y
+    return out
+
+def env(count, attack, decay):
+    a = max(n(attack), 1)
+    return [min(i / a, 1.0) * math.exp(-max(i - a, 0) / (decay * RATE)) for i in range(count)]
+
+def mix(*parts):
+    length = max(len(p) for p in parts)
+    return [sum(p[i] for p in parts if i < len(p)) for i in range(length)]
+
+def scale(x, s): return [v * s for v in x]
+def shape(x, e): return [a * b for a, b in zip(x, e)]
+
+def sine(count, hz, decay, rng=None, drop=0.0):
+    out = []; phase = 0.0
+    for i in range(count):
+        f = hz * (1 - drop * min(i / count, 1))
+        phase += 2 * math.pi * f / RATE
+        out.append(math.sin(phase) * math.exp(-i / (decay * RATE)))
+    return out
+
+def normalize(x, peak):
+    m = max(abs(v) for v in x) or 1
+    # Fade the tail so the sound ends in silence
+    tail = min(n(0.02), len(x))
+    x = [v * peak / m for v in x]
+    for i in range(tail):
+        x[len(x) - tail + i] *= 1 - i / tail
+    return x
+
+def grains(length, count, rng, hz, q, decay, spread):
+    out = [0.0] * length
+    for _ in range(count):
+        start = int(rng.random() ** spread * (length - n(0.03)))
+        g = shape(bandpass(noise(n(0.03), rng), hz * rng.uniform(0.7, 1.4), q), env(n(0.03), 0.0005, decay))
+        amp = rng.uniform(0.3, 1.0)
+        for i, v in enumerate(g): out[start + i] += v * amp
+    return out
+
+# ---- footstep sets ----
+
+def rock(kind, rng):
+    if kind == 'step':
+        length = n(0.18)
+        body = shape(lowpass(noise(length, rng), rng.uniform(900, 1400)), env(length, 0.002, 0.035))
+        thump = scale(sine(length, rng.uniform(80, 110), 0.03, drop=0.3), 0.5)
+        scuff = scale(shape(bandpass(noise(length, rng), 2500, 1.5), env(length, 0.01, 0.02)), 0.6)
+        return normalize(mix(body, thump, scuff), 0.7)
+    if kind == 'land':
+        length = n(0.4)
+        body = shape(lowpass(noise(length, rng), 700), env(length, 0.002, 0.08))
+        thump = scale(sine(length, 70, 0.07, drop=0.4), 0.9)
+        debris = scale(grains(length, 14, rng, 1800, 3, 0.01, 1.5), 0.25)
+        return normalize(mix(body, thump, debris), 0.85)
+    length = n(0.7)
+    e = [math.sin(math.pi * i / length) ** 0.6 for i in range(length)]
+    scrape = shape(bandpass(lowpass(noise(length, rng), 3000), 1200, 0.8), e)
+    rattle = scale(grains(length, 30, rng, 2200, 3, 0.008, 1.0), 0.15)
+    return normalize(mix(scrape, rattle), 0.6)
+
+def gravel(kind, rng):
+    if kind == 'step':
+        length = n(0.22)
+        crunch = grains(length, 22, rng, 2600, 2.5, 0.006, 2.0)
+        body = scale(shape(lowpass(noise(length, rng), 800), env(length, 0.003, 0.04)), 0.6)
+        return normalize(mix(crunch, body), 0.7)
+    if kind == 'land':
+        length = n(0.45)
+        crunch = grains(length, 60, rng, 2300, 2.5, 0.008, 2.5)
+        thump = scale(sine(length, 75, 0.06, drop=0.4), 0.6)
+        return normalize(mix(crunch, thump), 0.85)
+    length = n(0.75)
+    e = [math.sin(math.pi * i / length) ** 0.5 for i in range(length)]
+    crunch = shape(grains(length, 120, rng, 2400, 2.5, 0.006, 1.0), e)
+    hiss = scale(shape(highpass(noise(length, rng), 2000), e), 0.15)
+    return normalize(mix(crunch, hiss), 0.6)
+
+def ring(length, partials, rng, decay):
+    return mix(*[scale(sine(length, f * rng.uniform(0.98, 1.02), decay * rng.uniform(0.7, 1.2)), a) for f, a in partials])
+
+def crystal(kind, rng):
+    partials = [(2350, 0.5), (3710, 0.35), (5230, 0.25), (7020, 0.15)]
+    if kind == 'step':
+        length = n(0.3)
+        tap = shape(bandpass(noise(length, rng), 3000, 1.2), env(length, 0.001, 0.01))
+        return normalize(mix(tap, scale(ring(length, partials, rng, 0.08), 0.3)), 0.6)
+    if kind == 'land':
+        length = n(0.6)
+        tap = shape(bandpass(noise(length, rng), 2500, 1.0), env(length, 0.001, 0.03))
+        tinkle = scale(grains(length, 12, rng, 4500, 20, 0.04, 1.5), 0.4)
+        return normalize(mix(tap, scale(ring(length, partials, rng, 0.15), 0.5), tinkle), 0.8)
+    length = n(0.75)
+    e = [math.sin(math.pi * i / length) ** 0.6 for i in range(length)]
+    squeal = shape(bandpass(noise(length, rng), 3200, 6), e)
+    tinkle = scale(grains(length, 40, rng, 4800, 20, 0.02, 1.0), 0.3)
+    return normalize(mix(squeal, tinkle), 0.55)
+
+def buzz(length, hz, rng):
+    out = []
+    for i in range(length):
+        t = i / RATE
+        v = sum(math.sin(2 * math.pi * hz * k * t) / k for k in range(1, 8))
+        out.append(v)
+    return lowpass(out, 1500)
+
+def boundary(kind, rng):
+    if kind == 'step':
+        length = n(0.25)
+        thump = scale(sine(length, 95, 0.04, drop=0.2), 0.7)
+        hum = scale(shape(buzz(length, 110 * rng.uniform(0.97, 1.03), rng), env(length, 0.002, 0.05)), 0.25)
+        return normalize(mix(thump, hum), 0.65)
+    if kind == 'land':
+        length = n(0.55)
+        thump = sine(length, 65, 0.1, drop=0.4)
+        hum = scale(shape(buzz(length, 110, rng), env(length, 0.002, 0.14)), 0.35)
+        crackle = scale(grains(length, 10, rng, 5000, 2, 0.004, 1.5), 0.2)
+        return normalize(mix(thump, hum, crackle), 0.85)
+    length = n(0.75)
+    e = [math.sin(math.pi * i / length) ** 0.6 for i in range(length)]
+    hum = shape(buzz(length, 110, rng), e)
+    hiss = scale(shape(bandpass(noise(length, rng), 1800, 1), e), 0.6)
+    return normalize(mix(hum, hiss), 0.55)
+
+def metal(kind, rng):
+    partials = [(420, 0.6), (1130, 0.4), (1870, 0.3), (2760, 0.2)]
+    if kind == 'step':
+        length = n(0.35)
+        hit = shape(bandpass(noise(length, rng), 1800, 1.5), env(length, 0.001, 0.012))
+        clang = scale(ring(length, [(f * rng.uniform(0.9, 1.1), a) for f, a in partials], rng, 0.07), 0.35)
+        return normalize(mix(hit, clang), 0.65)
+    if kind == 'land':
+        length = n(0.7)
+        hit = shape(lowpass(noise(length, rng), 2000), env(length, 0.001, 0.03))
+        clang = scale(ring(length, partials, rng, 0.2), 0.6)
+        thump = scale(sine(length, 80, 0.06, drop=0.3), 0.5)
+        return normalize(mix(hit, clang, thump), 0.85)
+    length = n(0.75)
+    e = [math.sin(math.pi * i / length) ** 0.6 for i in range(length)]
+    grind = shape(bandpass(noise(length, rng), 2400, 4), e)
+    squeal = scale(shape(sine(length, 1650, 10), e), 0.15)
+    return normalize(mix(grind, squeal), 0.55)
+
+# ---- impacts ----
+
+def impact(material, rng):
+    length = n(0.35)
+    if material == 'rock':
+        crack = shape(lowpass(noise(length, rng), 2500), env(length, 0.0005, 0.03))
+        chips = scale(grains(length, 18, rng, 2200, 3, 0.008, 2.0), 0.3)
+        return normalize(mix(crack, chips), 0.8)
+    if material == 'ore':
+        crack = shape(lowpass(noise(length, rng), 3500), env(length, 0.0005, 0.025))
+        chips = scale(grains(length, 30, rng, 3200, 4, 0.01, 1.5), 0.45)
+        ping = scale(ring(length, [(1650, 0.3), (2930, 0.2)], rng, 0.05), 0.3)
+        return normalize(mix(crack, chips, ping), 0.8)
+    if material == 'crystal':
+        length = n(0.6)
+        crack = shape(highpass(noise(length, rng), 1500), env(length, 0.0005, 0.012))
+        shatter = scale(grains(length, 25, rng, 5000, 15, 0.05, 1.8), 0.5)
+        ring_ = scale(ring(length, [(2350, 0.5), (3710, 0.35), (5230, 0.25)], rng, 0.18), 0.35)
+        return normalize(mix(crack, shatter, ring_), 0.8)
+    if material == 'object':
+        knock = shape(bandpass(noise(length, rng), 900, 1.5), env(length, 0.0005, 0.02))
+        thud = scale(sine(length, 160, 0.04, drop=0.3), 0.6)
+        clank = scale(ring(length, [(740, 0.4), (1960, 0.25)], rng, 0.04), 0.3)
+        return normalize(mix(knock, thud, clank), 0.75)
+    # boundary
+    length = n(0.45)
+    zap = shape(bandpass(noise(length, rng), 1200, 2), env(length, 0.0005, 0.02))
+    hum = scale(shape(buzz(length, 110, rng), env(length, 0.002, 0.1)), 0.5)
+    return normalize(mix(zap, hum), 0.75)
+
+# ---- deflections ----
+
+def deflect(rng):
+    length = n(0.5)
+    # A sharp tick, then a bright ring that bends down like a ricochet
+    tick = shape(highpass(noise(length, rng), 3000), env(length, 0.0003, 0.006))
+    zing = scale(shape(sine(length, 3400, 0.12, drop=0.35), env(length, 0.001, 0.2)), 0.6)
+    ring_ = scale(ring(length, [(2350, 0.5), (3710, 0.35), (5230, 0.25)], rng, 0.12), 0.35)
+    return normalize(mix(tick, zing, ring_), 0.75)
+
+SETS = {'rock': rock, 'gravel': gravel, 'crystal': crystal, 'boundary': boundary, 'metal': metal}
+
+def write(path, samples):
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    open(path, 'wb').write(vorbis.encode(samples, RATE))
+    open(path + '.meta', 'w').write(META)
+    print(path, len(samples))
+
+META = """(
+    meta_format_version: "1.0",
+    asset: Load(
+        loader: "bevy_audio::audio_source::AudioLoader",
+        settings: (),
+    ),
+)"""
+
+if __name__ == '__main__':
+    root = sys.argv[1]
+    what = sys.argv[2]
+    if what == 'deflect':
+        write(f'{root}/sfx/deflect.ogg', deflect(random.Random(200)))
+    elif what == 'impact':
+        for i, m in enumerate(['rock', 'ore', 'crystal', 'boundary', 'object']):
+            write(f'{root}/sfx/impact/{m}.ogg', impact(m, random.Random(100 + i)))
+    else:
+        for name in what.split(','):
+            rng = random.Random(name)
+            for v in range(4):
+                write(f'{root}/sfx/footsteps/{name}/step_{v}.ogg', SETS[name]('step', rng))
+            write(f'{root}/sfx/footsteps/{name}/land.ogg', SETS[name]('land', rng))
+            write(f'{root}/sfx/footsteps/{name}/slide.ogg', SETS[name]('slide', rng))
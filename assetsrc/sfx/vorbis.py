@@ -0,0 +1,247 @@
+"""Minimal mono Ogg Vorbis encoder, used by synth.py.
+
+Every block is the same size, the floor is a straight line, and residues are coded with one
+uniform codebook. Files are larger than a real encoder's, but decode with any Vorbis decoder.
+"""
+import math, struct
+
+N = 512          # block size
+HALF = N // 2
+PSIZE = 32       # residue partition size
+QMAX = 127       # residue values are -QMAX..QMAX+1
+
+class Bits:
+    def __init__(s): s.data = bytearray(); s.acc = 0; s.n = 0
+    def put(s, value, bits):
+        for i in range(bits):
+            s.acc |= ((value >> i) & 1) << s.n
+            s.n += 1
+            if s.n == 8:
+                s.data.append(s.acc); s.acc = 0; s.n = 0
+    def code(s, code, length):  # Huffman codewords are read MSB first
+        for i in range(length - 1, -1, -1):
+            s.put((code >> i) & 1, 1)
+    def bytes(s):
+        out = bytearray(s.data)
+        if s.n: out.append(s.acc)
+        return bytes(out)
+
+def vfloat(v):
+    # 21 bit mantissa, 10 bit exponent biased by 788, sign
+    sign = 0x80000000 if v < 0 else 0
+    m = abs(v); e = 788
+    if m:
+        while m != int(m): m *= 2; e -= 1
+        while m >= (1 << 21): m /= 2; e += 1
+    return sign | (e << 21) | int(m)
+
+def ident(rate):
+    b = Bits(); b.put(1, 8)
+    for c in b"vorbis": b.put(c, 8)
+    b.put(0, 32); b.put(1, 8); b.put(rate, 32)
+    b.put(0, 32); b.put(0, 32); b.put(0, 32)
+    exp = N.bit_length() - 1
+    b.put(exp, 4); b.put(exp, 4); b.put(1, 1)
+    return b.bytes()
+
+def comment(vendor=b"caves-forever"):
+    b = Bits(); b.put(3, 8)
+    for c in b"vorbis": b.put(c, 8)
+    b.put(len(vendor), 32)
+    for c in vendor: b.put(c, 8)
+    b.put(0, 32); b.put(1, 1)
+    return b.bytes()
+
+VALUE_ENTRIES = 2 * (QMAX + 1)
+VALUE_LEN = VALUE_ENTRIES.bit_length() - 1
+
+def setup():
+    b = Bits(); b.put(5, 8)
+    for c in b"vorbis": b.put(c, 8)
+    b.put(2 - 1, 8)
+    # Book 0: residue classes, silent or coded
+    b.put(0x564342, 24); b.put(1, 16); b.put(2, 24); b.put(0, 1); b.put(0, 1)
+    for _ in range(2): b.put(1 - 1, 5)
+    b.put(0, 4)
+    # Book 1: residue values
+    b.put(0x564342, 24); b.put(1, 16); b.put(VALUE_ENTRIES, 24); b.put(0, 1); b.put(0, 1)
+    for _ in range(VALUE_ENTRIES): b.put(VALUE_LEN - 1, 5)
+    b.put(1, 4); b.put(vfloat(-QMAX), 32); b.put(vfloat(1), 32)
+    b.put(VALUE_LEN - 1, 4); b.put(0, 1)
+    for i in range(VALUE_ENTRIES): b.put(i, VALUE_LEN)
+    # Time domain transforms
+    b.put(0, 6); b.put(0, 16)
+    # Floor 1 without partitions, multiplier 1, X list [0, HALF]
+    b.put(0, 6); b.put(1, 16); b.put(0, 5); b.put(0, 2); b.put(HALF.bit_length() - 1, 4)
+    # Residue 1
+    b.put(0, 6); b.put(1, 16); b.put(0, 24); b.put(HALF, 24); b.put(PSIZE - 1, 24)
+    b.put(2 - 1, 6); b.put(0, 8)
+    b.put(0, 3); b.put(0, 1)      # class 0: no passes
+    b.put(1, 3); b.put(0, 1)      # class 1: pass 0
+    b.put(1, 8)
+    # Mapping 0
+    b.put(0, 6); b.put(0, 16); b.put(0, 1); b.put(0, 1); b.put(0, 2)
+    b.put(0, 8); b.put(0, 8); b.put(0, 8)
+    # Mode
+    b.put(0, 6); b.put(0, 1); b.put(0, 16); b.put(0, 16); b.put(0, 8)
+    b.put(1, 1)
+    return b.bytes()
+
+# The table from the Vorbis I spec, section 10.1
+DB = [
+ 1.0649863e-07, 1.1341951e-07, 1.2079015e-07, 1.2863978e-07, 1.3699951e-07, 1.4590251e-07, 1.5538408e-07, 1.6548181e-07,
+ 1.7623575e-07, 1.8768855e-07, 1.9988561e-07, 2.1287530e-07, 2.2670913e-07, 2.4144197e-07, 2.5713223e-07, 2.7384213e-07,
+ 2.9163793e-07, 3.1059021e-07, 3.3077411e-07, 3.5226968e-07, 3.7516214e-07, 3.9954229e-07, 4.2550680e-07, 4.5315863e-07,
+ 4.8260743e-07, 5.1396998e-07, 5.4737065e-07, 5.8294187e-07, 6.2082472e-07, 6.6116941e-07, 7.0413592e-07, 7.4989464e-07,
+ 7.9862701e-07, 8.5052630e-07, 9.0579828e-07, 9.6466216e-07, 1.0273513e-06, 1.0941144e-06, 1.1652161e-06, 1.2409384e-06,
+ 1.3215816e-06, 1.4074654e-06, 1.4989305e-06, 1.5963394e-06, 1.7000785e-06, 1.8105592e-06, 1.9282195e-06, 2.0535261e-06,
+ 2.1869758e-06, 2.3290978e-06, 2.4804557e-06, 2.6416497e-06, 2.8133190e-06, 2.9961443e-06, 3.1908506e-06, 3.3982101e-06,
+ 3.6190449e-06, 3.8542308e-06, 4.1047004e-06, 4.3714470e-06, 4.6555282e-06, 4.9580707e-06, 5.2802740e-06, 5.6234160e-06,
+ 5.9888572e-06, 6.3780469e-06, 6.7925283e-06, 7.2339451e-06, 7.7040476e-06, 8.2047000e-06, 8.7378876e-06, 9.3057248e-06,
+ 9.9104632e-06, 1.0554501e-05, 1.1240392e-05, 1.1970856e-05, 1.2748789e-05, 1.3577278e-05, 1.4459606e-05, 1.5399272e-05,
+ 1.6400004e-05, 1.7465768e-05, 1.8600792e-05, 1.9809576e-05, 2.1096914e-05, 2.2467911e-05, 2.3928002e-05, 2.5482978e-05,
+ 2.7139006e-05, 2.8902651e-05, 3.0780908e-05, 3.2781225e-05, 3.4911534e-05, 3.7180282e-05, 3.9596466e-05, 4.2169667e-05,
+ 4.4910090e-05, 4.7828601e-05, 5.0936773e-05, 5.4246931e-05, 5.7772202e-05, 6.1526565e-05, 6.5524908e-05, 6.9783085e-05,
+ 7.4317983e-05, 7.9147585e-05, 8.4291040e-05, 8.9768747e-05, 9.5602426e-05, 0.00010181521, 0.00010843174, 0.00011547824,
+ 0.00012298267, 0.00013097477, 0.00013948625, 0.00014855085, 0.00015820453, 0.00016848555, 0.00017943469, 0.00019109536,
+ 0.00020351382, 0.00021673929, 0.00023082423, 0.00024582449, 0.00026179955, 0.00027881276, 0.00029693158, 0.00031622787,
+ 0.00033677814, 0.00035866388, 0.00038197188, 0.00040679456, 0.00043323036, 0.00046138411, 0.00049136745, 0.00052329927,
+ 0.00055730621, 0.00059352311, 0.00063209358, 0.00067317058, 0.00071691700, 0.00076350630, 0.00081312324, 0.00086596457,
+ 0.00092223983, 0.00098217216, 0.0010459992, 0.0011139742, 0.0011863665, 0.0012634633, 0.0013455702, 0.0014330129,
+ 0.0015261382, 0.0016253153, 0.0017309374, 0.0018434235, 0.0019632195, 0.0020908006, 0.0022266726, 0.0023713743,
+ 0.0025254795, 0.0026895994, 0.0028643847, 0.0030505286, 0.0032487691, 0.0034598925, 0.0036847358, 0.0039241906,
+ 0.0041792066, 0.0044507950, 0.0047400328, 0.0050480668, 0.0053761186, 0.0057254891, 0.0060975636, 0.0064938176,
+ 0.0069158225, 0.0073652516, 0.0078438871, 0.0083536271, 0.0088964928, 0.009474637, 0.010090352, 0.010746080,
+ 0.011444421, 0.012188144, 0.012980198, 0.013823725, 0.014722068, 0.015678791, 0.016697687, 0.017782797,
+ 0.018938423, 0.020169149, 0.021479854, 0.022875735, 0.024362330, 0.025945531, 0.027631618, 0.029427276,
+ 0.031339626, 0.033376252, 0.035545228, 0.037855157, 0.040315199, 0.042935108, 0.045725273, 0.048696758,
+ 0.051861348, 0.055231591, 0.058820850, 0.062643361, 0.066714279, 0.071049749, 0.075666962, 0.080584227,
+ 0.085821044, 0.091398179, 0.097337747, 0.10366330, 0.11039993, 0.11757434, 0.12521498, 0.13335215,
+ 0.14201813, 0.15124727, 0.16107617, 0.17154380, 0.18269168, 0.19456402, 0.20720788, 0.22067342,
+ 0.23501402, 0.25028656, 0.26655159, 0.28387361, 0.30232132, 0.32196786, 0.34289114, 0.36517414,
+ 0.38890521, 0.41417847, 0.44109412, 0.46975890, 0.50028648, 0.53279791, 0.56742212, 0.60429640,
+ 0.64356699, 0.68538959, 0.72993007, 0.77736504, 0.82788260, 0.88168307, 0.9389798, 1.0,
+]
+
+def window(n):
+    return math.sin(math.pi / 2 * math.sin(math.pi * (n + 0.5) / N) ** 2)
+
+WIN = [window(n) for n in range(N)]
+COS = [[math.cos(2 * math.pi / N * (n + 0.5 + N / 4) * (k + 0.5)) for n in range(N)] for k in range(HALF)]
+SCALE = 4 / N  # matches the inverse transform of decoders
+
+def mdct(block):
+    xw = [block[n] * WIN[n] for n in range(N)]
+    return [SCALE * sum(c * x for c, x in zip(COS[k], xw)) for k in range(HALF)]
+
+def render_line(y0, y1):
+    # Integer line from the floor 1 decoder
+    dy = y1 - y0; adx = HALF; base = int(dy / adx); ady = abs(dy) - abs(base) * adx
+    sy = base - 1 if dy < 0 else base + 1
+    y = y0; err = 0; out = [DB[y]]
+    for _ in range(1, HALF):
+        err += ady
+        if err >= adx:
+            err -= adx; y += sy
+        else:
+            y += base
+        out.append(DB[y])
+    return out
+
+def audio_packet(coeffs):
+    b = Bits(); b.put(0, 1)
+    peak = max(abs(c) for c in coeffs)
+    if peak < 1e-9:
+        b.put(0, 1)
+        return b.bytes()
+    # Straight floor fitted under the peaks of each partition, so the loudest coefficient of each
+    # lands near the top of the residue range
+    def index(v):
+        y = 0
+        while y < 255 and DB[y] * QMAX < v:
+            y += 1
+        return y
+    points = []
+    for p in range(HALF // PSIZE):
+        part_peak = max(abs(c) for c in coeffs[p * PSIZE:(p + 1) * PSIZE])
+        if part_peak > DB[0]:
+            points.append((p * PSIZE + PSIZE / 2, index(part_peak)))
+    # The cheapest line that stays above every partition: try lines through pairs of points,
+    # and through single points with a clamped or flat end
+    def through(a, o):
+        if a[0] == o[0]:
+            return None
+        slope = (o[1] - a[1]) / (o[0] - a[0])
+        return a[1] - slope * a[0], a[1] + slope * (HALF - a[0])
+    candidates = [(p[1], p[1]) for p in points]
+    for i, a in enumerate(points):
+        for o in points[i + 1:]:
+            candidates.append(through(a, o))
+        for end in (0, 255):
+            candidates.append(through(a, (0, end)))
+            candidates.append(through(a, (HALF, end)))
+    best = None
+    for c in candidates:
+        if c is None:
+            continue
+        y0 = min(max(math.ceil(c[0]) + 1, 0), 255)
+        y1 = min(max(math.ceil(c[1]) + 1, 0), 255)
+        line = lambda x: y0 + (y1 - y0) * x / HALF
+        if any(line(x) < y for x, y in points):
+            continue
+        cost = sum(line(x) - y for x, y in points)
+        if best is None or cost < best[0]:
+            best = (cost, y0, y1)
+    if best is None:
+        y0 = y1 = index(peak)
+    else:
+        _, y0, y1 = best
+    b.put(1, 1); b.put(y0, 8); b.put(y1, 8)
+    floor = render_line(y0, y1)
+    q = [max(-QMAX, min(QMAX + 1, round(c / f))) for c, f in zip(coeffs, floor)]
+    for p in range(HALF // PSIZE):
+        part = q[p * PSIZE:(p + 1) * PSIZE]
+        coded = any(part)
+        b.code(1 if coded else 0, 1)
+        if coded:
+            for v in part:
+                b.code(v + QMAX, VALUE_LEN)
+    return b.bytes()
+
+# Ogg
+def crc32(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 24
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x04C11DB7) if crc & 0x80000000 else (crc << 1)
+            crc &= 0xFFFFFFFF
+    return crc
+
+def page(packets, granule, seq, flags, serial):
+    lacing = bytearray(); body = bytearray()
+    for p in packets:
+        n = len(p)
+        while n >= 255: lacing.append(255); n -= 255
+        lacing.append(n); body += p
+    assert len(lacing) <= 255
+    header = b"OggS" + struct.pack("<BBqIII", 0, flags, granule, serial, seq, 0) + bytes([len(lacing)]) + bytes(lacing)
+    data = bytearray(header + body)
+    struct.pack_into("<I", data, 22, crc32(data))
+    return bytes(data)
+
+def encode(samples, rate, serial=0x43415645):
+    # Pad so every sample is covered by two overlapping blocks
+    padded = [0.0] * HALF + list(samples) + [0.0] * (N + HALF - len(samples) % HALF)
+    blocks = (len(padded) - N) // HALF + 1
+    out = page([ident(rate)], 0, 0, 2, serial)
+    out += page([comment(), setup()], 0, 1, 0, serial)
+    seq = 2; pending = []; granule = 0
+    for i in range(blocks):
+        pending.append(audio_packet(mdct(padded[i * HALF:i * HALF + N])))
+        if i > 0: granule += HALF
+        last = i == blocks - 1
+        if sum(len(p) // 255 + 1 for p in pending) > 200 or last:
+            out += page(pending, len(samples) if last else granule, seq, 4 if last else 0, serial)
+            seq += 1; pending = []
+    return bytes(out)
@@ -1,21 +1,12 @@
-use std::{
-    fs::File,
-    io::{Read, Write},
-    path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    thread,
-};
+use std::{fs::File, io::Write, path::PathBuf};
 
-use anyhow::anyhow;
 use bytesize::ByteSize;
 use clap::{Parser, ValueEnum};
 use tracing::{debug, error, info, span, warn, Level};
 use tracing_subscriber::util::SubscriberInitExt;
-use walkdir::WalkDir;
 
-use editor_lib::{
-    data::Environment,
-    state::{EditorMode, FilePayload},
+use editor_lib::data::{
+    build_collection, collection_file_name, BuildReport, Environment, FileReport, FileStatus,
 };
 use lib::worldgen::asset::AssetCollection;
 
@@ -50,13 +41,6 @@ enum Format {
     Ron,
 }
 
-#[derive(Default)]
-struct Statistics {
-    skipped: u32,
-    failed: u32,
-    succeeded: u32,
-}
-
 #[derive(PartialEq, strum::Display)]
 #[repr(u8)]
 enum Code {
@@ -92,8 +76,8 @@ fn main() {
     let args = Args::parse();
 
     let assets = match build(args.clone()) {
-        Ok((stats, assets)) => {
-            if !check_build_statistics(&stats) {
+        Ok((report, assets)) => {
+            if !check_build_statistics(&report) {
                 exit_error(Code::NoOutput, None);
             }
             assets
@@ -117,44 +101,57 @@ fn main() {
     };
 }
 
-fn build(Args { env, input, .. }: Args) -> anyhow::Result<(Statistics, AssetCollection)> {
-    let stats = Arc::new(Mutex::new(Statistics::default()));
+fn build(Args { env, input, .. }: Args) -> anyhow::Result<(BuildReport, AssetCollection)> {
+    let span = span!(Level::TRACE, "build");
+    let _enter = span.enter();
 
-    let files = filter_input_files(input)?;
-    let assets = build_asset_collection(stats.clone(), env, files)?;
+    let (assets, report) = build_collection(&input, env)?;
+    report.files.iter().for_each(log_file_report);
 
-    let stats = Arc::try_unwrap(stats)
-        .map_err(|_| anyhow!("unwrapping statistics failed"))?
-        .into_inner()?;
+    Ok((report, assets))
+}
 
-    Ok((stats, assets))
+fn log_file_report(report: &FileReport) {
+    let file = report.path.display().to_string();
+
+    match report.status {
+        FileStatus::Built => debug!(file, "built"),
+        FileStatus::Skipped => debug!(file, step = "filter_by_environment", "skip"),
+        FileStatus::Failed => {
+            let problems = report
+                .errors
+                .iter()
+                .map(|error| format!("- {error}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            warn!(file, "build failed, problems:\n{problems}\n");
+        }
+    }
+    for warning in report.warnings.iter() {
+        warn!(file, warning, "warning");
+    }
 }
 
-fn check_build_statistics(stats: &Statistics) -> bool {
+fn check_build_statistics(report: &BuildReport) -> bool {
+    let skipped = report.count(FileStatus::Skipped);
+    let failed = report.count(FileStatus::Failed);
+    let succeeded = report.count(FileStatus::Built);
+    let warnings = report.warning_count();
+
     let mut message = "build".to_string();
-    if stats.succeeded > 0 {
+    if succeeded > 0 {
         message += " succeeded";
 
-        if stats.failed > 0 {
+        if failed > 0 {
             message += " with failures";
         }
     } else {
         message += " failed (no output)";
-        error!(
-            message,
-            skipped = stats.skipped,
-            failed = stats.failed,
-            succeeded = stats.succeeded,
-        );
+        error!(message, skipped, failed, succeeded, warnings);
         return false;
     }
 
-    info!(
-        message,
-        skipped = stats.skipped,
-        failed = stats.failed,
-        succeeded = stats.succeeded,
-    );
+    info!(message, skipped, failed, succeeded, warnings);
     return true;
 }
 
@@ -168,11 +165,7 @@ fn write_archive(
     }: Args,
     assets: AssetCollection,
 ) -> anyhow::Result<(PathBuf, u64)> {
-    let file_name = format!(
-        "{name}.{}.{}",
-        env.to_string().to_lowercase(),
-        format.to_string().to_lowercase()
-    );
+    let file_name = collection_file_name(&name, env, &format.to_string().to_lowercase());
     let path = output.join(file_name);
     let mut file = File::create(path.clone())?;
 
@@ -188,168 +181,3 @@ fn write_archive(
 
     Ok((path, size))
 }
-
-fn filter_input_files(path: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
-    let span = span!(Level::TRACE, "filter");
-    let _enter = span.enter();
-
-    let mut result = Vec::new();
-
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
-        let path = entry.path();
-
-        let skip = |reason: &str| {
-            debug!(path = path.display().to_string(), reason, "skip");
-        };
-
-        if path.is_dir() {
-            skip("directory");
-            continue;
-        }
-        let Some(file_name) = entry.file_name().to_str() else {
-            skip("invalid filename");
-            continue;
-        };
-        if file_name.starts_with(".") {
-            skip("hidden");
-            continue;
-        }
-        let Ok(mode) = EditorMode::from_path(path) else {
-            skip("not an editor file");
-            continue;
-        };
-
-        debug!(
-            path = path.display().to_string(),
-            mode = mode.to_string(),
-            "keep"
-        );
-        result.push(path.to_owned());
-    }
-
-    Ok(result)
-}
-
-fn build_asset_collection(
-    stats: Arc<Mutex<Statistics>>,
-    env: Environment,
-    files: Vec<PathBuf>,
-) -> anyhow::Result<AssetCollection> {
-    let assets = Arc::new(Mutex::new(AssetCollection::default()));
-
-    thread::scope(|s| {
-        for file in files {
-            let assets = assets.clone();
-            let stats = stats.clone();
-
-            s.spawn(move || {
-                let span = span!(Level::TRACE, "build");
-                let _enter = span.enter();
-                let file_name = file.display().to_string();
-
-                let data = match load_file_payload(env, file) {
-                    (_, Some(data)) => data,
-                    (skipped, None) => {
-                        let mut stats = stats.lock().unwrap();
-                        if skipped {
-                            stats.skipped += 1;
-                        } else {
-                            stats.failed += 1;
-                        }
-                        return;
-                    }
-                };
-
-                let mut assets = assets.lock().unwrap();
-                let success = match data {
-                    FilePayload::Tunnel(tunnel) => match tunnel.build(file_name.clone()) {
-                        Ok(tunnel) => {
-                            assets.tunnels.push(tunnel);
-                            true
-                        }
-                        Err(err) => {
-                            tracing::warn!(file = file_name, "{err}\n");
-                            false
-                        }
-                    },
-                    FilePayload::Room(room) => match room.build(file_name.clone()) {
-                        Ok(room) => {
-                            assets.rooms.push(room);
-                            true
-                        }
-                        Err(err) => {
-                            tracing::warn!{
-                                "validation failed for room \"{file_name}\", problems:\n{err}"
-                            };
-                            false
-                        }
-                    },
-                };
-
-                let mut stats = stats.lock().unwrap();
-                if success {
-                    stats.succeeded += 1;
-                } else {
-                    stats.failed += 1;
-                }
-            });
-        }
-    });
-
-    let assets = Arc::try_unwrap(assets)
-        .map_err(|_| anyhow!("unwrapping assets failed"))?
-        .into_inner()?;
-
-    Ok(assets)
-}
-
-fn load_file_payload(env: Environment, file: PathBuf) -> (bool, Option<FilePayload>) {
-    let fail = |step: &str, error: &anyhow::Error| {
-        warn!(
-            file = file.display().to_string(),
-            step,
-            error = error.to_string(),
-            "fail"
-        );
-    };
-
-    let text = match read_file(&file) {
-        Ok(data) => data,
-        Err(error) => {
-            fail("read", &error);
-            return (false, None);
-        }
-    };
-    let data = match deserialize_file(text) {
-        Ok(data) => data,
-        Err(error) => {
-            fail("deserialize", &error);
-            return (false, None);
-        }
-    };
-    if !data.environment().should_include_for(env) {
-        debug!(
-            file = file.display().to_string(),
-            step = "filter_by_environment",
-            "skip"
-        );
-        return (true, None);
-    }
-
-    (false, Some(data))
-}
-
-fn read_file(file: &Path) -> anyhow::Result<String> {
-    let mut file = File::open(file)?;
-    let mut text = String::new();
-    file.read_to_string(&mut text)?;
-
-    Ok(text)
-}
-
-fn deserialize_file(text: String) -> anyhow::Result<FilePayload> {
-    let data = ron::from_str(&text)?;
-
-    Ok(data)
-}
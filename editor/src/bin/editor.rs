@@ -19,15 +19,7 @@ use editor_lib::{
     gizmos::EditorGizmosPlugin, mode::EditorModesPlugin, picking::PickingPlugin,
     state::EditorState, ui::EditorUiPlugin,
 };
-use lib::{
-    materials::{CaveMaterialExtension, LineMaterialPlugin},
-    player::PlayerPlugin,
-    render_layer,
-    worldgen::{
-        layout::{self, InitLayoutCommand, LayoutPlugin},
-        terrain::TerrainPlugin,
-    },
-};
+use lib::{prelude::*, render_layer, worldgen::layout};
 
 fn main() {
     let mut app = App::new();
@@ -103,5 +95,8 @@ fn setup(mut commands: Commands) {
 }
 
 fn init_layout(mut commands: Commands) {
-    commands.queue(InitLayoutCommand { after: default() });
+    commands.queue(InitLayoutCommand {
+        after: default(),
+        from_save: None,
+    });
 }
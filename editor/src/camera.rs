@@ -1,6 +1,13 @@
 use core::f32;
+use std::ops::RangeInclusive;
 
-use bevy::{prelude::*, render::view::RenderLayers};
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    prelude::*,
+    render::view::RenderLayers,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_egui::EguiContexts;
 use bevy_trackball::{
     prelude::{Bound, Clamp, Scope},
     TrackballCamera, TrackballController, TrackballInput, TrackballVelocity, TrackballWheelUnit,
@@ -11,25 +18,79 @@ use transform_gizmo_bevy::GizmoCamera;
 
 use crate::state::{EditorMode, EditorState, EditorViewMode};
 
+/// Switches between orbiting the camera and flying it with WASD and mouse look.
+const FLY_TOGGLE_KEY: KeyCode = KeyCode::KeyF;
+const FLY_SENSITIVITY: f32 = 0.003;
+/// Meters per second. Scrolling multiplies or divides the speed by [`FLY_SPEED_STEP`].
+const FLY_DEFAULT_SPEED: f32 = 8.0;
+const FLY_SPEED_RANGE: RangeInclusive<f32> = 0.5..=256.0;
+const FLY_SPEED_STEP: f32 = 1.25;
+/// Speed multiplier while shift is held.
+const FLY_BOOST: f32 = 3.0;
+/// Distance to the point the trackball orbits around after flying.
+const FLY_TARGET_DISTANCE: f32 = 16.0;
+
 #[derive(Component)]
 pub struct AllowOrbit(pub bool);
 
+/// Present on the camera while it's flying. The trackball controller is removed so it doesn't
+/// fight over the camera, and the trackball frame follows the camera so the pose is kept when
+/// orbiting again.
+#[derive(Component)]
+pub struct Flying {
+    /// Buttons of the removed controller, which may have been swapped.
+    orbit_button: Option<MouseButton>,
+    slide_button: Option<MouseButton>,
+}
+
+impl Flying {
+    fn restore_controller(&self) -> TrackballController {
+        let mut controller = trackball_controller();
+        controller.input.orbit_button = self.orbit_button;
+        controller.input.slide_button = self.slide_button;
+        controller
+    }
+}
+
+#[derive(Resource)]
+pub struct FlySpeed(pub f32);
+
+impl Default for FlySpeed {
+    fn default() -> Self {
+        Self(FLY_DEFAULT_SPEED)
+    }
+}
+
 pub fn on_change_mode(
     mut commands: Commands,
     state: Res<EditorState>,
+    window: Option<Single<&mut Window, With<PrimaryWindow>>>,
     trackball: Option<
         Single<(
             Entity,
-            &mut TrackballController,
+            Option<&mut TrackballController>,
             &mut TrackballCamera,
             &mut AllowOrbit,
+            Option<&Flying>,
         )>,
     >,
 ) {
     let Some(trackball) = trackball else {
         return;
     };
-    let (entity, mut controller, mut camera, mut allow_orbit) = trackball.into_inner();
+    let (entity, controller, mut camera, mut allow_orbit, flying) = trackball.into_inner();
+    let mut restored = None;
+    let controller = match controller {
+        Some(controller) => controller.into_inner(),
+        None => {
+            // Switching modes stops flying
+            if let Some(window) = window {
+                release_cursor(window.into_inner());
+            }
+            commands.entity(entity).remove::<Flying>();
+            restored.insert(flying.map_or_else(trackball_controller, Flying::restore_controller))
+        }
+    };
     let (d, mut target, up, eps) = (16.0, Point3::origin(), &Vector3::y_axis(), f32::EPSILON);
 
     let mut block_orbit = false;
@@ -78,6 +139,9 @@ pub fn on_change_mode(
     camera.reset = camera.frame;
     camera.clamp = clamp(!block_orbit);
     allow_orbit.0 = !block_orbit;
+    if let Some(controller) = restored {
+        commands.entity(entity).insert(controller);
+    }
 }
 
 fn clamp(allow_orbit: bool) -> Option<Box<dyn Clamp<f32>>> {
@@ -93,10 +157,7 @@ fn clamp(allow_orbit: bool) -> Option<Box<dyn Clamp<f32>>> {
     }))
 }
 
-pub fn setup(mut commands: Commands) {
-    let mut scope = Scope::default();
-    scope.set_ortho(true);
-
+fn trackball_controller() -> TrackballController {
     let mut controller = TrackballController::default();
     controller.input = TrackballInput {
         velocity: TrackballVelocity::default(),
@@ -136,10 +197,17 @@ pub fn setup(mut commands: Commands) {
         scale_out_key: None,
     };
 
+    controller
+}
+
+pub fn setup(mut commands: Commands) {
+    let mut scope = Scope::default();
+    scope.set_ortho(true);
+
     commands.spawn((
         RenderLayers::from_layers(&[render_layer::WORLD, render_layer::EDITOR]),
         AllowOrbit(false),
-        controller,
+        trackball_controller(),
         TrackballCamera::look_at(Vec3::ZERO, Vec3::new(0.00, 16.0, f32::EPSILON), Vec3::Y)
             .with_scope(scope)
             .with_blend(0.0),
@@ -153,3 +221,113 @@ pub fn setup(mut commands: Commands) {
         },
     ));
 }
+
+//
+// Flying
+//
+
+pub fn toggle_fly(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    window: Single<&mut Window, With<PrimaryWindow>>,
+    camera: Option<
+        Single<(
+            Entity,
+            &AllowOrbit,
+            Option<&TrackballController>,
+            Option<&Flying>,
+        )>,
+    >,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let (entity, allow_orbit, controller, flying) = camera.into_inner();
+
+    if let Some(flying) = flying {
+        if keyboard.any_just_pressed([FLY_TOGGLE_KEY, KeyCode::Escape]) {
+            release_cursor(window.into_inner());
+            commands
+                .entity(entity)
+                .remove::<Flying>()
+                .insert(flying.restore_controller());
+        }
+    } else if let Some(controller) = controller {
+        if !allow_orbit.0
+            || !keyboard.just_pressed(FLY_TOGGLE_KEY)
+            || contexts.ctx_mut().wants_keyboard_input()
+        {
+            return;
+        }
+
+        let mut window = window.into_inner();
+        window.cursor_options.grab_mode = CursorGrabMode::Locked;
+        window.cursor_options.visible = false;
+        commands
+            .entity(entity)
+            .remove::<TrackballController>()
+            .insert(Flying {
+                orbit_button: controller.input.orbit_button,
+                slide_button: controller.input.slide_button,
+            });
+    }
+}
+
+pub fn fly(
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    motion: Res<AccumulatedMouseMotion>,
+    scroll: Res<AccumulatedMouseScroll>,
+    mut speed: ResMut<FlySpeed>,
+    camera: Option<Single<(&mut Transform, &mut TrackballCamera), With<Flying>>>,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let (mut transform, mut trackball) = camera.into_inner();
+
+    if scroll.delta.y != 0.0 {
+        speed.0 = (speed.0 * FLY_SPEED_STEP.powf(scroll.delta.y.signum()))
+            .clamp(*FLY_SPEED_RANGE.start(), *FLY_SPEED_RANGE.end());
+    }
+
+    let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    yaw -= motion.delta.x * FLY_SENSITIVITY;
+    pitch = (pitch - motion.delta.y * FLY_SENSITIVITY).clamp(-1.54, 1.54);
+    transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+
+    let mut direction = Vec3::ZERO;
+    for (key, axis) in [
+        (KeyCode::KeyW, *transform.forward()),
+        (KeyCode::KeyS, *transform.back()),
+        (KeyCode::KeyA, *transform.left()),
+        (KeyCode::KeyD, *transform.right()),
+        (KeyCode::KeyE, Vec3::Y),
+        (KeyCode::KeyQ, Vec3::NEG_Y),
+    ] {
+        if keyboard.pressed(key) {
+            direction += axis;
+        }
+    }
+    let boost = if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        FLY_BOOST
+    } else {
+        1.0
+    };
+    transform.translation += direction.normalize_or_zero() * speed.0 * boost * time.delta_secs();
+
+    let eye = transform.translation;
+    let target = eye + transform.forward() * FLY_TARGET_DISTANCE;
+    trackball
+        .frame
+        .set_target(Point3::new(target.x, target.y, target.z));
+    trackball
+        .frame
+        .set_eye(&Point3::new(eye.x, eye.y, eye.z), &Vector3::y_axis());
+}
+
+fn release_cursor(window: &mut Window) {
+    window.cursor_options.grab_mode = CursorGrabMode::None;
+    window.cursor_options.visible = true;
+}
@@ -9,18 +9,20 @@ use bevy::{
 };
 use strum::IntoEnumIterator;
 
-use super::{Room, RoomPart, RoomPartPayload, Tunnel};
+use super::{reachability::unreachable_pockets, Room, RoomPart, RoomPartPayload, Tunnel};
 use lib::worldgen::{
-    asset::{self, PortalDirection, RoomFlags, Spawnpoint},
+    asset::{self, PortalDirection, RoomBrush, RoomFlags, Spawnpoint},
+    brush::BrushOperation,
     utility::safe_vhacd,
 };
 
 impl Tunnel {
     pub fn build(&self, source: String) -> anyhow::Result<asset::Tunnel> {
+        self.validate()?;
         Ok(asset::Tunnel {
             source,
             weight: self.rarity.weight(),
-            points: self.points,
+            points: self.resampled(),
         })
     }
 }
@@ -28,17 +30,36 @@ impl Tunnel {
 impl Room {
     pub fn build(&self, source: String) -> anyhow::Result<asset::Room> {
         let mut room = asset::Room::new(self.rarity.weight(), source)?;
+        room.atmosphere = self.atmosphere;
+        room.content = self.content;
+        room.reverb = self.reverb;
+        room.paint = self.paint.clone();
+        if self.high_detail {
+            room.flags |= RoomFlags::HighDetail;
+        }
+        if self.boss {
+            room.flags |= RoomFlags::Boss;
+        }
 
         // TODO adjust transform so everything is centered on world origin
         // each roompart must implement compute_aabb()
 
+        // Names of the parts the cavities came from, so problems can point at them
+        let mut cavity_parts = Vec::<String>::new();
+
         for part in self.parts.values().cloned() {
             let RoomPart {
-                transform, data, ..
+                uuid,
+                transform,
+                data,
+                ..
             } = part;
 
             match data {
                 RoomPartPayload::Stl {
+                    path,
+                    material,
+                    operation,
                     vertices,
                     indices,
                     vhacd_parameters,
@@ -53,7 +74,16 @@ impl Room {
                     .transformed_by(transform);
 
                     let collider = safe_vhacd(&mesh, &vhacd_parameters)?;
-                    room.cavities.push(collider);
+                    if operation == BrushOperation::Carve {
+                        room.cavities.push(collider);
+                        cavity_parts.push(format!("{path} ({})", &uuid.to_string()[..8]));
+                    } else {
+                        room.brushes.push(RoomBrush {
+                            collider,
+                            operation,
+                            material,
+                        });
+                    }
                 }
                 RoomPartPayload::Portal { direction } => {
                     room.portals.push(asset::Portal {
@@ -69,10 +99,71 @@ impl Room {
                         angle: transform.rotation.to_euler(EulerRot::YXZ).0,
                     })
                 }
+                RoomPartPayload::Doorway { spec, channel } => {
+                    room.doorways.push(asset::Doorway {
+                        transform,
+                        spec,
+                        channel,
+                    });
+                }
+                RoomPartPayload::Switch { spec } => {
+                    room.switches.push(asset::Switch { transform, spec });
+                }
+                RoomPartPayload::Annotation { kind } => {
+                    room.annotations.push(asset::Annotation { transform, kind });
+                }
+                RoomPartPayload::PatrolPath { points, looped } => {
+                    room.patrol_paths.push(asset::PatrolPath {
+                        points: points
+                            .into_iter()
+                            .map(|point| transform.transform_point(point))
+                            .collect(),
+                        looped,
+                    });
+                }
+                RoomPartPayload::GravityZone { gravity } => {
+                    room.gravity_zones
+                        .push(asset::GravityZone { transform, gravity });
+                }
+                RoomPartPayload::FluidVolume { level } => {
+                    room.fluid_volumes
+                        .push(asset::FluidVolume { transform, level });
+                }
+                RoomPartPayload::AudioZone {
+                    ambience,
+                    volume,
+                    reverb,
+                } => {
+                    room.audio_zones.push(asset::AudioZone {
+                        transform,
+                        ambience: (!ambience.is_empty()).then_some(ambience),
+                        volume,
+                        reverb,
+                    });
+                }
+                RoomPartPayload::Light {
+                    color,
+                    intensity,
+                    flicker,
+                } => {
+                    room.lights.push(asset::LightFixture {
+                        transform,
+                        color,
+                        intensity,
+                        flicker,
+                    });
+                }
             }
         }
 
-        let problems = validate(&room);
+        if room.exit_count() == 0 {
+            room.flags |= RoomFlags::DeadEnd;
+        }
+
+        let mut problems = validate(&room);
+        if problems.is_empty() {
+            problems = unreachable_pockets(&room, &cavity_parts);
+        }
         if problems.len() > 0 {
             let problems = problems
                 .into_iter()
@@ -92,6 +183,7 @@ fn validate(
         cavities,
         portals,
         spawnpoints,
+        patrol_paths,
         ..
     }: &asset::Room,
 ) -> Vec<String> {
@@ -162,12 +254,11 @@ fn validate(
     let exits = *valid_portals.get(&PortalDirection::Exit).unwrap();
     let bidirectionals = *valid_portals.get(&PortalDirection::Bidirectional).unwrap();
 
+    // Rooms without exits are allowed, they're flagged as dead ends
     if entrances == 0 && exits == 0 && bidirectionals < 2 {
         problems.push("no valid entrance or exit".into());
     } else if entrances == 0 && exits == 1 && bidirectionals == 0 {
         problems.push("no valid entrance".into());
-    } else if entrances == 1 && exits == 0 && bidirectionals == 0 {
-        problems.push("no valid exit".into());
     }
 
     // Spawnpoints
@@ -184,5 +275,12 @@ fn validate(
         problems.push("out-of-bounds spawnpoint(s)".into());
     }
 
+    // Patrol paths
+    for (i, path) in patrol_paths.iter().enumerate() {
+        if path.points.len() < 2 {
+            problems.push(format!("patrol path [{i}] has fewer than 2 points"));
+        }
+    }
+
     problems
 }
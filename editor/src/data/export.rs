@@ -0,0 +1,108 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use strum::EnumIter;
+
+use super::room::stl_to_bevy_transform;
+
+/// Exported geometry is written here, next to the STLs it can be re-imported alongside.
+pub const EXPORT_DIRECTORY: &str = "assets/stl/export";
+
+#[derive(EnumIter, strum_macros::Display, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[strum(to_string = "STL")]
+    Stl,
+    #[strum(to_string = "OBJ")]
+    Obj,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Stl => "stl",
+            ExportFormat::Obj => "obj",
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        Path::new(EXPORT_DIRECTORY).join(format!("{name}.{}", self.extension()))
+    }
+}
+
+/// Write an indexed triangle list. STLs are converted back to the axes they're imported from, so
+/// an exported STL re-imports unchanged. OBJs are already Y up, so they're written as is.
+pub fn export_geometry(
+    path: &Path,
+    format: ExportFormat,
+    vertices: &[[f32; 3]],
+    indices: &[u32],
+) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        ExportFormat::Stl => {
+            let bevy_to_stl_transform = stl_to_bevy_transform().compute_affine().inverse();
+            let triangles = indices
+                .chunks_exact(3)
+                .map(|face| {
+                    let [a, b, c] = [0, 1, 2].map(|i| {
+                        bevy_to_stl_transform.transform_point3(vertices[face[i] as usize].into())
+                    });
+                    let normal = (b - a).cross(c - a).normalize_or_zero();
+
+                    stl_io::Triangle {
+                        normal: stl_io::Normal::new(normal.into()),
+                        vertices: [a, b, c].map(|v| stl_io::Vertex::new(v.into())),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            stl_io::write_stl(&mut writer, triangles.iter())?;
+        }
+        ExportFormat::Obj => {
+            for [x, y, z] in vertices {
+                writeln!(writer, "v {x} {y} {z}")?;
+            }
+            for face in indices.chunks_exact(3) {
+                // OBJ indices start at 1
+                writeln!(writer, "f {} {} {}", face[0] + 1, face[1] + 1, face[2] + 1)?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Append a mesh to an indexed triangle list, transforming it into world space.
+pub fn append_mesh_geometry(
+    mesh: &Mesh,
+    transform: &GlobalTransform,
+    vertices: &mut Vec<[f32; 3]>,
+    indices: &mut Vec<u32>,
+) {
+    let Some(positions) = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .and_then(|positions| positions.as_float3())
+    else {
+        return;
+    };
+    let Some(mesh_indices) = mesh.indices() else {
+        return;
+    };
+
+    let offset = vertices.len() as u32;
+    vertices.extend(
+        positions
+            .iter()
+            .map(|v| transform.transform_point(Vec3::from(*v)).into()),
+    );
+    indices.extend(mesh_indices.iter().map(|i| offset + i as u32));
+}
@@ -3,9 +3,14 @@ use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 
 mod build;
+mod export;
+mod pipeline;
+mod reachability;
 mod room;
 mod tunnel;
 mod utility;
+pub use export::*;
+pub use pipeline::*;
 pub use room::*;
 pub use tunnel::*;
 
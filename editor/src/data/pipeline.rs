@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use lib::worldgen::asset::{self, AssetCollection};
+
+use super::{Environment, Room, RoomPartPayload};
+use crate::state::{EditorMode, FilePayload, FilePickerState, FILENAME_CHARS, FILENAME_MAX_CHARS};
+
+/// Rooms with more portals than this crowd the layout and are hard to place.
+const MAX_PORTALS: usize = 8;
+
+/// Output file prefix used by the editor.
+pub const COLLECTION_NAME: &str = "worldgen";
+
+#[derive(strum_macros::Display, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    Built,
+    /// Not included in the environment being built.
+    Skipped,
+    Failed,
+}
+
+#[derive(Clone, Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub status: FileStatus,
+    /// Problems that keep the file out of the collection.
+    pub errors: Vec<String>,
+    /// Problems that don't stop the file from building, but probably should be fixed.
+    pub warnings: Vec<String>,
+}
+
+impl FileReport {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            status: FileStatus::Built,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn fail(mut self, step: &str, error: impl ToString) -> Self {
+        self.status = FileStatus::Failed;
+        self.errors.push(format!("{step}: {}", error.to_string()));
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BuildReport {
+    pub env: Environment,
+    /// Sorted by path.
+    pub files: Vec<FileReport>,
+}
+
+impl BuildReport {
+    pub fn count(&self, status: FileStatus) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.status == status)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.files.iter().map(|file| file.warnings.len()).sum()
+    }
+
+    /// Whether the collection is complete. Collections with failed files are missing assets.
+    pub fn is_complete(&self) -> bool {
+        self.count(FileStatus::Built) > 0 && self.count(FileStatus::Failed) == 0
+    }
+}
+
+enum BuiltAsset {
+    Tunnel(asset::Tunnel),
+    Room(asset::Room),
+}
+
+/// Returns the name of the file a collection is written to, e.g. `worldgen.staging.cbor`.
+pub fn collection_file_name(name: &str, env: Environment, extension: &str) -> String {
+    format!("{name}.{}.{extension}", env.to_string().to_lowercase())
+}
+
+/// Lists every editor file in the directory, skipping hidden files.
+pub fn find_asset_files(directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(directory) {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_dir() || entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if EditorMode::from_path(path).is_ok() {
+            files.push(path.to_owned());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Builds every editor file in the directory that's included in the environment. Files that
+/// fail are left out of the collection, so check the report before writing it.
+pub fn build_collection(
+    directory: &Path,
+    env: Environment,
+) -> anyhow::Result<(AssetCollection, BuildReport)> {
+    let mut files = find_asset_files(directory)?;
+    files.sort();
+
+    let results = files
+        .par_iter()
+        .map(|path| build_file(path, env))
+        .collect::<Vec<_>>();
+
+    let mut assets = AssetCollection::default();
+    let mut report = BuildReport {
+        env,
+        files: Vec::with_capacity(results.len()),
+    };
+    for (file, built) in results {
+        match built {
+            Some(BuiltAsset::Tunnel(tunnel)) => assets.tunnels.push(tunnel),
+            Some(BuiltAsset::Room(room)) => assets.rooms.push(room),
+            None => {}
+        }
+        report.files.push(file);
+    }
+
+    Ok((assets, report))
+}
+
+/// Writes the collection as CBOR. Returns the number of bytes written.
+pub fn write_collection(path: &Path, assets: &AssetCollection) -> anyhow::Result<u64> {
+    let bytes = cbor4ii::serde::to_vec(Vec::new(), assets)?;
+    File::create(path)?.write_all(&bytes)?;
+
+    Ok(bytes.len() as u64)
+}
+
+fn build_file(path: &Path, env: Environment) -> (FileReport, Option<BuiltAsset>) {
+    let mut report = FileReport::new(path);
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => return (report.fail("read", error), None),
+    };
+    let data = match FilePayload::from_ron(&text) {
+        Ok((data, migrations)) => {
+            report.warnings.extend(
+                migrations
+                    .into_iter()
+                    .map(|migration| format!("migrated ({migration}), save the file to update it")),
+            );
+            data
+        }
+        Err(error) => return (report.fail("deserialize", error), None),
+    };
+    if !data.environment().should_include_for(env) {
+        report.status = FileStatus::Skipped;
+        report.warnings.clear();
+        return (report, None);
+    }
+
+    report.warnings.extend(naming_problems(path));
+
+    let source = path.display().to_string();
+    let built = match data {
+        FilePayload::Tunnel(tunnel) => tunnel.build(source).map(BuiltAsset::Tunnel),
+        FilePayload::Room(room) => {
+            report.warnings.extend(non_manifold_brushes(&room));
+            room.build(source).map(BuiltAsset::Room)
+        }
+    };
+
+    match built {
+        Ok(built) => {
+            if let BuiltAsset::Room(room) = &built {
+                report.warnings.extend(portal_problems(room));
+            }
+            (report, Some(built))
+        }
+        Err(error) => {
+            // Room validation lists one problem per line
+            report.status = FileStatus::Failed;
+            report.errors.extend(
+                error
+                    .to_string()
+                    .lines()
+                    .map(|line| line.trim_start_matches("- ").to_owned()),
+            );
+            (report, None)
+        }
+    }
+}
+
+/// Names the file browser wouldn't allow, e.g. files created outside the editor.
+fn naming_problems(path: &Path) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let (Ok(mode), Some(name)) = (EditorMode::from_path(path), path.file_name()) else {
+        return problems;
+    };
+    let name = name.to_string_lossy();
+    let stem = name
+        .strip_suffix(&FilePickerState::file_ext_for_mode(&mode))
+        .unwrap_or(&name);
+
+    if stem.chars().count() > FILENAME_MAX_CHARS {
+        problems.push(format!(
+            "name is longer than {FILENAME_MAX_CHARS} characters"
+        ));
+    }
+    if !stem.chars().all(|c| FILENAME_CHARS.contains(c)) {
+        problems.push("name should only contain lowercase letters, digits, - and _".into());
+    }
+
+    problems
+}
+
+/// Preview brushes are meshed from the STL directly, so holes and stray faces show up in the
+/// editor even though the built room uses convex hulls.
+fn non_manifold_brushes(room: &Room) -> Vec<String> {
+    let mut parts = room.parts.values().collect::<Vec<_>>();
+    parts.sort_by_key(|part| part.uuid);
+
+    parts
+        .into_iter()
+        .filter_map(|part| {
+            let RoomPartPayload::Stl {
+                path,
+                vertices,
+                indices,
+                ..
+            } = &part.data
+            else {
+                return None;
+            };
+
+            let bad_edges = non_manifold_edge_count(vertices, indices);
+            (bad_edges > 0).then(|| {
+                format!(
+                    "STL part {path} ({}) isn't manifold, {bad_edges} edges aren't shared by \
+                     exactly two faces",
+                    &part.uuid.to_string()[..8]
+                )
+            })
+        })
+        .collect()
+}
+
+/// Counts edges that don't belong to exactly two triangles. STL files usually repeat vertices
+/// for every triangle, so vertices are matched by position rather than index.
+fn non_manifold_edge_count(vertices: &[[f32; 3]], indices: &[u32]) -> usize {
+    let key = |index: u32| vertices[index as usize].map(f32::to_bits);
+
+    let mut edges = HashMap::<_, u32>::new();
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            let (a, b) = (key(triangle[a]), key(triangle[b]));
+            *edges
+                .entry(if a < b { (a, b) } else { (b, a) })
+                .or_default() += 1;
+        }
+    }
+
+    edges.values().filter(|count| **count != 2).count()
+}
+
+fn portal_problems(room: &asset::Room) -> Option<String> {
+    (room.portals.len() > MAX_PORTALS).then(|| {
+        format!(
+            "{} portals, rooms with more than {MAX_PORTALS} are hard to place",
+            room.portals.len()
+        )
+    })
+}
@@ -0,0 +1,236 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use avian3d::prelude::{AnyCollider, Collider, Position, Rotation};
+use bevy::prelude::*;
+use lib::{
+    player::consts::{PLAYER_HEIGHT, PLAYER_RADIUS},
+    worldgen::{asset, brush::BrushOperation},
+};
+
+/// Smallest cell used to voxelize the room. Large rooms use larger cells to stay under
+/// [`MAX_CELLS`].
+const MIN_CELL_SIZE: f32 = 0.5;
+const MAX_CELLS: usize = 2_000_000;
+/// Pockets smaller than this many cubic units are ignored, e.g. crevices and sharp corners.
+const MIN_POCKET_VOLUME: f32 = 2.0;
+
+/// Voxelized open space of a room.
+struct Grid {
+    min: Vec3,
+    cell_size: f32,
+    size: UVec3,
+    /// Index of the first cavity containing each open cell.
+    open: Vec<Option<usize>>,
+}
+
+impl Grid {
+    fn new(room: &asset::Room) -> Option<Self> {
+        let aabb = room
+            .cavities
+            .iter()
+            .map(|cavity| cavity.aabb(Vec3::ZERO, Quat::IDENTITY))
+            .reduce(|a, b| a.merged(b))?;
+        let extents = aabb.max - aabb.min;
+        let volume = extents.element_product().max(0.0);
+        let cell_size = MIN_CELL_SIZE.max((volume / MAX_CELLS as f32).cbrt());
+        let size = (extents / cell_size).ceil().as_uvec3().max(UVec3::ONE);
+
+        let mut grid = Self {
+            min: aabb.min,
+            cell_size,
+            size,
+            open: vec![None; size.element_product() as usize],
+        };
+        let fill_brushes = room
+            .brushes
+            .iter()
+            .filter(|brush| brush.operation == BrushOperation::Fill)
+            .map(|brush| &brush.collider)
+            .collect::<Vec<_>>();
+
+        for i in 0..grid.open.len() {
+            let point = grid.center(grid.cell(i));
+            let contains = |collider: &Collider| {
+                collider.contains_point(Position::default(), Rotation::default(), point)
+            };
+            if fill_brushes.iter().copied().any(&contains) {
+                continue;
+            }
+            grid.open[i] = room.cavities.iter().position(&contains);
+        }
+
+        Some(grid)
+    }
+
+    fn cell(&self, index: usize) -> IVec3 {
+        let index = index as u32;
+        UVec3::new(
+            index % self.size.x,
+            index / self.size.x % self.size.y,
+            index / (self.size.x * self.size.y),
+        )
+        .as_ivec3()
+    }
+
+    fn index(&self, cell: IVec3) -> Option<usize> {
+        if cell.cmplt(IVec3::ZERO).any() || cell.cmpge(self.size.as_ivec3()).any() {
+            return None;
+        }
+        let cell = cell.as_uvec3();
+        Some((cell.x + cell.y * self.size.x + cell.z * self.size.x * self.size.y) as usize)
+    }
+
+    fn center(&self, cell: IVec3) -> Vec3 {
+        self.min + (cell.as_vec3() + 0.5) * self.cell_size
+    }
+
+    fn is_open(&self, cell: IVec3) -> bool {
+        self.index(cell).is_some_and(|i| self.open[i].is_some())
+    }
+}
+
+/// Flood fills the room's open space from its entrances with the player's capsule, and reports
+/// open space it can't reach. Jumping and gravity aren't considered, so this only catches pockets
+/// that are sealed off or too narrow for the player.
+pub(super) fn unreachable_pockets(room: &asset::Room, cavity_parts: &[String]) -> Vec<String> {
+    let mut problems = Vec::<String>::new();
+    let Some(grid) = Grid::new(room) else {
+        return problems;
+    };
+
+    // Cells covered by the capsule when its center is at the origin. Rounded down, so openings
+    // the player barely fits through aren't reported.
+    let radius = (PLAYER_RADIUS / grid.cell_size).floor() as i32;
+    let half_height = (PLAYER_HEIGHT / 2.0 / grid.cell_size).floor() as i32;
+    let capsule = (-half_height..=half_height)
+        .flat_map(|y| {
+            (-radius..=radius)
+                .flat_map(move |z| (-radius..=radius).map(move |x| IVec3::new(x, y, z)))
+        })
+        .filter(|offset| offset.xz().length_squared() <= radius * radius)
+        .collect::<Vec<_>>();
+    let fits = |cell: IVec3| capsule.iter().all(|offset| grid.is_open(cell + *offset));
+
+    // Start from every cell the capsule fits in near the inward side of each entrance
+    let mut reached = vec![false; grid.open.len()];
+    let mut queue = VecDeque::<IVec3>::new();
+    for (i, portal) in room.portals.iter().enumerate() {
+        if !portal.direction.is_entrance() {
+            continue;
+        }
+
+        let inward = portal.transform.transform_point(Vec3::Y / 2.0);
+        let search_radius = portal.transform.scale.max_element() / 2.0 + grid.cell_size;
+        let search_cells = (search_radius / grid.cell_size).ceil() as i32;
+        let origin = ((inward - grid.min) / grid.cell_size).floor().as_ivec3();
+
+        let mut found = false;
+        for z in -search_cells..=search_cells {
+            for y in -search_cells..=search_cells {
+                for x in -search_cells..=search_cells {
+                    let cell = origin + IVec3::new(x, y, z);
+                    let Some(index) = grid.index(cell) else {
+                        continue;
+                    };
+                    if grid.center(cell).distance(inward) > search_radius || !fits(cell) {
+                        continue;
+                    }
+                    found = true;
+                    if !reached[index] {
+                        reached[index] = true;
+                        queue.push_back(cell);
+                    }
+                }
+            }
+        }
+
+        if !found {
+            problems.push(format!("portal [{i}] is too narrow for the player"));
+        }
+    }
+
+    // Without a way in, everything would be reported
+    if queue.is_empty() {
+        return problems;
+    }
+
+    let neighbors = [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ];
+    while let Some(cell) = queue.pop_front() {
+        for neighbor in neighbors.map(|offset| cell + offset) {
+            let Some(index) = grid.index(neighbor) else {
+                continue;
+            };
+            if !reached[index] && fits(neighbor) {
+                reached[index] = true;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    // Everything the capsule touches along the way is reachable
+    let mut covered = vec![false; grid.open.len()];
+    for (i, _) in reached.iter().enumerate().filter(|(_, reached)| **reached) {
+        let cell = grid.cell(i);
+        for offset in capsule.iter() {
+            if let Some(index) = grid.index(cell + *offset) {
+                covered[index] = true;
+            }
+        }
+    }
+
+    // Group what's left into pockets
+    let cell_volume = grid.cell_size.powi(3);
+    let mut visited = covered;
+    for start in 0..grid.open.len() {
+        if visited[start] || grid.open[start].is_none() {
+            continue;
+        }
+
+        let mut cells = 0;
+        let mut sum = Vec3::ZERO;
+        let mut parts = BTreeSet::<usize>::new();
+        let mut pocket = VecDeque::from([grid.cell(start)]);
+        visited[start] = true;
+
+        while let Some(cell) = pocket.pop_front() {
+            let index = grid.index(cell).unwrap();
+            cells += 1;
+            sum += grid.center(cell);
+            parts.extend(grid.open[index]);
+
+            for neighbor in neighbors.map(|offset| cell + offset) {
+                let Some(index) = grid.index(neighbor) else {
+                    continue;
+                };
+                if !visited[index] && grid.open[index].is_some() {
+                    visited[index] = true;
+                    pocket.push_back(neighbor);
+                }
+            }
+        }
+
+        let volume = cells as f32 * cell_volume;
+        if volume < MIN_POCKET_VOLUME {
+            continue;
+        }
+        let center = sum / cells as f32;
+        let parts = parts
+            .into_iter()
+            .map(|i| cavity_parts.get(i).map_or("?", String::as_str))
+            .collect::<Vec<_>>()
+            .join(", ");
+        problems.push(format!(
+            "unreachable pocket of {volume:.1} cubic units near ({:.1}, {:.1}, {:.1}) in {parts}",
+            center.x, center.y, center.z
+        ));
+    }
+
+    problems
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs::OpenOptions, hash::Hasher};
+use std::{collections::HashMap, fs::OpenOptions, hash::Hasher, path::Path};
 
 use anyhow::anyhow;
 use avian3d::prelude::*;
@@ -13,32 +13,100 @@ use uuid::Uuid;
 
 use crate::picking::PickingMode;
 
-use super::{Environment, Rarity};
-use lib::worldgen::{asset::PortalDirection, brush::TerrainBrushRequest, voxel::VoxelMaterial};
+use super::{export_geometry, Environment, ExportFormat, Rarity};
+use lib::{
+    meshgen::{DoorwaySpec, SwitchSpec},
+    worldgen::{
+        asset::{
+            migration::Versioned, AnnotationKind, PortalDirection, ReverbPreset, RoomAtmosphere,
+            RoomContent,
+        },
+        brush::{paint::PaintStroke, BrushOperation, TerrainBrushRequest},
+        voxel::VoxelMaterial,
+    },
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Room {
+    /// Schema version. Missing from files saved before versioning.
+    #[serde(default)]
+    pub version: u32,
     pub environment: Environment,
     pub rarity: Rarity,
     pub parts: HashMap<Uuid, RoomPart>,
+    #[serde(default)]
+    pub atmosphere: RoomAtmosphere,
+    /// Sample the terrain around this room at a higher resolution.
+    #[serde(default)]
+    pub high_detail: bool,
+    /// Only chosen where the layout asks for a boss room.
+    #[serde(default)]
+    pub boss: bool,
+    #[serde(default)]
+    pub content: RoomContent,
+    /// Estimated from the room's size if not set.
+    #[serde(default)]
+    pub reverb: Option<ReverbPreset>,
+    /// Material painted over the terrain, in the order it was painted.
+    #[serde(default)]
+    pub paint: Vec<PaintStroke>,
+    /// Distances measured in the editor. They're notes for editing, and aren't exported.
+    #[serde(default)]
+    pub measurements: Vec<Measurement>,
 }
 
 impl Default for Room {
     fn default() -> Self {
         Self {
+            version: Self::VERSION,
             environment: Environment::Development,
             rarity: Rarity::Uncommon,
             parts: Default::default(),
+            atmosphere: Default::default(),
+            high_detail: false,
+            boss: false,
+            content: Default::default(),
+            reverb: None,
+            paint: Vec::new(),
+            measurements: Vec::new(),
         }
     }
 }
 
+impl Versioned for Room {
+    const VERSION: u32 = 1;
+    const CHANGES: &'static [&'static str] = &["added schema versions"];
+}
+
 impl Room {
     pub fn push(&mut self, part: RoomPart) {
         self.parts.insert(part.uuid, part);
     }
 }
 
+/// A distance between two points in a room, kept as an annotation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub start: Vec3,
+    pub end: Vec3,
+    #[serde(default)]
+    pub label: String,
+}
+
+impl Measurement {
+    /// Distance and the difference on each axis, for display.
+    pub fn describe(start: Vec3, end: Vec3) -> String {
+        let delta = end - start;
+        format!(
+            "{:.2} m\nx {:.2}  y {:.2}  z {:.2}",
+            start.distance(end),
+            delta.x,
+            delta.y,
+            delta.z
+        )
+    }
+}
+
 #[derive(Component)]
 pub struct RoomPartUuid(pub Uuid, pub Option<u64>);
 
@@ -58,6 +126,8 @@ pub enum RoomPartPayload {
     Stl {
         path: String,
         material: VoxelMaterial,
+        #[serde(default)]
+        operation: BrushOperation,
         vertices: Vec<[f32; 3]>,
         indices: Vec<u32>,
         geometry_hash: u64,
@@ -69,6 +139,48 @@ pub enum RoomPartPayload {
 
     #[strum(props(name = "Spawnpoint"))]
     Spawnpoint,
+
+    #[strum(props(name = "Doorway"))]
+    Doorway {
+        spec: DoorwaySpec,
+        #[serde(default)]
+        channel: Option<u8>,
+    },
+
+    #[strum(props(name = "Switch"))]
+    Switch { spec: SwitchSpec },
+
+    /// Unit cube, scaled by the part's transform.
+    #[strum(props(name = "Annotation"))]
+    Annotation { kind: AnnotationKind },
+
+    /// Points are relative to the part's transform.
+    #[strum(props(name = "Patrol Path"))]
+    PatrolPath { points: Vec<Vec3>, looped: bool },
+
+    /// Unit cube, scaled by the part's transform. Gravity is relative to its rotation.
+    #[strum(props(name = "Gravity Zone"))]
+    GravityZone { gravity: Vec3 },
+
+    /// Unit cube, scaled by the part's transform. Filled with water up to the level.
+    #[strum(props(name = "Fluid Volume"))]
+    FluidVolume { level: f32 },
+
+    /// Unit cube, scaled by the part's transform. An empty ambience plays nothing.
+    #[strum(props(name = "Audio Zone"))]
+    AudioZone {
+        ambience: String,
+        volume: f32,
+        reverb: Option<ReverbPreset>,
+    },
+
+    /// Color is linear RGB, and flicker is how far the intensity dips, from 0 to 1.
+    #[strum(props(name = "Light"))]
+    Light {
+        color: [f32; 3],
+        intensity: f32,
+        flicker: f32,
+    },
 }
 
 impl RoomPart {
@@ -83,6 +195,7 @@ impl RoomPart {
         match data {
             RoomPartPayload::Stl {
                 material,
+                operation,
                 vertices,
                 indices,
                 vhacd_parameters,
@@ -90,6 +203,7 @@ impl RoomPart {
             } => Some(TerrainBrushRequest::Mesh {
                 uuid: (*uuid).into(),
                 material: *material,
+                operation: *operation,
                 transform: *transform,
                 mesh: Mesh::new(
                     PrimitiveTopology::TriangleList,
@@ -112,6 +226,16 @@ impl RoomPart {
                 vec![PickingMode::Selectable, PickingMode::GroundPlane]
             }
             RoomPartPayload::Spawnpoint => vec![PickingMode::Terrain, PickingMode::GroundPlane],
+            RoomPartPayload::Doorway { .. }
+            | RoomPartPayload::Switch { .. }
+            | RoomPartPayload::Annotation { .. }
+            | RoomPartPayload::PatrolPath { .. }
+            | RoomPartPayload::GravityZone { .. }
+            | RoomPartPayload::FluidVolume { .. }
+            | RoomPartPayload::AudioZone { .. }
+            | RoomPartPayload::Light { .. } => {
+                vec![PickingMode::Terrain, PickingMode::GroundPlane]
+            }
         }
     }
 
@@ -122,7 +246,9 @@ impl RoomPart {
     pub fn stl(path: &str, material: VoxelMaterial, transform: Transform) -> anyhow::Result<Self> {
         let (vertices, indices) = load_stl_to_raw_geometry(path)?;
         let vhacd_parameters = VhacdParameters::default();
-        let geometry_hash = hash_geometry(&vertices, &indices, &vhacd_parameters);
+        let operation = BrushOperation::Carve;
+        let geometry_hash =
+            hash_geometry(&vertices, &indices, &vhacd_parameters, operation, material);
 
         Ok(Self {
             uuid: Uuid::new_v4(),
@@ -130,6 +256,7 @@ impl RoomPart {
             data: RoomPartPayload::Stl {
                 path: path.to_owned(),
                 material,
+                operation,
                 vertices,
                 indices,
                 geometry_hash,
@@ -153,6 +280,8 @@ impl RoomPart {
             ref mut indices,
             ref mut geometry_hash,
             ref vhacd_parameters,
+            operation,
+            material,
             path,
             ..
         } = &mut self.data
@@ -161,24 +290,52 @@ impl RoomPart {
         };
 
         (*vertices, *indices) = load_stl_to_raw_geometry(&path)?;
-        *geometry_hash = hash_geometry(&vertices, &indices, &vhacd_parameters);
+        *geometry_hash = hash_geometry(
+            &vertices,
+            &indices,
+            &vhacd_parameters,
+            *operation,
+            *material,
+        );
 
         Ok(())
     }
 
+    /// Write the part's geometry, without its transform, so it can be edited and re-imported.
+    pub fn export_stl(&self, path: &Path, format: ExportFormat) -> anyhow::Result<()> {
+        let RoomPartPayload::Stl {
+            ref vertices,
+            ref indices,
+            ..
+        } = self.data
+        else {
+            return Err(anyhow!("not an stl"));
+        };
+
+        export_geometry(path, format, vertices, indices)
+    }
+
     pub fn rehash_stl(&mut self) -> anyhow::Result<()> {
         let RoomPartPayload::Stl {
             ref vertices,
             ref indices,
             ref mut geometry_hash,
             ref vhacd_parameters,
+            operation,
+            material,
             ..
         } = &mut self.data
         else {
             return Err(anyhow!("not an stl"));
         };
 
-        *geometry_hash = hash_geometry(&vertices, &indices, &vhacd_parameters);
+        *geometry_hash = hash_geometry(
+            &vertices,
+            &indices,
+            &vhacd_parameters,
+            *operation,
+            *material,
+        );
 
         Ok(())
     }
@@ -208,13 +365,138 @@ impl RoomPart {
             place_after_spawn: false,
         }
     }
+
+    //
+    // Doorway
+    //
+
+    pub fn doorway(transform: Transform, spec: DoorwaySpec) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::Doorway {
+                spec,
+                channel: None,
+            },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Switch
+    //
+
+    pub fn switch(transform: Transform, spec: SwitchSpec) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::Switch { spec },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Annotation
+    //
+
+    pub fn annotation(transform: Transform, kind: AnnotationKind) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::Annotation { kind },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Patrol path
+    //
+
+    pub fn patrol_path(transform: Transform) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::PatrolPath {
+                points: vec![Vec3::ZERO, Vec3::Z * 4.0],
+                looped: false,
+            },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Gravity zone
+    //
+
+    pub fn gravity_zone(transform: Transform, gravity: Vec3) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::GravityZone { gravity },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Fluid volume
+    //
+
+    pub fn fluid_volume(transform: Transform, level: f32) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::FluidVolume { level },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Audio zone
+    //
+
+    pub fn audio_zone(transform: Transform) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::AudioZone {
+                ambience: String::new(),
+                volume: 1.0,
+                reverb: None,
+            },
+            place_after_spawn: false,
+        }
+    }
+
+    //
+    // Light
+    //
+
+    pub fn light(transform: Transform) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            transform,
+            data: RoomPartPayload::Light {
+                color: [1.0, 0.8, 0.6],
+                intensity: 200_000.0,
+                flicker: 0.0,
+            },
+            place_after_spawn: false,
+        }
+    }
 }
 
 //
 // Utility
 //
 
-fn hash_geometry(vertices: &[[f32; 3]], indices: &[u32], vhacd: &VhacdParameters) -> u64 {
+/// Also covers how the geometry is applied to the terrain, so changing it rebuilds the brush.
+fn hash_geometry(
+    vertices: &[[f32; 3]],
+    indices: &[u32],
+    vhacd: &VhacdParameters,
+    operation: BrushOperation,
+    material: VoxelMaterial,
+) -> u64 {
     let mut hasher = std::hash::DefaultHasher::new();
 
     vertices
@@ -240,19 +522,48 @@ fn hash_geometry(vertices: &[[f32; 3]], indices: &[u32], vhacd: &VhacdParameters
         false => 0,
     });
     hasher.write_u32(vhacd.max_convex_hulls);
+    hasher.write_u8(operation as u8);
+    hasher.write_u8(material.0);
 
     hasher.finish()
 }
 
-fn load_stl_to_raw_geometry(path: &str) -> anyhow::Result<(Vec<[f32; 3]>, Vec<u32>)> {
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let stl = stl_io::read_stl(&mut file)?;
-    let stl_to_bevy_transform = Transform::from_rotation(Quat::from_euler(
+pub fn hash_doorway(spec: &DoorwaySpec) -> u64 {
+    let mut hasher = std::hash::DefaultHasher::new();
+
+    [
+        spec.frame.min.x,
+        spec.frame.min.y,
+        spec.frame.max.x,
+        spec.frame.max.y,
+        spec.frame_depth,
+        spec.frame_uv_scale,
+        spec.door.min.x,
+        spec.door.min.y,
+        spec.door.max.x,
+        spec.door.max.y,
+        spec.door_depth,
+        spec.door_uv_scale,
+    ]
+    .iter()
+    .for_each(|f| hasher.write_u32(f.to_bits()));
+
+    hasher.finish()
+}
+
+pub(super) fn stl_to_bevy_transform() -> Transform {
+    Transform::from_rotation(Quat::from_euler(
         EulerRot::XZY,
         -90.0_f32.to_radians(),
         180.0_f32.to_radians(),
         0.0,
-    ));
+    ))
+}
+
+pub fn load_stl_to_raw_geometry(path: &str) -> anyhow::Result<(Vec<[f32; 3]>, Vec<u32>)> {
+    let mut file = OpenOptions::new().read(true).open(path)?;
+    let stl = stl_io::read_stl(&mut file)?;
+    let stl_to_bevy_transform = stl_to_bevy_transform();
 
     let vertices = stl
         .vertices
@@ -1,5 +1,6 @@
-use std::f32::consts::PI;
+use std::{collections::BTreeSet, f32::consts::PI, fmt};
 
+use anyhow::anyhow;
 use bevy::{
     asset::RenderAssetUsages,
     math::Vec2,
@@ -8,12 +9,17 @@ use bevy::{
 };
 use curvo::prelude::{KnotStyle, NurbsCurve, NurbsCurve3D, Tessellation};
 use nalgebra::{Const, OPoint, Point2, Point3};
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 
 use super::{Environment, Rarity};
-use lib::worldgen::asset::TUNNEL_POINTS;
+use lib::worldgen::asset::{migration::Versioned, TUNNEL_POINTS};
 
 const TUNNEL_DEFAULT_RADIUS: f32 = 5.0;
+/// Profiles with fewer points can't be interpolated into a closed curve.
+pub const TUNNEL_MIN_POINTS: usize = 4;
 
 pub struct TunnelMeshInfo {
     pub center: Vec2,
@@ -22,20 +28,26 @@ pub struct TunnelMeshInfo {
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Tunnel {
+    /// Schema version. Missing from files saved before versioning.
+    #[serde(default)]
+    pub version: u32,
     pub environment: Environment,
     pub rarity: Rarity,
-    pub points: [Point2<f32>; TUNNEL_POINTS],
+    /// Built tunnels are resampled to [`TUNNEL_POINTS`], so they can blend with each other.
+    pub points: Vec<Point2<f32>>,
 }
 
 impl Default for Tunnel {
     fn default() -> Self {
-        let mut points = [Point2::<f32>::default(); TUNNEL_POINTS];
-        for i in 0..TUNNEL_POINTS {
-            let radians = (i as f32 / TUNNEL_POINTS as f32) * PI * 2.0;
-            points[i] = Point2::new(radians.sin(), -radians.cos()) * TUNNEL_DEFAULT_RADIUS;
-        }
+        let points = (0..TUNNEL_POINTS)
+            .map(|i| {
+                let radians = (i as f32 / TUNNEL_POINTS as f32) * PI * 2.0;
+                Point2::new(radians.sin(), -radians.cos()) * TUNNEL_DEFAULT_RADIUS
+            })
+            .collect();
 
         Self {
+            version: Self::VERSION,
             points,
             environment: Environment::Development,
             rarity: Rarity::Uncommon,
@@ -43,6 +55,75 @@ impl Default for Tunnel {
     }
 }
 
+impl Versioned for Tunnel {
+    const VERSION: u32 = 3;
+    const CHANGES: &'static [&'static str] = &[
+        "added schema versions",
+        "profiles can have any number of points",
+        "profile points are stored as a list",
+    ];
+
+    fn deserialize_legacy<'de, D>(version: u32, deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match version {
+            0..=2 => TunnelV2::deserialize(deserializer).map(Self::from),
+            _ => Self::deserialize(deserializer),
+        }
+    }
+}
+
+/// Tunnels up to version 2, which stored their points as a tuple, like the fixed size arrays
+/// they used to be.
+#[derive(Deserialize)]
+struct TunnelV2 {
+    environment: Environment,
+    rarity: Rarity,
+    #[serde(deserialize_with = "deserialize_point_tuple")]
+    points: Vec<Point2<f32>>,
+}
+
+impl From<TunnelV2> for Tunnel {
+    fn from(tunnel: TunnelV2) -> Self {
+        Self {
+            version: Self::VERSION,
+            environment: tunnel.environment,
+            rarity: tunnel.rarity,
+            points: tunnel.points,
+        }
+    }
+}
+
+fn deserialize_point_tuple<'de, D>(deserializer: D) -> Result<Vec<Point2<f32>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct PointTupleVisitor;
+
+    impl<'de> Visitor<'de> for PointTupleVisitor {
+        type Value = Vec<Point2<f32>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a tuple of points")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut points = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(point) = seq.next_element()? {
+                points.push(point);
+            }
+            Ok(points)
+        }
+    }
+
+    // The length is ignored by self-describing formats, which read as many points as there are
+    deserializer.deserialize_tuple(usize::MAX, PointTupleVisitor)
+}
+
 impl Tunnel {
     pub fn to_3d_xz(&self) -> Vec<OPoint<f32, Const<3>>> {
         self.points
@@ -51,8 +132,9 @@ impl Tunnel {
             .collect()
     }
 
+    /// The profile the tunnel is built with, scaled.
     pub fn to_3d_xy_scaled(&self, scale: Vec2) -> Vec<OPoint<f32, Const<3>>> {
-        self.points
+        self.resampled()
             .iter()
             .map(|p| Point3::new(p.x * scale.x, p.y * scale.y, 0.0))
             .collect()
@@ -85,6 +167,113 @@ impl Tunnel {
             point.y -= info.center.y;
         }
     }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.points.len() < TUNNEL_MIN_POINTS {
+            return Err(anyhow!(
+                "tunnel profile has {} points, but needs at least {TUNNEL_MIN_POINTS}",
+                self.points.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Points spaced evenly along the curve through the profile, which is how it's built.
+    /// Profiles that already have [`TUNNEL_POINTS`] are kept as they are.
+    pub fn resampled(&self) -> [Point2<f32>; TUNNEL_POINTS] {
+        if let Ok(points) = self.points.as_slice().try_into() {
+            return points;
+        }
+
+        let curve = self.to_curve_3d();
+        let (start, end) = curve.knots_domain();
+        std::array::from_fn(|i| {
+            let t = start + (end - start) * i as f32 / TUNNEL_POINTS as f32;
+            let point = curve.point_at(t);
+            Point2::new(point.x, point.z)
+        })
+    }
+
+    /// The points, with the points across the axis added when mirroring.
+    pub fn with_mirrored(&self, indices: &BTreeSet<usize>, mirror: bool) -> BTreeSet<usize> {
+        let len = self.points.len();
+        let mut indices = indices.clone();
+        if mirror {
+            indices.extend(indices.clone().into_iter().map(|i| (len - i) % len));
+        }
+        indices
+    }
+
+    /// Inserts a point on the segment from `segment` to the point after it, and on the segment
+    /// across the axis when mirroring. Returns the index of the inserted point.
+    pub fn insert_point(&mut self, segment: usize, point: Point2<f32>, mirror: bool) -> usize {
+        let len = self.points.len();
+        let at = segment + 1;
+        // The mirror of the segment runs between the mirrors of its points, in reverse
+        let mirror_at = len - segment;
+
+        if !mirror || mirror_at == at {
+            self.points.insert(at, point);
+            return at;
+        }
+
+        let mirrored = Point2::new(-point.x, point.y);
+        if mirror_at > at {
+            self.points.insert(mirror_at, mirrored);
+            self.points.insert(at, point);
+            at
+        } else {
+            self.points.insert(at, point);
+            self.points.insert(mirror_at, mirrored);
+            at + 1
+        }
+    }
+
+    /// Removes the points, and the points across the axis when mirroring. The points on the axis
+    /// are kept when mirroring, since removing them would break the symmetry.
+    pub fn remove_points(&mut self, indices: &BTreeSet<usize>, mirror: bool) -> anyhow::Result<()> {
+        let len = self.points.len();
+        let mut indices = self.with_mirrored(indices, mirror);
+        if mirror {
+            indices.retain(|i| *i != 0 && *i != len / 2);
+        }
+        if len - indices.len() < TUNNEL_MIN_POINTS {
+            return Err(anyhow!(
+                "tunnel profiles need at least {TUNNEL_MIN_POINTS} points"
+            ));
+        }
+
+        for i in indices.into_iter().rev() {
+            self.points.remove(i);
+        }
+        Ok(())
+    }
+
+    /// Moves the points evenly onto the segments between their nearest unselected neighbours,
+    /// removing their influence on the curve while keeping the number of points fixed.
+    pub fn flatten(&mut self, indices: &BTreeSet<usize>) {
+        let len = self.points.len();
+        let Some(start) = (0..len).find(|i| !indices.contains(i)) else {
+            return;
+        };
+
+        let mut run = Vec::new();
+        for offset in 1..=len {
+            let i = (start + offset) % len;
+            if indices.contains(&i) {
+                run.push(i);
+                continue;
+            }
+
+            let prev = (i + len - run.len() - 1) % len;
+            let (a, b) = (self.points[prev], self.points[i]);
+            let steps = run.len() + 1;
+            for (n, j) in run.drain(..).enumerate() {
+                let t = (n + 1) as f32 / steps as f32;
+                self.points[j] = a + (b - a) * t;
+            }
+        }
+    }
 }
 
 impl TunnelMeshInfo {
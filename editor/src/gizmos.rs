@@ -6,14 +6,17 @@ use transform_gizmo_bevy::{
 use crate::{
     data::{RoomPartPayload, RoomPartUuid},
     mode::EditorGizmos,
-    picking::{Placing, PrimarySelection, Selectable},
+    picking::{nearest_socket, Placing, PrimarySelection, Selectable, SnapSocket},
     state::{EditorState, FilePayload, SpawnPickerMode},
 };
 use lib::{
     player::consts::{PLAYER_HEIGHT, PLAYER_RADIUS},
-    worldgen::asset::PortalDirection,
+    worldgen::{asset::PortalDirection, layout::STANDARD_GRAVITY},
 };
 
+/// Length of the arrow drawn in gravity zones with normal gravity.
+const GRAVITY_ARROW_LENGTH: f32 = 4.0;
+
 pub struct EditorGizmosPlugin;
 
 /// This is used for the playtest function, not real spawnpoints.
@@ -26,6 +29,15 @@ pub struct SpawnpointGizmos;
 #[derive(Component)]
 pub struct PortalGizmos;
 
+#[derive(Component)]
+pub struct PatrolPathGizmos;
+
+#[derive(Component)]
+pub struct GravityZoneGizmos;
+
+#[derive(Component)]
+pub struct FluidVolumeGizmos;
+
 #[derive(Component)]
 pub struct ConnectionPoint;
 
@@ -56,12 +68,29 @@ impl Plugin for EditorGizmosPlugin {
                 draw_playtest_spawn_position,
                 draw_spawnpoints,
                 draw_portals,
+                draw_patrol_paths,
+                draw_gravity_zones,
+                draw_fluid_volumes,
                 draw_connection_points,
+                draw_snap_indicator,
+                update_gizmo_snapping,
             ),
         );
     }
 }
 
+fn update_gizmo_snapping(state: Res<EditorState>, mut options: ResMut<GizmoOptions>) {
+    if !state.is_changed() {
+        return;
+    }
+
+    let snap = state.rooms_mode.snap;
+    options.snapping = snap.enabled;
+    options.snap_distance = snap.distance;
+    options.snap_angle = snap.angle.to_radians();
+    options.snap_scale = snap.scale;
+}
+
 fn draw_playtest_spawn_position(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -117,6 +146,34 @@ fn draw_spawnpoints(
     });
 }
 
+fn draw_snap_indicator(
+    mut gizmos: Gizmos<EditorGizmos>,
+    placing: Option<Single<&Transform, (With<Placing>, Without<SnapSocket>)>>,
+    selected: Query<(&Transform, &GizmoTarget), Without<SnapSocket>>,
+    sockets: Query<&Transform, (With<SnapSocket>, Without<Placing>)>,
+) {
+    let dragging = match selected.iter().collect::<Vec<_>>()[..] {
+        [(transform, target)] if target.is_active() => Some(transform),
+        _ => None,
+    };
+    let Some(moving) = placing.map(|placing| *placing).or(dragging) else {
+        return;
+    };
+    let Some(socket) = nearest_socket(moving.translation, sockets.iter()) else {
+        return;
+    };
+
+    let color = Color::srgb(1.0, 0.8, 0.0);
+    let isometry = Isometry3d {
+        translation: socket.translation.into(),
+        rotation: socket.rotation
+            * Quat::from_euler(EulerRot::XYZ, 90.0_f32.to_radians(), 0.0, 0.0),
+    };
+    gizmos.circle(isometry, 0.5, color);
+    gizmos.circle(isometry, 0.25, color);
+    gizmos.line(moving.translation, socket.translation, color);
+}
+
 fn draw_portals(
     mut gizmos: Gizmos,
     state: Res<EditorState>,
@@ -210,6 +267,95 @@ fn draw_portals(
     );
 }
 
+fn draw_patrol_paths(
+    mut gizmos: Gizmos<EditorGizmos>,
+    state: Res<EditorState>,
+    paths: Query<(&Transform, &RoomPartUuid), With<PatrolPathGizmos>>,
+) {
+    let Some(FilePayload::Room(data)) = state.files.current_data() else {
+        return;
+    };
+
+    paths.iter().for_each(|(transform, uuid)| {
+        let Some(part) = data.parts.get(&uuid.0) else {
+            return;
+        };
+        let RoomPartPayload::PatrolPath { ref points, looped } = part.data else {
+            return;
+        };
+
+        let color = Color::srgb(1.0, 0.5, 0.0);
+        let points = points
+            .iter()
+            .map(|point| transform.transform_point(*point))
+            .collect::<Vec<_>>();
+        points.iter().for_each(|point| {
+            gizmos.sphere(Isometry3d::from_translation(*point), 0.15, color);
+        });
+        if looped && points.len() > 2 {
+            gizmos.linestrip(points.iter().chain(points.first()).copied(), color);
+        } else {
+            gizmos.linestrip(points, color);
+        }
+    });
+}
+
+fn draw_gravity_zones(
+    mut gizmos: Gizmos<EditorGizmos>,
+    state: Res<EditorState>,
+    zones: Query<(&Transform, &RoomPartUuid), With<GravityZoneGizmos>>,
+) {
+    let Some(FilePayload::Room(data)) = state.files.current_data() else {
+        return;
+    };
+
+    zones.iter().for_each(|(transform, uuid)| {
+        let Some(part) = data.parts.get(&uuid.0) else {
+            return;
+        };
+        let RoomPartPayload::GravityZone { gravity } = part.data else {
+            return;
+        };
+
+        // Arrow length is relative to normal gravity, capped to the zone's size
+        let color = Color::srgb(0.6, 0.3, 1.0);
+        let length = (gravity.length() / STANDARD_GRAVITY * GRAVITY_ARROW_LENGTH)
+            .min(transform.scale.min_element());
+        let direction = transform.rotation * gravity.normalize_or_zero();
+        let start = transform.translation - direction * length / 2.0;
+        gizmos.arrow(start, start + direction * length, color);
+    });
+}
+
+fn draw_fluid_volumes(
+    mut gizmos: Gizmos<EditorGizmos>,
+    state: Res<EditorState>,
+    volumes: Query<(&Transform, &RoomPartUuid), With<FluidVolumeGizmos>>,
+) {
+    let Some(FilePayload::Room(data)) = state.files.current_data() else {
+        return;
+    };
+
+    volumes.iter().for_each(|(transform, uuid)| {
+        let Some(part) = data.parts.get(&uuid.0) else {
+            return;
+        };
+        let RoomPartPayload::FluidVolume { level } = part.data else {
+            return;
+        };
+
+        // Outline the water surface
+        let color = Color::srgb(0.2, 0.6, 1.0);
+        let surface = transform.transform_point(Vec3::Y * (level - 0.5));
+        let isometry = Isometry3d {
+            translation: Vec3A::new(surface.x, surface.y, surface.z),
+            rotation: transform.rotation
+                * Quat::from_euler(EulerRot::XYZ, 90.0_f32.to_radians(), 0.0, 0.0),
+        };
+        gizmos.rect(isometry, transform.scale.xz(), color);
+    });
+}
+
 fn draw_connection_points(
     mut gizmos: Gizmos,
     state: Res<EditorState>,
@@ -128,8 +128,10 @@ impl Plugin for EditorModesPlugin {
             playtest,
         });
 
+        app.init_resource::<camera::FlySpeed>();
         app.add_systems(Startup, (camera::setup, setup).chain());
         app.add_systems(Update, (switch_modes, update_curr_mode).chain());
+        app.add_systems(Update, (camera::toggle_fly, camera::fly).chain());
     }
 }
 
@@ -154,8 +156,10 @@ pub fn setup(world: &mut World) {
                 update: vec![
                     world.register_system(tunnel::pick_profile_point),
                     world.register_system(tunnel::drag_profile_point),
+                    world.register_system(tunnel::edit_selected_profile_points),
                     world.register_system(tunnel::update_tunnel_info),
                     world.register_system(tunnel::draw_size_references),
+                    world.register_system(tunnel::draw_built_profile),
                     world.register_system(tunnel::remesh_preview_path),
                     world.register_system(tunnel::update_preview_brush),
                 ],
@@ -172,6 +176,9 @@ pub fn setup(world: &mut World) {
                     world.register_system(room::detect_removals),
                     world.register_system(room::detect_hash_changes),
                     world.register_system(room::update_preview_brushes),
+                    world.register_system(room::paint_terrain),
+                    world.register_system(room::update_preview_paint),
+                    world.register_system(room::measure),
                     world.register_system(room::correct_portal_orientations),
                 ],
                 ..default()
@@ -2,30 +2,69 @@ use std::collections::HashSet;
 
 use bevy::{
     asset::{Assets, RenderAssetUsages},
-    math::Vec3,
-    prelude::{Changed, Commands, Component, Entity, Mesh, Mesh3d, Query, Res, ResMut, Transform},
+    color::Color,
+    input::{keyboard::KeyCode, mouse::MouseButton, ButtonInput},
+    math::{Isometry3d, Vec3},
+    prelude::{
+        Camera, Changed, Commands, Component, Cuboid, Entity, Gizmos, GlobalTransform, Local, Mesh,
+        Mesh3d, Query, Res, ResMut, Single, Transform, With,
+    },
     render::mesh::{Indices, PrimitiveTopology},
     time::Time,
 };
+use bevy_egui::EguiContexts;
+use bevy_trackball::TrackballCamera;
+use egui::{Align2, Color32, FontId, LayerId, Pos2};
 use uuid::Uuid;
 
 use crate::{
-    data::{RoomPartPayload, RoomPartUuid},
-    state::{EditorState, FilePayload},
+    data::{hash_doorway, Measurement, RoomPartPayload, RoomPartUuid},
+    picking::{PickingMode, PickingTargets},
+    state::{EditorState, EditorViewMode, FilePayload},
+    ui::EguiHasPointer,
 };
-use lib::worldgen::{asset::PortalDirection, brush::TerrainBrush};
+use lib::{
+    meshgen::{generate_door_meshes, switch_size},
+    worldgen::{
+        asset::PortalDirection,
+        brush::{paint::PaintStroke, TerrainBrush},
+    },
+};
+
+use super::EditorGizmos;
 
 pub mod ui;
 mod utility;
 
 use utility::SpawnRoomPartEditorBundle;
 
+/// Uuid of the brush previewing the room's paint.
+const PAINT_BRUSH_UUID: &str = "paint";
+/// Strokes are added each time the cursor moves this far, relative to the brush radius.
+const PAINT_SPACING: f32 = 0.5;
+/// Least time between rebuilds of the paint preview, since every stroke rebuilds every chunk it
+/// covers.
+const PAINT_PREVIEW_SECS: f64 = 0.25;
+/// Measurements start and end on the first of these under the cursor.
+const MEASURE_MODES: [PickingMode; 3] = [
+    PickingMode::Selectable,
+    PickingMode::Terrain,
+    PickingMode::GroundPlane,
+];
+const MEASURE_COLOR: Color = Color::srgb(1.0, 0.8, 0.2);
+
 #[derive(Component)]
 pub struct UpdatePreviewBrush {
     time: f64,
     uuid: Uuid,
 }
 
+impl UpdatePreviewBrush {
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
+
 //
 // Systems
 //
@@ -155,6 +194,26 @@ pub fn detect_hash_changes(
                 commands.entity(entity).insert(Mesh3d(meshes.add(mesh)));
                 update_uuids.push(*uuid);
             }
+            RoomPartPayload::Doorway { ref spec, .. } => {
+                let hash = hash_doorway(spec);
+                if *world_hash == Some(hash) {
+                    return;
+                }
+
+                world_part.1 .1 = Some(hash);
+                let mesh = generate_door_meshes(*spec).frame_mesh;
+                commands.entity(entity).insert(Mesh3d(meshes.add(mesh)));
+            }
+            RoomPartPayload::Switch { ref spec } => {
+                let hash = spec.kind as u64;
+                if *world_hash == Some(hash) {
+                    return;
+                }
+
+                world_part.1 .1 = Some(hash);
+                let mesh = Cuboid::from_size(switch_size(spec.kind));
+                commands.entity(entity).insert(Mesh3d(meshes.add(mesh)));
+            }
             _ => {}
         }
     });
@@ -219,6 +278,208 @@ pub fn update_preview_brushes(
     });
 }
 
+/// Paints strokes onto the terrain under the cursor while the left mouse button is held, or erases
+/// the strokes under it while shift is also held.
+// Hook: update
+pub fn paint_terrain(
+    mut gizmos: Gizmos<EditorGizmos>,
+    mut state: ResMut<EditorState>,
+    mut last_stroke: Local<Option<Vec3>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    picking_targets: Res<PickingTargets>,
+    egui_has_pointer: Res<EguiHasPointer>,
+) {
+    if !mouse.pressed(MouseButton::Left) {
+        *last_stroke = None;
+    }
+    if !state.painting() || egui_has_pointer.0 {
+        return;
+    }
+    let Some(target) = picking_targets.target(&PickingMode::Terrain) else {
+        return;
+    };
+
+    let brush = &state.rooms_mode.paint;
+    let erase = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    let color = if erase {
+        Color::srgb(1.0, 0.2, 0.2)
+    } else {
+        Color::srgb(0.0, 1.0, 1.0)
+    };
+    gizmos.sphere(
+        Isometry3d::from_translation(target.point),
+        brush.radius,
+        color,
+    );
+
+    if !mouse.pressed(MouseButton::Left) {
+        return;
+    }
+    let spacing = brush.radius * PAINT_SPACING;
+    if last_stroke.is_some_and(|last| last.distance(target.point) < spacing) {
+        return;
+    }
+    *last_stroke = Some(target.point);
+
+    let stroke = PaintStroke {
+        position: target.point,
+        radius: brush.radius,
+        strength: brush.strength,
+        material: brush.material,
+    };
+    let Some(FilePayload::Room(data)) = state.files.current_data_mut() else {
+        return;
+    };
+    if erase {
+        data.paint
+            .retain(|existing| existing.position.distance(stroke.position) > stroke.radius);
+    } else {
+        data.paint.push(stroke);
+    }
+}
+
+/// Picks two points on parts, the terrain, or the ground plane to measure between, and labels every
+/// measurement with its distance. Escape cancels the measurement being taken.
+// Hook: update
+pub fn measure(
+    mut gizmos: Gizmos<EditorGizmos>,
+    mut contexts: EguiContexts,
+    mut state: ResMut<EditorState>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    picking_targets: Res<PickingTargets>,
+    egui_has_pointer: Res<EguiHasPointer>,
+    camera: Single<(&Camera, &GlobalTransform), With<TrackballCamera>>,
+) {
+    if state.view != EditorViewMode::Editor {
+        return;
+    }
+    let Some(FilePayload::Room(data)) = state.files.current_data() else {
+        return;
+    };
+
+    let mut labels = data
+        .measurements
+        .iter()
+        .map(|measurement| {
+            let Measurement { start, end, label } = measurement;
+            draw_measurement(&mut gizmos, *start, Some(*end));
+            let description = Measurement::describe(*start, *end);
+            let text = if label.is_empty() {
+                description
+            } else {
+                format!("{label}\n{description}")
+            };
+            ((*start + *end) / 2.0, text)
+        })
+        .collect::<Vec<_>>();
+
+    let measuring = state.measuring();
+    let start = state.rooms_mode.measure.start.filter(|_| measuring);
+    let hovered = (measuring && !egui_has_pointer.0)
+        .then(|| picking_targets.targets(&MEASURE_MODES))
+        .flatten()
+        .map(|target| target.point);
+
+    match (start, hovered) {
+        (Some(start), end) => {
+            draw_measurement(&mut gizmos, start, end);
+            if let Some(end) = end {
+                labels.push(((start + end) / 2.0, Measurement::describe(start, end)));
+            }
+        }
+        (None, Some(point)) => {
+            gizmos.sphere(Isometry3d::from_translation(point), 0.1, MEASURE_COLOR);
+        }
+        (None, None) => {}
+    }
+
+    if !measuring || keyboard.just_pressed(KeyCode::Escape) {
+        if state.rooms_mode.measure.start.is_some() {
+            state.rooms_mode.measure.start = None;
+        }
+    } else if let (Some(point), true) = (hovered, mouse.just_released(MouseButton::Left)) {
+        state.rooms_mode.measure.start = match start {
+            None => Some(point),
+            Some(start) => {
+                if let Some(FilePayload::Room(data)) = state.files.current_data_mut() {
+                    data.measurements.push(Measurement {
+                        start,
+                        end: point,
+                        label: String::new(),
+                    });
+                }
+                None
+            }
+        };
+    }
+
+    // Labels are drawn behind the panels
+    let (camera, camera_transform) = *camera;
+    let painter = contexts.ctx_mut().layer_painter(LayerId::background());
+    for (position, text) in labels {
+        let Ok(position) = camera.world_to_viewport(camera_transform, position) else {
+            continue;
+        };
+        let [r, g, b, _] = MEASURE_COLOR.to_srgba().to_u8_array();
+        painter.text(
+            Pos2::new(position.x, position.y),
+            Align2::CENTER_BOTTOM,
+            text,
+            FontId::monospace(12.0),
+            Color32::from_rgb(r, g, b),
+        );
+    }
+}
+
+/// Draws a measurement, or just its start if it has no end yet.
+fn draw_measurement(gizmos: &mut Gizmos<EditorGizmos>, start: Vec3, end: Option<Vec3>) {
+    gizmos.sphere(Isometry3d::from_translation(start), 0.1, MEASURE_COLOR);
+    if let Some(end) = end {
+        gizmos.sphere(Isometry3d::from_translation(end), 0.1, MEASURE_COLOR);
+        gizmos.line(start, end, MEASURE_COLOR);
+    }
+}
+
+// Hook: update
+pub fn update_preview_paint(
+    mut commands: Commands,
+    mut last_update: Local<f64>,
+    time: Res<Time>,
+    state: Res<EditorState>,
+    terrain_brushes: Query<(Entity, &TerrainBrush)>,
+) {
+    let Some(data) = state.files.current_data() else {
+        return;
+    };
+    let FilePayload::Room(data) = data else {
+        return;
+    };
+    if time.elapsed_secs_f64() - *last_update < PAINT_PREVIEW_SECS {
+        return;
+    }
+
+    let existing = terrain_brushes
+        .iter()
+        .find(|(_, brush)| brush.uuid() == PAINT_BRUSH_UUID);
+    let strokes: &[PaintStroke] = match existing {
+        Some((_, TerrainBrush::Paint { strokes, .. })) => strokes.as_slice(),
+        _ => &[],
+    };
+    if strokes == data.paint.as_slice() {
+        return;
+    }
+
+    *last_update = time.elapsed_secs_f64();
+    if let Some((entity, _)) = existing {
+        commands.entity(entity).despawn();
+    }
+    if !data.paint.is_empty() {
+        commands.spawn(TerrainBrush::paint(PAINT_BRUSH_UUID, 0, data.paint.clone()));
+    }
+}
+
 pub fn correct_portal_orientations(
     state: Res<EditorState>,
     terrain_brushes: Query<(Entity, &TerrainBrush)>,
@@ -1,21 +1,44 @@
+use std::ops::RangeInclusive;
+
 use bevy::{
-    math::{EulerRot, Quat, Vec3},
-    prelude::{Single, Transform, With},
+    log::{error, info},
+    math::{EulerRot, Quat, Rect, Vec2, Vec3},
+    prelude::{Commands, DetectChangesMut, Mut, Single, Transform, With},
 };
 use egui::{
-    menu, Align, CollapsingHeader, ComboBox, Frame, Label, Layout, RichText, ScrollArea, Ui,
+    menu, Align, CollapsingHeader, Color32, ComboBox, Context, DragValue, Frame, Id, Label,
+    LayerId, Layout, Order, Pos2, RichText, ScrollArea, Shape, Stroke, Ui,
+};
+use lib::{
+    meshgen::{DoorwaySpec, SwitchKind, SwitchSpec},
+    worldgen::{
+        asset::{AnnotationKind, PortalDirection, ReverbPreset, RoomAtmosphere, RoomContent},
+        brush::{paint::PaintStroke, BrushOperation},
+        layout::STANDARD_GRAVITY,
+        terrain::VoxelMaterialRegistry,
+        voxel::VoxelMaterial,
+    },
 };
-use lib::worldgen::asset::PortalDirection;
 use strum::{EnumProperty, IntoEnumIterator};
 
 use crate::{
-    data::{Environment, Rarity, RoomPart, RoomPartPayload, RoomPartUuid},
+    data::{
+        Environment, ExportFormat, Measurement, Rarity, RoomPart, RoomPartPayload, RoomPartUuid,
+    },
     picking::PrimarySelection,
-    state::{EditorState, EditorViewMode, FilePayload},
+    state::{
+        EditorState, EditorViewMode, FilePayload, FilePickerState, FileState, MeasureTool,
+        PaintBrush, RegionSelection, TransformSnap,
+    },
     ui::vhacd_parameters_sidebar,
 };
 
-pub fn topbar(state: &mut EditorState, ui: &mut Ui) {
+use super::utility::ExportRoomSurfaceCommand;
+
+pub fn topbar(state: &mut EditorState, commands: &mut Commands, ui: &mut Ui) {
+    let Some(name) = state.files.current_file().map(export_name) else {
+        return;
+    };
     let Some(data) = state.files.current_data_mut() else {
         return;
     };
@@ -59,6 +82,96 @@ pub fn topbar(state: &mut EditorState, ui: &mut Ui) {
                             ui.close_menu();
                             add = Some(RoomPart::spawnpoint(Transform::default()));
                         };
+
+                        // Doorway
+                        if ui.selectable_label(false, "Doorway").clicked() {
+                            ui.close_menu();
+                            add = Some(RoomPart::doorway(
+                                Transform::default(),
+                                DoorwaySpec::default(),
+                            ));
+                        };
+
+                        // Switch
+                        ui.menu_button("Switch", |ui| {
+                            SwitchKind::iter().for_each(|kind| {
+                                if ui.selectable_label(false, kind.to_string()).clicked() {
+                                    ui.close_menu();
+                                    add = Some(RoomPart::switch(
+                                        Transform::default(),
+                                        SwitchSpec { kind, channel: 0 },
+                                    ));
+                                }
+                            });
+                        });
+
+                        ui.separator();
+
+                        // Annotation
+                        ui.menu_button("Annotation", |ui| {
+                            AnnotationKind::iter().for_each(|kind| {
+                                if ui.selectable_label(false, kind.to_string()).clicked() {
+                                    ui.close_menu();
+                                    add = Some(RoomPart::annotation(
+                                        Transform::from_scale(Vec3::splat(4.0)),
+                                        kind,
+                                    ));
+                                }
+                            });
+                        });
+
+                        // Patrol path
+                        if ui.selectable_label(false, "Patrol Path").clicked() {
+                            ui.close_menu();
+                            add = Some(RoomPart::patrol_path(Transform::default()));
+                        };
+
+                        // Gravity zone
+                        if ui.selectable_label(false, "Gravity Zone").clicked() {
+                            ui.close_menu();
+                            add = Some(RoomPart::gravity_zone(
+                                Transform::from_scale(Vec3::splat(8.0)),
+                                Vec3::NEG_Y * STANDARD_GRAVITY / 4.0,
+                            ));
+                        };
+
+                        // Fluid volume
+                        if ui.selectable_label(false, "Fluid Volume").clicked() {
+                            ui.close_menu();
+                            add = Some(RoomPart::fluid_volume(
+                                Transform::from_scale(Vec3::new(8.0, 4.0, 8.0)),
+                                0.75,
+                            ));
+                        };
+
+                        // Audio zone
+                        if ui.selectable_label(false, "Audio Zone").clicked() {
+                            ui.close_menu();
+                            add = Some(RoomPart::audio_zone(Transform::from_scale(Vec3::splat(
+                                8.0,
+                            ))));
+                        };
+
+                        // Light
+                        if ui.selectable_label(false, "Light").clicked() {
+                            ui.close_menu();
+                            add = Some(RoomPart::light(Transform::default()));
+                        };
+                    });
+
+                    ui.toggle_value(&mut state.rooms_mode.library, "Library");
+
+                    ui.menu_button("Export", |ui| {
+                        ExportFormat::iter().for_each(|format| {
+                            let label = format!("Room surface as {format}");
+                            if ui.selectable_label(false, label).clicked() {
+                                ui.close_menu();
+                                commands.queue(ExportRoomSurfaceCommand {
+                                    path: format.path(&name),
+                                    format,
+                                });
+                            }
+                        });
                     });
                 });
             });
@@ -71,15 +184,39 @@ pub fn topbar(state: &mut EditorState, ui: &mut Ui) {
     }
 }
 
+/// Outline of the region being dragged out to select parts.
+pub fn region_overlay(ctx: &Context, state: &EditorState) {
+    let Some(region) = &state.rooms_mode.region else {
+        return;
+    };
+    let outline = match region {
+        RegionSelection::Rectangle(a, b) => {
+            vec![*a, Vec2::new(b.x, a.y), *b, Vec2::new(a.x, b.y)]
+        }
+        RegionSelection::Lasso(outline) => outline.clone(),
+    };
+
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("region_selection")));
+    painter.add(Shape::closed_line(
+        outline.iter().map(|p| Pos2::new(p.x, p.y)).collect(),
+        Stroke::new(1.5, Color32::from_rgba_unmultiplied(0, 255, 255, 128)),
+    ));
+}
+
 pub fn sidebar(
     state: &mut EditorState,
     ui: &mut Ui,
-    selected: Option<Single<&RoomPartUuid, With<PrimarySelection>>>,
+    selected: Option<Single<(&RoomPartUuid, &mut Transform), With<PrimarySelection>>>,
+    materials: &VoxelMaterialRegistry,
 ) {
+    let paint = &mut state.rooms_mode.paint;
+    let snap = &mut state.rooms_mode.snap;
+    let measure = &mut state.rooms_mode.measure;
     let picker = &mut state.files;
     let Some(file) = picker.current_file_mut() else {
         return;
     };
+    let name = export_name(file);
     let Some(ref mut data) = file.data else {
         return;
     };
@@ -119,6 +256,60 @@ pub fn sidebar(
         });
     });
 
+    // Content
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Content").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            ComboBox::from_id_salt("room_content")
+                .selected_text(format!("{}", data.content))
+                .show_ui(right, |ui| {
+                    RoomContent::iter().for_each(|content| {
+                        ui.selectable_value(&mut data.content, content, format!("{content}"));
+                    });
+                });
+        });
+    });
+
+    // Reverb
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Reverb").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            let reverb_name = |reverb: Option<ReverbPreset>| {
+                reverb.map_or("Automatic".to_owned(), |reverb| reverb.to_string())
+            };
+            ComboBox::from_id_salt("room_reverb")
+                .selected_text(reverb_name(data.reverb))
+                .show_ui(right, |ui| {
+                    [None]
+                        .into_iter()
+                        .chain(ReverbPreset::iter().map(Some))
+                        .for_each(|reverb| {
+                            ui.selectable_value(&mut data.reverb, reverb, reverb_name(reverb));
+                        });
+                });
+        });
+    });
+
+    // Detail
+    ui.checkbox(&mut data.high_detail, "High detail terrain");
+    ui.checkbox(&mut data.boss, "Boss room");
+
+    // Atmosphere
+    atmosphere_sidebar(ui, &mut data.atmosphere);
+
+    // Paint and measure, which both take over left clicks
+    let (painting, measuring) = (paint.active, measure.active);
+    paint_sidebar(ui, paint, &mut data.paint, materials);
+    measure_sidebar(ui, measure, &mut data.measurements);
+    if paint.active && !painting {
+        measure.active = false;
+    } else if measure.active && !measuring {
+        paint.active = false;
+    }
+
+    // Snapping
+    snap_sidebar(ui, snap);
+
     ui.separator();
 
     // Selection
@@ -126,7 +317,7 @@ pub fn sidebar(
         let Some(selected) = selected else {
             return;
         };
-        let selected_uuid = selected.into_inner();
+        let (selected_uuid, mut transform) = selected.into_inner();
         let Some(part) = data.parts.get_mut(&selected_uuid.0) else {
             todo!()
         };
@@ -136,13 +327,18 @@ pub fn sidebar(
 
         ui.add(Label::new(RichText::new("Selection").heading()).selectable(false));
 
+        transform_sidebar(ui, &mut transform, snap);
+
         match &mut part.data {
             RoomPartPayload::Stl {
                 path,
+                material,
+                operation,
                 vhacd_parameters,
                 ..
             } => {
                 let mut reload = false;
+                let mut export = None;
 
                 CollapsingHeader::new(part_name)
                     .default_open(true)
@@ -154,14 +350,33 @@ pub fn sidebar(
                             }
                             if ui.button("Browse").clicked() {}
                         });
+                        ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                            ExportFormat::iter().for_each(|format| {
+                                if ui.button(format!("Export {format}")).clicked() {
+                                    export = Some(format);
+                                }
+                            });
+                        });
                     });
 
+                let brush_changed = brush_sidebar(ui, operation, material, materials);
                 let vhacd_changed = vhacd_parameters_sidebar(ui, vhacd_parameters);
 
+                if let Some(format) = export {
+                    let uuid = part.uuid.to_string();
+                    let export_path = format.path(&format!("{name}_{}", &uuid[..8]));
+                    match part.export_stl(&export_path, format) {
+                        Ok(()) => info!(path = ?export_path, "exported room part"),
+                        Err(err) => {
+                            error!(path = ?export_path, "failed to export room part: {err}")
+                        }
+                    }
+                }
+
                 // TODO handle errors
                 if reload {
                     part.reload_stl().unwrap();
-                } else if vhacd_changed {
+                } else if brush_changed || vhacd_changed {
                     part.rehash_stl().unwrap();
                 }
             }
@@ -184,6 +399,533 @@ pub fn sidebar(
                     });
             }
             RoomPartPayload::Spawnpoint => {}
+            RoomPartPayload::Doorway { spec, channel } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        doorway_sidebar(ui, spec);
+                        channel_sidebar(ui, channel);
+                    });
+            }
+            RoomPartPayload::Switch { spec } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| switch_sidebar(ui, spec));
+            }
+            RoomPartPayload::Annotation { kind } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| annotation_sidebar(ui, kind));
+            }
+            RoomPartPayload::PatrolPath { points, looped } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| patrol_path_sidebar(ui, points, looped));
+            }
+            RoomPartPayload::GravityZone { gravity } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| gravity_zone_sidebar(ui, gravity));
+            }
+            RoomPartPayload::FluidVolume { level } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| fluid_volume_sidebar(ui, level));
+            }
+            RoomPartPayload::AudioZone {
+                ambience,
+                volume,
+                reverb,
+            } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| audio_zone_sidebar(ui, ambience, volume, reverb));
+            }
+            RoomPartPayload::Light {
+                color,
+                intensity,
+                flicker,
+            } => {
+                CollapsingHeader::new(part_name)
+                    .default_open(true)
+                    .show(ui, |ui| light_sidebar(ui, color, intensity, flicker));
+            }
         }
     });
 }
+
+fn doorway_sidebar(ui: &mut Ui, spec: &mut DoorwaySpec) {
+    fn value(ui: &mut Ui, label: &str, value: &mut f32, min: f32) {
+        ui.columns_const(|[left, right]| {
+            left.add(Label::new(label).selectable(false));
+            right.with_layout(Layout::right_to_left(Align::Min), |right| {
+                right.add(DragValue::new(value).speed(0.05).range(min..=f32::MAX));
+            });
+        });
+    }
+
+    // Dimensions are edited as sizes, with the frame centered and resting on the origin
+    let mut frame_size = spec.frame.size();
+    let mut door_size = spec.door.size();
+    let mut door_offset = spec.door.min - spec.frame.min - (frame_size - door_size) / 2.0;
+    door_offset.y = spec.door.min.y - spec.frame.min.y;
+
+    value(ui, "Frame width", &mut frame_size.x, 0.1);
+    value(ui, "Frame height", &mut frame_size.y, 0.1);
+    value(ui, "Frame depth", &mut spec.frame_depth, 0.01);
+    value(ui, "Door width", &mut door_size.x, 0.1);
+    value(ui, "Door height", &mut door_size.y, 0.1);
+    value(ui, "Door depth", &mut spec.door_depth, 0.01);
+    value(ui, "Door offset X", &mut door_offset.x, f32::MIN);
+    value(ui, "Door offset Y", &mut door_offset.y, 0.0);
+    value(ui, "Frame UV scale", &mut spec.frame_uv_scale, 0.01);
+    value(ui, "Door UV scale", &mut spec.door_uv_scale, 0.01);
+
+    // Keep the door inside the frame
+    door_size = door_size.min(frame_size);
+    let max_offset = (frame_size.x - door_size.x) / 2.0;
+    door_offset.x = door_offset.x.clamp(-max_offset, max_offset);
+    door_offset.y = door_offset.y.clamp(0.0, frame_size.y - door_size.y);
+
+    spec.frame = Rect {
+        min: Vec2::new(-frame_size.x / 2.0, 0.0),
+        max: Vec2::new(frame_size.x / 2.0, frame_size.y),
+    };
+    spec.door = Rect {
+        min: Vec2::new(-door_size.x / 2.0 + door_offset.x, door_offset.y),
+        max: Vec2::new(
+            door_size.x / 2.0 + door_offset.x,
+            door_offset.y + door_size.y,
+        ),
+    };
+}
+
+/// Wires a doorway to the switches on a channel.
+fn channel_sidebar(ui: &mut Ui, channel: &mut Option<u8>) {
+    ui.columns_const(|[left, right]| {
+        let mut wired = channel.is_some();
+        left.checkbox(&mut wired, "Switch channel");
+        match (wired, channel.as_mut()) {
+            (false, _) => *channel = None,
+            (true, None) => *channel = Some(0),
+            (true, Some(channel)) => {
+                right.with_layout(Layout::right_to_left(Align::Min), |right| {
+                    right.add(DragValue::new(channel));
+                });
+            }
+        }
+    });
+}
+
+fn switch_sidebar(ui: &mut Ui, spec: &mut SwitchSpec) {
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Kind").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            ComboBox::from_id_salt("switch_kind")
+                .selected_text(spec.kind.to_string())
+                .show_ui(right, |ui| {
+                    SwitchKind::iter().for_each(|kind| {
+                        ui.selectable_value(&mut spec.kind, kind, kind.to_string());
+                    });
+                });
+        });
+    });
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Channel").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.add(DragValue::new(&mut spec.channel));
+        });
+    });
+}
+
+fn annotation_sidebar(ui: &mut Ui, kind: &mut AnnotationKind) {
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Kind").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            ComboBox::from_id_salt("annotation_kind")
+                .selected_text(kind.to_string())
+                .show_ui(right, |ui| {
+                    AnnotationKind::iter().for_each(|k| {
+                        ui.selectable_value(kind, k, k.to_string());
+                    });
+                });
+        });
+    });
+}
+
+/// Gravity is edited relative to the part's rotation.
+fn gravity_zone_sidebar(ui: &mut Ui, gravity: &mut Vec3) {
+    ui.horizontal(|ui| {
+        ui.add(Label::new("Gravity").selectable(false));
+        ui.add(DragValue::new(&mut gravity.x).speed(0.1).prefix("x "));
+        ui.add(DragValue::new(&mut gravity.y).speed(0.1).prefix("y "));
+        ui.add(DragValue::new(&mut gravity.z).speed(0.1).prefix("z "));
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Low").clicked() {
+            *gravity = Vec3::NEG_Y * STANDARD_GRAVITY / 4.0;
+        }
+        if ui.button("None").clicked() {
+            *gravity = Vec3::ZERO;
+        }
+        if ui.button("Inverted").clicked() {
+            *gravity = Vec3::Y * STANDARD_GRAVITY;
+        }
+    });
+}
+
+/// The level is relative to the height of the part, from the bottom.
+fn fluid_volume_sidebar(ui: &mut Ui, level: &mut f32) {
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Water level").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.add(DragValue::new(level).speed(0.01).range(0.0..=1.0));
+        });
+    });
+}
+
+/// The ambience is a path relative to the assets directory.
+fn audio_zone_sidebar(
+    ui: &mut Ui,
+    ambience: &mut String,
+    volume: &mut f32,
+    reverb: &mut Option<ReverbPreset>,
+) {
+    ui.add(Label::new("Ambience").selectable(false));
+    ui.text_edit_singleline(ambience);
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Volume").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.add(DragValue::new(volume).speed(0.01).range(0.0..=1.0));
+        });
+    });
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Reverb").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            let reverb_name = |reverb: Option<ReverbPreset>| {
+                reverb.map_or("Room".to_owned(), |reverb| reverb.to_string())
+            };
+            ComboBox::from_id_salt("audio_zone_reverb")
+                .selected_text(reverb_name(*reverb))
+                .show_ui(right, |ui| {
+                    [None]
+                        .into_iter()
+                        .chain(ReverbPreset::iter().map(Some))
+                        .for_each(|preset| {
+                            ui.selectable_value(reverb, preset, reverb_name(preset));
+                        });
+                });
+        });
+    });
+}
+
+/// The color is linear RGB.
+fn light_sidebar(ui: &mut Ui, color: &mut [f32; 3], intensity: &mut f32, flicker: &mut f32) {
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Color").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.color_edit_button_rgb(color);
+        });
+    });
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Intensity").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.add(
+                DragValue::new(intensity)
+                    .speed(1000.0)
+                    .range(0.0..=f32::MAX)
+                    .suffix(" lm"),
+            );
+        });
+    });
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Flicker").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.add(DragValue::new(flicker).speed(0.01).range(0.0..=1.0));
+        });
+    });
+}
+
+/// Points are edited relative to the part's transform.
+fn patrol_path_sidebar(ui: &mut Ui, points: &mut Vec<Vec3>, looped: &mut bool) {
+    ui.checkbox(looped, "Looped");
+
+    let mut remove: Option<usize> = None;
+    for (i, point) in points.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(Label::new(format!("{i}")).selectable(false));
+            ui.add(DragValue::new(&mut point.x).speed(0.1).prefix("x "));
+            ui.add(DragValue::new(&mut point.y).speed(0.1).prefix("y "));
+            ui.add(DragValue::new(&mut point.z).speed(0.1).prefix("z "));
+            if ui.small_button("x").on_hover_text("Remove").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        points.remove(i);
+    }
+
+    if ui.button("Add point").clicked() {
+        let next = points
+            .last()
+            .map_or(Vec3::ZERO, |last| *last + Vec3::Z * 4.0);
+        points.push(next);
+    }
+}
+
+/// Returns true if anything changed.
+fn brush_sidebar(
+    ui: &mut Ui,
+    operation: &mut BrushOperation,
+    material: &mut VoxelMaterial,
+    materials: &VoxelMaterialRegistry,
+) -> bool {
+    let mut changed = false;
+
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Operation").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            ComboBox::from_id_salt("brush_operation")
+                .selected_text(operation.to_string())
+                .show_ui(right, |ui| {
+                    BrushOperation::iter().for_each(|op| {
+                        changed |= ui.selectable_value(operation, op, op.to_string()).changed();
+                    });
+                });
+        });
+    });
+    changed |= material_sidebar(ui, "brush_material", material, materials);
+
+    changed
+}
+
+/// Returns true if the material changed. Lists the natural materials in the registry.
+fn material_sidebar(
+    ui: &mut Ui,
+    id_salt: &str,
+    material: &mut VoxelMaterial,
+    materials: &VoxelMaterialRegistry,
+) -> bool {
+    let mut changed = false;
+
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Material").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            ComboBox::from_id_salt(id_salt)
+                .selected_text(materials.name(*material))
+                .show_ui(right, |ui| {
+                    materials
+                        .materials
+                        .keys()
+                        .filter(|mat| mat.is_natural())
+                        .for_each(|&mat| {
+                            changed |= ui
+                                .selectable_value(material, mat, materials.name(mat))
+                                .changed();
+                        });
+                });
+        });
+    });
+
+    changed
+}
+
+/// While measuring, left clicks pick the start and end of a measurement.
+fn measure_sidebar(ui: &mut Ui, tool: &mut MeasureTool, measurements: &mut Vec<Measurement>) {
+    CollapsingHeader::new("Measure")
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.checkbox(&mut tool.active, "Measure distances");
+
+            let mut remove: Option<usize> = None;
+            for (i, measurement) in measurements.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    let distance = measurement.start.distance(measurement.end);
+                    ui.add(Label::new(format!("{distance:.2} m")).selectable(false));
+                    ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                        if ui.small_button("x").on_hover_text("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.text_edit_singleline(&mut measurement.label);
+                    });
+                });
+            }
+            if let Some(i) = remove {
+                measurements.remove(i);
+            }
+
+            if !measurements.is_empty() && ui.button("Clear").clicked() {
+                measurements.clear();
+            }
+        });
+}
+
+/// The world transform is edited, and copied to the part by `detect_world_changes`.
+fn transform_sidebar(ui: &mut Ui, transform: &mut Mut<Transform>, snap: &TransformSnap) {
+    fn vec3(ui: &mut Ui, label: &str, value: &mut Vec3, speed: f64, suffix: &str) -> bool {
+        ui.horizontal(|ui| {
+            ui.add(Label::new(label).selectable(false));
+            ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                // Right to left, so z comes first
+                [&mut value.z, &mut value.y, &mut value.x]
+                    .into_iter()
+                    .map(|value| ui.add(DragValue::new(value).speed(speed).suffix(suffix)))
+                    .fold(false, |changed, response| changed | response.changed())
+            })
+            .inner
+        })
+        .inner
+    }
+
+    let mut edited = **transform;
+    let (x, y, z) = edited.rotation.to_euler(EulerRot::YXZ);
+    let mut euler = Vec3::new(y, x, z).to_degrees();
+
+    CollapsingHeader::new("Transform")
+        .default_open(true)
+        .show(ui, |ui| {
+            vec3(ui, "Position", &mut edited.translation, 0.05, " m");
+            // Rebuilt only when edited, so rounding doesn't creep into the rotation
+            if vec3(ui, "Rotation", &mut euler, 1.0, "°") {
+                let euler = euler.to_radians();
+                edited.rotation = Quat::from_euler(EulerRot::YXZ, euler.y, euler.x, euler.z);
+            }
+            vec3(ui, "Scale", &mut edited.scale, 0.01, "");
+
+            ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                if ui.button("Reset rotation").clicked() {
+                    edited.rotation = Quat::IDENTITY;
+                }
+                if ui.button("Snap to grid").clicked() {
+                    edited.translation = TransformSnap {
+                        enabled: true,
+                        ..*snap
+                    }
+                    .translation(edited.translation);
+                }
+            });
+        });
+
+    transform.set_if_neq(edited);
+}
+
+/// The transform gizmo snaps while this is enabled, or while control is held.
+fn snap_sidebar(ui: &mut Ui, snap: &mut TransformSnap) {
+    fn value(ui: &mut Ui, label: &str, value: &mut f32, speed: f64, suffix: &str) {
+        ui.columns_const(|[left, right]| {
+            left.add(Label::new(label).selectable(false));
+            right.with_layout(Layout::right_to_left(Align::Min), |right| {
+                right.add(
+                    DragValue::new(value)
+                        .speed(speed)
+                        .range(0.01..=f32::MAX)
+                        .suffix(suffix),
+                );
+            });
+        });
+    }
+
+    CollapsingHeader::new("Snapping")
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.checkbox(&mut snap.enabled, "Snap transforms");
+            value(ui, "Grid", &mut snap.distance, 0.01, " m");
+            value(ui, "Angle", &mut snap.angle, 0.5, "°");
+            value(ui, "Scale", &mut snap.scale, 0.01, "");
+        });
+}
+
+/// While the brush is active, left clicks on the terrain paint and shift left clicks erase.
+fn paint_sidebar(
+    ui: &mut Ui,
+    brush: &mut PaintBrush,
+    strokes: &mut Vec<PaintStroke>,
+    materials: &VoxelMaterialRegistry,
+) {
+    fn value(ui: &mut Ui, label: &str, value: &mut f32, speed: f64, range: RangeInclusive<f32>) {
+        ui.columns_const(|[left, right]| {
+            left.add(Label::new(label).selectable(false));
+            right.with_layout(Layout::right_to_left(Align::Min), |right| {
+                right.add(DragValue::new(value).speed(speed).range(range));
+            });
+        });
+    }
+
+    CollapsingHeader::new("Material paint")
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.checkbox(&mut brush.active, "Paint on terrain");
+            value(ui, "Radius", &mut brush.radius, 0.05, 0.25..=32.0);
+            value(ui, "Strength", &mut brush.strength, 0.01, 0.0..=1.0);
+            material_sidebar(ui, "paint_material", &mut brush.material, materials);
+
+            ui.horizontal(|ui| {
+                ui.add(Label::new(format!("{} strokes", strokes.len())).selectable(false));
+                ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                    if ui.button("Clear").clicked() {
+                        strokes.clear();
+                    }
+                    if ui.button("Undo").clicked() {
+                        strokes.pop();
+                    }
+                });
+            });
+        });
+}
+
+fn atmosphere_sidebar(ui: &mut Ui, atmosphere: &mut RoomAtmosphere) {
+    fn color_override(ui: &mut Ui, label: &str, value: &mut Option<[f32; 3]>) {
+        ui.columns_const(|[left, right]| {
+            let mut enabled = value.is_some();
+            left.checkbox(&mut enabled, label);
+            match (enabled, value.as_mut()) {
+                (false, _) => *value = None,
+                (true, None) => *value = Some([1.0, 1.0, 1.0]),
+                (true, Some(color)) => {
+                    right.with_layout(Layout::right_to_left(Align::Min), |right| {
+                        right.color_edit_button_rgb(color);
+                    });
+                }
+            }
+        });
+    }
+
+    fn value_override(ui: &mut Ui, label: &str, value: &mut Option<f32>, default: f32, speed: f64) {
+        ui.columns_const(|[left, right]| {
+            let mut enabled = value.is_some();
+            left.checkbox(&mut enabled, label);
+            match (enabled, value.as_mut()) {
+                (false, _) => *value = None,
+                (true, None) => *value = Some(default),
+                (true, Some(value)) => {
+                    right.with_layout(Layout::right_to_left(Align::Min), |right| {
+                        right.add(DragValue::new(value).speed(speed).range(0.0..=f32::MAX));
+                    });
+                }
+            }
+        });
+    }
+
+    CollapsingHeader::new("Atmosphere")
+        .default_open(false)
+        .show(ui, |ui| {
+            color_override(ui, "Ambient color", &mut atmosphere.ambient_color);
+            value_override(
+                ui,
+                "Ambient brightness",
+                &mut atmosphere.ambient_brightness,
+                35.0,
+                1.0,
+            );
+            color_override(ui, "Fog color", &mut atmosphere.fog_color);
+            value_override(ui, "Fog density", &mut atmosphere.fog_density, 0.01, 0.001);
+        });
+}
+
+/// Name for files exported from this room, without the markers of unsaved new files.
+fn export_name(file: &FileState) -> String {
+    FilePickerState::file_stem(file)
+        .trim_matches('*')
+        .to_owned()
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use bevy::{
     asset::RenderAssetUsages,
     ecs::system::SystemState,
@@ -11,18 +13,25 @@ use bevy::{
 use uuid::Uuid;
 
 use crate::{
-    data::{RoomPart, RoomPartPayload, RoomPartUuid},
-    gizmos::{PortalGizmos, SpawnpointGizmos},
+    data::{
+        append_mesh_geometry, export_geometry, hash_doorway, ExportFormat, RoomPart,
+        RoomPartPayload, RoomPartUuid,
+    },
+    gizmos::{
+        FluidVolumeGizmos, GravityZoneGizmos, PatrolPathGizmos, PortalGizmos, SpawnpointGizmos,
+    },
     mode::ModeSpecific,
     picking::{
         MaterialIndicatesSelection, Selectable, SelectionMaterials, SelectionWireframeColors,
-        SpawnAndPlaceCommand, WireframeIndicatesSelection,
+        SnapSocket, SpawnAndPlaceCommand, WireframeIndicatesSelection,
     },
     state::{EditorMode, EditorState, FilePayload},
 };
 use lib::{
+    meshgen::{generate_door_meshes, switch_size},
     player::consts::{PLAYER_HEIGHT, PLAYER_RADIUS},
     render_layer,
+    worldgen::terrain::Chunk,
 };
 
 pub struct SpawnRoomPartEditorBundle(pub Uuid);
@@ -94,6 +103,7 @@ impl Command for SpawnRoomPartEditorBundle {
                     RenderLayers::from_layers(&[render_layer::EDITOR]),
                     RoomPartUuid(*uuid, None),
                     PortalGizmos,
+                    SnapSocket,
                     Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(1.0, 1.0, 1.0)))),
                     materials.unselected(),
                     MaterialIndicatesSelection,
@@ -137,8 +147,198 @@ impl Command for SpawnRoomPartEditorBundle {
                     commands.spawn(bundle);
                 }
             }
+            RoomPartPayload::Doorway { spec, .. } => {
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, Some(hash_doorway(spec))),
+                    Mesh3d(meshes.add(generate_door_meshes(*spec).frame_mesh)),
+                    materials.unselected(),
+                    MaterialIndicatesSelection,
+                    Selectable { order: 0 },
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::ZERO,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
+            RoomPartPayload::Switch { spec } => {
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, Some(spec.kind as u64)),
+                    Mesh3d(meshes.add(Cuboid::from_size(switch_size(spec.kind)))),
+                    materials.unselected(),
+                    MaterialIndicatesSelection,
+                    Selectable { order: 0 },
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::ZERO,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
+            RoomPartPayload::Annotation { .. } | RoomPartPayload::AudioZone { .. } => {
+                // Only the wireframe is drawn, so the volume doesn't hide the room
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, None),
+                    Selectable { order: 0 },
+                    WireframeIndicatesSelection,
+                    Wireframe,
+                    wireframes.unselected(),
+                    Mesh3d(meshes.add(Cuboid::from_size(Vec3::ONE))),
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::Y * transform.scale.y / 2.0,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
+            RoomPartPayload::GravityZone { .. } => {
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, None),
+                    GravityZoneGizmos,
+                    Selectable { order: 0 },
+                    WireframeIndicatesSelection,
+                    Wireframe,
+                    wireframes.unselected(),
+                    Mesh3d(meshes.add(Cuboid::from_size(Vec3::ONE))),
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::Y * transform.scale.y / 2.0,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
+            RoomPartPayload::FluidVolume { .. } => {
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, None),
+                    FluidVolumeGizmos,
+                    Selectable { order: 0 },
+                    WireframeIndicatesSelection,
+                    Wireframe,
+                    wireframes.unselected(),
+                    Mesh3d(meshes.add(Cuboid::from_size(Vec3::ONE))),
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::Y * transform.scale.y / 2.0,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
+            RoomPartPayload::Light { .. } => {
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, None),
+                    Mesh3d(meshes.add(Sphere::new(0.25))),
+                    materials.unselected(),
+                    MaterialIndicatesSelection,
+                    Selectable { order: 0 },
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::Y * 0.5,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
+            RoomPartPayload::PatrolPath { .. } => {
+                let bundle = (
+                    ModeSpecific(EditorMode::Rooms, None),
+                    RenderLayers::from_layers(&[render_layer::EDITOR]),
+                    RoomPartUuid(*uuid, None),
+                    PatrolPathGizmos,
+                    Mesh3d(meshes.add(Sphere::new(0.25))),
+                    materials.unselected(),
+                    MaterialIndicatesSelection,
+                    Selectable { order: 0 },
+                    *transform,
+                );
+                if *place_after_spawn {
+                    commands.queue(SpawnAndPlaceCommand {
+                        modes: placement,
+                        offset: Vec3::ZERO,
+                        align_to_hit_normal: false,
+                        bundle,
+                    });
+                } else {
+                    commands.spawn(bundle);
+                }
+            }
         };
 
         system_state.apply(world);
     }
 }
+
+/// Export the carved terrain surface from the preview mesher.
+pub struct ExportRoomSurfaceCommand {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+}
+
+impl Command for ExportRoomSurfaceCommand {
+    fn apply(self, world: &mut World) {
+        let mut system_state: SystemState<(
+            Res<Assets<Mesh>>,
+            Query<(&Mesh3d, &GlobalTransform), With<Chunk>>,
+        )> = SystemState::new(world);
+        let (meshes, chunks) = system_state.get(world);
+
+        let mut vertices = Vec::<[f32; 3]>::new();
+        let mut indices = Vec::<u32>::new();
+        for (mesh, transform) in chunks.iter() {
+            if let Some(mesh) = meshes.get(&mesh.0) {
+                append_mesh_geometry(mesh, transform, &mut vertices, &mut indices);
+            }
+        }
+
+        match export_geometry(&self.path, self.format, &vertices, &indices) {
+            Ok(()) => info!(path = ?self.path, "exported room surface"),
+            Err(err) => error!(path = ?self.path, "failed to export room surface: {err}"),
+        }
+    }
+}
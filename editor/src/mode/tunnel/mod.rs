@@ -1,4 +1,7 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    collections::BTreeSet,
+    hash::{Hash, Hasher},
+};
 
 use bevy::{
     math::Vec3A,
@@ -6,6 +9,7 @@ use bevy::{
     render::{mesh::PrimitiveTopology, view::RenderLayers},
     window::PrimaryWindow,
 };
+use bevy_egui::EguiContexts;
 use bevy_trackball::TrackballCamera;
 use curvo::prelude::{NurbsCurve3D, Tessellation};
 use nalgebra::{Point2, Point3};
@@ -15,10 +19,10 @@ use uuid::Uuid;
 
 use super::{EditorGizmos, ModeSpecific};
 use crate::{
-    data::{Tunnel, TunnelMeshInfo},
+    data::{Tunnel, TunnelMeshInfo, TUNNEL_MIN_POINTS},
     gizmos::{ConnectedPath, ConnectionPoint, PortalGizmos},
     picking::{cursor_to_ground_plane, MaterialIndicatesSelection, Selectable, SelectionMaterials},
-    state::{EditorMode, EditorState, EditorViewMode, FilePayload},
+    state::{EditorMode, EditorState, EditorViewMode, FilePayload, RegionSelection},
     ui::EguiHasPointer,
     util::mesh_text,
 };
@@ -27,7 +31,11 @@ use lib::{
     player::consts::{PLAYER_HEIGHT, PLAYER_RADIUS},
     render_layer,
     worldgen::{
-        brush::{curve::mesh_curve, sweep::ProfileRamp, TerrainBrush, TerrainBrushRequest},
+        asset::TUNNEL_POINTS,
+        brush::{
+            curve::mesh_curve, sweep::ProfileRamp, BrushOperation, TerrainBrush,
+            TerrainBrushRequest,
+        },
         consts::CHUNK_SIZE_F,
         voxel::VoxelMaterial,
     },
@@ -37,6 +45,18 @@ pub mod ui;
 mod utility;
 use utility::spawn_fake_portal;
 
+/// Minimum distance between lasso outline points.
+const LASSO_SPACING: f32 = 0.1;
+/// Profile points are picked within this distance of the cursor.
+const POINT_PICK_RADIUS: f32 = 0.25;
+/// Double clicks within this distance of a segment insert a point on it.
+const SEGMENT_PICK_RADIUS: f32 = 0.5;
+/// Longest time between the clicks of a double click.
+const DOUBLE_CLICK_SECS: f64 = 0.3;
+const NUDGE_STEP: f32 = 0.1;
+/// Used while holding shift.
+const NUDGE_STEP_LARGE: f32 = 1.0;
+
 #[derive(Component)]
 pub struct TunnelInfo(Tunnel, TunnelMeshInfo);
 
@@ -196,9 +216,12 @@ pub fn draw_size_references(mut gizmos: Gizmos<EditorGizmos>, info: Option<Singl
 pub fn pick_profile_point(
     mut gizmos: Gizmos<EditorGizmos>,
     mut state: ResMut<EditorState>,
+    mut last_click: Local<Option<(f64, Vec2)>>,
+    time: Res<Time>,
     window: Single<&Window, With<PrimaryWindow>>,
     camera: Single<(&Camera, &GlobalTransform), With<TrackballCamera>>,
     mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
     egui_has_pointer: Res<EguiHasPointer>,
 ) {
     if state.view != EditorViewMode::Editor {
@@ -206,7 +229,7 @@ pub fn pick_profile_point(
     }
 
     let cursor = cursor_to_ground_plane(&window, *camera);
-    let radius = 0.25;
+    let radius = POINT_PICK_RADIUS;
     let mut picked: Option<usize> = None;
 
     let Some(current) = state.files.current_data() else {
@@ -216,19 +239,20 @@ pub fn pick_profile_point(
         panic!("pick_profile_point ran in the wrong mode");
     };
 
-    let len = data.points.len();
-    data.points.iter().enumerate().for_each(|(i, p)| {
+    let points = data.points.clone();
+    let len = points.len();
+    let mode = &state.tunnels_mode;
+    let busy = mode.dragging() || mode.selecting_region();
+    points.iter().enumerate().for_each(|(i, p)| {
         let isometry = Isometry3d {
             rotation: Quat::from_euler(EulerRot::XYZ, -90.0_f32.to_radians(), 0.0, 0.0),
             translation: Vec3A::new(p.x, 0.0, p.y),
         };
+        let position = Vec2::new(p.x, p.y);
 
         let mut picked_this = false;
         if let Some(cursor) = cursor {
-            if !state.tunnels_mode.dragging()
-                && picked.is_none()
-                && cursor.distance(Vec2::new(p.x, p.y)) <= radius
-            {
+            if !busy && picked.is_none() && cursor.distance(position) <= radius {
                 picked_this = true;
             }
         }
@@ -239,14 +263,16 @@ pub fn pick_profile_point(
 
         let mut color = Color::srgba(1.0, 1.0, 1.0, 0.35);
 
-        if picked_this {
+        let in_region = mode
+            .region
+            .as_ref()
+            .is_some_and(|region| region.contains(position));
+        if picked_this || in_region {
             color = Color::srgb(1.0, 1.0, 1.0);
         }
 
-        if let Some(drag_point) = state.tunnels_mode.selected_point {
-            if drag_point == i {
-                color = Color::srgb(0.0, 1.0, 1.0);
-            }
+        if mode.selected_points.contains(&i) {
+            color = Color::srgb(0.0, 1.0, 1.0);
         }
 
         gizmos.circle(isometry, radius, color);
@@ -256,36 +282,145 @@ pub fn pick_profile_point(
         }
     });
 
+    if let Some(region) = &mode.region {
+        draw_region(&mut gizmos, region);
+    }
+
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    // Double clicking a segment inserts a point on it
+    let double_click = mouse.just_pressed(MouseButton::Left).then(|| {
+        let now = time.elapsed_secs_f64();
+        let previous = last_click.replace((now, cursor.unwrap_or_default()));
+        previous.is_some_and(|(secs, position)| {
+            now - secs <= DOUBLE_CLICK_SECS
+                && cursor.is_some_and(|cursor| cursor.distance(position) <= radius)
+        })
+    });
+    if double_click == Some(true) && picked.is_none() && !egui_has_pointer.0 {
+        let segment = cursor.and_then(|cursor| nearest_segment(&points, cursor));
+        if let Some((segment, point)) = segment {
+            let mirror = state.tunnels_mode.mirror;
+            let Some(FilePayload::Tunnel(data)) = state.files.current_data_mut() else {
+                return;
+            };
+            let inserted = data.insert_point(segment, Point2::new(point.x, point.y), mirror);
+            let mode = &mut state.tunnels_mode;
+            mode.region = None;
+            mode.selected_points = BTreeSet::from([inserted]);
+            *last_click = None;
+            return;
+        }
+    }
+
+    let mode = &mut state.tunnels_mode;
+
     if mouse.just_pressed(MouseButton::Left) {
-        if let Some(picked) = picked {
-            if let Some(cursor) = cursor {
-                state.tunnels_mode.drag_start = Some((data.points[picked], cursor));
-                state.tunnels_mode.selected_point = Some(picked);
+        if let (Some(picked), Some(cursor)) = (picked, cursor) {
+            if shift {
+                if !mode.selected_points.remove(&picked) {
+                    mode.selected_points.insert(picked);
+                }
+            } else {
+                if !mode.selected_points.contains(&picked) {
+                    mode.selected_points = BTreeSet::from([picked]);
+                }
+                let start = mode
+                    .selected_points
+                    .iter()
+                    .map(|i| (*i, points[*i]))
+                    .collect();
+                mode.drag_start = Some((start, cursor));
             }
         } else if !egui_has_pointer.0 {
-            state.tunnels_mode.selected_point = None;
+            if !shift {
+                mode.selected_points.clear();
+            }
+            if let Some(cursor) = cursor {
+                let lasso = keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+                mode.region = Some(if lasso {
+                    RegionSelection::Lasso(vec![cursor])
+                } else {
+                    RegionSelection::Rectangle(cursor, cursor)
+                });
+            }
         }
     } else if mouse.just_released(MouseButton::Left) {
-        state.tunnels_mode.drag_start = None;
+        mode.drag_start = None;
+        if let Some(region) = mode.region.take() {
+            mode.selected_points.extend(
+                (0..len).filter(|i| region.contains(Vec2::new(points[*i].x, points[*i].y))),
+            );
+        }
+    } else if let (Some(region), Some(cursor)) = (&mut mode.region, cursor) {
+        match region {
+            RegionSelection::Rectangle(_, end) => *end = cursor,
+            RegionSelection::Lasso(outline) => {
+                if !outline
+                    .last()
+                    .is_some_and(|last| last.distance(cursor) < LASSO_SPACING)
+                {
+                    outline.push(cursor);
+                }
+            }
+        }
     }
 }
 
+/// The segment closest to the point, within [`SEGMENT_PICK_RADIUS`], and the closest point on it.
+fn nearest_segment(points: &[Point2<f32>], point: Vec2) -> Option<(usize, Vec2)> {
+    let len = points.len();
+    (0..len)
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % len]);
+            let (a, b) = (Vec2::new(a.x, a.y), Vec2::new(b.x, b.y));
+            let t = ((point - a).dot(b - a) / (b - a).length_squared().max(f32::EPSILON))
+                .clamp(0.0, 1.0);
+            (i, a.lerp(b, t))
+        })
+        .map(|(i, closest)| (i, closest, closest.distance(point)))
+        .filter(|(.., distance)| *distance <= SEGMENT_PICK_RADIUS)
+        .min_by(|(.., a), (.., b)| a.total_cmp(b))
+        .map(|(i, closest, _)| (i, closest))
+}
+
+fn draw_region(gizmos: &mut Gizmos<EditorGizmos>, region: &RegionSelection) {
+    let color = Color::srgba(0.0, 1.0, 1.0, 0.5);
+    let outline = match region {
+        RegionSelection::Rectangle(a, b) => {
+            vec![*a, Vec2::new(b.x, a.y), *b, Vec2::new(a.x, b.y)]
+        }
+        RegionSelection::Lasso(outline) => outline.clone(),
+    };
+
+    gizmos.linestrip(
+        outline
+            .iter()
+            .chain(outline.first())
+            .map(|p| Vec3::new(p.x, 0.0, p.y)),
+        color,
+    );
+}
+
 // Hook: update
 pub fn drag_profile_point(
     mut state: ResMut<EditorState>,
     window: Single<&Window, With<PrimaryWindow>>,
     camera: Single<(&Camera, &GlobalTransform), With<TrackballCamera>>,
 ) {
-    let Some(drag_point) = state.tunnels_mode.selected_point else {
-        return;
-    };
-    let Some((point_start, cursor_start)) = state.tunnels_mode.drag_start else {
+    let Some((points_start, cursor_start)) = &state.tunnels_mode.drag_start else {
         return;
     };
     let Some(cursor) = cursor_to_ground_plane(&window, *camera) else {
         return;
     };
 
+    let cursor_diff = cursor - *cursor_start;
+    let moved = points_start
+        .iter()
+        .map(|(i, p)| (*i, Point2::new(p.x + cursor_diff.x, p.y + cursor_diff.y)))
+        .collect::<Vec<_>>();
+
     let mirror = state.tunnels_mode.mirror;
     let data = state.files.current_data_mut();
 
@@ -296,23 +431,120 @@ pub fn drag_profile_point(
         todo!();
     };
 
-    let cursor_diff = cursor - cursor_start;
-    let point_new_pos = Point2::new(point_start.x + cursor_diff.x, point_start.y + cursor_diff.y);
+    move_profile_points(data, &moved, mirror);
+}
+
+// Hook: update
+pub fn edit_selected_profile_points(
+    mut state: ResMut<EditorState>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut contexts: EguiContexts,
+) {
+    let mode = &state.tunnels_mode;
+    if state.view != EditorViewMode::Editor || mode.selected_points.is_empty() || mode.dragging() {
+        return;
+    }
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let step = if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        NUDGE_STEP_LARGE
+    } else {
+        NUDGE_STEP
+    };
+
+    // Looking down from above, +Z is up and -X is right on screen
+    let mut nudge = Vec2::ZERO;
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        nudge.y += step;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        nudge.y -= step;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        nudge.x += step;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        nudge.x -= step;
+    }
+    let delete = keyboard.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]);
 
-    data.points[drag_point] = point_new_pos;
+    if nudge == Vec2::ZERO && !delete {
+        return;
+    }
+
+    let mirror = mode.mirror;
+    let selected = mode.selected_points.clone();
+    let Some(FilePayload::Tunnel(data)) = state.files.current_data_mut() else {
+        return;
+    };
+
+    if delete {
+        match data.remove_points(&selected, mirror) {
+            Ok(()) => state.tunnels_mode.selected_points.clear(),
+            Err(err) => warn!("failed to remove tunnel points: {err}"),
+        }
+        return;
+    }
+
+    let moved = selected
+        .iter()
+        .map(|i| {
+            let p = data.points[*i];
+            (*i, Point2::new(p.x + nudge.x, p.y + nudge.y))
+        })
+        .collect::<Vec<_>>();
+    move_profile_points(data, &moved, mirror);
+}
+
+/// Moves points to new positions. When mirroring, the points across the axis follow unless they
+/// are being moved too.
+fn move_profile_points(data: &mut Tunnel, moved: &[(usize, Point2<f32>)], mirror: bool) {
     let len = data.points.len();
+    for (i, point) in moved {
+        data.points[*i] = *point;
+    }
 
-    if !mirror || drag_point == 0 || drag_point == len / 2 {
+    if !mirror {
         return;
     }
 
-    let point_new_pos = Point2::new(
-        -point_start.x - cursor_diff.x,
-        point_start.y + cursor_diff.y,
-    );
+    for (i, point) in moved {
+        if *i == 0 || *i == len / 2 {
+            continue;
+        }
+
+        let mirror_point = (len - i) % len;
+        if moved.iter().any(|(j, _)| *j == mirror_point) {
+            continue;
+        }
+        data.points[mirror_point] = Point2::new(-point.x, point.y);
+    }
+}
 
-    let mirror_point = (len - drag_point) % len;
-    data.points[mirror_point] = point_new_pos;
+/// Shows the profile the tunnel is built with, when it's resampled to a different number of
+/// points.
+// Hook: update
+pub fn draw_built_profile(mut gizmos: Gizmos<EditorGizmos>, state: Res<EditorState>) {
+    if state.view != EditorViewMode::Editor {
+        return;
+    }
+    let Some(FilePayload::Tunnel(data)) = state.files.current_data() else {
+        return;
+    };
+    if data.points.len() == TUNNEL_POINTS || data.points.len() < TUNNEL_MIN_POINTS {
+        return;
+    }
+
+    let color = Color::srgba(1.0, 0.6, 0.0, 0.6);
+    let points = data
+        .resampled()
+        .map(|point| Vec3::new(point.x, 0.0, point.y));
+    gizmos.linestrip(points.iter().chain(points.first()).copied(), color);
+    for point in points {
+        gizmos.sphere(Isometry3d::from_translation(point), 0.08, color);
+    }
 }
 
 // Hook: update
@@ -521,8 +753,10 @@ pub fn update_preview_brush(
     commands.spawn(TerrainBrushRequest::Sweep {
         uuid: Uuid::new_v4().into(),
         material: VoxelMaterial::BrownRock,
+        operation: BrushOperation::Carve,
         rail: upb.rail.clone(),
         profile: upb.profile.clone(),
+        smoothness: 0.0,
         sequence: 0, // TODO
     });
 }
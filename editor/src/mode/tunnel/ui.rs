@@ -1,8 +1,10 @@
-use egui::{menu, Align, ComboBox, Frame, Label, Layout, RichText, ScrollArea, Ui};
+use egui::{menu, Align, Button, ComboBox, Frame, Label, Layout, RichText, ScrollArea, Ui};
 use strum::IntoEnumIterator;
 
+use lib::worldgen::asset::TUNNEL_POINTS;
+
 use crate::{
-    data::{Environment, Rarity},
+    data::{Environment, Rarity, TUNNEL_MIN_POINTS},
     state::{EditorState, EditorViewMode, FilePayload},
 };
 
@@ -27,6 +29,18 @@ pub fn topbar(state: &mut EditorState, ui: &mut Ui) {
                             ui.close_menu();
                             data.center();
                         };
+                        if ui
+                            .add_enabled(
+                                !state.tunnels_mode.selected_points.is_empty(),
+                                Button::new("Flatten selection").frame(false),
+                            )
+                            .clicked()
+                        {
+                            ui.close_menu();
+                            let mirror = state.tunnels_mode.mirror;
+                            let selected = &state.tunnels_mode.selected_points;
+                            data.flatten(&data.with_mirrored(selected, mirror));
+                        };
                     });
                 });
             });
@@ -81,11 +95,33 @@ pub fn sidebar(state: &mut EditorState, ui: &mut Ui) {
         });
     });
 
+    // Profile
+    ui.columns_const(|[left, right]| {
+        left.add(Label::new("Profile points").selectable(false));
+        right.with_layout(Layout::right_to_left(Align::Min), |right| {
+            right.add(
+                Label::new(format!(
+                    "{} (built with {TUNNEL_POINTS})",
+                    data.points.len()
+                ))
+                .selectable(false),
+            );
+        });
+    });
+    if data.points.len() < TUNNEL_MIN_POINTS {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!("Profiles need at least {TUNNEL_MIN_POINTS} points to be saved."),
+        );
+    }
+
     ui.separator();
 
     // Point
     ScrollArea::vertical().show(ui, |ui| {
-        if let Some(selection_index) = state.tunnels_mode.selected_point {
+        let selected = &state.tunnels_mode.selected_points;
+        if selected.len() == 1 {
+            let selection_index = *selected.first().unwrap();
             ui.add(
                 Label::new(RichText::new(format!("Point {selection_index}")).heading())
                     .selectable(false),
@@ -99,9 +135,25 @@ pub fn sidebar(state: &mut EditorState, ui: &mut Ui) {
                 ))
                 .selectable(false),
             );
+        } else if !selected.is_empty() {
+            ui.add(Label::new(RichText::new("Points").heading()).selectable(false));
+            ui.add(Label::new(format!("{} points selected.", selected.len())).selectable(false));
+            selected.iter().for_each(|i| {
+                let point = &data.points[*i];
+                ui.add(Label::new(format!("{i}: ({}, {})", point.x, point.y)).selectable(false));
+            });
         } else {
             ui.add(Label::new(RichText::new("Point").heading()).selectable(false));
             ui.add(Label::new("No point selected.").selectable(false));
         }
+
+        ui.add(
+            Label::new(
+                "Drag to select a region, alt-drag to lasso, and shift to add to the selection. \
+                Arrow keys nudge the selection and delete removes it. Double-click a segment \
+                to insert a point.",
+            )
+            .selectable(false),
+        );
     });
 }
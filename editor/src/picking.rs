@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use bevy::{
     ecs::system::SystemState, pbr::wireframe::WireframeColor, picking::backend::ray::RayMap,
-    prelude::*, window::PrimaryWindow,
+    prelude::*, render::primitives::Aabb, window::PrimaryWindow,
 };
 use bevy_trackball::TrackballCamera;
 use strum::{EnumIter, IntoEnumIterator};
@@ -10,11 +10,20 @@ use transform_gizmo_bevy::GizmoTarget;
 
 use crate::{
     data::RoomPartUuid,
-    state::{EditorState, FilePayload, SpawnPickerMode},
+    state::{
+        EditorMode, EditorState, EditorViewMode, FilePayload, RegionSelection, SpawnPickerMode,
+    },
     ui::EguiHasPointer,
 };
 use lib::worldgen::terrain::Chunk;
 
+/// Parts moved within this distance of a snap socket's center snap to it.
+pub const SNAP_DISTANCE: f32 = 1.5;
+/// Region selections smaller than this on screen, in pixels, are clicks.
+const REGION_MIN_SIZE: f32 = 4.0;
+/// Lasso points are added each time the cursor moves this far, in pixels.
+const LASSO_SPACING: f32 = 8.0;
+
 #[derive(Resource)]
 pub struct SelectionMaterials {
     unselected: Handle<StandardMaterial>,
@@ -53,6 +62,10 @@ pub struct Selectable {
 #[derive(Component)]
 pub struct PrimarySelection;
 
+/// Other parts snap to the center of this when placed or dragged near it, flush with its plane.
+#[derive(Component)]
+pub struct SnapSocket;
+
 #[repr(u8)]
 #[derive(Debug, EnumIter, PartialEq, Eq, Hash, Clone)]
 pub enum PickingMode {
@@ -80,11 +93,11 @@ pub struct PickingTarget {
 pub struct PickingTargets(pub HashMap<PickingMode, Option<PickingTarget>>);
 
 impl PickingTargets {
-    fn target(&self, mode: &PickingMode) -> &Option<PickingTarget> {
+    pub fn target(&self, mode: &PickingMode) -> &Option<PickingTarget> {
         self.0.get(mode).unwrap()
     }
 
-    fn targets(&self, modes: &[PickingMode]) -> Option<&PickingTarget> {
+    pub fn targets(&self, modes: &[PickingMode]) -> Option<&PickingTarget> {
         for mode in modes {
             if let Some(target) = self.target(mode) {
                 return Some(target);
@@ -186,7 +199,11 @@ impl Plugin for PickingPlugin {
             Update,
             (
                 update_picking_targets,
-                ((pick, pick_spawn_position).chain(), place_new_entity),
+                (
+                    (pick, select_region, pick_spawn_position).chain(),
+                    place_new_entity,
+                    snap_dragged_entity,
+                ),
                 update_selection_indications,
             )
                 .chain(),
@@ -313,7 +330,7 @@ fn pick(
     if !placing.is_empty() {
         return;
     };
-    if state.spawn.mode != SpawnPickerMode::Inactive {
+    if state.spawn.mode != SpawnPickerMode::Inactive || state.painting() || state.measuring() {
         return;
     }
     if !mouse.just_released(MouseButton::Left) {
@@ -322,6 +339,9 @@ fn pick(
     if gizmo_targets.iter().any(|(_, target)| target.is_focused()) {
         return;
     }
+    if state.rooms_mode.region.as_ref().is_some_and(is_region_drag) {
+        return;
+    }
 
     let multiselect = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
 
@@ -346,6 +366,142 @@ fn pick(
     commands.insert(PrimarySelection);
 }
 
+/// Dragging on the viewport selects every part touching the rectangle, or the lasso while alt is
+/// held. Shift adds to the selection.
+fn select_region(
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    egui_has_pointer: Res<EguiHasPointer>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<TrackballCamera>>,
+    gizmo_targets: Query<(Entity, &GizmoTarget)>,
+    primary_selection: Option<Single<Entity, With<PrimarySelection>>>,
+    selectable: Query<(Entity, &GlobalTransform, Option<&Aabb>), With<Selectable>>,
+    placing: Query<&Placing>,
+) {
+    let cursor = window.cursor_position();
+
+    if mouse.just_pressed(MouseButton::Left) {
+        let start = state.mode() == Some(EditorMode::Rooms)
+            && state.view == EditorViewMode::Editor
+            && state.spawn.mode == SpawnPickerMode::Inactive
+            && !state.painting()
+            && !state.measuring()
+            && placing.is_empty()
+            && !egui_has_pointer.0
+            && !gizmo_targets.iter().any(|(_, target)| target.is_focused());
+        let lasso = keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+        state.rooms_mode.region = cursor.filter(|_| start).map(|cursor| {
+            if lasso {
+                RegionSelection::Lasso(vec![cursor])
+            } else {
+                RegionSelection::Rectangle(cursor, cursor)
+            }
+        });
+        return;
+    }
+    if state.rooms_mode.region.is_none() {
+        return;
+    }
+
+    if !mouse.just_released(MouseButton::Left) {
+        let (Some(region), Some(cursor)) = (&mut state.rooms_mode.region, cursor) else {
+            return;
+        };
+        match region {
+            RegionSelection::Rectangle(_, end) => *end = cursor,
+            RegionSelection::Lasso(outline) => {
+                if !outline
+                    .last()
+                    .is_some_and(|last| last.distance(cursor) < LASSO_SPACING)
+                {
+                    outline.push(cursor);
+                }
+            }
+        }
+        return;
+    }
+
+    // Small regions were clicks, which are handled by `pick`
+    let Some(region) = state.rooms_mode.region.take().filter(is_region_drag) else {
+        return;
+    };
+
+    let multiselect = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !multiselect {
+        gizmo_targets.iter().for_each(|(entity, _)| {
+            commands.entity(entity).remove::<GizmoTarget>();
+        });
+    }
+
+    let (camera, camera_transform) = *camera;
+    let mut selected = selectable
+        .iter()
+        .filter(|(entity, ..)| placing.get(*entity).is_err())
+        .filter_map(|(entity, transform, aabb)| {
+            let bounds = screen_bounds(camera, camera_transform, transform, aabb)?;
+            let distance = transform
+                .translation()
+                .distance(camera_transform.translation());
+            region.intersects(bounds).then_some((entity, distance))
+        })
+        .collect::<Vec<_>>();
+    selected.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    selected.iter().for_each(|(entity, _)| {
+        commands.entity(*entity).insert(GizmoTarget::default());
+    });
+
+    // The nearest part becomes the primary selection, unless it's being added to
+    let Some((nearest, _)) = selected.first() else {
+        return;
+    };
+    if let Some(primary) = primary_selection {
+        if multiselect && gizmo_targets.contains(*primary) {
+            return;
+        }
+        commands.entity(*primary).remove::<PrimarySelection>();
+    }
+    commands.entity(*nearest).insert(PrimarySelection);
+}
+
+fn is_region_drag(region: &RegionSelection) -> bool {
+    region.bounds().size().max_element() >= REGION_MIN_SIZE
+}
+
+/// The screen space rectangle around an entity's bounding box, or its origin if it has none.
+fn screen_bounds(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    transform: &GlobalTransform,
+    aabb: Option<&Aabb>,
+) -> Option<Rect> {
+    let (center, half_extents) = aabb.map_or((Vec3::ZERO, Vec3::ZERO), |aabb| {
+        (aabb.center.into(), aabb.half_extents.into())
+    });
+
+    (0..8)
+        .map(|i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            );
+            transform.transform_point(center + half_extents * corner)
+        })
+        // Corners behind the camera are left out
+        .filter_map(|corner| camera.world_to_viewport(camera_transform, corner).ok())
+        .fold(None, |bounds: Option<Rect>, point| {
+            Some(
+                bounds
+                    .unwrap_or(Rect::from_center_size(point, Vec2::ZERO))
+                    .union_point(point),
+            )
+        })
+}
+
 fn pick_spawn_position(
     mut state: ResMut<EditorState>,
     mouse: Res<ButtonInput<MouseButton>>,
@@ -379,25 +535,33 @@ fn place_new_entity(
     mouse: Res<ButtonInput<MouseButton>>,
     egui_has_pointer: Res<EguiHasPointer>,
     picking_targets: Res<PickingTargets>,
-    placing: Option<Single<(Entity, &mut Transform, &Placing)>>,
+    state: Res<EditorState>,
+    placing: Option<Single<(Entity, &mut Transform, &Placing, Has<SnapSocket>)>>,
+    sockets: Query<&Transform, (With<SnapSocket>, Without<Placing>)>,
 ) {
     let Some(placing) = placing else {
         return;
     };
 
-    let (entity, mut transform, placement) = placing.into_inner();
+    let (entity, mut transform, placement, is_socket) = placing.into_inner();
     let finish = mouse.just_released(MouseButton::Left)
         && !egui_has_pointer.0
         && (time.elapsed_secs_f64() - placement.spawned_time >= 0.75);
 
     if let Some(target) = picking_targets.targets(&placement.modes) {
-        transform.translation = target.point + placement.offset;
+        transform.translation = state.rooms_mode.snap.translation(target.point) + placement.offset;
 
         if placement.align_to_hit_normal {
             transform.look_at(target.point + target.normal, Vec3::Y);
             transform.rotate_local_x(-90.0_f32.to_radians());
         }
 
+        if !is_socket {
+            if let Some(socket) = nearest_socket(transform.translation, sockets.iter()) {
+                snap_to_socket(&mut transform, socket);
+            }
+        }
+
         if finish {
             let mut commands = commands.entity(entity);
             commands.remove::<Placing>();
@@ -408,6 +572,54 @@ fn place_new_entity(
     }
 }
 
+/// Snaps the dragged entity when the transform gizmo is released, since the gizmo overwrites the
+/// transform while dragging.
+fn snap_dragged_entity(
+    mut dragging: Local<Option<Entity>>,
+    mut dragged: Query<(Entity, &mut Transform, &GizmoTarget), Without<SnapSocket>>,
+    selected: Query<(), With<GizmoTarget>>,
+    sockets: Query<&Transform, With<SnapSocket>>,
+) {
+    // Multiple selections move together, so snapping one would break their layout
+    let active = (selected.iter().count() == 1)
+        .then(|| dragged.iter().find(|(_, _, target)| target.is_active()))
+        .flatten()
+        .map(|(entity, ..)| entity);
+    if active.is_some() {
+        *dragging = active;
+        return;
+    }
+    let Some(entity) = dragging.take() else {
+        return;
+    };
+    let Ok((_, mut transform, _)) = dragged.get_mut(entity) else {
+        return;
+    };
+
+    if let Some(socket) = nearest_socket(transform.translation, sockets.iter()) {
+        snap_to_socket(&mut transform, socket);
+    }
+}
+
+/// Centers the transform on the socket and turns it to match, so it's flush with the socket's
+/// plane. Scale is kept.
+fn snap_to_socket(transform: &mut Transform, socket: &Transform) {
+    transform.translation = socket.translation;
+    transform.rotation = socket.rotation;
+}
+
+/// The closest socket within [`SNAP_DISTANCE`].
+pub fn nearest_socket<'a>(
+    translation: Vec3,
+    sockets: impl Iterator<Item = &'a Transform>,
+) -> Option<&'a Transform> {
+    sockets
+        .map(|socket| (socket, socket.translation.distance(translation)))
+        .filter(|(_, distance)| *distance <= SNAP_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(socket, _)| socket)
+}
+
 fn update_selection_indications(
     mut commands: Commands,
     materials: Res<SelectionMaterials>,
@@ -1,4 +1,6 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
     fs::File,
     io::{Read, Write},
     path::{Path, PathBuf},
@@ -8,8 +10,15 @@ use std::{
 
 use anyhow::anyhow;
 use bevy::prelude::*;
+use lib::worldgen::{
+    asset::migration::{version_of, VersionSeed, Versioned},
+    voxel::VoxelMaterial,
+};
 use nalgebra::Point2;
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{EnumAccess, VariantAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use strum::{EnumIter, EnumProperty, IntoEnumIterator};
 
 use crate::data::{Environment, Room, Tunnel};
@@ -76,22 +85,90 @@ pub enum EditorViewMode {
 #[derive(Debug)]
 pub struct TunnelsModeState {
     pub mirror: bool,
-    pub selected_point: Option<usize>,
-    pub drag_start: Option<(Point2<f32>, Vec2)>,
+    pub selected_points: BTreeSet<usize>,
+    /// Starting positions of the dragged points, and the cursor position the drag started at.
+    pub drag_start: Option<(Vec<(usize, Point2<f32>)>, Vec2)>,
+    pub region: Option<RegionSelection>,
 }
 
 impl TunnelsModeState {
     pub fn dragging(&self) -> bool {
         self.drag_start.is_some()
     }
+
+    pub fn selecting_region(&self) -> bool {
+        self.region.is_some()
+    }
 }
 
 impl Default for TunnelsModeState {
     fn default() -> Self {
         Self {
             mirror: true,
-            selected_point: None,
+            selected_points: BTreeSet::new(),
             drag_start: None,
+            region: None,
+        }
+    }
+}
+
+/// An area being dragged out to select things. It's on the ground plane in tunnels mode, and on
+/// screen in rooms mode.
+#[derive(Debug, Clone)]
+pub enum RegionSelection {
+    Rectangle(Vec2, Vec2),
+    Lasso(Vec<Vec2>),
+}
+
+impl RegionSelection {
+    pub fn contains(&self, point: Vec2) -> bool {
+        match self {
+            RegionSelection::Rectangle(a, b) => Rect::from_corners(*a, *b).contains(point),
+            RegionSelection::Lasso(outline) => {
+                // Even-odd rule
+                let mut inside = false;
+                for (i, a) in outline.iter().enumerate() {
+                    let b = outline[(i + 1) % outline.len()];
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        match self {
+            RegionSelection::Rectangle(a, b) => Rect::from_corners(*a, *b),
+            RegionSelection::Lasso(outline) => outline
+                .iter()
+                .fold(Rect::from_corners(Vec2::MAX, Vec2::MIN), |rect, point| {
+                    rect.union_point(*point)
+                }),
+        }
+    }
+
+    /// Whether any of a rectangle is inside the region. Lassos only check its corners and center
+    /// against the outline, and the outline against it.
+    pub fn intersects(&self, rect: Rect) -> bool {
+        let overlaps = |a: Rect, b: Rect| a.min.cmple(b.max).all() && b.min.cmple(a.max).all();
+        match self {
+            RegionSelection::Rectangle(..) => overlaps(self.bounds(), rect),
+            RegionSelection::Lasso(outline) => {
+                let points = [
+                    rect.center(),
+                    rect.min,
+                    rect.max,
+                    Vec2::new(rect.min.x, rect.max.y),
+                    Vec2::new(rect.max.x, rect.min.y),
+                ];
+                overlaps(self.bounds(), rect)
+                    && (points.into_iter().any(|point| self.contains(point))
+                        || outline.iter().any(|point| rect.contains(*point)))
+            }
         }
     }
 }
@@ -100,12 +177,78 @@ impl Default for TunnelsModeState {
 // Rooms mode
 //
 
+#[derive(Debug, Default)]
+pub struct RoomsModeState {
+    pub paint: PaintBrush,
+    /// Shows the window of STL parts to place.
+    pub library: bool,
+    pub snap: TransformSnap,
+    /// Area on screen being dragged out to select parts.
+    pub region: Option<RegionSelection>,
+    pub measure: MeasureTool,
+}
+
+/// Measures the distance between two clicked points.
+#[derive(Debug, Default)]
+pub struct MeasureTool {
+    /// Left clicks pick points to measure between instead of selecting parts.
+    pub active: bool,
+    /// First point of the measurement being taken.
+    pub start: Option<Vec3>,
+}
+
+/// Increments the transform gizmo and placement snap to. Holding control snaps while this is
+/// disabled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformSnap {
+    pub enabled: bool,
+    /// Grid size, in meters.
+    pub distance: f32,
+    /// Rotation increment, in degrees.
+    pub angle: f32,
+    pub scale: f32,
+}
+
+impl Default for TransformSnap {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: 0.5,
+            angle: 15.0,
+            scale: 0.25,
+        }
+    }
+}
+
+impl TransformSnap {
+    /// Rounds a translation to the grid.
+    pub fn translation(&self, translation: Vec3) -> Vec3 {
+        if !self.enabled || self.distance <= 0.0 {
+            return translation;
+        }
+        (translation / self.distance).round() * self.distance
+    }
+}
+
+/// Settings for painting material onto the preview terrain.
 #[derive(Debug)]
-pub struct RoomsModeState {}
+pub struct PaintBrush {
+    /// Left clicks paint instead of selecting parts.
+    pub active: bool,
+    pub radius: f32,
+    /// Share of voxels repainted at the edge of each stroke, from 0 to 1.
+    pub strength: f32,
+    pub material: VoxelMaterial,
+}
 
-impl Default for RoomsModeState {
+impl Default for PaintBrush {
     fn default() -> Self {
-        Self {}
+        Self {
+            active: false,
+            radius: 2.0,
+            strength: 0.5,
+            material: VoxelMaterial::ShinyGreenRock,
+        }
     }
 }
 
@@ -127,21 +270,157 @@ impl FilePayload {
         }
     }
 
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        match self {
+            FilePayload::Tunnel(tunnel) => &mut tunnel.environment,
+            FilePayload::Room(room) => &mut room.environment,
+        }
+    }
+
     pub fn default_for_mode(mode: EditorMode) -> Self {
         match mode {
             EditorMode::Tunnels => Self::Tunnel(Tunnel::default()),
             EditorMode::Rooms => Self::Room(Room::default()),
         }
     }
+
+    pub fn version_mut(&mut self) -> &mut u32 {
+        match self {
+            FilePayload::Tunnel(tunnel) => &mut tunnel.version,
+            FilePayload::Room(room) => &mut room.version,
+        }
+    }
+
+    /// Reads a file saved with any schema version. Returns the descriptions of the changes made
+    /// since it was saved. See [`Versioned::from_ron`].
+    pub fn from_ron(s: &str) -> anyhow::Result<(Self, Vec<&'static str>)> {
+        let (version, changes) = match ron::from_str(s)? {
+            FileHeader::Tunnel(value) => {
+                let version = version_of(&value)?;
+                (version, Tunnel::changes_since(version)?)
+            }
+            FileHeader::Room(value) => {
+                let version = version_of(&value)?;
+                (version, Room::changes_since(version)?)
+            }
+        };
+
+        let mut deserializer = ron::Deserializer::from_str(s)?;
+        let data = (&mut deserializer).deserialize_enum(
+            "FilePayload",
+            &["Tunnel", "Room"],
+            FilePayloadVisitor { version },
+        )?;
+        deserializer.end()?;
+
+        Ok((data, changes.to_vec()))
+    }
+
+    /// Checks the file can be saved.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            FilePayload::Tunnel(tunnel) => tunnel.validate(),
+            FilePayload::Room(_) => Ok(()),
+        }
+    }
+}
+
+/// A file read without its types, to find which kind it is and what version it was saved with.
+#[derive(Deserialize)]
+enum FileHeader {
+    Tunnel(ron::Value),
+    Room(ron::Value),
+}
+
+#[derive(Deserialize)]
+enum FileKind {
+    Tunnel,
+    Room,
+}
+
+/// Reads a [`FilePayload`] saved with a known version.
+struct FilePayloadVisitor {
+    version: u32,
+}
+
+impl<'de> Visitor<'de> for FilePayloadVisitor {
+    type Value = FilePayload;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tunnel or a room")
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let seed = VersionSeed::new(self.version);
+        Ok(match data.variant()? {
+            (FileKind::Tunnel, variant) => FilePayload::Tunnel(variant.newtype_variant_seed(seed)?),
+            (FileKind::Room, variant) => FilePayload::Room(variant.newtype_variant_seed(seed)?),
+        })
+    }
+}
+
+/// Stored next to the assets it describes. It starts with a dot, so it isn't listed as a file.
+const ASSET_INDEX_FILE_NAME: &str = ".index.ron";
+
+/// Metadata about assets that doesn't belong in the assets themselves.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct AssetIndex {
+    /// Tags by file name.
+    pub tags: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl AssetIndex {
+    fn read(directory: &Path) -> Self {
+        let Ok(s) = std::fs::read_to_string(directory.join(ASSET_INDEX_FILE_NAME)) else {
+            return Self::default();
+        };
+
+        ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("failed to parse asset index: {err}");
+            Self::default()
+        })
+    }
+
+    fn write(&self, directory: &Path) -> anyhow::Result<()> {
+        let s = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(directory.join(ASSET_INDEX_FILE_NAME), s)?;
+
+        Ok(())
+    }
+
+    pub fn tags(&self, name: &str) -> impl Iterator<Item = &String> {
+        self.tags.get(name).into_iter().flatten()
+    }
+
+    pub fn has_tag(&self, name: &str, tag: &str) -> bool {
+        self.tags.get(name).is_some_and(|tags| tags.contains(tag))
+    }
+
+    pub fn all_tags(&self) -> BTreeSet<&String> {
+        self.tags.values().flatten().collect()
+    }
 }
 
+/// Characters allowed in file names, not counting the extension.
+pub const FILENAME_CHARS: &str = "-_0123456789abcdefghijklmnopqrstuvwxyz";
+pub const FILENAME_MAX_CHARS: usize = 24;
+
 #[derive(Debug)]
 pub struct FilePickerState {
     pub directory: PathBuf,
     pub files: Vec<FileState>,
+    pub index: AssetIndex,
     pub filter: String,
     pub filter_mode: Option<EditorMode>,
+    pub filter_environment: Option<Environment>,
+    /// Files must have every one of these tags.
+    pub filter_tags: BTreeSet<String>,
     pub current: Option<usize>,
+    /// Given to the next file opened in a tab, so tabs stay in the order they were opened.
+    next_tab: usize,
 }
 
 impl FilePickerState {
@@ -153,6 +432,12 @@ impl FilePickerState {
         format!("{name}{}", Self::file_ext_for_mode(mode))
     }
 
+    /// Returns the file name without the extension.
+    pub fn file_stem(file: &FileState) -> &str {
+        let ext = Self::file_ext_for_mode(&file.mode);
+        file.name.strip_suffix(&ext).unwrap_or(&file.name)
+    }
+
     fn new_file_name(index: usize, mode: &EditorMode) -> String {
         format!("*{}{index}*", mode.get_str("file_ext").unwrap())
     }
@@ -162,6 +447,24 @@ impl FilePickerState {
         Self::new_file_name(index, mode)
     }
 
+    /// Whether the file passes every filter.
+    pub fn is_visible(&self, file: &FileState) -> bool {
+        let filter = self.filter.trim();
+        if !filter.is_empty() && !file.name.contains(filter) {
+            return false;
+        }
+        if self.filter_mode.is_some_and(|mode| file.mode != mode) {
+            return false;
+        }
+        if self.filter_environment.is_some() && file.environment() != self.filter_environment {
+            return false;
+        }
+
+        self.filter_tags
+            .iter()
+            .all(|tag| self.index.has_tag(&file.name, tag))
+    }
+
     pub fn current_file(&self) -> Option<&FileState> {
         self.current.map(|i| self.files.get(i))?
     }
@@ -178,14 +481,17 @@ impl FilePickerState {
         self.current_file_mut().map(|c| c.data.as_mut())?
     }
 
-    pub fn switch_to_file(&mut self, index: usize) -> anyhow::Result<()> {
-        if let Some(current_file) = self.current_file_mut() {
-            if !current_file.changed && current_file.path.is_some() {
-                current_file.data = None;
-                current_file.last_saved_data = None;
-            }
-        }
+    /// Indices of files open in tabs, in the order they were opened.
+    pub fn tabs(&self) -> Vec<usize> {
+        let mut tabs = (0..self.files.len())
+            .filter(|i| self.files[*i].tab.is_some())
+            .collect::<Vec<_>>();
+        tabs.sort_by_key(|i| self.files[*i].tab);
+        tabs
+    }
 
+    pub fn switch_to_file(&mut self, index: usize) -> anyhow::Result<()> {
+        let next_tab = self.next_tab;
         let file = self
             .files
             .get_mut(index)
@@ -193,6 +499,11 @@ impl FilePickerState {
 
         self.current = Some(index);
 
+        if file.tab.is_none() {
+            file.tab = Some(next_tab);
+            self.next_tab += 1;
+        }
+
         if file.data.is_some() {
             return Ok(());
         }
@@ -204,6 +515,37 @@ impl FilePickerState {
         Ok(())
     }
 
+    /// Closes the file's tab, discarding unsaved changes. New files that were never saved are
+    /// removed entirely. If it was the current file, the most recently opened tab is switched to.
+    pub fn close_file(&mut self, index: usize) -> anyhow::Result<()> {
+        let file = self
+            .files
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("file does not exist"))?;
+
+        let was_current = self.current == Some(index);
+        file.tab = None;
+        if file.path.is_some() {
+            file.environment = file.environment();
+            file.data = None;
+            file.last_saved_data = None;
+        } else {
+            self.files.remove(index);
+            if self.current.is_some_and(|i| i > index) {
+                self.current = self.current.map(|i| i - 1);
+            }
+        }
+
+        if was_current {
+            self.current = None;
+            if let Some(last_tab) = self.tabs().last() {
+                self.switch_to_file(*last_tab)?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn revert_file(&mut self, index: usize) -> anyhow::Result<()> {
         let file = self
             .files
@@ -237,9 +579,66 @@ impl FilePickerState {
 
         std::fs::rename(old_path, new_path)?;
 
+        if let Some(tags) = self.index.tags.remove(&old_name) {
+            self.index.tags.insert(new_name, tags);
+        } else {
+            self.index.tags.remove(&new_name);
+        }
+        self.index.write(&self.directory)?;
+
         Ok(())
     }
 
+    /// Renames each file in `renames`, given as the current file name and the new name without
+    /// an extension. The current file stays current.
+    pub fn bulk_rename(&mut self, renames: &[(String, String)]) -> anyhow::Result<()> {
+        let mut current_name = self.current_file().map(|file| file.name.clone());
+
+        let result = renames.iter().try_for_each(|(old_name, new_stem)| {
+            let index = self
+                .files
+                .iter()
+                .position(|file| &file.name == old_name)
+                .ok_or_else(|| anyhow!("file does not exist"))?;
+            self.rename_file(index, new_stem.clone())?;
+
+            if current_name.as_ref() == Some(old_name) {
+                current_name = Some(self.files[0].name.clone());
+            }
+
+            anyhow::Ok(())
+        });
+
+        self.current = current_name.and_then(|name| self.files.iter().position(|f| f.name == name));
+
+        result
+    }
+
+    /// Adds the tag if the file doesn't have it, otherwise removes it. Only saved files can be
+    /// tagged.
+    pub fn toggle_tag(&mut self, index: usize, tag: &str) -> anyhow::Result<()> {
+        let file = self
+            .files
+            .get(index)
+            .ok_or_else(|| anyhow!("file does not exist"))?;
+        if file.path.is_none() {
+            return Err(anyhow!("tried to tag unsaved file"));
+        }
+
+        let tags = self.index.tags.entry(file.name.clone()).or_default();
+        if !tags.remove(tag) {
+            tags.insert(tag.to_owned());
+        }
+        if tags.is_empty() {
+            self.index.tags.remove(&file.name);
+        }
+        if !self.index.tags.values().any(|tags| tags.contains(tag)) {
+            self.filter_tags.remove(tag);
+        }
+
+        self.index.write(&self.directory)
+    }
+
     pub fn create_new_file(&mut self, mode: EditorMode) {
         self.files.insert(
             0,
@@ -250,9 +649,12 @@ impl FilePickerState {
                 changed: true,
                 data: Some(FilePayload::default_for_mode(mode)),
                 last_saved_data: Some(FilePayload::default_for_mode(mode)),
+                environment: None,
                 modified_time: SystemTime::now(),
+                tab: Some(self.next_tab),
             },
         );
+        self.next_tab += 1;
         self.current = Some(0);
     }
 
@@ -279,23 +681,32 @@ impl FilePickerState {
             .ok_or_else(|| anyhow!("file does not exist"))?;
 
         let name = Self::file_name_for_mode(name, &current_file.mode);
-        let old_path = current_file.path.clone();
-        let path = PathBuf::from_str(&name).unwrap();
-        let path = self.directory.clone().join(path);
         let is_new_file = current_file.path.is_none();
-
-        let mut file = if is_new_file {
-            self.files.remove(index)
-        } else {
-            if current_file.data.is_none() {
-                current_file.read(old_path.unwrap())?;
+        if current_file.data.is_none() {
+            if let Some(old_path) = current_file.path.clone() {
+                current_file.read(old_path)?;
             }
-            current_file.clone()
-        };
+        }
 
-        file.path = Some(path);
+        // Written before anything else changes, so a file that fails to validate or write is left
+        // as it was
+        let mut file = current_file.clone();
+        file.path = Some(self.directory.join(&name));
         file.name = name.clone();
         file.write()?;
+
+        if is_new_file {
+            self.files.remove(index);
+        } else {
+            // The changes move to the new file, so the original goes back to how it was saved
+            current_file.tab = None;
+            current_file.environment = current_file
+                .last_saved_data
+                .as_ref()
+                .map(FilePayload::environment);
+            current_file.data = None;
+            current_file.last_saved_data = None;
+        }
         self.files.retain(|f| f.name != name);
         self.files.insert(0, file);
         self.current = Some(0);
@@ -319,6 +730,10 @@ impl FilePickerState {
 
         if let Some(ref path) = file.path {
             std::fs::remove_file(path)?;
+
+            if self.index.tags.remove(&file.name).is_some() {
+                self.index.write(&self.directory)?;
+            }
         }
         self.files.remove(index);
 
@@ -355,7 +770,9 @@ impl FilePickerState {
                         changed: false,
                         data: None,
                         last_saved_data: None,
+                        environment: FileState::peek_environment(&f.path()),
                         modified_time,
+                        tab: None,
                     })
                 }
             })
@@ -363,10 +780,14 @@ impl FilePickerState {
 
         Self {
             files,
+            index: AssetIndex::read(&directory),
             directory,
             filter: String::new(),
             filter_mode: None,
+            filter_environment: None,
+            filter_tags: BTreeSet::new(),
             current: None,
+            next_tab: 0,
         }
     }
 }
@@ -378,14 +799,49 @@ pub struct FileState {
     /// If data is None, it's because the file isn't loaded, not because it's empty.
     pub data: Option<FilePayload>,
     pub last_saved_data: Option<FilePayload>,
+    /// Environment of the file when it was last read, so unloaded files can still be filtered.
+    /// Use [`FileState::environment`] instead.
+    pub environment: Option<Environment>,
     pub mode: EditorMode,
     // Don't touch this, it's automatically updated by EditorModesPlugin.
     pub changed: bool,
     /// Only tracks the modified time according to the file metadata.
     pub modified_time: SystemTime,
+    /// Set while the file is open in a tab. Used to order tabs.
+    pub tab: Option<usize>,
 }
 
 impl FileState {
+    /// Reads a file just to find out its environment.
+    fn peek_environment(path: &Path) -> Option<Environment> {
+        let s = std::fs::read_to_string(path).ok()?;
+        let (data, _) = FilePayload::from_ron(&s).ok()?;
+        Some(data.environment())
+    }
+
+    pub fn environment(&self) -> Option<Environment> {
+        self.data
+            .as_ref()
+            .map(FilePayload::environment)
+            .or(self.environment)
+    }
+
+    /// Loads the file if needed. The change isn't saved.
+    pub fn set_environment(&mut self, environment: Environment) -> anyhow::Result<()> {
+        if self.data.is_none() {
+            let path = self
+                .path
+                .clone()
+                .ok_or_else(|| anyhow!("tried to load file with no path"))?;
+            self.read(path)?;
+        }
+
+        let data = self.data.as_mut().ok_or_else(|| anyhow!("file is empty"))?;
+        *data.environment_mut() = environment;
+
+        Ok(())
+    }
+
     pub fn read(&mut self, path: PathBuf) -> anyhow::Result<()> {
         if self.data.is_some() {
             return Err(anyhow!("tried to reread loaded file"));
@@ -395,8 +851,16 @@ impl FileState {
         let mut s = String::new();
         file.read_to_string(&mut s)?;
 
-        self.data = Some(ron::from_str(&s)?);
-        self.last_saved_data = self.data.clone();
+        let (data, migrations) = FilePayload::from_ron(&s)?;
+        // Migrated files show up as changed, so saving them upgrades them. The file as it was
+        // saved can't be read anymore, so reverting keeps the migrated data.
+        let mut last_saved_data = data.clone();
+        *last_saved_data.version_mut() -= migrations.len() as u32;
+        self.last_saved_data = Some(last_saved_data);
+        for migration in migrations {
+            info!("{}: migrated, {migration}", self.name);
+        }
+        self.data = Some(data);
 
         Ok(())
     }
@@ -405,6 +869,7 @@ impl FileState {
         let Some(ref data) = self.data else {
             return Err(anyhow!("tried to write empty file"));
         };
+        data.validate()?;
         let Some(ref path) = self.path else {
             return Err(anyhow!("tried to save file with no path"));
         };
@@ -471,4 +936,25 @@ impl EditorState {
     pub fn mode(&self) -> Option<EditorMode> {
         self.files.current_file().map(|f| f.mode)
     }
+
+    /// Whether left clicks paint material instead of selecting room parts.
+    pub fn painting(&self) -> bool {
+        self.rooms_mode.paint.active
+            && self.view == EditorViewMode::Editor
+            && self.mode() == Some(EditorMode::Rooms)
+    }
+
+    /// Whether left clicks pick points to measure between instead of selecting room parts.
+    pub fn measuring(&self) -> bool {
+        self.rooms_mode.measure.active
+            && self.view == EditorViewMode::Editor
+            && self.mode() == Some(EditorMode::Rooms)
+    }
+
+    /// Whether the part library window is open.
+    pub fn part_library_open(&self) -> bool {
+        self.rooms_mode.library
+            && self.view == EditorViewMode::Editor
+            && self.mode() == Some(EditorMode::Rooms)
+    }
 }
@@ -0,0 +1,99 @@
+use bevy::{
+    log::{info, warn},
+    prelude::{Commands, Entity, EventReader, Query},
+};
+use egui::{Align2, Area, Context, Frame, Id, Label, Margin, RichText, Rounding, Spinner};
+use lib::worldgen::brush::{
+    CancelTerrainBrushTasksCommand, TerrainBrushTask, TerrainBrushTaskEvent,
+};
+
+use crate::mode::room::UpdatePreviewBrush;
+
+/// Lists brush rebuilds that are waiting or in progress, with a button to cancel each.
+pub fn brush_tasks_area(
+    ctx: &Context,
+    commands: &mut Commands,
+    anchor_offset: egui::Vec2,
+    tasks: &Query<&TerrainBrushTask>,
+    pending: &Query<(Entity, &UpdatePreviewBrush)>,
+) {
+    if tasks.is_empty() && pending.is_empty() {
+        return;
+    }
+
+    Area::new(Id::new("brush_tasks"))
+        .anchor(Align2::RIGHT_BOTTOM, anchor_offset)
+        .show(ctx, |ui| {
+            Frame::none()
+                .inner_margin(Margin::same(8.0))
+                .rounding(Rounding::same(4.0))
+                .fill(ui.style().visuals.panel_fill)
+                .show(ui, |ui| {
+                    ui.add(Label::new(RichText::new("Brush tasks").strong()).selectable(false));
+
+                    pending.iter().for_each(|(entity, upb)| {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                Label::new(short_uuid(&upb.uuid().to_string())).selectable(false),
+                            );
+                            ui.add(Label::new("Waiting").selectable(false));
+                            if ui.small_button("Cancel").clicked() {
+                                commands.entity(entity).despawn();
+                            }
+                        });
+                    });
+
+                    tasks.iter().for_each(|task| {
+                        ui.horizontal(|ui| {
+                            ui.add(Spinner::new());
+                            ui.add(Label::new(short_uuid(task.uuid())).selectable(false));
+                            ui.add(
+                                Label::new(format!(
+                                    "{} ({:.1}s)",
+                                    task.stage(),
+                                    task.elapsed().as_secs_f32()
+                                ))
+                                .selectable(false),
+                            );
+                            if ui.small_button("Cancel").clicked() {
+                                commands.queue(CancelTerrainBrushTasksCommand {
+                                    uuid: task.uuid().to_owned(),
+                                });
+                            }
+                        });
+                    });
+                });
+        });
+}
+
+fn short_uuid(uuid: &str) -> &str {
+    uuid.get(..8).unwrap_or(uuid)
+}
+
+pub fn log_brush_tasks(mut events: EventReader<TerrainBrushTaskEvent>) {
+    for event in events.read() {
+        match event {
+            TerrainBrushTaskEvent::Started { .. } => {}
+            TerrainBrushTaskEvent::Finished {
+                uuid,
+                duration,
+                fallback,
+            } => {
+                if *fallback {
+                    warn!(
+                        "brush {uuid} failed after {:.2}s, using fallback",
+                        duration.as_secs_f32()
+                    );
+                } else {
+                    info!("brush {uuid} finished in {:.2}s", duration.as_secs_f32());
+                }
+            }
+            TerrainBrushTaskEvent::Cancelled { uuid, duration } => {
+                info!(
+                    "brush {uuid} cancelled after {:.2}s",
+                    duration.as_secs_f32()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    log::{info, warn},
+    prelude::{ResMut, Resource},
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+use egui::{
+    Align, Align2, Area, Button, CollapsingHeader, Color32, Context, Frame, Id, Label, Layout,
+    Margin, RichText, Rounding, ScrollArea, SelectableLabel, Spinner, Ui, Vec2,
+};
+
+use crate::data::{
+    build_collection, collection_file_name, write_collection, BuildReport, Environment, FileStatus,
+    COLLECTION_NAME,
+};
+
+const WIDTH: f32 = 480.0;
+
+/// Environments that can be built from the build menu.
+const BUILD_ENVIRONMENTS: [Environment; 2] = [Environment::Staging, Environment::Production];
+
+struct BuildOutput {
+    env: Environment,
+    report: Option<BuildReport>,
+    /// Where the collection was written, or why it wasn't.
+    result: Result<PathBuf, String>,
+}
+
+/// Builds asset collections in the background and reports the problems with each file.
+#[derive(Resource, Default)]
+pub struct BuildState {
+    pub visible: bool,
+    task: Option<Task<Vec<BuildOutput>>>,
+    outputs: Vec<BuildOutput>,
+    /// Also list files that were skipped or built without warnings.
+    show_all: bool,
+}
+
+impl BuildState {
+    /// Builds each environment in order. Collections are written next to the directory that
+    /// contains the editor files, where the game loads them from.
+    pub fn start(&mut self, directory: &Path, envs: Vec<Environment>) {
+        if self.task.is_some() {
+            return;
+        }
+
+        let directory = directory.to_owned();
+        self.visible = true;
+        self.outputs.clear();
+        self.task =
+            Some(AsyncComputeTaskPool::get().spawn(async move {
+                envs.into_iter().map(|env| build(&directory, env)).collect()
+            }));
+    }
+}
+
+/// Collections with failed files are missing assets, so they're never written.
+fn build(directory: &Path, env: Environment) -> BuildOutput {
+    let (assets, report) = match build_collection(directory, env) {
+        Ok(built) => built,
+        Err(error) => {
+            return BuildOutput {
+                env,
+                report: None,
+                result: Err(error.to_string()),
+            }
+        }
+    };
+
+    let result = if report.is_complete() {
+        let output = directory.parent().unwrap_or(directory);
+        let path = output.join(collection_file_name(COLLECTION_NAME, env, "cbor"));
+        write_collection(&path, &assets)
+            .map(|_| path)
+            .map_err(|error| error.to_string())
+    } else if report.count(FileStatus::Failed) > 0 {
+        Err(format!(
+            "{} files failed, so the collection wasn't written",
+            report.count(FileStatus::Failed)
+        ))
+    } else {
+        Err("no files were built".to_owned())
+    };
+
+    BuildOutput {
+        env,
+        report: Some(report),
+        result,
+    }
+}
+
+pub fn receive_build_task(mut build: ResMut<BuildState>) {
+    let Some(task) = build.task.as_mut() else {
+        return;
+    };
+    let Some(outputs) = block_on(future::poll_once(task)) else {
+        return;
+    };
+
+    for output in outputs.iter() {
+        match &output.result {
+            Ok(path) => info!("built {} collection: {}", output.env, path.display()),
+            Err(error) => warn!("{} build failed: {error}", output.env),
+        }
+    }
+    build.task = None;
+    build.outputs = outputs;
+}
+
+pub fn build_menu(build: &mut BuildState, directory: &Path, ui: &mut Ui) {
+    let idle = build.task.is_none();
+
+    BUILD_ENVIRONMENTS.into_iter().for_each(|env| {
+        let button = ui.add_enabled(idle, SelectableLabel::new(false, env.to_string()));
+        if button.clicked() {
+            ui.close_menu();
+            build.start(directory, vec![env]);
+        }
+    });
+
+    let all_button = ui.add_enabled(idle, SelectableLabel::new(false, "All"));
+    if all_button.clicked() {
+        ui.close_menu();
+        build.start(directory, BUILD_ENVIRONMENTS.to_vec());
+    }
+
+    ui.separator();
+
+    let report_button = ui.add_enabled(
+        !build.outputs.is_empty() || !idle,
+        SelectableLabel::new(build.visible, "Report"),
+    );
+    if report_button.clicked() {
+        ui.close_menu();
+        build.visible = !build.visible;
+    }
+}
+
+pub fn build_report(ctx: &Context, build: &mut BuildState) {
+    let error_color = Color32::from_rgb(160, 70, 70);
+    let warning_color = Color32::from_rgb(200, 160, 50);
+    let success_color = Color32::from_rgb(70, 170, 70);
+
+    let mut close = false;
+
+    Area::new(Id::new("build_report"))
+        .default_width(WIDTH)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            Frame::none()
+                .inner_margin(Margin::same(16.0))
+                .rounding(Rounding::same(8.0))
+                .fill(ui.style().visuals.panel_fill)
+                .show(ui, |ui| {
+                    ui.set_width(WIDTH);
+                    ui.style_mut().spacing.item_spacing.y = 8.0;
+
+                    ui.add(Label::new(RichText::new("Build report").heading()).selectable(false));
+
+                    if build.task.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.add(Spinner::new());
+                            ui.add(Label::new("Building...").selectable(false));
+                        });
+                    }
+
+                    ui.checkbox(&mut build.show_all, "Show skipped and clean files");
+
+                    ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                        for output in build.outputs.iter() {
+                            ui.separator();
+
+                            let (summary, color) = match &output.result {
+                                Ok(path) => (format!("Wrote {}", path.display()), success_color),
+                                Err(error) => (error.clone(), error_color),
+                            };
+                            ui.add(
+                                Label::new(RichText::new(output.env.to_string()).strong())
+                                    .selectable(false),
+                            );
+                            ui.add(Label::new(RichText::new(summary).color(color)));
+
+                            let Some(report) = &output.report else {
+                                continue;
+                            };
+                            ui.add(
+                                Label::new(format!(
+                                    "{} built, {} failed, {} skipped, {} warnings",
+                                    report.count(FileStatus::Built),
+                                    report.count(FileStatus::Failed),
+                                    report.count(FileStatus::Skipped),
+                                    report.warning_count(),
+                                ))
+                                .selectable(false),
+                            );
+
+                            for file in report.files.iter() {
+                                let clean = file.errors.is_empty() && file.warnings.is_empty();
+                                if clean && !build.show_all {
+                                    continue;
+                                }
+
+                                let name = file
+                                    .path
+                                    .file_name()
+                                    .map(|name| name.to_string_lossy().into_owned())
+                                    .unwrap_or_default();
+                                let color = match file.status {
+                                    FileStatus::Failed => error_color,
+                                    _ if !file.warnings.is_empty() => warning_color,
+                                    _ => ui.style().visuals.text_color(),
+                                };
+
+                                CollapsingHeader::new(
+                                    RichText::new(format!("{name} ({})", file.status)).color(color),
+                                )
+                                .id_salt((output.env as u8, &file.path))
+                                .default_open(!clean)
+                                .show(ui, |ui| {
+                                    for error in file.errors.iter() {
+                                        ui.add(Label::new(RichText::new(error).color(error_color)));
+                                    }
+                                    for warning in file.warnings.iter() {
+                                        ui.add(Label::new(
+                                            RichText::new(warning).color(warning_color),
+                                        ));
+                                    }
+                                });
+                            }
+                        }
+                    });
+
+                    ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                        if ui.add(Button::new("Close")).clicked() {
+                            close = true;
+                        }
+                    });
+                });
+        });
+
+    if close {
+        build.visible = false;
+    }
+}
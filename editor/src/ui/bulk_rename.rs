@@ -0,0 +1,244 @@
+use bevy::prelude::Resource;
+use egui::{
+    Align, Align2, Area, Button, Color32, ComboBox, Context, Frame, Grid, Id, Label, Layout,
+    Margin, RichText, Rounding, ScrollArea, TextEdit, Vec2,
+};
+use regex::Regex;
+use strum::IntoEnumIterator;
+
+use crate::state::{EditorState, FilePickerState, FILENAME_CHARS, FILENAME_MAX_CHARS};
+
+const WIDTH: f32 = 360.0;
+
+#[derive(strum_macros::Display, strum_macros::EnumIter, Default, Clone, Copy, PartialEq)]
+pub enum BulkRenameMode {
+    #[default]
+    Prefix,
+    Suffix,
+    Regex,
+}
+
+/// Renames every file that passed the file browser's filters when the dialog was opened.
+#[derive(Resource, Default)]
+pub struct BulkRenameState {
+    pub visible: bool,
+    mode: BulkRenameMode,
+    /// Added to, or removed from, each name in prefix and suffix modes.
+    affix: String,
+    remove: bool,
+    pattern: String,
+    replacement: String,
+    files: Vec<String>,
+    error: Option<String>,
+}
+
+struct Rename {
+    old_name: String,
+    new_stem: String,
+    problem: Option<&'static str>,
+}
+
+impl BulkRenameState {
+    pub fn open(&mut self, state: &EditorState) {
+        self.visible = true;
+        self.error = None;
+        self.files = state
+            .files
+            .files
+            .iter()
+            .filter(|file| file.path.is_some() && state.files.is_visible(file))
+            .map(|file| file.name.clone())
+            .collect();
+    }
+
+    fn new_stem(&self, stem: &str, regex: Option<&Regex>) -> String {
+        match (self.mode, self.remove) {
+            (BulkRenameMode::Prefix, false) => format!("{}{stem}", self.affix),
+            (BulkRenameMode::Prefix, true) => {
+                stem.strip_prefix(&self.affix).unwrap_or(stem).to_owned()
+            }
+            (BulkRenameMode::Suffix, false) => format!("{stem}{}", self.affix),
+            (BulkRenameMode::Suffix, true) => {
+                stem.strip_suffix(&self.affix).unwrap_or(stem).to_owned()
+            }
+            (BulkRenameMode::Regex, _) => regex
+                .map(|regex| regex.replace_all(stem, &self.replacement).into_owned())
+                .unwrap_or_else(|| stem.to_owned()),
+        }
+    }
+
+    /// Files whose names would change, and why each can't be renamed, if it can't.
+    fn preview(&self, files: &FilePickerState, regex: Option<&Regex>) -> Vec<Rename> {
+        let mut renames = self
+            .files
+            .iter()
+            .filter_map(|name| files.files.iter().find(|file| &file.name == name))
+            .filter_map(|file| {
+                let stem = FilePickerState::file_stem(file);
+                let new_stem = self.new_stem(stem, regex);
+                (new_stem != stem).then(|| {
+                    let new_name =
+                        FilePickerState::file_name_for_mode(new_stem.clone(), &file.mode);
+                    (file.name.clone(), new_stem, new_name)
+                })
+            })
+            .collect::<Vec<_>>();
+        renames.sort_by(|a, b| a.0.cmp(&b.0));
+
+        renames
+            .iter()
+            .map(|(old_name, new_stem, new_name)| {
+                let problem = if new_stem.is_empty() {
+                    Some("Empty name")
+                } else if new_stem.chars().count() > FILENAME_MAX_CHARS {
+                    Some("Too long")
+                } else if !new_stem.chars().all(|c| FILENAME_CHARS.contains(c)) {
+                    Some("Invalid characters")
+                } else if files.files.iter().any(|file| &file.name == new_name) {
+                    // Renaming onto a file that's also being renamed depends on order, so it's
+                    // treated as a conflict too
+                    Some("Already exists")
+                } else if renames
+                    .iter()
+                    .filter(|(.., other)| other == new_name)
+                    .count()
+                    > 1
+                {
+                    Some("Duplicate")
+                } else {
+                    None
+                };
+
+                Rename {
+                    old_name: old_name.clone(),
+                    new_stem: new_stem.clone(),
+                    problem,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn bulk_rename_dialog(ctx: &Context, state: &mut EditorState, bulk: &mut BulkRenameState) {
+    let error_color = Color32::from_rgb(160, 70, 70);
+
+    let regex = match bulk.mode {
+        BulkRenameMode::Regex if !bulk.pattern.is_empty() => Some(Regex::new(&bulk.pattern)),
+        _ => None,
+    };
+    let regex_error = regex
+        .as_ref()
+        .and_then(|regex| regex.as_ref().err())
+        .map(|err| err.to_string());
+    let regex = regex.and_then(Result::ok);
+    let renames = bulk.preview(&state.files, regex.as_ref());
+    let can_rename = !renames.is_empty() && renames.iter().all(|rename| rename.problem.is_none());
+
+    let mut close_dialog = false;
+    let mut execute_action = false;
+
+    Area::new(Id::new("bulk_rename_dialog"))
+        .default_width(WIDTH)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            Frame::none()
+                .inner_margin(Margin::same(16.0))
+                .rounding(Rounding::same(8.0))
+                .fill(ui.style().visuals.panel_fill)
+                .show(ui, |ui| {
+                    ui.set_width(WIDTH);
+                    ui.style_mut().spacing.item_spacing.y = 12.0;
+
+                    ui.add(Label::new(RichText::new("Bulk rename").heading()).selectable(false));
+                    ui.add(
+                        Label::new(format!("{} files match the filters.", bulk.files.len()))
+                            .selectable(false),
+                    );
+
+                    ui.horizontal(|ui| {
+                        ComboBox::from_id_salt("bulk_rename_mode")
+                            .selected_text(bulk.mode.to_string())
+                            .show_ui(ui, |ui| {
+                                BulkRenameMode::iter().for_each(|mode| {
+                                    ui.selectable_value(&mut bulk.mode, mode, mode.to_string());
+                                });
+                            });
+
+                        if bulk.mode != BulkRenameMode::Regex {
+                            ui.checkbox(&mut bulk.remove, "Remove");
+                        }
+                    });
+
+                    match bulk.mode {
+                        BulkRenameMode::Prefix | BulkRenameMode::Suffix => {
+                            ui.add(TextEdit::singleline(&mut bulk.affix).hint_text("Text"));
+                        }
+                        BulkRenameMode::Regex => {
+                            ui.add(TextEdit::singleline(&mut bulk.pattern).hint_text("Pattern"));
+                            ui.add(
+                                TextEdit::singleline(&mut bulk.replacement)
+                                    .hint_text("Replacement, $1 for groups"),
+                            );
+                            if let Some(err) = &regex_error {
+                                ui.add(
+                                    Label::new(RichText::new(err).color(error_color).monospace())
+                                        .selectable(false),
+                                );
+                            }
+                        }
+                    }
+
+                    ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                        Grid::new("bulk_rename_preview")
+                            .striped(true)
+                            .num_columns(3)
+                            .show(ui, |ui| {
+                                for rename in renames.iter() {
+                                    ui.add(Label::new(rename.old_name.as_str()).selectable(false));
+                                    ui.add(Label::new(rename.new_stem.as_str()).selectable(false));
+                                    if let Some(problem) = rename.problem {
+                                        ui.add(
+                                            Label::new(RichText::new(problem).color(error_color))
+                                                .selectable(false),
+                                        );
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                    if let Some(err) = &bulk.error {
+                        ui.add(Label::new(RichText::new(err).color(error_color)).selectable(false));
+                    }
+
+                    ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                        let confirm_button = ui.add_enabled(
+                            can_rename,
+                            Button::new(format!("Rename {}", renames.len()))
+                                .fill(Color32::from_rgb(45, 100, 45)),
+                        );
+                        if confirm_button.clicked() {
+                            execute_action = true;
+                        }
+                        if ui.add(Button::new("Cancel")).clicked() {
+                            close_dialog = true;
+                        }
+                    });
+                });
+        });
+
+    if execute_action {
+        let renames = renames
+            .into_iter()
+            .map(|rename| (rename.old_name, rename.new_stem))
+            .collect::<Vec<_>>();
+
+        match state.files.bulk_rename(&renames) {
+            Ok(()) => close_dialog = true,
+            Err(err) => bulk.error = Some(err.to_string()),
+        }
+    }
+    if close_dialog {
+        bulk.visible = false;
+    }
+}
@@ -1,23 +1,42 @@
-use bevy::prelude::Commands;
+use std::collections::BTreeSet;
+
+use bevy::{log::error, prelude::Commands};
 use egui::{
-    menu, Align, Align2, Area, Button, Color32, ComboBox, Context, Frame, Id, Label, Layout,
-    Margin, Response, RichText, Rounding, ScrollArea, SelectableLabel, Sense, Stroke, TextEdit, Ui,
-    UiBuilder, Vec2,
+    menu, Align, Align2, Area, Button, Color32, ComboBox, Context, Frame, Id, Key, Label, Layout,
+    Margin, PopupCloseBehavior, Response, RichText, Rounding, ScrollArea, SelectableLabel, Sense,
+    Stroke, TextEdit, Ui, UiBuilder, Vec2,
 };
 use strum::{EnumProperty, IntoEnumIterator};
 
 use crate::{
+    data::Environment,
     mode::RevertCommand,
-    state::{EditorMode, EditorState},
+    state::{
+        EditorMode, EditorState, FilePickerState, FileState, FILENAME_CHARS, FILENAME_MAX_CHARS,
+    },
     ui::{open_file_action_dialog, FileActionDialogMode},
 };
 
-use super::{icons, EditorDialogVisibility, FileActionDialogState};
+use super::{icons, BulkRenameState, EditorDialogVisibility, FileActionDialogState};
+
+/// Space reserved for the environment badge in each row.
+const ENVIRONMENT_BADGE_WIDTH: f32 = 40.0;
+
+fn environment_badge(environment: Option<Environment>) -> RichText {
+    let (text, color) = match environment {
+        Some(Environment::Production) => ("PROD", Color32::from_rgb(70, 170, 70)),
+        Some(Environment::Staging) => ("STAGE", Color32::from_rgb(200, 160, 50)),
+        Some(Environment::Development) => ("DEV", Color32::from_gray(140)),
+        None => ("?", Color32::from_rgb(160, 70, 70)),
+    };
+    RichText::new(text).small().strong().color(color)
+}
 
 pub fn file_browser(
     state: &mut EditorState,
     dialogs: &mut EditorDialogVisibility,
     dialog_state: &mut FileActionDialogState,
+    bulk_rename: &mut BulkRenameState,
     ui: &mut Ui,
 ) {
     Frame::none()
@@ -51,6 +70,68 @@ pub fn file_browser(
                         });
                     });
             });
+
+            ui.columns_const(|[left, right]| {
+                left.add(Label::new("Filter by environment:").selectable(false));
+                let mut filter_environment_text = "All".to_owned();
+                if let Some(environment) = state.files.filter_environment {
+                    filter_environment_text = environment.to_string();
+                }
+
+                ComboBox::from_id_salt("filter_environment")
+                    .selected_text(filter_environment_text)
+                    .show_ui(right, |ui| {
+                        ui.selectable_value(&mut state.files.filter_environment, None, "All");
+
+                        Environment::iter().for_each(|environment| {
+                            ui.selectable_value(
+                                &mut state.files.filter_environment,
+                                Some(environment),
+                                environment.to_string(),
+                            );
+                        });
+                    });
+            });
+
+            ui.columns_const(|[left, right]| {
+                left.add(Label::new("Filter by tags:").selectable(false));
+                let filter_tags_text = if state.files.filter_tags.is_empty() {
+                    "All".to_owned()
+                } else {
+                    state
+                        .files
+                        .filter_tags
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+
+                ComboBox::from_id_salt("filter_tags")
+                    .selected_text(filter_tags_text)
+                    .close_behavior(PopupCloseBehavior::CloseOnClickOutside)
+                    .show_ui(right, |ui| {
+                        let all_tags = state.files.index.all_tags();
+                        if all_tags.is_empty() {
+                            ui.add(Label::new("No tags").selectable(false));
+                        }
+
+                        for tag in all_tags {
+                            let mut checked = state.files.filter_tags.contains(tag);
+                            if ui.checkbox(&mut checked, tag.as_str()).changed() {
+                                if checked {
+                                    state.files.filter_tags.insert(tag.clone());
+                                } else {
+                                    state.files.filter_tags.remove(tag);
+                                }
+                            }
+                        }
+                    });
+            });
+
+            if ui.button("Bulk rename...").clicked() {
+                bulk_rename.open(state);
+            }
         });
 
     ui.style_mut().spacing.item_spacing.y = 0.0;
@@ -65,14 +146,15 @@ pub fn file_browser(
         SaveAs,
         Rename,
         Delete,
+        SetEnvironment(Environment),
+        ToggleTag(String),
     }
 
     ScrollArea::vertical().show(ui, |ui| {
         ui.style_mut().spacing.item_spacing.y = 0.0;
 
         let current = state.files.current;
-        let filter = state.files.filter.trim();
-        let filter_mode = state.files.filter_mode;
+        let all_tags = state.files.index.all_tags();
 
         let mut action = Action::None;
         let mut index_to_act: Option<usize> = None;
@@ -91,14 +173,10 @@ pub fn file_browser(
 
         let mut row_i = 0; // For alternative bg colors
         for (file_i, file) in sorted.into_iter() {
-            if !filter.is_empty() && !file.name.contains(filter) {
+            if !state.files.is_visible(file) {
                 continue;
             }
-            if let Some(filter_mode) = filter_mode {
-                if file.mode != filter_mode {
-                    continue;
-                };
-            }
+            let environment = file.environment();
 
             let response = ui
                 .scope_builder(UiBuilder::new().sense(Sense::click()), |ui| {
@@ -137,12 +215,38 @@ pub fn file_browser(
                                 }
 
                                 ui.add(Label::new(filename).selectable(false));
-                                ui.add_space(ui.available_size_before_wrap().x - 18.0);
+                                for tag in state.files.index.tags(&file.name) {
+                                    ui.add(
+                                        Label::new(RichText::new(format!("#{tag}")).small().weak())
+                                            .selectable(false),
+                                    );
+                                }
+                                ui.add_space(
+                                    ui.available_size_before_wrap().x
+                                        - 18.0
+                                        - ENVIRONMENT_BADGE_WIDTH,
+                                );
 
                                 Frame::none().show(ui, |ui| {
                                     ui.shrink_width_to_current();
 
                                     menu::bar(ui, |ui| {
+                                        ui.menu_button(environment_badge(environment), |ui| {
+                                            Environment::iter().for_each(|option| {
+                                                let selected = environment == Some(option);
+                                                let label = ui
+                                                    .selectable_label(selected, option.to_string());
+                                                if label.clicked() && !selected {
+                                                    action = Action::SetEnvironment(option);
+                                                }
+                                            });
+
+                                            if action != Action::None {
+                                                ui.close_menu();
+                                                index_to_act = Some(file_i);
+                                            }
+                                        });
+
                                         ui.menu_button("...", |ui| {
                                             ui.add(Label::new(file.name.clone()).selectable(false));
 
@@ -177,6 +281,18 @@ pub fn file_browser(
                                                 action = Action::Delete;
                                             }
 
+                                            ui.separator();
+
+                                            ui.add_enabled_ui(file.path.is_some(), |ui| {
+                                                ui.menu_button("Tags", |ui| {
+                                                    if let Some(tag) =
+                                                        tags_menu(ui, &state.files, file, &all_tags)
+                                                    {
+                                                        action = Action::ToggleTag(tag);
+                                                    }
+                                                });
+                                            });
+
                                             if action != Action::None {
                                                 ui.close_menu();
                                                 index_to_act = Some(file_i);
@@ -203,15 +319,19 @@ pub fn file_browser(
             // TODO handle errors
             match action {
                 Action::Open => state.files.switch_to_file(file_index).unwrap(),
-                Action::Save => {
-                    if !state.files.save_file(file_index).unwrap() {
-                        open_dialog_with_mode = Some(FileActionDialogMode::SaveAs)
-                    }
-                }
+                Action::Save => match state.files.save_file(file_index) {
+                    Ok(true) => {}
+                    Ok(false) => open_dialog_with_mode = Some(FileActionDialogMode::SaveAs),
+                    Err(err) => error!("failed to save: {err}"),
+                },
                 Action::SaveAs => open_dialog_with_mode = Some(FileActionDialogMode::SaveAs),
                 Action::Revert => open_dialog_with_mode = Some(FileActionDialogMode::Revert),
                 Action::Rename => open_dialog_with_mode = Some(FileActionDialogMode::Rename),
                 Action::Delete => open_dialog_with_mode = Some(FileActionDialogMode::Delete),
+                Action::SetEnvironment(environment) => state.files.files[file_index]
+                    .set_environment(environment)
+                    .unwrap(),
+                Action::ToggleTag(tag) => state.files.toggle_tag(file_index, &tag).unwrap(),
                 _ => {}
             };
 
@@ -222,6 +342,46 @@ pub fn file_browser(
     });
 }
 
+/// Toggles for every known tag, and a field to add a new one. Returns the tag to toggle.
+fn tags_menu(
+    ui: &mut Ui,
+    files: &FilePickerState,
+    file: &FileState,
+    all_tags: &BTreeSet<&String>,
+) -> Option<String> {
+    let mut toggled = None;
+
+    for tag in all_tags.iter() {
+        let mut checked = files.index.has_tag(&file.name, tag);
+        if ui.checkbox(&mut checked, tag.as_str()).changed() {
+            toggled = Some((*tag).clone());
+        }
+    }
+
+    if !all_tags.is_empty() {
+        ui.separator();
+    }
+
+    let id = Id::new("new_tag").with(&file.name);
+    let mut new_tag = ui.data_mut(|data| data.get_temp::<String>(id).unwrap_or_default());
+    let response = ui.add(
+        TextEdit::singleline(&mut new_tag)
+            .hint_text("New tag")
+            .desired_width(120.0),
+    );
+    new_tag = new_tag.replace(char::is_whitespace, "_").to_lowercase();
+
+    if response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
+        if !new_tag.is_empty() && !files.index.has_tag(&file.name, &new_tag) {
+            toggled = Some(new_tag.clone());
+        }
+        new_tag.clear();
+    }
+    ui.data_mut(|data| data.insert_temp(id, new_tag));
+
+    toggled
+}
+
 pub fn file_action_dialog(
     dialog_state: &mut FileActionDialogState,
     ctx: &mut Context,
@@ -237,10 +397,11 @@ pub fn file_action_dialog(
     let mut execute_action = false;
 
     fn filename_edit_field(ui: &mut Ui, value: &mut String) -> Response {
-        const ALLOWED_CHARS: &str = "-_0123456789abcdefghijklmnopqrstuvwxyz";
         let res = ui.add_sized(
             [WIDTH, 20.0],
-            TextEdit::singleline(value).char_limit(24).clip_text(true),
+            TextEdit::singleline(value)
+                .char_limit(FILENAME_MAX_CHARS)
+                .clip_text(true),
         );
         *value = value
             .chars()
@@ -251,7 +412,7 @@ pub fn file_action_dialog(
                     c.to_ascii_lowercase()
                 }
             })
-            .filter(|c| ALLOWED_CHARS.contains(c.to_owned()))
+            .filter(|c| FILENAME_CHARS.contains(c.to_owned()))
             .collect::<String>();
         res
     }
@@ -288,6 +449,10 @@ pub fn file_action_dialog(
                             Label::new("Are you sure you want to revert this file?")
                                 .selectable(false),
                         );
+                    } else if dialog_state.mode == FileActionDialogMode::Close {
+                        ui.add(
+                            Label::new("This file has unsaved changes.").selectable(false),
+                        );
                     } else if dialog_state.mode == FileActionDialogMode::Delete {
 		        ui.add(
                             Label::new("Are you sure you want to delete this file?")
@@ -323,6 +488,19 @@ pub fn file_action_dialog(
                             return;
                         };
 
+                        if dialog_state.mode == FileActionDialogMode::Close
+                            && ui.add(Button::new("Save")).clicked()
+                        {
+                            dialog_state.close_after_saving = true;
+                            if dialog_state.file_has_path {
+                                execute_action = true;
+                                close_dialog = true;
+                            } else {
+                                dialog_state.mode = FileActionDialogMode::SaveAs;
+                            }
+                            return;
+                        };
+
                         if ui.add(Button::new("Cancel")).clicked() {
                             close_dialog = true;
                             return;
@@ -341,16 +519,24 @@ pub fn execute_file_action_dialog_action(
         mode,
         file_index,
         input_name,
+        close_after_saving,
         ..
     }: &mut FileActionDialogState,
 ) {
     // TODO handle errors
     match *mode {
         FileActionDialogMode::SaveAs => {
-            state
+            match state
                 .files
                 .save_file_with_name(*file_index, input_name.clone())
-                .unwrap();
+            {
+                Err(err) => error!("failed to save: {err}"),
+                Ok(()) if *close_after_saving => {
+                    // Saved files are moved to the top
+                    state.files.close_file(0).unwrap();
+                }
+                Ok(()) => {}
+            }
         }
         FileActionDialogMode::Rename => {
             state
@@ -365,7 +551,25 @@ pub fn execute_file_action_dialog_action(
         FileActionDialogMode::Delete => {
             state.files.delete_file(*file_index).unwrap();
         }
+        FileActionDialogMode::Close => {
+            let saved = if *close_after_saving {
+                state.files.save_file(*file_index).map(|_| ())
+            } else {
+                Ok(())
+            };
+            // Files that fail to save stay open, so their changes aren't lost
+            if let Err(err) = saved {
+                error!("failed to save: {err}");
+            } else {
+                let was_current = state.files.current == Some(*file_index);
+                state.files.close_file(*file_index).unwrap();
+                if was_current && state.files.current.is_some() {
+                    commands.queue(RevertCommand);
+                }
+            }
+        }
     }
 
     input_name.clear();
+    *close_after_saving = false;
 }
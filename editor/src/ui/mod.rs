@@ -1,6 +1,9 @@
 use bevy::{
     app::{App, Plugin, Update},
-    prelude::{Commands, MouseButton, ResMut, Resource, Single, With},
+    log::error,
+    prelude::{
+        Commands, Entity, MouseButton, Query, Res, ResMut, Resource, Single, Transform, With,
+    },
 };
 use bevy_egui::{
     egui::{self, menu, Color32, Margin, Ui},
@@ -11,6 +14,7 @@ use egui::{
     vec2, Align2, Area, Frame, Id, Label, Layout, RichText, Rounding, SelectableLabel, SidePanel,
     TopBottomPanel, Vec2, Visuals,
 };
+use lib::worldgen::{brush::TerrainBrushTask, terrain::VoxelMaterials};
 use nalgebra::{Point3, Vector3};
 use strum::{EnumProperty, IntoEnumIterator};
 
@@ -23,14 +27,26 @@ use crate::{
     },
 };
 
+mod brush_tasks;
+mod build;
+mod bulk_rename;
 mod file_browser;
 mod icons;
+mod part_library;
 mod vhacd;
 
+use brush_tasks::{brush_tasks_area, log_brush_tasks};
+pub use build::BuildState;
+use build::{build_menu, build_report, receive_build_task};
+use bulk_rename::bulk_rename_dialog;
+pub use bulk_rename::BulkRenameState;
 use file_browser::{execute_file_action_dialog_action, file_action_dialog, file_browser};
+pub use part_library::PartLibraryState;
+use part_library::{part_library_window, render_thumbnails};
 pub use vhacd::vhacd_parameters_sidebar;
 
 const TOP_PANEL_HEIGHT: f32 = 30.0;
+const TAB_BAR_HEIGHT: f32 = 24.0;
 const LEFT_PANEL_WIDTH: f32 = 230.0;
 const RIGHT_PANEL_WIDTH: f32 = 230.0;
 
@@ -50,6 +66,8 @@ pub enum FileActionDialogMode {
     Revert,
     #[strum(props(title = "Delete", confirm = "Delete"))]
     Delete,
+    #[strum(props(title = "Close", confirm = "Discard"))]
+    Close,
 }
 
 #[derive(Resource, Default)]
@@ -60,6 +78,9 @@ pub struct FileActionDialogState {
     pub current_name: String,
     pub input_name: String,
     pub file_extension: String,
+    pub file_has_path: bool,
+    /// Set when saving from the close dialog, so the tab closes once the file is saved.
+    pub close_after_saving: bool,
 }
 
 #[derive(Resource)]
@@ -87,8 +108,14 @@ impl Plugin for EditorUiPlugin {
         app.init_resource::<EditorDialogVisibility>();
         app.init_resource::<SidePanelVisibility>();
         app.init_resource::<FileActionDialogState>();
+        app.init_resource::<BulkRenameState>();
+        app.init_resource::<BuildState>();
         app.init_resource::<EguiHasPointer>();
-        app.add_systems(Update, ui);
+        app.init_resource::<PartLibraryState>();
+        app.add_systems(
+            Update,
+            (ui, log_brush_tasks, receive_build_task, render_thumbnails),
+        );
     }
 }
 
@@ -98,10 +125,18 @@ fn ui(
     mut side_panel_visibility: ResMut<SidePanelVisibility>,
     mut dialogs: ResMut<EditorDialogVisibility>,
     mut file_action_dialog_state: ResMut<FileActionDialogState>,
+    mut bulk_rename: ResMut<BulkRenameState>,
+    mut build: ResMut<BuildState>,
+    mut part_library: ResMut<PartLibraryState>,
     mut egui_has_pointer: ResMut<EguiHasPointer>,
+    voxel_materials: Res<VoxelMaterials>,
     mut contexts: EguiContexts,
     trackball: Option<Single<(&mut TrackballController, &mut TrackballCamera)>>,
-    room_mode_primary_selection: Option<Single<&RoomPartUuid, With<PrimarySelection>>>,
+    room_mode_primary_selection: Option<
+        Single<(&RoomPartUuid, &mut Transform), With<PrimarySelection>>,
+    >,
+    brush_tasks: Query<&TerrainBrushTask>,
+    pending_part_rebuilds: Query<(Entity, &room::UpdatePreviewBrush)>,
 ) {
     let ctx = contexts.ctx_mut();
     ctx.set_visuals(Visuals::dark());
@@ -115,14 +150,31 @@ fn ui(
         .resizable(false)
         .show(ctx, |ui| {
             top_panel(
+                &mut commands,
                 &mut state,
                 &mut dialogs,
                 &mut file_action_dialog_state,
+                &mut build,
                 ui,
                 trackball,
             );
         });
 
+    // Tab bar
+    let show_tab_bar = state.files.files.iter().any(|file| file.tab.is_some());
+    if show_tab_bar {
+        let mut tab_frame = Frame::side_top_panel(&ctx.style());
+        tab_frame.inner_margin = Margin::symmetric(8.0, 2.0);
+        TopBottomPanel::top("tab_bar")
+            .frame(tab_frame)
+            .exact_height(TAB_BAR_HEIGHT)
+            .resizable(false)
+            .show(ctx, |ui| {
+                tab_bar(&mut state, &mut dialogs, &mut file_action_dialog_state, ui);
+            });
+    }
+    let top_height = TOP_PANEL_HEIGHT + if show_tab_bar { TAB_BAR_HEIGHT } else { 0.0 };
+
     // Left panel
     if side_panel_visibility.left {
         let mut left_frame = Frame::side_top_panel(&ctx.style());
@@ -133,7 +185,13 @@ fn ui(
             .max_width(LEFT_PANEL_WIDTH)
             .resizable(false)
             .show(ctx, |ui| {
-                file_browser(&mut state, &mut dialogs, &mut file_action_dialog_state, ui);
+                file_browser(
+                    &mut state,
+                    &mut dialogs,
+                    &mut file_action_dialog_state,
+                    &mut bulk_rename,
+                    ui,
+                );
                 ui.allocate_rect(ui.available_rect_before_wrap(), egui::Sense::hover());
             });
     }
@@ -150,9 +208,12 @@ fn ui(
             .show(ctx, |ui| {
                 match state.mode() {
                     Some(EditorMode::Tunnels) => tunnel::ui::sidebar(&mut state, ui),
-                    Some(EditorMode::Rooms) => {
-                        room::ui::sidebar(&mut state, ui, room_mode_primary_selection)
-                    }
+                    Some(EditorMode::Rooms) => room::ui::sidebar(
+                        &mut state,
+                        ui,
+                        room_mode_primary_selection,
+                        &voxel_materials,
+                    ),
                     _ => {}
                 };
                 ui.allocate_rect(ui.available_rect_before_wrap(), egui::Sense::hover());
@@ -169,7 +230,7 @@ fn ui(
                 } else {
                     0.0
                 } + 8.0,
-                TOP_PANEL_HEIGHT + 8.0,
+                top_height + 8.0,
             ),
         )
         .show(ctx, |ui| {
@@ -184,7 +245,7 @@ fn ui(
                 } else {
                     0.0
                 } - 8.0,
-                TOP_PANEL_HEIGHT + 8.0,
+                top_height + 8.0,
             ),
         )
         .show(ctx, |ui| {
@@ -196,6 +257,22 @@ fn ui(
         .inner
         .contains_pointer();
 
+    // Brush tasks
+    brush_tasks_area(
+        ctx,
+        &mut commands,
+        vec2(
+            if side_panel_visibility.right {
+                -RIGHT_PANEL_WIDTH
+            } else {
+                0.0
+            } - 8.0,
+            -8.0,
+        ),
+        &brush_tasks,
+        &pending_part_rebuilds,
+    );
+
     // No open files indicator
     if state.files.current.is_none() {
         Area::new(Id::new("no_open_files"))
@@ -255,13 +332,33 @@ fn ui(
         }
     }
 
+    // Bulk rename dialog
+    if bulk_rename.visible {
+        bulk_rename_dialog(ctx, &mut state, &mut bulk_rename);
+    }
+
+    // Build report
+    if build.visible {
+        build_report(ctx, &mut build);
+    }
+
+    // Part library
+    part_library_window(ctx, &mut state, &mut part_library);
+
+    // Region selection
+    if state.mode() == Some(EditorMode::Rooms) {
+        room::ui::region_overlay(ctx, &state);
+    }
+
     egui_has_pointer.0 = ctx.is_pointer_over_area();
 }
 
 fn top_panel(
+    commands: &mut Commands,
     state: &mut EditorState,
     dialogs: &mut EditorDialogVisibility,
     dialog_state: &mut FileActionDialogState,
+    build: &mut BuildState,
     ui: &mut Ui,
     trackball: Option<Single<(&mut TrackballController, &mut TrackballCamera)>>,
 ) {
@@ -278,6 +375,9 @@ fn top_panel(
                         && state.view == EditorViewMode::Editor);
                     viewport_menu(ui, allow_orbit, trackball);
                 });
+                ui.menu_button("Build", |ui| {
+                    build_menu(build, &state.files.directory, ui);
+                });
             });
         });
 
@@ -332,12 +432,79 @@ fn top_panel(
         // Mode-specific
         match state.mode() {
             Some(EditorMode::Tunnels) => tunnel::ui::topbar(state, ui),
-            Some(EditorMode::Rooms) => room::ui::topbar(state, ui),
+            Some(EditorMode::Rooms) => room::ui::topbar(state, commands, ui),
             _ => {}
         }
     });
 }
 
+fn tab_bar(
+    state: &mut EditorState,
+    dialogs: &mut EditorDialogVisibility,
+    dialog_state: &mut FileActionDialogState,
+    ui: &mut Ui,
+) {
+    let mut switch_to: Option<usize> = None;
+    let mut close: Option<usize> = None;
+
+    egui::ScrollArea::horizontal().show(ui, |ui| {
+        ui.horizontal(|ui| {
+            for index in state.files.tabs() {
+                let file = &state.files.files[index];
+                let selected = state.files.current == Some(index);
+
+                Frame::none()
+                    .inner_margin(Margin::symmetric(6.0, 0.0))
+                    .rounding(Rounding::same(4.0))
+                    .fill(if selected {
+                        ui.style().visuals.selection.bg_fill
+                    } else {
+                        ui.style().visuals.faint_bg_color
+                    })
+                    .show(ui, |ui| {
+                        ui.style_mut().spacing.item_spacing.x = 4.0;
+                        if file.changed {
+                            icons::changed_default(ui);
+                        }
+                        let name = FilePickerState::file_stem(file).to_owned();
+                        if ui
+                            .add(
+                                Label::new(name)
+                                    .selectable(false)
+                                    .sense(egui::Sense::click()),
+                            )
+                            .on_hover_text(&file.name)
+                            .clicked()
+                        {
+                            switch_to = Some(index);
+                        }
+                        if ui.small_button("x").on_hover_text("Close").clicked() {
+                            close = Some(index);
+                        }
+                    });
+            }
+        });
+    });
+
+    // TODO handle errors
+    if let Some(index) = switch_to {
+        state.files.switch_to_file(index).unwrap();
+    }
+    if let Some(index) = close {
+        if state.files.files[index].changed {
+            open_file_action_dialog(
+                state,
+                dialogs,
+                dialog_state,
+                FileActionDialogMode::Close,
+                index,
+            );
+        } else {
+            state.files.close_file(index).unwrap();
+        }
+    }
+}
+
 fn file_menu(
     state: &mut EditorState,
     dialogs: &mut EditorDialogVisibility,
@@ -366,8 +533,9 @@ fn file_menu(
     let save_button = ui.add_enabled(changed, SelectableLabel::new(false, "Save"));
     if save_button.clicked() {
         ui.close_menu();
-        // TODO handle this
-        save_current_file(state, dialogs, dialog_state).expect("save failed");
+        if let Err(err) = save_current_file(state, dialogs, dialog_state) {
+            error!("failed to save: {err}");
+        }
     };
 
     let save_as_button = ui.add_enabled(
@@ -411,6 +579,29 @@ fn file_menu(
         );
     };
 
+    let close_button = ui.add_enabled(
+        state.files.current.is_some(),
+        SelectableLabel::new(false, "Close"),
+    );
+    if close_button.clicked() {
+        ui.close_menu();
+        let index = state.files.current.unwrap();
+        if changed {
+            open_file_action_dialog(
+                state,
+                dialogs,
+                dialog_state,
+                FileActionDialogMode::Close,
+                index,
+            );
+        } else {
+            // TODO handle errors
+            state.files.close_file(index).unwrap();
+        }
+    };
+
+    ui.separator();
+
     let delete_button = ui.add_enabled(
         state.files.current.is_some(),
         SelectableLabel::new(false, "Delete"),
@@ -506,6 +697,10 @@ fn viewport_menu(
         controller.input.slide_button = slide;
         ui.close_menu();
     }
+
+    ui.add_enabled_ui(allow_orbit, |ui| {
+        ui.weak("Press F to fly with WASD, scroll to change speed");
+    });
 }
 
 //
@@ -558,6 +753,8 @@ pub fn open_file_action_dialog(
         .collect();
     dialog_state.file_extension = FilePickerState::file_ext_for_mode(&file.mode);
     dialog_state.input_name = String::new();
+    dialog_state.file_has_path = file.path.is_some();
+    dialog_state.close_after_saving = false;
 
     dialogs.show_filename_dialog = true;
 }
@@ -0,0 +1,345 @@
+use std::path::Path;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    log::{error, warn},
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        mesh::{Indices, PrimitiveTopology},
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::RenderLayers,
+    },
+};
+use bevy_egui::EguiContexts;
+use egui::{Button, Context, Label, ScrollArea, Sense, Spinner, TextEdit, Ui, Window};
+use lib::{render_layer, worldgen::voxel::VoxelMaterial};
+
+use crate::{
+    data::{load_stl_to_raw_geometry, RoomPart, EXPORT_DIRECTORY},
+    state::{EditorState, FilePayload},
+};
+
+/// Scanned for STL files, including subdirectories other than [`EXPORT_DIRECTORY`], which holds
+/// exported rooms rather than parts.
+const PARTS_DIRECTORY: &str = "assets/stl";
+const THUMBNAIL_SIZE: u32 = 96;
+/// Frames each thumbnail's camera is kept for, so the image is rendered before it's despawned.
+const THUMBNAIL_RENDER_FRAMES: u32 = 3;
+const THUMBNAIL_BACKGROUND: Color = Color::srgb(0.12, 0.12, 0.14);
+/// Direction the thumbnail camera looks at parts from.
+const THUMBNAIL_VIEW: Vec3 = Vec3::new(1.0, 0.8, 1.0);
+
+/// STL room parts found in [`PARTS_DIRECTORY`], with thumbnails rendered offscreen one at a time.
+#[derive(Resource, Default)]
+pub struct PartLibraryState {
+    filter: String,
+    parts: Vec<LibraryPart>,
+    /// Cleared to rescan the parts directory.
+    scanned: bool,
+}
+
+struct LibraryPart {
+    path: String,
+    name: String,
+    thumbnail: Thumbnail,
+}
+
+enum Thumbnail {
+    Pending,
+    Rendering,
+    Ready {
+        image: Handle<Image>,
+        texture: egui::TextureId,
+    },
+    Failed,
+}
+
+/// Camera, light, and mesh of a thumbnail being rendered.
+#[derive(Component)]
+pub struct ThumbnailScene {
+    path: String,
+    image: Handle<Image>,
+    frames_left: u32,
+}
+
+impl PartLibraryState {
+    /// Finds the parts. Thumbnails from a previous scan are freed and rendered again, since the
+    /// parts may have changed.
+    fn scan(&mut self, contexts: &mut EguiContexts, images: &mut Assets<Image>) {
+        for part in self.parts.drain(..) {
+            if let Thumbnail::Ready { image, .. } = part.thumbnail {
+                contexts.remove_image(&image);
+                images.remove(&image);
+            }
+        }
+
+        let mut paths = Vec::new();
+        find_stl_files(Path::new(PARTS_DIRECTORY), &mut paths);
+        paths.sort();
+
+        self.scanned = true;
+        self.parts = paths
+            .into_iter()
+            .map(|path| LibraryPart {
+                name: Path::new(&path)
+                    .file_stem()
+                    .map_or(path.clone(), |stem| stem.to_string_lossy().into_owned()),
+                path,
+                thumbnail: Thumbnail::Pending,
+            })
+            .collect();
+    }
+}
+
+fn find_stl_files(directory: &Path, paths: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        warn!(?directory, "failed to read room part directory");
+        return;
+    };
+
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if path.is_dir() {
+            if path != Path::new(EXPORT_DIRECTORY) {
+                find_stl_files(&path, paths);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("stl"))
+        {
+            paths.push(path.to_string_lossy().replace('\\', "/"));
+        }
+    }
+}
+
+//
+// Systems
+//
+
+/// Scans the parts directory when the library is first opened, then renders one thumbnail at a
+/// time while it's open.
+pub fn render_thumbnails(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    state: Res<EditorState>,
+    mut library: ResMut<PartLibraryState>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut scenes: Query<(Entity, &mut ThumbnailScene)>,
+) {
+    if !state.part_library_open() {
+        return;
+    }
+    if !library.scanned {
+        library.scan(&mut contexts, &mut images);
+    }
+
+    // Finish the thumbnail being rendered
+    if let Some((entity, mut scene)) = scenes.iter_mut().next() {
+        scene.frames_left = scene.frames_left.saturating_sub(1);
+        if scene.frames_left > 0 {
+            return;
+        }
+
+        commands.entity(entity).despawn_recursive();
+        match library
+            .parts
+            .iter_mut()
+            .find(|part| part.path == scene.path && matches!(part.thumbnail, Thumbnail::Rendering))
+        {
+            Some(part) => {
+                part.thumbnail = Thumbnail::Ready {
+                    image: scene.image.clone(),
+                    texture: contexts.add_image(scene.image.clone()),
+                };
+            }
+            // Rescanned while rendering
+            None => {
+                images.remove(&scene.image);
+            }
+        }
+        return;
+    }
+
+    // Start the next one
+    let Some(part) = library
+        .parts
+        .iter_mut()
+        .find(|part| matches!(part.thumbnail, Thumbnail::Pending))
+    else {
+        return;
+    };
+    let (vertices, indices) = match load_stl_to_raw_geometry(&part.path) {
+        Ok(geometry) => geometry,
+        Err(err) => {
+            warn!(path = %part.path, "failed to load room part thumbnail: {err}");
+            part.thumbnail = Thumbnail::Failed;
+            return;
+        }
+    };
+    part.thumbnail = Thumbnail::Rendering;
+
+    // Frame the part's bounding sphere
+    let (min, max) = vertices
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), v| {
+            (min.min(Vec3::from(*v)), max.max(Vec3::from(*v)))
+        });
+    let center = (min + max) / 2.0;
+    let radius = (max - min).length().max(0.01) / 2.0;
+    let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
+        .with_inserted_indices(Indices::U32(indices))
+        .with_duplicated_vertices()
+        .with_computed_flat_normals();
+
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: THUMBNAIL_SIZE,
+            height: THUMBNAIL_SIZE,
+            ..default()
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::all(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+    let image = images.add(image);
+
+    let layer = RenderLayers::layer(render_layer::THUMBNAIL);
+    let distance = radius / (std::f32::consts::FRAC_PI_4 / 2.0).sin();
+    commands
+        .spawn((
+            ThumbnailScene {
+                path: part.path.clone(),
+                image: image.clone(),
+                frames_left: THUMBNAIL_RENDER_FRAMES,
+            },
+            Transform::default(),
+            Visibility::default(),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Camera3d::default(),
+                Camera {
+                    target: RenderTarget::Image(image),
+                    // Before the editor cameras
+                    order: -1,
+                    clear_color: ClearColorConfig::Custom(THUMBNAIL_BACKGROUND),
+                    ..default()
+                },
+                Transform::from_translation(THUMBNAIL_VIEW.normalize() * distance)
+                    .looking_at(Vec3::ZERO, Vec3::Y),
+                layer.clone(),
+            ));
+            parent.spawn((
+                DirectionalLight::default(),
+                Transform::from_translation(THUMBNAIL_VIEW).looking_at(Vec3::ZERO, Vec3::Y),
+                layer.clone(),
+            ));
+            parent.spawn((
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: Color::srgb(0.75, 0.6, 0.45),
+                    perceptual_roughness: 0.9,
+                    double_sided: true,
+                    cull_mode: None,
+                    ..default()
+                })),
+                Transform::from_translation(-center),
+                layer,
+            ));
+        });
+}
+
+//
+// Ui
+//
+
+/// Clicking a part, or dragging it into the viewport, places it like the add menu does.
+pub fn part_library_window(ctx: &Context, state: &mut EditorState, library: &mut PartLibraryState) {
+    if !state.part_library_open() {
+        return;
+    }
+
+    let mut open = true;
+    let mut add: Option<String> = None;
+    Window::new("Part library")
+        .open(&mut open)
+        .default_width(THUMBNAIL_SIZE as f32 * 3.5)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut library.filter).hint_text("Filter"));
+                if ui.button("Rescan").clicked() {
+                    library.scanned = false;
+                }
+            });
+
+            if library.parts.is_empty() {
+                ui.add(Label::new(format!("No parts in {PARTS_DIRECTORY}")).selectable(false));
+                return;
+            }
+
+            ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let filter = library.filter.to_lowercase();
+                    for part in library
+                        .parts
+                        .iter()
+                        .filter(|part| part.name.to_lowercase().contains(&filter))
+                    {
+                        if part_tile(ui, part) {
+                            add = Some(part.path.clone());
+                        }
+                    }
+                });
+            });
+        });
+    if !open {
+        state.rooms_mode.library = false;
+    }
+
+    let Some(path) = add else {
+        return;
+    };
+    let Some(FilePayload::Room(data)) = state.files.current_data_mut() else {
+        return;
+    };
+    match RoomPart::stl(&path, VoxelMaterial::BrownRock, Transform::default()) {
+        Ok(mut part) => {
+            part.place_after_spawn = true;
+            data.push(part);
+        }
+        Err(err) => error!(%path, "failed to load room part: {err}"),
+    }
+}
+
+/// Returns true when the part should be placed.
+fn part_tile(ui: &mut Ui, part: &LibraryPart) -> bool {
+    let size = egui::Vec2::splat(THUMBNAIL_SIZE as f32);
+
+    ui.vertical(|ui| {
+        ui.set_width(size.x);
+        let response = match &part.thumbnail {
+            Thumbnail::Ready { texture, .. } => {
+                ui.add(Button::image((*texture, size)).sense(Sense::click_and_drag()))
+            }
+            Thumbnail::Failed => ui.add_enabled(false, Button::new("Failed").min_size(size)),
+            Thumbnail::Pending | Thumbnail::Rendering => ui
+                .allocate_ui(size, |ui| {
+                    ui.centered_and_justified(|ui| ui.add(Spinner::new()))
+                })
+                .response
+                .interact(Sense::click_and_drag()),
+        };
+        ui.add(Label::new(part.name.as_str()).truncate().selectable(false));
+
+        // Dragging starts placement right away, so releasing over the viewport drops the part
+        let response = response.on_hover_text(part.path.as_str());
+        response.clicked() || response.drag_started()
+    })
+    .inner
+}
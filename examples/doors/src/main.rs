@@ -14,9 +14,9 @@ use bevy::{
 use bevy_egui::EguiPlugin;
 use bevy_rand::{plugin::EntropyPlugin, prelude::WyRand};
 use lib::{
-    meshgen::{AddDoorwayToEntity, DoorwaySpec, MeshGenerationPlugin},
-    physics::GameLayer,
-    player::{PlayerPlugin, SpawnPlayerCommand},
+    meshgen::{AddDoorwayToEntity, DoorwaySpec, SwitchKind, SwitchSpec},
+    prelude::*,
+    worldgen::asset,
 };
 
 fn main() {
@@ -64,7 +64,7 @@ fn main() {
 
     app.add_plugins((MeshGenerationPlugin, PlayerPlugin));
 
-    app.add_systems(Startup, (setup_world, setup_player).chain());
+    app.add_systems(Startup, (setup_world, setup_room, setup_player).chain());
     app.add_systems(Update, fixup_images);
 
     app.run();
@@ -110,31 +110,79 @@ fn setup_world(
         })),
     ));
 
+    let doorway = commands.spawn(Transform::default()).id();
+    commands.queue(AddDoorwayToEntity {
+        spec: doorway_spec(),
+        entity: doorway,
+        channel: None,
+    });
+}
+
+fn doorway_spec() -> DoorwaySpec {
     let frame_width = 6.0;
     let frame_height = 4.0;
     let door_width = 2.75;
     let door_height = 2.25;
     let door_offset = (0.6, 0.15);
-    let doorway = commands.spawn(Transform::default()).id();
-    commands.queue(AddDoorwayToEntity {
-        spec: DoorwaySpec {
-            frame: Rect {
-                min: Vec2::new(-frame_width / 2.0, 0.0),
-                max: Vec2::new(frame_width / 2.0, frame_height),
+    DoorwaySpec {
+        frame: Rect {
+            min: Vec2::new(-frame_width / 2.0, 0.0),
+            max: Vec2::new(frame_width / 2.0, frame_height),
+        },
+        door: Rect {
+            min: Vec2::new(-door_width / 2.0 + door_offset.0, door_offset.1),
+            max: Vec2::new(
+                door_width / 2.0 + door_offset.0,
+                door_offset.1 + door_height,
+            ),
+        },
+        frame_depth: 0.4,
+        door_depth: 0.075,
+        frame_uv_scale: 4.0,
+        door_uv_scale: 4.0,
+    }
+}
+
+/// A room with wired doorways, spawned the same way the layout spawns rooms. There's no terrain
+/// here, so its brushes aren't dug.
+fn setup_room(mut commands: Commands) {
+    let room = asset::Room {
+        cavities: vec![Collider::cuboid(24.0, 8.0, 12.0)],
+        doorways: vec![
+            asset::Doorway {
+                transform: Transform::from_xyz(-8.0, 0.0, 0.0),
+                spec: doorway_spec(),
+                channel: Some(0),
             },
-            door: Rect {
-                min: Vec2::new(-door_width / 2.0 + door_offset.0, door_offset.1),
-                max: Vec2::new(
-                    door_width / 2.0 + door_offset.0,
-                    door_offset.1 + door_height,
-                ),
+            asset::Doorway {
+                transform: Transform::from_xyz(8.0, 0.0, 0.0),
+                spec: doorway_spec(),
+                channel: Some(1),
             },
-            frame_depth: 0.4,
-            door_depth: 0.075,
-            frame_uv_scale: 4.0,
-            door_uv_scale: 4.0,
-        },
-        entity: doorway,
+        ],
+        switches: vec![
+            asset::Switch {
+                transform: Transform::from_xyz(-8.0, 0.0, 4.0),
+                spec: SwitchSpec {
+                    kind: SwitchKind::PressurePlate,
+                    channel: 0,
+                },
+            },
+            asset::Switch {
+                transform: Transform::from_xyz(8.0, 1.0, 4.0),
+                spec: SwitchSpec {
+                    kind: SwitchKind::Button,
+                    channel: 1,
+                },
+            },
+        ],
+        ..default()
+    };
+
+    commands.queue(SpawnRoomAssetCommand {
+        room,
+        transform: Transform::from_xyz(0.0, 0.0, -16.0),
+        connect: Vec::new(),
     });
 }
 
@@ -2,6 +2,10 @@
 
 use avian3d::prelude::*;
 use bevy::prelude::*;
+use lib::{
+    cable::{AnchorQuery, CableAnchor},
+    worldgen::terrain::Chunk,
+};
 
 use crate::player::{Player, PlayerCamera, PlayerMotion, Section};
 
@@ -119,10 +123,12 @@ fn despawn_missed_hooks(
 fn attach_to_surface(
     mut commands: Commands,
     mut collisions: EventReader<Collision>,
-    positions: Query<&Position>,
+    validator: AnchorQuery,
+    positions: Query<(&Position, &Rotation)>,
     grappling_hook: Option<Single<(Entity, &mut GrapplingHook)>>,
     player: Option<Single<Entity, With<Player>>>,
     sensors: Query<(), With<Sensor>>,
+    chunks: Query<(), With<Chunk>>,
 ) {
     let Some(player) = player else {
         return;
@@ -187,9 +193,21 @@ fn attach_to_surface(
             entity1
         };
 
-        let Ok(other_position) = positions.get(*other) else {
+        let Ok((other_position, other_rotation)) = positions.get(*other) else {
             return;
         };
+        let point = **other_position + shallowest_contact.point2;
+        let normal = shallowest_contact.global_normal2(other_rotation);
+
+        // Terrain has to be able to hold the hook, other bodies always do
+        if chunks.contains(*other) {
+            if let Err(err) = validator.validate(point, normal) {
+                info!("grappling hook didn't hold: {err}");
+                commands.entity(entity).despawn();
+                return;
+            }
+            commands.entity(entity).insert(CableAnchor { normal });
+        }
 
         let joint = commands
             .spawn(
@@ -199,9 +217,7 @@ fn attach_to_surface(
             )
             .id();
         let mut commands = commands.entity(entity);
-        commands.insert(Transform::from_translation(
-            **other_position + shallowest_contact.point2,
-        ));
+        commands.insert(Transform::from_translation(point));
         commands.insert(RigidBody::Dynamic);
         commands.remove::<LinearVelocity>();
         commands.add_child(joint);
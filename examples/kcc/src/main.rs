@@ -17,13 +17,12 @@ use bevy::{
 };
 use bevy_egui::EguiPlugin;
 use lib::{
+    prelude::*,
     render_layer,
-    weapon::{weapons, PlayerWeapons, WeaponPickup, WeaponPlugin, WeaponSlots},
+    weapon::{weapons, PlayerWeapons, WeaponSlots},
+    worldgen::layout::{GravityZonePlugin, STANDARD_GRAVITY},
 };
-use player::{Player, PlayerInputConfig, PlayerPlugin, PlayerWalkModMode};
-
-#[allow(unused)]
-use lib::weapon::ViewModelCamera;
+use player::{Climbable, Player, PlayerInputConfig, PlayerPlugin, PlayerWalkModMode};
 
 #[cfg(feature = "camera")]
 use grappling_hook::GrapplingHookPlugin;
@@ -72,7 +71,7 @@ fn main() {
         //PhysicsDebugPlugin::default(),
     ));
 
-    app.add_plugins((PlayerPlugin, WeaponPlugin));
+    app.add_plugins((PlayerPlugin, WeaponPlugin, FluidPlugin, GravityZonePlugin));
 
     #[cfg(feature = "camera")]
     app.add_plugins(GrapplingHookPlugin);
@@ -83,7 +82,12 @@ fn main() {
     app.run();
 }
 
-fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_world(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
     commands.insert_resource(AmbientLight {
         color: Color::srgb(1.0, 1.0, 1.0).into(),
         brightness: 600.0,
@@ -114,6 +118,44 @@ fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>) {
         Transform::from_translation(Vec3::Z * -4.0),
         WeaponPickup::new(&weapons::SHOTGUN),
     ));
+    commands.spawn((
+        Transform::from_xyz(4.0, 0.0, -8.0),
+        WeaponPickup::new(&weapons::GRAPPLE),
+    ));
+
+    // Ladder
+    commands.spawn((
+        Transform::from_xyz(4.0, 3.0, -4.0),
+        Collider::cuboid(1.0, 6.0, 0.2),
+        RigidBody::Static,
+        Climbable,
+        Mesh3d(meshes.add(Cuboid::new(1.0, 6.0, 0.2))),
+        MeshMaterial3d(materials.add(Color::srgb(0.6, 0.4, 0.2))),
+    ));
+
+    // Turntable
+    commands.spawn((
+        Transform::from_xyz(8.0, 0.25, 8.0),
+        Collider::cylinder(3.0, 0.5),
+        RigidBody::Kinematic,
+        AngularVelocity(Vec3::Y * 0.5),
+        Mesh3d(meshes.add(Cylinder::new(3.0, 0.5))),
+        MeshMaterial3d(materials.add(Color::srgb(0.4, 0.4, 0.5))),
+    ));
+
+    // Pool
+    commands.spawn((
+        Transform::from_xyz(-8.0, 1.0, -8.0).with_scale(Vec3::new(8.0, 4.0, 8.0)),
+        FluidVolume { level: 0.75 },
+    ));
+
+    // Low gravity column
+    commands.spawn((
+        Transform::from_xyz(-8.0, 8.0, 8.0).with_scale(Vec3::new(4.0, 16.0, 4.0)),
+        GravityZone {
+            gravity: Vec3::NEG_Y * STANDARD_GRAVITY * 0.2,
+        },
+    ));
 }
 
 fn setup_collider(
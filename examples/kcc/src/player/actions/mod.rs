@@ -108,7 +108,17 @@ pub fn perform_actions(
                     return false;
                 };
 
-                if state.grounded {
+                if let Some(climbing) = state.climbing {
+                    // Push off the surface
+                    state.release_climb(time.elapsed_secs_f64());
+                    state.forces.gravity.y += jump_config.force * 0.5;
+                    state.forces.external += climbing.normal * jump_config.force * 0.5;
+                    consume();
+                } else if state.grounded {
+                    state.forces.gravity.y += jump_config.force;
+                    consume();
+                } else if state.swimming && state.submerged < 1.0 {
+                    // Climb out of the water from the surface
                     state.forces.gravity.y += jump_config.force;
                     consume();
                 }
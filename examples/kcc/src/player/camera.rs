@@ -7,9 +7,15 @@ use bevy::{
     render::view::RenderLayers,
     window::{CursorGrabMode, PrimaryWindow},
 };
-use lib::render_layer;
+use lib::{
+    input::{shape_stick, InputBindings},
+    render_layer,
+};
 
-use super::{config::PlayerCameraConfig, input::PlayerYaw, Player, PlayerConfig, Section};
+use super::{
+    config::PlayerCameraConfig, input::PlayerYaw, motion::ride_platforms, Player, PlayerConfig,
+    PlayerMotion, Section,
+};
 
 use super::config::{PlayerCameraMode, PlayerInputConfig};
 
@@ -29,7 +35,11 @@ impl Plugin for PlayerCameraPlugin {
         app.init_resource::<PlayerCameraConfig>();
         app.add_systems(
             Update,
-            (add_required_components, toggle_cursor_lock, mouselook),
+            (
+                add_required_components,
+                toggle_cursor_lock,
+                (turn_with_platform.after(ride_platforms), mouselook).chain(),
+            ),
         );
         app.add_systems(
             Update,
@@ -125,9 +135,12 @@ fn toggle_cursor_lock(
 }
 
 fn mouselook(
+    time: Res<Time>,
     window: Option<Single<&Window, With<PrimaryWindow>>>,
     config: Res<PlayerCameraConfig>,
+    bindings: Res<InputBindings>,
     mouse: Res<AccumulatedMouseMotion>,
+    gamepads: Query<&Gamepad>,
     camera: Option<Single<&mut Transform, With<PlayerCamera>>>,
     mut player_yaw: ResMut<PlayerYaw>,
 ) {
@@ -140,7 +153,23 @@ fn mouselook(
     let Some(mut camera) = camera else {
         return;
     };
-    if mouse.delta.length() == 0.0 {
+
+    // Stick y is up, mouse y is down
+    let settings = &bindings.gamepad;
+    let invert = if settings.invert_look_y { 1.0 } else { -1.0 };
+    let stick = gamepads
+        .iter()
+        .map(|gamepad| {
+            shape_stick(
+                gamepad.right_stick(),
+                settings.deadzone,
+                settings.response_exponent,
+            )
+        })
+        .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+        .unwrap_or_default()
+        * Vec2::new(1.0, invert);
+    if mouse.delta.length() == 0.0 && stick.length() == 0.0 {
         return;
     }
 
@@ -153,7 +182,8 @@ fn mouselook(
         }
     };
 
-    let delta = mouse.delta * window_scale * config.sensitivity * MOUSE_MOTION_SCALE;
+    let delta = mouse.delta * window_scale * config.sensitivity * MOUSE_MOTION_SCALE
+        + stick * settings.look_sensitivity * time.delta_secs();
     let (yaw, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
     let pitch = (pitch - delta.y).clamp(-PITCH_LIMIT, PITCH_LIMIT);
     let yaw = yaw - delta.x;
@@ -162,6 +192,26 @@ fn mouselook(
     camera.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
 }
 
+/// Turns the camera along with the platform the player is riding, which already turned the
+/// player's yaw.
+fn turn_with_platform(
+    player: Option<Single<&PlayerMotion>>,
+    camera: Option<Single<&mut Transform, With<PlayerCamera>>>,
+) {
+    let (Some(player), Some(mut camera)) = (player, camera) else {
+        return;
+    };
+    let Some(platform) = player.platform else {
+        return;
+    };
+    if platform.yaw_delta == 0.0 {
+        return;
+    }
+
+    let (yaw, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+    camera.rotation = Quat::from_euler(EulerRot::YXZ, yaw + platform.yaw_delta, pitch, 0.0);
+}
+
 fn can_switch_mode(config: Res<PlayerCameraConfig>) -> bool {
     config.allowed_modes.len() > 1
 }
@@ -171,12 +221,13 @@ fn switch_camera_mode(
     mut config: ResMut<PlayerCameraConfig>,
     keyboard: Res<ButtonInput<KeyCode>>,
     mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
 ) {
     let Some(ref switch_camera) = input_config.binds.switch_camera else {
         return;
     };
 
-    if switch_camera.just_released(&keyboard, &mouse) {
+    if switch_camera.just_released(&keyboard, &mouse, &gamepads) {
         config.mode = match config.mode {
             PlayerCameraMode::FirstPerson => PlayerCameraMode::ThirdPerson,
             PlayerCameraMode::ThirdPerson => PlayerCameraMode::FirstPerson,
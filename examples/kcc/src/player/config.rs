@@ -24,6 +24,25 @@ pub struct PlayerMotionConfig {
     pub air_accelerate: f32,
     pub max_velocity_ground: f32,
     pub max_velocity_air: f32,
+    pub climb_speed: f32,
+    /// How close the player needs to be to a climbable surface to hold onto it.
+    pub climb_reach: f32,
+    /// How directly the player needs to walk into a climbable surface to mount it, from 0 to 1.
+    pub climb_mount_dot: f32,
+    /// The push up and over the top edge of a climbable surface.
+    pub climb_dismount_force: f32,
+    /// Delay before the player can grab onto a surface again after letting go.
+    pub climb_remount_delay_secs: f64,
+    /// How much of the player needs to be underwater to swim, from 0 to 1.
+    pub swim_depth: f32,
+    /// Swimming stops once the player is this much shallower than `swim_depth`.
+    pub swim_depth_hysteresis: f32,
+    pub swim_friction: f32,
+    pub swim_accelerate: f32,
+    pub max_velocity_swim: f32,
+    pub breath_secs: f32,
+    /// Seconds of breath regained per second above water.
+    pub breath_recovery_speed: f32,
 }
 
 #[derive(Resource)]
@@ -61,7 +80,7 @@ pub struct SlideActionConfig {
     pub min_acceleration_slope_degrees: f32,
 }
 
-#[derive(Resource, Default)]
+#[derive(Resource)]
 pub struct PlayerInputConfig {
     /// Run by default. The run key becomes the walk key.
     pub always_run: bool,
@@ -92,6 +111,7 @@ pub struct PlayerKeybinds {
     pub right: Option<Keybind>,
     pub jump: Option<Keybind>,
     pub crouch: Option<Keybind>,
+    pub slide: Option<Keybind>,
 
     /// Run, unless [PlayerInputConfig.always_run], then it's walk.
     pub walk_mod: Option<Keybind>,
@@ -103,6 +123,10 @@ pub struct PlayerKeybinds {
 pub enum Keybind {
     Keyboard(KeyCode),
     Mouse(MouseButton),
+    /// Any connected gamepad.
+    Gamepad(GamepadButton),
+    /// Any of these, e.g. a key and a gamepad button.
+    Any(Vec<Keybind>),
 }
 
 #[cfg(feature = "camera")]
@@ -159,6 +183,21 @@ impl Default for PlayerMotionConfig {
             air_accelerate: 0.35 * QUAKE_UNITS_PER_METER,
             max_velocity_ground: 160.0 / QUAKE_UNITS_PER_METER,
             max_velocity_air: 160.0 / QUAKE_UNITS_PER_METER,
+
+            climb_speed: 3.0,
+            climb_reach: 0.1,
+            climb_mount_dot: 0.5,
+            climb_dismount_force: 6.0,
+            climb_remount_delay_secs: 0.3,
+
+            // Ratios based on Quake's water movement, which is slower and has more friction.
+            swim_depth: 0.6,
+            swim_depth_hysteresis: 0.1,
+            swim_friction: 4.0,
+            swim_accelerate: 5.0 * QUAKE_UNITS_PER_METER,
+            max_velocity_swim: 0.7 * 160.0 / QUAKE_UNITS_PER_METER,
+            breath_secs: 12.0,
+            breath_recovery_speed: 4.0,
         }
     }
 }
@@ -215,17 +254,28 @@ impl Default for SlideActionConfig {
     }
 }
 
+impl Default for PlayerInputConfig {
+    fn default() -> Self {
+        Self {
+            always_run: false,
+            walk_mod_mode: default(),
+            binds: default(),
+        }
+    }
+}
+
 impl PlayerKeybinds {
     pub fn any_pressed<const N: usize>(
         binds: [&Option<Keybind>; N],
         keyboard: &ButtonInput<KeyCode>,
         mouse: &ButtonInput<MouseButton>,
+        gamepads: &Query<&Gamepad>,
     ) -> bool {
         binds.iter().any(|bind| {
             let Some(bind) = bind else {
                 return false;
             };
-            bind.pressed(keyboard, mouse)
+            bind.pressed(keyboard, mouse, gamepads)
         })
     }
 }
@@ -237,12 +287,28 @@ impl Default for PlayerKeybinds {
             backward: Some(Keybind::Keyboard(KeyCode::KeyS)),
             left: Some(Keybind::Keyboard(KeyCode::KeyA)),
             right: Some(Keybind::Keyboard(KeyCode::KeyD)),
-            walk_mod: Some(Keybind::Keyboard(KeyCode::ShiftLeft)),
-            jump: Some(Keybind::Keyboard(KeyCode::Space)),
-            crouch: Some(Keybind::Keyboard(KeyCode::ControlLeft)),
+            walk_mod: Some(Keybind::Any(vec![
+                Keybind::Keyboard(KeyCode::ShiftLeft),
+                Keybind::Gamepad(GamepadButton::LeftThumb),
+            ])),
+            jump: Some(Keybind::Any(vec![
+                Keybind::Keyboard(KeyCode::Space),
+                Keybind::Gamepad(GamepadButton::South),
+            ])),
+            crouch: Some(Keybind::Any(vec![
+                Keybind::Keyboard(KeyCode::ControlLeft),
+                Keybind::Gamepad(GamepadButton::East),
+            ])),
+            slide: Some(Keybind::Any(vec![
+                Keybind::Keyboard(KeyCode::KeyC),
+                Keybind::Gamepad(GamepadButton::RightThumb),
+            ])),
 
             #[cfg(feature = "camera")]
-            switch_camera: Some(Keybind::Mouse(MouseButton::Middle)),
+            switch_camera: Some(Keybind::Any(vec![
+                Keybind::Mouse(MouseButton::Middle),
+                Keybind::Gamepad(GamepadButton::Select),
+            ])),
         }
     }
 }
@@ -252,10 +318,15 @@ impl Keybind {
         &self,
         keyboard: &ButtonInput<KeyCode>,
         mouse: &ButtonInput<MouseButton>,
+        gamepads: &Query<&Gamepad>,
     ) -> bool {
         match self {
             Keybind::Keyboard(key_code) => keyboard.pressed(*key_code),
             Keybind::Mouse(mouse_button) => mouse.pressed(*mouse_button),
+            Keybind::Gamepad(button) => gamepads.iter().any(|pad| pad.pressed(*button)),
+            Keybind::Any(binds) => binds
+                .iter()
+                .any(|bind| bind.pressed(keyboard, mouse, gamepads)),
         }
     }
 
@@ -263,10 +334,15 @@ impl Keybind {
         &self,
         keyboard: &ButtonInput<KeyCode>,
         mouse: &ButtonInput<MouseButton>,
+        gamepads: &Query<&Gamepad>,
     ) -> bool {
         match self {
             Keybind::Keyboard(key_code) => keyboard.just_pressed(*key_code),
             Keybind::Mouse(mouse_button) => mouse.just_pressed(*mouse_button),
+            Keybind::Gamepad(button) => gamepads.iter().any(|pad| pad.just_pressed(*button)),
+            Keybind::Any(binds) => binds
+                .iter()
+                .any(|bind| bind.just_pressed(keyboard, mouse, gamepads)),
         }
     }
 
@@ -274,10 +350,15 @@ impl Keybind {
         &self,
         keyboard: &ButtonInput<KeyCode>,
         mouse: &ButtonInput<MouseButton>,
+        gamepads: &Query<&Gamepad>,
     ) -> bool {
         match self {
             Keybind::Keyboard(key_code) => keyboard.just_released(*key_code),
             Keybind::Mouse(mouse_button) => mouse.just_released(*mouse_button),
+            Keybind::Gamepad(button) => gamepads.iter().any(|pad| pad.just_released(*button)),
+            Keybind::Any(binds) => binds
+                .iter()
+                .any(|bind| bind.just_released(keyboard, mouse, gamepads)),
         }
     }
 }
@@ -1,5 +1,8 @@
 use bevy::prelude::*;
 
+#[cfg(feature = "input")]
+use lib::input::{shape_stick, InputBindings};
+
 #[cfg(feature = "input")]
 use super::{
     actions::PlayerAction, config::PlayerKeybinds, utility::running, PlayerInputConfig,
@@ -18,6 +21,8 @@ pub struct PlayerInput {
     pub walk_mod: bool,
     pub crouch: bool,
     pub slide: bool,
+    /// Held jump, used to swim up.
+    pub jump: bool,
 }
 
 pub struct PlayerInputPlugin;
@@ -42,8 +47,10 @@ pub fn process_input(
     time: Res<Time>,
     actions_config: Res<PlayerActionsConfig>,
     input_config: Res<PlayerInputConfig>,
+    bindings: Res<InputBindings>,
     keyboard: Res<ButtonInput<KeyCode>>,
     mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
     state: Option<Single<&PlayerMotion>>,
 ) {
     use super::actions::can_stand;
@@ -57,40 +64,58 @@ pub fn process_input(
     input.direction = Vec2::ZERO;
 
     if let Some(forward) = &input_config.binds.forward {
-        if forward.pressed(&keyboard, &mouse) {
+        if forward.pressed(&keyboard, &mouse, &gamepads) {
             input.direction += Vec2::NEG_Y;
         }
     }
     if let Some(backward) = &input_config.binds.backward {
-        if backward.pressed(&keyboard, &mouse) {
+        if backward.pressed(&keyboard, &mouse, &gamepads) {
             input.direction += Vec2::Y;
         }
     }
     if let Some(left) = &input_config.binds.left {
-        if left.pressed(&keyboard, &mouse) {
+        if left.pressed(&keyboard, &mouse, &gamepads) {
             input.direction += Vec2::NEG_X;
         }
     }
     if let Some(right) = &input_config.binds.right {
-        if right.pressed(&keyboard, &mouse) {
+        if right.pressed(&keyboard, &mouse, &gamepads) {
             input.direction += Vec2::X;
         }
     }
 
-    if input.direction.length() > 0.0 {
-        input.direction = input.direction.normalize();
+    // Stick y points up
+    if let Some(stick) = gamepads
+        .iter()
+        .map(|pad| {
+            shape_stick(
+                pad.left_stick(),
+                bindings.gamepad.deadzone,
+                bindings.gamepad.response_exponent,
+            )
+        })
+        .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+    {
+        input.direction += stick * Vec2::new(1.0, -1.0);
     }
 
+    // Keys move at full speed, sticks can move slower
+    input.direction = input.direction.clamp_length_max(1.0);
+
     if let (Some(jump_bind), Some(jump_config)) = (&input_config.binds.jump, &actions_config.jump) {
-        if let Some(ground_distance) = state.ground_distance {
-            if jump_bind.just_pressed(&keyboard, &mouse)
-                && ground_distance <= jump_config.buffer_distance
-            {
-                if jump_config.bufferable {
-                    actions.buffer(PlayerAction::Jump, now);
-                } else {
-                    actions.instant(PlayerAction::Jump);
-                }
+        input.jump = jump_bind.pressed(&keyboard, &mouse, &gamepads);
+
+        // Climbing and swimming players can jump from any height
+        let near_ground = state
+            .ground_distance
+            .is_some_and(|ground_distance| ground_distance <= jump_config.buffer_distance);
+        if jump_bind.just_pressed(&keyboard, &mouse, &gamepads)
+            && (near_ground || state.climbing.is_some() || state.swimming)
+        {
+            if jump_config.bufferable {
+                actions.buffer(PlayerAction::Jump, now);
+            } else {
+                actions.instant(PlayerAction::Jump);
             }
         }
     };
@@ -98,7 +123,7 @@ pub fn process_input(
     if let (Some(crouch_bind), Some(crouch_config)) =
         (&input_config.binds.crouch, &actions_config.crouch)
     {
-        if crouch_bind.pressed(&keyboard, &mouse) {
+        if crouch_bind.pressed(&keyboard, &mouse, &gamepads) {
             if !input.crouch {
                 if crouch_config.slide_if_running && !input.crouch && running(&input, &input_config)
                 {
@@ -112,13 +137,20 @@ pub fn process_input(
         }
     }
 
+    if let (Some(slide_bind), Some(_)) = (&input_config.binds.slide, &actions_config.slide) {
+        if slide_bind.just_pressed(&keyboard, &mouse, &gamepads) && !input.crouch {
+            actions.instant(PlayerAction::Slide);
+            actions.instant(PlayerAction::Crouch(true));
+        }
+    }
+
     if let Some(walk_mod) = &input_config.binds.walk_mod {
         match input_config.walk_mod_mode {
             PlayerWalkModMode::Hold => {
-                input.walk_mod = walk_mod.pressed(&keyboard, &mouse);
+                input.walk_mod = walk_mod.pressed(&keyboard, &mouse, &gamepads);
             }
             PlayerWalkModMode::Toggle => {
-                if walk_mod.just_pressed(&keyboard, &mouse) {
+                if walk_mod.just_pressed(&keyboard, &mouse, &gamepads) {
                     input.walk_mod = !input.walk_mod;
                 }
             }
@@ -132,23 +164,24 @@ pub fn process_input(
                     ],
                     &keyboard,
                     &mouse,
+                    &gamepads,
                 );
 
                 match input_config.walk_mod_mode {
                     PlayerWalkModMode::ToggleHybrid => {
-                        input.walk_mod = if walk_mod.just_pressed(&keyboard, &mouse) {
+                        input.walk_mod = if walk_mod.just_pressed(&keyboard, &mouse, &gamepads) {
                             !input.walk_mod
                         } else if input.walk_mod {
                             moving
                         } else {
-                            walk_mod.just_pressed(&keyboard, &mouse)
+                            walk_mod.just_pressed(&keyboard, &mouse, &gamepads)
                         };
                     }
                     PlayerWalkModMode::Hybrid => {
                         input.walk_mod = if input.walk_mod {
                             moving
                         } else {
-                            walk_mod.just_pressed(&keyboard, &mouse)
+                            walk_mod.just_pressed(&keyboard, &mouse, &gamepads)
                         };
                     }
                     _ => unreachable!(),
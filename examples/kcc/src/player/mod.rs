@@ -1,5 +1,6 @@
 use avian3d::prelude::{LockedAxes, RigidBody};
 use bevy::{pbr::NotShadowCaster, prelude::*};
+use lib::input::InputBindingsPlugin;
 
 mod config;
 pub use config::{PlayerConfig, PlayerInputConfig, PlayerWalkModMode};
@@ -18,8 +19,8 @@ mod actions;
 use actions::PlayerActionsPlugin;
 
 mod motion;
-pub use motion::PlayerMotion;
 use motion::PlayerMotionPlugin;
+pub use motion::{Climbable, Climbing, Platform, PlayerBreath, PlayerMotion};
 
 mod quakeish;
 
@@ -34,6 +35,9 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PlayerConfig>();
+        if !app.is_plugin_added::<InputBindingsPlugin>() {
+            app.add_plugins(InputBindingsPlugin);
+        }
         app.add_plugins((
             PlayerMotionPlugin,
             PlayerInputPlugin,
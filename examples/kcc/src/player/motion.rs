@@ -8,12 +8,16 @@
 
 use avian3d::prelude::*;
 use bevy::prelude::*;
+use lib::worldgen::{
+    fluid::Fluids,
+    layout::{GravityZones, STANDARD_GRAVITY},
+};
 
 use super::{
     actions,
     config::{PlayerActionsConfig, PlayerMotionConfig},
     input::{self, PlayerInput, PlayerYaw},
-    quakeish::{air_move, ground_move},
+    quakeish::{air_move, ground_move, water_move},
     utility::{running, wish_dir},
     PlayerInputConfig, Section,
 };
@@ -30,14 +34,61 @@ impl PlayerForces {
     }
 }
 
+/// Seconds of air the player has left. It runs out while their head is underwater.
+#[derive(Resource)]
+pub struct PlayerBreath {
+    pub remaining_secs: f32,
+}
+impl PlayerBreath {
+    pub fn drowning(&self) -> bool {
+        self.remaining_secs <= 0.0
+    }
+}
+
+/// Surfaces the player can climb, like ladders and vines. Only surfaces too steep to walk on can
+/// be climbed.
+#[derive(Component, Default)]
+pub struct Climbable;
+
+#[derive(Clone, Copy)]
+pub struct Climbing {
+    pub surface: Entity,
+    pub normal: Vec3,
+}
+
+/// Whatever the player is standing on or climbing, which carries them along as it moves.
+#[derive(Clone, Copy)]
+pub struct Platform {
+    pub entity: Entity,
+    /// Where the platform was last tick.
+    pub transform: GlobalTransform,
+    pub velocity: Vec3,
+    /// How far the platform turned the player this tick, in radians.
+    pub yaw_delta: f32,
+}
+
 #[derive(Component, Default)]
 pub struct PlayerMotion {
     pub grounded: bool,
     pub ground_normal: Option<Vec3>,
     pub ground_distance: Option<f32>,
+    pub ground_entity: Option<Entity>,
+    pub platform: Option<Platform>,
     pub landed_time: f64,
     pub no_gravity_this_frame: bool,
     pub forces: PlayerForces,
+    pub climbing: Option<Climbing>,
+    pub climb_released_time: f64,
+    /// How much of the player is underwater, from 0 to 1.
+    pub submerged: f32,
+    pub swimming: bool,
+}
+impl PlayerMotion {
+    pub fn release_climb(&mut self, now: f64) {
+        if self.climbing.take().is_some() {
+            self.climb_released_time = now;
+        }
+    }
 }
 
 pub struct PlayerMotionPlugin;
@@ -45,11 +96,12 @@ pub struct PlayerMotionPlugin;
 impl Plugin for PlayerMotionPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PlayerMotionConfig>();
+        app.add_systems(Startup, setup_breath);
 
         #[cfg(feature = "input")]
         app.add_systems(
             Update,
-            (snap_to_ground, motion)
+            (swim, snap_to_ground, ride_platforms, climb, motion)
                 .after(input::process_input)
                 .after(actions::perform_actions)
                 .chain(),
@@ -57,13 +109,57 @@ impl Plugin for PlayerMotionPlugin {
         #[cfg(not(feature = "input"))]
         app.add_systems(
             Update,
-            (snap_to_ground, motion)
+            (swim, snap_to_ground, ride_platforms, climb, motion)
                 .after(actions::perform_actions)
                 .chain(),
         );
     }
 }
 
+fn setup_breath(mut commands: Commands, motion_config: Res<PlayerMotionConfig>) {
+    commands.insert_resource(PlayerBreath {
+        remaining_secs: motion_config.breath_secs,
+    });
+}
+
+fn swim(
+    time: Res<Time>,
+    fluids: Fluids,
+    motion_config: Res<PlayerMotionConfig>,
+    mut breath: ResMut<PlayerBreath>,
+    player: Option<Single<(&Transform, &Section, &mut PlayerMotion)>>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+
+    let (transform, section, mut state) = player.into_inner();
+    let bottom = section.center(transform.translation) - Vec3::Y * section.height / 2.0;
+    state.submerged = fluids.surface_at(bottom).map_or(0.0, |surface| {
+        ((surface - bottom.y) / section.height).clamp(0.0, 1.0)
+    });
+
+    // Deeper to start swimming than to stop, so the player doesn't flicker between the two at
+    // the surface
+    let was_swimming = state.swimming;
+    state.swimming = if was_swimming {
+        state.submerged >= motion_config.swim_depth - motion_config.swim_depth_hysteresis
+    } else {
+        state.submerged >= motion_config.swim_depth
+    };
+    if state.swimming && !was_swimming {
+        state.release_climb(time.elapsed_secs_f64());
+    }
+
+    let delta = time.delta_secs();
+    breath.remaining_secs = if state.submerged >= 1.0 {
+        (breath.remaining_secs - delta).max(0.0)
+    } else {
+        (breath.remaining_secs + delta * motion_config.breath_recovery_speed)
+            .min(motion_config.breath_secs)
+    };
+}
+
 fn snap_to_ground(
     time: Res<Time>,
     spatial_query: SpatialQuery,
@@ -95,6 +191,7 @@ fn snap_to_ground(
     let Some(hit) = shapecast else {
         state.grounded = false;
         state.ground_distance = None;
+        state.ground_entity = None;
         return;
     };
 
@@ -102,6 +199,7 @@ fn snap_to_ground(
     state.grounded = angle < motion_config.max_slope_degrees.to_radians();
     state.ground_normal = Some(hit.normal1);
     state.ground_distance = Some(hit.distance);
+    state.ground_entity = Some(hit.entity);
 
     if hit.distance > motion_config.snap_to_ground_distance {
         state.grounded = false;
@@ -110,7 +208,8 @@ fn snap_to_ground(
 
     let prev_grounded = state.grounded;
 
-    if !state.grounded {
+    // Climbing or swimming up from the ground would be undone by snapping back down
+    if !state.grounded || state.climbing.is_some() || state.swimming {
         return;
     }
 
@@ -125,6 +224,165 @@ fn snap_to_ground(
     }
 }
 
+/// Moves and turns the player along with the ground or surface they're on, so moving geometry
+/// doesn't slide out from under them.
+pub fn ride_platforms(
+    time: Res<Time>,
+    mut yaw: ResMut<PlayerYaw>,
+    player: Option<Single<(&mut Transform, &mut PlayerMotion)>>,
+    transforms: Query<&GlobalTransform>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+
+    let (mut transform, mut state) = player.into_inner();
+    let carrier = state
+        .climbing
+        .map(|climbing| climbing.surface)
+        .or(state.ground_entity.filter(|_| state.grounded))
+        .and_then(|entity| Some((entity, *transforms.get(entity).ok()?)));
+
+    let Some((entity, platform_transform)) = carrier else {
+        // Stepping or jumping off keeps the platform's momentum
+        if let Some(platform) = state.platform.take() {
+            state.forces.external += platform.velocity;
+        }
+        return;
+    };
+    let Some(previous) = state.platform.filter(|platform| platform.entity == entity) else {
+        state.platform = Some(Platform {
+            entity,
+            transform: platform_transform,
+            velocity: Vec3::ZERO,
+            yaw_delta: 0.0,
+        });
+        return;
+    };
+
+    let local = previous
+        .transform
+        .affine()
+        .inverse()
+        .transform_point3(transform.translation);
+    let carried = platform_transform.transform_point(local);
+    let delta = carried - transform.translation;
+    transform.translation = carried;
+
+    let turned = platform_transform.rotation() * previous.transform.rotation().inverse();
+    let (yaw_delta, _, _) = turned.to_euler(EulerRot::YXZ);
+    yaw.0 += yaw_delta;
+
+    let delta_secs = time.delta_secs();
+    state.platform = Some(Platform {
+        entity,
+        transform: platform_transform,
+        velocity: if delta_secs > 0.0 {
+            delta / delta_secs
+        } else {
+            previous.velocity
+        },
+        yaw_delta,
+    });
+}
+
+fn climb(
+    time: Res<Time>,
+    spatial_query: SpatialQuery,
+    input: Res<PlayerInput>,
+    yaw: Res<PlayerYaw>,
+    motion_config: Res<PlayerMotionConfig>,
+    player: Option<Single<(Entity, &Transform, &Section, &mut PlayerMotion)>>,
+    climbables: Query<(), With<Climbable>>,
+    sensors: Query<Entity, With<Sensor>>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+
+    let (entity, transform, section, mut state) = player.into_inner();
+    let now = time.elapsed_secs_f64();
+    let mut filter_entities: Vec<Entity> = sensors.iter().collect();
+    filter_entities.push(entity);
+    let filter = SpatialQueryFilter::from_excluded_entities(filter_entities);
+
+    let reach = |direction: Dir3| {
+        spatial_query
+            .cast_shape(
+                &section.collider_centered(),
+                section.center(transform.translation),
+                default(),
+                direction,
+                &ShapeCastConfig::from_max_distance(motion_config.climb_reach),
+                &filter,
+            )
+            .filter(|hit| climbables.contains(hit.entity))
+    };
+
+    let Some(climbing) = state.climbing else {
+        // Mount by walking into a climbable surface
+        if input.direction == Vec2::ZERO
+            || now - state.climb_released_time < motion_config.climb_remount_delay_secs
+        {
+            return;
+        }
+        let wish_dir = wish_dir(&yaw, &input);
+        let Some(hit) = reach(wish_dir) else {
+            return;
+        };
+        let walkable =
+            hit.normal1.angle_between(Vec3::Y) < motion_config.max_slope_degrees.to_radians();
+        if walkable || wish_dir.dot(-hit.normal1) < motion_config.climb_mount_dot {
+            return;
+        }
+
+        state.climbing = Some(Climbing {
+            surface: hit.entity,
+            normal: hit.normal1,
+        });
+        state.forces.gravity = Vec3::ZERO;
+        state.forces.movement = Vec3::ZERO;
+        return;
+    };
+
+    // Step off at the bottom
+    if state.grounded && input.direction.y > 0.0 {
+        state.release_climb(now);
+        return;
+    }
+
+    let Ok(toward) = Dir3::new(-climbing.normal) else {
+        state.release_climb(now);
+        return;
+    };
+    let Some(hit) = reach(toward) else {
+        state.release_climb(now);
+        return;
+    };
+
+    // Once the feet are past the top edge, climb over it onto whatever is above
+    let feet = transform.translation + Vec3::Y * section.radius;
+    let feet_on_surface = spatial_query
+        .cast_ray(
+            feet,
+            toward,
+            section.radius + motion_config.climb_reach,
+            true,
+            &filter,
+        )
+        .is_some_and(|hit| climbables.contains(hit.entity));
+    if !feet_on_surface && input.direction.y < 0.0 {
+        state.forces.external += (Vec3::Y - climbing.normal) * motion_config.climb_dismount_force;
+        state.release_climb(now);
+        return;
+    }
+
+    state.climbing = Some(Climbing {
+        surface: hit.entity,
+        normal: hit.normal1,
+    });
+}
+
 fn motion(
     mut commands: Commands,
     centers: Query<(&Position, &ComputedCenterOfMass)>,
@@ -136,12 +394,15 @@ fn motion(
     player: Option<Single<(Entity, &mut Transform, &Section, &mut PlayerMotion)>>,
     sensors: Query<Entity, With<Sensor>>,
     yaw: Res<PlayerYaw>,
+    gravity_zones: GravityZones,
 ) {
     let Some(player) = player else {
         return;
     };
 
     let (entity, mut transform, section, mut state) = player.into_inner();
+    // The player falls faster than standard gravity, so zones are scaled to match
+    let gravity_direction = gravity_zones.gravity_at(transform.translation) / STANDARD_GRAVITY;
     let mut filter_entities: Vec<Entity> = sensors.iter().collect();
     filter_entities.push(entity);
     let filter = SpatialQueryFilter::from_excluded_entities(filter_entities);
@@ -175,14 +436,37 @@ fn motion(
             false => 1.0,
             true => motion_config.run_speed_mod,
         };
-
-        if state.grounded {
+        // Sticks can be pushed part of the way
+        let analog = input.direction.length().min(1.0);
+
+        if let Some(climbing) = state.climbing {
+            // Forward climbs up and strafing climbs sideways, along the surface
+            let up = Vec3::Y.reject_from(climbing.normal).normalize_or(Vec3::Y);
+            let right = up.cross(climbing.normal);
+            let wish = up * -input.direction.y + right * input.direction.x;
+            state.forces.movement = wish.normalize_or_zero() * motion_config.climb_speed;
+        } else if state.swimming {
+            // Jump swims up and crouch swims down
+            let vertical = match (input.jump, input.crouch) {
+                (true, false) => 1.0,
+                (false, true) => -1.0,
+                _ => 0.0,
+            };
+            let wish = (wish_dir * analog + Vec3::Y * vertical).normalize_or_zero();
+            water_move(
+                wish,
+                &mut state.forces.movement,
+                &time,
+                speed_mod,
+                &motion_config,
+            );
+        } else if state.grounded {
             ground_move(
                 wish_dir,
                 state.landed_time,
                 &mut state.forces.movement,
                 &time,
-                speed_mod,
+                speed_mod * analog,
                 &motion_config,
             );
         } else {
@@ -190,7 +474,7 @@ fn motion(
                 wish_dir,
                 &mut state.forces.movement,
                 &time,
-                speed_mod,
+                speed_mod * analog,
                 &motion_config,
             );
         }
@@ -203,7 +487,18 @@ fn motion(
             state.no_gravity_this_frame = false;
             break 'gravity;
         }
-        let mut gravity = Vec3::NEG_Y * motion_config.gravity * time.delta_secs();
+        if state.climbing.is_some() {
+            state.forces.gravity = Vec3::ZERO;
+            break 'gravity;
+        }
+        // Neutrally buoyant, so falling into water slows to a stop instead of sinking
+        if state.swimming {
+            let drag = 1.0 + motion_config.swim_friction * time.delta_secs();
+            state.forces.gravity /= drag;
+            collide_and_slide(&mut state.forces.gravity);
+            break 'gravity;
+        }
+        let mut gravity = gravity_direction * motion_config.gravity * time.delta_secs();
         if state.grounded && !input.slide {
             gravity *= 0.01;
         }
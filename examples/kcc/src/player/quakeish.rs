@@ -61,3 +61,29 @@ pub fn air_move(
         time,
     );
 }
+
+pub fn water_move(
+    wish: Vec3,
+    curr_velocity: &mut Vec3,
+    time: &Res<Time>,
+    speed_mod: f32,
+    motion_config: &Res<PlayerMotionConfig>,
+) {
+    let speed = curr_velocity.length();
+
+    if speed != 0.0 {
+        let drop = speed * motion_config.swim_friction * time.delta_secs();
+        *curr_velocity *= f32::max(speed - drop, 0.0) / speed;
+    }
+
+    let Ok(direction) = Dir3::new(wish) else {
+        return;
+    };
+    *curr_velocity = accelerate(
+        direction,
+        *curr_velocity,
+        motion_config.swim_accelerate * speed_mod,
+        motion_config.max_velocity_swim * speed_mod,
+        time,
+    );
+}
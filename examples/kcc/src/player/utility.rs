@@ -178,7 +178,8 @@ pub fn wish_dir(yaw: &PlayerYaw, input: &PlayerInput) -> Dir3 {
     let mut wishdir = Vec3::new(input.direction.x, 0.0, input.direction.y);
     wishdir = Quat::from_euler(EulerRot::YXZ, yaw.0, 0.0, 0.0).mul_vec3(wishdir);
 
-    Dir3::new_unchecked(wishdir)
+    // Zero while there's no input
+    Dir3::new_unchecked(wishdir.normalize_or_zero())
 }
 
 pub fn running(input: &PlayerInput, input_config: &PlayerInputConfig) -> bool {
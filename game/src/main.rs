@@ -1,60 +1,114 @@
+use std::{env, path::PathBuf};
+
 use avian3d::prelude::*;
-use bevy::{ecs::world::CommandQueue, prelude::*, window::PresentMode};
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    ecs::world::CommandQueue,
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::{ExitCondition, PresentMode},
+    winit::WinitPlugin,
+};
 use bevy_egui::EguiPlugin;
 use bevy_rand::{plugin::EntropyPlugin, prelude::WyRand};
-use noisy_bevy::NoisyShaderPlugin;
 
-use lib::{
-    debug_aim::DebugAimPlugin,
-    materials::{CaveMaterial, LineMaterialPlugin},
-    player::{PlayerPlugin, SpawnPlayerCommand},
-    worldgen::{
-        layout::{self, InitLayoutCommand, LayoutPlugin},
-        terrain::TerrainPlugin,
-    },
-};
+use lib::{prelude::*, worldgen::layout};
 
 fn main() {
+    // Usage: game [--replay <path>] [--view <path>] [--load <path>] [--seed <number>]
+    //             [--challenge]
+    let args = env::args().collect::<Vec<_>>();
+    let arg = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+            .map(PathBuf::from)
+    };
+    let replay =
+        arg("--replay").map(|path| ReplayBundle::read(&path).expect("failed to read replay"));
+    let view = arg("--view").map(|path| ReplayBundle::read(&path).expect("failed to read replay"));
+    let load = arg("--load");
+    let save_seed = load
+        .as_ref()
+        .map(|path| SaveGame::read(path).expect("failed to read save"))
+        .and_then(|save| save.seed);
+    let arg_seed = arg("--seed").map(|seed| {
+        seed.to_str()
+            .and_then(|seed| seed.parse::<u64>().ok())
+            .expect("seed must be a number")
+    });
+    // Saves and replays must be resumed with the seed they were generated with
+    let seed = save_seed
+        .or_else(|| replay.as_ref().and_then(|replay| replay.seed))
+        .or_else(|| view.as_ref().and_then(|view| view.seed))
+        .or(arg_seed)
+        .unwrap_or_else(rand::random);
+
     let mut app = App::new();
-    app.add_plugins(
-        DefaultPlugins
-            .set(WindowPlugin {
-                primary_window: Some(Window {
-                    present_mode: PresentMode::AutoNoVsync,
-                    title: "Caves Forever".to_string(),
+    let default_plugins = DefaultPlugins.set(AssetPlugin {
+        file_path: "../assets".to_owned(),
+        ..default()
+    });
+
+    if replay.is_some() {
+        app.add_plugins((
+            default_plugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
                     ..default()
-                }),
-                ..default()
-            })
-            .set(AssetPlugin {
-                file_path: "../assets".to_owned(),
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::default(),
+        ));
+    } else {
+        app.add_plugins(default_plugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                present_mode: PresentMode::AutoNoVsync,
+                title: "Caves Forever".to_string(),
                 ..default()
             }),
-    );
+            ..default()
+        }));
+    }
 
     app.add_plugins((
         EguiPlugin,
         PhysicsPlugins::default(),
-        LineMaterialPlugin,
-        NoisyShaderPlugin,
-        EntropyPlugin::<WyRand>::default(),
+        EntropyPlugin::<WyRand>::with_seed(seed.to_le_bytes()),
     ));
+    app.insert_resource(WorldSeed(seed));
 
-    app.add_plugins((
-        LayoutPlugin,
-        TerrainPlugin,
-        MaterialPlugin::<CaveMaterial>::default(),
-        PlayerPlugin,
-        // debug
-        DebugAimPlugin,
-    ));
+    let challenge = args.iter().any(|arg| arg == "--challenge");
+    app.add_plugins(CavesForeverPlugins::default().challenge(challenge));
+
+    // The viewer follows a ghost instead of the player. Replays don't save, or restore chunks
+    // modified in earlier games, since they only play back what was recorded.
+    let spawn_player = view.is_none();
+    match (replay, view) {
+        (Some(replay), _) => app.add_plugins(ReplayPlugin(replay)),
+        (None, Some(view)) => app.add_plugins(ReplayViewerPlugin(view)),
+        (None, None) => app.add_plugins((ReplayRecorderPlugin, SaveGamePlugin)),
+    };
 
-    app.add_systems(Startup, setup.after(layout::setup_state));
+    app.add_systems(
+        Startup,
+        (move |commands: Commands| setup(commands, load.clone(), spawn_player))
+            .after(layout::setup_state),
+    );
 
     app.run();
 }
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, load: Option<PathBuf>, spawn_player: bool) {
     commands.insert_resource(AmbientLight {
         color: Color::srgb(1.0, 1.0, 1.0).into(),
         brightness: 35.0,
@@ -63,8 +117,11 @@ fn setup(mut commands: Commands) {
     commands.queue(InitLayoutCommand {
         after: {
             let mut queue = CommandQueue::default();
-            queue.push(SpawnPlayerCommand::default());
+            if spawn_player {
+                queue.push(SpawnPlayerCommand::default());
+            }
             queue
         },
+        from_save: load,
     });
 }
@@ -0,0 +1,189 @@
+//! Shared control over how playing sounds are heard, so the systems that fade, muffle, and
+//! reverberate them don't overwrite each other.
+
+use std::time::Duration;
+
+use bevy::{
+    audio::{AddAudioSource, AudioSinkPlayback, Decodable, Source, SpatialAudioSink},
+    prelude::*,
+    utils::HashMap,
+};
+
+/// Delays of the reverb's comb filters, in seconds. They don't share factors, so their echoes
+/// don't line up.
+const COMB_DELAYS: [f32; 4] = [0.0297, 0.0371, 0.0411, 0.0437];
+
+/// Factors multiplied into a sound's volume by channel, e.g. one for fading it out and another
+/// for muffling it underwater. The sink's volume is set from their product.
+#[derive(Component, Default)]
+pub struct VolumeFactors {
+    /// Volume before the factors are applied. Defaults to the volume the sound was played with.
+    pub base: Option<f32>,
+    factors: HashMap<&'static str, f32>,
+}
+
+impl VolumeFactors {
+    pub fn new(channel: &'static str, factor: f32) -> Self {
+        let mut factors = Self::default();
+        factors.set(channel, factor);
+        factors
+    }
+
+    pub fn with_base(mut self, base: f32) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    pub fn get(&self, channel: &'static str) -> f32 {
+        self.factors.get(channel).copied().unwrap_or(1.0)
+    }
+
+    pub fn set(&mut self, channel: &'static str, factor: f32) {
+        self.factors.insert(channel, factor);
+    }
+
+    fn volume(&self, settings: &PlaybackSettings, global: &GlobalVolume) -> f32 {
+        let base = self.base.unwrap_or(settings.volume.get());
+        base * global.volume.get() * self.factors.values().product::<f32>()
+    }
+}
+
+/// Sound played with reverb. The reverb is fixed once the sound starts playing.
+#[derive(Asset, TypePath, Clone)]
+pub struct ReverbAudio {
+    pub source: AudioSource,
+    /// Seconds for the reverb to die down.
+    pub decay_secs: f32,
+    /// Share of the reverberated signal in the mix, from 0 to 1.
+    pub wet: f32,
+}
+
+impl Decodable for ReverbAudio {
+    type DecoderItem = i16;
+    type Decoder = ReverbDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        ReverbDecoder::new(self.source.decoder(), self.decay_secs, self.wet)
+    }
+}
+
+struct CombFilter {
+    /// Interleaved samples, so each channel is delayed by the same number of frames.
+    buffer: Vec<f32>,
+    position: usize,
+    feedback: f32,
+}
+
+/// Mixes a sound with a few feedback comb filters, and keeps playing until the reverb dies down
+/// after the sound ends.
+pub struct ReverbDecoder {
+    inner: <AudioSource as Decodable>::Decoder,
+    channels: u16,
+    sample_rate: u32,
+    wet: f32,
+    combs: Vec<CombFilter>,
+    tail_samples: usize,
+}
+
+impl ReverbDecoder {
+    fn new(inner: <AudioSource as Decodable>::Decoder, decay_secs: f32, wet: f32) -> Self {
+        let channels = inner.channels();
+        let sample_rate = inner.sample_rate();
+        let decay_secs = decay_secs.max(f32::EPSILON);
+
+        let combs = COMB_DELAYS
+            .iter()
+            .map(|delay| {
+                let frames = ((delay * sample_rate as f32) as usize).max(1);
+                CombFilter {
+                    buffer: vec![0.0; frames * channels as usize],
+                    position: 0,
+                    // Dies down by 60 dB over the decay
+                    feedback: 10.0_f32.powf(-3.0 * delay / decay_secs),
+                }
+            })
+            .collect();
+
+        Self {
+            inner,
+            channels,
+            sample_rate,
+            wet: wet.clamp(0.0, 1.0),
+            combs,
+            tail_samples: (decay_secs * sample_rate as f32) as usize * channels as usize,
+        }
+    }
+}
+
+impl Iterator for ReverbDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let dry = match self.inner.next() {
+            Some(sample) => sample as f32 / i16::MAX as f32,
+            None if self.tail_samples > 0 => {
+                self.tail_samples -= 1;
+                0.0
+            }
+            None => return None,
+        };
+
+        let mut wet = 0.0;
+        for comb in self.combs.iter_mut() {
+            let delayed = comb.buffer[comb.position];
+            comb.buffer[comb.position] = dry + delayed * comb.feedback;
+            comb.position = (comb.position + 1) % comb.buffer.len();
+            wet += delayed;
+        }
+        wet /= self.combs.len() as f32;
+
+        let mixed = dry * (1.0 - self.wet) + wet * self.wet;
+        Some((mixed.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+    }
+}
+
+impl Source for ReverbDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+pub struct AudioEffectsPlugin;
+
+impl Plugin for AudioEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<ReverbAudio>();
+        app.add_systems(PostUpdate, apply_volume_factors);
+    }
+}
+
+fn apply_volume_factors(
+    global_volume: Res<GlobalVolume>,
+    sinks: Query<
+        (&VolumeFactors, &PlaybackSettings, &AudioSink),
+        Or<(Changed<VolumeFactors>, Added<AudioSink>)>,
+    >,
+    spatial_sinks: Query<
+        (&VolumeFactors, &PlaybackSettings, &SpatialAudioSink),
+        Or<(Changed<VolumeFactors>, Added<SpatialAudioSink>)>,
+    >,
+) {
+    for (factors, settings, sink) in sinks.iter() {
+        sink.set_volume(factors.volume(settings, &global_volume));
+    }
+    for (factors, settings, sink) in spatial_sinks.iter() {
+        sink.set_volume(factors.volume(settings, &global_volume));
+    }
+}
@@ -0,0 +1,380 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    ecs::system::SystemParam,
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{
+        camera::{ScalingMode, Viewport},
+        primitives::Aabb,
+        view::RenderLayers,
+    },
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
+};
+
+use crate::{
+    input::{ActionInput, InputAction, InputBindingsPlugin},
+    player::IsPlayer,
+    render_layer,
+    worldgen::{
+        consts::CHUNK_SIZE_F,
+        terrain::{
+            Chunk, ChunkComposition, ChunkDespawned, ChunkMeshed, UpdateChunks, VoxelMaterials,
+        },
+        voxel::VoxelMaterial,
+    },
+};
+
+/// Chunks within this many chunks of the player's, along each axis, are visited.
+const REVEAL_RADIUS: i32 = 1;
+/// Size of the map relative to the height of the window.
+const MAP_SIZE: f32 = 0.4;
+/// Distance from the corner of the window, in physical pixels.
+const MAP_MARGIN: u32 = 16;
+const MAP_BACKGROUND: Color = Color::srgb(0.02, 0.04, 0.05);
+const TILE_ALPHA: f32 = 0.35;
+/// Color of tiles without solid terrain. Other tiles take the particle color of their most common
+/// material.
+const TILE_COLOR: Color = Color::srgba(0.3, 0.9, 1.0, TILE_ALPHA);
+const MARKER_COLOR: Color = Color::srgb(1.0, 0.6, 0.2);
+const MARKER_RADIUS: f32 = 1.5;
+/// Only terrain this far above or below the player is drawn in the slice view.
+const SLICE_HALF_HEIGHT: f32 = 3.0;
+/// Width and height of the area shown in the slice view, in meters.
+const SLICE_EXTENT: f32 = 96.0;
+const HOLOGRAM_DISTANCE: f32 = 80.0;
+/// Angle below the horizon the hologram is viewed from, in degrees.
+const HOLOGRAM_PITCH: f32 = 40.0;
+/// Radians per second.
+const HOLOGRAM_SPIN_SPEED: f32 = 0.25;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AutomapView {
+    /// Top-down view of the terrain around the player's height.
+    #[default]
+    Slice,
+    /// Every visited chunk, slowly spinning around the player.
+    Hologram,
+}
+
+/// Chunks the player has been close to. Each visited chunk is copied onto the map layer, where
+/// it stays after the chunk unloads.
+#[derive(Resource, Default)]
+pub struct Automap {
+    pub visible: bool,
+    pub view: AutomapView,
+    visited: HashSet<IVec3>,
+    /// Loaded chunk entities, by position.
+    loaded: HashMap<IVec3, Entity>,
+    /// Copies of visited chunks on the map layer, by position.
+    tiles: HashMap<IVec3, Entity>,
+}
+
+impl Automap {
+    pub fn is_visited(&self, chunk_pos: IVec3) -> bool {
+        self.visited.contains(&chunk_pos)
+    }
+
+    pub fn visited(&self) -> impl Iterator<Item = &IVec3> {
+        self.visited.iter()
+    }
+}
+
+#[derive(Component)]
+pub struct AutomapCamera;
+
+#[derive(Component)]
+struct AutomapTile;
+
+#[derive(Component)]
+struct AutomapMarker;
+
+/// What a tile copies from its chunk.
+type TileSourceQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Mesh3d,
+        &'static Transform,
+        &'static Aabb,
+        Option<&'static ChunkComposition>,
+    ),
+    With<Chunk>,
+>;
+
+/// Tile materials, by the most common material of the tile's chunk.
+#[derive(Resource, Default)]
+struct AutomapTileMaterials(HashMap<Option<VoxelMaterial>, Handle<StandardMaterial>>);
+
+/// Creates tile materials as they're needed.
+#[derive(SystemParam)]
+struct TileMaterials<'w> {
+    handles: ResMut<'w, AutomapTileMaterials>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    voxel_materials: Res<'w, VoxelMaterials>,
+}
+
+impl TileMaterials<'_> {
+    fn get(&mut self, dominant: Option<VoxelMaterial>) -> Handle<StandardMaterial> {
+        let Self {
+            handles,
+            materials,
+            voxel_materials,
+        } = self;
+        handles
+            .0
+            .entry(dominant)
+            .or_insert_with(|| {
+                let color = match dominant {
+                    Some(material) => {
+                        let [r, g, b] = voxel_materials.get(material).particle_color;
+                        Color::srgba(r, g, b, TILE_ALPHA)
+                    }
+                    None => TILE_COLOR,
+                };
+                materials.add(StandardMaterial {
+                    base_color: color,
+                    emissive: color.to_linear(),
+                    alpha_mode: AlphaMode::Add,
+                    unlit: true,
+                    double_sided: true,
+                    cull_mode: None,
+                    ..default()
+                })
+            })
+            .clone()
+    }
+}
+
+pub struct AutomapPlugin;
+
+impl Plugin for AutomapPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<InputBindingsPlugin>() {
+            app.add_plugins(InputBindingsPlugin);
+        }
+        app.init_resource::<Automap>();
+        app.init_resource::<AutomapTileMaterials>();
+        app.add_systems(Startup, setup);
+        app.add_systems(
+            Update,
+            (
+                toggle,
+                track_chunks.after(UpdateChunks),
+                reveal_chunks,
+                (move_marker, move_camera),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        AutomapCamera,
+        Camera3d::default(),
+        Camera {
+            // Above the world and view model cameras
+            order: 2,
+            is_active: false,
+            clear_color: ClearColorConfig::Custom(MAP_BACKGROUND),
+            ..default()
+        },
+        Transform::default(),
+        RenderLayers::layer(render_layer::MAP),
+    ));
+
+    commands.spawn((
+        AutomapMarker,
+        Mesh3d(meshes.add(Sphere::new(MARKER_RADIUS))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: MARKER_COLOR,
+            unlit: true,
+            ..default()
+        })),
+        Transform::default(),
+        RenderLayers::layer(render_layer::MAP),
+        NotShadowCaster,
+    ));
+}
+
+fn toggle(input: ActionInput, mut automap: ResMut<Automap>) {
+    if input.just_pressed(InputAction::Automap) {
+        automap.visible = !automap.visible;
+    }
+    if input.just_pressed(InputAction::AutomapView) {
+        automap.view = match automap.view {
+            AutomapView::Slice => AutomapView::Hologram,
+            AutomapView::Hologram => AutomapView::Slice,
+        };
+    }
+}
+
+/// Keeps tiles up to date as visited chunks are remeshed, e.g. after destruction.
+fn track_chunks(
+    mut commands: Commands,
+    mut automap: ResMut<Automap>,
+    mut tile_materials: TileMaterials,
+    mut meshed: EventReader<ChunkMeshed>,
+    mut despawned: EventReader<ChunkDespawned>,
+    chunks: TileSourceQuery,
+) {
+    for event in despawned.read() {
+        if automap.loaded.get(&event.chunk_pos) == Some(&event.entity) {
+            automap.loaded.remove(&event.chunk_pos);
+        }
+    }
+
+    for event in meshed.read() {
+        automap.loaded.insert(event.chunk_pos, event.entity);
+        if automap.is_visited(event.chunk_pos) {
+            update_tile(
+                &mut commands,
+                &mut automap,
+                &mut tile_materials,
+                &chunks,
+                event.chunk_pos,
+            );
+        }
+    }
+}
+
+fn reveal_chunks(
+    mut commands: Commands,
+    mut automap: ResMut<Automap>,
+    mut tile_materials: TileMaterials,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+    chunks: TileSourceQuery,
+) {
+    let Some(player) = player else {
+        return;
+    };
+    let center = (player.translation() / CHUNK_SIZE_F).floor().as_ivec3();
+
+    for x in -REVEAL_RADIUS..=REVEAL_RADIUS {
+        for y in -REVEAL_RADIUS..=REVEAL_RADIUS {
+            for z in -REVEAL_RADIUS..=REVEAL_RADIUS {
+                let chunk_pos = center + IVec3::new(x, y, z);
+                // Chunks that aren't loaded yet are revealed once the player is near them again
+                if automap.is_visited(chunk_pos) || !automap.loaded.contains_key(&chunk_pos) {
+                    continue;
+                }
+
+                automap.visited.insert(chunk_pos);
+                update_tile(
+                    &mut commands,
+                    &mut automap,
+                    &mut tile_materials,
+                    &chunks,
+                    chunk_pos,
+                );
+            }
+        }
+    }
+}
+
+/// Copies a loaded chunk's mesh onto its tile, spawning the tile if needed.
+fn update_tile(
+    commands: &mut Commands,
+    automap: &mut Automap,
+    tile_materials: &mut TileMaterials,
+    chunks: &TileSourceQuery,
+    chunk_pos: IVec3,
+) {
+    let Some(entity) = automap.loaded.get(&chunk_pos) else {
+        return;
+    };
+    let Ok((mesh, transform, aabb, composition)) = chunks.get(*entity) else {
+        return;
+    };
+
+    // Tiles are tinted by their most common material, which destruction can change
+    let material = tile_materials.get(composition.and_then(ChunkComposition::dominant));
+    let tile = (mesh.clone(), *transform, *aabb, MeshMaterial3d(material));
+    match automap.tiles.get(&chunk_pos) {
+        Some(tile_entity) => {
+            commands.entity(*tile_entity).insert(tile);
+        }
+        None => {
+            let tile_entity = commands
+                .spawn((
+                    AutomapTile,
+                    tile,
+                    RenderLayers::layer(render_layer::MAP),
+                    NotShadowCaster,
+                ))
+                .id();
+            automap.tiles.insert(chunk_pos, tile_entity);
+        }
+    }
+}
+
+fn move_marker(
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+    mut marker: Single<&mut Transform, With<AutomapMarker>>,
+) {
+    if let Some(player) = player {
+        marker.translation = player.translation();
+    }
+}
+
+fn move_camera(
+    time: Res<Time>,
+    automap: Res<Automap>,
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+    camera: Single<(&mut Camera, &mut Transform, &mut Projection), With<AutomapCamera>>,
+) {
+    let (mut camera, mut transform, mut projection) = camera.into_inner();
+    let (Some(window), Some(player)) = (window, player) else {
+        camera.is_active = false;
+        return;
+    };
+    camera.is_active = automap.visible;
+    if !automap.visible {
+        return;
+    }
+
+    // Square in the top right corner of the window
+    let window_size = window.physical_size();
+    let size = ((window_size.y as f32 * MAP_SIZE) as u32)
+        .min(window_size.x.saturating_sub(MAP_MARGIN * 2))
+        .max(1);
+    camera.viewport = Some(Viewport {
+        physical_position: UVec2::new(window_size.x.saturating_sub(size + MAP_MARGIN), MAP_MARGIN),
+        physical_size: UVec2::splat(size),
+        ..default()
+    });
+
+    let center = player.translation();
+    match automap.view {
+        AutomapView::Slice => {
+            // The near and far planes cut out everything but a slice around the player
+            *transform = Transform::from_translation(center + Vec3::Y * SLICE_HALF_HEIGHT)
+                .looking_to(Vec3::NEG_Y, Vec3::NEG_Z);
+            *projection = Projection::Orthographic(OrthographicProjection {
+                near: 0.0,
+                far: SLICE_HALF_HEIGHT * 2.0,
+                scaling_mode: ScalingMode::Fixed {
+                    width: SLICE_EXTENT,
+                    height: SLICE_EXTENT,
+                },
+                ..OrthographicProjection::default_3d()
+            });
+        }
+        AutomapView::Hologram => {
+            let yaw = time.elapsed_secs_wrapped() * HOLOGRAM_SPIN_SPEED % (PI * 2.0);
+            let rotation = Quat::from_euler(EulerRot::YXZ, yaw, -HOLOGRAM_PITCH.to_radians(), 0.0);
+            let eye = center + rotation * Vec3::Z * HOLOGRAM_DISTANCE;
+            *transform = Transform::from_translation(eye).looking_at(center, Vec3::Y);
+            if !matches!(*projection, Projection::Perspective(_)) {
+                *projection = Projection::Perspective(default());
+            }
+        }
+    }
+}
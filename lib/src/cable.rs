@@ -1,24 +1,211 @@
 use std::f32::consts::PI;
 
+use anyhow::anyhow;
 use avian3d::prelude::*;
 use bevy::{
     asset::RenderAssetUsages,
+    ecs::system::{SystemParam, SystemState},
     prelude::*,
-    render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+    render::{
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        view::NoFrustumCulling,
+    },
 };
 
+use crate::worldgen::{
+    consts::CHUNK_SIZE_F,
+    layout::GravityZones,
+    terrain::{ChunkMeshed, TerrainStateMutex, UpdateChunks, VoxelMaterialQuery},
+};
+
+/// Solid terrain needed behind an anchor for it to hold.
+const ANCHOR_MIN_THICKNESS: f32 = 0.75;
+/// Distance between samples when measuring the terrain behind an anchor.
+const ANCHOR_PROBE_STEP: f32 = 0.1;
+/// Share of a point's velocity kept each step.
+const DAMPING: f32 = 0.98;
+/// Passes over the length constraints per step. More passes make the cable stretch less.
+const CONSTRAINT_ITERATIONS: usize = 16;
+/// Longest step simulated at once, so a hitch doesn't launch the cable.
+const MAX_STEP_SECS: f32 = 1.0 / 30.0;
+/// Distance between samples when finding the direction out of the terrain.
+const COLLISION_GRADIENT_STEP: f32 = 0.05;
+
 pub struct CableSegments {
     pub length: f32,
     pub radius: f32,
     pub faces: u16,
 }
 
+impl Default for CableSegments {
+    fn default() -> Self {
+        Self {
+            length: 0.25,
+            radius: 0.03,
+            faces: 6,
+        }
+    }
+}
+
 impl CableSegments {
     fn total_segments(&self, max_length: f32) -> u16 {
         (max_length / self.length).ceil().max(0.0) as u16
     }
 }
 
+/// Where one end of a cable is held.
+#[derive(Clone, Copy, Debug)]
+pub enum CableAttachment {
+    /// Follows an entity, offset in its local space. The end hangs free if the entity is
+    /// despawned.
+    Entity {
+        entity: Entity,
+        offset: Vec3,
+    },
+    /// Fixed in world space.
+    Point(Vec3),
+    Free,
+}
+
+impl CableAttachment {
+    pub fn entity(entity: Entity) -> Self {
+        Self::Entity {
+            entity,
+            offset: Vec3::ZERO,
+        }
+    }
+}
+
+/// Rope simulated as a chain of points. The mesh is rebuilt from the points every frame, and the
+/// points collide with the terrain.
+#[derive(Component)]
+pub struct Cable {
+    pub start: CableAttachment,
+    pub end: CableAttachment,
+    /// Longer than the distance between the ends makes the cable sag. Can be changed at any
+    /// time, e.g. to reel the cable in.
+    pub length: f32,
+    pub radius: f32,
+    points: Vec<Vec3>,
+    previous: Vec<Vec3>,
+    tension: f32,
+}
+
+impl Cable {
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// How far the cable is stretched past its length, relative to the length. Zero while it
+    /// sags.
+    pub fn tension(&self) -> f32 {
+        self.tension
+    }
+
+    /// Direction the cable pulls its start toward.
+    pub fn start_direction(&self) -> Vec3 {
+        (self.points[1] - self.points[0]).normalize_or_zero()
+    }
+
+    /// Direction the cable pulls its end toward.
+    pub fn end_direction(&self) -> Vec3 {
+        let n = self.points.len();
+        (self.points[n - 2] - self.points[n - 1]).normalize_or_zero()
+    }
+
+    fn segment_length(&self) -> f32 {
+        self.length / (self.points.len() - 1) as f32
+    }
+}
+
+/// Spawns a cable between two attachments.
+pub struct SpawnCableCommand {
+    pub start: CableAttachment,
+    pub end: CableAttachment,
+    pub length: f32,
+    /// Spacing of the simulated points, and the shape of the mesh.
+    pub segments: CableSegments,
+    /// Spawns the cable onto this entity instead of a new one, so it can be found later.
+    pub entity: Option<Entity>,
+}
+
+impl SpawnCableCommand {
+    pub fn new(start: CableAttachment, end: CableAttachment, length: f32) -> Self {
+        Self {
+            start,
+            end,
+            length,
+            segments: default(),
+            entity: None,
+        }
+    }
+
+    pub fn with_entity(mut self, entity: Entity) -> Self {
+        self.entity = Some(entity);
+        self
+    }
+}
+
+impl Command for SpawnCableCommand {
+    fn apply(self, world: &mut World) {
+        let mut system_state = SystemState::<(
+            Commands,
+            ResMut<Assets<Mesh>>,
+            Res<CableAssets>,
+            Query<&GlobalTransform>,
+        )>::new(world);
+        let (mut commands, mut meshes, assets, transforms) = system_state.get_mut(world);
+
+        // Free ends hang straight down from the other end
+        let start = attachment_position(&self.start, &transforms);
+        let end = attachment_position(&self.end, &transforms);
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            (Some(start), None) => (start, start + Vec3::NEG_Y * self.length),
+            (None, Some(end)) => (end + Vec3::Y * self.length, end),
+            (None, None) => (Vec3::ZERO, Vec3::NEG_Y * self.length),
+        };
+
+        let count = self.segments.total_segments(self.length).max(1) as usize + 1;
+        let points = (0..count)
+            .map(|i| start.lerp(end, i as f32 / (count - 1) as f32))
+            .collect::<Vec<_>>();
+        let mesh = generate_tube_mesh(&points, self.segments.radius, self.segments.faces);
+
+        let mut entity = match self.entity {
+            Some(entity) => commands.entity(entity),
+            None => commands.spawn_empty(),
+        };
+        entity.insert((
+            Cable {
+                start: self.start,
+                end: self.end,
+                length: self.length,
+                radius: self.segments.radius,
+                previous: points.clone(),
+                points,
+                tension: 0.0,
+            },
+            CableFaces(self.segments.faces),
+            // The mesh is in world space
+            Transform::default(),
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(assets.material.clone()),
+            NoFrustumCulling,
+        ));
+
+        system_state.apply(world);
+    }
+}
+
+#[derive(Component)]
+struct CableFaces(u16);
+
+#[derive(Resource)]
+struct CableAssets {
+    material: Handle<StandardMaterial>,
+}
+
 #[derive(Component)]
 pub struct CableStart;
 
@@ -28,17 +215,302 @@ pub struct CableEnd;
 #[derive(Component)]
 pub struct CableSegment;
 
+/// Insert on the player while they're holding a cable, such as a grapple. The view model's
+/// off-hand reaches toward the anchor.
+#[derive(Component)]
+pub struct HeldCable {
+    pub anchor: Entity,
+}
+
 #[derive(Component)]
 pub struct CableSkinnedMeshJoint(pub Entity);
 
+/// Where a cable is attached to the terrain. The anchor breaks if the terrain holding it is
+/// destroyed, or becomes too thin.
+#[derive(Component)]
+pub struct CableAnchor {
+    /// Surface normal at the anchor.
+    pub normal: Vec3,
+}
+
+/// Sent when an anchor breaks. The anchor and everything under it is despawned, and anyone
+/// holding the cable lets go.
+#[derive(Event, Debug)]
+pub struct CableBrokenEvent {
+    pub anchor: Entity,
+    pub position: Vec3,
+}
+
+#[derive(SystemParam)]
+pub struct AnchorQuery<'w> {
+    terrain: Option<Res<'w, TerrainStateMutex>>,
+    materials: VoxelMaterialQuery<'w>,
+}
+
+impl AnchorQuery<'_> {
+    /// Checks that the terrain at a surface point can hold an anchor: it must be made of a
+    /// material that holds anchors, with enough solid terrain behind it.
+    pub fn validate(&self, position: Vec3, normal: Vec3) -> anyhow::Result<()> {
+        let terrain = self.terrain.as_ref().ok_or_else(|| anyhow!("no terrain"))?;
+
+        let inside = position - normal * ANCHOR_PROBE_STEP;
+        let material = terrain
+            .material_at(inside)
+            .ok_or_else(|| anyhow!("terrain isn't loaded"))?;
+        let holds_anchors = self
+            .materials
+            .properties(material)
+            .is_some_and(|properties| properties.holds_anchors);
+        if !holds_anchors {
+            return Err(anyhow!("{material:?} is too soft to hold an anchor"));
+        }
+
+        let thickness = terrain
+            .thickness_at(position, normal, ANCHOR_PROBE_STEP, ANCHOR_MIN_THICKNESS)
+            .ok_or_else(|| anyhow!("terrain isn't loaded"))?;
+        if thickness < ANCHOR_MIN_THICKNESS {
+            return Err(anyhow!("terrain is too thin to hold an anchor"));
+        }
+
+        Ok(())
+    }
+}
+
 pub struct CablePlugin;
 
 impl Plugin for CablePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, sync_joints);
+        app.add_event::<CableBrokenEvent>();
+        app.add_systems(Startup, setup);
+        app.add_systems(
+            Update,
+            (
+                sync_joints,
+                break_anchors.after(UpdateChunks),
+                (simulate_cables, update_cable_meshes).chain(),
+            ),
+        );
+    }
+}
+
+fn setup(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    commands.insert_resource(CableAssets {
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgb(0.15, 0.13, 0.1),
+            perceptual_roughness: 0.9,
+            ..default()
+        }),
+    });
+}
+
+/// Revalidates anchors in remeshed chunks, since remeshing follows destruction.
+fn break_anchors(
+    mut commands: Commands,
+    validator: AnchorQuery,
+    mut meshed: EventReader<ChunkMeshed>,
+    mut broken: EventWriter<CableBrokenEvent>,
+    anchors: Query<(Entity, &GlobalTransform, &CableAnchor)>,
+    holders: Query<(Entity, &HeldCable)>,
+) {
+    let remeshed = meshed
+        .read()
+        .map(|event| event.chunk_pos)
+        .collect::<Vec<_>>();
+    if remeshed.is_empty() {
+        return;
+    }
+
+    for (entity, transform, anchor) in anchors.iter() {
+        let position = transform.translation();
+        let chunk_pos = (position / CHUNK_SIZE_F).floor().as_ivec3();
+        if !remeshed.contains(&chunk_pos) {
+            continue;
+        }
+        let Err(err) = validator.validate(position, anchor.normal) else {
+            continue;
+        };
+        info!("cable anchor broke: {err}");
+
+        for (holder, held) in holders.iter() {
+            if held.anchor == entity {
+                commands.entity(holder).remove::<HeldCable>();
+            }
+        }
+        commands.entity(entity).despawn_recursive();
+        broken.send(CableBrokenEvent {
+            anchor: entity,
+            position,
+        });
+    }
+}
+
+fn attachment_position(
+    attachment: &CableAttachment,
+    transforms: &Query<&GlobalTransform>,
+) -> Option<Vec3> {
+    match *attachment {
+        CableAttachment::Entity { entity, offset } => transforms
+            .get(entity)
+            .ok()
+            .map(|transform| transform.transform_point(offset)),
+        CableAttachment::Point(point) => Some(point),
+        CableAttachment::Free => None,
+    }
+}
+
+/// Verlet integration, followed by length constraints and collision with the terrain.
+fn simulate_cables(
+    time: Res<Time>,
+    gravity: GravityZones,
+    terrain: Option<Res<TerrainStateMutex>>,
+    transforms: Query<&GlobalTransform>,
+    mut cables: Query<&mut Cable>,
+) {
+    let dt = time.delta_secs().min(MAX_STEP_SECS);
+    if dt <= 0.0 {
+        return;
+    }
+    let terrain = terrain.as_ref().map(|terrain| terrain.lock().unwrap());
+
+    for mut cable in cables.iter_mut() {
+        let cable = &mut *cable;
+        let last = cable.points.len() - 1;
+        let start = attachment_position(&cable.start, &transforms);
+        let end = attachment_position(&cable.end, &transforms);
+
+        for (point, previous) in cable.points.iter_mut().zip(cable.previous.iter_mut()) {
+            let velocity = (*point - *previous) * DAMPING;
+            *previous = *point;
+            *point += velocity + gravity.gravity_at(*point) * dt * dt;
+        }
+
+        let segment_length = cable.segment_length();
+        let pinned = |i: usize| (i == 0 && start.is_some()) || (i == last && end.is_some());
+        for _ in 0..CONSTRAINT_ITERATIONS {
+            if let Some(start) = start {
+                cable.points[0] = start;
+            }
+            if let Some(end) = end {
+                cable.points[last] = end;
+            }
+
+            for i in 0..last {
+                let (a, b) = (cable.points[i], cable.points[i + 1]);
+                let delta = b - a;
+                let distance = delta.length();
+                if distance <= f32::EPSILON {
+                    continue;
+                }
+                let correction = delta * ((distance - segment_length) / distance);
+                match (pinned(i), pinned(i + 1)) {
+                    (true, true) => {}
+                    (true, false) => cable.points[i + 1] -= correction,
+                    (false, true) => cable.points[i] += correction,
+                    (false, false) => {
+                        cable.points[i] += correction * 0.5;
+                        cable.points[i + 1] -= correction * 0.5;
+                    }
+                }
+            }
+        }
+
+        // Push points out of solid terrain along the gradient of the distance field
+        if let Some(terrain) = terrain.as_ref() {
+            for i in 0..=last {
+                if pinned(i) {
+                    continue;
+                }
+                let point = cable.points[i];
+                let Some(distance) = terrain.distance_at(point) else {
+                    continue;
+                };
+                let penetration = distance + cable.radius;
+                if penetration <= 0.0 {
+                    continue;
+                }
+
+                let sample = |offset: Vec3| terrain.distance_at(point + offset).unwrap_or(distance);
+                let gradient = Vec3::new(
+                    sample(Vec3::X * COLLISION_GRADIENT_STEP) - distance,
+                    sample(Vec3::Y * COLLISION_GRADIENT_STEP) - distance,
+                    sample(Vec3::Z * COLLISION_GRADIENT_STEP) - distance,
+                );
+                let Some(outward) = (-gradient).try_normalize() else {
+                    continue;
+                };
+                cable.points[i] += outward * penetration;
+                // Friction against the surface
+                cable.previous[i] = cable.points[i];
+            }
+        }
+
+        let stretched = cable
+            .points
+            .windows(2)
+            .map(|pair| pair[0].distance(pair[1]))
+            .sum::<f32>();
+        cable.tension = ((stretched - cable.length) / cable.length.max(f32::EPSILON)).max(0.0);
     }
 }
 
+fn update_cable_meshes(
+    mut meshes: ResMut<Assets<Mesh>>,
+    cables: Query<(&Cable, &CableFaces, &Mesh3d), Changed<Cable>>,
+) {
+    for (cable, faces, mesh) in cables.iter() {
+        let Some(mesh) = meshes.get_mut(&mesh.0) else {
+            continue;
+        };
+        let (positions, normals) = tube_vertices(&cable.points, cable.radius, faces.0);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    }
+}
+
+/// Ring of vertices around every point. Rings are rotated along the cable so they don't twist.
+fn tube_vertices(points: &[Vec3], radius: f32, faces: u16) -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+    let mut positions = Vec::with_capacity(points.len() * faces as usize);
+    let mut normals = Vec::with_capacity(points.len() * faces as usize);
+
+    let mut side = Vec3::ZERO;
+    for (i, point) in points.iter().enumerate() {
+        let tangent = points[(i + 1).min(points.len() - 1)] - points[i.saturating_sub(1)];
+        let tangent = tangent.normalize_or(Vec3::Y);
+        side = side
+            .reject_from_normalized(tangent)
+            .normalize_or(tangent.any_orthonormal_vector());
+        let up = tangent.cross(side);
+
+        for face in 0..faces {
+            let theta = face as f32 / faces as f32 * 2.0 * PI;
+            let normal = side * theta.cos() + up * theta.sin();
+            positions.push((*point + normal * radius).to_array());
+            normals.push(normal.to_array());
+        }
+    }
+
+    (positions, normals)
+}
+
+fn generate_tube_mesh(points: &[Vec3], radius: f32, faces: u16) -> Mesh {
+    let (positions, normals) = tube_vertices(points, radius, faces);
+
+    let mut indices = Vec::<u32>::new();
+    let faces = faces as u32;
+    for ring in 0..points.len() as u32 - 1 {
+        for i in 0..faces {
+            let bottom = |index: u32| (index + i) % faces + faces * ring;
+            let top = |index: u32| bottom(index) + faces;
+            indices.extend([bottom(1), bottom(0), top(0), top(0), top(1), bottom(1)]);
+        }
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_indices(Indices::U32(indices))
+}
+
 fn sync_joints(
     segments: Query<&GlobalTransform, With<CableSegment>>,
     mut joints: Query<(&CableSkinnedMeshJoint, &mut Transform), Without<CableSegment>>,
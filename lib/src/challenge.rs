@@ -0,0 +1,474 @@
+//! Timed challenge mode. Every sequence collapses a while after the player first reaches it, so
+//! players who linger get buried. Runs are recorded to a leaderboard when they end.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use bevy_rand::{global::GlobalEntropy, prelude::WyRand};
+use curvo::prelude::NurbsCurve3D;
+use nalgebra::Point3;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    haptics::HapticEvent,
+    player::{DespawnPlayerCommand, IsPlayer},
+    worldgen::{
+        brush::{BrushOperation, TerrainBrush},
+        layout::{CurrentRoom, PacingCurve, PortalConnection, Room, TrackCurrentRoom, WorldSeed},
+        voxel::VoxelMaterial,
+    },
+};
+
+const CHALLENGE_CONFIG_PATH: &str = "./assets/challenge.ron";
+const LEADERBOARD_PATH: &str = "leaderboard.ron";
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Radius of the fill swept through the tunnels of a collapsing sequence.
+const COLLAPSE_TUNNEL_RADIUS: f32 = 7.0;
+/// Seconds between rumbles while a collapse is coming.
+const RUMBLE_INTERVAL: f32 = 1.5;
+/// Rumbles are felt like a cave-in this large, at the player's position.
+const RUMBLE_RADIUS: f32 = 12.0;
+/// Dust falling around the player right before their sequence collapses.
+const DUST_PER_SECOND: f32 = 60.0;
+const DUST_SPAWN_RADIUS: f32 = 10.0;
+const DUST_SPAWN_HEIGHT: f32 = 6.0;
+const DUST_LIFETIME: f32 = 2.0;
+
+/// Timing of the challenge mode. Loaded from `assets/challenge.ron`.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct ChallengeConfig {
+    /// Seconds between the player reaching a sequence and it collapsing.
+    pub collapse_secs: f32,
+    /// Seconds of rumbling and falling dust before a sequence collapses.
+    pub warning_secs: f32,
+    /// Extra seconds per unit of combat in the pacing curve's target mix for the sequence, so
+    /// fights don't get the player buried.
+    pub combat_bonus_secs: f32,
+}
+
+impl Default for ChallengeConfig {
+    fn default() -> Self {
+        Self {
+            collapse_secs: 90.0,
+            warning_secs: 15.0,
+            combat_bonus_secs: 60.0,
+        }
+    }
+}
+
+impl ChallengeConfig {
+    pub fn collapse_secs(&self, curve: Option<&PacingCurve>, sequence: usize) -> f32 {
+        let combat = curve.map_or(0.0, |curve| curve.target(sequence).combat);
+        self.collapse_secs + self.combat_bonus_secs * combat
+    }
+}
+
+/// Progress of the current challenge run.
+#[derive(Resource, Default, Debug)]
+pub struct ChallengeRun {
+    /// Seconds since the run started.
+    pub elapsed: f32,
+    /// Deepest sequence the player has reached, once they've entered a room.
+    pub depth: Option<usize>,
+    /// Sequence the player is in, including the tunnels leading into it.
+    pub sequence: Option<usize>,
+    /// When each sequence that hasn't collapsed yet will, in seconds since the run started.
+    pub collapses: BTreeMap<usize, f32>,
+    pub over: bool,
+}
+
+impl ChallengeRun {
+    /// Seconds until the player's sequence collapses.
+    pub fn remaining(&self) -> Option<f32> {
+        let sequence = self.sequence?;
+        let (_, at) = self.collapses.range(sequence..).next()?;
+        Some((at - self.elapsed).max(0.0))
+    }
+}
+
+/// Sent when the rooms and tunnels of a sequence are filled in.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SequenceCollapsedEvent {
+    pub sequence: usize,
+}
+
+/// Sent when the player is buried, after the run is recorded.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChallengeOverEvent {
+    pub entry: LeaderboardEntry,
+    /// Position on the leaderboard, if the run made it.
+    pub rank: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LeaderboardEntry {
+    pub depth: usize,
+    pub secs: f32,
+    pub seed: Option<u64>,
+}
+
+/// Best challenge runs, stored in `leaderboard.ron`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    /// An empty leaderboard if the file doesn't exist yet.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let s = fs::read_to_string(path).context("failed to read leaderboard")?;
+        Ok(ron::from_str(&s)?)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let s = ron::ser::to_string_pretty(self, default())?;
+        fs::write(path, s).context("failed to write leaderboard")?;
+        Ok(())
+    }
+
+    /// Deeper runs rank higher, and faster runs break ties. Returns the entry's rank, if it's
+    /// among the best.
+    pub fn record(&mut self, entry: LeaderboardEntry) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| {
+                entry.depth > other.depth || (entry.depth == other.depth && entry.secs < other.secs)
+            })
+            .unwrap_or(self.entries.len());
+        if rank >= LEADERBOARD_SIZE {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(LEADERBOARD_SIZE);
+        Some(rank)
+    }
+}
+
+#[derive(Component)]
+struct Dust {
+    velocity: Vec3,
+    remaining: f32,
+}
+
+#[derive(Resource)]
+struct DustAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+/// Requires the layout and the player.
+pub struct ChallengePlugin;
+
+impl Plugin for ChallengePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChallengeRun>();
+        app.add_event::<SequenceCollapsedEvent>();
+        app.add_event::<ChallengeOverEvent>();
+        app.add_systems(Startup, (load_challenge_config, setup_dust_assets));
+        app.add_systems(
+            Update,
+            (
+                (track_player, warn_of_collapse, collapse)
+                    .chain()
+                    .after(TrackCurrentRoom)
+                    .run_if(|run: Res<ChallengeRun>| !run.over),
+                fall_dust,
+                draw_timer,
+            ),
+        );
+    }
+}
+
+fn load_challenge_config(mut commands: Commands) {
+    let config = match fs::read_to_string(CHALLENGE_CONFIG_PATH) {
+        Ok(s) => ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("failed to parse challenge config, using default: {err}");
+            ChallengeConfig::default()
+        }),
+        Err(_) => ChallengeConfig::default(),
+    };
+
+    commands.insert_resource(config);
+}
+
+fn setup_dust_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(DustAssets {
+        mesh: meshes.add(Cuboid::from_size(Vec3::splat(0.06))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgba(0.45, 0.38, 0.3, 0.6),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+/// Schedules each sequence's collapse when the player first reaches it.
+fn track_player(
+    time: Res<Time>,
+    config: Option<Res<ChallengeConfig>>,
+    curve: Option<Res<PacingCurve>>,
+    current_room: Res<CurrentRoom>,
+    mut run: ResMut<ChallengeRun>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+    rooms: Query<&Room>,
+    connections: Query<&PortalConnection>,
+) {
+    let (Some(config), Some(player)) = (config, player) else {
+        return;
+    };
+    run.elapsed += time.delta_secs();
+
+    let room = current_room.0.and_then(|entity| rooms.get(entity).ok());
+    if let Some(room) = room {
+        if run.depth.is_none_or(|depth| room.sequence > depth) {
+            let at = run.elapsed + config.collapse_secs(curve.as_deref(), room.sequence);
+            run.depth = Some(room.sequence);
+            run.collapses.insert(room.sequence, at);
+        }
+    }
+
+    // Tunnels belong to the sequence they lead into
+    let position = player.translation();
+    run.sequence = room.map(|room| room.sequence).or_else(|| {
+        connections
+            .iter()
+            .filter_map(|connection| {
+                let distance = connection
+                    .path
+                    .iter()
+                    .map(|point| point.distance_squared(position))
+                    .reduce(f32::min)?;
+                Some((connection.sequence, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(sequence, _)| sequence)
+    });
+}
+
+/// Rumbles while any collapse is coming, and drops dust on the player when it's theirs.
+fn warn_of_collapse(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Option<Res<ChallengeConfig>>,
+    assets: Option<Res<DustAssets>>,
+    run: Res<ChallengeRun>,
+    mut rng: GlobalEntropy<WyRand>,
+    mut haptics: Option<ResMut<Events<HapticEvent>>>,
+    mut next_rumble: Local<f32>,
+    mut dust_budget: Local<f32>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+) {
+    let (Some(config), Some(assets), Some(player)) = (config, assets, player) else {
+        return;
+    };
+    let position = player.translation();
+
+    let soonest = run.collapses.values().copied().reduce(f32::min);
+    let coming = soonest.is_some_and(|at| at - run.elapsed <= config.warning_secs);
+    if coming && run.elapsed >= *next_rumble {
+        *next_rumble = run.elapsed + RUMBLE_INTERVAL;
+        if let Some(haptics) = haptics.as_mut() {
+            haptics.send(HapticEvent::CaveIn {
+                position,
+                radius: RUMBLE_RADIUS,
+            });
+        }
+    }
+
+    // Dust thickens as the player's own sequence is about to come down
+    let intensity = run.remaining().map_or(0.0, |remaining| {
+        1.0 - (remaining / config.warning_secs.max(f32::EPSILON)).min(1.0)
+    });
+    *dust_budget += DUST_PER_SECOND * intensity * time.delta_secs();
+    while *dust_budget >= 1.0 {
+        *dust_budget -= 1.0;
+
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let distance = DUST_SPAWN_RADIUS * rng.gen::<f32>().sqrt();
+        let offset = Vec3::new(
+            angle.cos() * distance,
+            DUST_SPAWN_HEIGHT,
+            angle.sin() * distance,
+        );
+        commands.spawn((
+            Mesh3d(assets.mesh.clone()),
+            MeshMaterial3d(assets.material.clone()),
+            Transform::from_translation(position + offset),
+            NotShadowCaster,
+            Dust {
+                velocity: Vec3::new(rng.gen_range(-0.3..0.3), 0.0, rng.gen_range(-0.3..0.3)),
+                remaining: DUST_LIFETIME,
+            },
+        ));
+    }
+}
+
+/// Fills in sequences whose time is up, and ends the run if the player was in one.
+fn collapse(
+    mut commands: Commands,
+    mut run: ResMut<ChallengeRun>,
+    mut collapsed: EventWriter<SequenceCollapsedEvent>,
+    mut over: EventWriter<ChallengeOverEvent>,
+    mut haptics: Option<ResMut<Events<HapticEvent>>>,
+    seed: Option<Res<WorldSeed>>,
+    rooms: Query<(Entity, &Room, &GlobalTransform)>,
+    connections: Query<(Entity, &PortalConnection)>,
+) {
+    let due = run
+        .collapses
+        .iter()
+        .filter(|(_, at)| **at <= run.elapsed)
+        .map(|(sequence, _)| *sequence)
+        .collect::<Vec<_>>();
+    if due.is_empty() {
+        return;
+    }
+
+    for sequence in due.iter().copied() {
+        run.collapses.remove(&sequence);
+
+        for (entity, room, transform) in rooms.iter() {
+            if room.sequence != sequence {
+                continue;
+            }
+            let center = transform.transform_point(room.center);
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn(TerrainBrush::fill(
+                    "",
+                    sequence,
+                    VoxelMaterial::BrownRock,
+                    center,
+                    room.radius,
+                ));
+            });
+            if let Some(haptics) = haptics.as_mut() {
+                haptics.send(HapticEvent::CaveIn {
+                    position: center,
+                    radius: room.radius,
+                });
+            }
+        }
+
+        for (entity, connection) in connections.iter() {
+            if connection.sequence != sequence {
+                continue;
+            }
+            let points = connection
+                .path
+                .iter()
+                .map(|point| (*point).into())
+                .collect::<Vec<Point3<f32>>>();
+            if NurbsCurve3D::<f32>::try_interpolate(&points, 3).is_err() {
+                continue;
+            }
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn(
+                    TerrainBrush::curve(
+                        "",
+                        sequence,
+                        VoxelMaterial::BrownRock,
+                        &points,
+                        COLLAPSE_TUNNEL_RADIUS,
+                    )
+                    .with_operation(BrushOperation::Fill),
+                );
+            });
+        }
+
+        collapsed.send(SequenceCollapsedEvent { sequence });
+    }
+
+    let buried = run
+        .sequence
+        .is_some_and(|sequence| due.iter().any(|collapsed| sequence <= *collapsed));
+    if !buried {
+        return;
+    }
+
+    run.over = true;
+    let entry = LeaderboardEntry {
+        depth: run.depth.unwrap_or_default(),
+        secs: run.elapsed,
+        seed: seed.map(|seed| seed.0),
+    };
+    let path = PathBuf::from(LEADERBOARD_PATH);
+    let result = Leaderboard::read(&path).and_then(|mut leaderboard| {
+        let rank = leaderboard.record(entry);
+        leaderboard.write(&path)?;
+        Ok(rank)
+    });
+    let rank = match result {
+        Ok(rank) => rank,
+        Err(err) => {
+            error!("failed to record challenge run: {err:?}");
+            None
+        }
+    };
+
+    info!(
+        "buried at depth {} after {:.1}s, rank {rank:?}",
+        entry.depth, entry.secs
+    );
+    over.send(ChallengeOverEvent { entry, rank });
+    commands.queue(DespawnPlayerCommand);
+}
+
+fn fall_dust(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dust: Query<(Entity, &mut Dust, &mut Transform)>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut dust, mut transform) in dust.iter_mut() {
+        dust.remaining -= delta;
+        if dust.remaining <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        // Dust drifts down slowly instead of falling like a rock
+        dust.velocity.y -= 2.0 * delta;
+        transform.translation += dust.velocity * delta;
+    }
+}
+
+fn draw_timer(mut contexts: EguiContexts, run: Res<ChallengeRun>) {
+    let Some(depth) = run.depth else {
+        return;
+    };
+
+    let text = match (run.over, run.remaining()) {
+        (true, _) => format!("Buried at depth {depth}"),
+        (false, Some(remaining)) => format!("Depth {depth}  |  Collapse in {remaining:.0}s"),
+        (false, None) => format!("Depth {depth}"),
+    };
+    egui::Area::new(egui::Id::new("challenge_timer"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 16.0))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(text)
+                    .strong()
+                    .size(18.0)
+                    .color(egui::Color32::from_rgb(255, 204, 51)),
+            );
+        });
+}
@@ -0,0 +1,481 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::{anyhow, bail};
+use avian3d::prelude::*;
+use bevy::{
+    ecs::system::SystemState,
+    input::InputSystem,
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    player::{IsPlayer, PlayerCamera, PlayerMotionConfig},
+    worldgen::{
+        layout::{LayoutState, StepLayoutCommand},
+        terrain::DestroyTerrainEvent,
+        voxel::DamageType,
+    },
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::Backquote;
+/// Oldest lines are dropped past this many.
+const MAX_OUTPUT_LINES: usize = 200;
+const AIM_RANGE: f32 = 200.0;
+const DEFAULT_DESTROY_RADIUS: f32 = 2.0;
+const DEFAULT_DESTROY_FORCE: f32 = 1.0;
+
+/// Something that can be typed into the console. Other plugins add their own with
+/// [`RegisterConsoleCommand::register_console_command`].
+pub trait ConsoleCommand: Send + Sync + 'static {
+    /// First word of the line that runs this command.
+    fn name(&self) -> &'static str;
+
+    /// One line describing the arguments, shown by `help`.
+    fn usage(&self) -> &'static str;
+
+    /// Runs the command with the rest of the words on the line. Returns what to print.
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String>;
+}
+
+/// Every command the console knows, by name.
+#[derive(Resource, Default, Clone)]
+pub struct ConsoleCommands(BTreeMap<&'static str, Arc<dyn ConsoleCommand>>);
+
+pub trait RegisterConsoleCommand {
+    /// Adds a command, replacing any with the same name. Works whether or not the console plugin
+    /// has been added yet.
+    fn register_console_command(&mut self, command: impl ConsoleCommand) -> &mut Self;
+}
+
+impl RegisterConsoleCommand for App {
+    fn register_console_command(&mut self, command: impl ConsoleCommand) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .insert(command.name(), Arc::new(command));
+        self
+    }
+}
+
+/// Developer console. While it's open, the game doesn't see the keyboard or mouse.
+#[derive(Resource, Default)]
+pub struct DebugConsole {
+    pub visible: bool,
+    input: String,
+    output: Vec<String>,
+    /// Lines that were run, oldest first.
+    history: Vec<String>,
+    history_cursor: Option<usize>,
+    /// Cursor state from before the console was opened.
+    previous_cursor: Option<(CursorGrabMode, bool)>,
+}
+
+impl DebugConsole {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.extend(line.into().lines().map(str::to_owned));
+        let overflow = self.output.len().saturating_sub(MAX_OUTPUT_LINES);
+        self.output.drain(..overflow);
+    }
+}
+
+pub struct DebugConsolePlugin;
+
+impl Plugin for DebugConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugConsole>();
+        app.init_resource::<ConsoleCommands>();
+        app.register_console_command(HelpCommand)
+            .register_console_command(TeleportCommand)
+            .register_console_command(LayoutCommand)
+            .register_console_command(GizmosCommand)
+            .register_console_command(DestroyCommand)
+            .register_console_command(SetCommand);
+        app.add_systems(
+            PreUpdate,
+            (toggle, swallow_input).chain().after(InputSystem),
+        );
+        app.add_systems(Update, ui);
+    }
+}
+
+/// Runs a line typed into the console and prints the result.
+pub struct RunConsoleLineCommand(pub String);
+
+impl Command for RunConsoleLineCommand {
+    fn apply(self, world: &mut World) {
+        let words = self.0.split_whitespace().collect::<Vec<_>>();
+        let Some((name, args)) = words.split_first() else {
+            return;
+        };
+
+        let command = world.resource::<ConsoleCommands>().0.get(name).cloned();
+        let result = match command {
+            Some(command) => command.run(args, world),
+            None => Err(anyhow!("unknown command '{name}', try 'help'")),
+        };
+
+        let mut console = world.resource_mut::<DebugConsole>();
+        console.print(format!("> {}", self.0));
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => console.print(output),
+            Err(err) => console.print(format!("error: {err}")),
+        }
+    }
+}
+
+//
+// Systems
+//
+
+fn toggle(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut console: ResMut<DebugConsole>,
+    window: Option<Single<&mut Window, With<PrimaryWindow>>>,
+) {
+    if !keyboard.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    console.visible = !console.visible;
+
+    // The cursor is needed to use the console, and is grabbed again when it closes
+    let Some(mut window) = window else {
+        return;
+    };
+    if console.visible {
+        let cursor = &window.cursor_options;
+        console.previous_cursor = Some((cursor.grab_mode, cursor.visible));
+        window.cursor_options.grab_mode = CursorGrabMode::None;
+        window.cursor_options.visible = true;
+    } else if let Some((grab_mode, visible)) = console.previous_cursor.take() {
+        window.cursor_options.grab_mode = grab_mode;
+        window.cursor_options.visible = visible;
+    }
+}
+
+/// Keeps what's typed into the console from moving the player or firing weapons. Egui reads
+/// input events, so the console still receives it.
+fn swallow_input(
+    console: Res<DebugConsole>,
+    mut keyboard: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+) {
+    if console.visible {
+        keyboard.reset_all();
+        mouse.reset_all();
+    }
+}
+
+fn ui(mut commands: Commands, mut contexts: EguiContexts, mut console: ResMut<DebugConsole>) {
+    if !console.visible {
+        return;
+    }
+
+    let mut submitted = None;
+    egui::TopBottomPanel::top("debug_console")
+        .resizable(true)
+        .default_height(240.0)
+        .show(contexts.ctx_mut(), |ui| {
+            let input_height = ui.spacing().interact_size.y + ui.spacing().item_spacing.y * 2.0;
+            egui::ScrollArea::vertical()
+                .max_height(ui.available_height() - input_height)
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in console.output.iter() {
+                        ui.label(egui::RichText::new(line).monospace());
+                    }
+                });
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut console.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY)
+                    .hint_text("Type 'help' for a list of commands"),
+            );
+            // The toggle key's character would otherwise end up in the input
+            console.input.retain(|c| c != '`');
+
+            let (enter, up, down) = ui.input(|input| {
+                (
+                    input.key_pressed(egui::Key::Enter),
+                    input.key_pressed(egui::Key::ArrowUp),
+                    input.key_pressed(egui::Key::ArrowDown),
+                )
+            });
+            if response.lost_focus() && enter {
+                submitted = Some(std::mem::take(&mut console.input));
+            } else if response.has_focus() && (up || down) {
+                browse_history(&mut console, up);
+            }
+            response.request_focus();
+        });
+
+    let Some(line) = submitted.filter(|line| !line.trim().is_empty()) else {
+        return;
+    };
+    console.history_cursor = None;
+    if console.history.last() != Some(&line) {
+        console.history.push(line.clone());
+    }
+    commands.queue(RunConsoleLineCommand(line));
+}
+
+fn browse_history(console: &mut DebugConsole, older: bool) {
+    if console.history.is_empty() {
+        return;
+    }
+
+    let last = console.history.len() - 1;
+    console.history_cursor = match (console.history_cursor, older) {
+        (None, true) => Some(last),
+        (None, false) => None,
+        (Some(index), true) => Some(index.saturating_sub(1)),
+        (Some(index), false) if index < last => Some(index + 1),
+        (Some(_), false) => None,
+    };
+    console.input = match console.history_cursor {
+        Some(index) => console.history[index].clone(),
+        None => String::new(),
+    };
+}
+
+//
+// Commands
+//
+
+struct HelpCommand;
+
+impl ConsoleCommand for HelpCommand {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "help - lists every command"
+    }
+
+    fn run(&self, _args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        let commands = world.resource::<ConsoleCommands>();
+        let lines = commands
+            .0
+            .values()
+            .map(|command| command.usage())
+            .collect::<Vec<_>>();
+
+        Ok(lines.join("\n"))
+    }
+}
+
+struct TeleportCommand;
+
+impl ConsoleCommand for TeleportCommand {
+    fn name(&self) -> &'static str {
+        "tp"
+    }
+
+    fn usage(&self) -> &'static str {
+        "tp <x> <y> <z> - moves the player"
+    }
+
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        let [x, y, z] = args else {
+            bail!("expected 3 coordinates");
+        };
+        let position = Vec3::new(x.parse()?, y.parse()?, z.parse()?);
+
+        let mut query =
+            world.query_filtered::<(&mut Transform, Option<&mut LinearVelocity>), With<IsPlayer>>();
+        let (mut transform, velocity) = query
+            .get_single_mut(world)
+            .map_err(|_| anyhow!("there is no player"))?;
+        transform.translation = position;
+        if let Some(mut velocity) = velocity {
+            velocity.0 = Vec3::ZERO;
+        }
+
+        Ok(format!(
+            "teleported to {:.1} {:.1} {:.1}",
+            position.x, position.y, position.z
+        ))
+    }
+}
+
+struct LayoutCommand;
+
+impl ConsoleCommand for LayoutCommand {
+    fn name(&self) -> &'static str {
+        "layout"
+    }
+
+    fn usage(&self) -> &'static str {
+        "layout step - generates the next sequence of rooms"
+    }
+
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        if args != ["step"] {
+            bail!("expected 'layout step'");
+        }
+        let Some(layout) = world.get_resource::<LayoutState>() else {
+            bail!("there is no layout");
+        };
+        let sequence = layout.sequence;
+
+        StepLayoutCommand.apply(world);
+        Ok(format!("stepping layout past sequence {sequence}"))
+    }
+}
+
+struct GizmosCommand;
+
+impl ConsoleCommand for GizmosCommand {
+    fn name(&self) -> &'static str {
+        "gizmos"
+    }
+
+    fn usage(&self) -> &'static str {
+        "gizmos [on|off] - shows or hides debug gizmos, including physics"
+    }
+
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        let mut store = world.resource_mut::<GizmoConfigStore>();
+        let enabled = match args {
+            [] => !store.config::<DefaultGizmoConfigGroup>().0.enabled,
+            ["on"] => true,
+            ["off"] => false,
+            _ => bail!("expected 'on' or 'off'"),
+        };
+
+        for (_, config, _) in store.iter_mut() {
+            config.enabled = enabled;
+        }
+
+        Ok(format!("gizmos {}", if enabled { "on" } else { "off" }))
+    }
+}
+
+struct DestroyCommand;
+
+impl ConsoleCommand for DestroyCommand {
+    fn name(&self) -> &'static str {
+        "destroy"
+    }
+
+    fn usage(&self) -> &'static str {
+        "destroy [radius] [force] - destroys terrain where the player is aiming"
+    }
+
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        let radius = args
+            .first()
+            .map_or(Ok(DEFAULT_DESTROY_RADIUS), |s| s.parse())?;
+        let force = args
+            .get(1)
+            .map_or(Ok(DEFAULT_DESTROY_FORCE), |s| s.parse())?;
+
+        let mut system_state: SystemState<(
+            SpatialQuery,
+            Option<Single<&GlobalTransform, With<PlayerCamera>>>,
+            Option<Single<Entity, With<IsPlayer>>>,
+            EventWriter<DestroyTerrainEvent>,
+        )> = SystemState::new(world);
+        let (spatial_query, camera, player, mut destroy) = system_state.get_mut(world);
+
+        let Some(camera) = camera else {
+            bail!("there is no player camera");
+        };
+        let filter = SpatialQueryFilter::from_excluded_entities(player.map(|p| *p));
+        let Some(hit) = spatial_query.cast_ray(
+            camera.translation(),
+            camera.forward(),
+            AIM_RANGE,
+            true,
+            &filter,
+        ) else {
+            bail!("not aiming at anything within {AIM_RANGE} meters");
+        };
+
+        let position = camera.translation() + camera.forward() * hit.distance;
+        destroy.send(DestroyTerrainEvent {
+            position,
+            radius,
+            force,
+            damage: DamageType::Kinetic,
+        });
+        system_state.apply(world);
+
+        Ok(format!(
+            "destroyed terrain at {:.1} {:.1} {:.1}",
+            position.x, position.y, position.z
+        ))
+    }
+}
+
+struct SetCommand;
+
+impl SetCommand {
+    const FIELDS: [&'static str; 6] = [
+        "speed",
+        "sprint_speed_multiplier",
+        "crouch_speed_multiplier",
+        "actions_in_air",
+        "jump_buffer_time",
+        "coyote_time",
+    ];
+}
+
+impl ConsoleCommand for SetCommand {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn usage(&self) -> &'static str {
+        "set [field] [value] - shows or changes the player's motion config"
+    }
+
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        let mut query = world.query_filtered::<&mut PlayerMotionConfig, With<IsPlayer>>();
+        let mut config = query
+            .get_single_mut(world)
+            .map_err(|_| anyhow!("there is no player"))?;
+
+        let (field, value) = match args {
+            [] => {
+                let lines = Self::FIELDS
+                    .iter()
+                    .map(|field| format!("{field} = {}", get_field(&config, field).unwrap()))
+                    .collect::<Vec<_>>();
+                return Ok(lines.join("\n"));
+            }
+            [field] => return Ok(format!("{field} = {}", get_field(&config, field)?)),
+            [field, value] => (*field, *value),
+            _ => bail!("expected a field and a value"),
+        };
+
+        match field {
+            "speed" => config.speed = value.parse()?,
+            "sprint_speed_multiplier" => config.sprint_speed_multiplier = value.parse()?,
+            "crouch_speed_multiplier" => config.crouch_speed_multiplier = value.parse()?,
+            "actions_in_air" => config.actions_in_air = value.parse()?,
+            "jump_buffer_time" => config.jump_buffer_time = value.parse()?,
+            "coyote_time" => config.coyote_time = value.parse()?,
+            _ => bail!("unknown field '{field}'"),
+        }
+
+        Ok(format!("{field} = {}", get_field(&config, field)?))
+    }
+}
+
+fn get_field(config: &PlayerMotionConfig, field: &str) -> anyhow::Result<String> {
+    Ok(match field {
+        "speed" => config.speed.to_string(),
+        "sprint_speed_multiplier" => config.sprint_speed_multiplier.to_string(),
+        "crouch_speed_multiplier" => config.crouch_speed_multiplier.to_string(),
+        "actions_in_air" => config.actions_in_air.to_string(),
+        "jump_buffer_time" => config.jump_buffer_time.to_string(),
+        "coyote_time" => config.coyote_time.to_string(),
+        _ => bail!("unknown field '{field}'"),
+    })
+}
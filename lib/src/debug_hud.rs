@@ -0,0 +1,98 @@
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    player::IsPlayer,
+    worldgen::{
+        consts::CHUNK_SIZE_F,
+        layout::{CurrentRoom, LayoutState, Room, WorldSeed},
+    },
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F4;
+
+/// Small overlay with everything needed to reproduce what's on screen, so screenshots and videos
+/// from testers include it.
+#[derive(Resource, Default)]
+pub struct DebugHud {
+    pub visible: bool,
+}
+
+pub struct DebugHudPlugin;
+
+impl Plugin for DebugHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugHud>();
+        app.add_systems(Update, (toggle, ui).chain());
+    }
+}
+
+fn toggle(keyboard: Res<ButtonInput<KeyCode>>, mut hud: ResMut<DebugHud>) {
+    if keyboard.just_pressed(TOGGLE_KEY) {
+        hud.visible = !hud.visible;
+    }
+}
+
+fn ui(
+    mut contexts: EguiContexts,
+    hud: Res<DebugHud>,
+    time: Res<Time>,
+    diagnostics: Res<DiagnosticsStore>,
+    seed: Option<Res<WorldSeed>>,
+    layout: Option<Res<LayoutState>>,
+    current_room: Option<Res<CurrentRoom>>,
+    rooms: Query<&Room>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+) {
+    if !hud.visible {
+        return;
+    }
+
+    let frame_time_ms = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|d| d.smoothed())
+        .unwrap_or(time.delta_secs_f64() * 1000.0);
+    let room = current_room
+        .and_then(|current| current.0)
+        .and_then(|entity| rooms.get(entity).ok());
+
+    let mut lines = vec![
+        match seed {
+            Some(seed) => format!("Seed: {}", seed.0),
+            None => "Seed: -".to_owned(),
+        },
+        match layout {
+            Some(layout) => format!("Sequence: {}", layout.sequence),
+            None => "Sequence: -".to_owned(),
+        },
+        match room {
+            Some(room) => format!("Room: {} (sequence {})", room.source, room.sequence),
+            None => "Room: -".to_owned(),
+        },
+    ];
+    if let Some(player) = player {
+        let position = player.translation();
+        let chunk = (position / CHUNK_SIZE_F).floor().as_ivec3();
+        lines.push(format!(
+            "Position: {:.1} {:.1} {:.1}",
+            position.x, position.y, position.z
+        ));
+        lines.push(format!("Chunk: {} {} {}", chunk.x, chunk.y, chunk.z));
+    }
+    lines.push(format!("Frame time: {frame_time_ms:.2} ms"));
+
+    egui::Area::new(egui::Id::new("debug_hud"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+        .order(egui::Order::Foreground)
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                for line in lines {
+                    ui.label(egui::RichText::new(line).monospace().small());
+                }
+            });
+        });
+}
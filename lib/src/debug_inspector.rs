@@ -0,0 +1,258 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::worldgen::{
+    brush::TerrainBrush,
+    consts::CHUNK_SIZE_F,
+    layout::{Portal, PortalConnection, Room},
+    terrain::{ChunkComposition, TerrainDetail, TerrainStateMutex},
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+const HIGHLIGHT_COLOR: Color = Color::srgb(1.0, 0.0, 1.0);
+const RELATED_COLOR: Color = Color::srgb(1.0, 0.6, 1.0);
+const PORTAL_RADIUS: f32 = 3.0;
+/// Height of each list, so long lists like chunks don't push the others off screen.
+const SECTION_HEIGHT: f32 = 160.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Highlight {
+    Entity(Entity),
+    Chunk(IVec3),
+}
+
+/// Lists the generated world's rooms, connections, portals, brushes, and chunks. Click an item to
+/// highlight it in the world.
+#[derive(Resource, Default)]
+pub struct DebugInspector {
+    pub visible: bool,
+    highlight: Option<Highlight>,
+}
+
+impl DebugInspector {
+    fn row(&mut self, ui: &mut egui::Ui, highlight: Highlight, text: String) {
+        let selected = self.highlight == Some(highlight);
+        if ui.selectable_label(selected, text).clicked() {
+            self.highlight = (!selected).then_some(highlight);
+        }
+    }
+}
+
+pub struct DebugInspectorPlugin;
+
+impl Plugin for DebugInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugInspector>();
+        app.add_systems(Update, (toggle, ui, draw_highlight).chain());
+    }
+}
+
+fn toggle(keyboard: Res<ButtonInput<KeyCode>>, mut inspector: ResMut<DebugInspector>) {
+    if keyboard.just_pressed(TOGGLE_KEY) {
+        inspector.visible = !inspector.visible;
+    }
+}
+
+fn ui(
+    mut contexts: EguiContexts,
+    mut inspector: ResMut<DebugInspector>,
+    terrain: Option<Res<TerrainStateMutex>>,
+    rooms: Query<(Entity, &Room)>,
+    portals: Query<(Entity, &Portal, &Parent)>,
+    connections: Query<(Entity, &PortalConnection)>,
+    brushes: Query<(Entity, &TerrainBrush)>,
+    compositions: Query<&ChunkComposition>,
+) {
+    if !inspector.visible {
+        return;
+    }
+
+    let mut rooms = rooms.iter().collect::<Vec<_>>();
+    rooms.sort_by_key(|(entity, room)| (room.sequence, *entity));
+    let mut connections = connections.iter().collect::<Vec<_>>();
+    connections.sort_by_key(|(entity, connection)| (connection.sequence, *entity));
+    let mut brushes = brushes.iter().collect::<Vec<_>>();
+    brushes.sort_by_key(|(entity, brush)| (brush.sequence(), *entity));
+    let chunks = terrain
+        .map(|terrain| {
+            let terrain = terrain.lock().unwrap();
+            let mut chunks = terrain
+                .chunk_data
+                .values()
+                .map(|(data, entity)| (data.chunk_pos(), data.detail(), *entity))
+                .collect::<Vec<_>>();
+            chunks.sort_by_key(|(pos, ..)| pos.to_array());
+            chunks
+        })
+        .unwrap_or_default();
+
+    let mut visible = inspector.visible;
+    egui::Window::new("Inspector")
+        .open(&mut visible)
+        .default_width(320.0)
+        .show(contexts.ctx_mut(), |ui| {
+            section(ui, "Rooms", rooms.len(), |ui| {
+                for (entity, room) in rooms.iter() {
+                    let text = format!(
+                        "{entity}  seq {}  portals {}  radius {:.1}",
+                        room.sequence,
+                        room.portals.len(),
+                        room.radius
+                    );
+                    inspector.row(ui, Highlight::Entity(*entity), text);
+                }
+            });
+
+            section(ui, "Connections", connections.len(), |ui| {
+                for (entity, connection) in connections.iter() {
+                    let text = format!(
+                        "{entity}  seq {}  {} -> {}",
+                        connection.sequence, connection.from_portal, connection.to_portal
+                    );
+                    inspector.row(ui, Highlight::Entity(*entity), text);
+                }
+            });
+
+            section(ui, "Portals", portals.iter().count(), |ui| {
+                for (entity, portal, parent) in portals.iter() {
+                    let connection = portal
+                        .connection
+                        .map(|connection| connection.to_string())
+                        .unwrap_or_else(|| "none".to_owned());
+                    let text = format!(
+                        "{entity}  {}  room {}  connection {connection}",
+                        portal.direction,
+                        parent.get()
+                    );
+                    inspector.row(ui, Highlight::Entity(entity), text);
+                }
+            });
+
+            section(ui, "Brushes", brushes.len(), |ui| {
+                for (entity, brush) in brushes.iter() {
+                    let kind = match brush {
+                        TerrainBrush::Curve { .. } => "curve",
+                        TerrainBrush::Collider { .. } => "collider",
+                        TerrainBrush::Primitive { .. } => "primitive",
+                        TerrainBrush::Fill { .. } => "fill",
+                        TerrainBrush::Paint { .. } => "paint",
+                    };
+                    let text = format!(
+                        "{entity}  seq {}  {kind}  {}  {}",
+                        brush.sequence(),
+                        brush.operation(),
+                        brush.uuid()
+                    );
+                    inspector.row(ui, Highlight::Entity(*entity), text);
+                }
+            });
+
+            section(ui, "Chunks", chunks.len(), |ui| {
+                for (pos, detail, entity) in chunks.iter() {
+                    let detail = match detail {
+                        TerrainDetail::Standard => "",
+                        TerrainDetail::High => "  high detail",
+                    };
+                    let dominant = compositions
+                        .get(*entity)
+                        .ok()
+                        .and_then(|composition| composition.dominant())
+                        .map(|material| format!("  {material}"))
+                        .unwrap_or_default();
+                    let text = format!("{entity}  {} {} {}{detail}{dominant}", pos.x, pos.y, pos.z);
+                    inspector.row(ui, Highlight::Chunk(*pos), text);
+                }
+            });
+        });
+    inspector.visible = visible;
+}
+
+fn section(ui: &mut egui::Ui, title: &str, count: usize, add_rows: impl FnOnce(&mut egui::Ui)) {
+    egui::CollapsingHeader::new(format!("{title} ({count})"))
+        .id_salt(title)
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt(title)
+                .max_height(SECTION_HEIGHT)
+                .show(ui, add_rows);
+        });
+}
+
+fn draw_highlight(
+    mut gizmos: Gizmos,
+    mut inspector: ResMut<DebugInspector>,
+    transforms: Query<&GlobalTransform>,
+    rooms: Query<&Room>,
+    portals: Query<&Portal>,
+    connections: Query<&PortalConnection>,
+    brushes: Query<&TerrainBrush>,
+) {
+    let Some(highlight) = inspector.highlight else {
+        return;
+    };
+
+    let entity = match highlight {
+        Highlight::Chunk(pos) => {
+            chunk_box(&mut gizmos, pos, pos + IVec3::ONE, HIGHLIGHT_COLOR);
+            return;
+        }
+        Highlight::Entity(entity) => entity,
+    };
+
+    // Brushes aren't necessarily positioned in the world
+    if let Ok(brush) = brushes.get(entity) {
+        let chunks = brush.chunks();
+        chunk_box(&mut gizmos, chunks.min, chunks.max, HIGHLIGHT_COLOR);
+        return;
+    }
+
+    // The entity was despawned since it was selected
+    let Ok(transform) = transforms.get(entity) else {
+        inspector.highlight = None;
+        return;
+    };
+
+    if let Ok(room) = rooms.get(entity) {
+        gizmos.sphere(
+            Isometry3d::from_translation(transform.transform_point(room.center)),
+            room.radius,
+            HIGHLIGHT_COLOR,
+        );
+        for portal in room.portals.iter() {
+            if let Ok(transform) = transforms.get(*portal) {
+                portal_sphere(&mut gizmos, transform, RELATED_COLOR);
+            }
+        }
+    } else if portals.contains(entity) {
+        portal_sphere(&mut gizmos, transform, HIGHLIGHT_COLOR);
+    } else if let Ok(connection) = connections.get(entity) {
+        let from = transforms.get(connection.from_portal);
+        let to = transforms.get(connection.to_portal);
+        if let (Ok(from), Ok(to)) = (from, to) {
+            gizmos.line(from.translation(), to.translation(), HIGHLIGHT_COLOR);
+            portal_sphere(&mut gizmos, from, RELATED_COLOR);
+            portal_sphere(&mut gizmos, to, RELATED_COLOR);
+        }
+    }
+}
+
+fn portal_sphere(gizmos: &mut Gizmos, transform: &GlobalTransform, color: Color) {
+    gizmos.sphere(
+        Isometry3d {
+            translation: transform.translation().into(),
+            rotation: transform.rotation(),
+        },
+        PORTAL_RADIUS,
+        color,
+    );
+}
+
+/// Outlines the chunks from `min` up to, but not including, `max`.
+fn chunk_box(gizmos: &mut Gizmos, min: IVec3, max: IVec3, color: Color) {
+    let min = min.as_vec3() * CHUNK_SIZE_F;
+    let max = max.as_vec3() * CHUNK_SIZE_F;
+    gizmos.cuboid(
+        Transform::from_translation((min + max) / 2.0).with_scale(max - min),
+        color,
+    );
+}
@@ -0,0 +1,142 @@
+use avian3d::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    health::{DamageEvent, DamageKind, Health, HealthPlugin},
+    physics::GameLayer,
+    worldgen::{terrain::DestroyTerrainEvent, voxel::DamageType},
+};
+
+/// Impulses push from slightly below the center, so bodies on the ground are thrown upward.
+const IMPULSE_LIFT: f32 = 0.5;
+
+/// Shared by everything that explodes, so grenades, barrels, and rockets all push bodies, hurt
+/// entities, and carve terrain the same way.
+#[derive(Event, Clone, Debug)]
+pub struct ExplosionEvent {
+    pub position: Vec3,
+    pub radius: f32,
+    /// Impulse applied to bodies at the center.
+    pub impulse: f32,
+    /// Damage dealt to entities at the center.
+    pub damage: f32,
+    /// How impulse and damage fall off toward the edge. 1 is linear, and higher values
+    /// concentrate the explosion near its center.
+    pub falloff: f32,
+    /// Strength of the terrain destruction. Explosions with no force leave the terrain alone.
+    pub terrain_force: f32,
+    pub source: Option<Entity>,
+}
+
+impl ExplosionEvent {
+    pub fn new(position: Vec3, radius: f32) -> Self {
+        Self {
+            position,
+            radius,
+            impulse: 0.0,
+            damage: 0.0,
+            falloff: 1.0,
+            terrain_force: 0.0,
+            source: None,
+        }
+    }
+
+    /// From 1 at the center to 0 at the edge.
+    pub fn strength_at(&self, point: Vec3) -> f32 {
+        let t = (point.distance(self.position) / self.radius).clamp(0.0, 1.0);
+        (1.0 - t).powf(self.falloff)
+    }
+}
+
+pub struct ExplosionPlugin;
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<HealthPlugin>() {
+            app.add_plugins(HealthPlugin);
+        }
+        app.add_event::<ExplosionEvent>();
+        app.add_systems(Update, explode);
+    }
+}
+
+fn explode(
+    mut events: EventReader<ExplosionEvent>,
+    mut damage: EventWriter<DamageEvent>,
+    mut destroy: EventWriter<DestroyTerrainEvent>,
+    spatial_query: SpatialQuery,
+    colliders: Query<&ColliderParent>,
+    mut bodies: Query<(&RigidBody, &GlobalTransform, &mut ExternalImpulse)>,
+    targets: Query<(Entity, &GlobalTransform), With<Health>>,
+) {
+    let walls = SpatialQueryFilter::from_mask(GameLayer::World);
+
+    for event in events.read() {
+        if event.radius <= 0.0 {
+            continue;
+        }
+
+        // Walls between the explosion and a point shield it
+        let exposed = |point: Vec3| {
+            let to_point = point - event.position;
+            let Ok(direction) = Dir3::new(to_point) else {
+                return true;
+            };
+            spatial_query
+                .cast_ray(event.position, direction, to_point.length(), true, &walls)
+                .is_none()
+        };
+
+        if event.impulse > 0.0 {
+            let hit = spatial_query
+                .shape_intersections(
+                    &Collider::sphere(event.radius),
+                    event.position,
+                    Quat::IDENTITY,
+                    &SpatialQueryFilter::default(),
+                )
+                .into_iter()
+                .map(|entity| colliders.get(entity).map_or(entity, |parent| parent.get()))
+                .collect::<HashSet<_>>();
+
+            for body in hit {
+                let Ok((rigid_body, transform, mut impulse)) = bodies.get_mut(body) else {
+                    continue;
+                };
+                let center = transform.translation();
+                if !rigid_body.is_dynamic() || !exposed(center) {
+                    continue;
+                }
+
+                let origin = event.position - Vec3::Y * IMPULSE_LIFT;
+                let direction = (center - origin).normalize_or(Vec3::Y);
+                impulse.apply_impulse(direction * event.impulse * event.strength_at(center));
+            }
+        }
+
+        if event.damage > 0.0 {
+            for (entity, transform) in targets.iter() {
+                let center = transform.translation();
+                if center.distance(event.position) > event.radius || !exposed(center) {
+                    continue;
+                }
+
+                damage.send(DamageEvent {
+                    entity,
+                    amount: event.damage * event.strength_at(center),
+                    kind: DamageKind::Blast,
+                    source: event.source,
+                });
+            }
+        }
+
+        if event.terrain_force > 0.0 {
+            destroy.send(DestroyTerrainEvent {
+                position: event.position,
+                radius: event.radius,
+                force: event.terrain_force,
+                damage: DamageType::Explosive,
+            });
+        }
+    }
+}
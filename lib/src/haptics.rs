@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::event::EventCursor,
+    input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest},
+    prelude::*,
+};
+
+use crate::{
+    input::{InputBindings, InputBindingsPlugin},
+    player::IsPlayer,
+    weapon::{deflect::DeflectEvent, PlayerWeapons, WeaponFiredEvent},
+    worldgen::{terrain::DestroyTerrainEvent, voxel::DamageType},
+};
+
+/// Terrain destruction at least this large is felt as a cave-in, whatever caused it.
+const CAVE_IN_RADIUS: f32 = 12.0;
+/// Explosions and cave-ins can be felt up to this many times their radius away.
+const FALLOFF_RADII: f32 = 6.0;
+/// Deflections further away than this aren't felt.
+const IMPACT_RANGE: f32 = 8.0;
+
+/// Gameplay events that can be felt through the gamepad.
+#[derive(Event, Clone, Copy, Debug)]
+pub enum HapticEvent {
+    WeaponFire,
+    /// Amount is in the range 0..=1, relative to the player's health.
+    Damage(f32),
+    Impact {
+        position: Vec3,
+    },
+    Explosion {
+        position: Vec3,
+        radius: f32,
+    },
+    CaveIn {
+        position: Vec3,
+        radius: f32,
+    },
+}
+
+struct RumblePattern {
+    strong: f32,
+    weak: f32,
+    seconds: f32,
+}
+
+impl HapticEvent {
+    fn pattern(&self) -> RumblePattern {
+        match self {
+            HapticEvent::WeaponFire => RumblePattern {
+                strong: 0.0,
+                weak: 0.6,
+                seconds: 0.08,
+            },
+            HapticEvent::Damage(amount) => RumblePattern {
+                strong: 0.4 + amount.clamp(0.0, 1.0) * 0.6,
+                weak: 0.3,
+                seconds: 0.2,
+            },
+            HapticEvent::Impact { .. } => RumblePattern {
+                strong: 0.0,
+                weak: 0.35,
+                seconds: 0.05,
+            },
+            HapticEvent::Explosion { .. } => RumblePattern {
+                strong: 0.8,
+                weak: 0.5,
+                seconds: 0.35,
+            },
+            HapticEvent::CaveIn { .. } => RumblePattern {
+                strong: 1.0,
+                weak: 0.2,
+                seconds: 1.2,
+            },
+        }
+    }
+
+    /// How strongly the event is felt by a listener at `listener`, from 0 to 1.
+    fn attenuation(&self, listener: Option<Vec3>) -> f32 {
+        let (position, range) = match *self {
+            HapticEvent::WeaponFire | HapticEvent::Damage(_) => return 1.0,
+            HapticEvent::Impact { position } => (position, IMPACT_RANGE),
+            HapticEvent::Explosion { position, radius }
+            | HapticEvent::CaveIn { position, radius } => (position, radius * FALLOFF_RADII),
+        };
+        let Some(listener) = listener else {
+            return 0.0;
+        };
+
+        (1.0 - listener.distance(position) / range).clamp(0.0, 1.0)
+    }
+}
+
+pub struct HapticsPlugin;
+
+impl Plugin for HapticsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<InputBindingsPlugin>() {
+            app.add_plugins(InputBindingsPlugin);
+        }
+        app.add_event::<HapticEvent>();
+        app.add_systems(
+            Update,
+            (
+                forward_terrain_destruction,
+                forward_deflections,
+                forward_weapon_fire,
+                rumble,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn forward_terrain_destruction(
+    mut destruction: EventReader<DestroyTerrainEvent>,
+    mut haptics: EventWriter<HapticEvent>,
+) {
+    for event in destruction.read() {
+        let (position, radius) = (event.position, event.radius);
+        // Smaller destruction is only felt if something exploded, so bullet holes aren't
+        if radius >= CAVE_IN_RADIUS {
+            haptics.send(HapticEvent::CaveIn { position, radius });
+        } else if event.damage == DamageType::Explosive {
+            haptics.send(HapticEvent::Explosion { position, radius });
+        }
+    }
+}
+
+fn forward_deflections(
+    mut deflections: EventReader<DeflectEvent>,
+    mut haptics: EventWriter<HapticEvent>,
+) {
+    for event in deflections.read() {
+        haptics.send(HapticEvent::Impact {
+            position: event.position,
+        });
+    }
+}
+
+/// The event is read with a cursor since the weapon plugin may not be added.
+fn forward_weapon_fire(
+    fired: Option<Res<Events<WeaponFiredEvent>>>,
+    mut cursor: Local<EventCursor<WeaponFiredEvent>>,
+    mut haptics: EventWriter<HapticEvent>,
+    shooters: Query<(), With<PlayerWeapons>>,
+) {
+    let Some(fired) = fired else {
+        return;
+    };
+    for _ in cursor
+        .read(&fired)
+        .filter(|event| shooters.contains(event.shooter))
+    {
+        haptics.send(HapticEvent::WeaponFire);
+    }
+}
+
+fn rumble(
+    bindings: Res<InputBindings>,
+    mut events: EventReader<HapticEvent>,
+    mut requests: EventWriter<GamepadRumbleRequest>,
+    gamepads: Query<Entity, With<Gamepad>>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+) {
+    let settings = &bindings.rumble;
+    if !settings.enabled || gamepads.is_empty() {
+        events.clear();
+        return;
+    }
+
+    let listener = player.map(|player| player.translation());
+
+    for event in events.read() {
+        let scale = event.attenuation(listener) * settings.intensity.max(0.0);
+        if scale <= 0.0 {
+            continue;
+        }
+
+        let pattern = event.pattern();
+        let intensity = GamepadRumbleIntensity {
+            strong_motor: (pattern.strong * scale).min(1.0),
+            weak_motor: (pattern.weak * scale).min(1.0),
+        };
+        for gamepad in gamepads.iter() {
+            requests.send(GamepadRumbleRequest::Add {
+                gamepad,
+                intensity,
+                duration: Duration::from_secs_f32(pattern.seconds),
+            });
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+
+use crate::{
+    haptics::HapticEvent,
+    player::{DespawnPlayerCommand, IsPlayer, PlayerMotion, SpawnPlayerCommand},
+    worldgen::{layout::Spawnpoint, terrain::TerrainStateMutex},
+};
+
+/// Seconds between the player dying and respawning.
+const RESPAWN_DELAY_SECS: f32 = 3.0;
+/// Spawnpoints become the respawn point once the player stands this close to them.
+const SAFE_SPAWNPOINT_RADIUS: f32 = 4.0;
+/// Entities whose origin is this far inside solid terrain, e.g. after a cave-in fills in around
+/// them, are being crushed.
+const CRUSH_DEPTH: f32 = 0.25;
+const CRUSH_DAMAGE_PER_SEC: f32 = 40.0;
+
+/// What caused damage. Unlike [`crate::worldgen::voxel::DamageType`], these apply to entities
+/// rather than terrain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DamageKind {
+    Ballistic,
+    Blast,
+    Fall,
+    Crush,
+}
+
+impl DamageKind {
+    /// Damage dealt a little every frame. It isn't blocked by invulnerability, and doesn't make
+    /// the entity invulnerable, or it would only land a few frames a second.
+    pub fn is_over_time(&self) -> bool {
+        matches!(self, DamageKind::Crush)
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    /// Seconds after taking damage during which no more damage is taken.
+    pub invulnerability_secs: f32,
+    invulnerable_until_secs: f32,
+}
+
+impl Health {
+    pub fn new(max: f32, invulnerability_secs: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            invulnerability_secs,
+            invulnerable_until_secs: 0.0,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    pub fn is_invulnerable(&self, time: &Time) -> bool {
+        time.elapsed_secs() < self.invulnerable_until_secs
+    }
+
+    pub fn fraction(&self) -> f32 {
+        (self.current / self.max).clamp(0.0, 1.0)
+    }
+}
+
+/// Damages the entity when it lands faster than `safe_speed`. Needs [`PlayerMotion`] to track the
+/// landing.
+#[derive(Component, Clone, Debug)]
+pub struct FallDamage {
+    /// In meters per second.
+    pub safe_speed: f32,
+    /// Damage per meter per second over the safe speed.
+    pub damage_per_speed: f32,
+}
+
+impl FallDamage {
+    pub fn new(safe_speed: f32, damage_per_speed: f32) -> Self {
+        Self {
+            safe_speed,
+            damage_per_speed,
+        }
+    }
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct DamageEvent {
+    pub entity: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
+    /// Whatever dealt the damage, if anything did.
+    pub source: Option<Entity>,
+}
+
+/// Sent once when an entity's health runs out.
+#[derive(Event, Clone, Debug)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub kind: DamageKind,
+    pub source: Option<Entity>,
+}
+
+/// Where the player respawns. Set to the last spawnpoint the player stood near while alive and
+/// on the ground. A random spawnpoint is used if there is none.
+#[derive(Resource, Default)]
+pub struct SafeSpawnpoint(pub Option<Vec3>);
+
+/// Counts down to respawning after the player dies.
+#[derive(Resource, Default)]
+struct PendingRespawn(Option<Timer>);
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<DeathEvent>();
+        app.init_resource::<SafeSpawnpoint>();
+        app.init_resource::<PendingRespawn>();
+        app.add_systems(
+            Update,
+            (
+                fall_damage,
+                crush_damage,
+                apply_damage,
+                track_safe_spawnpoint,
+                respawn_player,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn fall_damage(
+    mut damage: EventWriter<DamageEvent>,
+    query: Query<(Entity, &FallDamage, &PlayerMotion)>,
+) {
+    for (entity, fall, motion) in query.iter() {
+        let Some(landing_speed) = motion.landing_speed() else {
+            continue;
+        };
+
+        let excess = landing_speed - fall.safe_speed;
+        if excess > 0.0 {
+            damage.send(DamageEvent {
+                entity,
+                amount: excess * fall.damage_per_speed,
+                kind: DamageKind::Fall,
+                source: None,
+            });
+        }
+    }
+}
+
+fn crush_damage(
+    time: Res<Time>,
+    terrain: Option<Res<TerrainStateMutex>>,
+    mut damage: EventWriter<DamageEvent>,
+    query: Query<(Entity, &GlobalTransform), With<Health>>,
+) {
+    let Some(terrain) = terrain else {
+        return;
+    };
+
+    for (entity, transform) in query.iter() {
+        let embedded = terrain
+            .distance_at(transform.translation())
+            .is_some_and(|distance| distance >= CRUSH_DEPTH);
+        if embedded {
+            damage.send(DamageEvent {
+                entity,
+                amount: CRUSH_DAMAGE_PER_SEC * time.delta_secs(),
+                kind: DamageKind::Crush,
+                source: None,
+            });
+        }
+    }
+}
+
+/// Haptics are read as an optional resource since their plugin may not be added.
+fn apply_damage(
+    time: Res<Time>,
+    mut events: EventReader<DamageEvent>,
+    mut deaths: EventWriter<DeathEvent>,
+    mut haptics: Option<ResMut<Events<HapticEvent>>>,
+    mut query: Query<(&mut Health, Has<IsPlayer>)>,
+) {
+    for event in events.read() {
+        let Ok((mut health, is_player)) = query.get_mut(event.entity) else {
+            continue;
+        };
+        let over_time = event.kind.is_over_time();
+        if health.is_dead() || (!over_time && health.is_invulnerable(&time)) || event.amount <= 0.0
+        {
+            continue;
+        }
+
+        if is_player {
+            if let Some(haptics) = haptics.as_mut() {
+                let taken = event.amount.min(health.current);
+                haptics.send(HapticEvent::Damage(taken / health.max));
+            }
+        }
+
+        health.current -= event.amount;
+        if !over_time {
+            health.invulnerable_until_secs = time.elapsed_secs() + health.invulnerability_secs;
+        }
+
+        if health.is_dead() {
+            deaths.send(DeathEvent {
+                entity: event.entity,
+                kind: event.kind,
+                source: event.source,
+            });
+        }
+    }
+}
+
+/// Run condition that's false while the player is dead, waiting to respawn.
+pub fn player_alive(players: Query<&Health, With<IsPlayer>>) -> bool {
+    players.iter().all(|health| !health.is_dead())
+}
+
+fn track_safe_spawnpoint(
+    mut safe: ResMut<SafeSpawnpoint>,
+    player: Option<Single<(&GlobalTransform, &Health, &PlayerMotion), With<IsPlayer>>>,
+    spawnpoints: Query<&GlobalTransform, With<Spawnpoint>>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+    let (transform, health, motion) = *player;
+    if health.is_dead() || !motion.grounded() {
+        return;
+    }
+
+    let position = transform.translation();
+    if let Some(spawnpoint) = spawnpoints
+        .iter()
+        .map(|spawnpoint| spawnpoint.translation())
+        .find(|spawnpoint| spawnpoint.distance(position) <= SAFE_SPAWNPOINT_RADIUS)
+    {
+        safe.0 = Some(spawnpoint);
+    }
+}
+
+fn respawn_player(
+    mut commands: Commands,
+    time: Res<Time>,
+    safe: Res<SafeSpawnpoint>,
+    mut pending: ResMut<PendingRespawn>,
+    mut deaths: EventReader<DeathEvent>,
+    players: Query<(), With<IsPlayer>>,
+) {
+    if deaths.read().any(|death| players.contains(death.entity)) && pending.0.is_none() {
+        info!("player died, respawning in {RESPAWN_DELAY_SECS}s");
+        pending.0 = Some(Timer::from_seconds(RESPAWN_DELAY_SECS, TimerMode::Once));
+    }
+
+    let Some(timer) = pending.0.as_mut() else {
+        return;
+    };
+    if !timer.tick(time.delta()).finished() {
+        return;
+    }
+
+    pending.0 = None;
+    commands.queue(DespawnPlayerCommand);
+    commands.queue(SpawnPlayerCommand { position: safe.0 });
+}
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+use crate::worldgen::terrain::FrontierProximity;
+
+const VIGNETTE_WIDTH: f32 = 64.0;
+
+/// Darkens the edges of the screen as the player nears the loading frontier.
+#[derive(Component)]
+pub struct FrontierVignette;
+
+/// On-screen overlays for the player.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_frontier_vignette);
+        app.add_systems(
+            Update,
+            update_frontier_vignette.run_if(resource_changed::<FrontierProximity>),
+        );
+    }
+}
+
+fn setup_frontier_vignette(mut commands: Commands) {
+    commands.spawn((
+        FrontierVignette,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            border: UiRect::all(Val::Px(VIGNETTE_WIDTH)),
+            ..default()
+        },
+        BorderColor(Color::NONE),
+        BorderRadius::all(Val::Px(VIGNETTE_WIDTH * 2.0)),
+        PickingBehavior::IGNORE,
+    ));
+}
+
+fn update_frontier_vignette(
+    proximity: Res<FrontierProximity>,
+    mut vignettes: Query<&mut BorderColor, With<FrontierVignette>>,
+) {
+    for mut color in vignettes.iter_mut() {
+        color.0 = Color::srgba(0.0, 0.0, 0.0, proximity.intensity * 0.85);
+    }
+}
@@ -0,0 +1,535 @@
+//! Rebindable controls. Bindings are read from `settings/input.ron` at startup, and written back
+//! whenever they change.
+
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use bevy::{ecs::system::SystemParam, log::warn, prelude::*};
+use serde::{Deserialize, Serialize};
+
+pub const SETTINGS_DIRECTORY: &str = "./settings";
+const BINDINGS_FILE_NAME: &str = "input.ron";
+/// Cancels a pending rebind.
+const CANCEL_REBIND_KEY: KeyCode = KeyCode::Escape;
+
+/// Something the player can do, which any number of buttons can be bound to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InputAction {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Crouch,
+    Slide,
+    Sprint,
+    Fire,
+    /// Secondary fire, like reeling in a grapple.
+    AltFire,
+    Interact,
+    DropWeapon,
+    DeployShield,
+    /// Held to open the weapon wheel.
+    RadialMenu,
+    /// Sends a scanner pulse.
+    Scan,
+    /// Switches to a weapon slot, counting from 0.
+    WeaponSlot(usize),
+    Flashlight,
+    Fullscreen,
+    /// Locks or frees the cursor.
+    GrabCursor,
+    Automap,
+    /// Switches between the automap's views.
+    AutomapView,
+    /// Places the next room of the layout, for debugging.
+    StepLayout,
+}
+
+impl fmt::Display for InputAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputAction::MoveForward => write!(f, "Move forward"),
+            InputAction::MoveBack => write!(f, "Move back"),
+            InputAction::MoveLeft => write!(f, "Move left"),
+            InputAction::MoveRight => write!(f, "Move right"),
+            InputAction::Jump => write!(f, "Jump"),
+            InputAction::Crouch => write!(f, "Crouch"),
+            InputAction::Slide => write!(f, "Slide"),
+            InputAction::Sprint => write!(f, "Sprint"),
+            InputAction::Fire => write!(f, "Fire"),
+            InputAction::AltFire => write!(f, "Alt fire"),
+            InputAction::Interact => write!(f, "Interact"),
+            InputAction::DropWeapon => write!(f, "Drop weapon"),
+            InputAction::DeployShield => write!(f, "Deploy shield"),
+            InputAction::RadialMenu => write!(f, "Weapon wheel"),
+            InputAction::Scan => write!(f, "Scan"),
+            InputAction::WeaponSlot(slot) => write!(f, "Weapon {}", slot + 1),
+            InputAction::Flashlight => write!(f, "Flashlight"),
+            InputAction::Fullscreen => write!(f, "Fullscreen"),
+            InputAction::GrabCursor => write!(f, "Grab cursor"),
+            InputAction::Automap => write!(f, "Map"),
+            InputAction::AutomapView => write!(f, "Map view"),
+            InputAction::StepLayout => write!(f, "Step layout"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Any connected gamepad.
+    Gamepad(GamepadButton),
+}
+
+impl fmt::Display for InputBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputBinding::Key(key) => write!(f, "{key:?}"),
+            InputBinding::Mouse(button) => write!(f, "Mouse {button:?}"),
+            InputBinding::Gamepad(button) => write!(f, "Gamepad {button:?}"),
+        }
+    }
+}
+
+/// How the sticks of every gamepad respond.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct GamepadSettings {
+    /// Stick deflection below this is ignored, from 0 to 1.
+    pub deadzone: f32,
+    /// Deflection past the deadzone is raised to this power. Above 1 gives finer control near the
+    /// center of the stick.
+    pub response_exponent: f32,
+    /// Camera turn speed at full deflection of the right stick, in radians per second.
+    pub look_sensitivity: f32,
+    pub invert_look_y: bool,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.15,
+            response_exponent: 2.0,
+            look_sensitivity: 3.0,
+            invert_look_y: false,
+        }
+    }
+}
+
+/// Gamepad rumble, see [`crate::haptics`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct RumbleSettings {
+    pub enabled: bool,
+    /// Multiplier applied to every rumble.
+    pub intensity: f32,
+}
+
+impl Default for RumbleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// Rescales a stick so deflection starts from zero at the edge of the deadzone, then applies the
+/// response curve. The length of the result is at most 1.
+pub fn shape_stick(stick: Vec2, deadzone: f32, response_exponent: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= deadzone || deadzone >= 1.0 {
+        return Vec2::ZERO;
+    }
+
+    let deflection = ((length - deadzone) / (1.0 - deadzone)).clamp(0.0, 1.0);
+    stick / length * deflection.powf(response_exponent)
+}
+
+/// Buttons bound to each action. Actions that aren't listed have no bindings.
+#[derive(Serialize, Deserialize, Resource, Clone, Debug)]
+pub struct InputBindings {
+    pub bindings: BTreeMap<InputAction, Vec<InputBinding>>,
+    #[serde(default)]
+    pub gamepad: GamepadSettings,
+    #[serde(default)]
+    pub rumble: RumbleSettings,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use InputBinding::{Gamepad as Pad, Key, Mouse};
+
+        let mut bindings = BTreeMap::from([
+            (
+                InputAction::MoveForward,
+                vec![
+                    Key(KeyCode::KeyW),
+                    Key(KeyCode::ArrowUp),
+                    Pad(GamepadButton::DPadUp),
+                ],
+            ),
+            (
+                InputAction::MoveBack,
+                vec![
+                    Key(KeyCode::KeyS),
+                    Key(KeyCode::ArrowDown),
+                    Pad(GamepadButton::DPadDown),
+                ],
+            ),
+            (
+                InputAction::MoveLeft,
+                vec![
+                    Key(KeyCode::KeyA),
+                    Key(KeyCode::ArrowLeft),
+                    Pad(GamepadButton::DPadLeft),
+                ],
+            ),
+            (
+                InputAction::MoveRight,
+                vec![
+                    Key(KeyCode::KeyD),
+                    Key(KeyCode::ArrowRight),
+                    Pad(GamepadButton::DPadRight),
+                ],
+            ),
+            (
+                InputAction::Jump,
+                vec![Key(KeyCode::Space), Pad(GamepadButton::South)],
+            ),
+            (
+                InputAction::Crouch,
+                vec![
+                    Key(KeyCode::ControlLeft),
+                    Key(KeyCode::ControlRight),
+                    Pad(GamepadButton::East),
+                ],
+            ),
+            (
+                InputAction::Slide,
+                vec![Key(KeyCode::KeyC), Pad(GamepadButton::RightThumb)],
+            ),
+            (
+                InputAction::Sprint,
+                vec![
+                    Key(KeyCode::ShiftLeft),
+                    Key(KeyCode::ShiftRight),
+                    Pad(GamepadButton::LeftThumb),
+                ],
+            ),
+            (
+                InputAction::Fire,
+                vec![Mouse(MouseButton::Left), Pad(GamepadButton::RightTrigger2)],
+            ),
+            (
+                InputAction::AltFire,
+                vec![Mouse(MouseButton::Right), Pad(GamepadButton::LeftTrigger2)],
+            ),
+            (
+                InputAction::Interact,
+                vec![Key(KeyCode::KeyE), Pad(GamepadButton::West)],
+            ),
+            (
+                InputAction::DropWeapon,
+                vec![Key(KeyCode::KeyG), Pad(GamepadButton::North)],
+            ),
+            (
+                InputAction::DeployShield,
+                vec![Key(KeyCode::KeyV), Pad(GamepadButton::RightTrigger)],
+            ),
+            (
+                InputAction::RadialMenu,
+                vec![Key(KeyCode::KeyQ), Pad(GamepadButton::LeftTrigger)],
+            ),
+            (
+                InputAction::Scan,
+                vec![Key(KeyCode::KeyR), Pad(GamepadButton::Select)],
+            ),
+            (InputAction::Flashlight, vec![Key(KeyCode::KeyL)]),
+            (InputAction::Fullscreen, vec![Key(KeyCode::KeyF)]),
+            (InputAction::GrabCursor, vec![Key(KeyCode::KeyT)]),
+            (InputAction::Automap, vec![Key(KeyCode::KeyM)]),
+            (InputAction::AutomapView, vec![Key(KeyCode::KeyB)]),
+            (InputAction::StepLayout, vec![Key(KeyCode::KeyN)]),
+        ]);
+
+        let digits = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+        ];
+        for (slot, digit) in digits.into_iter().enumerate() {
+            bindings.insert(InputAction::WeaponSlot(slot), vec![Key(digit)]);
+        }
+
+        Self {
+            bindings,
+            gamepad: default(),
+            rumble: default(),
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn read(directory: &Path) -> Self {
+        let Ok(s) = std::fs::read_to_string(directory.join(BINDINGS_FILE_NAME)) else {
+            return Self::default();
+        };
+
+        ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("failed to parse input bindings: {err}");
+            Self::default()
+        })
+    }
+
+    pub fn write(&self, directory: &Path) -> anyhow::Result<()> {
+        let s = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::create_dir_all(directory)?;
+        std::fs::write(directory.join(BINDINGS_FILE_NAME), s)?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, action: InputAction) -> &[InputBinding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Actions the binding is bound to.
+    pub fn actions_for(&self, binding: InputBinding) -> impl Iterator<Item = InputAction> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+
+    /// Adds a binding to an action, keeping its other bindings. Returns the actions the binding
+    /// was taken from, since a button can only do one thing.
+    pub fn bind(&mut self, action: InputAction, binding: InputBinding) -> Vec<InputAction> {
+        let taken_from = self.unbind(binding);
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+
+        taken_from
+            .into_iter()
+            .filter(|other| *other != action)
+            .collect()
+    }
+
+    /// Replaces every binding of an action with this one. Returns the actions the binding was
+    /// taken from.
+    pub fn rebind(&mut self, action: InputAction, binding: InputBinding) -> Vec<InputAction> {
+        self.clear(action);
+        self.bind(action, binding)
+    }
+
+    /// Removes a binding from every action. Returns the actions it was bound to.
+    pub fn unbind(&mut self, binding: InputBinding) -> Vec<InputAction> {
+        let mut removed_from = Vec::<InputAction>::new();
+        for (action, bindings) in self.bindings.iter_mut() {
+            let len = bindings.len();
+            bindings.retain(|other| *other != binding);
+            if bindings.len() != len {
+                removed_from.push(*action);
+            }
+        }
+        removed_from
+    }
+
+    pub fn clear(&mut self, action: InputAction) {
+        self.bindings.remove(&action);
+    }
+
+    /// Restores the default bindings of an action. Defaults that are now bound to something else
+    /// are taken back.
+    pub fn reset(&mut self, action: InputAction) {
+        self.clear(action);
+        for binding in Self::default().get(action) {
+            self.bind(action, *binding);
+        }
+    }
+}
+
+/// Set to an action to bind the next button pressed to it, e.g. from a settings menu. Actions
+/// don't fire while waiting.
+#[derive(Resource, Default, Debug)]
+pub struct PendingRebind(pub Option<InputAction>);
+
+/// Reads actions through the current bindings.
+#[derive(SystemParam)]
+pub struct ActionInput<'w, 's> {
+    bindings: Res<'w, InputBindings>,
+    pending: Res<'w, PendingRebind>,
+    keyboard: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl ActionInput<'_, '_> {
+    fn any(&self, action: InputAction, check: impl Fn(&Self, InputBinding) -> bool) -> bool {
+        self.pending.0.is_none()
+            && self
+                .bindings
+                .get(action)
+                .iter()
+                .any(|binding| check(self, *binding))
+    }
+
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.any(action, |input, binding| match binding {
+            InputBinding::Key(key) => input.keyboard.pressed(key),
+            InputBinding::Mouse(button) => input.mouse.pressed(button),
+            InputBinding::Gamepad(button) => input.gamepads.iter().any(|pad| pad.pressed(button)),
+        })
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.any(action, |input, binding| match binding {
+            InputBinding::Key(key) => input.keyboard.just_pressed(key),
+            InputBinding::Mouse(button) => input.mouse.just_pressed(button),
+            InputBinding::Gamepad(button) => {
+                input.gamepads.iter().any(|pad| pad.just_pressed(button))
+            }
+        })
+    }
+
+    pub fn just_released(&self, action: InputAction) -> bool {
+        self.any(action, |input, binding| match binding {
+            InputBinding::Key(key) => input.keyboard.just_released(key),
+            InputBinding::Mouse(button) => input.mouse.just_released(button),
+            InputBinding::Gamepad(button) => {
+                input.gamepads.iter().any(|pad| pad.just_released(button))
+            }
+        })
+    }
+
+    /// Movement from the move actions and the left stick, with x to the right and y backward.
+    /// The length is at most 1.
+    pub fn movement(&self) -> Vec2 {
+        let mut direction = Vec2::ZERO;
+        if self.pressed(InputAction::MoveForward) {
+            direction.y -= 1.0;
+        }
+        if self.pressed(InputAction::MoveBack) {
+            direction.y += 1.0;
+        }
+        if self.pressed(InputAction::MoveLeft) {
+            direction.x -= 1.0;
+        }
+        if self.pressed(InputAction::MoveRight) {
+            direction.x += 1.0;
+        }
+
+        // Stick y points up
+        direction += self.stick(Gamepad::left_stick) * Vec2::new(1.0, -1.0);
+        direction.clamp_length_max(1.0)
+    }
+
+    /// How fast the right stick turns the camera, in radians per second. x turns right and y
+    /// looks up.
+    pub fn look(&self) -> Vec2 {
+        let settings = &self.bindings.gamepad;
+        let invert = if settings.invert_look_y { -1.0 } else { 1.0 };
+        self.stick(Gamepad::right_stick) * Vec2::new(1.0, invert) * settings.look_sensitivity
+    }
+
+    /// The most deflected stick of any gamepad, shaped by the gamepad settings.
+    fn stick(&self, stick: fn(&Gamepad) -> Vec2) -> Vec2 {
+        if self.pending.0.is_some() {
+            return Vec2::ZERO;
+        }
+
+        let settings = &self.bindings.gamepad;
+        self.gamepads
+            .iter()
+            .map(|pad| shape_stick(stick(pad), settings.deadzone, settings.response_exponent))
+            .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            .unwrap_or(Vec2::ZERO)
+    }
+}
+
+pub struct InputBindingsPlugin;
+
+impl Plugin for InputBindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputBindings::read(Path::new(SETTINGS_DIRECTORY)));
+        app.init_resource::<PendingRebind>();
+        app.add_systems(PreUpdate, capture_rebind.after(bevy::input::InputSystem));
+        app.add_systems(Last, save_bindings);
+    }
+}
+
+/// Binds the next button pressed to the pending action. The press is consumed, so it doesn't also
+/// trigger whatever it was bound to before.
+fn capture_rebind(
+    mut pending: ResMut<PendingRebind>,
+    mut bindings: ResMut<InputBindings>,
+    mut keyboard: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+    mut gamepads: Query<&mut Gamepad>,
+) {
+    let Some(action) = pending.0 else {
+        return;
+    };
+    if keyboard.clear_just_pressed(CANCEL_REBIND_KEY) {
+        pending.0 = None;
+        return;
+    }
+
+    let binding = keyboard
+        .get_just_pressed()
+        .next()
+        .map(|key| InputBinding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|button| InputBinding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepads
+                .iter()
+                .find_map(|pad| pad.get_just_pressed().next().copied())
+                .map(InputBinding::Gamepad)
+        });
+    let Some(binding) = binding else {
+        return;
+    };
+
+    match binding {
+        InputBinding::Key(key) => {
+            keyboard.clear_just_pressed(key);
+        }
+        InputBinding::Mouse(button) => {
+            mouse.clear_just_pressed(button);
+        }
+        InputBinding::Gamepad(button) => {
+            for mut pad in &mut gamepads {
+                pad.digital_mut().clear_just_pressed(button);
+            }
+        }
+    }
+
+    for other in bindings.rebind(action, binding) {
+        info!("unbound {binding} from {other}");
+    }
+    pending.0 = None;
+}
+
+fn save_bindings(bindings: Res<InputBindings>) {
+    if !bindings.is_changed() || bindings.is_added() {
+        return;
+    }
+
+    if let Err(err) = bindings.write(Path::new(SETTINGS_DIRECTORY)) {
+        warn!("failed to write input bindings: {err}");
+    }
+}
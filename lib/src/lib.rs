@@ -1,11 +1,25 @@
+pub mod audio;
+pub mod automap;
 pub mod cable;
+pub mod challenge;
 pub mod debug_camera;
+pub mod explosion;
+pub mod haptics;
+pub mod health;
+pub mod hud;
+pub mod input;
 pub mod materials;
 pub mod meshgen;
 pub mod physics;
 pub mod player;
+pub mod plugins;
+pub mod prelude;
 pub mod render_layer;
+pub mod replay;
+pub mod save;
 pub mod weapon;
 pub mod worldgen;
 
-pub mod debug_aim;
+pub mod debug_console;
+pub mod debug_hud;
+pub mod debug_inspector;
@@ -8,26 +8,97 @@ use bevy::{
     },
 };
 
-/// Specifies what type of voxel the vertex belongs to. The last u8 is
-/// not used for anything.
-pub const ATTRIBUTE_VOXEL_TYPE: MeshVertexAttribute =
-    MeshVertexAttribute::new("Vertex_VoxelType", 989717230, VertexFormat::Uint8x4);
+use crate::worldgen::voxel::VoxelMaterial;
+
+/// Weights of the natural voxel types, 0 to 3, at the vertex. Each vertex has a single voxel
+/// type, so interpolating the weights blends the types across the triangle.
+pub const ATTRIBUTE_VOXEL_WEIGHTS: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_VoxelWeights", 989717230, VertexFormat::Unorm8x4);
+
+/// Weights of the special voxel types, from [`FIRST_SPECIAL_VOXEL_TYPE`] to 255, at the vertex.
+pub const ATTRIBUTE_SPECIAL_VOXEL_WEIGHTS: MeshVertexAttribute = MeshVertexAttribute::new(
+    "Vertex_SpecialVoxelWeights",
+    989717231,
+    VertexFormat::Unorm8x4,
+);
+
+/// Lowest voxel type weighted by [`ATTRIBUTE_SPECIAL_VOXEL_WEIGHTS`].
+pub const FIRST_SPECIAL_VOXEL_TYPE: u8 = 252;
+
+/// Whether the cave shader has a look for this voxel type. Other types are drawn as invalid.
+pub fn is_drawn_voxel_type(voxel_type: u8) -> bool {
+    voxel_type < 4 || voxel_type >= FIRST_SPECIAL_VOXEL_TYPE
+}
 
-pub const ATTRIBUTE_VOXEL_RATIO: MeshVertexAttribute =
-    MeshVertexAttribute::new("Vertex_VoxelRatio", 989717231, VertexFormat::Float32x3);
+/// Ambient occlusion baked from the SDF, from 0.0 (fully occluded) to 1.0 (unoccluded).
+pub const ATTRIBUTE_VOXEL_OCCLUSION: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_VoxelOcclusion", 989717232, VertexFormat::Float32);
 
 const SHADER_VERTEX_PATH: &str = "shaders/CaveMaterialExtension/vertex.wgsl";
 const SHADER_FRAGMENT_PATH: &str = "shaders/CaveMaterialExtension/fragment.wgsl";
 
 pub type CaveMaterial = ExtendedMaterial<StandardMaterial, CaveMaterialExtension>;
 
+/// How much work the cave shader does per fragment. Insert it as a resource to change the quality
+/// of every cave material at runtime.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CaveMaterialQuality {
+    /// Samples each voxel type once at the fragment's position. Cheap enough for WebGL2.
+    Fast,
+    /// Samples each voxel type on three planes, blended by the slope of the surface, and bumps the
+    /// normal by the brightness of the result.
+    High,
+}
+
+impl Default for CaveMaterialQuality {
+    fn default() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            Self::Fast
+        } else {
+            Self::High
+        }
+    }
+}
+
 #[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+#[bind_group_data(CaveMaterialKey)]
 pub struct CaveMaterialExtension {
     #[uniform(100)]
     pub render_voxel_size: f32,
 
     #[uniform(100)]
     pub voxel_type_transition_steps: f32,
+
+    /// Distance the prospecting scanner pulse has traveled.
+    #[uniform(100)]
+    pub scan_radius: f32,
+
+    /// Brightness of the scanner highlights, from 0.0 (hidden) to 1.0.
+    #[uniform(100)]
+    pub scan_intensity: f32,
+
+    #[uniform(100)]
+    pub scan_origin: Vec3,
+
+    /// Bitmask of the voxel types highlighted by the scanner.
+    #[uniform(100)]
+    pub scan_materials: u32,
+
+    /// Changing it specializes a new pipeline, so it shouldn't change often.
+    pub quality: CaveMaterialQuality,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CaveMaterialKey {
+    quality: CaveMaterialQuality,
+}
+
+impl From<&CaveMaterialExtension> for CaveMaterialKey {
+    fn from(material: &CaveMaterialExtension) -> Self {
+        Self {
+            quality: material.quality,
+        }
+    }
 }
 
 impl CaveMaterialExtension {
@@ -35,10 +106,30 @@ impl CaveMaterialExtension {
         Self {
             render_voxel_size,
             voxel_type_transition_steps,
+            scan_radius: 0.0,
+            scan_intensity: 0.0,
+            scan_origin: Vec3::ZERO,
+            scan_materials: 0,
+            quality: default(),
         }
     }
 }
 
+/// Values of [`ATTRIBUTE_VOXEL_WEIGHTS`] and [`ATTRIBUTE_SPECIAL_VOXEL_WEIGHTS`] for a vertex of
+/// this voxel type. Types without a weight are weighted as invalid.
+pub fn voxel_weights(voxel_type: u8) -> ([u8; 4], [u8; 4]) {
+    let (mut natural, mut special) = ([0; 4], [0; 4]);
+    match voxel_type {
+        0..=3 => natural[voxel_type as usize] = u8::MAX,
+        FIRST_SPECIAL_VOXEL_TYPE.. => {
+            special[(voxel_type - FIRST_SPECIAL_VOXEL_TYPE) as usize] = u8::MAX
+        }
+        _ => special[(VoxelMaterial::Invalid.0 - FIRST_SPECIAL_VOXEL_TYPE) as usize] = u8::MAX,
+    }
+
+    (natural, special)
+}
+
 impl MaterialExtension for CaveMaterialExtension {
     fn vertex_shader() -> ShaderRef {
         SHADER_VERTEX_PATH.into()
@@ -52,8 +143,14 @@ impl MaterialExtension for CaveMaterialExtension {
         _pipeline: &MaterialExtensionPipeline,
         descriptor: &mut RenderPipelineDescriptor,
         layout: &MeshVertexBufferLayoutRef,
-        _key: MaterialExtensionKey<Self>,
+        key: MaterialExtensionKey<Self>,
     ) -> Result<(), SpecializedMeshPipelineError> {
+        if key.bind_group_data.quality == CaveMaterialQuality::High {
+            if let Some(fragment) = &mut descriptor.fragment {
+                fragment.shader_defs.push("CAVE_TRIPLANAR".into());
+            }
+        }
+
         let prepass = Some("pbr_prepass_pipeline") == descriptor.label.as_deref();
         let mut attrs = Vec::<VertexAttributeDescriptor>::new();
 
@@ -66,8 +163,9 @@ impl MaterialExtension for CaveMaterialExtension {
         push_if_present(&mut attrs, layout, prepass, Mesh::ATTRIBUTE_JOINT_WEIGHT);
         push_if_present(&mut attrs, layout, prepass, Mesh::ATTRIBUTE_COLOR);
 
-        attrs.push(ATTRIBUTE_VOXEL_TYPE.at_shader_location(8));
-        attrs.push(ATTRIBUTE_VOXEL_RATIO.at_shader_location(9));
+        attrs.push(ATTRIBUTE_VOXEL_WEIGHTS.at_shader_location(8));
+        attrs.push(ATTRIBUTE_SPECIAL_VOXEL_WEIGHTS.at_shader_location(9));
+        attrs.push(ATTRIBUTE_VOXEL_OCCLUSION.at_shader_location(10));
 
         let vertex_layout = layout.0.get_layout(&attrs)?;
         descriptor.vertex.buffers = vec![vertex_layout];
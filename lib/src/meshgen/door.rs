@@ -1,20 +1,22 @@
-use std::{f32::consts::PI, mem::take};
+use std::f32::consts::PI;
 
 use avian3d::prelude::*;
-use bevy::{
-    asset::RenderAssetUsages,
-    ecs::system::SystemState,
-    prelude::*,
-    render::mesh::{Indices, PrimitiveTopology},
-};
+use bevy::{ecs::system::SystemState, prelude::*};
+
+use serde::{Deserialize, Serialize};
 
 use crate::player::IsPlayer;
 
+use super::{
+    util::{finish_mesh, MeshParts},
+    Switch, SwitchChannel, SwitchEvent, SwitchKind,
+};
+
 const DOOR_MAX_ANGLE: f32 = 90.0 * PI / 180.0;
 const DOOR_ANIMATION_SECS: f64 = 2.5;
 const DOOR_AUTOCLOSE_SECS: f64 = 4.0;
 
-#[derive(Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct DoorwaySpec {
     pub frame: Rect,
     pub frame_depth: f32,
@@ -24,6 +26,28 @@ pub struct DoorwaySpec {
     pub door_uv_scale: f32,
 }
 
+impl Default for DoorwaySpec {
+    fn default() -> Self {
+        let (frame_width, frame_height) = (6.0, 4.0);
+        let (door_width, door_height) = (2.75, 2.25);
+
+        Self {
+            frame: Rect {
+                min: Vec2::new(-frame_width / 2.0, 0.0),
+                max: Vec2::new(frame_width / 2.0, frame_height),
+            },
+            frame_depth: 0.4,
+            frame_uv_scale: 4.0,
+            door: Rect {
+                min: Vec2::new(-door_width / 2.0, 0.0),
+                max: Vec2::new(door_width / 2.0, door_height),
+            },
+            door_depth: 0.075,
+            door_uv_scale: 4.0,
+        }
+    }
+}
+
 pub struct DoorMeshes {
     pub frame_mesh: Mesh,
     pub door_meshes: [(Mesh, Vec3); 2],
@@ -38,6 +62,10 @@ pub struct Doorway {
     animating: bool,
     doors: [Entity; 2], // [left, right]
     sfx_position: Vec3,
+    /// Wired doors only open from switches.
+    channel: Option<SwitchChannel>,
+    /// Held open by a switch, so it doesn't close on its own.
+    held: bool,
 }
 
 impl Doorway {
@@ -155,6 +183,58 @@ pub fn open_doors_on_contact(
     }
 }
 
+pub fn open_doors_on_switch(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut switch_events: EventReader<SwitchEvent>,
+    mut doorways: Query<(Entity, &GlobalTransform, &mut Doorway)>,
+    switches: Query<&Switch>,
+    door_sfx: Res<DoorSfx>,
+) {
+    let mut pressed = Vec::<Entity>::new();
+    for event in switch_events.read() {
+        for (entity, doorway_transform, mut doorway) in doorways.iter_mut() {
+            if doorway.channel != Some(event.channel) {
+                continue;
+            }
+
+            match (event.kind, event.pressed) {
+                (SwitchKind::HoldPlate, true) => doorway.held = true,
+                (SwitchKind::HoldPlate, false) => {
+                    // Doors stay held while any plate on the channel is
+                    doorway.held = switches.iter().any(|switch| switch.holds(event.channel));
+                    if !doorway.held && doorway.close(&time) {
+                        commands.spawn((
+                            Transform::from_translation(
+                                doorway_transform.translation() + doorway.sfx_position,
+                            ),
+                            AudioPlayer::new(door_sfx.close_start.clone()),
+                            PlaybackSettings::DESPAWN.with_spatial(true),
+                        ));
+                    }
+                }
+                (_, true) => pressed.push(entity),
+                (_, false) => {}
+            }
+        }
+    }
+
+    // Held doors keep trying to open, since a door can't reopen until it finishes closing
+    for (entity, doorway_transform, mut doorway) in doorways.iter_mut() {
+        if !doorway.held && !pressed.contains(&entity) {
+            continue;
+        }
+
+        if doorway.open(true, &time) {
+            commands.spawn((
+                Transform::from_translation(doorway_transform.translation() + doorway.sfx_position),
+                AudioPlayer::new(door_sfx.open.clone()),
+                PlaybackSettings::DESPAWN.with_spatial(true),
+            ));
+        }
+    }
+}
+
 pub fn animate_doors(
     mut commands: Commands,
     door_sfx: Res<DoorSfx>,
@@ -172,7 +252,7 @@ pub fn animate_doors(
 
             let mut elapsed = time.elapsed_secs_f64() - doorway.animation_start_secs;
 
-            if doorway.open && elapsed >= DOOR_AUTOCLOSE_SECS {
+            if doorway.open && !doorway.held && elapsed >= DOOR_AUTOCLOSE_SECS {
                 doorway.close(&time);
                 elapsed = 0.0;
 
@@ -222,6 +302,8 @@ pub fn animate_doors(
 pub struct AddDoorwayToEntity {
     pub spec: DoorwaySpec,
     pub entity: Entity,
+    /// Wires the doorway to switches on this channel instead of opening on contact.
+    pub channel: Option<SwitchChannel>,
 }
 
 impl Command for AddDoorwayToEntity {
@@ -288,7 +370,7 @@ impl Command for AddDoorwayToEntity {
         let doorway_entity = {
             let mut doorway_entity = commands.spawn((
                 Doorway {
-                    locked: false,
+                    locked: self.channel.is_some(),
                     open: false,
                     open_inward: false,
                     animation_start_secs: -DOOR_ANIMATION_SECS,
@@ -299,6 +381,8 @@ impl Command for AddDoorwayToEntity {
                         self.spec.door.center().y,
                         0.0,
                     ),
+                    channel: self.channel,
+                    held: false,
                 },
                 Transform::default(),
                 RigidBody::Static,
@@ -430,16 +514,6 @@ pub fn generate_door_triggers(door: DoorwaySpec) -> [(Collider, bool); 2] {
     ]
 }
 
-#[derive(Default)]
-struct MeshParts {
-    pub positions: Vec<[f32; 3]>,
-    pub normals: Vec<[f32; 3]>,
-    pub colors: Vec<[f32; 4]>,
-    pub uvs: Vec<[f32; 2]>,
-    pub indices: Vec<u16>,
-    pub curr_idx: u16,
-}
-
 pub fn generate_door_meshes(
     DoorwaySpec {
         frame,
@@ -447,11 +521,10 @@ pub fn generate_door_meshes(
         frame_depth,
         door_depth,
         frame_uv_scale,
-        ..
+        door_uv_scale,
     }: DoorwaySpec,
 ) -> DoorMeshes {
     let mut mesh_parts = MeshParts::default();
-    let door_uv_scale = door.max.x - door.min.x; // TEMP (?)
 
     // Wall
     fill_rect_difference(
@@ -557,19 +630,6 @@ pub fn generate_door_meshes(
 // Utility
 //
 
-fn finish_mesh(mesh_parts: &mut MeshParts) -> Mesh {
-    mesh_parts.curr_idx = 0;
-    Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::RENDER_WORLD,
-    )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, take(&mut mesh_parts.positions))
-    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, take(&mut mesh_parts.normals))
-    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, take(&mut mesh_parts.colors))
-    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, take(&mut mesh_parts.uvs))
-    .with_inserted_indices(Indices::U16(take(&mut mesh_parts.indices)))
-}
-
 fn vert(
     position: [f32; 3],
     normal: Vec3,
@@ -1,13 +1,24 @@
 use bevy::prelude::*;
 
 mod door;
+mod switch;
+mod util;
 pub use door::*; //TEMP
+pub use switch::*;
 
 pub struct MeshGenerationPlugin;
 
 impl Plugin for MeshGenerationPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<SwitchEvent>();
         app.add_systems(Startup, door::init_resources);
-        app.add_systems(Update, (door::open_doors_on_contact, door::animate_doors));
+        app.add_systems(
+            Update,
+            (
+                door::open_doors_on_contact,
+                (switch::update_switches, door::open_doors_on_switch).chain(),
+                door::animate_doors,
+            ),
+        );
     }
 }
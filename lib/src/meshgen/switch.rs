@@ -0,0 +1,206 @@
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemState, prelude::*};
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+use crate::player::IsPlayer;
+
+const BUTTON_SIZE: Vec3 = Vec3::new(0.3, 0.3, 0.1);
+/// How far in front of a button the player has to be to press it.
+const BUTTON_REACH: f32 = 0.5;
+const PLATE_SIZE: Vec3 = Vec3::new(1.5, 0.1, 1.5);
+/// Height above a pressure plate that counts as resting on it.
+const PLATE_REACH: f32 = 0.3;
+/// How far a switch sinks while pressed.
+const SWITCH_TRAVEL: f32 = 0.05;
+
+#[repr(u8)]
+#[derive(
+    EnumIter,
+    strum::Display,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+)]
+pub enum SwitchKind {
+    /// Opens wired doors when the player walks into it.
+    #[default]
+    Button = 0,
+    /// Opens wired doors when the player or an object lands on it.
+    PressurePlate = 1,
+    /// Holds wired doors open only while something rests on it, so opening them takes a placed
+    /// object or a second player.
+    HoldPlate = 2,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwitchSpec {
+    pub kind: SwitchKind,
+    /// Doors with the same channel are controlled by this switch.
+    pub channel: u8,
+}
+
+/// Connects switches to doors. Channels are only shared within a scope, usually the room both
+/// were spawned in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SwitchChannel {
+    pub scope: Entity,
+    pub channel: u8,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SwitchEvent {
+    pub channel: SwitchChannel,
+    pub kind: SwitchKind,
+    pub pressed: bool,
+}
+
+#[derive(Component)]
+pub struct Switch {
+    kind: SwitchKind,
+    channel: SwitchChannel,
+    pressed: bool,
+    cap: Entity,
+}
+
+impl Switch {
+    /// True if this is a hold plate on `channel` with something resting on it.
+    pub fn holds(&self, channel: SwitchChannel) -> bool {
+        self.kind == SwitchKind::HoldPlate && self.channel == channel && self.pressed
+    }
+}
+
+/// The visible, moving part of a switch.
+#[derive(Component)]
+pub struct SwitchCap;
+
+pub fn update_switches(
+    mut switches: Query<(&mut Switch, &CollidingEntities)>,
+    mut caps: Query<&mut Transform, With<SwitchCap>>,
+    bodies: Query<&RigidBody>,
+    player: Query<(), With<IsPlayer>>,
+    mut switch_events: EventWriter<SwitchEvent>,
+) {
+    for (mut switch, colliding) in switches.iter_mut() {
+        let pressed = colliding.iter().any(|entity| {
+            if player.contains(*entity) {
+                return true;
+            }
+            // Buttons can't be pressed by objects
+            switch.kind != SwitchKind::Button
+                && bodies.get(*entity).is_ok_and(|body| body.is_dynamic())
+        });
+        if pressed == switch.pressed {
+            continue;
+        }
+        switch.pressed = pressed;
+
+        if let Ok(mut cap) = caps.get_mut(switch.cap) {
+            cap.translation = cap_translation(switch.kind, pressed);
+        }
+
+        // Only hold plates report being released
+        if pressed || switch.kind == SwitchKind::HoldPlate {
+            switch_events.send(SwitchEvent {
+                channel: switch.channel,
+                kind: switch.kind,
+                pressed,
+            });
+        }
+    }
+}
+
+pub struct AddSwitchToEntity {
+    pub spec: SwitchSpec,
+    pub entity: Entity,
+    pub scope: Entity,
+}
+
+impl Command for AddSwitchToEntity {
+    fn apply(self, world: &mut World) {
+        let mut system_state: SystemState<(
+            Commands,
+            ResMut<Assets<Mesh>>,
+            ResMut<Assets<StandardMaterial>>,
+        )> = SystemState::new(world);
+        let (mut commands, mut meshes, mut materials) = system_state.get_mut(world);
+
+        let material = materials.add(StandardMaterial {
+            base_color: match self.spec.kind {
+                SwitchKind::Button => Color::srgb(0.6, 0.1, 0.1),
+                SwitchKind::PressurePlate => Color::srgb(0.4, 0.4, 0.35),
+                SwitchKind::HoldPlate => Color::srgb(0.5, 0.35, 0.1),
+            },
+            perceptual_roughness: 0.6,
+            ..default()
+        });
+
+        let cap = commands
+            .spawn((
+                SwitchCap,
+                Transform::from_translation(cap_translation(self.spec.kind, false)),
+                Mesh3d(meshes.add(Cuboid::from_size(switch_size(self.spec.kind)))),
+                MeshMaterial3d(material),
+            ))
+            .id();
+
+        let (sensor_size, sensor_offset) = match self.spec.kind {
+            SwitchKind::Button => (
+                BUTTON_SIZE.with_z(BUTTON_REACH),
+                Vec3::Z * BUTTON_REACH / 2.0,
+            ),
+            SwitchKind::PressurePlate | SwitchKind::HoldPlate => {
+                (PLATE_SIZE.with_y(PLATE_REACH), Vec3::Y * PLATE_REACH / 2.0)
+            }
+        };
+        let switch = commands
+            .spawn((
+                Switch {
+                    kind: self.spec.kind,
+                    channel: SwitchChannel {
+                        scope: self.scope,
+                        channel: self.spec.channel,
+                    },
+                    pressed: false,
+                    cap,
+                },
+                Transform::default(),
+                Collider::compound(vec![(
+                    sensor_offset,
+                    Rotation::default(),
+                    Collider::cuboid(sensor_size.x, sensor_size.y, sensor_size.z),
+                )]),
+                Sensor,
+                CollidingEntities::default(),
+                DebugRender::default().with_collider_color(Color::srgb(0.9, 0.6, 0.1)),
+            ))
+            .add_child(cap)
+            .id();
+        commands.entity(self.entity).add_child(switch);
+
+        system_state.apply(world);
+    }
+}
+
+pub fn switch_size(kind: SwitchKind) -> Vec3 {
+    match kind {
+        SwitchKind::Button => BUTTON_SIZE,
+        SwitchKind::PressurePlate | SwitchKind::HoldPlate => PLATE_SIZE,
+    }
+}
+
+/// Buttons sit against walls facing +Z, and plates sit on floors facing +Y.
+fn cap_translation(kind: SwitchKind, pressed: bool) -> Vec3 {
+    let size = switch_size(kind);
+    let travel = if pressed { SWITCH_TRAVEL } else { 0.0 };
+    match kind {
+        SwitchKind::Button => Vec3::Z * (size.z / 2.0 - travel),
+        SwitchKind::PressurePlate | SwitchKind::HoldPlate => Vec3::Y * (size.y / 2.0 - travel),
+    }
+}
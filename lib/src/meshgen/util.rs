@@ -0,0 +1,30 @@
+use std::mem::take;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+
+#[derive(Default)]
+pub struct MeshParts {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 4]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u16>,
+    pub curr_idx: u16,
+}
+
+pub fn finish_mesh(mesh_parts: &mut MeshParts) -> Mesh {
+    mesh_parts.curr_idx = 0;
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, take(&mut mesh_parts.positions))
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, take(&mut mesh_parts.normals))
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, take(&mut mesh_parts.colors))
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, take(&mut mesh_parts.uvs))
+    .with_inserted_indices(Indices::U16(take(&mut mesh_parts.indices)))
+}
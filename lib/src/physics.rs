@@ -8,6 +8,8 @@ pub enum GameLayer {
     Cable,
     Player,
     Enemy,
+    /// Debris from destroyed terrain, which only collides with the world.
+    Debris,
 }
 
 //pub const BRUSH_ONLY: SpatialQueryFilter = SpatialQueryFilter::from_mask(GameLayer::Brush);
@@ -1,6 +1,7 @@
 use std::f32::consts::FRAC_PI_2;
 
 use bevy::{
+    diagnostic::{DiagnosticPath, DiagnosticsStore},
     input::mouse::MouseMotion,
     prelude::*,
     window::{CursorGrabMode, PrimaryWindow, WindowMode},
@@ -8,6 +9,13 @@ use bevy::{
 use bevy_egui::{egui, EguiContexts};
 use bevy_tnua::math::{Float, Vector3};
 
+use crate::{
+    health::Health,
+    input::{ActionInput, InputAction},
+    weapon::RadialMenu,
+    worldgen::terrain::{DESTROY_CARVED_VOXELS, DESTROY_MERGED_EVENTS, DESTROY_QUEUE_LENGTH},
+};
+
 use super::PLAYER_CENTER_TO_EYES_HEIGHT;
 
 const MOUSE_MOTION_SCALE: f32 = 0.00015;
@@ -73,6 +81,7 @@ fn ui(
     mut ui_state: ResMut<UiState>,
     mut contexts: EguiContexts,
     player: Option<Single<&Camera, With<PlayerCamera>>>,
+    diagnostics: Res<DiagnosticsStore>,
 ) {
     if !window.cursor_options.visible {
         return;
@@ -95,7 +104,8 @@ fn ui(
             ui.label("Press T to toggle camera control.");
             ui.label("Press L to toggle flashlight.");
             ui.label("Press F to toggle fullscreen.");
-            ui.label("Left click to destroy terrain.");
+            ui.label("Left click to fire.");
+            ui.label("Press V to deploy a shield.");
 
             ui.add_space(10.0);
 
@@ -105,11 +115,34 @@ fn ui(
                     float_edit_field(ui, &mut ui_state.sensitivity);
                 });
             });
+
+            ui.add_space(10.0);
+
+            ui.group(|ui| {
+                let measurement = |path: &DiagnosticPath| {
+                    diagnostics
+                        .get(path)
+                        .and_then(|d| d.smoothed())
+                        .unwrap_or_default()
+                };
+                ui.label(format!(
+                    "Destruction queue: {:.0}",
+                    measurement(&DESTROY_QUEUE_LENGTH)
+                ));
+                ui.label(format!(
+                    "Carved voxels per batch: {:.0}",
+                    measurement(&DESTROY_CARVED_VOXELS)
+                ));
+                ui.label(format!(
+                    "Merged destruction: {:.1}",
+                    measurement(&DESTROY_MERGED_EVENTS)
+                ));
+            });
         });
 }
 
 fn toggle_fullscreen_and_flashlight(
-    keyboard: Res<ButtonInput<KeyCode>>,
+    input: ActionInput,
     mut window: Single<&mut Window, With<PrimaryWindow>>,
     light: Option<Single<(&mut SpotLight, &Flashlight)>>,
 ) {
@@ -117,7 +150,7 @@ fn toggle_fullscreen_and_flashlight(
         return;
     };
 
-    if keyboard.just_pressed(KeyCode::KeyF) {
+    if input.just_pressed(InputAction::Fullscreen) {
         window.mode = match window.mode {
             WindowMode::Windowed => WindowMode::BorderlessFullscreen(MonitorSelection::Current),
             _ => WindowMode::Windowed,
@@ -125,7 +158,7 @@ fn toggle_fullscreen_and_flashlight(
     }
 
     let mut light = light.into_inner();
-    if keyboard.just_pressed(KeyCode::KeyL) {
+    if input.just_pressed(InputAction::Flashlight) {
         light.0.intensity = match light.0.intensity {
             0.0 => light.1 .0,
             _ => 0.0,
@@ -133,11 +166,8 @@ fn toggle_fullscreen_and_flashlight(
     }
 }
 
-fn grab_ungrab_mouse(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut window: Single<&mut Window, With<PrimaryWindow>>,
-) {
-    if !keyboard.just_pressed(KeyCode::KeyT) {
+fn grab_ungrab_mouse(input: ActionInput, mut window: Single<&mut Window, With<PrimaryWindow>>) {
+    if !input.just_pressed(InputAction::GrabCursor) {
         return;
     }
 
@@ -153,15 +183,20 @@ fn grab_ungrab_mouse(
 }
 
 fn apply_camera_controls(
+    time: Res<Time>,
+    input: ActionInput,
     primary_window_query: Query<&Window, With<PrimaryWindow>>,
     mut mouse_motion: EventReader<MouseMotion>,
-    mut player_character_query: Query<(&GlobalTransform, &mut ForwardFromCamera)>,
+    mut player_character_query: Query<(&GlobalTransform, &mut ForwardFromCamera, Option<&Health>)>,
     mut camera_query: Query<&mut Transform, With<Camera>>,
     ui_state: Res<UiState>,
+    radial_menu: Option<Res<RadialMenu>>,
 ) {
+    // The radial menu takes over the mouse while it's open
     let mouse_controls_camera = primary_window_query
         .get_single()
-        .map_or(false, |w| !w.cursor_options.visible);
+        .map_or(false, |w| !w.cursor_options.visible)
+        && !radial_menu.is_some_and(|menu| menu.is_open());
 
     let total_delta = if mouse_controls_camera {
         mouse_motion.read().map(|event| event.delta).sum()
@@ -186,10 +221,26 @@ fn apply_camera_controls(
 
     let total_delta = total_delta * MOUSE_MOTION_SCALE * ui_state.sensitivity * window_scale;
 
-    let Ok((player_transform, mut forward_from_camera)) = player_character_query.get_single_mut()
+    // The right stick points the radial menu while it's open. Stick y points up, and mouse motion
+    // y points down.
+    let stick_delta = if mouse_controls_camera {
+        input.look() * Vec2::new(1.0, -1.0) * time.delta_secs()
+    } else {
+        Vec2::ZERO
+    };
+    let total_delta = total_delta + stick_delta;
+
+    let Ok((player_transform, mut forward_from_camera, health)) =
+        player_character_query.get_single_mut()
     else {
         return;
     };
+    // The camera still follows the body while waiting to respawn
+    let total_delta = if health.is_some_and(Health::is_dead) {
+        Vec2::ZERO
+    } else {
+        total_delta
+    };
 
     let yaw = Quat::from_rotation_y(-total_delta.x);
     let pitch = total_delta.y;
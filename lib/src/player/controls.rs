@@ -1,11 +1,17 @@
-use avian3d::prelude::PhysicsSchedule;
-use bevy::prelude::*;
+use avian3d::prelude::{LinearVelocity, PhysicsSchedule};
+use bevy::{prelude::*, utils::HashMap};
 use bevy_tnua::{
     builtins::{TnuaBuiltinCrouch, TnuaBuiltinCrouchState},
     control_helpers::{TnuaCrouchEnforcer, TnuaSimpleAirActionsCounter},
     math::Vector3,
     prelude::{TnuaBuiltinJump, TnuaBuiltinWalk, TnuaController},
-    TnuaAction, TnuaUserControlsSystemSet,
+    TnuaAction, TnuaPipelineStages, TnuaRigidBodyTracker, TnuaUserControlsSystemSet,
+};
+
+use crate::{
+    health::{player_alive, Health},
+    input::{ActionInput, InputAction},
+    worldgen::{layout::GravityZones, terrain::FrontierProximity},
 };
 
 use super::camera::ForwardFromCamera;
@@ -14,9 +20,19 @@ pub struct PlayerControlsPlugin;
 
 impl Plugin for PlayerControlsPlugin {
     fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (buffer_jump_input.run_if(player_alive), track_motion),
+        );
+        app.add_systems(
+            PhysicsSchedule,
+            (apply_platformer_controls, apply_external_motion).in_set(TnuaUserControlsSystemSet),
+        );
         app.add_systems(
             PhysicsSchedule,
-            apply_platformer_controls.in_set(TnuaUserControlsSystemSet),
+            apply_gravity_zones
+                .after(TnuaPipelineStages::Sensors)
+                .before(TnuaPipelineStages::Logic),
         );
     }
 }
@@ -30,18 +46,87 @@ pub struct PlayerMotionConfig {
     pub jump: TnuaBuiltinJump,
     pub crouch: TnuaBuiltinCrouch,
     pub actions_in_air: usize,
+    /// Seconds a jump press is remembered for if it can't be performed yet, such as right
+    /// before landing.
+    pub jump_buffer_time: f32,
+    /// Seconds after leaving the ground during which a jump is still allowed.
+    pub coyote_time: f32,
+}
+
+/// Accelerations applied to the player on top of its controls, by channel. Each channel keeps
+/// applying until it's cleared, e.g. a grapple reeling the player in. Also tracks how the player
+/// is moving, for effects like view model bobbing and fall damage.
+#[derive(Component, Default)]
+pub struct PlayerMotion {
+    external: HashMap<&'static str, Vec3>,
+    speed: f32,
+    grounded: bool,
+    /// Fastest downward speed since leaving the ground.
+    fall_speed: f32,
+    landing_speed: Option<f32>,
+}
+
+impl PlayerMotion {
+    /// Horizontal speed, in meters per second.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn grounded(&self) -> bool {
+        self.grounded
+    }
+
+    /// Fastest downward speed of the fall the player just landed from, in meters per second.
+    /// Only set on the frame they land.
+    pub fn landing_speed(&self) -> Option<f32> {
+        self.landing_speed
+    }
+
+    pub fn set_external(&mut self, channel: &'static str, acceleration: Vec3) {
+        self.external.insert(channel, acceleration);
+    }
+
+    pub fn clear_external(&mut self, channel: &'static str) {
+        self.external.remove(channel);
+    }
+
+    pub fn external_acceleration(&self) -> Vec3 {
+        self.external.values().sum()
+    }
+}
+
+/// Jump buffering and coyote time state.
+#[derive(Component, Default)]
+pub struct JumpAssist {
+    buffer_remaining: f32,
+    since_grounded: f32,
+    jumped_since_grounded: bool,
+}
+
+fn buffer_jump_input(input: ActionInput, mut query: Query<(&PlayerMotionConfig, &mut JumpAssist)>) {
+    if !input.just_pressed(InputAction::Jump) {
+        return;
+    }
+
+    for (config, mut assist) in query.iter_mut() {
+        assist.buffer_remaining = config.jump_buffer_time;
+    }
 }
 
 #[allow(clippy::type_complexity)]
 #[allow(clippy::useless_conversion)]
 pub fn apply_platformer_controls(
-    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    input: ActionInput,
+    frontier: Option<Res<FrontierProximity>>,
     mut query: Query<(
         &PlayerMotionConfig,
         &mut TnuaController,
         &mut TnuaCrouchEnforcer,
         &mut TnuaSimpleAirActionsCounter,
+        Option<&mut JumpAssist>,
         Option<&ForwardFromCamera>,
+        Option<&Health>,
     )>,
 ) {
     for (
@@ -49,25 +134,16 @@ pub fn apply_platformer_controls(
         mut controller,
         mut crouch_enforcer,
         mut air_actions_counter,
+        jump_assist,
         forward_from_camera,
+        health,
     ) in query.iter_mut()
     {
-        let mut direction = Vector3::ZERO;
-
-        if keyboard.any_pressed([KeyCode::ArrowUp, KeyCode::KeyW]) {
-            direction -= Vector3::Z;
-        }
-        if keyboard.any_pressed([KeyCode::ArrowDown, KeyCode::KeyS]) {
-            direction += Vector3::Z;
-        }
-        if keyboard.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]) {
-            direction -= Vector3::X;
-        }
-        if keyboard.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD]) {
-            direction += Vector3::X;
-        }
+        // The dead don't move on their own while waiting to respawn
+        let alive = health.is_none_or(|health| !health.is_dead());
 
-        direction = direction.clamp_length_max(1.0);
+        let movement = if alive { input.movement() } else { Vec2::ZERO };
+        let mut direction = Vector3::new(movement.x, 0.0, movement.y);
 
         if let Some(forward_from_camera) = forward_from_camera {
             direction = Transform::default()
@@ -75,12 +151,36 @@ pub fn apply_platformer_controls(
                 .transform_point(direction)
         }
 
-        let jump = keyboard.any_pressed([KeyCode::Space]);
-        let sprint = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
-        let crouch = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let mut jump = alive && input.pressed(InputAction::Jump);
+        let sprint = alive && input.pressed(InputAction::Sprint);
+        let crouch = alive && input.pressed(InputAction::Crouch);
 
         air_actions_counter.update(controller.as_mut());
 
+        let mut coyote = false;
+        if let Some(mut assist) = jump_assist {
+            let delta = time.delta_secs();
+            let grounded = !controller.is_airborne().unwrap_or(true);
+            let jumping = controller.concrete_action::<TnuaBuiltinJump>().is_some();
+
+            if grounded && !jumping {
+                assist.since_grounded = 0.0;
+                assist.jumped_since_grounded = false;
+            } else {
+                assist.since_grounded += delta;
+            }
+            if jumping {
+                assist.jumped_since_grounded = true;
+                assist.buffer_remaining = 0.0;
+            }
+
+            coyote = !grounded
+                && !assist.jumped_since_grounded
+                && assist.since_grounded <= config.coyote_time;
+            jump |= assist.buffer_remaining > 0.0;
+            assist.buffer_remaining = (assist.buffer_remaining - delta).max(0.0);
+        }
+
         let speed_factor =
             if let Some((_, state)) = controller.concrete_action::<TnuaBuiltinCrouch>() {
                 if matches!(state, TnuaBuiltinCrouchState::Rising) {
@@ -94,8 +194,13 @@ pub fn apply_platformer_controls(
                 1.0
             };
 
+        // The walk basis only steers along the ground, so the push does too
+        let frontier_push = frontier
+            .as_ref()
+            .map_or(Vec3::ZERO, |frontier| frontier.push.reject_from(Vec3::Y));
+
         controller.basis(TnuaBuiltinWalk {
-            desired_velocity: direction * speed_factor * config.speed,
+            desired_velocity: direction * speed_factor * config.speed + frontier_push,
             desired_forward: Dir3::new(forward_from_camera.unwrap().forward).ok(),
             ..config.walk.clone()
         });
@@ -106,10 +211,50 @@ pub fn apply_platformer_controls(
 
         if jump {
             controller.action(TnuaBuiltinJump {
-                allow_in_air: air_actions_counter.air_count_for(TnuaBuiltinJump::NAME)
-                    <= config.actions_in_air,
+                allow_in_air: coyote
+                    || air_actions_counter.air_count_for(TnuaBuiltinJump::NAME)
+                        <= config.actions_in_air,
                 ..config.jump.clone()
             });
         }
     }
 }
+
+fn track_motion(mut query: Query<(&mut PlayerMotion, &LinearVelocity, &TnuaController)>) {
+    for (mut motion, velocity, controller) in query.iter_mut() {
+        let was_grounded = motion.grounded;
+        motion.speed = velocity.0.xz().length();
+        motion.grounded = !controller.is_airborne().unwrap_or(true);
+
+        motion.landing_speed = None;
+        if !motion.grounded {
+            motion.fall_speed = motion.fall_speed.max(-velocity.y);
+        } else if !was_grounded {
+            motion.landing_speed = Some(std::mem::take(&mut motion.fall_speed));
+        }
+    }
+}
+
+/// The controller only steers toward its desired velocity, so external accelerations are added to
+/// the velocity directly.
+fn apply_external_motion(time: Res<Time>, mut query: Query<(&PlayerMotion, &mut LinearVelocity)>) {
+    for (motion, mut velocity) in query.iter_mut() {
+        velocity.0 += motion.external_acceleration() * time.delta_secs();
+    }
+}
+
+/// The controller floats the player by countering gravity, and only knows the physics engine's
+/// gravity. Gravity zones already accelerate the player's body, so the controller is told about
+/// them too.
+fn apply_gravity_zones(
+    zones: GravityZones,
+    mut query: Query<(&GlobalTransform, &mut TnuaRigidBodyTracker)>,
+) {
+    if zones.is_empty() {
+        return;
+    }
+
+    for (transform, mut tracker) in query.iter_mut() {
+        tracker.gravity = zones.gravity_at(transform.translation());
+    }
+}
@@ -0,0 +1,161 @@
+use avian3d::prelude::*;
+use bevy::{audio::Volume, prelude::*, utils::HashMap};
+use bevy_rand::{global::GlobalEntropy, prelude::WyRand};
+use bevy_tnua::{builtins::TnuaBuiltinCrouch, prelude::TnuaController};
+use rand::Rng;
+
+use crate::{
+    physics::GameLayer,
+    worldgen::terrain::{Chunk, VoxelMaterialQuery},
+};
+
+use super::{controls::PlayerMotion, IsPlayer, PLAYER_FLOAT_HEIGHT_FROM_CENTER};
+
+/// Sound set of surfaces that aren't terrain.
+const FOOTSTEP_DEFAULT_SET: &str = "metal";
+/// Variations of each step sound, named `step_0.ogg` and up.
+const FOOTSTEP_VARIATIONS: usize = 4;
+/// Distance travelled per step, in meters. Half of a view model bob cycle.
+const FOOTSTEP_STRIDE: f32 = 1.6;
+/// Slower than this doesn't make a sound.
+const FOOTSTEP_MIN_SPEED: f32 = 1.0;
+/// Random pitch change of each sound, as a fraction of its speed.
+const FOOTSTEP_PITCH_VARIATION: f32 = 0.1;
+const FOOTSTEP_VOLUME: f32 = 0.6;
+/// How far below the player's feet the surface is looked for.
+const FOOTSTEP_PROBE_MARGIN: f32 = 0.5;
+/// Landings faster than this make a sound, in meters per second.
+const LAND_MIN_SPEED: f32 = 4.0;
+/// Landings are at full volume at this speed, in meters per second.
+const LAND_FULL_SPEED: f32 = 20.0;
+/// Crouching faster than this slides, in meters per second.
+const SLIDE_MIN_SPEED: f32 = 12.0;
+
+/// Movement sounds of the player, picked by the surface under their feet.
+#[derive(Component, Default)]
+pub struct Footsteps {
+    /// Distance travelled since the last step.
+    distance: f32,
+    sliding: bool,
+}
+
+/// Footstep sounds by path, loaded the first time they're played.
+#[derive(Resource, Default)]
+struct FootstepSfx(HashMap<String, Handle<AudioSource>>);
+
+enum FootstepSound {
+    Step,
+    Land,
+    Slide,
+}
+
+pub struct PlayerFootstepsPlugin;
+
+impl Plugin for PlayerFootstepsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FootstepSfx>();
+        app.add_systems(Update, play_footsteps);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn play_footsteps(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut sfx: ResMut<FootstepSfx>,
+    mut rng: GlobalEntropy<WyRand>,
+    spatial_query: SpatialQuery,
+    terrain: VoxelMaterialQuery,
+    chunks: Query<(), With<Chunk>>,
+    mut players: Query<
+        (
+            Entity,
+            &Transform,
+            &PlayerMotion,
+            &TnuaController,
+            &mut Footsteps,
+        ),
+        With<IsPlayer>,
+    >,
+) {
+    for (entity, transform, motion, controller, mut footsteps) in players.iter_mut() {
+        if !motion.grounded() {
+            footsteps.sliding = false;
+            continue;
+        }
+
+        let crouching = controller.concrete_action::<TnuaBuiltinCrouch>().is_some();
+        let was_sliding = footsteps.sliding;
+        footsteps.sliding = crouching && motion.speed() >= SLIDE_MIN_SPEED;
+
+        let (sound, volume) = if let Some(landing_speed) = motion.landing_speed() {
+            footsteps.distance = 0.0;
+            if landing_speed < LAND_MIN_SPEED {
+                continue;
+            }
+            (
+                FootstepSound::Land,
+                (landing_speed / LAND_FULL_SPEED).min(1.0),
+            )
+        } else if footsteps.sliding {
+            if was_sliding {
+                continue;
+            }
+            (FootstepSound::Slide, 1.0)
+        } else {
+            if motion.speed() < FOOTSTEP_MIN_SPEED {
+                footsteps.distance = 0.0;
+                continue;
+            }
+            footsteps.distance += motion.speed() * time.delta_secs();
+            if footsteps.distance < FOOTSTEP_STRIDE {
+                continue;
+            }
+            footsteps.distance -= FOOTSTEP_STRIDE;
+            (FootstepSound::Step, 1.0)
+        };
+
+        let Some(hit) = spatial_query.cast_ray(
+            transform.translation,
+            Dir3::NEG_Y,
+            PLAYER_FLOAT_HEIGHT_FROM_CENTER + FOOTSTEP_PROBE_MARGIN,
+            true,
+            &SpatialQueryFilter::from_mask(!LayerMask::from(GameLayer::Debris))
+                .with_excluded_entities([entity]),
+        ) else {
+            continue;
+        };
+        let point = transform.translation + Vec3::NEG_Y * hit.distance;
+        let set = chunks
+            .contains(hit.entity)
+            .then(|| terrain.surface_at(point, hit.normal))
+            .flatten()
+            .map_or(FOOTSTEP_DEFAULT_SET, |(_, properties)| {
+                properties.footsteps.as_str()
+            });
+
+        let path = match sound {
+            FootstepSound::Step => {
+                let variation = rng.gen_range(0..FOOTSTEP_VARIATIONS);
+                format!("sfx/footsteps/{set}/step_{variation}.ogg")
+            }
+            FootstepSound::Land => format!("sfx/footsteps/{set}/land.ogg"),
+            FootstepSound::Slide => format!("sfx/footsteps/{set}/slide.ogg"),
+        };
+        let handle = sfx
+            .0
+            .entry(path)
+            .or_insert_with_key(|path| asset_server.load(path.clone()));
+        let pitch = 1.0 + rng.gen_range(-FOOTSTEP_PITCH_VARIATION..=FOOTSTEP_PITCH_VARIATION);
+
+        commands.spawn((
+            AudioPlayer::new(handle.clone()),
+            PlaybackSettings::DESPAWN
+                .with_spatial(true)
+                .with_speed(pitch)
+                .with_volume(Volume::new(FOOTSTEP_VOLUME * volume)),
+            Transform::from_translation(point),
+        ));
+    }
+}
@@ -5,12 +5,17 @@ use bevy_tnua_avian3d::TnuaAvian3dPlugin;
 use camera::PlayerCameraPlugin;
 use consts::*;
 use controls::PlayerControlsPlugin;
+use footsteps::PlayerFootstepsPlugin;
+
+use crate::{health::HealthPlugin, input::InputBindingsPlugin};
 
 mod camera;
 mod controls;
+mod footsteps;
 mod spawn;
 
-pub use camera::ForwardFromCamera;
+pub use camera::{ForwardFromCamera, PlayerCamera};
+pub use controls::{PlayerMotion, PlayerMotionConfig};
 pub use spawn::*;
 
 pub mod consts {
@@ -40,12 +45,19 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<InputBindingsPlugin>() {
+            app.add_plugins(InputBindingsPlugin);
+        }
+        if !app.is_plugin_added::<HealthPlugin>() {
+            app.add_plugins(HealthPlugin);
+        }
         app.add_plugins((
             TnuaAvian3dPlugin::new(PhysicsSchedule),
             TnuaControllerPlugin::new(PhysicsSchedule),
             TnuaCrouchEnforcerPlugin::new(PhysicsSchedule),
             PlayerCameraPlugin,
             PlayerControlsPlugin,
+            PlayerFootstepsPlugin,
         ));
     }
 }
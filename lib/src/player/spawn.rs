@@ -9,11 +9,15 @@ use bevy_tnua::{
 use bevy_tnua_avian3d::TnuaAvian3dSensorShape;
 use rand::seq::SliceRandom;
 
-use crate::worldgen::layout::{LayoutState, Spawnpoint};
+use crate::{
+    health::{FallDamage, Health},
+    worldgen::layout::{LayoutState, Spawnpoint},
+};
 
 use super::{
     camera::{Flashlight, PlayerCamera},
-    controls::PlayerMotionConfig,
+    controls::{JumpAssist, PlayerMotion, PlayerMotionConfig},
+    footsteps::Footsteps,
     ForwardFromCamera, IsPlayer, PLAYER_COLLIDER, PLAYER_FLOAT_HEIGHT_FROM_CENTER, PLAYER_RADIUS,
 };
 
@@ -77,6 +81,11 @@ impl Command for SpawnPlayerCommand {
                 ..default()
             }),
             SpatialListener::new(-PLAYER_RADIUS * 2.0),
+            DistanceFog {
+                color: Color::BLACK,
+                falloff: FogFalloff::Exponential { density: 0.0 },
+                ..default()
+            },
             Flashlight(10_000_000.0),
             SpotLight {
                 intensity: 10_000_000.0,
@@ -119,7 +128,14 @@ impl Command for SpawnPlayerCommand {
                 ..Default::default()
             },
             actions_in_air: 0,
+            jump_buffer_time: 0.15,
+            coyote_time: 0.12,
         });
+        commands.insert(JumpAssist::default());
+        commands.insert(PlayerMotion::default());
+        commands.insert(Footsteps::default());
+        commands.insert(Health::new(100.0, 0.5));
+        commands.insert(FallDamage::new(24.0, 4.0));
         commands.insert(ForwardFromCamera::default());
         commands.insert(TnuaCrouchEnforcer::new(0.5 * Vector3::Y, |cmd| {
             let bundle = TnuaAvian3dSensorShape(
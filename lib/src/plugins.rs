@@ -0,0 +1,144 @@
+use bevy::{app::PluginGroupBuilder, prelude::*};
+use noisy_bevy::NoisyShaderPlugin;
+
+use crate::{
+    automap::AutomapPlugin,
+    cable::CablePlugin,
+    challenge::ChallengePlugin,
+    debug_console::DebugConsolePlugin,
+    debug_hud::DebugHudPlugin,
+    debug_inspector::DebugInspectorPlugin,
+    explosion::ExplosionPlugin,
+    haptics::HapticsPlugin,
+    hud::HudPlugin,
+    materials::{CaveMaterial, LineMaterialPlugin},
+    meshgen::MeshGenerationPlugin,
+    player::PlayerPlugin,
+    weapon::WeaponPlugin,
+    worldgen::{fluid::FluidPlugin, layout::LayoutPlugin, terrain::TerrainPlugin},
+};
+
+/// Every plugin needed to run the game. Expects [`DefaultPlugins`], the physics plugins, and
+/// the egui plugin to be added separately.
+///
+/// ```ignore
+/// app.add_plugins(CavesForeverPlugins::default().weapons(false).debug(false));
+/// ```
+#[derive(Clone, Copy)]
+pub struct CavesForeverPlugins {
+    layout: bool,
+    player: bool,
+    weapons: bool,
+    automap: bool,
+    haptics: bool,
+    challenge: bool,
+    debug: bool,
+}
+
+impl Default for CavesForeverPlugins {
+    fn default() -> Self {
+        Self {
+            layout: true,
+            player: true,
+            weapons: true,
+            automap: true,
+            haptics: true,
+            challenge: false,
+            debug: true,
+        }
+    }
+}
+
+impl CavesForeverPlugins {
+    /// Only what is needed to generate and render terrain.
+    pub fn terrain_only() -> Self {
+        Self {
+            layout: false,
+            player: false,
+            weapons: false,
+            automap: false,
+            haptics: false,
+            challenge: false,
+            debug: false,
+        }
+    }
+
+    pub fn layout(mut self, enabled: bool) -> Self {
+        self.layout = enabled;
+        self
+    }
+
+    pub fn player(mut self, enabled: bool) -> Self {
+        self.player = enabled;
+        self
+    }
+
+    /// Weapons require the player.
+    pub fn weapons(mut self, enabled: bool) -> Self {
+        self.weapons = enabled;
+        self
+    }
+
+    /// Map of the chunks the player has visited. Requires the player.
+    pub fn automap(mut self, enabled: bool) -> Self {
+        self.automap = enabled;
+        self
+    }
+
+    pub fn haptics(mut self, enabled: bool) -> Self {
+        self.haptics = enabled;
+        self
+    }
+
+    /// Timed challenge mode, where sequences collapse behind the player. Requires the layout and
+    /// the player.
+    pub fn challenge(mut self, enabled: bool) -> Self {
+        self.challenge = enabled;
+        self
+    }
+
+    /// The debug console and inspector overlays.
+    pub fn debug(mut self, enabled: bool) -> Self {
+        self.debug = enabled;
+        self
+    }
+}
+
+impl PluginGroup for CavesForeverPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let mut group = PluginGroupBuilder::start::<Self>()
+            .add(LineMaterialPlugin)
+            .add(NoisyShaderPlugin)
+            .add(MaterialPlugin::<CaveMaterial>::default())
+            .add(TerrainPlugin)
+            .add(FluidPlugin)
+            .add(MeshGenerationPlugin)
+            .add(CablePlugin)
+            .add(ExplosionPlugin)
+            .add(DebugHudPlugin);
+
+        if self.layout {
+            group = group.add(LayoutPlugin);
+        }
+        if self.player {
+            group = group.add(PlayerPlugin).add(HudPlugin);
+        }
+        if self.player && self.weapons {
+            group = group.add(WeaponPlugin);
+        }
+        if self.player && self.automap {
+            group = group.add(AutomapPlugin);
+        }
+        if self.haptics {
+            group = group.add(HapticsPlugin);
+        }
+        if self.layout && self.player && self.challenge {
+            group = group.add(ChallengePlugin);
+        }
+        if self.debug {
+            group = group.add(DebugConsolePlugin).add(DebugInspectorPlugin);
+        }
+
+        group
+    }
+}
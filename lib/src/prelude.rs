@@ -0,0 +1,55 @@
+//! The plugins, commands, events, and components most users need, re-exported from one place so
+//! module reorganizations don't break downstream code.
+//!
+//! ```ignore
+//! use lib::prelude::*;
+//! ```
+
+pub use crate::{
+    automap::{Automap, AutomapPlugin, AutomapView},
+    cable::{Cable, CableAttachment, CableBrokenEvent, SpawnCableCommand},
+    challenge::{
+        ChallengeConfig, ChallengeOverEvent, ChallengePlugin, ChallengeRun, Leaderboard,
+        SequenceCollapsedEvent,
+    },
+    debug_camera::DebugCameraPlugin,
+    debug_console::{ConsoleCommand, DebugConsole, DebugConsolePlugin, RegisterConsoleCommand},
+    debug_hud::{DebugHud, DebugHudPlugin},
+    debug_inspector::DebugInspectorPlugin,
+    explosion::{ExplosionEvent, ExplosionPlugin},
+    haptics::{HapticEvent, HapticsPlugin},
+    health::{DamageEvent, DamageKind, DeathEvent, Health, HealthPlugin, SafeSpawnpoint},
+    input::{ActionInput, InputAction, InputBinding, InputBindings, PendingRebind},
+    materials::{CaveMaterial, CaveMaterialExtension, CaveMaterialQuality, LineMaterialPlugin},
+    meshgen::{MeshGenerationPlugin, SwitchEvent},
+    physics::GameLayer,
+    player::{DespawnPlayerCommand, IsPlayer, PlayerPlugin, SpawnPlayerCommand},
+    plugins::CavesForeverPlugins,
+    replay::{ReplayBundle, ReplayPlugin, ReplayRecorderPlugin, ReplayViewer, ReplayViewerPlugin},
+    save::{SaveGame, SaveGameCommand, SaveGamePlugin},
+    weapon::{
+        deflect::DeflectEvent, fire::WeaponHitEvent, scanner::Scanner, shield::DeployShieldCommand,
+        DropWeaponEvent, SwitchWeaponEvent, ViewModelCamera, WeaponFiredEvent, WeaponPickup,
+        WeaponPlugin,
+    },
+    worldgen::{
+        brush::{
+            BrushOperation, CancelTerrainBrushTasksCommand, TerrainBrush, TerrainBrushTaskEvent,
+        },
+        fluid::{FluidPlugin, FluidVolume, Fluids, Submersion},
+        layout::{
+            Annotation, AudioZone, BelongsToRoom, CaveCulling, CaveLighting, CurrentRoom,
+            GravityZone, GravityZones, InitLayoutCommand, LayoutGradient, LayoutLoops,
+            LayoutMilestones, LayoutPlugin, LayoutProgress, LayoutProgressEvent, LayoutStage,
+            PatrolPath, Portal, Room, RoomChangedEvent, SpawnRoomAssetCommand, Spawnpoint,
+            StepLayoutCommand, WorldSeed,
+        },
+        terrain::{
+            ChunkComposition, ChunkDespawned, ChunkLoader, ChunkMeshed, ChunkSpawned,
+            DestroyTerrain, DestroyTerrainEvent, FrontierTelemetryEvent, GpuSampling, NavGrid,
+            TerrainDebris, TerrainDetail, TerrainPlugin, UpdateChunks, VoxelMaterialProperties,
+            VoxelMaterialQuery, VoxelMaterialRegistry, VoxelMaterials,
+        },
+        voxel::DamageType,
+    },
+};
@@ -1,4 +1,6 @@
 pub const WORLD: usize = 0;
 pub const EDITOR: usize = 1;
 pub const EDITOR_PREVIEW: usize = 2;
+pub const MAP: usize = 3;
 pub const VIEW_MODEL: usize = 4;
+pub const THUMBNAIL: usize = 5;
@@ -0,0 +1,847 @@
+//! Captures the last few seconds of physics-relevant state and input so movement and physics
+//! bugs can be reproduced headlessly. Press F9 to write a bundle to `replays/`.
+//!
+//! Bundles can also be watched with [`ReplayViewerPlugin`], which follows a ghost of the player
+//! along the recorded path instead of simulating it.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use avian3d::prelude::*;
+use bevy::{
+    app::AppExit, ecs::event::EventCursor, input::mouse::AccumulatedMouseMotion, prelude::*,
+    time::TimeUpdateStrategy,
+};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    health::{DamageEvent, Health},
+    physics::GameLayer,
+    player::{
+        consts::{PLAYER_HEIGHT, PLAYER_RADIUS},
+        ForwardFromCamera, IsPlayer,
+    },
+    worldgen::{
+        layout::{LayoutProgressEvent, LayoutStage, LayoutState, StepLayoutCommand, WorldSeed},
+        terrain::{
+            terrain_is_idle, ChunkLoader, DestroyTerrain, DestroyTerrainEvent, DestroyTerrainQueue,
+        },
+    },
+};
+
+/// Seconds of input per keyframe. Bundles contain between one and two keyframes of input.
+const KEYFRAME_SECONDS: f32 = 5.0;
+const REPLAY_DIRECTORY: &str = "replays";
+/// Most destruction remembered for restoring the terrain at the start of a keyframe. Older
+/// destruction is forgotten, so replays of long sessions may differ where it happened.
+const MAX_DESTRUCTION_HISTORY: usize = 4096;
+
+/// Distance behind and above the ghost when the viewer follows it.
+const FOLLOW_OFFSET: Vec3 = Vec3::new(0.0, 1.5, 4.0);
+/// Units per second the viewer's free camera flies, and how much faster it goes with shift.
+const FREE_CAMERA_SPEED: f32 = 12.0;
+const FREE_CAMERA_BOOST: f32 = 4.0;
+const FREE_CAMERA_SENSITIVITY: f32 = 0.003;
+/// Jumping to an event starts this many seconds before it, to see what led up to it.
+const EVENT_LEAD_SECS: f32 = 1.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BodyKind {
+    Player,
+    Enemy,
+    Dynamic,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BodySnapshot {
+    pub kind: BodyKind,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReplaySnapshot {
+    pub bodies: Vec<BodySnapshot>,
+    pub pending_destruction: Vec<DestroyTerrain>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InputFrame {
+    pub delta: f32,
+    pub keys: Vec<KeyCode>,
+    pub mouse_buttons: Vec<MouseButton>,
+    pub forward: Vec3,
+    pub pitch_angle: f32,
+    /// Where the player was during the frame. Only used by the viewer.
+    #[serde(default)]
+    pub translation: Option<Vec3>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum ReplayEventKind {
+    /// Amount is in the range 0..=1, relative to the player's health.
+    Damage(f32),
+    Destruction(DestroyTerrain),
+    /// The layout started generating the sequence.
+    SequenceGenerated(usize),
+}
+
+/// Something that happened during a replay, for jumping around in the viewer.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ReplayEvent {
+    /// Index of the input frame the event happened in.
+    pub frame: usize,
+    pub kind: ReplayEventKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayBundle {
+    pub seed: Option<u64>,
+    /// Destruction applied before the snapshot was taken.
+    pub destruction: Vec<DestroyTerrain>,
+    pub start: ReplaySnapshot,
+    pub inputs: Vec<InputFrame>,
+    /// State at the time of capture, used to check whether the replay diverged.
+    pub end: ReplaySnapshot,
+    /// Layout sequence when the snapshot was taken.
+    #[serde(default)]
+    pub sequence: Option<usize>,
+    #[serde(default)]
+    pub events: Vec<ReplayEvent>,
+}
+
+impl ReplayBundle {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let mut file = File::open(path).context("failed to open replay")?;
+        let mut vec = Vec::new();
+        file.read_to_end(&mut vec)?;
+        Ok(cbor4ii::serde::from_slice(&vec)?)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let vec = cbor4ii::serde::to_vec(Vec::new(), self)?;
+        let mut file = File::create(path).context("failed to create replay")?;
+        file.write_all(&vec)?;
+        Ok(())
+    }
+
+    /// Destroys the terrain that was destroyed when the recording started, and queues what was
+    /// still waiting to be destroyed.
+    fn restore_destruction(
+        &self,
+        destruction: &mut EventWriter<DestroyTerrainEvent>,
+        queue: &mut DestroyTerrainQueue,
+    ) {
+        destruction.send_batch(self.destruction.iter().map(|d| DestroyTerrainEvent {
+            position: d.position,
+            radius: d.radius,
+            force: d.force,
+            damage: d.damage,
+        }));
+        queue
+            .0
+            .extend(self.start.pending_destruction.iter().copied());
+    }
+}
+
+#[derive(Default)]
+struct Keyframe {
+    destruction: Vec<DestroyTerrain>,
+    snapshot: ReplaySnapshot,
+    sequence: Option<usize>,
+    inputs: Vec<InputFrame>,
+    events: Vec<ReplayEvent>,
+    duration: f32,
+}
+
+#[derive(Resource, Default)]
+struct ReplayRecorder {
+    destruction: VecDeque<DestroyTerrain>,
+    previous: Option<Keyframe>,
+    current: Option<Keyframe>,
+}
+
+//
+// Recording
+//
+
+pub struct ReplayRecorderPlugin;
+
+impl Plugin for ReplayRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>();
+        app.add_systems(PostUpdate, (record, record_events, capture).chain());
+    }
+}
+
+type BodyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static RigidBody,
+        Option<&'static LinearVelocity>,
+        Option<&'static AngularVelocity>,
+        Option<&'static CollisionLayers>,
+        Has<IsPlayer>,
+    ),
+>;
+
+fn snapshot(bodies: &BodyQuery, queue: &DestroyTerrainQueue) -> ReplaySnapshot {
+    let bodies = bodies
+        .iter()
+        .filter_map(|(transform, body, linear, angular, layers, is_player)| {
+            let kind = if is_player {
+                BodyKind::Player
+            } else if layers.is_some_and(|l| l.memberships.has_all(GameLayer::Enemy)) {
+                BodyKind::Enemy
+            } else if body.is_dynamic() {
+                BodyKind::Dynamic
+            } else {
+                return None;
+            };
+
+            Some(BodySnapshot {
+                kind,
+                translation: transform.translation,
+                rotation: transform.rotation,
+                linear_velocity: linear.map_or(Vec3::ZERO, |v| v.0),
+                angular_velocity: angular.map_or(Vec3::ZERO, |v| v.0),
+            })
+        })
+        .collect();
+
+    ReplaySnapshot {
+        bodies,
+        pending_destruction: queue.0.clone(),
+    }
+}
+
+fn record(
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    queue: Res<DestroyTerrainQueue>,
+    layout: Option<Res<LayoutState>>,
+    bodies: BodyQuery,
+    player: Option<Single<(&Transform, &ForwardFromCamera)>>,
+    mut destruction: EventReader<DestroyTerrainEvent>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+    let (transform, player) = *player;
+
+    let recorder = &mut *recorder;
+    recorder
+        .destruction
+        .extend(destruction.read().map(|event| event.unevent()));
+    let excess = recorder
+        .destruction
+        .len()
+        .saturating_sub(MAX_DESTRUCTION_HISTORY);
+    recorder.destruction.drain(..excess);
+
+    let needs_keyframe = recorder
+        .current
+        .as_ref()
+        .map_or(true, |keyframe| keyframe.duration >= KEYFRAME_SECONDS);
+    if needs_keyframe {
+        let keyframe = Keyframe {
+            destruction: recorder.destruction.iter().copied().collect(),
+            snapshot: snapshot(&bodies, &queue),
+            sequence: layout.map(|layout| layout.sequence),
+            ..default()
+        };
+        recorder.previous = recorder.current.replace(keyframe);
+    }
+
+    let keyframe = recorder.current.as_mut().unwrap();
+    keyframe.duration += time.delta_secs();
+    keyframe.inputs.push(InputFrame {
+        delta: time.delta_secs(),
+        keys: keyboard.get_pressed().copied().collect(),
+        mouse_buttons: mouse.get_pressed().copied().collect(),
+        forward: player.forward,
+        pitch_angle: player.pitch_angle,
+        translation: Some(transform.translation),
+    });
+}
+
+/// Events are read with cursors since their plugins may not be added.
+fn record_events(
+    damage: Option<Res<Events<DamageEvent>>>,
+    layout: Option<Res<Events<LayoutProgressEvent>>>,
+    destruction: Res<Events<DestroyTerrainEvent>>,
+    players: Query<&Health, With<IsPlayer>>,
+    mut damage_cursor: Local<EventCursor<DamageEvent>>,
+    mut layout_cursor: Local<EventCursor<LayoutProgressEvent>>,
+    mut destruction_cursor: Local<EventCursor<DestroyTerrainEvent>>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let mut kinds = Vec::<ReplayEventKind>::new();
+    if let Some(damage) = damage {
+        kinds.extend(damage_cursor.read(&damage).filter_map(|event| {
+            let health = players.get(event.entity).ok()?;
+            Some(ReplayEventKind::Damage(event.amount / health.max))
+        }));
+    }
+    if let Some(layout) = layout {
+        kinds.extend(
+            layout_cursor
+                .read(&layout)
+                .filter(|event| event.stage == LayoutStage::Arranging)
+                .map(|event| ReplayEventKind::SequenceGenerated(event.sequence)),
+        );
+    }
+    kinds.extend(
+        destruction_cursor
+            .read(&destruction)
+            .map(|event| ReplayEventKind::Destruction(event.unevent())),
+    );
+
+    let Some(keyframe) = recorder.current.as_mut() else {
+        return;
+    };
+    let frame = keyframe.inputs.len().saturating_sub(1);
+    keyframe
+        .events
+        .extend(kinds.into_iter().map(|kind| ReplayEvent { frame, kind }));
+}
+
+fn capture(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    seed: Option<Res<WorldSeed>>,
+    queue: Res<DestroyTerrainQueue>,
+    bodies: BodyQuery,
+    recorder: Res<ReplayRecorder>,
+) {
+    if !keyboard.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    let Some(current) = &recorder.current else {
+        warn!("nothing to capture");
+        return;
+    };
+    let first = recorder.previous.as_ref().unwrap_or(current);
+    let mut inputs = first.inputs.clone();
+    let mut events = first.events.clone();
+    if recorder.previous.is_some() {
+        let offset = inputs.len();
+        inputs.extend(current.inputs.iter().cloned());
+        events.extend(current.events.iter().map(|event| ReplayEvent {
+            frame: event.frame + offset,
+            kind: event.kind,
+        }));
+    }
+
+    let bundle = ReplayBundle {
+        seed: seed.map(|seed| seed.0),
+        destruction: first.destruction.clone(),
+        start: first.snapshot.clone(),
+        inputs,
+        end: snapshot(&bodies, &queue),
+        sequence: first.sequence,
+        events,
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = PathBuf::from(REPLAY_DIRECTORY).join(format!("{timestamp}.replay.cbor"));
+
+    let result = fs::create_dir_all(REPLAY_DIRECTORY)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| bundle.write(&path));
+    match result {
+        Ok(_) => info!("wrote replay to {}", path.display()),
+        Err(err) => error!("failed to write replay: {err:?}"),
+    }
+}
+
+//
+// Playback
+//
+
+#[derive(Resource)]
+struct ReplayPlayback {
+    bundle: ReplayBundle,
+    frame: Option<usize>,
+}
+
+/// Plays back a bundle once the world has loaded, then exits.
+pub struct ReplayPlugin(pub ReplayBundle);
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayPlayback {
+            bundle: self.0.clone(),
+            frame: None,
+        });
+        app.add_systems(
+            PreUpdate,
+            (
+                begin_playback.run_if(terrain_is_idle),
+                play_inputs.after(bevy::input::InputSystem),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn begin_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    mut destruction: EventWriter<DestroyTerrainEvent>,
+    mut queue: ResMut<DestroyTerrainQueue>,
+    mut bodies: Query<(
+        &mut Transform,
+        &RigidBody,
+        Option<&mut LinearVelocity>,
+        Option<&mut AngularVelocity>,
+        Has<IsPlayer>,
+    )>,
+) {
+    if playback.frame.is_some() {
+        return;
+    }
+    if !bodies.iter().any(|(.., is_player)| is_player) {
+        return;
+    }
+
+    let bundle = &playback.bundle;
+    bundle.restore_destruction(&mut destruction, &mut queue);
+
+    for snapshot in bundle.start.bodies.iter() {
+        // Entities aren't stable between runs, so match bodies by kind and proximity
+        let closest = bodies
+            .iter_mut()
+            .filter(|(_, body, .., is_player)| match snapshot.kind {
+                BodyKind::Player => *is_player,
+                _ => !is_player && body.is_dynamic(),
+            })
+            .min_by(|a, b| {
+                let a = a.0.translation.distance_squared(snapshot.translation);
+                let b = b.0.translation.distance_squared(snapshot.translation);
+                a.total_cmp(&b)
+            });
+        let Some((mut transform, _, linear, angular, _)) = closest else {
+            warn!("no body to restore {:?} snapshot to", snapshot.kind);
+            continue;
+        };
+
+        transform.translation = snapshot.translation;
+        transform.rotation = snapshot.rotation;
+        if let Some(mut linear) = linear {
+            linear.0 = snapshot.linear_velocity;
+        }
+        if let Some(mut angular) = angular {
+            angular.0 = snapshot.angular_velocity;
+        }
+    }
+
+    playback.frame = Some(0);
+}
+
+fn play_inputs(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut keyboard: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+    mut exit: EventWriter<AppExit>,
+    player: Option<Single<(&Transform, &mut ForwardFromCamera), With<IsPlayer>>>,
+) {
+    let Some(frame) = playback.frame else {
+        return;
+    };
+    let Some(player) = player else {
+        return;
+    };
+    let (transform, mut forward) = player.into_inner();
+
+    let Some(input) = playback.bundle.inputs.get(frame) else {
+        let expected = playback
+            .bundle
+            .end
+            .bodies
+            .iter()
+            .find(|body| body.kind == BodyKind::Player)
+            .map(|body| body.translation);
+        match expected {
+            Some(expected) => info!(
+                "replay finished at {}, {} from the captured position",
+                transform.translation,
+                transform.translation.distance(expected)
+            ),
+            None => info!("replay finished at {}", transform.translation),
+        }
+        exit.send(AppExit::Success);
+        return;
+    };
+
+    fn apply<T: Copy + Eq + std::hash::Hash + Send + Sync + 'static>(
+        input: &mut ButtonInput<T>,
+        pressed: &[T],
+    ) {
+        let released = input
+            .get_pressed()
+            .filter(|button| !pressed.contains(button))
+            .copied()
+            .collect::<Vec<_>>();
+        released
+            .into_iter()
+            .for_each(|button| input.release(button));
+        pressed.iter().for_each(|button| input.press(*button));
+    }
+
+    apply(&mut keyboard, &input.keys);
+    apply(&mut mouse, &input.mouse_buttons);
+    forward.forward = input.forward;
+    forward.pitch_angle = input.pitch_angle;
+
+    commands.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        input.delta,
+    )));
+
+    playback.frame = Some(frame + 1);
+}
+
+//
+// Viewing
+//
+
+/// State of the replay viewer. Scrubbing backwards doesn't undo destruction or layout steps,
+/// since they can't be reversed.
+#[derive(Resource)]
+pub struct ReplayViewer {
+    bundle: ReplayBundle,
+    /// Seconds from the start of the replay to the start of each frame.
+    frame_times: Vec<f32>,
+    pub time: f32,
+    pub playing: bool,
+    /// Follow the ghost instead of flying freely.
+    pub follow: bool,
+    started: bool,
+    /// Number of the bundle's events that have been applied to the world.
+    applied_events: usize,
+}
+
+impl ReplayViewer {
+    pub fn new(bundle: ReplayBundle) -> Self {
+        let frame_times = bundle
+            .inputs
+            .iter()
+            .scan(0.0, |time, input| {
+                let start = *time;
+                *time += input.delta;
+                Some(start)
+            })
+            .collect();
+
+        Self {
+            bundle,
+            frame_times,
+            time: 0.0,
+            playing: true,
+            follow: true,
+            started: false,
+            applied_events: 0,
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.bundle.inputs.iter().map(|input| input.delta).sum()
+    }
+
+    pub fn frame_time(&self, frame: usize) -> f32 {
+        self.frame_times.get(frame).copied().unwrap_or(0.0)
+    }
+
+    /// Index of the frame playing at the current time.
+    pub fn frame(&self) -> usize {
+        self.frame_times
+            .partition_point(|start| *start <= self.time)
+            .saturating_sub(1)
+    }
+
+    /// Where the player was, and which way they faced, at the current time.
+    pub fn ghost(&self) -> Option<(Vec3, Vec3)> {
+        let frame = self.frame();
+        let input = self.bundle.inputs.get(frame)?;
+        let translation = input.translation?;
+
+        // Ease toward the next frame so slow motion stays smooth
+        let translation = match self.bundle.inputs.get(frame + 1) {
+            Some(next) if input.delta > 0.0 => {
+                let t = ((self.time - self.frame_time(frame)) / input.delta).clamp(0.0, 1.0);
+                translation.lerp(next.translation.unwrap_or(translation), t)
+            }
+            _ => translation,
+        };
+
+        Some((translation, input.forward))
+    }
+
+    pub fn events(&self) -> &[ReplayEvent] {
+        &self.bundle.events
+    }
+}
+
+#[derive(Component)]
+struct ReplayGhost;
+
+#[derive(Component)]
+struct ReplayViewerCamera;
+
+/// Shows a bundle in the world it was recorded in, following a ghost of the player instead of
+/// simulating it. Expects the layout without a player.
+pub struct ReplayViewerPlugin(pub ReplayBundle);
+
+impl Plugin for ReplayViewerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayViewer::new(self.0.clone()));
+        app.add_systems(Startup, setup_viewer);
+        app.add_systems(
+            Update,
+            (
+                begin_viewing.run_if(terrain_is_idle),
+                advance_viewer,
+                apply_viewer_events,
+                move_ghost,
+                move_viewer_camera,
+                viewer_ui,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn setup_viewer(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    viewer: Res<ReplayViewer>,
+) {
+    let (translation, forward) = viewer.ghost().unwrap_or((Vec3::ZERO, Vec3::NEG_Z));
+
+    commands.spawn((
+        ReplayGhost,
+        Mesh3d(meshes.add(Capsule3d::new(
+            PLAYER_RADIUS,
+            PLAYER_HEIGHT - PLAYER_RADIUS * 2.0,
+        ))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgba(0.3, 0.8, 1.0, 0.5),
+            emissive: LinearRgba::rgb(0.1, 0.4, 0.6),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        })),
+        Transform::from_translation(translation),
+    ));
+
+    let eye = translation - forward.with_y(0.0).normalize_or(Vec3::NEG_Z) * FOLLOW_OFFSET.z
+        + Vec3::Y * FOLLOW_OFFSET.y;
+    commands.spawn((
+        ReplayViewerCamera,
+        Camera3d::default(),
+        PointLight {
+            intensity: 2_000_000.0,
+            range: 64.0,
+            ..default()
+        },
+        ChunkLoader::default(),
+        Transform::from_translation(eye).looking_at(translation, Vec3::Y),
+    ));
+}
+
+/// Restores the terrain and layout from when the recording started.
+fn begin_viewing(
+    mut commands: Commands,
+    mut viewer: ResMut<ReplayViewer>,
+    mut destruction: EventWriter<DestroyTerrainEvent>,
+    mut queue: ResMut<DestroyTerrainQueue>,
+    layout: Option<Res<LayoutState>>,
+) {
+    if viewer.started {
+        return;
+    }
+    viewer.started = true;
+
+    let bundle = &viewer.bundle;
+    bundle.restore_destruction(&mut destruction, &mut queue);
+
+    if let (Some(layout), Some(sequence)) = (layout, bundle.sequence) {
+        for _ in layout.sequence..sequence {
+            commands.queue(StepLayoutCommand);
+        }
+    }
+}
+
+fn advance_viewer(time: Res<Time>, mut viewer: ResMut<ReplayViewer>) {
+    if !viewer.started || !viewer.playing {
+        return;
+    }
+
+    let duration = viewer.duration();
+    viewer.time = (viewer.time + time.delta_secs()).min(duration);
+    if viewer.time >= duration {
+        viewer.playing = false;
+    }
+}
+
+/// Applies what happened in the world as the replay passes it.
+fn apply_viewer_events(
+    mut commands: Commands,
+    mut viewer: ResMut<ReplayViewer>,
+    mut destruction: EventWriter<DestroyTerrainEvent>,
+) {
+    let frame = viewer.frame();
+    while let Some(event) = viewer.bundle.events.get(viewer.applied_events).copied() {
+        if event.frame > frame {
+            break;
+        }
+        viewer.applied_events += 1;
+
+        match event.kind {
+            ReplayEventKind::Damage(_) => {}
+            ReplayEventKind::Destruction(d) => {
+                destruction.send(DestroyTerrainEvent {
+                    position: d.position,
+                    radius: d.radius,
+                    force: d.force,
+                    damage: d.damage,
+                });
+            }
+            ReplayEventKind::SequenceGenerated(_) => commands.queue(StepLayoutCommand),
+        }
+    }
+}
+
+fn move_ghost(viewer: Res<ReplayViewer>, mut ghost: Single<&mut Transform, With<ReplayGhost>>) {
+    let Some((translation, forward)) = viewer.ghost() else {
+        return;
+    };
+
+    ghost.translation = translation;
+    if let Ok(direction) = Dir3::new(forward.with_y(0.0)) {
+        ghost.look_to(direction, Vec3::Y);
+    }
+}
+
+fn move_viewer_camera(
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    viewer: Res<ReplayViewer>,
+    mut camera: Single<&mut Transform, With<ReplayViewerCamera>>,
+) {
+    if viewer.follow {
+        if let Some((translation, forward)) = viewer.ghost() {
+            let back = -forward.with_y(0.0).normalize_or(Vec3::NEG_Z);
+            let eye = translation + back * FOLLOW_OFFSET.z + Vec3::Y * FOLLOW_OFFSET.y;
+            **camera = Transform::from_translation(eye).looking_at(translation, Vec3::Y);
+        }
+        return;
+    }
+
+    // Look around while the right mouse button is held
+    if mouse.pressed(MouseButton::Right) {
+        let (mut yaw, mut pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+        yaw -= motion.delta.x * FREE_CAMERA_SENSITIVITY;
+        pitch = (pitch - motion.delta.y * FREE_CAMERA_SENSITIVITY).clamp(-1.54, 1.54);
+        camera.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+    }
+
+    let mut direction = Vec3::ZERO;
+    for (key, axis) in [
+        (KeyCode::KeyW, *camera.forward()),
+        (KeyCode::KeyS, *camera.back()),
+        (KeyCode::KeyA, *camera.left()),
+        (KeyCode::KeyD, *camera.right()),
+        (KeyCode::KeyE, Vec3::Y),
+        (KeyCode::KeyQ, Vec3::NEG_Y),
+    ] {
+        if keyboard.pressed(key) {
+            direction += axis;
+        }
+    }
+    let speed = if keyboard.pressed(KeyCode::ShiftLeft) {
+        FREE_CAMERA_SPEED * FREE_CAMERA_BOOST
+    } else {
+        FREE_CAMERA_SPEED
+    };
+    camera.translation += direction.normalize_or_zero() * speed * time.delta_secs();
+}
+
+fn viewer_ui(mut contexts: EguiContexts, mut viewer: ResMut<ReplayViewer>) {
+    let duration = viewer.duration();
+    let mut jump_to = None;
+
+    egui::Window::new("Replay").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            let label = if viewer.playing { "Pause" } else { "Play" };
+            if ui.button(label).clicked() {
+                if !viewer.playing && viewer.time >= duration {
+                    viewer.time = 0.0;
+                }
+                viewer.playing = !viewer.playing;
+            }
+            ui.checkbox(&mut viewer.follow, "Follow ghost");
+        });
+        ui.add(
+            egui::Slider::new(&mut viewer.time, 0.0..=duration)
+                .suffix("s")
+                .max_decimals(2),
+        );
+        ui.label(format!(
+            "Frame {} of {}",
+            viewer.frame() + 1,
+            viewer.bundle.inputs.len()
+        ));
+        if !viewer.follow {
+            ui.label("Hold right click to look, WASD to fly, Q and E to go down and up");
+        }
+
+        ui.separator();
+        if viewer.events().is_empty() {
+            ui.label("No events recorded");
+        }
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .show(ui, |ui| {
+                for event in viewer.events() {
+                    let time = viewer.frame_time(event.frame);
+                    let label = match event.kind {
+                        ReplayEventKind::Damage(amount) => {
+                            format!("{time:.2}s  Damage {:.0}%", amount * 100.0)
+                        }
+                        ReplayEventKind::Destruction(d) => {
+                            format!("{time:.2}s  Destruction, radius {:.1}", d.radius)
+                        }
+                        ReplayEventKind::SequenceGenerated(sequence) => {
+                            format!("{time:.2}s  Sequence {sequence} generated")
+                        }
+                    };
+                    if ui.button(label).clicked() {
+                        jump_to = Some((time - EVENT_LEAD_SECS).max(0.0));
+                    }
+                }
+            });
+    });
+
+    if let Some(time) = jump_to {
+        viewer.time = time;
+    }
+}
@@ -0,0 +1,226 @@
+//! Save games. Press F5 to write a quicksave to `saves/`, and start the game with
+//! `--load <path>` to resume it. Modified chunks are stored for each world seed as they unload,
+//! and copied to a directory next to each save.
+
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use bevy::{ecs::world::CommandQueue, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::IsPlayer,
+    worldgen::{
+        layout::{SavedLayout, WorldSeed},
+        terrain::{ChunkStore, DestroyTerrain, DestroyTerrainQueue, TerrainStateMutex},
+    },
+};
+
+const SAVE_DIRECTORY: &str = "saves";
+const QUICKSAVE_FILE: &str = "quicksave.save.cbor";
+const SAVE_EXTENSION: &str = "save.cbor";
+/// Extension of the directory next to a save that holds its modified chunks.
+const CHUNK_STORE_EXTENSION: &str = "terrain";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SavedPlayer {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveGame {
+    pub seed: Option<u64>,
+    pub layout: SavedLayout,
+    pub player: Option<SavedPlayer>,
+    /// Every destruction applied to the terrain, including chunks that are unloaded.
+    pub destruction: Vec<DestroyTerrain>,
+}
+
+impl SaveGame {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let mut file = File::open(path).context("failed to open save")?;
+        let mut vec = Vec::new();
+        file.read_to_end(&mut vec)?;
+        Ok(cbor4ii::serde::from_slice(&vec)?)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let vec = cbor4ii::serde::to_vec(Vec::new(), self)?;
+        let mut file = File::create(path).context("failed to create save")?;
+        file.write_all(&vec)?;
+        Ok(())
+    }
+
+    /// Where the modified chunks of the save at the path are kept, e.g. `quicksave.terrain` for
+    /// `quicksave.save.cbor`.
+    pub fn chunk_store(path: &Path) -> ChunkStore {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = name
+            .strip_suffix(&format!(".{SAVE_EXTENSION}"))
+            .unwrap_or(&name);
+        ChunkStore::new(path.with_file_name(format!("{name}.{CHUNK_STORE_EXTENSION}")))
+    }
+
+    pub fn capture(world: &mut World) -> anyhow::Result<Self> {
+        let layout = SavedLayout::capture(world)?;
+
+        let player = world
+            .query_filtered::<&Transform, With<IsPlayer>>()
+            .get_single(world)
+            .ok()
+            .map(|transform| SavedPlayer {
+                translation: transform.translation,
+                rotation: transform.rotation,
+            });
+
+        let mut destruction = Vec::<DestroyTerrain>::new();
+        if let Some(state) = world.get_resource::<TerrainStateMutex>() {
+            let state = state.lock().unwrap();
+            let loaded = state
+                .chunk_data
+                .values()
+                .map(|(data, _)| data.destruction());
+            let unloaded = state
+                .unloaded
+                .values()
+                .map(|destruction| destruction.as_slice());
+            // Destruction spanning several chunks is recorded by each of them
+            for destroy in loaded.chain(unloaded).flatten() {
+                if !destruction.contains(destroy) {
+                    destruction.push(*destroy);
+                }
+            }
+        }
+
+        Ok(Self {
+            seed: world.get_resource::<WorldSeed>().map(|seed| seed.0),
+            layout,
+            player,
+            destruction,
+        })
+    }
+
+    /// Restores the layout and terrain, runs `after`, then moves the player to where it was
+    /// saved. `after` is expected to spawn the player.
+    pub fn restore(&self, path: &Path, world: &mut World, mut after: CommandQueue) {
+        // The world's chunks are replaced with the ones saved along with it, which are restored as
+        // they load, so terrain destroyed after saving doesn't come back
+        if let (Some(state), Some(seed)) = (world.get_resource::<TerrainStateMutex>(), self.seed) {
+            let store = ChunkStore::for_seed(seed);
+            if let Err(err) = store.copy_from(&Self::chunk_store(path)) {
+                warn!("failed to restore saved chunks: {err:?}");
+            }
+            state.lock().unwrap().store = Some(store);
+        }
+
+        self.layout.restore(world);
+
+        if let Some(mut queue) = world.get_resource_mut::<DestroyTerrainQueue>() {
+            queue.0.extend(self.destruction.iter().copied());
+        }
+
+        after.apply(world);
+
+        if let Some(saved) = self.player {
+            let mut players = world.query_filtered::<&mut Transform, With<IsPlayer>>();
+            if let Ok(mut transform) = players.get_single_mut(world) {
+                transform.translation = saved.translation;
+                transform.rotation = saved.rotation;
+            }
+        }
+    }
+}
+
+pub struct SaveGameCommand {
+    pub path: PathBuf,
+}
+
+impl Command for SaveGameCommand {
+    fn apply(self, world: &mut World) {
+        let result = SaveGame::capture(world).and_then(|save| {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            save.write(&self.path)?;
+
+            if let Some(state) = world.get_resource::<TerrainStateMutex>() {
+                let state = state.lock().unwrap();
+                let saved = SaveGame::chunk_store(&self.path);
+                match &state.store {
+                    Some(store) => {
+                        store.write_all(&state)?;
+                        saved.copy_from(store)?;
+                    }
+                    None => {
+                        saved.clear()?;
+                        saved.write_all(&state)?;
+                    }
+                }
+            }
+            Ok(())
+        });
+        match result {
+            Ok(_) => info!("wrote save to {}", self.path.display()),
+            Err(err) => error!("failed to write save: {err:?}"),
+        }
+    }
+}
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, prune_chunk_stores);
+        app.add_systems(
+            Update,
+            (
+                open_world_chunk_store.run_if(resource_exists_and_changed::<WorldSeed>),
+                quicksave,
+            ),
+        );
+    }
+}
+
+/// Removes modified chunks whose save was deleted.
+fn prune_chunk_stores() {
+    let Ok(entries) = fs::read_dir(SAVE_DIRECTORY) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_store = path.is_dir()
+            && path
+                .extension()
+                .is_some_and(|extension| extension == CHUNK_STORE_EXTENSION);
+        if !is_store || path.with_extension(SAVE_EXTENSION).exists() {
+            continue;
+        }
+
+        if let Err(err) = fs::remove_dir_all(&path) {
+            warn!("failed to remove {}: {err:?}", path.display());
+        }
+    }
+}
+
+/// Stores chunks of the world as they unload, and restores them when they load again.
+fn open_world_chunk_store(seed: Res<WorldSeed>, state: Option<Res<TerrainStateMutex>>) {
+    if let Some(state) = state {
+        state.lock().unwrap().store = Some(ChunkStore::for_seed(seed.0));
+    }
+}
+
+fn quicksave(mut commands: Commands, keyboard: Res<ButtonInput<KeyCode>>) {
+    if !keyboard.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    commands.queue(SaveGameCommand {
+        path: PathBuf::from(SAVE_DIRECTORY).join(QUICKSAVE_FILE),
+    });
+}
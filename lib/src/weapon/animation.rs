@@ -0,0 +1,151 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::player::PlayerMotion;
+
+use super::{camera::inertia, WeaponFiredEvent, WeaponSlots};
+
+/// Seconds taken to raise a view model after switching to it.
+const RAISE_SECS: f32 = 0.25;
+/// Seconds taken to lower a view model after switching away from it.
+const LOWER_SECS: f32 = 0.15;
+/// How far a lowered view model is swung down, in radians.
+const LOWERED_PITCH: f32 = 1.0;
+/// Idle sway amplitude, in meters.
+const SWAY_AMPLITUDE: Vec2 = Vec2::new(0.004, 0.003);
+/// Idle sway cycles per second.
+const SWAY_FREQUENCY: f32 = 0.4;
+/// Bob amplitude, in meters.
+const BOB_AMPLITUDE: Vec2 = Vec2::new(0.012, 0.01);
+/// Distance travelled per bob cycle, in meters.
+const BOB_STRIDE: f32 = 3.2;
+/// Bobbing is at full strength at this speed, in meters per second.
+const BOB_FULL_SPEED: f32 = 6.0;
+/// How quickly bobbing blends in and out.
+const BOB_BLEND_RATE: f32 = 8.0;
+/// Kick per unit of recoil, in meters toward the camera.
+const RECOIL_DISTANCE: f32 = 0.05;
+/// Kick per unit of recoil, in radians upward.
+const RECOIL_PITCH: f32 = 0.1;
+const MAX_RECOIL: f32 = 1.5;
+/// How quickly recoil settles.
+const RECOIL_RECOVERY_RATE: f32 = 10.0;
+
+/// Sway, bob, recoil, and switching animations, layered on top of the view model's inertia.
+#[derive(Component)]
+pub struct ViewModelAnimation {
+    shooter: Entity,
+    /// 0 while lowered and 1 once raised. Starts below 0 to wait for the previous view model.
+    raise: f32,
+    lowering: bool,
+    bob_phase: f32,
+    /// Blends bobbing in and out as the shooter starts and stops moving.
+    bob_weight: f32,
+    recoil: f32,
+}
+
+impl ViewModelAnimation {
+    /// Set `wait` when another view model is lowering, so this one is raised after it's gone.
+    pub fn new(shooter: Entity, wait: bool) -> Self {
+        Self {
+            shooter,
+            raise: if wait { -LOWER_SECS / RAISE_SECS } else { 0.0 },
+            lowering: false,
+            bob_phase: 0.0,
+            bob_weight: 0.0,
+            recoil: 0.0,
+        }
+    }
+
+    /// Starts lowering. The view model is despawned once it's out of view.
+    pub fn lower(&mut self) {
+        self.lowering = true;
+        // Waiting view models haven't been seen yet
+        self.raise = self.raise.max(0.0);
+    }
+}
+
+pub struct ViewModelAnimationPlugin;
+
+impl Plugin for ViewModelAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, kick);
+        app.add_systems(
+            PostUpdate,
+            animate
+                .after(inertia)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn kick(
+    mut events: EventReader<WeaponFiredEvent>,
+    shooters: Query<&WeaponSlots>,
+    mut animations: Query<&mut ViewModelAnimation>,
+) {
+    for event in events.read() {
+        let Some(weapon) = shooters
+            .get(event.shooter)
+            .ok()
+            .and_then(|slots| slots.weapons.get(slots.current).copied().flatten())
+        else {
+            continue;
+        };
+
+        for mut animation in animations.iter_mut() {
+            if animation.shooter == event.shooter && !animation.lowering {
+                animation.recoil = (animation.recoil + weapon.recoil * event.power).min(MAX_RECOIL);
+            }
+        }
+    }
+}
+
+fn animate(
+    mut commands: Commands,
+    time: Res<Time>,
+    motions: Query<&PlayerMotion>,
+    mut viewmodels: Query<(Entity, &mut ViewModelAnimation, &mut Transform)>,
+) {
+    let delta = time.delta_secs();
+
+    for (entity, mut animation, mut transform) in viewmodels.iter_mut() {
+        if animation.lowering {
+            animation.raise -= delta / LOWER_SECS;
+            if animation.raise <= 0.0 {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        } else {
+            animation.raise = (animation.raise + delta / RAISE_SECS).min(1.0);
+        }
+
+        let (speed, grounded) = motions
+            .get(animation.shooter)
+            .map_or((0.0, false), |motion| (motion.speed(), motion.grounded()));
+        let target_weight = match grounded {
+            true => (speed / BOB_FULL_SPEED).min(1.0),
+            false => 0.0,
+        };
+        animation.bob_weight = animation
+            .bob_weight
+            .lerp(target_weight, (delta * BOB_BLEND_RATE).min(1.0));
+        animation.bob_phase = (animation.bob_phase + speed / BOB_STRIDE * TAU * delta) % TAU;
+        animation.recoil *= (-RECOIL_RECOVERY_RATE * delta).exp();
+
+        // Sway fades out as bobbing takes over
+        let sway_time = time.elapsed_secs_wrapped() * SWAY_FREQUENCY * TAU;
+        let sway = Vec2::new(sway_time.sin(), (sway_time * 2.0).sin()) * SWAY_AMPLITUDE;
+        let phase = animation.bob_phase;
+        let bob = Vec2::new(phase.cos(), -phase.sin().abs()) * BOB_AMPLITUDE;
+        let offset = sway.lerp(bob, animation.bob_weight);
+
+        let raise = animation.raise.clamp(0.0, 1.0);
+        let raise = raise * raise * (3.0 - 2.0 * raise);
+        let pitch = animation.recoil * RECOIL_PITCH - (1.0 - raise) * LOWERED_PITCH;
+
+        transform.translation += offset.extend(animation.recoil * RECOIL_DISTANCE);
+        transform.rotation *= Quat::from_rotation_x(pitch);
+    }
+}
@@ -1,11 +1,16 @@
 use std::f32::consts::PI;
 
-use bevy::{prelude::*, render::view::RenderLayers, scene::SceneInstance};
+use bevy::{pbr::NotShadowCaster, prelude::*, render::view::RenderLayers, scene::SceneInstance};
 
 use crate::render_layer;
 
 pub const VIEWMODEL_FOV: f32 = 65.0;
 
+/// Fraction of the world's directional light used by the view model's fill light.
+const FILL_LIGHT_RATIO: f32 = 0.75;
+/// Fill light illuminance when the world has no directional light.
+const FALLBACK_FILL_ILLUMINANCE: f32 = 250.0;
+
 #[derive(Component, Default)]
 pub struct ViewModel {
     pub yaw: f32,
@@ -15,6 +20,18 @@ pub struct ViewModel {
 #[derive(Component)]
 pub struct NeedsRenderLayers(pub RenderLayers);
 
+/// Whether the view model casts shadows into the world layer.
+#[derive(Component)]
+pub struct ViewModelShadows(pub bool);
+
+/// Directional light on the view model layer which mirrors the world's directional light.
+#[derive(Component)]
+pub struct ViewModelFillLight;
+
+/// Marks world point/spot lights that have been extended to also light the view model layer.
+#[derive(Component)]
+struct SharedWithViewModel;
+
 #[derive(Component, Default)]
 pub struct ViewModelCamera;
 
@@ -22,12 +39,23 @@ pub struct ViewModelPlugin;
 
 impl Plugin for ViewModelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (add_required_components, insert_render_layers));
+        app.add_systems(
+            Update,
+            (
+                add_required_components,
+                insert_render_layers,
+                share_world_lights,
+                mirror_world_light,
+            ),
+        );
         app.add_systems(PostUpdate, inertia);
+
+        #[cfg(debug_assertions)]
+        app.add_systems(Update, validate_render_layers);
     }
 }
 
-fn inertia(
+pub(super) fn inertia(
     time: Res<Time>,
     parents: Query<&GlobalTransform, Without<ViewModel>>,
     mut viewmodels: Query<(&mut ViewModel, &mut Transform, &Parent), With<ViewModel>>,
@@ -82,17 +110,113 @@ fn add_required_components(
             RenderLayers::layer(render_layer::VIEW_MODEL),
         ));
     });
+
+    if !viewmodel_cameras.is_empty() {
+        commands.spawn((
+            ViewModelFillLight,
+            DirectionalLight {
+                shadows_enabled: false,
+                illuminance: 0.0,
+                ..default()
+            },
+            Transform::default(),
+            RenderLayers::layer(render_layer::VIEW_MODEL),
+        ));
+    }
+}
+
+fn mirror_world_light(
+    viewmodel_cameras: Query<&GlobalTransform, With<ViewModelCamera>>,
+    world_lights: Query<
+        (&DirectionalLight, &GlobalTransform, Option<&RenderLayers>),
+        Without<ViewModelFillLight>,
+    >,
+    mut fill_lights: Query<(&mut DirectionalLight, &mut Transform), With<ViewModelFillLight>>,
+) {
+    let world_layer = RenderLayers::layer(render_layer::WORLD);
+    let world_light = world_lights
+        .iter()
+        .find(|(_, _, layers)| layers.map_or(true, |layers| layers.intersects(&world_layer)));
+
+    for (mut fill, mut transform) in fill_lights.iter_mut() {
+        if let Some((light, light_transform, _)) = world_light {
+            fill.color = light.color;
+            fill.illuminance = light.illuminance * FILL_LIGHT_RATIO;
+            transform.rotation = light_transform.rotation();
+        } else if let Some(camera) = viewmodel_cameras.iter().next() {
+            // Light the view model from slightly above the camera
+            fill.color = Color::WHITE;
+            fill.illuminance = FALLBACK_FILL_ILLUMINANCE;
+            transform.rotation = camera.rotation() * Quat::from_rotation_x(-0.5);
+        }
+    }
+}
+
+/// Point and spot lights in the world also light the view model, so that the flashlight and
+/// nearby lamps affect it. This also lets the view model cast shadows from those lights into
+/// the world, which is toggled per weapon by `ViewModelShadows`.
+fn share_world_lights(
+    mut commands: Commands,
+    lights: Query<
+        (Entity, Option<&RenderLayers>),
+        (
+            Or<(With<PointLight>, With<SpotLight>)>,
+            Without<SharedWithViewModel>,
+        ),
+    >,
+) {
+    let world_layer = RenderLayers::layer(render_layer::WORLD);
+
+    for (entity, layers) in lights.iter() {
+        let layers = layers.cloned().unwrap_or_default();
+        if !layers.intersects(&world_layer) {
+            continue;
+        }
+
+        commands
+            .entity(entity)
+            .insert((layers.with(render_layer::VIEW_MODEL), SharedWithViewModel));
+    }
+}
+
+/// Warns once per entity when the world and view model layers leak into each other.
+#[cfg(debug_assertions)]
+fn validate_render_layers(
+    mut warned: Local<bevy::utils::HashSet<Entity>>,
+    cameras: Query<(Entity, Option<&RenderLayers>, Has<ViewModelCamera>), With<Camera3d>>,
+) {
+    let viewmodel_layer = RenderLayers::layer(render_layer::VIEW_MODEL);
+
+    for (entity, layers, is_viewmodel) in cameras.iter() {
+        let layers = layers.cloned().unwrap_or_default();
+        let problem = if is_viewmodel && layers != viewmodel_layer {
+            "view model camera renders layers other than the view model layer"
+        } else if !is_viewmodel && layers.intersects(&viewmodel_layer) {
+            "world camera renders the view model layer"
+        } else {
+            continue;
+        };
+
+        if warned.insert(entity) {
+            warn!("render layer isolation: {problem} ({entity})");
+        }
+    }
 }
 
 // HACK https://github.com/bevyengine/bevy/issues/5183
 fn insert_render_layers(
     mut commands: Commands,
-    scenes: Query<(Entity, &SceneInstance, &NeedsRenderLayers)>,
+    scenes: Query<(
+        Entity,
+        &SceneInstance,
+        &NeedsRenderLayers,
+        Option<&ViewModelShadows>,
+    )>,
     scene_spawner: Res<SceneSpawner>,
 ) {
     scenes
         .iter()
-        .for_each(|(entity, scene, needs_render_layers)| {
+        .for_each(|(entity, scene, needs_render_layers, shadows)| {
             if !scene_spawner.instance_is_ready(**scene) {
                 return;
             }
@@ -100,9 +224,11 @@ fn insert_render_layers(
             scene_spawner
                 .iter_instance_entities(**scene)
                 .for_each(|entity| {
-                    commands
-                        .entity(entity)
-                        .insert(needs_render_layers.0.clone());
+                    let mut commands = commands.entity(entity);
+                    commands.insert(needs_render_layers.0.clone());
+                    if shadows.is_some_and(|shadows| !shadows.0) {
+                        commands.insert(NotShadowCaster);
+                    }
                 });
 
             commands.entity(entity).remove::<NeedsRenderLayers>();
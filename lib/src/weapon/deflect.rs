@@ -0,0 +1,177 @@
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::worldgen::terrain::{Chunk, VoxelMaterialQuery};
+
+const DEFLECT_CUE_SECONDS: f32 = 0.25;
+
+/// Marks non-terrain colliders that hitscan and projectiles bounce off.
+#[derive(Component)]
+pub struct Deflective;
+
+#[derive(Event)]
+pub struct DeflectEvent {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub direction: Dir3,
+}
+
+/// Played where shots are deflected.
+#[derive(Resource)]
+pub struct DeflectSfx(pub Handle<AudioSource>);
+
+#[derive(Component)]
+struct DeflectCue {
+    position: Vec3,
+    normal: Vec3,
+    expires: f32,
+}
+
+pub struct RayDeflection {
+    pub hit: RayHitData,
+    pub origin: Vec3,
+    pub direction: Dir3,
+    pub deflected: bool,
+}
+
+#[derive(SystemParam)]
+pub struct DeflectionQuery<'w, 's> {
+    spatial_query: SpatialQuery<'w, 's>,
+    terrain: VoxelMaterialQuery<'w>,
+    chunks: Query<'w, 's, (), With<Chunk>>,
+    deflectives: Query<'w, 's, (), With<Deflective>>,
+    events: EventWriter<'w, DeflectEvent>,
+}
+
+impl DeflectionQuery<'_, '_> {
+    pub fn is_deflective(&self, entity: Entity, point: Vec3) -> bool {
+        if self.deflectives.contains(entity) {
+            return true;
+        }
+        if !self.chunks.contains(entity) {
+            return false;
+        }
+
+        self.terrain
+            .material_at(point)
+            .and_then(|material| self.terrain.properties(material))
+            .is_some_and(|properties| properties.deflective)
+    }
+
+    /// Casts a ray that bounces off deflective surfaces, returning every segment's hit.
+    /// The last hit is the one that stopped the ray, unless it ran out of bounces.
+    pub fn cast_ray(
+        &mut self,
+        origin: Vec3,
+        direction: Dir3,
+        max_distance: f32,
+        max_bounces: usize,
+        filter: &SpatialQueryFilter,
+    ) -> Vec<RayDeflection> {
+        let mut hits = Vec::new();
+        let mut origin = origin;
+        let mut direction = direction;
+        let mut remaining = max_distance;
+
+        for _ in 0..=max_bounces {
+            let Some(hit) = self
+                .spatial_query
+                .cast_ray(origin, direction, remaining, true, filter)
+            else {
+                break;
+            };
+
+            let (distance, normal) = (hit.distance, hit.normal);
+            let point = origin + direction * distance;
+            let deflected = self.is_deflective(hit.entity, point);
+            hits.push(RayDeflection {
+                hit,
+                origin,
+                direction,
+                deflected,
+            });
+
+            if !deflected {
+                break;
+            }
+
+            let reflected = self.deflect(point, normal, direction);
+
+            remaining -= distance;
+            origin = point + normal * 0.01;
+            direction = reflected;
+        }
+
+        hits
+    }
+
+    /// Bounces something moving in `direction` off a deflective surface, and returns its new
+    /// direction.
+    pub fn deflect(&mut self, point: Vec3, normal: Vec3, direction: Dir3) -> Dir3 {
+        let reflected = Dir3::new_unchecked(direction.reflect(normal));
+        self.events.send(DeflectEvent {
+            position: point,
+            normal,
+            direction: reflected,
+        });
+        reflected
+    }
+}
+
+pub struct DeflectPlugin;
+
+impl Plugin for DeflectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DeflectEvent>();
+        app.add_systems(Startup, setup);
+        app.add_systems(Update, (play_cues, draw_cues));
+    }
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(DeflectSfx(asset_server.load("sfx/deflect.ogg")));
+}
+
+fn play_cues(
+    mut commands: Commands,
+    time: Res<Time>,
+    sfx: Res<DeflectSfx>,
+    mut events: EventReader<DeflectEvent>,
+) {
+    for event in events.read() {
+        commands.spawn((
+            AudioPlayer::new(sfx.0.clone()),
+            PlaybackSettings::DESPAWN.with_spatial(true),
+            Transform::from_translation(event.position),
+        ));
+        commands.spawn(DeflectCue {
+            position: event.position,
+            normal: event.normal,
+            expires: time.elapsed_secs() + DEFLECT_CUE_SECONDS,
+        });
+    }
+}
+
+fn draw_cues(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+    cues: Query<(Entity, &DeflectCue)>,
+) {
+    for (entity, cue) in cues.iter() {
+        let remaining = cue.expires - time.elapsed_secs();
+        if remaining <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let fac = remaining / DEFLECT_CUE_SECONDS;
+        let color = Color::srgba(0.55, 0.85, 0.95, fac);
+        let rotation = Quat::from_rotation_arc(Vec3::Z, cue.normal);
+        gizmos.circle(
+            Isometry3d::new(cue.position, rotation),
+            0.5 * (1.0 - fac) + 0.1,
+            color,
+        );
+    }
+}
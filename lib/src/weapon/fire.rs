@@ -0,0 +1,425 @@
+use avian3d::prelude::*;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
+};
+use bevy_rand::{global::GlobalEntropy, prelude::WyRand};
+use rand::Rng;
+
+use crate::{
+    explosion::ExplosionEvent,
+    health::{player_alive, DamageEvent, DamageKind, Health},
+    input::{ActionInput, InputAction},
+    physics::GameLayer,
+    player::PlayerCamera,
+    worldgen::{
+        sdf,
+        terrain::{Chunk, DestroyTerrainEvent, VoxelMaterialQuery},
+        voxel::DamageType,
+    },
+};
+
+use super::{
+    deflect::DeflectionQuery, PlayerWeapons, RadialMenu, RangedMode, RangedSpread, WeaponAction,
+    WeaponFiredEvent, WeaponImpact, WeaponSlots, WeaponTrigger,
+};
+
+const HITSCAN_RANGE: f32 = 200.0;
+const HITSCAN_MAX_BOUNCES: usize = 4;
+/// Damage dealt to entities with health by each ray, multiplied by the power of the shot.
+const HIT_DAMAGE: f32 = 12.0;
+/// Impulse and damage at the center of explosive impacts, multiplied by the power of the shot.
+const EXPLOSION_IMPULSE: f32 = 8.0;
+const EXPLOSION_DAMAGE: f32 = 30.0;
+const IMPACT_CUE_SECONDS: f32 = 0.4;
+/// Radius of impact cues that don't carve anything. Cues grow with the share of the impact's
+/// radius that gets through the material.
+const IMPACT_CUE_MIN_RADIUS: f32 = 0.1;
+const IMPACT_CUE_MAX_RADIUS: f32 = 0.35;
+/// Impacts on anything that isn't terrain.
+const IMPACT_OBJECT_COLOR: Color = Color::srgb(1.0, 0.75, 0.3);
+const IMPACT_OBJECT_SFX: &str = "sfx/impact/object.ogg";
+
+/// Sent for every hitscan ray that stops on something, after bouncing off deflective surfaces.
+#[derive(Event, Debug)]
+pub struct WeaponHitEvent {
+    pub shooter: Entity,
+    pub entity: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// Multiplies damage and speed. Only charged shots have a power other than 1.
+    pub power: f32,
+    pub impact: WeaponImpact,
+}
+
+/// Impact sounds by path, loaded the first time they're played.
+#[derive(Resource, Default)]
+struct ImpactSfx(HashMap<String, Handle<AudioSource>>);
+
+#[derive(Component)]
+struct ImpactCue {
+    position: Vec3,
+    normal: Vec3,
+    color: Color,
+    radius: f32,
+    expires: f32,
+}
+
+pub struct FirePlugin;
+
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WeaponHitEvent>();
+        app.init_resource::<ImpactSfx>();
+        app.add_systems(
+            Update,
+            (
+                equip_trigger,
+                fire_weapons.run_if(player_alive),
+                damage_targets,
+                destroy_terrain,
+                spawn_impacts,
+                draw_impacts,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Trigger progress of whoever is holding weapons. Resets when they switch weapons.
+#[derive(Component, Default)]
+pub struct TriggerState {
+    slot: usize,
+    /// When the weapon can fire again.
+    ready_secs: f32,
+    burst_remaining: usize,
+    charge_start_secs: Option<f32>,
+}
+
+impl TriggerState {
+    /// From 0.0 to 1.0 while charging a charged weapon.
+    pub fn charge(&self, trigger: &WeaponTrigger, time: &Time) -> Option<f32> {
+        let WeaponTrigger::Charge { charge_secs, .. } = *trigger else {
+            return None;
+        };
+        let start = self.charge_start_secs?;
+        Some(((time.elapsed_secs() - start) / charge_secs).min(1.0))
+    }
+
+    /// Returns the power of the shot to fire this frame, if any.
+    fn update(
+        &mut self,
+        trigger: &WeaponTrigger,
+        held: bool,
+        pressed: bool,
+        now: f32,
+    ) -> Option<f32> {
+        let ready = now >= self.ready_secs;
+
+        match *trigger {
+            WeaponTrigger::SemiAuto { rpm } => {
+                if !pressed || !ready {
+                    return None;
+                }
+                self.ready_secs = now + 60.0 / rpm;
+                Some(1.0)
+            }
+            WeaponTrigger::FullAuto { rpm } => {
+                if !held || !ready {
+                    return None;
+                }
+                self.ready_secs = now + 60.0 / rpm;
+                Some(1.0)
+            }
+            WeaponTrigger::Burst {
+                rounds,
+                rpm,
+                delay_secs,
+            } => {
+                if pressed && ready && self.burst_remaining == 0 {
+                    self.burst_remaining = rounds;
+                }
+                if self.burst_remaining == 0 || !ready {
+                    return None;
+                }
+                self.burst_remaining -= 1;
+                self.ready_secs = now + 60.0 / rpm;
+                if self.burst_remaining == 0 {
+                    self.ready_secs += delay_secs;
+                }
+                Some(1.0)
+            }
+            WeaponTrigger::Charge {
+                charge_secs,
+                max_power,
+                overcharge_secs,
+                penalty_secs,
+            } => {
+                if pressed && ready {
+                    self.charge_start_secs = Some(now);
+                }
+                let start = self.charge_start_secs?;
+                let charged_secs = now - start;
+
+                if charged_secs > charge_secs + overcharge_secs {
+                    self.charge_start_secs = None;
+                    self.ready_secs = now + penalty_secs;
+                    return None;
+                }
+                if held {
+                    return None;
+                }
+
+                self.charge_start_secs = None;
+                let charge = (charged_secs / charge_secs).min(1.0);
+                Some(1.0_f32.lerp(max_power, charge))
+            }
+        }
+    }
+}
+
+fn equip_trigger(
+    mut commands: Commands,
+    shooters: Query<Entity, (With<PlayerWeapons>, Without<TriggerState>)>,
+) {
+    for shooter in shooters.iter() {
+        commands.entity(shooter).insert(TriggerState::default());
+    }
+}
+
+fn fire_weapons(
+    mut deflection: DeflectionQuery,
+    mut rng: GlobalEntropy<WyRand>,
+    mut hits: EventWriter<WeaponHitEvent>,
+    mut fired: EventWriter<WeaponFiredEvent>,
+    time: Res<Time>,
+    input: ActionInput,
+    menu: Res<RadialMenu>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Option<Single<&GlobalTransform, With<PlayerCamera>>>,
+    player: Option<Single<(Entity, &WeaponSlots, &mut TriggerState), With<PlayerWeapons>>>,
+) {
+    let (Some(camera), Some(player)) = (camera, player) else {
+        return;
+    };
+    let (shooter, slots, mut trigger_state) = player.into_inner();
+    if trigger_state.slot != slots.current {
+        *trigger_state = TriggerState {
+            slot: slots.current,
+            ..default()
+        };
+    }
+    let Some(weapon) = slots.weapons.get(slots.current).copied().flatten() else {
+        return;
+    };
+
+    let WeaponAction::Ranged {
+        ref spread,
+        ref mode,
+        ref trigger,
+        impact,
+        projectiles,
+    } = weapon.action
+    else {
+        return;
+    };
+
+    // Releasing the trigger when the cursor is freed also releases charged shots
+    let blocked = window.cursor_options.visible || menu.is_open();
+    let held = !blocked && input.pressed(InputAction::Fire);
+    let pressed = !blocked && input.just_pressed(InputAction::Fire);
+    let Some(power) = trigger_state.update(trigger, held, pressed, time.elapsed_secs()) else {
+        return;
+    };
+    fired.send(WeaponFiredEvent { shooter, power });
+    if !matches!(mode, RangedMode::Hitscan) {
+        return;
+    }
+
+    let origin = camera.translation();
+    let rotation = camera.rotation();
+    let filter = SpatialQueryFilter::from_mask(!LayerMask::from(GameLayer::Debris))
+        .with_excluded_entities([shooter]);
+
+    for _ in 0..projectiles {
+        let (u, v) = (rng.gen::<f32>(), rng.gen::<f32>());
+        let direction = Dir3::new_unchecked(rotation * spread_direction(spread, u, v));
+        let rays = deflection.cast_ray(
+            origin,
+            direction,
+            HITSCAN_RANGE,
+            HITSCAN_MAX_BOUNCES,
+            &filter,
+        );
+        let Some(last) = rays.last() else {
+            continue;
+        };
+        if last.deflected {
+            continue;
+        }
+
+        hits.send(WeaponHitEvent {
+            shooter,
+            entity: last.hit.entity,
+            point: last.origin + last.direction * last.hit.distance,
+            normal: last.hit.normal,
+            power,
+            impact,
+        });
+    }
+}
+
+/// Direction within the spread from two random numbers between 0 and 1, relative to a camera
+/// looking down -Z.
+fn spread_direction(spread: &RangedSpread, u: f32, v: f32) -> Vec3 {
+    let (x, y) = match *spread {
+        RangedSpread::Circle(radius) => (radius, radius),
+        RangedSpread::Ellipse(x, y) => (x, y),
+    };
+
+    // Uniform over the area of the ellipse
+    let distance = u.sqrt();
+    let angle = v * std::f32::consts::TAU;
+    let yaw = (angle.cos() * distance * x).to_radians();
+    let pitch = (angle.sin() * distance * y).to_radians();
+
+    Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0) * Vec3::NEG_Z
+}
+
+fn damage_targets(
+    mut events: EventReader<WeaponHitEvent>,
+    mut damage: EventWriter<DamageEvent>,
+    targets: Query<(), With<Health>>,
+    colliders: Query<&ColliderParent>,
+) {
+    for event in events.read() {
+        // Hits land on colliders, which may be children of the body with health
+        let entity = colliders
+            .get(event.entity)
+            .map_or(event.entity, |parent| parent.get());
+        if !targets.contains(entity) {
+            continue;
+        }
+
+        damage.send(DamageEvent {
+            entity,
+            amount: HIT_DAMAGE * event.power,
+            kind: DamageKind::Ballistic,
+            source: Some(event.shooter),
+        });
+    }
+}
+
+/// Explosive impacts explode wherever they land, so they also push and hurt what's around them.
+/// Other impacts only carve the terrain they hit.
+fn destroy_terrain(
+    mut events: EventReader<WeaponHitEvent>,
+    mut destroy: EventWriter<DestroyTerrainEvent>,
+    mut explosions: Option<ResMut<Events<ExplosionEvent>>>,
+    chunks: Query<(), With<Chunk>>,
+) {
+    for event in events.read() {
+        let force = event.impact.force * event.power;
+
+        if event.impact.damage == DamageType::Explosive {
+            if let Some(explosions) = explosions.as_mut() {
+                explosions.send(ExplosionEvent {
+                    impulse: EXPLOSION_IMPULSE * event.power,
+                    damage: EXPLOSION_DAMAGE * event.power,
+                    terrain_force: force,
+                    source: Some(event.shooter),
+                    ..ExplosionEvent::new(event.point, event.impact.radius)
+                });
+                continue;
+            }
+        }
+
+        if !chunks.contains(event.entity) {
+            continue;
+        }
+
+        destroy.send(DestroyTerrainEvent {
+            position: event.point,
+            radius: event.impact.radius,
+            force,
+            damage: event.impact.damage,
+        });
+    }
+}
+
+/// Impacts on terrain look and sound like the material that was hit, and grow with how much of it
+/// gets carved. Pellets that hit surfaces with the same sound in the same frame share one sound.
+/// Materials without an impact sound are only seen.
+fn spawn_impacts(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut sfx: ResMut<ImpactSfx>,
+    terrain: VoxelMaterialQuery,
+    chunks: Query<(), With<Chunk>>,
+    mut events: EventReader<WeaponHitEvent>,
+) {
+    let mut played = HashSet::<&str>::new();
+
+    for event in events.read() {
+        let surface = chunks
+            .contains(event.entity)
+            .then(|| terrain.surface_at(event.point, event.normal))
+            .flatten();
+        let (path, color, carved) = match surface {
+            Some((_, properties)) => {
+                let force = event.impact.force * event.power;
+                let resistance = properties.resistance(event.impact.damage);
+                let carved = sdf::strength(force, resistance);
+                (
+                    properties.impact_sfx.as_deref(),
+                    properties.particle_color(),
+                    carved,
+                )
+            }
+            None => (Some(IMPACT_OBJECT_SFX), IMPACT_OBJECT_COLOR, 0.0),
+        };
+
+        if let Some(path) = path.filter(|path| played.insert(*path)) {
+            let handle = sfx
+                .0
+                .entry(path.to_owned())
+                .or_insert_with(|| asset_server.load(path.to_owned()));
+            commands.spawn((
+                AudioPlayer::new(handle.clone()),
+                PlaybackSettings::DESPAWN.with_spatial(true),
+                Transform::from_translation(event.point),
+            ));
+        }
+
+        commands.spawn(ImpactCue {
+            position: event.point,
+            normal: event.normal,
+            color,
+            radius: IMPACT_CUE_MIN_RADIUS.lerp(IMPACT_CUE_MAX_RADIUS, carved),
+            expires: time.elapsed_secs() + IMPACT_CUE_SECONDS,
+        });
+    }
+}
+
+fn draw_impacts(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    time: Res<Time>,
+    cues: Query<(Entity, &ImpactCue)>,
+) {
+    for (entity, cue) in cues.iter() {
+        let remaining = cue.expires - time.elapsed_secs();
+        if remaining <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let fac = remaining / IMPACT_CUE_SECONDS;
+        let color = cue.color.with_alpha(fac);
+        let rotation = Quat::from_rotation_arc(Vec3::Z, cue.normal);
+        gizmos.circle(Isometry3d::new(cue.position, rotation), cue.radius, color);
+        gizmos.line(cue.position, cue.position + cue.normal * 0.3 * fac, color);
+    }
+}
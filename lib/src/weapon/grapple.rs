@@ -0,0 +1,371 @@
+use avian3d::prelude::*;
+use bevy::{pbr::NotShadowCaster, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    cable::{AnchorQuery, Cable, CableAnchor, CableAttachment, HeldCable, SpawnCableCommand},
+    health::player_alive,
+    input::{ActionInput, InputAction},
+    player::{PlayerCamera, PlayerMotion},
+    worldgen::terrain::Chunk,
+};
+
+use super::{
+    deflect::DeflectionQuery, PlayerWeapons, RadialMenu, WeaponAction, WeaponFiredEvent,
+    WeaponSlots,
+};
+
+/// Channel of the player's external motion used for reeling in.
+const MOTION_CHANNEL: &str = "grapple";
+/// Tension at which the cable pulls the player with the weapon's full pull.
+const FULL_PULL_TENSION: f32 = 0.05;
+/// Reeling in stops at this length.
+const MIN_CABLE_LENGTH: f32 = 1.0;
+/// Where the cable is held, relative to the shooter.
+const CABLE_OFFSET: Vec3 = Vec3::new(0.0, 0.3, 0.0);
+const HOOK_RADIUS: f32 = 0.08;
+/// Deflective surfaces the hook can bounce off in one frame.
+const HOOK_MAX_BOUNCES: usize = 4;
+
+/// Hook in flight. It's despawned once it has travelled the weapon's range without hitting
+/// anything.
+#[derive(Component)]
+pub struct GrappleHook {
+    pub shooter: Entity,
+    pub velocity: Vec3,
+    /// Distance left before the hook gives up.
+    pub remaining: f32,
+}
+
+/// Inserted on shooters once they can fire a grapple.
+#[derive(Component, Default)]
+pub struct GrappleState {
+    /// Hook in flight, or anchored at the end of the cable.
+    pub hook: Option<Entity>,
+    pub cable: Option<Entity>,
+}
+
+#[derive(Resource)]
+struct GrappleAssets {
+    hook_mesh: Handle<Mesh>,
+    hook_material: Handle<StandardMaterial>,
+}
+
+pub struct GrapplePlugin;
+
+impl Plugin for GrapplePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup);
+        app.add_systems(
+            Update,
+            (
+                equip_grapple,
+                fire_grapple.run_if(player_alive),
+                move_hooks,
+                reel,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(GrappleAssets {
+        hook_mesh: meshes.add(Sphere::new(HOOK_RADIUS)),
+        hook_material: materials.add(StandardMaterial {
+            base_color: Color::srgb(0.5, 0.5, 0.52),
+            metallic: 0.8,
+            perceptual_roughness: 0.4,
+            ..default()
+        }),
+    });
+}
+
+fn equip_grapple(
+    mut commands: Commands,
+    shooters: Query<Entity, (With<PlayerWeapons>, Without<GrappleState>)>,
+) {
+    for shooter in shooters.iter() {
+        commands.entity(shooter).insert(GrappleState::default());
+    }
+}
+
+/// Lets go of the cable, and despawns the hook and anchor.
+fn release(
+    commands: &mut Commands,
+    shooter: Entity,
+    state: &mut GrappleState,
+    held: Option<&HeldCable>,
+    motion: Option<&mut PlayerMotion>,
+) {
+    // Broken anchors have already been despawned, along with the hook
+    let anchor = held.map(|held| held.anchor);
+    for entity in [state.hook.take(), state.cable.take(), anchor]
+        .into_iter()
+        .flatten()
+    {
+        if let Some(entity) = commands.get_entity(entity) {
+            entity.despawn_recursive();
+        }
+    }
+    commands.entity(shooter).remove::<HeldCable>();
+    if let Some(motion) = motion {
+        motion.clear_external(MOTION_CHANNEL);
+    }
+}
+
+fn fire_grapple(
+    mut commands: Commands,
+    assets: Res<GrappleAssets>,
+    input: ActionInput,
+    mut fired: EventWriter<WeaponFiredEvent>,
+    menu: Res<RadialMenu>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Option<Single<&GlobalTransform, With<PlayerCamera>>>,
+    player: Option<
+        Single<
+            (
+                Entity,
+                &WeaponSlots,
+                &mut GrappleState,
+                Option<&HeldCable>,
+                Option<&mut PlayerMotion>,
+            ),
+            With<PlayerWeapons>,
+        >,
+    >,
+) {
+    let (Some(camera), Some(player)) = (camera, player) else {
+        return;
+    };
+    let (shooter, slots, mut state, held, mut motion) = player.into_inner();
+    let weapon = slots.weapons.get(slots.current).copied().flatten();
+
+    // Switching away from the grapple lets go
+    let Some(&WeaponAction::Grapple {
+        range, hook_speed, ..
+    }) = weapon.map(|weapon| &weapon.action)
+    else {
+        if state.hook.is_some() || state.cable.is_some() {
+            release(
+                &mut commands,
+                shooter,
+                &mut state,
+                held,
+                motion.as_deref_mut(),
+            );
+        }
+        return;
+    };
+
+    let blocked = window.cursor_options.visible || menu.is_open();
+    if blocked || !input.just_pressed(InputAction::Fire) {
+        return;
+    }
+    if state.hook.is_some() || state.cable.is_some() {
+        release(
+            &mut commands,
+            shooter,
+            &mut state,
+            held,
+            motion.as_deref_mut(),
+        );
+        return;
+    }
+
+    let hook = commands
+        .spawn((
+            GrappleHook {
+                shooter,
+                velocity: camera.forward() * hook_speed,
+                remaining: range,
+            },
+            Transform::from_translation(camera.translation()),
+            Mesh3d(assets.hook_mesh.clone()),
+            MeshMaterial3d(assets.hook_material.clone()),
+            NotShadowCaster,
+        ))
+        .id();
+    state.hook = Some(hook);
+    fired.send(WeaponFiredEvent {
+        shooter,
+        power: 1.0,
+    });
+}
+
+fn move_hooks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut deflection: DeflectionQuery,
+    validator: AnchorQuery,
+    mut hooks: Query<(Entity, &mut GrappleHook, &mut Transform)>,
+    mut shooters: Query<(&GlobalTransform, &mut GrappleState)>,
+    chunks: Query<(), With<Chunk>>,
+    colliders: Query<&ColliderParent>,
+    transforms: Query<&GlobalTransform>,
+) {
+    for (entity, mut hook, mut transform) in hooks.iter_mut() {
+        let Ok((shooter_transform, mut state)) = shooters.get_mut(hook.shooter) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        if state.hook != Some(entity) || state.cable.is_some() {
+            continue;
+        }
+
+        let Ok(direction) = Dir3::new(hook.velocity) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        let step = (hook.velocity.length() * time.delta_secs()).min(hook.remaining);
+        let origin = transform.translation;
+        let filter = SpatialQueryFilter::from_excluded_entities([hook.shooter]);
+
+        let rays = deflection.cast_ray(origin, direction, step, HOOK_MAX_BOUNCES, &filter);
+        let Some(ray) = rays.last().filter(|ray| !ray.deflected) else {
+            // Deflective surfaces bounce the hook without slowing it down
+            transform.translation = match rays.last() {
+                Some(ray) => {
+                    let travelled = rays.iter().map(|ray| ray.hit.distance).sum::<f32>();
+                    let reflected = Dir3::new_unchecked(ray.direction.reflect(ray.hit.normal));
+                    hook.velocity = reflected * hook.velocity.length();
+                    ray.origin
+                        + ray.direction * ray.hit.distance
+                        + reflected * (step - travelled).max(0.0)
+                }
+                None => origin + direction * step,
+            };
+            hook.remaining -= step;
+            if hook.remaining <= 0.0 {
+                commands.entity(entity).despawn_recursive();
+                state.hook = None;
+            }
+            continue;
+        };
+
+        let hit = &ray.hit;
+        let point = ray.origin + ray.direction * hit.distance;
+        transform.translation = point;
+
+        // Terrain anchors are checked like any other cable anchor, other bodies always hold
+        let anchor = if chunks.contains(hit.entity) {
+            if let Err(err) = validator.validate(point, hit.normal) {
+                info!("grapple didn't hold: {err}");
+                commands.entity(entity).despawn_recursive();
+                state.hook = None;
+                continue;
+            }
+            commands
+                .spawn((
+                    CableAnchor { normal: hit.normal },
+                    Transform::from_translation(point),
+                ))
+                .id()
+        } else if let Some((body, body_transform)) = colliders
+            .get(hit.entity)
+            .ok()
+            .and_then(|parent| Some((parent.get(), transforms.get(parent.get()).ok()?)))
+        {
+            let local = body_transform.affine().inverse().transform_point3(point);
+            let anchor = commands.spawn(Transform::from_translation(local)).id();
+            commands.entity(body).add_child(anchor);
+            anchor
+        } else {
+            commands.entity(entity).despawn_recursive();
+            state.hook = None;
+            continue;
+        };
+
+        // The hook rides along with the anchor
+        commands.entity(entity).remove::<GrappleHook>();
+        commands
+            .entity(entity)
+            .insert(Transform::default())
+            .set_parent(anchor);
+
+        let start = shooter_transform.transform_point(CABLE_OFFSET);
+        let cable = commands.spawn_empty().id();
+        commands.queue(
+            SpawnCableCommand::new(
+                CableAttachment::Entity {
+                    entity: hook.shooter,
+                    offset: CABLE_OFFSET,
+                },
+                CableAttachment::entity(anchor),
+                start.distance(point),
+            )
+            .with_entity(cable),
+        );
+        commands.entity(hook.shooter).insert(HeldCable { anchor });
+        state.cable = Some(cable);
+    }
+}
+
+fn reel(
+    mut commands: Commands,
+    time: Res<Time>,
+    input: ActionInput,
+    window: Single<&Window, With<PrimaryWindow>>,
+    player: Option<
+        Single<
+            (
+                Entity,
+                &WeaponSlots,
+                &mut GrappleState,
+                Option<&HeldCable>,
+                Option<&mut PlayerMotion>,
+            ),
+            With<PlayerWeapons>,
+        >,
+    >,
+    mut cables: Query<&mut Cable>,
+    anchors: Query<(), With<GlobalTransform>>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+    let (shooter, slots, mut state, held, mut motion) = player.into_inner();
+    let Some(cable_entity) = state.cable else {
+        return;
+    };
+    let Some(&WeaponAction::Grapple {
+        reel_speed, pull, ..
+    }) = slots
+        .weapons
+        .get(slots.current)
+        .copied()
+        .flatten()
+        .map(|weapon| &weapon.action)
+    else {
+        return;
+    };
+
+    // The cable is spawned by a command, so it may not exist for a frame
+    let Ok(mut cable) = cables.get_mut(cable_entity) else {
+        return;
+    };
+    // The anchor broke, or the body it was on is gone
+    if !held.is_some_and(|held| anchors.contains(held.anchor)) {
+        release(
+            &mut commands,
+            shooter,
+            &mut state,
+            held,
+            motion.as_deref_mut(),
+        );
+        return;
+    }
+
+    // Alt fire reels in
+    if !window.cursor_options.visible && input.pressed(InputAction::AltFire) {
+        cable.length = (cable.length - reel_speed * time.delta_secs()).max(MIN_CABLE_LENGTH);
+    }
+
+    if let Some(mut motion) = motion {
+        let strength = (cable.tension() / FULL_PULL_TENSION).min(1.0);
+        motion.set_external(MOTION_CHANNEL, cable.start_direction() * pull * strength);
+    }
+}
@@ -0,0 +1,158 @@
+use bevy::{pbr::NotShadowCaster, prelude::*, render::view::RenderLayers};
+
+use crate::{cable::HeldCable, render_layer};
+
+use super::PlayerWeapons;
+
+/// Seconds taken to raise or lower the off-hand.
+const BLEND_SECONDS: f32 = 0.2;
+const ARM_RADIUS: f32 = 0.035;
+
+/// Two bone chain for the off-hand, configured per weapon. Positions are in view model space.
+pub struct OffHandIk {
+    pub shoulder: Vec3,
+    pub upper_length: f32,
+    pub lower_length: f32,
+    /// The elbow bends toward this point.
+    pub pole: Vec3,
+    /// Where the hand rests when it isn't holding anything, usually out of view.
+    pub rest: Vec3,
+}
+
+impl OffHandIk {
+    /// Returns the elbow and hand positions that reach toward `target`.
+    fn solve(&self, target: Vec3) -> (Vec3, Vec3) {
+        let (a, b) = (self.upper_length, self.lower_length);
+        let to_target = target - self.shoulder;
+        let direction = to_target.normalize_or(Vec3::NEG_Z);
+        let distance = to_target.length().clamp((a - b).abs() + 1e-4, a + b - 1e-4);
+
+        // Law of cosines gives the angle between the upper arm and the target
+        let cos = ((a * a + distance * distance - b * b) / (2.0 * a * distance)).clamp(-1.0, 1.0);
+        let bend = (self.pole - self.shoulder)
+            .reject_from_normalized(direction)
+            .normalize_or(direction.any_orthonormal_vector());
+
+        let elbow = self.shoulder + direction * a * cos + bend * a * (1.0 - cos * cos).sqrt();
+        let hand = self.shoulder + direction * distance;
+        (elbow, hand)
+    }
+}
+
+/// Spawned under the view model of weapons that have an off-hand.
+#[derive(Component)]
+pub struct OffHand {
+    pub ik: &'static OffHandIk,
+    blend: f32,
+}
+
+impl OffHand {
+    pub fn new(ik: &'static OffHandIk) -> Self {
+        Self { ik, blend: 0.0 }
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum OffHandBone {
+    Upper,
+    Lower,
+}
+
+#[derive(Resource)]
+struct OffHandMaterial(Handle<StandardMaterial>);
+
+pub struct OffHandPlugin;
+
+impl Plugin for OffHandPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup);
+        app.add_systems(Update, (spawn_bones, track_cable).chain());
+    }
+}
+
+fn setup(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    commands.insert_resource(OffHandMaterial(materials.add(StandardMaterial {
+        base_color: Color::srgb(0.22, 0.2, 0.18),
+        perceptual_roughness: 0.9,
+        ..default()
+    })));
+}
+
+fn spawn_bones(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<OffHandMaterial>,
+    hands: Query<(Entity, &OffHand), Added<OffHand>>,
+) {
+    for (entity, hand) in hands.iter() {
+        commands.entity(entity).with_children(|parent| {
+            for (bone, length) in [
+                (OffHandBone::Upper, hand.ik.upper_length),
+                (OffHandBone::Lower, hand.ik.lower_length),
+            ] {
+                parent.spawn((
+                    bone,
+                    Mesh3d(meshes.add(Capsule3d::new(ARM_RADIUS, length))),
+                    MeshMaterial3d(material.0.clone()),
+                    Transform::default(),
+                    Visibility::Hidden,
+                    RenderLayers::layer(render_layer::VIEW_MODEL),
+                    NotShadowCaster,
+                ));
+            }
+        });
+    }
+}
+
+fn track_cable(
+    time: Res<Time>,
+    player: Option<Single<Option<&HeldCable>, With<PlayerWeapons>>>,
+    anchors: Query<&GlobalTransform>,
+    mut hands: Query<(&mut OffHand, &Children, &Parent)>,
+    mut bones: Query<(&OffHandBone, &mut Transform, &mut Visibility)>,
+) {
+    let anchor = player
+        .and_then(|player| *player)
+        .and_then(|held| anchors.get(held.anchor).ok())
+        .map(|anchor| anchor.translation());
+
+    for (mut hand, children, parent) in hands.iter_mut() {
+        // The hand entity has an identity transform, so the view model's space is used
+        let Ok(view_model) = anchors.get(parent.get()) else {
+            continue;
+        };
+        let target = anchor.map(|anchor| view_model.affine().inverse().transform_point3(anchor));
+
+        let step = time.delta_secs() / BLEND_SECONDS;
+        hand.blend = if target.is_some() {
+            (hand.blend + step).min(1.0)
+        } else {
+            (hand.blend - step).max(0.0)
+        };
+
+        // Raise the hand from rest toward the anchor
+        let ik = hand.ik;
+        let reach = target.unwrap_or(ik.rest);
+        let target = ik.rest.lerp(ik.solve(reach).1, hand.blend);
+        let (elbow, wrist) = ik.solve(target);
+
+        for child in children.iter() {
+            let Ok((bone, mut transform, mut visibility)) = bones.get_mut(*child) else {
+                continue;
+            };
+            let (start, end) = match bone {
+                OffHandBone::Upper => (ik.shoulder, elbow),
+                OffHandBone::Lower => (elbow, wrist),
+            };
+
+            transform.translation = (start + end) / 2.0;
+            transform.rotation =
+                Quat::from_rotation_arc(Vec3::Y, (end - start).normalize_or(Vec3::Y));
+            *visibility = if hand.blend > 0.0 {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
@@ -1,15 +1,38 @@
 use bevy::{prelude::*, render::view::RenderLayers};
 
+mod animation;
 mod camera;
+pub mod deflect;
+pub mod fire;
+pub mod grapple;
+mod hand;
 mod pickup;
+mod radial;
+pub mod scanner;
+pub mod shield;
 pub mod weapons;
 
+use animation::{ViewModelAnimation, ViewModelAnimationPlugin};
 pub use camera::ViewModelCamera;
-use camera::{NeedsRenderLayers, ViewModel, ViewModelPlugin};
-pub use pickup::WeaponPickup;
+use camera::{NeedsRenderLayers, ViewModel, ViewModelPlugin, ViewModelShadows};
+use deflect::DeflectPlugin;
+use fire::FirePlugin;
+use grapple::GrapplePlugin;
+pub use hand::OffHandIk;
+use hand::{OffHand, OffHandPlugin};
 use pickup::WeaponPickupPlugin;
+pub use pickup::{AimedPickup, DropWeaponEvent, WeaponPickup};
+pub use radial::RadialMenu;
+use radial::RadialMenuPlugin;
+use scanner::ScannerPlugin;
+use shield::ShieldPlugin;
 
-use crate::render_layer;
+use crate::{
+    health::{player_alive, HealthPlugin},
+    input::{ActionInput, InputAction, InputBindingsPlugin},
+    render_layer,
+    worldgen::voxel::DamageType,
+};
 
 /// Weapon spread radii, in degrees.
 pub enum RangedSpread {
@@ -26,12 +49,55 @@ pub enum RangedMode {
     },
 }
 
+/// How holding and releasing the trigger fires the weapon. Rates are in rounds per minute.
+pub enum WeaponTrigger {
+    /// One round per press.
+    SemiAuto { rpm: f32 },
+    /// Fires continuously while held.
+    FullAuto { rpm: f32 },
+    /// Fires a number of rounds per press, then waits before the next burst can start.
+    Burst {
+        rounds: usize,
+        rpm: f32,
+        delay_secs: f32,
+    },
+    /// Fires on release, with power scaling up while held. Holding too long past full charge
+    /// overcharges the weapon, which fizzles and locks it for a while.
+    Charge {
+        charge_secs: f32,
+        /// Power at full charge. An uncharged shot has a power of 1.
+        max_power: f32,
+        overcharge_secs: f32,
+        penalty_secs: f32,
+    },
+}
+
+/// Terrain destroyed by each projectile that hits it.
+#[derive(Clone, Copy, Debug)]
+pub struct WeaponImpact {
+    pub radius: f32,
+    /// Multiplied by the power of the shot.
+    pub force: f32,
+    pub damage: DamageType,
+}
+
 pub enum WeaponAction {
     Ranged {
         spread: RangedSpread,
         mode: RangedMode,
+        trigger: WeaponTrigger,
+        impact: WeaponImpact,
         projectiles: usize,
     },
+    /// Fires a hook that trails a cable, and anchors it to whatever it hits. Firing again lets go.
+    Grapple {
+        range: f32,
+        hook_speed: f32,
+        /// How quickly the cable shortens while reeling in, in meters per second.
+        reel_speed: f32,
+        /// Acceleration toward the anchor while the cable is taut.
+        pull: f32,
+    },
 }
 
 pub struct Weapon {
@@ -39,6 +105,12 @@ pub struct Weapon {
     pub model: &'static str,
     pub action: WeaponAction,
     pub viewmodel_offset: Vec3,
+    /// Whether the view model casts shadows into the world.
+    pub world_shadows: bool,
+    /// How hard the view model kicks back when firing.
+    pub recoil: f32,
+    /// Reaches for held cables.
+    pub off_hand: Option<OffHandIk>,
 }
 
 #[derive(Component)]
@@ -97,13 +169,59 @@ pub struct SwitchWeaponEvent {
     pub slot: usize,
 }
 
+/// Sent once per trigger pull that fires, no matter how many projectiles it fires.
+#[derive(Event)]
+pub struct WeaponFiredEvent {
+    pub shooter: Entity,
+    /// Only charged shots have a power other than 1.
+    pub power: f32,
+}
+
 pub struct WeaponPlugin;
 
 impl Plugin for WeaponPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((ViewModelPlugin, WeaponPickupPlugin));
+        if !app.is_plugin_added::<InputBindingsPlugin>() {
+            app.add_plugins(InputBindingsPlugin);
+        }
+        if !app.is_plugin_added::<HealthPlugin>() {
+            app.add_plugins(HealthPlugin);
+        }
+        app.add_plugins((
+            ViewModelPlugin,
+            ViewModelAnimationPlugin,
+            WeaponPickupPlugin,
+            DeflectPlugin,
+            FirePlugin,
+            GrapplePlugin,
+            ShieldPlugin,
+            ScannerPlugin,
+            RadialMenuPlugin,
+            OffHandPlugin,
+        ));
         app.add_event::<SwitchWeaponEvent>();
-        app.add_systems(Update, switch_weapons);
+        app.add_event::<WeaponFiredEvent>();
+        app.add_systems(
+            Update,
+            (select_slots.run_if(player_alive), switch_weapons).chain(),
+        );
+    }
+}
+
+fn select_slots(
+    input: ActionInput,
+    shooters: Query<(Entity, &WeaponSlots), With<PlayerWeapons>>,
+    mut events: EventWriter<SwitchWeaponEvent>,
+) {
+    for (shooter, slots) in shooters.iter() {
+        let Some(slot) =
+            (0..slots.capacity).find(|slot| input.just_pressed(InputAction::WeaponSlot(*slot)))
+        else {
+            continue;
+        };
+        if slot != slots.current {
+            events.send(SwitchWeaponEvent { shooter, slot });
+        }
     }
 }
 
@@ -112,6 +230,8 @@ fn switch_weapons(
     mut events: EventReader<SwitchWeaponEvent>,
     mut weapons: Query<(&mut WeaponSlots, &PlayerWeapons)>,
     cameras: Query<Entity, With<ViewModelCamera>>,
+    children: Query<&Children>,
+    mut animations: Query<&mut ViewModelAnimation>,
     asset_server: Res<AssetServer>,
 ) {
     for event in events.read() {
@@ -122,20 +242,42 @@ fn switch_weapons(
             continue;
         };
 
-        commands.entity(camera).despawn_descendants();
+        // The previous view model is despawned once it's lowered
+        let mut lowering = false;
+        for child in children.get(camera).into_iter().flatten() {
+            match animations.get_mut(*child) {
+                Ok(mut animation) => {
+                    animation.lower();
+                    lowering = true;
+                }
+                Err(_) => commands.entity(*child).despawn_recursive(),
+            }
+        }
 
         let Some(weapon) = slots.switch(event.slot) else {
             continue;
         };
 
         let child = commands
-            .spawn((Transform::default(), ViewModel::default()))
+            .spawn((
+                Transform::default(),
+                ViewModel::default(),
+                ViewModelAnimation::new(event.shooter, lowering),
+            ))
             .with_children(|parent| {
                 parent.spawn((
                     Transform::from_translation(weapon.viewmodel_offset),
                     NeedsRenderLayers(RenderLayers::layer(render_layer::VIEW_MODEL)),
+                    ViewModelShadows(weapon.world_shadows),
                     SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(weapon.model))),
                 ));
+                if let Some(ik) = &weapon.off_hand {
+                    parent.spawn((
+                        Transform::default(),
+                        Visibility::default(),
+                        OffHand::new(ik),
+                    ));
+                }
             })
             .id();
 
@@ -1,9 +1,29 @@
 use std::f32::consts::PI;
 
 use avian3d::prelude::*;
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
 
-use super::{SwitchWeaponEvent, Weapon, WeaponSlots};
+use crate::{
+    input::{ActionInput, InputAction, InputBindings},
+    physics::GameLayer,
+    worldgen::terrain::PlaceOnTerrain,
+};
+
+use super::{PlayerWeapons, RadialMenu, SwitchWeaponEvent, Weapon, WeaponSlots};
+
+/// Height of the model above the pickup's origin, before bouncing.
+const MODEL_HEIGHT: f32 = 1.35;
+/// Pickups can be equipped from this far away, measured from the eye to the model.
+const INTERACT_RANGE: f32 = 3.0;
+/// Pickups within this many degrees of the crosshair can be aimed at.
+const AIM_ANGLE: f32 = 12.0;
+const HIGHLIGHT_COLOR: Color = Color::srgb(1.0, 0.8, 0.2);
+const HIGHLIGHT_RADIUS: f32 = 0.5;
+/// Dropped weapons are thrown forward at this speed, on top of the shooter's velocity.
+const DROP_SPEED: f32 = 4.0;
+/// How far in front of the eye dropped weapons appear.
+const DROP_DISTANCE: f32 = 0.5;
 
 #[derive(Resource)]
 pub struct PickupSfx(pub Handle<AudioSource>);
@@ -25,12 +45,36 @@ impl WeaponPickup {
 #[derive(Component)]
 pub struct WeaponPickupChild;
 
+/// Dropped weapon that's still in the air. It can be picked up once it lands.
+#[derive(Component)]
+pub struct DroppedWeapon {
+    pub velocity: Vec3,
+}
+
+/// The pickup the player is aiming at, if any.
+#[derive(Resource, Default)]
+pub struct AimedPickup(pub Option<Entity>);
+
+/// Empties the shooter's current slot, and throws the weapon that was in it.
+#[derive(Event)]
+pub struct DropWeaponEvent {
+    pub shooter: Entity,
+}
+
 pub struct WeaponPickupPlugin;
 
 impl Plugin for WeaponPickupPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<AimedPickup>();
+        app.add_event::<DropWeaponEvent>();
         app.add_systems(Startup, setup);
-        app.add_systems(Update, (add_required_components, animate, pickup));
+        app.add_systems(
+            Update,
+            (
+                (add_required_components, animate, fly),
+                (aim, interact, request_drop, drop_weapons, draw_prompt).chain(),
+            ),
+        );
     }
 }
 
@@ -41,9 +85,9 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 fn add_required_components(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    pickups: Query<(Entity, &WeaponPickup), Added<WeaponPickup>>,
+    pickups: Query<(Entity, &WeaponPickup, Has<DroppedWeapon>), Added<WeaponPickup>>,
 ) {
-    pickups.iter().for_each(|(entity, pickup)| {
+    pickups.iter().for_each(|(entity, pickup, dropped)| {
         let child = commands
             .spawn((
                 WeaponPickupChild,
@@ -62,18 +106,29 @@ fn add_required_components(
         ));
         commands.insert_if_new(Transform::default());
         commands.insert_if_new(Visibility::Visible);
+        // Dropped weapons are placed once they land
+        if !dropped {
+            commands.insert_if_new(place_pickup());
+        }
     });
 }
 
+/// The pickup's origin is on the floor, below its sensor, and the model hovers above it.
+fn place_pickup() -> PlaceOnTerrain {
+    PlaceOnTerrain {
+        offset: Some(0.0),
+        ..default()
+    }
+}
+
 fn animate(time: Res<Time>, mut pickups: Query<&mut Transform, With<WeaponPickupChild>>) {
     const SECONDS_PER_ROTATION: f32 = 5.0;
     const SECONDS_PER_BOUNCE: f32 = 0.65;
-    const Y: f32 = 1.35;
     const BOUNCE_HEIGHT: f32 = 0.125;
     const CIRCLE: f32 = PI * 2.0;
 
     pickups.iter_mut().for_each(|mut pickup| {
-        pickup.translation.y = Y
+        pickup.translation.y = MODEL_HEIGHT
             + (time.elapsed_secs_wrapped() / SECONDS_PER_BOUNCE)
                 .sin()
                 .abs()
@@ -87,34 +142,210 @@ fn animate(time: Res<Time>, mut pickups: Query<&mut Transform, With<WeaponPickup
     });
 }
 
-fn pickup(
+fn fly(
+    mut commands: Commands,
+    time: Res<Time>,
+    gravity: Res<Gravity>,
+    spatial_query: SpatialQuery,
+    mut pickups: Query<(
+        Entity,
+        &mut DroppedWeapon,
+        &mut WeaponPickup,
+        &mut Transform,
+    )>,
+) {
+    let filter = SpatialQueryFilter::from_mask(GameLayer::World);
+
+    for (entity, mut dropped, mut pickup, mut transform) in pickups.iter_mut() {
+        dropped.velocity += gravity.0 * time.delta_secs();
+        let Ok(direction) = Dir3::new(dropped.velocity) else {
+            continue;
+        };
+        let step = dropped.velocity.length() * time.delta_secs();
+        let origin = transform.translation;
+
+        let Some(hit) = spatial_query.cast_ray(origin, direction, step, true, &filter) else {
+            transform.translation += direction * step;
+            continue;
+        };
+
+        // Weapons that land on walls or ceilings drop down to the floor below
+        transform.translation = origin + direction * hit.distance + hit.normal * 0.05;
+        pickup.active = true;
+        commands
+            .entity(entity)
+            .remove::<DroppedWeapon>()
+            .insert(place_pickup());
+    }
+}
+
+fn aim(
+    mut aimed: ResMut<AimedPickup>,
+    spatial_query: SpatialQuery,
+    player: Option<Single<&PlayerWeapons>>,
+    transforms: Query<&GlobalTransform>,
+    pickups: Query<(Entity, &WeaponPickup, &GlobalTransform)>,
+) {
+    aimed.0 = None;
+    let Some(eye) = player.and_then(|player| transforms.get(player.viewmodel_camera).ok()) else {
+        return;
+    };
+
+    let origin = eye.translation();
+    let forward = eye.forward();
+    let min_cos = AIM_ANGLE.to_radians().cos();
+    let filter = SpatialQueryFilter::from_mask(GameLayer::World);
+
+    aimed.0 = pickups
+        .iter()
+        .filter(|(_, pickup, _)| pickup.active)
+        .filter_map(|(entity, _, transform)| {
+            let to_model = transform.translation() + Vec3::Y * MODEL_HEIGHT - origin;
+            let distance = to_model.length();
+            let direction = Dir3::new(to_model).ok()?;
+            let cos = direction.dot(*forward);
+            if distance > INTERACT_RANGE || cos < min_cos {
+                return None;
+            }
+
+            // Walls between the eye and the pickup hide it
+            let occluded = spatial_query
+                .cast_ray(origin, direction, distance, true, &filter)
+                .is_some();
+            (!occluded).then_some((entity, cos))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity);
+}
+
+fn interact(
     sfx: Res<PickupSfx>,
     mut commands: Commands,
-    mut collisions: EventReader<CollisionStarted>,
+    input: ActionInput,
+    aimed: Res<AimedPickup>,
     mut switch_weapons: EventWriter<SwitchWeaponEvent>,
-    mut slots: Query<(Entity, &mut WeaponSlots)>,
-    mut pickups: Query<(Entity, &mut WeaponPickup)>,
+    player: Option<Single<(Entity, &mut WeaponSlots), With<PlayerWeapons>>>,
+    mut pickups: Query<&mut WeaponPickup>,
+) {
+    let (Some(player), Some(pickup_entity)) = (player, aimed.0) else {
+        return;
+    };
+    if !input.just_pressed(InputAction::Interact) {
+        return;
+    }
+    let (shooter, mut slots) = player.into_inner();
+    let Ok(mut pickup) = pickups.get_mut(pickup_entity) else {
+        return;
+    };
+
+    let Some(slot) = slots.equip(pickup.weapon, None) else {
+        return;
+    };
+
+    pickup.active = false;
+    commands.entity(pickup_entity).despawn_recursive();
+    commands.spawn((AudioPlayer::new(sfx.0.clone()), PlaybackSettings::DESPAWN));
+    switch_weapons.send(SwitchWeaponEvent { shooter, slot });
+}
+
+fn request_drop(
+    input: ActionInput,
+    menu: Res<RadialMenu>,
+    player: Option<Single<Entity, With<PlayerWeapons>>>,
+    mut events: EventWriter<DropWeaponEvent>,
 ) {
-    for CollisionStarted(entity1, entity2) in collisions.read() {
-        let ((pickup_entity, mut pickup), (shooter, mut slots)) =
-            match (pickups.get_mut(*entity1), slots.get_mut(*entity2)) {
-                (Ok(pickup), Ok(shooter)) => (pickup, shooter),
-                _ => match (pickups.get_mut(*entity2), slots.get_mut(*entity1)) {
-                    (Ok(pickup), Ok(shooter)) => (pickup, shooter),
-                    _ => continue,
-                },
-            };
-        if !pickup.active {
+    let Some(shooter) = player else {
+        return;
+    };
+    if !menu.is_open() && input.just_pressed(InputAction::DropWeapon) {
+        events.send(DropWeaponEvent { shooter: *shooter });
+    }
+}
+
+fn drop_weapons(
+    mut commands: Commands,
+    mut events: EventReader<DropWeaponEvent>,
+    mut switch_weapons: EventWriter<SwitchWeaponEvent>,
+    mut shooters: Query<(&mut WeaponSlots, &PlayerWeapons, Option<&LinearVelocity>)>,
+    transforms: Query<&GlobalTransform>,
+) {
+    for event in events.read() {
+        let Ok((mut slots, weapons, velocity)) = shooters.get_mut(event.shooter) else {
             continue;
         };
-
-        let Some(slot) = slots.equip(pickup.weapon, None) else {
+        let Ok(eye) = transforms.get(weapons.viewmodel_camera) else {
             continue;
         };
+        let current = slots.current;
+        let Some(weapon) = slots.weapons.get_mut(current).and_then(Option::take) else {
+            continue;
+        };
+
+        // The model starts out in front of the eye
+        let forward = eye.forward();
+        let position = eye.translation() + forward * DROP_DISTANCE - Vec3::Y * MODEL_HEIGHT;
+        let inherited = velocity.map_or(Vec3::ZERO, |velocity| velocity.0);
+        commands.spawn((
+            WeaponPickup {
+                weapon,
+                active: false,
+            },
+            DroppedWeapon {
+                velocity: inherited + forward * DROP_SPEED,
+            },
+            Transform::from_translation(position),
+        ));
 
-        pickup.active = false;
-        commands.entity(pickup_entity).despawn_recursive();
-        commands.spawn((AudioPlayer::new(sfx.0.clone()), PlaybackSettings::DESPAWN));
-        switch_weapons.send(SwitchWeaponEvent { shooter, slot });
+        // Switching to the now empty slot lowers the view model
+        switch_weapons.send(SwitchWeaponEvent {
+            shooter: event.shooter,
+            slot: current,
+        });
     }
 }
+
+fn draw_prompt(
+    mut contexts: EguiContexts,
+    mut gizmos: Gizmos,
+    bindings: Res<InputBindings>,
+    aimed: Res<AimedPickup>,
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    player: Option<Single<&WeaponSlots, With<PlayerWeapons>>>,
+    pickups: Query<(&WeaponPickup, &GlobalTransform)>,
+) {
+    let (Some(window), Some(slots)) = (window, player) else {
+        return;
+    };
+    let Some((pickup, transform)) = aimed.0.and_then(|entity| pickups.get(entity).ok()) else {
+        return;
+    };
+    if window.cursor_options.visible {
+        return;
+    }
+
+    gizmos.sphere(
+        Isometry3d::from_translation(transform.translation() + Vec3::Y * MODEL_HEIGHT),
+        HIGHLIGHT_RADIUS,
+        HIGHLIGHT_COLOR,
+    );
+
+    let name = pickup.weapon.name;
+    let text = match (
+        slots.first_empty_slot(),
+        bindings.get(InputAction::Interact).first(),
+    ) {
+        (None, _) => format!("{name}  |  No free slots"),
+        (Some(_), Some(binding)) => format!("[{binding}] Pick up {name}"),
+        (Some(_), None) => format!("{name}  |  {} is unbound", InputAction::Interact),
+    };
+    egui::Area::new(egui::Id::new("pickup_prompt"))
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 48.0))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(text)
+                    .strong()
+                    .color(egui::Color32::from_rgb(255, 204, 51)),
+            );
+        });
+}
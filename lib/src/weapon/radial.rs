@@ -0,0 +1,238 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::input::{ActionInput, InputAction};
+
+use super::{PlayerWeapons, SwitchWeaponEvent, WeaponSlots};
+
+/// Distance from the center of the menu to the center of each slot, in points.
+const MENU_RADIUS: f32 = 120.0;
+const SLOT_RADIUS: f32 = 36.0;
+/// Pointer distance from the center, relative to the menu radius, below which nothing is
+/// selected. Releasing inside the deadzone keeps the current weapon.
+const DEADZONE: f32 = 0.35;
+/// Scroll distance in pixels that counts as one line.
+const PIXELS_PER_SCROLL_LINE: f32 = 32.0;
+
+/// Hold to open, point at a slot, and release to switch to it. The pointer is driven by mouse
+/// motion or the right stick.
+#[derive(Resource, Default)]
+pub struct RadialMenu {
+    open: bool,
+    pointer: Vec2,
+    hovered: Option<usize>,
+    scroll: f32,
+}
+
+impl RadialMenu {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update_hovered(&mut self, capacity: usize) {
+        if capacity == 0 || self.pointer.length() < MENU_RADIUS * DEADZONE {
+            self.hovered = None;
+            return;
+        }
+
+        // Slots go clockwise from the top, and screen space y points down
+        let angle = self.pointer.x.atan2(-self.pointer.y).rem_euclid(TAU);
+        let slot_angle = TAU / capacity as f32;
+        self.hovered = Some((angle / slot_angle).round() as usize % capacity);
+    }
+}
+
+/// Direction of a slot from the center of the menu, in screen space.
+fn slot_direction(slot: usize, capacity: usize) -> Vec2 {
+    let angle = slot as f32 / capacity as f32 * TAU;
+    Vec2::new(angle.sin(), -angle.cos())
+}
+
+pub struct RadialMenuPlugin;
+
+impl Plugin for RadialMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RadialMenu>();
+        app.add_systems(Update, (update_menu, scroll_weapons, draw_menu).chain());
+    }
+}
+
+fn update_menu(
+    mut menu: ResMut<RadialMenu>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut switch_weapons: EventWriter<SwitchWeaponEvent>,
+    input: ActionInput,
+    gamepads: Query<&Gamepad>,
+    player: Option<Single<(Entity, &WeaponSlots), With<PlayerWeapons>>>,
+) {
+    let Some(player) = player else {
+        menu.open = false;
+        mouse_motion.clear();
+        return;
+    };
+    let (shooter, slots) = player.into_inner();
+
+    let pressed = input.just_pressed(InputAction::RadialMenu);
+    let held = input.pressed(InputAction::RadialMenu);
+
+    if pressed && !menu.open {
+        menu.open = true;
+        menu.pointer = Vec2::ZERO;
+        menu.hovered = None;
+    }
+    if !menu.open {
+        mouse_motion.clear();
+        return;
+    }
+
+    // Pointer
+    let motion = mouse_motion.read().map(|event| event.delta).sum::<Vec2>();
+    menu.pointer = (menu.pointer + motion).clamp_length_max(MENU_RADIUS);
+    if let Some(stick) = gamepads
+        .iter()
+        .map(|pad| pad.right_stick())
+        .find(|stick| stick.length() >= DEADZONE)
+    {
+        menu.pointer = Vec2::new(stick.x, -stick.y).clamp_length_max(1.0) * MENU_RADIUS;
+    }
+    menu.update_hovered(slots.capacity);
+
+    // Select
+    if !held {
+        menu.open = false;
+        let Some(slot) = menu.hovered else {
+            return;
+        };
+        if slot != slots.current && slots.weapons.get(slot).is_some_and(Option::is_some) {
+            switch_weapons.send(SwitchWeaponEvent { shooter, slot });
+        }
+    }
+}
+
+/// Cycles through occupied slots with the mouse wheel.
+fn scroll_weapons(
+    mut menu: ResMut<RadialMenu>,
+    mut wheel: EventReader<MouseWheel>,
+    mut switch_weapons: EventWriter<SwitchWeaponEvent>,
+    player: Option<Single<(Entity, &WeaponSlots), With<PlayerWeapons>>>,
+) {
+    let delta = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_SCROLL_LINE,
+        })
+        .sum::<f32>();
+    let Some(player) = player else {
+        return;
+    };
+    if menu.open {
+        menu.scroll = 0.0;
+        return;
+    }
+
+    menu.scroll += delta;
+    let steps = menu.scroll.trunc() as isize;
+    if steps == 0 {
+        return;
+    }
+    menu.scroll -= steps as f32;
+
+    let (shooter, slots) = player.into_inner();
+    let capacity = slots.capacity as isize;
+    if capacity == 0 {
+        return;
+    }
+
+    // Scrolling up goes to the previous weapon
+    let direction = -steps.signum();
+    let mut slot = slots.current as isize;
+    for _ in 0..steps.abs() {
+        let next = (1..=capacity)
+            .map(|offset| (slot + offset * direction).rem_euclid(capacity))
+            .find(|slot| slots.weapons[*slot as usize].is_some());
+        let Some(next) = next else {
+            return;
+        };
+        slot = next;
+    }
+
+    if slot as usize != slots.current {
+        switch_weapons.send(SwitchWeaponEvent {
+            shooter,
+            slot: slot as usize,
+        });
+    }
+}
+
+fn draw_menu(
+    menu: Res<RadialMenu>,
+    mut contexts: EguiContexts,
+    player: Option<Single<&WeaponSlots, With<PlayerWeapons>>>,
+) {
+    let Some(slots) = player else {
+        return;
+    };
+    if !menu.open || slots.capacity == 0 {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("radial_menu"),
+    ));
+    let center = ctx.screen_rect().center();
+    let visuals = ctx.style().visuals.clone();
+
+    painter.circle_filled(
+        center,
+        MENU_RADIUS + SLOT_RADIUS + 8.0,
+        visuals.window_fill.gamma_multiply(0.6),
+    );
+
+    for (slot, weapon) in slots.weapons.iter().enumerate() {
+        let direction = slot_direction(slot, slots.capacity) * MENU_RADIUS;
+        let position = center + egui::vec2(direction.x, direction.y);
+        let hovered = menu.hovered == Some(slot);
+
+        let fill = if hovered {
+            visuals.selection.bg_fill
+        } else if slot == slots.current {
+            visuals.widgets.active.bg_fill
+        } else {
+            visuals.widgets.inactive.bg_fill
+        };
+        painter.circle_filled(position, SLOT_RADIUS, fill);
+
+        match weapon {
+            Some(weapon) => {
+                painter.text(
+                    position,
+                    egui::Align2::CENTER_CENTER,
+                    weapon.name,
+                    egui::FontId::proportional(12.0),
+                    visuals.text_color(),
+                );
+            }
+            None => {
+                painter.text(
+                    position,
+                    egui::Align2::CENTER_CENTER,
+                    format!("{}", slot + 1),
+                    egui::FontId::proportional(12.0),
+                    visuals.weak_text_color(),
+                );
+            }
+        }
+    }
+
+    // Pointer
+    let pointer = center + egui::vec2(menu.pointer.x, menu.pointer.y);
+    painter.circle_filled(pointer, 4.0, visuals.text_color());
+}
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+
+use crate::{
+    input::{ActionInput, InputAction},
+    materials::CaveMaterial,
+    player::IsPlayer,
+    worldgen::{
+        consts::CHUNK_SIZE_F,
+        terrain::{ChunkComposition, VoxelMaterials},
+    },
+};
+
+/// Speed of the pulse, in meters per second.
+const SCAN_SPEED: f32 = 30.0;
+const SCAN_RANGE: f32 = 48.0;
+/// How long highlights remain after the pulse reaches its full range.
+const SCAN_FADE_SECS: f32 = 3.0;
+const SCAN_COOLDOWN_SECS: f32 = 5.0;
+const SCAN_BATTERY_COST: f32 = 0.25;
+/// Battery recharged per second.
+const SCAN_BATTERY_RECHARGE: f32 = 0.02;
+/// Chunks with at least this share of valuable voxels are marked through walls.
+const MARKER_MIN_FRACTION: f32 = 0.01;
+const MARKER_COLOR: Color = Color::srgb(0.3, 1.0, 0.8);
+
+/// Prospecting scanner. Sends out a pulse that highlights valuable materials nearby.
+#[derive(Component)]
+pub struct Scanner {
+    /// From 0.0 (empty) to 1.0 (full).
+    pub battery: f32,
+    ready_secs: f32,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            battery: 1.0,
+            ready_secs: 0.0,
+        }
+    }
+}
+
+impl Scanner {
+    pub fn ready(&self, time: &Time) -> bool {
+        self.battery >= SCAN_BATTERY_COST && time.elapsed_secs() >= self.ready_secs
+    }
+}
+
+#[derive(Resource)]
+struct ScanPulse {
+    origin: Vec3,
+    start_secs: f32,
+}
+
+impl ScanPulse {
+    fn radius(&self, time: &Time) -> f32 {
+        ((time.elapsed_secs() - self.start_secs) * SCAN_SPEED).min(SCAN_RANGE)
+    }
+
+    fn intensity(&self, time: &Time) -> f32 {
+        let fading_secs = time.elapsed_secs() - self.start_secs - SCAN_RANGE / SCAN_SPEED;
+        (1.0 - fading_secs / SCAN_FADE_SECS).clamp(0.0, 1.0)
+    }
+}
+
+/// Drawn on top of everything, so markers show through walls.
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct ScannerGizmos;
+
+pub struct ScannerPlugin;
+
+impl Plugin for ScannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_gizmo_group::<ScannerGizmos>();
+        app.insert_gizmo_config(
+            ScannerGizmos,
+            GizmoConfig {
+                depth_bias: -1.0,
+                ..default()
+            },
+        );
+        app.add_systems(
+            Update,
+            (equip_scanner, use_scanner, update_pulse, draw_markers).chain(),
+        );
+    }
+}
+
+fn equip_scanner(
+    mut commands: Commands,
+    players: Query<Entity, (With<IsPlayer>, Without<Scanner>)>,
+) {
+    for player in players.iter() {
+        commands.entity(player).insert(Scanner::default());
+    }
+}
+
+fn use_scanner(
+    mut commands: Commands,
+    time: Res<Time>,
+    input: ActionInput,
+    mut scanners: Query<(&GlobalTransform, &mut Scanner)>,
+) {
+    for (transform, mut scanner) in scanners.iter_mut() {
+        scanner.battery = (scanner.battery + SCAN_BATTERY_RECHARGE * time.delta_secs()).min(1.0);
+
+        if !input.just_pressed(InputAction::Scan) || !scanner.ready(&time) {
+            continue;
+        }
+
+        scanner.battery -= SCAN_BATTERY_COST;
+        scanner.ready_secs = time.elapsed_secs() + SCAN_COOLDOWN_SECS;
+        commands.insert_resource(ScanPulse {
+            origin: transform.translation(),
+            start_secs: time.elapsed_secs(),
+        });
+    }
+}
+
+fn update_pulse(
+    mut commands: Commands,
+    time: Res<Time>,
+    pulse: Option<Res<ScanPulse>>,
+    voxel_materials: Res<VoxelMaterials>,
+    mut materials: ResMut<Assets<CaveMaterial>>,
+) {
+    let Some(pulse) = pulse else {
+        return;
+    };
+
+    let scan_materials = voxel_materials.valuable_textures();
+    let intensity = pulse.intensity(&time);
+    for (_, material) in materials.iter_mut() {
+        let extension = &mut material.extension;
+        extension.scan_origin = pulse.origin;
+        extension.scan_radius = pulse.radius(&time);
+        extension.scan_intensity = intensity;
+        extension.scan_materials = scan_materials;
+    }
+
+    if intensity <= 0.0 {
+        commands.remove_resource::<ScanPulse>();
+    }
+}
+
+/// Marks scanned chunks that contain valuable materials, sized by how much they contain.
+fn draw_markers(
+    mut gizmos: Gizmos<ScannerGizmos>,
+    time: Res<Time>,
+    pulse: Option<Res<ScanPulse>>,
+    materials: Res<VoxelMaterials>,
+    chunks: Query<(&GlobalTransform, &ChunkComposition)>,
+) {
+    let Some(pulse) = pulse else {
+        return;
+    };
+
+    let radius = pulse.radius(&time);
+    let color = MARKER_COLOR.with_alpha(pulse.intensity(&time));
+    for (transform, composition) in chunks.iter() {
+        let center = transform.translation() + CHUNK_SIZE_F / 2.0;
+        if center.distance(pulse.origin) > radius {
+            continue;
+        }
+
+        let fraction = composition
+            .iter()
+            .filter(|(material, _)| materials.get(*material).valuable)
+            .map(|(material, _)| composition.fraction(material))
+            .sum::<f32>();
+        if fraction < MARKER_MIN_FRACTION {
+            continue;
+        }
+
+        gizmos.sphere(
+            Isometry3d::from_translation(center),
+            0.5 + fraction * 4.0,
+            color,
+        );
+    }
+}
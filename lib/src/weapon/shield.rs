@@ -0,0 +1,102 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    input::{ActionInput, InputAction},
+    player::PlayerCamera,
+    worldgen::{
+        brush::{primitive::BrushPrimitive, BrushOperation, TerrainBrush},
+        layout::LayoutState,
+        voxel::VoxelMaterial,
+    },
+};
+
+/// Large enough to always contain terrain samples, which are 4 meters apart at standard detail.
+pub const SHIELD_SIZE: Vec3 = Vec3::new(8.0, 6.0, 4.0);
+pub const SHIELD_DURATION: f32 = 8.0;
+/// Deflective, so shots bounce off the barrier like they do off crystal in caves.
+pub const SHIELD_MATERIAL: VoxelMaterial = VoxelMaterial::Crystal;
+/// Distance between the player and a deployed shield.
+const SHIELD_DEPLOY_DISTANCE: f32 = 2.5;
+
+/// A terrain brush that fills in a barrier until it expires.
+#[derive(Component)]
+pub struct ShieldBarrier {
+    pub expires: f32,
+}
+
+pub struct DeployShieldCommand {
+    pub transform: Transform,
+    pub duration: f32,
+}
+
+impl DeployShieldCommand {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            duration: SHIELD_DURATION,
+        }
+    }
+}
+
+impl Command for DeployShieldCommand {
+    fn apply(self, world: &mut World) {
+        let expires = world.resource::<Time>().elapsed_secs() + self.duration;
+        let sequence = world
+            .get_resource::<LayoutState>()
+            .map_or(0, |state| state.sequence);
+
+        world.spawn((
+            ShieldBarrier { expires },
+            TerrainBrush::primitive(
+                "",
+                sequence,
+                SHIELD_MATERIAL,
+                BrushPrimitive::Box {
+                    half_extents: SHIELD_SIZE / 2.0,
+                },
+                self.transform,
+            )
+            .with_operation(BrushOperation::Fill),
+        ));
+    }
+}
+
+pub struct ShieldPlugin;
+
+impl Plugin for ShieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (deploy_shield, expire_barriers));
+    }
+}
+
+fn deploy_shield(
+    mut commands: Commands,
+    input: ActionInput,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Option<Single<&GlobalTransform, With<PlayerCamera>>>,
+) {
+    if !input.just_pressed(InputAction::DeployShield) || window.cursor_options.visible {
+        return;
+    }
+    let Some(camera) = camera else {
+        return;
+    };
+
+    let forward = camera.forward().with_y(0.0).normalize_or(Vec3::NEG_Z);
+    let position = camera.translation() + forward * (SHIELD_SIZE.z / 2.0 + SHIELD_DEPLOY_DISTANCE);
+    let transform = Transform::from_translation(position).looking_to(forward, Vec3::Y);
+
+    commands.queue(DeployShieldCommand::new(transform));
+}
+
+fn expire_barriers(
+    mut commands: Commands,
+    time: Res<Time>,
+    barriers: Query<(Entity, &ShieldBarrier)>,
+) {
+    for (entity, barrier) in barriers.iter() {
+        if time.elapsed_secs() >= barrier.expires {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
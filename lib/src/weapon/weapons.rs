@@ -1,6 +1,18 @@
 use bevy::prelude::*;
 
-use super::{RangedMode, RangedSpread, Weapon, WeaponAction};
+use crate::worldgen::voxel::DamageType;
+
+use super::{
+    OffHandIk, RangedMode, RangedSpread, Weapon, WeaponAction, WeaponImpact, WeaponTrigger,
+};
+
+const OFF_HAND: OffHandIk = OffHandIk {
+    shoulder: Vec3::new(-0.2, -0.3, 0.05),
+    upper_length: 0.28,
+    lower_length: 0.26,
+    pole: Vec3::new(-0.6, -0.5, 0.0),
+    rest: Vec3::new(-0.15, -0.6, -0.1),
+};
 
 pub const SHOTGUN: Weapon = Weapon {
     name: "Shotgun",
@@ -8,7 +20,31 @@ pub const SHOTGUN: Weapon = Weapon {
     action: WeaponAction::Ranged {
         spread: RangedSpread::Circle(10.0),
         mode: RangedMode::Hitscan,
+        trigger: WeaponTrigger::SemiAuto { rpm: 70.0 },
+        impact: WeaponImpact {
+            radius: 0.5,
+            force: 0.6,
+            damage: DamageType::Kinetic,
+        },
         projectiles: 8,
     },
     viewmodel_offset: Vec3::new(0.175, -0.125, -0.4),
+    world_shadows: true,
+    recoil: 1.0,
+    off_hand: Some(OFF_HAND),
+};
+
+pub const GRAPPLE: Weapon = Weapon {
+    name: "Grapple",
+    model: "models/weapon/grapple.glb",
+    action: WeaponAction::Grapple {
+        range: 48.0,
+        hook_speed: 96.0,
+        reel_speed: 8.0,
+        pull: 40.0,
+    },
+    viewmodel_offset: Vec3::new(0.175, -0.125, -0.4),
+    world_shadows: true,
+    recoil: 0.3,
+    off_hand: Some(OFF_HAND),
 };
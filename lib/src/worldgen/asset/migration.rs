@@ -0,0 +1,109 @@
+use std::marker::PhantomData;
+
+use anyhow::anyhow;
+use cbor4ii::{core::utils::SliceReader, serde::Deserializer as CborDeserializer};
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed},
+    Deserialize, Deserializer,
+};
+
+/// Assets that store the version of the schema they were saved with, in a field named
+/// `version`. Files saved before versioning was added are version 0.
+///
+/// Older versions are read with their own types, which are converted to the current one.
+pub trait Versioned: DeserializeOwned + 'static {
+    /// Version written by this build.
+    const VERSION: u32;
+    /// What changed in each version, starting with the change from version 0. There's one for
+    /// every version before [`Self::VERSION`].
+    const CHANGES: &'static [&'static str];
+
+    /// Reads a value saved with an older version. Versions that store the same data as the
+    /// current one don't need to be handled, since they're read as the current type by default.
+    fn deserialize_legacy<'de, D>(version: u32, deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let _ = version;
+        Self::deserialize(deserializer)
+    }
+
+    /// Descriptions of the changes made since `version`.
+    fn changes_since(version: u32) -> anyhow::Result<&'static [&'static str]> {
+        if version > Self::VERSION {
+            return Err(anyhow!(
+                "saved with version {version}, but this build only reads up to version {}",
+                Self::VERSION
+            ));
+        }
+        Self::CHANGES
+            .get(version as usize..)
+            .ok_or_else(|| anyhow!("no changes listed for version {version}"))
+    }
+
+    /// Reads RON saved with any version up to [`Self::VERSION`]. Returns the descriptions of the
+    /// changes made since it was saved.
+    fn from_ron(s: &str) -> anyhow::Result<(Self, Vec<&'static str>)> {
+        let version = version_of(&ron::from_str(s)?)?;
+        let changes = Self::changes_since(version)?;
+
+        let mut deserializer = ron::Deserializer::from_str(s)?;
+        let value = VersionSeed::new(version).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+
+        Ok((value, changes.to_vec()))
+    }
+
+    /// Reads CBOR saved with any version up to [`Self::VERSION`]. See [`Self::from_ron`].
+    fn from_cbor(bytes: &[u8]) -> anyhow::Result<(Self, Vec<&'static str>)> {
+        let version = version_of(&cbor4ii::serde::from_slice(bytes)?)?;
+        let changes = Self::changes_since(version)?;
+
+        let mut deserializer = CborDeserializer::new(SliceReader::new(bytes));
+        let value = VersionSeed::new(version).deserialize(&mut deserializer)?;
+
+        Ok((value, changes.to_vec()))
+    }
+}
+
+/// Reads the `version` field of an untyped asset.
+pub fn version_of(value: &ron::Value) -> anyhow::Result<u32> {
+    let ron::Value::Map(fields) = value else {
+        return Err(anyhow!("expected a struct"));
+    };
+
+    match fields.get(&ron::Value::String("version".to_string())) {
+        Some(version) => Ok(version.clone().into_rust()?),
+        None => Ok(0),
+    }
+}
+
+/// Reads a [`Versioned`] value saved with a known version, for assets nested in other types.
+pub struct VersionSeed<T> {
+    version: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T> VersionSeed<T> {
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T: Versioned> DeserializeSeed<'de> for VersionSeed<T> {
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if self.version == T::VERSION {
+            T::deserialize(deserializer)
+        } else {
+            T::deserialize_legacy(self.version, deserializer)
+        }
+    }
+}
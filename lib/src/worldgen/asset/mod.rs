@@ -1,19 +1,84 @@
-use bevy::prelude::Resource;
+use std::ops::RangeInclusive;
+
+use bevy::prelude::{default, Resource};
 use bevy_rand::prelude::*;
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
+pub mod migration;
+pub mod pack;
 mod room;
 mod tunnel;
+use migration::Versioned;
 pub use room::*;
 pub use tunnel::*;
 
-#[derive(Serialize, Deserialize, Debug, Default, Resource)]
+/// Constraints for choosing rooms. The default matches every room.
+#[derive(Clone, Debug, Default)]
+pub struct RoomQuery {
+    /// Rooms must have all of these flags.
+    pub flags: RoomFlags,
+    /// Rooms must have none of these flags.
+    pub excluded_flags: RoomFlags,
+    pub min_exits: Option<usize>,
+    pub max_exits: Option<usize>,
+    pub max_radius: Option<f32>,
+    /// Rarity, as a range of selection weights.
+    pub weight_range: Option<RangeInclusive<f32>>,
+}
+
+impl RoomQuery {
+    pub fn with_flags(flags: RoomFlags) -> Self {
+        Self { flags, ..default() }
+    }
+
+    /// Rooms that can be chosen anywhere. Dead ends and boss rooms are only chosen on request.
+    pub fn regular() -> Self {
+        Self {
+            excluded_flags: RoomFlags::DeadEnd | RoomFlags::Boss,
+            ..default()
+        }
+    }
+
+    pub fn matches(&self, room: &Room) -> bool {
+        let exits = room.exit_count();
+
+        room.flags.contains(self.flags.clone())
+            && !room.flags.intersects(self.excluded_flags.clone())
+            && self.min_exits.is_none_or(|min| exits >= min)
+            && self.max_exits.is_none_or(|max| exits <= max)
+            && self.max_radius.is_none_or(|max| room.radius() <= max)
+            && self
+                .weight_range
+                .as_ref()
+                .is_none_or(|range| range.contains(&room.weight))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Resource)]
 pub struct AssetCollection {
+    /// Schema version of the collection. Missing from collections built before versioning.
+    #[serde(default)]
+    pub version: u32,
     pub tunnels: Vec<Tunnel>,
     pub rooms: Vec<Room>,
 }
 
+impl Default for AssetCollection {
+    fn default() -> Self {
+        Self {
+            version: Self::VERSION,
+            tunnels: Vec::new(),
+            rooms: Vec::new(),
+        }
+    }
+}
+
+impl Versioned for AssetCollection {
+    const VERSION: u32 = 1;
+    const CHANGES: &'static [&'static str] = &["added schema versions"];
+}
+
 impl AssetCollection {
     pub fn random_tunnel<R>(&self, rng: &mut R) -> &Tunnel
     where
@@ -28,16 +93,46 @@ impl AssetCollection {
         self.rooms.choose_weighted(rng, |room| room.weight).unwrap()
     }
 
+    /// Like [`Self::random_room`], but each room's weight is multiplied by `bias`.
+    pub fn random_room_biased<R, F>(&self, rng: &mut R, bias: F) -> &Room
+    where
+        R: Rng + ?Sized,
+        F: Fn(&Room) -> f32,
+    {
+        self.rooms
+            .choose_weighted(rng, |room| room.weight * bias(room))
+            .unwrap()
+    }
+
     pub fn random_room_with_flags<R>(&self, flags: RoomFlags, rng: &mut R) -> &Room
     where
         R: Rng + ?Sized,
     {
-        let rooms = self
-            .rooms
-            .iter()
-            .filter(|room| room.flags.contains(flags.clone()))
-            .collect::<Vec<_>>();
+        self.random_room_matching(&RoomQuery::with_flags(flags), rng, |_| 1.0)
+            .unwrap()
+    }
+
+    pub fn rooms_matching<'a>(&'a self, query: &'a RoomQuery) -> impl Iterator<Item = &'a Room> {
+        self.rooms.iter().filter(|room| query.matches(room))
+    }
+
+    /// Like [`Self::random_room_biased`], but only rooms matching the query are considered.
+    /// Returns `None` if no rooms match.
+    pub fn random_room_matching<R, F>(
+        &self,
+        query: &RoomQuery,
+        rng: &mut R,
+        bias: F,
+    ) -> Option<&Room>
+    where
+        R: Rng + ?Sized,
+        F: Fn(&Room) -> f32,
+    {
+        let rooms = self.rooms_matching(query).collect::<Vec<_>>();
 
-        rooms.choose_weighted(rng, |room| room.weight).unwrap()
+        rooms
+            .choose_weighted(rng, |room| room.weight * bias(room))
+            .ok()
+            .copied()
     }
 }
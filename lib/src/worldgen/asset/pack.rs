@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::Read,
+    path::Path,
+};
+
+use anyhow::anyhow;
+use bevy::{
+    log::{info, warn},
+    prelude::Resource,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{migration::Versioned, AssetCollection};
+
+/// Every directory in here is an asset pack.
+pub const MODS_DIRECTORY: &str = "./mods";
+/// Stored in the mods directory. Lists every pack that has been found, and whether it's enabled.
+const PACK_SETTINGS_FILE_NAME: &str = "settings.ron";
+/// Collections in a pack's directory, tried in order. These are the asset builder's output for
+/// the production environment, e.g. with `--input mods/<pack>/worldgen --output mods/<pack>`.
+const PACK_COLLECTION_FILE_NAMES: [&str; 2] =
+    ["worldgen.production.cbor", "worldgen.production.ron"];
+/// Separates the name of a pack from the source of an asset in it.
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+/// Whether each pack is loaded, by directory name. Packs that aren't listed yet are enabled.
+#[derive(Serialize, Deserialize, Resource, Clone, Debug, Default)]
+pub struct PackSettings {
+    pub packs: BTreeMap<String, bool>,
+}
+
+impl PackSettings {
+    pub fn read(directory: &Path) -> Self {
+        let Ok(s) = std::fs::read_to_string(directory.join(PACK_SETTINGS_FILE_NAME)) else {
+            return Self::default();
+        };
+
+        ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("failed to parse asset pack settings: {err}");
+            Self::default()
+        })
+    }
+
+    pub fn write(&self, directory: &Path) -> anyhow::Result<()> {
+        let s = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(directory.join(PACK_SETTINGS_FILE_NAME), s)?;
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, pack: &str) -> bool {
+        self.packs.get(pack).copied().unwrap_or(true)
+    }
+}
+
+/// What happened to a pack found at startup.
+#[derive(Clone, Debug)]
+pub struct PackReport {
+    pub name: String,
+    pub enabled: bool,
+    pub rooms: usize,
+    pub tunnels: usize,
+    /// Problems found while loading or merging the pack. Packs that fail to load aren't merged.
+    pub problems: Vec<String>,
+}
+
+/// Asset packs found at startup, in the order they were merged.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct AssetPacks(pub Vec<PackReport>);
+
+/// Id of an asset from a pack. Assets from the game's own collection aren't namespaced.
+pub fn namespaced(pack: &str, source: &str) -> String {
+    format!("{pack}{NAMESPACE_SEPARATOR}{source}")
+}
+
+/// Splits an id into the pack it came from, if any, and its source within the pack.
+pub fn split_namespace(id: &str) -> (Option<&str>, &str) {
+    match id.split_once(NAMESPACE_SEPARATOR) {
+        Some((pack, source)) => (Some(pack), source),
+        None => (None, id),
+    }
+}
+
+impl AssetCollection {
+    /// Adds a pack's assets, namespacing their sources with the pack's name. Returns conflicts with
+    /// assets already in the collection. Assets with an id that's already taken are skipped.
+    pub fn merge_pack(&mut self, pack: &str, assets: AssetCollection) -> Vec<String> {
+        let mut problems = Vec::<String>::new();
+        merge_assets(
+            &mut self.rooms,
+            assets.rooms,
+            pack,
+            "room",
+            |room| &mut room.source,
+            &mut problems,
+        );
+        merge_assets(
+            &mut self.tunnels,
+            assets.tunnels,
+            pack,
+            "tunnel",
+            |tunnel| &mut tunnel.source,
+            &mut problems,
+        );
+
+        problems
+    }
+}
+
+fn merge_assets<T, F>(
+    existing: &mut Vec<T>,
+    incoming: Vec<T>,
+    pack: &str,
+    kind: &str,
+    mut source: F,
+    problems: &mut Vec<String>,
+) where
+    F: FnMut(&mut T) -> &mut String,
+{
+    let mut ids = existing
+        .iter_mut()
+        .map(|asset| source(asset).clone())
+        .collect::<HashSet<_>>();
+
+    for mut asset in incoming {
+        let id = namespaced(pack, source(&mut asset));
+        if ids.contains(&id) {
+            problems.push(format!("skipped duplicate {kind} {id}"));
+            continue;
+        }
+
+        // Namespaces keep these apart, but they're likely the same asset installed twice
+        let (_, own_source) = split_namespace(&id);
+        let mut shadowed = ids
+            .iter()
+            .filter(|other| split_namespace(other).1 == own_source)
+            .map(|other| match split_namespace(other).0 {
+                Some(other_pack) => format!("pack {other_pack}"),
+                None => "the base game".to_owned(),
+            })
+            .collect::<Vec<_>>();
+        shadowed.sort();
+        if !shadowed.is_empty() {
+            problems.push(format!(
+                "{kind} {own_source} is also in {}",
+                shadowed.join(", ")
+            ));
+        }
+
+        *source(&mut asset) = id.clone();
+        ids.insert(id);
+        existing.push(asset);
+    }
+}
+
+/// Loads every pack in the directory and merges the enabled ones, in alphabetical order so ids
+/// are taken deterministically. Packs found for the first time are added to the settings.
+pub fn load_packs(assets: &mut AssetCollection, directory: &Path) -> Vec<PackReport> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut names = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.'))
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let mut settings = PackSettings::read(directory);
+    let mut settings_changed = false;
+    let mut reports = Vec::<PackReport>::new();
+
+    for name in names {
+        if !settings.packs.contains_key(&name) {
+            settings.packs.insert(name.clone(), true);
+            settings_changed = true;
+        }
+
+        let mut report = PackReport {
+            enabled: settings.is_enabled(&name),
+            name,
+            rooms: 0,
+            tunnels: 0,
+            problems: Vec::new(),
+        };
+        if !report.enabled {
+            reports.push(report);
+            continue;
+        }
+        if report.name.contains(NAMESPACE_SEPARATOR) {
+            report
+                .problems
+                .push(format!("pack names can't contain '{NAMESPACE_SEPARATOR}'"));
+            reports.push(report);
+            continue;
+        }
+
+        let pack = read_pack(&directory.join(&report.name)).map(|(pack, migrations)| {
+            for migration in migrations {
+                info!("asset pack {}: migrated, {migration}", report.name);
+            }
+            pack
+        });
+        match pack {
+            Ok(pack) => {
+                report.rooms = pack.rooms.len();
+                report.tunnels = pack.tunnels.len();
+                report.problems = assets.merge_pack(&report.name, pack);
+            }
+            Err(err) => report.problems.push(format!("failed to load: {err}")),
+        }
+        reports.push(report);
+    }
+
+    if settings_changed {
+        if let Err(err) = settings.write(directory) {
+            warn!("failed to write asset pack settings: {err}");
+        }
+    }
+
+    reports
+}
+
+fn read_pack(directory: &Path) -> anyhow::Result<(AssetCollection, Vec<&'static str>)> {
+    let path = PACK_COLLECTION_FILE_NAMES
+        .iter()
+        .map(|name| directory.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow!("no asset collection"))?;
+
+    let mut file = File::open(&path)?;
+    let mut vec = Vec::new();
+    file.read_to_end(&mut vec)?;
+
+    if path.extension().is_some_and(|ext| ext == "ron") {
+        AssetCollection::from_ron(std::str::from_utf8(&vec)?)
+    } else {
+        AssetCollection::from_cbor(&vec)
+    }
+}
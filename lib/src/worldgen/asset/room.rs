@@ -4,12 +4,25 @@ use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 
+use crate::{
+    meshgen::{DoorwaySpec, SwitchSpec},
+    worldgen::{
+        brush::{paint::PaintStroke, BrushOperation},
+        voxel::VoxelMaterial,
+    },
+};
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct RoomFlags(u8);
 
 bitflags! {
     impl RoomFlags: u8 {
         const Spawnable = 1;
+        /// Terrain overlapping the room is sampled at a higher resolution.
+        const HighDetail = 2;
+        /// The room has no exits, so it ends whatever path leads to it.
+        const DeadEnd = 4;
+        const Boss = 8;
     }
 }
 
@@ -19,8 +32,37 @@ pub struct Room {
     pub source: String,
     pub weight: f32,
     pub cavities: Vec<Collider>,
+    /// Brushes that don't carve, applied after the cavities.
+    #[serde(default)]
+    pub brushes: Vec<RoomBrush>,
     pub portals: Vec<Portal>,
     pub spawnpoints: Vec<Spawnpoint>,
+    #[serde(default)]
+    pub atmosphere: RoomAtmosphere,
+    #[serde(default)]
+    pub doorways: Vec<Doorway>,
+    #[serde(default)]
+    pub switches: Vec<Switch>,
+    #[serde(default)]
+    pub content: RoomContent,
+    /// Overrides the reverb estimated from the room's size.
+    #[serde(default)]
+    pub reverb: Option<ReverbPreset>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub patrol_paths: Vec<PatrolPath>,
+    #[serde(default)]
+    pub gravity_zones: Vec<GravityZone>,
+    #[serde(default)]
+    pub fluid_volumes: Vec<FluidVolume>,
+    #[serde(default)]
+    pub audio_zones: Vec<AudioZone>,
+    #[serde(default)]
+    pub lights: Vec<LightFixture>,
+    /// Material painted over the terrain after every brush, in the order it was painted.
+    #[serde(default)]
+    pub paint: Vec<PaintStroke>,
 }
 
 impl Room {
@@ -58,6 +100,105 @@ impl Room {
         let (min, max) = self.aabb();
         max.distance(min) / 2.0
     }
+
+    pub fn exit_count(&self) -> usize {
+        self.portals
+            .iter()
+            .filter(|portal| portal.direction.is_exit())
+            .count()
+    }
+}
+
+/// Overrides for the global atmosphere while the player is inside a room. Colors are sRGB.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct RoomAtmosphere {
+    pub ambient_color: Option<[f32; 3]>,
+    pub ambient_brightness: Option<f32>,
+    pub fog_color: Option<[f32; 3]>,
+    pub fog_density: Option<f32>,
+}
+
+/// What a room mostly offers the player. Used to pace the content of each sequence.
+#[repr(u8)]
+#[derive(
+    EnumIter,
+    strum::Display,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+)]
+pub enum RoomContent {
+    Combat = 0,
+    Loot = 1,
+    #[default]
+    Transit = 2,
+}
+
+/// What an annotation volume tells enemies about an area of a room.
+#[repr(u8)]
+#[derive(
+    EnumIter,
+    strum::Display,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+)]
+pub enum AnnotationKind {
+    /// Elevated cover with a view over the room, for ranged enemies.
+    #[default]
+    #[strum(to_string = "Sniper Nest")]
+    SniperNest = 0,
+    /// Hidden spot where enemies wait for the player to pass.
+    #[strum(to_string = "Ambush Point")]
+    AmbushPoint = 1,
+    /// Enemies don't spawn or follow the player here.
+    #[strum(to_string = "Safe Zone")]
+    SafeZone = 2,
+}
+
+/// How sounds echo while the player is inside a room.
+#[repr(u8)]
+#[derive(
+    EnumIter, strum::Display, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum ReverbPreset {
+    #[strum(to_string = "Small Chamber")]
+    SmallChamber = 0,
+    #[strum(to_string = "Large Cavern")]
+    LargeCavern = 1,
+    Flooded = 2,
+}
+
+impl ReverbPreset {
+    /// How long the reverb tail lasts.
+    pub fn decay_secs(&self) -> f32 {
+        match self {
+            ReverbPreset::SmallChamber => 0.8,
+            ReverbPreset::LargeCavern => 3.5,
+            ReverbPreset::Flooded => 2.0,
+        }
+    }
+
+    /// Share of the reverberated signal in the mix, from 0 to 1.
+    pub fn wet(&self) -> f32 {
+        match self {
+            ReverbPreset::SmallChamber => 0.25,
+            ReverbPreset::LargeCavern => 0.45,
+            ReverbPreset::Flooded => 0.6,
+        }
+    }
 }
 
 #[repr(u8)]
@@ -98,6 +239,14 @@ impl PortalDirection {
     }
 }
 
+/// Fills or repaints terrain, like a pillar standing in a cavity.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomBrush {
+    pub collider: Collider,
+    pub operation: BrushOperation,
+    pub material: VoxelMaterial,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Portal {
     pub transform: Transform,
@@ -109,3 +258,77 @@ pub struct Spawnpoint {
     pub position: Vec3,
     pub angle: f32,
 }
+
+/// Interactive generated doorway, placed at the authored transform.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Doorway {
+    pub transform: Transform,
+    pub spec: DoorwaySpec,
+    /// Switch channel that opens the doorway. Unwired doorways open on contact.
+    #[serde(default)]
+    pub channel: Option<u8>,
+}
+
+/// Invisible volume marking an area for enemies. The volume is a unit cube, scaled by the
+/// transform.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Annotation {
+    pub transform: Transform,
+    pub kind: AnnotationKind,
+}
+
+/// Volume that replaces gravity inside it. The volume is a unit cube, scaled by the transform.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GravityZone {
+    pub transform: Transform,
+    /// Acceleration inside the volume, relative to its rotation.
+    pub gravity: Vec3,
+}
+
+/// Flooded region. The region is a unit cube, scaled by the transform, and is filled with water
+/// up to the level.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FluidVolume {
+    pub transform: Transform,
+    /// Height of the surface above the bottom of the region, from 0 to 1.
+    pub level: f32,
+}
+
+/// Region with its own ambient loop and reverb. The region is a unit cube, scaled by the
+/// transform.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AudioZone {
+    pub transform: Transform,
+    /// Looping sound, relative to the assets directory.
+    pub ambience: Option<String>,
+    pub volume: f32,
+    /// Overrides the room's reverb inside the region.
+    pub reverb: Option<ReverbPreset>,
+}
+
+/// Point light placed at the transform's translation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LightFixture {
+    pub transform: Transform,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    /// Luminous power, in lumens.
+    pub intensity: f32,
+    /// How far the intensity dips when flickering, from 0 for a steady light to 1.
+    pub flicker: f32,
+}
+
+/// Route for enemies to walk along.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PatrolPath {
+    pub points: Vec<Vec3>,
+    /// Walk from the last point back to the first, instead of turning around.
+    pub looped: bool,
+}
+
+/// Button or pressure plate that opens the doorways on its channel.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Switch {
+    pub transform: Transform,
+    pub spec: SwitchSpec,
+}
@@ -1,4 +1,5 @@
-use nalgebra::Point2;
+use bevy::prelude::*;
+use nalgebra::{Point2, Point3};
 use serde::{Deserialize, Serialize};
 
 // All tunnel profiles must have this number of points.
@@ -10,3 +11,26 @@ pub struct Tunnel {
     pub weight: f32,
     pub points: [Point2<f32>; TUNNEL_POINTS],
 }
+
+impl Tunnel {
+    /// Sweep profile in the XY plane, centered and stretched to fill `size`.
+    pub fn profile(&self, size: Vec2) -> Vec<Point3<f32>> {
+        let (min, max) = self
+            .points
+            .iter()
+            .fold((Vec2::MAX, Vec2::MIN), |(min, max), point| {
+                let point = Vec2::new(point.x, point.y);
+                (min.min(point), max.max(point))
+            });
+        let center = (min + max) / 2.0;
+        let scale = size / (max - min).max(Vec2::splat(f32::EPSILON));
+
+        self.points
+            .iter()
+            .map(|point| {
+                let point = (Vec2::new(point.x, point.y) - center) * scale;
+                Point3::new(point.x, point.y, 0.0)
+            })
+            .collect()
+    }
+}
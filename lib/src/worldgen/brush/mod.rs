@@ -1,26 +1,175 @@
+use std::{
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
 use avian3d::prelude::*;
 use bevy::{
+    ecs::system::SystemState,
     prelude::*,
     tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
 use curvo::prelude::{NurbsCurve3D, Tessellation};
 use nalgebra::{Const, Point3};
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
 
 use super::{
     chunk::ChunksAABB,
-    consts::{TUNNEL_VHACD_PARAMETERS, VOXEL_REAL_SIZE},
+    consts::{CHUNK_SIZE_F, TUNNEL_VHACD_PARAMETERS},
+    sdf,
     utility::safe_vhacd,
     voxel::{VoxelMaterial, VoxelSample},
 };
 
 pub mod curve;
+pub mod paint;
+pub mod primitive;
 pub mod sweep;
 
 use curve::curve_bounding_box;
+use paint::{sample_strokes, PaintStroke};
+use primitive::BrushPrimitive;
 use sweep::{sweep_zero_twist_filled, ProfileRamp};
 
+/// How a brush is merged into the terrain. Brushes are applied in the order listed here, so fills
+/// and material replacements act on the space carved out by every other brush.
+#[repr(u8)]
+#[derive(
+    EnumIter,
+    strum::Display,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+pub enum BrushOperation {
+    /// Hollows out the volume of the brush.
+    #[default]
+    Carve = 0,
+    /// Makes the volume of the brush solid, e.g. for pillars or back-filling.
+    Fill = 1,
+    /// Changes the material of the terrain inside the brush without changing its shape.
+    #[strum(to_string = "Replace Material")]
+    ReplaceMaterial = 2,
+}
+
+impl BrushOperation {
+    /// Merges a brush sample into a terrain sample. Surfaces are rounded off within `smoothness`
+    /// of where they meet, or left sharp if it's zero.
+    pub fn merge(
+        &self,
+        sample: VoxelSample,
+        smoothness: f32,
+        distance: &mut f32,
+        material: &mut VoxelMaterial,
+    ) {
+        match self {
+            BrushOperation::Carve => {
+                if sample.distance < *distance {
+                    *material = sample.material;
+                } else if *material == VoxelMaterial::Unset {
+                    *material = sample.material;
+                }
+                *distance = sdf::carve(*distance, sample.distance, smoothness);
+            }
+            BrushOperation::Fill => {
+                if -sample.distance > *distance {
+                    *material = sample.material;
+                }
+                *distance = sdf::fill(*distance, sample.distance, smoothness);
+            }
+            BrushOperation::ReplaceMaterial => {
+                if sample.distance <= 0.0 {
+                    *material = sample.material;
+                }
+            }
+        }
+    }
+}
+
+/// What a brush task is currently working on.
+#[repr(u8)]
+#[derive(strum::Display, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushTaskStage {
+    Queued = 0,
+    Sweeping = 1,
+    Decomposing = 2,
+}
+
+impl BrushTaskStage {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Sweeping,
+            2 => Self::Decomposing,
+            _ => Self::Queued,
+        }
+    }
+}
+
+/// Shared between a brush task and the world so the task can report its progress.
+#[derive(Clone, Default)]
+pub struct BrushTaskProgress(Arc<AtomicU8>);
+
+impl BrushTaskProgress {
+    fn set(&self, stage: BrushTaskStage) {
+        self.0.store(stage as u8, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> BrushTaskStage {
+        BrushTaskStage::from_u8(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// A brush request being processed in the background.
 #[derive(Component)]
-struct TerrainBrushTask(Task<TerrainBrush>);
+pub struct TerrainBrushTask {
+    task: Task<(TerrainBrush, bool)>,
+    progress: BrushTaskProgress,
+    uuid: String,
+    started: Instant,
+}
+
+impl TerrainBrushTask {
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn stage(&self) -> BrushTaskStage {
+        self.progress.get()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+#[derive(Event, Debug)]
+pub enum TerrainBrushTaskEvent {
+    Started {
+        uuid: String,
+    },
+    /// `fallback` is true if processing failed and a placeholder brush was used instead.
+    Finished {
+        uuid: String,
+        duration: Duration,
+        fallback: bool,
+    },
+    Cancelled {
+        uuid: String,
+        duration: Duration,
+    },
+}
 
 #[derive(Component, Clone)]
 pub enum TerrainBrushRequest {
@@ -28,6 +177,7 @@ pub enum TerrainBrushRequest {
         uuid: String,
         sequence: usize,
         material: VoxelMaterial,
+        operation: BrushOperation,
         points: Vec<Point3<f32>>,
         radius: f32,
     },
@@ -35,17 +185,29 @@ pub enum TerrainBrushRequest {
         uuid: String,
         sequence: usize,
         material: VoxelMaterial,
+        operation: BrushOperation,
         rail: Vec<Point3<f32>>,
         profile: ProfileRamp,
+        smoothness: f32,
     },
     Mesh {
         uuid: String,
         sequence: usize,
         material: VoxelMaterial,
+        operation: BrushOperation,
         mesh: Mesh,
         transform: Transform,
         vhacd_parameters: VhacdParameters,
     },
+    /// Box, sphere, or capsule, sampled exactly instead of being decomposed like meshes.
+    Primitive {
+        uuid: String,
+        sequence: usize,
+        material: VoxelMaterial,
+        operation: BrushOperation,
+        primitive: BrushPrimitive,
+        transform: Transform,
+    },
 }
 
 #[derive(Component, Clone)]
@@ -56,6 +218,8 @@ pub enum TerrainBrush {
         curve: NurbsCurve3D<f32>,
         radius: f32,
         material: VoxelMaterial,
+        operation: BrushOperation,
+        smoothness: f32,
         chunks: ChunksAABB,
     },
     Collider {
@@ -63,33 +227,124 @@ pub enum TerrainBrush {
         sequence: usize,
         collider: Collider,
         material: VoxelMaterial,
+        operation: BrushOperation,
+        smoothness: f32,
+        chunks: ChunksAABB,
+        transform: Transform,
+    },
+    /// Scale is ignored, the primitive's dimensions are used as is.
+    Primitive {
+        uuid: String,
+        sequence: usize,
+        primitive: BrushPrimitive,
+        material: VoxelMaterial,
+        operation: BrushOperation,
+        smoothness: f32,
         chunks: ChunksAABB,
         transform: Transform,
     },
+    /// Sphere of solid terrain. Always uses [`BrushOperation::Fill`].
+    Fill {
+        uuid: String,
+        sequence: usize,
+        position: Vec3,
+        radius: f32,
+        material: VoxelMaterial,
+        smoothness: f32,
+        chunks: ChunksAABB,
+    },
+    /// Strokes of material painted over the terrain, in the order they were painted. Always uses
+    /// [`BrushOperation::ReplaceMaterial`].
+    Paint {
+        uuid: String,
+        sequence: usize,
+        strokes: Vec<PaintStroke>,
+        chunks: ChunksAABB,
+    },
 }
 
 impl TerrainBrushRequest {
-    pub fn process(self) -> TerrainBrush {
+    pub fn uuid(&self) -> &str {
+        match self {
+            TerrainBrushRequest::Curve { uuid, .. } => uuid,
+            TerrainBrushRequest::Sweep { uuid, .. } => uuid,
+            TerrainBrushRequest::Mesh { uuid, .. } => uuid,
+            TerrainBrushRequest::Primitive { uuid, .. } => uuid,
+        }
+    }
+
+    pub fn operation(&self) -> BrushOperation {
+        match self {
+            TerrainBrushRequest::Curve { operation, .. } => *operation,
+            TerrainBrushRequest::Sweep { operation, .. } => *operation,
+            TerrainBrushRequest::Mesh { operation, .. } => *operation,
+            TerrainBrushRequest::Primitive { operation, .. } => *operation,
+        }
+    }
+
+    pub fn smoothness(&self) -> f32 {
         match self {
+            TerrainBrushRequest::Sweep { smoothness, .. } => *smoothness,
+            _ => 0.0,
+        }
+    }
+
+    pub fn process(self) -> TerrainBrush {
+        self.process_with_progress(&BrushTaskProgress::default()).0
+    }
+
+    /// Also returns true if processing failed and a fallback brush was used.
+    pub fn process_with_progress(self, progress: &BrushTaskProgress) -> (TerrainBrush, bool) {
+        let operation = self.operation();
+        let smoothness = self.smoothness();
+        let (brush, fallback) = match self {
             TerrainBrushRequest::Curve {
                 uuid,
                 sequence,
                 material,
                 points,
                 radius,
-            } => TerrainBrush::curve(&uuid, sequence, material, &points, radius),
+                ..
+            } => (
+                TerrainBrush::curve(&uuid, sequence, material, &points, radius),
+                false,
+            ),
             TerrainBrushRequest::Sweep {
                 uuid,
                 sequence,
                 material,
                 rail,
                 profile,
-            } => TerrainBrush::sweep(&uuid, sequence, material, &rail, &profile).unwrap_or_else(
-                |_| {
-                    // TODO dynamic fallback curve radius
-                    TerrainBrush::curve(&uuid, sequence, VoxelMaterial::Invalid, &rail, 4.0)
-                },
-            ),
+                ..
+            } => {
+                progress.set(BrushTaskStage::Sweeping);
+                TerrainBrush::sweep_mesh(&rail, &profile)
+                    .and_then(|mesh| {
+                        progress.set(BrushTaskStage::Decomposing);
+                        TerrainBrush::mesh(
+                            &uuid,
+                            sequence,
+                            material,
+                            &mesh,
+                            None,
+                            &TUNNEL_VHACD_PARAMETERS,
+                        )
+                    })
+                    .map(|brush| (brush, false))
+                    .unwrap_or_else(|_| {
+                        // TODO dynamic fallback curve radius
+                        (
+                            TerrainBrush::curve(
+                                &uuid,
+                                sequence,
+                                VoxelMaterial::Invalid,
+                                &rail,
+                                4.0,
+                            ),
+                            true,
+                        )
+                    })
+            }
             TerrainBrushRequest::Mesh {
                 uuid,
                 sequence,
@@ -97,25 +352,48 @@ impl TerrainBrushRequest {
                 mesh,
                 transform,
                 vhacd_parameters,
-            } => TerrainBrush::mesh(
-                &uuid,
-                sequence,
-                material,
-                &mesh,
-                Some(transform),
-                &vhacd_parameters,
-            )
-            .unwrap_or_else(|_| {
-                // TODO dynamic fallback sphere radius
-                TerrainBrush::collider(
+                ..
+            } => {
+                progress.set(BrushTaskStage::Decomposing);
+                TerrainBrush::mesh(
                     &uuid,
                     sequence,
-                    VoxelMaterial::Invalid,
-                    Collider::sphere(2.0 * transform.scale.max_element()),
-                    transform,
+                    material,
+                    &mesh,
+                    Some(transform),
+                    &vhacd_parameters,
                 )
-            }),
-        }
+                .map(|brush| (brush, false))
+                .unwrap_or_else(|_| {
+                    // TODO dynamic fallback sphere radius
+                    (
+                        TerrainBrush::collider(
+                            &uuid,
+                            sequence,
+                            VoxelMaterial::Invalid,
+                            Collider::sphere(2.0 * transform.scale.max_element()),
+                            transform,
+                        ),
+                        true,
+                    )
+                })
+            }
+            TerrainBrushRequest::Primitive {
+                uuid,
+                sequence,
+                material,
+                primitive,
+                transform,
+                ..
+            } => (
+                TerrainBrush::primitive(&uuid, sequence, material, primitive, transform),
+                false,
+            ),
+        };
+
+        let brush = brush.with_operation(operation).with_smoothness(smoothness);
+
+        (brush, fallback)
     }
 }
 
@@ -124,6 +402,9 @@ impl TerrainBrush {
         match self {
             TerrainBrush::Curve { uuid, .. } => uuid,
             TerrainBrush::Collider { uuid, .. } => uuid,
+            TerrainBrush::Primitive { uuid, .. } => uuid,
+            TerrainBrush::Fill { uuid, .. } => uuid,
+            TerrainBrush::Paint { uuid, .. } => uuid,
         }
     }
 
@@ -131,6 +412,9 @@ impl TerrainBrush {
         match self {
             TerrainBrush::Curve { sequence, .. } => *sequence,
             TerrainBrush::Collider { sequence, .. } => *sequence,
+            TerrainBrush::Primitive { sequence, .. } => *sequence,
+            TerrainBrush::Fill { sequence, .. } => *sequence,
+            TerrainBrush::Paint { sequence, .. } => *sequence,
         }
     }
 
@@ -138,6 +422,9 @@ impl TerrainBrush {
         match self {
             TerrainBrush::Curve { chunks, .. } => chunks,
             TerrainBrush::Collider { chunks, .. } => chunks,
+            TerrainBrush::Primitive { chunks, .. } => chunks,
+            TerrainBrush::Fill { chunks, .. } => chunks,
+            TerrainBrush::Paint { chunks, .. } => chunks,
         }
     }
 
@@ -145,9 +432,77 @@ impl TerrainBrush {
         match self {
             TerrainBrush::Curve { .. } => self.sample_curve(point),
             TerrainBrush::Collider { .. } => self.sample_collider(point),
+            TerrainBrush::Primitive { .. } => self.sample_primitive(point),
+            TerrainBrush::Fill { .. } => self.sample_fill(point),
+            TerrainBrush::Paint { strokes, .. } => sample_strokes(strokes, point),
         }
     }
 
+    pub fn operation(&self) -> BrushOperation {
+        match self {
+            TerrainBrush::Curve { operation, .. } => *operation,
+            TerrainBrush::Collider { operation, .. } => *operation,
+            TerrainBrush::Primitive { operation, .. } => *operation,
+            TerrainBrush::Fill { .. } => BrushOperation::Fill,
+            TerrainBrush::Paint { .. } => BrushOperation::ReplaceMaterial,
+        }
+    }
+
+    /// Blend radius used where this brush meets the terrain carved or filled before it.
+    pub fn smoothness(&self) -> f32 {
+        match self {
+            TerrainBrush::Curve { smoothness, .. } => *smoothness,
+            TerrainBrush::Collider { smoothness, .. } => *smoothness,
+            TerrainBrush::Primitive { smoothness, .. } => *smoothness,
+            TerrainBrush::Fill { smoothness, .. } => *smoothness,
+            TerrainBrush::Paint { .. } => 0.0,
+        }
+    }
+
+    /// Also grows the affected chunks to cover the blended region. Has no effect on paint brushes.
+    pub fn with_smoothness(mut self, smoothness: f32) -> Self {
+        match &mut self {
+            TerrainBrush::Curve {
+                smoothness: s,
+                chunks,
+                ..
+            }
+            | TerrainBrush::Collider {
+                smoothness: s,
+                chunks,
+                ..
+            }
+            | TerrainBrush::Primitive {
+                smoothness: s,
+                chunks,
+                ..
+            }
+            | TerrainBrush::Fill {
+                smoothness: s,
+                chunks,
+                ..
+            } => {
+                *s = smoothness.max(0.0);
+                chunks.inflate((*s / CHUNK_SIZE_F).ceil() as i32);
+            }
+            TerrainBrush::Paint { .. } => {}
+        }
+
+        self
+    }
+
+    /// Has no effect on fill and paint brushes.
+    pub fn with_operation(mut self, operation: BrushOperation) -> Self {
+        match &mut self {
+            TerrainBrush::Curve { operation: op, .. }
+            | TerrainBrush::Collider { operation: op, .. }
+            | TerrainBrush::Primitive { operation: op, .. } => *op = operation,
+            TerrainBrush::Fill { .. } | TerrainBrush::Paint { .. } => {}
+        }
+
+        self
+    }
+
     //
     // Spawning
     //
@@ -170,6 +525,8 @@ impl TerrainBrush {
             curve,
             radius,
             material,
+            operation: BrushOperation::Carve,
+            smoothness: 0.0,
             chunks,
         }
     }
@@ -181,8 +538,7 @@ impl TerrainBrush {
         rail: &[Point3<f32>],
         profile: &ProfileRamp,
     ) -> anyhow::Result<Self> {
-        let rail = NurbsCurve3D::<f32>::try_interpolate(rail, 3)?;
-        let mesh = sweep_zero_twist_filled::<Const<4>>(profile, &rail, Some(4))?;
+        let mesh = Self::sweep_mesh(rail, profile)?;
 
         Self::mesh(
             uuid,
@@ -194,6 +550,11 @@ impl TerrainBrush {
         )
     }
 
+    fn sweep_mesh(rail: &[Point3<f32>], profile: &ProfileRamp) -> anyhow::Result<Mesh> {
+        let rail = NurbsCurve3D::<f32>::try_interpolate(rail, 3)?;
+        sweep_zero_twist_filled::<Const<4>>(profile, &rail, Some(4))
+    }
+
     pub fn mesh(
         uuid: &str,
         sequence: usize,
@@ -227,7 +588,7 @@ impl TerrainBrush {
     ) -> Self {
         let aabb = collider
             .aabb(Vec3::ZERO, Rotation::default())
-            .grow(Vec3::splat(VOXEL_REAL_SIZE));
+            .grow(Vec3::splat(sdf::INFLATION));
         let chunks = ChunksAABB::from_world_aabb(
             (
                 aabb.min + transform.translation,
@@ -241,11 +602,81 @@ impl TerrainBrush {
             sequence,
             collider,
             material,
+            operation: BrushOperation::Carve,
+            smoothness: 0.0,
+            chunks,
+            transform,
+        }
+    }
+
+    pub fn primitive(
+        uuid: &str,
+        sequence: usize,
+        material: VoxelMaterial,
+        primitive: BrushPrimitive,
+        transform: Transform,
+    ) -> Self {
+        let extents = primitive.rotated_half_extents(transform.rotation) + sdf::INFLATION;
+        let chunks = ChunksAABB::from_world_aabb(
+            (
+                transform.translation - extents,
+                transform.translation + extents,
+            ),
+            0,
+        );
+
+        Self::Primitive {
+            uuid: uuid.to_owned(),
+            sequence,
+            primitive,
+            material,
+            operation: BrushOperation::Carve,
+            smoothness: 0.0,
             chunks,
             transform,
         }
     }
 
+    pub fn fill(
+        uuid: &str,
+        sequence: usize,
+        material: VoxelMaterial,
+        position: Vec3,
+        radius: f32,
+    ) -> Self {
+        let extents = Vec3::splat(sdf::inflate(radius));
+        let chunks = ChunksAABB::from_world_aabb((position - extents, position + extents), 0);
+
+        Self::Fill {
+            uuid: uuid.to_owned(),
+            sequence,
+            position,
+            radius,
+            material,
+            smoothness: 0.0,
+            chunks,
+        }
+    }
+
+    pub fn paint(uuid: &str, sequence: usize, strokes: Vec<PaintStroke>) -> Self {
+        let (min, max) = strokes
+            .iter()
+            .map(|stroke| {
+                let extents = Vec3::splat(sdf::inflate(stroke.radius));
+                (stroke.position - extents, stroke.position + extents)
+            })
+            .reduce(|(a_min, a_max), (b_min, b_max)| (a_min.min(b_min), a_max.max(b_max)))
+            .unwrap_or_default();
+        let chunks = ChunksAABB::from_world_aabb((min, max), 0);
+
+        Self::Paint {
+            uuid: uuid.to_owned(),
+            sequence,
+            strokes,
+            chunks,
+        }
+    }
+
     //
     // Sampling
     //
@@ -285,17 +716,47 @@ impl TerrainBrush {
         let (position, rotation) = (transform.translation, transform.rotation);
         let (closest, _) = collider.project_point(position, rotation, point, false);
         let (closest_solid, _) = collider.project_point(position, rotation, point, true);
-        let mut distance = point.distance(closest);
         let is_inside = closest_solid.distance(point) <= 0.01;
 
-        if is_inside {
-            distance *= -1.0;
-            distance = distance.min(-0.001);
+        VoxelSample {
+            material: *material,
+            distance: sdf::signed(point.distance(closest), is_inside),
         }
+    }
+
+    fn sample_primitive(&self, point: Vec3) -> VoxelSample {
+        let TerrainBrush::Primitive {
+            primitive,
+            material,
+            transform,
+            ..
+        } = self
+        else {
+            panic!("wrong sample function");
+        };
+
+        let local = transform.rotation.inverse() * (point - transform.translation);
 
         VoxelSample {
             material: *material,
-            distance,
+            distance: primitive.distance(local),
+        }
+    }
+
+    fn sample_fill(&self, point: Vec3) -> VoxelSample {
+        let TerrainBrush::Fill {
+            position,
+            radius,
+            material,
+            ..
+        } = self
+        else {
+            panic!("wrong sample function");
+        };
+
+        VoxelSample {
+            material: *material,
+            distance: sdf::sphere(point, *position, *radius),
         }
     }
 }
@@ -308,12 +769,14 @@ pub struct TerrainBrushPlugin;
 
 impl Plugin for TerrainBrushPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<TerrainBrushTaskEvent>();
         app.add_systems(Update, (process_brushes, receive_brushes));
     }
 }
 
 fn process_brushes(
     mut commands: Commands,
+    mut events: EventWriter<TerrainBrushTaskEvent>,
     requests: Query<(Option<&Parent>, Entity, &TerrainBrushRequest)>,
 ) {
     let task_pool = AsyncComputeTaskPool::get();
@@ -322,9 +785,22 @@ fn process_brushes(
         .iter()
         .for_each(|(parent, request_entity, request)| {
             let request = request.clone();
-            let task = task_pool.spawn(async move { request.process() });
-
-            let task_entity = commands.spawn(TerrainBrushTask(task)).id();
+            let uuid = request.uuid().to_owned();
+            let progress = BrushTaskProgress::default();
+            let task = {
+                let progress = progress.clone();
+                task_pool.spawn(async move { request.process_with_progress(&progress) })
+            };
+
+            events.send(TerrainBrushTaskEvent::Started { uuid: uuid.clone() });
+            let task_entity = commands
+                .spawn(TerrainBrushTask {
+                    task,
+                    progress,
+                    uuid,
+                    started: Instant::now(),
+                })
+                .id();
             if let Some(parent) = parent {
                 let mut commands = commands.entity(parent.get());
                 commands.remove_children(&[request_entity]);
@@ -336,15 +812,22 @@ fn process_brushes(
 
 fn receive_brushes(
     mut commands: Commands,
+    mut events: EventWriter<TerrainBrushTaskEvent>,
     mut tasks: Query<(Option<&Parent>, Entity, &mut TerrainBrushTask)>,
 ) {
     for (parent, task_entity, mut task) in tasks.iter_mut() {
-        let status = block_on(future::poll_once(&mut task.0));
+        let status = block_on(future::poll_once(&mut task.task));
 
-        let Some(brush) = status else {
+        let Some((brush, fallback)) = status else {
             continue;
         };
 
+        events.send(TerrainBrushTaskEvent::Finished {
+            uuid: task.uuid.clone(),
+            duration: task.elapsed(),
+            fallback,
+        });
+
         let brush_entity = commands.spawn(brush).id();
         if let Some(parent) = parent {
             let mut commands = commands.entity(parent.get());
@@ -354,3 +837,40 @@ fn receive_brushes(
         commands.entity(task_entity).despawn();
     }
 }
+
+/// Cancels brush requests and in-flight brush tasks with the given uuid. Decomposition can't be
+/// interrupted, so a task that's already running finishes in the background and is discarded.
+pub struct CancelTerrainBrushTasksCommand {
+    pub uuid: String,
+}
+
+impl Command for CancelTerrainBrushTasksCommand {
+    fn apply(self, world: &mut World) {
+        let mut system_state: SystemState<(
+            Commands,
+            EventWriter<TerrainBrushTaskEvent>,
+            Query<(Entity, &TerrainBrushTask)>,
+            Query<(Entity, &TerrainBrushRequest)>,
+        )> = SystemState::new(world);
+        let (mut commands, mut events, tasks, requests) = system_state.get_mut(world);
+
+        tasks
+            .iter()
+            .filter(|(_, task)| task.uuid == self.uuid)
+            .for_each(|(entity, task)| {
+                events.send(TerrainBrushTaskEvent::Cancelled {
+                    uuid: task.uuid.clone(),
+                    duration: task.elapsed(),
+                });
+                commands.entity(entity).despawn();
+            });
+        requests
+            .iter()
+            .filter(|(_, request)| request.uuid() == self.uuid)
+            .for_each(|(entity, _)| {
+                commands.entity(entity).despawn();
+            });
+
+        system_state.apply(world);
+    }
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::worldgen::{
+    sdf,
+    voxel::{VoxelMaterial, VoxelSample},
+};
+
+/// Sphere of material painted onto the terrain. Weak strokes only repaint some of the voxels they
+/// touch, so the material underneath shows through.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PaintStroke {
+    pub position: Vec3,
+    pub radius: f32,
+    /// Share of voxels repainted at the edge of the stroke, from 0 to 1. Doubles toward the
+    /// center.
+    pub strength: f32,
+    pub material: VoxelMaterial,
+}
+
+impl PaintStroke {
+    pub fn transformed_by(self, transform: Transform) -> Self {
+        Self {
+            position: transform.transform_point(self.position),
+            radius: self.radius * transform.scale.max_element(),
+            ..self
+        }
+    }
+
+    /// Negative where the point is painted.
+    pub fn sample(&self, point: Vec3) -> VoxelSample {
+        let sphere = sdf::sphere(point, self.position, self.radius);
+
+        VoxelSample {
+            material: self.material,
+            distance: sdf::paint(sphere, self.radius, self.strength, sdf::dither(point)),
+        }
+    }
+}
+
+/// Samples the last stroke that paints the point, so strokes cover the ones painted before them.
+pub fn sample_strokes(strokes: &[PaintStroke], point: Vec3) -> VoxelSample {
+    strokes
+        .iter()
+        .rev()
+        .map(|stroke| stroke.sample(point))
+        .find(|sample| sample.distance <= 0.0)
+        .unwrap_or(VoxelSample {
+            material: VoxelMaterial::Unset,
+            distance: f32::MAX,
+        })
+}
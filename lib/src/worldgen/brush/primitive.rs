@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::worldgen::sdf;
+
+/// Shapes with an exact signed distance function, centered on the brush origin.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BrushPrimitive {
+    Box {
+        half_extents: Vec3,
+    },
+    Sphere {
+        radius: f32,
+    },
+    /// Aligned with the Y axis.
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+}
+
+impl BrushPrimitive {
+    /// Signed distance from a point in the primitive's local space.
+    pub fn distance(&self, point: Vec3) -> f32 {
+        match *self {
+            BrushPrimitive::Box { half_extents } => sdf::cuboid(point, half_extents),
+            BrushPrimitive::Sphere { radius } => sdf::sphere(point, Vec3::ZERO, radius),
+            BrushPrimitive::Capsule {
+                half_height,
+                radius,
+            } => sdf::capsule(point, half_height, radius),
+        }
+    }
+
+    /// Half extents of the world aligned bounding box after rotating the primitive.
+    pub fn rotated_half_extents(&self, rotation: Quat) -> Vec3 {
+        let matrix = Mat3::from_quat(rotation);
+        let abs = Mat3::from_cols(
+            matrix.x_axis.abs(),
+            matrix.y_axis.abs(),
+            matrix.z_axis.abs(),
+        );
+        match *self {
+            BrushPrimitive::Box { half_extents } => abs * half_extents,
+            BrushPrimitive::Sphere { radius } => Vec3::splat(radius),
+            BrushPrimitive::Capsule {
+                half_height,
+                radius,
+            } => abs * Vec3::Y * half_height + radius,
+        }
+    }
+}
@@ -6,7 +6,8 @@ use curvo::prelude::*;
 use earclip::earclip;
 use nalgebra::{Const, DimName, Point3, Rotation3, Translation3, Vector3};
 
-/// Facilitates interpolating between multiple sweep profiles.
+/// Facilitates interpolating between multiple sweep profiles. Keyframes are placed along the rail
+/// by parameter, from 0 at the start to 1 at the end, and must all have the same number of points.
 #[derive(Clone)]
 pub struct ProfileRamp(Vec<(f32, Vec<Point3<f32>>)>);
 
@@ -20,7 +21,8 @@ impl ProfileRamp {
         self
     }
 
-    pub fn point(mut self, parameter: f32, profile: Vec<Point3<f32>>) -> Self {
+    /// Insert a profile partway along the rail, e.g. to narrow a tunnel in the middle.
+    pub fn keyframe(mut self, parameter: f32, profile: Vec<Point3<f32>>) -> Self {
         let mut i: usize = 0;
         while i < self.0.len() {
             if self.0[i].0 > parameter {
@@ -32,28 +34,34 @@ impl ProfileRamp {
         self
     }
 
+    pub fn keyframes(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Profile at the parameter, interpolated between the keyframes on either side of it.
     pub fn sample(&self, parameter: f32) -> Vec<Point3<f32>> {
-        if self.0.len() == 2 {
-            Self::lerp_profile(&mut self.0[0].1.clone(), &self.0[1].1, parameter);
+        let first = self.0.first().unwrap();
+        if parameter <= first.0 {
+            return first.1.clone();
         }
 
         self.0
             .windows(2)
             .find_map(|w| {
-                if w[0].0 == parameter {
-                    return Some(w[0].1.clone());
+                if parameter > w[1].0 {
+                    return None;
                 }
 
-                if w[1].0 > parameter {
-                    let diff = w[1].0 - w[0].0;
-                    let fac = (parameter - w[0].0) / diff;
-                    let mut profile = w[0].1.clone();
-                    Self::lerp_profile(&mut profile, &w[1].1, fac);
-
-                    return Some(profile);
-                }
+                let diff = w[1].0 - w[0].0;
+                let fac = if diff > 0.0 {
+                    (parameter - w[0].0) / diff
+                } else {
+                    1.0
+                };
+                let mut profile = w[0].1.clone();
+                Self::lerp_profile(&mut profile, &w[1].1, fac);
 
-                None
+                Some(profile)
             })
             .unwrap_or_else(|| self.0.last().unwrap().1.clone())
     }
@@ -95,9 +103,13 @@ where
         .enumerate()
         .map(|(i, frame)| {
             let translate = Translation3::from(*frame.position());
-            let tangent = frame.tangent();
-            let angle = tangent.x.atan2(tangent.z);
-            let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), angle);
+            let tangent = frame.tangent().normalize();
+            // The profile faces along the rail and stays upright, so steep sections keep
+            // their width instead of flattening out
+            let yaw = tangent.x.atan2(tangent.z);
+            let pitch = tangent.y.clamp(-1.0, 1.0).asin();
+            let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), yaw)
+                * Rotation3::from_axis_angle(&Vector3::x_axis(), -pitch);
             let transform = translate * rotation;
 
             let sample = profile.sample(parameters[i]);
@@ -0,0 +1,242 @@
+use avian3d::prelude::*;
+use bevy::{audio::SpatialAudioSink, ecs::system::SystemParam, pbr::NotShadowCaster, prelude::*};
+
+use crate::{
+    audio::{AudioEffectsPlugin, VolumeFactors},
+    player::PlayerCamera,
+};
+
+use super::layout::GravityZones;
+
+/// Upward acceleration on fully submerged bodies, relative to gravity. Above 1 so things float.
+const BUOYANCY: f32 = 1.2;
+/// Share of velocity lost per second by fully submerged bodies.
+const DRAG: f32 = 2.0;
+/// Volume of sounds while the camera is underwater, relative to their normal volume.
+const MUFFLED_VOLUME: f32 = 0.3;
+/// How quickly sounds become muffled or clear up, per second.
+const MUFFLE_SPEED: f32 = 4.0;
+/// Channel of [`VolumeFactors`] for muffling sounds underwater.
+const MUFFLE_CHANNEL: &str = "muffle";
+
+/// Flooded region. The region is a unit cube, scaled by the transform, and is filled with water
+/// from the bottom up to the level. Keep the transform upright so the surface is level.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct FluidVolume {
+    /// Height of the surface above the bottom of the region, from 0 to 1.
+    pub level: f32,
+}
+
+impl FluidVolume {
+    /// Height of the surface in world space, if the point is above the bottom of the region and
+    /// within its footprint. Points above the surface are included, so partly submerged bodies
+    /// can find it.
+    pub fn surface_at(&self, transform: &GlobalTransform, point: Vec3) -> Option<f32> {
+        let local = transform.affine().inverse().transform_point3(point);
+        if local.xz().abs().max_element() > 0.5 || local.y < -0.5 {
+            return None;
+        }
+
+        let surface = transform.transform_point(Vec3::Y * (self.level - 0.5));
+        Some(surface.y)
+    }
+}
+
+/// Water anywhere in the world.
+#[derive(SystemParam)]
+pub struct Fluids<'w, 's> {
+    volumes: Query<'w, 's, (&'static FluidVolume, &'static GlobalTransform)>,
+}
+
+impl Fluids<'_, '_> {
+    /// Where volumes overlap, the highest surface wins.
+    pub fn surface_at(&self, position: Vec3) -> Option<f32> {
+        self.volumes
+            .iter()
+            .filter_map(|(volume, transform)| volume.surface_at(transform, position))
+            .reduce(f32::max)
+    }
+
+    /// Distance below the surface, or zero outside of water.
+    pub fn depth_at(&self, position: Vec3) -> f32 {
+        self.surface_at(position)
+            .map_or(0.0, |surface| (surface - position.y).max(0.0))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.volumes.is_empty()
+    }
+}
+
+/// How far underwater the player's camera is.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct Submersion {
+    pub depth: f32,
+    /// How muffled sounds are, from 0 to 1. Eases toward 1 while the camera is underwater.
+    pub muffle: f32,
+}
+
+#[derive(Resource)]
+struct FluidAssets {
+    surface_mesh: Handle<Mesh>,
+    surface_material: Handle<StandardMaterial>,
+}
+
+pub struct FluidPlugin;
+
+impl Plugin for FluidPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AudioEffectsPlugin>() {
+            app.add_plugins(AudioEffectsPlugin);
+        }
+
+        app.init_resource::<Submersion>();
+        app.add_systems(Startup, setup_fluid_assets);
+        app.add_systems(
+            Update,
+            (
+                add_surfaces,
+                apply_fluid_forces,
+                (track_submersion, muffle_audio).chain(),
+            ),
+        );
+    }
+}
+
+fn setup_fluid_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(FluidAssets {
+        surface_mesh: meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(0.5))),
+        // Visible from above and below
+        surface_material: materials.add(StandardMaterial {
+            base_color: Color::srgba(0.08, 0.25, 0.3, 0.75),
+            perceptual_roughness: 0.05,
+            reflectance: 0.6,
+            alpha_mode: AlphaMode::Blend,
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        }),
+    });
+}
+
+fn add_surfaces(
+    mut commands: Commands,
+    assets: Option<Res<FluidAssets>>,
+    volumes: Query<(Entity, &FluidVolume), Added<FluidVolume>>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+
+    for (entity, volume) in volumes.iter() {
+        commands
+            .entity(entity)
+            .insert_if_new(Visibility::default())
+            .with_children(|parent| {
+                parent.spawn((
+                    Mesh3d(assets.surface_mesh.clone()),
+                    MeshMaterial3d(assets.surface_material.clone()),
+                    Transform::from_xyz(0.0, volume.level - 0.5, 0.0),
+                    NotShadowCaster,
+                ));
+            });
+    }
+}
+
+/// Bodies are pushed against gravity by how much of them is underwater, and slowed down by the
+/// water. This includes the player, whose controller floats on top of it.
+fn apply_fluid_forces(
+    time: Res<Time>,
+    fluids: Fluids,
+    gravity: GravityZones,
+    mut bodies: Query<
+        (
+            &RigidBody,
+            &GlobalTransform,
+            &mut LinearVelocity,
+            Option<&ColliderAabb>,
+        ),
+        Without<Sleeping>,
+    >,
+) {
+    if fluids.is_empty() {
+        return;
+    }
+
+    for (body, transform, mut velocity, aabb) in bodies.iter_mut() {
+        if !body.is_dynamic() {
+            continue;
+        }
+
+        // Bodies without their own collider are treated as a point
+        let (bottom, top) = aabb.map_or_else(
+            || (transform.translation(), transform.translation()),
+            |aabb| (aabb.min, aabb.max),
+        );
+        let center = (bottom + top) / 2.0;
+        let Some(surface) = fluids.surface_at(center.with_y(bottom.y)) else {
+            continue;
+        };
+
+        let height = top.y - bottom.y;
+        let submerged = if height > 0.0 {
+            ((surface - bottom.y) / height).clamp(0.0, 1.0)
+        } else if surface >= bottom.y {
+            1.0
+        } else {
+            0.0
+        };
+        if submerged == 0.0 {
+            continue;
+        }
+
+        let delta = time.delta_secs();
+        velocity.0 -= gravity.gravity_at(center) * BUOYANCY * submerged * delta;
+        velocity.0 /= 1.0 + DRAG * submerged * delta;
+    }
+}
+
+fn track_submersion(
+    time: Res<Time>,
+    fluids: Fluids,
+    camera: Option<Single<&GlobalTransform, With<PlayerCamera>>>,
+    mut submersion: ResMut<Submersion>,
+) {
+    let depth = camera.map_or(0.0, |camera| fluids.depth_at(camera.translation()));
+    let target = if depth > 0.0 { 1.0 } else { 0.0 };
+    let t = (MUFFLE_SPEED * time.delta_secs()).clamp(0.0, 1.0);
+
+    submersion.depth = depth;
+    submersion.muffle = submersion.muffle.lerp(target, t);
+}
+
+/// Bevy has no audio filters, so sounds are muffled by turning them down.
+fn muffle_audio(
+    mut commands: Commands,
+    submersion: Res<Submersion>,
+    mut sounds: Query<
+        (Entity, Option<&mut VolumeFactors>),
+        Or<(With<AudioSink>, With<SpatialAudioSink>)>,
+    >,
+) {
+    let factor = 1.0 - submersion.muffle * (1.0 - MUFFLED_VOLUME);
+    for (entity, factors) in sounds.iter_mut() {
+        match factors {
+            Some(mut factors) => {
+                if factors.get(MUFFLE_CHANNEL) != factor {
+                    factors.set(MUFFLE_CHANNEL, factor);
+                }
+            }
+            None if factor < 1.0 => {
+                commands
+                    .entity(entity)
+                    .insert(VolumeFactors::new(MUFFLE_CHANNEL, factor));
+            }
+            None => {}
+        }
+    }
+}
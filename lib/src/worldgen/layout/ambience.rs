@@ -0,0 +1,237 @@
+use bevy::{
+    audio::{SpatialAudioSink, Volume},
+    prelude::*,
+};
+
+use crate::{
+    audio::{AudioEffectsPlugin, ReverbAudio, VolumeFactors},
+    player::IsPlayer,
+    worldgen::asset::ReverbPreset,
+};
+
+use super::{
+    occupancy::{CurrentRoom, TrackCurrentRoom},
+    room::{volume_contains, Room},
+};
+
+/// How quickly the reverb approaches the current room's preset, per second.
+const TRANSITION_SPEED: f32 = 1.5;
+/// Rooms at least this large are estimated to echo like a large cavern.
+const LARGE_CAVERN_RADIUS: f32 = 24.0;
+/// Seconds taken to fade between ambient loops.
+const CROSSFADE_SECS: f32 = 2.0;
+/// Volume factor channel used to fade ambient loops in and out.
+const CROSSFADE_CHANNEL: &str = "crossfade";
+
+/// Region of a room with its own ambient loop and reverb. The region is a unit cube, scaled by
+/// the transform.
+#[derive(Component, Clone, Debug)]
+pub struct AudioZone {
+    /// Looping sound, relative to the assets directory.
+    pub ambience: Option<String>,
+    pub volume: f32,
+    /// Overrides the room's reverb inside the region.
+    pub reverb: Option<ReverbPreset>,
+}
+
+/// The smallest audio zone that contains the player, if any.
+#[derive(Resource, Default, PartialEq)]
+pub struct CurrentAudioZone(pub Option<Entity>);
+
+/// Ambient sound of a zone, played from its center. Fades in while its zone is current, and is
+/// despawned once faded out.
+#[derive(Component)]
+struct AmbientLoop {
+    zone: Entity,
+    /// Crossfade progress, from 0 to 1.
+    fade: f32,
+}
+
+/// Reverb for sounds heard by the player. Follows the current room's preset, or an estimate
+/// based on its size. Tunnels use the small chamber preset.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Reverb {
+    pub preset: ReverbPreset,
+    pub decay_secs: f32,
+    /// Share of the reverberated signal in the mix, from 0 to 1.
+    pub wet: f32,
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        let preset = ReverbPreset::SmallChamber;
+        Self {
+            preset,
+            decay_secs: preset.decay_secs(),
+            wet: preset.wet(),
+        }
+    }
+}
+
+pub struct AmbiencePlugin;
+
+impl Plugin for AmbiencePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AudioEffectsPlugin>() {
+            app.add_plugins(AudioEffectsPlugin);
+        }
+
+        app.init_resource::<Reverb>();
+        app.init_resource::<CurrentAudioZone>();
+        app.add_systems(
+            Update,
+            (track_audio_zone, (interpolate_reverb, crossfade_ambience))
+                .chain()
+                .after(TrackCurrentRoom),
+        );
+        // Sounds start playing after transform propagation
+        app.add_systems(
+            PostUpdate,
+            apply_reverb.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn estimate_reverb(room: &Room) -> ReverbPreset {
+    if room.radius >= LARGE_CAVERN_RADIUS {
+        ReverbPreset::LargeCavern
+    } else {
+        ReverbPreset::SmallChamber
+    }
+}
+
+fn track_audio_zone(
+    mut current_zone: ResMut<CurrentAudioZone>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+    zones: Query<(Entity, &AudioZone, &GlobalTransform)>,
+) {
+    let Some(player) = player else {
+        current_zone.set_if_neq(CurrentAudioZone(None));
+        return;
+    };
+    let position = player.translation();
+
+    // Nested zones take priority over the zones around them
+    let zone = zones
+        .iter()
+        .filter(|(_, _, transform)| volume_contains(transform, position))
+        .map(|(entity, _, transform)| {
+            let size = transform.affine().matrix3.determinant().abs();
+            (entity, size)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity);
+
+    current_zone.set_if_neq(CurrentAudioZone(zone));
+}
+
+fn interpolate_reverb(
+    time: Res<Time>,
+    current_room: Res<CurrentRoom>,
+    current_zone: Res<CurrentAudioZone>,
+    rooms: Query<&Room>,
+    zones: Query<&AudioZone>,
+    mut reverb: ResMut<Reverb>,
+    player: Option<Single<(), With<IsPlayer>>>,
+) {
+    if player.is_none() {
+        return;
+    }
+
+    let zone_preset = current_zone
+        .0
+        .and_then(|entity| zones.get(entity).ok())
+        .and_then(|zone| zone.reverb);
+    let preset = zone_preset
+        .or_else(|| {
+            let room = rooms.get(current_room.0?).ok()?;
+            Some(room.reverb.unwrap_or_else(|| estimate_reverb(room)))
+        })
+        .unwrap_or(ReverbPreset::SmallChamber);
+
+    let t = (TRANSITION_SPEED * time.delta_secs()).clamp(0.0, 1.0);
+
+    reverb.preset = preset;
+    reverb.decay_secs = reverb.decay_secs.lerp(preset.decay_secs(), t);
+    reverb.wet = reverb.wet.lerp(preset.wet(), t);
+}
+
+fn crossfade_ambience(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    current_zone: Res<CurrentAudioZone>,
+    zones: Query<(&AudioZone, &GlobalTransform)>,
+    mut loops: Query<(Entity, &mut AmbientLoop, &mut VolumeFactors)>,
+) {
+    let target = current_zone.0.and_then(|entity| {
+        let (zone, transform) = zones.get(entity).ok()?;
+        Some((entity, zone.ambience.as_ref()?, zone.volume, transform))
+    });
+
+    let step = time.delta_secs() / CROSSFADE_SECS;
+    let mut playing_target = false;
+    for (entity, mut ambient, mut factors) in loops.iter_mut() {
+        let fading_in = target.is_some_and(|(zone, ..)| zone == ambient.zone);
+        playing_target |= fading_in;
+
+        let target_fade = if fading_in { 1.0 } else { 0.0 };
+        ambient.fade += (target_fade - ambient.fade).clamp(-step, step);
+        if ambient.fade <= 0.0 && !fading_in {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        if factors.get(CROSSFADE_CHANNEL) != ambient.fade {
+            factors.set(CROSSFADE_CHANNEL, ambient.fade);
+        }
+    }
+
+    if let (Some((zone, source, volume, transform)), false) = (target, playing_target) {
+        commands.spawn((
+            AmbientLoop { zone, fade: 0.0 },
+            AudioPlayer::<AudioSource>(asset_server.load(source)),
+            // Silent until the volume factors are applied
+            PlaybackSettings::LOOP
+                .with_volume(Volume::ZERO)
+                .with_spatial(true),
+            VolumeFactors::new(CROSSFADE_CHANNEL, 0.0).with_base(volume),
+            Transform::from_translation(transform.translation()),
+        ));
+    }
+}
+
+/// Plays spatial sounds through the current reverb once their source has loaded. Ambient loops
+/// stay dry, since they're recorded in their space.
+fn apply_reverb(
+    mut commands: Commands,
+    reverb: Res<Reverb>,
+    sources: Res<Assets<AudioSource>>,
+    mut reverb_sources: ResMut<Assets<ReverbAudio>>,
+    sounds: Query<
+        (Entity, &AudioPlayer<AudioSource>, &PlaybackSettings),
+        (
+            Without<AudioSink>,
+            Without<SpatialAudioSink>,
+            Without<AmbientLoop>,
+        ),
+    >,
+) {
+    for (entity, player, settings) in sounds.iter() {
+        if !settings.spatial {
+            continue;
+        }
+        let Some(source) = sources.get(&player.0) else {
+            continue;
+        };
+
+        let source = reverb_sources.add(ReverbAudio {
+            source: source.clone(),
+            decay_secs: reverb.decay_secs,
+            wet: reverb.wet,
+        });
+        commands
+            .entity(entity)
+            .remove::<AudioPlayer<AudioSource>>()
+            .insert(AudioPlayer(source));
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::{pbr::DistanceFog, prelude::*};
+
+use crate::player::IsPlayer;
+
+use super::{
+    occupancy::{CurrentRoom, TrackCurrentRoom},
+    room::Room,
+};
+
+/// How quickly the atmosphere approaches the current room's overrides, per second.
+const TRANSITION_SPEED: f32 = 1.5;
+
+/// Atmosphere used outside of rooms, and for anything a room doesn't override. The ambient
+/// light is captured from the `AmbientLight` resource after startup.
+#[derive(Resource, Clone, Copy)]
+pub struct DefaultAtmosphere {
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    pub fog_color: Color,
+    pub fog_density: f32,
+}
+
+impl Default for DefaultAtmosphere {
+    fn default() -> Self {
+        Self {
+            ambient_color: Color::WHITE,
+            ambient_brightness: 35.0,
+            fog_color: Color::BLACK,
+            fog_density: 0.0,
+        }
+    }
+}
+
+pub struct AtmospherePlugin;
+
+impl Plugin for AtmospherePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DefaultAtmosphere>();
+        app.add_systems(PostStartup, capture_default_atmosphere);
+        app.add_systems(Update, interpolate_atmosphere.after(TrackCurrentRoom));
+    }
+}
+
+fn capture_default_atmosphere(ambient: Res<AmbientLight>, mut defaults: ResMut<DefaultAtmosphere>) {
+    defaults.ambient_color = ambient.color;
+    defaults.ambient_brightness = ambient.brightness;
+}
+
+fn interpolate_atmosphere(
+    time: Res<Time>,
+    defaults: Res<DefaultAtmosphere>,
+    current_room: Res<CurrentRoom>,
+    rooms: Query<&Room>,
+    mut ambient: ResMut<AmbientLight>,
+    mut fogs: Query<&mut DistanceFog>,
+    player: Option<Single<(), With<IsPlayer>>>,
+) {
+    if player.is_none() {
+        return;
+    }
+
+    let overrides = current_room
+        .0
+        .and_then(|entity| rooms.get(entity).ok())
+        .map(|room| room.atmosphere)
+        .unwrap_or_default();
+
+    let srgb = |c: [f32; 3]| Color::srgb(c[0], c[1], c[2]);
+    let ambient_color = overrides.ambient_color.map_or(defaults.ambient_color, srgb);
+    let ambient_brightness = overrides
+        .ambient_brightness
+        .unwrap_or(defaults.ambient_brightness);
+    let fog_color = overrides.fog_color.map_or(defaults.fog_color, srgb);
+    let fog_density = overrides.fog_density.unwrap_or(defaults.fog_density);
+
+    let t = (TRANSITION_SPEED * time.delta_secs()).clamp(0.0, 1.0);
+
+    ambient.color = ambient.color.mix(&ambient_color, t);
+    ambient.brightness = ambient.brightness.lerp(ambient_brightness, t);
+
+    for mut fog in fogs.iter_mut() {
+        fog.color = fog.color.mix(&fog_color, t);
+
+        let density = match fog.falloff {
+            FogFalloff::Exponential { density } => density,
+            _ => 0.0,
+        };
+        fog.falloff = FogFalloff::Exponential {
+            density: density.lerp(fog_density, t),
+        };
+    }
+}
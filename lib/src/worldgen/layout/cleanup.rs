@@ -0,0 +1,123 @@
+use bevy::{audio::SpatialAudioSink, prelude::*};
+
+use crate::audio::VolumeFactors;
+
+use super::room::Room;
+
+/// Seconds taken to fade out sounds whose room was unloaded.
+const AUDIO_FADE_OUT_SECS: f32 = 0.75;
+/// Channel of [`VolumeFactors`] for fading out sounds.
+const FADE_OUT_CHANNEL: &str = "fade_out";
+
+/// Ties an entity that isn't a child of a room to that room's lifetime. When the room is
+/// unloaded, sounds are faded out and everything else is despawned.
+///
+/// Spatial sounds spawned at the top level are assigned to the room they were spawned in
+/// automatically.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BelongsToRoom(pub Entity);
+
+/// Fades out a sound, then despawns it.
+#[derive(Component, Default)]
+pub struct AudioFadeOut {
+    elapsed: f32,
+}
+
+pub struct CleanupPlugin;
+
+impl Plugin for CleanupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (assign_spatial_audio, cleanup_unloaded, fade_out_audio).chain(),
+        );
+    }
+}
+
+fn assign_spatial_audio(
+    mut commands: Commands,
+    sounds: Query<
+        (Entity, &PlaybackSettings, &Transform),
+        (
+            Added<PlaybackSettings>,
+            Without<BelongsToRoom>,
+            Without<Parent>,
+        ),
+    >,
+    rooms: Query<(Entity, &Room, &GlobalTransform)>,
+) {
+    for (entity, playback, transform) in sounds.iter() {
+        if !playback.spatial {
+            continue;
+        }
+
+        let position = transform.translation;
+        let room = rooms
+            .iter()
+            .filter_map(|(room_entity, room, room_transform)| {
+                let distance = room_transform
+                    .transform_point(room.center)
+                    .distance(position);
+                (distance <= room.radius).then_some((room_entity, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((room, _)) = room {
+            commands.entity(entity).insert(BelongsToRoom(room));
+        }
+    }
+}
+
+fn cleanup_unloaded(
+    mut commands: Commands,
+    belongings: Query<(Entity, &BelongsToRoom, Has<PlaybackSettings>), Without<AudioFadeOut>>,
+    rooms: Query<(), With<Room>>,
+) {
+    for (entity, belongs_to, is_audio) in belongings.iter() {
+        if rooms.contains(belongs_to.0) {
+            continue;
+        }
+
+        if is_audio {
+            commands.entity(entity).insert(AudioFadeOut::default());
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn fade_out_audio(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sounds: Query<(
+        Entity,
+        &mut AudioFadeOut,
+        Option<&mut VolumeFactors>,
+        Has<AudioSink>,
+        Has<SpatialAudioSink>,
+    )>,
+) {
+    for (entity, mut fade, factors, has_sink, has_spatial_sink) in sounds.iter_mut() {
+        // Sounds that haven't started playing yet can be dropped right away
+        if !has_sink && !has_spatial_sink {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        fade.elapsed += time.delta_secs();
+        let t = (fade.elapsed / AUDIO_FADE_OUT_SECS).min(1.0);
+        match factors {
+            Some(mut factors) => factors.set(FADE_OUT_CHANNEL, 1.0 - t),
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(VolumeFactors::new(FADE_OUT_CHANNEL, 1.0 - t));
+            }
+        }
+
+        // Dropping the sink stops the sound
+        if t >= 1.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -13,7 +13,34 @@ pub const DEPENETRATION_DISTANCE: f32 = 8.0;
 /// Number of points per unit of volume in the navigable hull between two rooms.
 pub const HULL_DENSITY: f32 = 0.00001;
 
+/// Long tunnels get an intermediate profile about this often along their path.
+pub const TUNNEL_KEYFRAME_SPACING: f32 = 32.0;
+
+/// Intermediate tunnel profiles are scaled by a random factor in this range, relative to the
+/// profiles at the tunnel's mouths.
+pub const TUNNEL_KEYFRAME_SCALE: (f32, f32) = (0.6, 1.2);
+
+/// Tunnels that dip at least this far below both of their mouths are flooded.
+pub const TUNNEL_FLOOD_MIN_DEPTH: f32 = 4.0;
+
+/// Distance between the surface of a flooded tunnel and its lower mouth, so the water doesn't
+/// spill into the room.
+pub const TUNNEL_FLOOD_MARGIN: f32 = 2.0;
+
+/// Flooded regions extend this far past the tunnel's path, so they cover its walls.
+pub const TUNNEL_FLOOD_PADDING: f32 = 8.0;
+
+/// Steepest pitch that tunnels are expected to have, in radians. Rooms that are further apart
+/// vertically get a wider area to pathfind through, so tunnels can wind between them.
+pub const MAX_TUNNEL_PITCH: f32 = 0.6;
+
 /// Distance considered to a be short hop when pathfinding between portals.
 pub const SHORT_HOP: f32 = 24.0;
 
 pub const TRIGGER_OFFSET: f32 = 8.0;
+
+/// Furthest that spawnpoints and props are dropped onto the floor from where they were authored.
+pub const ROOM_PLACEMENT_MAX_DROP: f32 = 4.0;
+
+/// Times room content tries to find the floor, every half second, while its chunks generate.
+pub const ROOM_PLACEMENT_ATTEMPTS: usize = 32;
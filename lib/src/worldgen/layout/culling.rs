@@ -0,0 +1,266 @@
+use bevy::{
+    math::{
+        bounding::{Aabb3d, BoundingSphere, BoundingVolume, IntersectsVolume, RayCast3d},
+        Vec3A,
+    },
+    prelude::*,
+    render::{
+        primitives::{Frustum, Sphere},
+        view::VisibilitySystems,
+    },
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    player::PlayerCamera,
+    worldgen::{consts::CHUNK_SIZE_F, terrain::Chunk},
+};
+
+use super::{
+    occupancy::CurrentRoom,
+    room::{Portal, Room},
+    tunnel::PortalConnection,
+};
+
+/// Chunks this close to a cell belong to it, so the walls around a room or tunnel are hidden
+/// along with it.
+const CELL_MARGIN: f32 = 4.0;
+
+/// Hides terrain that can't be seen from the player's cell. Rooms and the tunnels between them
+/// are cells, and the portals where tunnels meet rooms are the only openings between cells.
+/// Chunks that don't belong to any cell are always shown.
+#[derive(Resource)]
+pub struct CaveCulling {
+    pub enabled: bool,
+    /// Cells more than this many portals away from the player's cell are hidden.
+    pub max_depth: usize,
+    /// Only look through portals that are in view of the camera.
+    pub frustum: bool,
+}
+
+impl Default for CaveCulling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_depth: 2,
+            frustum: true,
+        }
+    }
+}
+
+enum CellBounds {
+    Room(BoundingSphere),
+    Tunnel {
+        segments: Vec<(Vec3, Dir3, f32)>,
+        radius: f32,
+    },
+}
+
+impl CellBounds {
+    fn intersects(&self, aabb: &Aabb3d) -> bool {
+        match self {
+            CellBounds::Room(sphere) => sphere.intersects(aabb),
+            CellBounds::Tunnel { segments, radius } => {
+                let aabb = aabb.grow(Vec3::splat(*radius));
+                segments.iter().any(|(start, direction, length)| {
+                    RayCast3d::new(*start, *direction, *length)
+                        .aabb_intersection_at(&aabb)
+                        .is_some()
+                })
+            }
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct Cells {
+    bounds: HashMap<Entity, CellBounds>,
+    /// Portals leading out of each cell, and the cell on the other side.
+    portals: HashMap<Entity, Vec<(Sphere, Entity)>>,
+    /// Cells that were visible when chunks were last culled. [`None`] if every chunk is shown.
+    visible: Option<HashSet<Entity>>,
+    changed: bool,
+}
+
+impl Cells {
+    /// The room the player is in, or the tunnel containing the camera.
+    fn find(&self, current_room: &CurrentRoom, position: Vec3) -> Option<Entity> {
+        if let Some(room) = current_room.0.filter(|room| self.bounds.contains_key(room)) {
+            return Some(room);
+        }
+
+        let point = Aabb3d::new(position, Vec3::ZERO);
+        self.bounds
+            .iter()
+            .find(|(_, bounds)| {
+                matches!(bounds, CellBounds::Tunnel { .. }) && bounds.intersects(&point)
+            })
+            .map(|(cell, _)| *cell)
+    }
+
+    /// Walks through portals from the starting cell, up to the maximum depth.
+    fn visible_from(
+        &self,
+        start: Entity,
+        settings: &CaveCulling,
+        frustum: Option<&Frustum>,
+    ) -> HashSet<Entity> {
+        let mut visible = HashSet::default();
+        visible.insert(start);
+        let mut frontier = vec![start];
+
+        for _ in 0..settings.max_depth {
+            let mut next = Vec::new();
+            for cell in frontier {
+                for (portal, neighbor) in self.portals.get(&cell).into_iter().flatten() {
+                    let in_view =
+                        frustum.is_none_or(|frustum| frustum.intersects_sphere(portal, true));
+                    if in_view && visible.insert(*neighbor) {
+                        next.push(*neighbor);
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        visible
+    }
+
+    /// Chunks are hidden if every cell they belong to is hidden.
+    fn chunk_visibility(&self, aabb: &Aabb3d) -> Visibility {
+        let Some(visible) = &self.visible else {
+            return Visibility::Inherited;
+        };
+
+        let mut cells = self
+            .bounds
+            .iter()
+            .filter(|(_, bounds)| bounds.intersects(aabb))
+            .map(|(cell, _)| cell)
+            .peekable();
+        if cells.peek().is_none() || cells.any(|cell| visible.contains(cell)) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    }
+}
+
+pub struct CullingPlugin;
+
+impl Plugin for CullingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CaveCulling>();
+        app.init_resource::<Cells>();
+        // Runs after the camera frustum is updated, so culling doesn't lag behind when turning
+        app.add_systems(
+            PostUpdate,
+            (update_cells, cull_chunks)
+                .chain()
+                .after(VisibilitySystems::UpdateFrusta)
+                .before(VisibilitySystems::VisibilityPropagate),
+        );
+    }
+}
+
+fn update_cells(
+    mut cells: ResMut<Cells>,
+    moved: Query<(), (Or<(With<Room>, With<Portal>)>, Changed<GlobalTransform>)>,
+    added: Query<(), Added<PortalConnection>>,
+    mut removed_rooms: RemovedComponents<Room>,
+    mut removed_connections: RemovedComponents<PortalConnection>,
+    rooms: Query<(Entity, &Room, &GlobalTransform)>,
+    portals: Query<(&GlobalTransform, &Parent), With<Portal>>,
+    connections: Query<(Entity, &PortalConnection)>,
+) {
+    let removed = removed_rooms.read().count() + removed_connections.read().count();
+    if moved.is_empty() && added.is_empty() && removed == 0 {
+        return;
+    }
+
+    cells.bounds.clear();
+    cells.portals.clear();
+    cells.changed = true;
+
+    for (entity, room, transform) in rooms.iter() {
+        let center = transform.transform_point(room.center);
+        let sphere = BoundingSphere::new(center, room.radius + CELL_MARGIN);
+        cells.bounds.insert(entity, CellBounds::Room(sphere));
+    }
+
+    for (entity, connection) in connections.iter() {
+        let Ok([from, to]) = portals.get_many([connection.from_portal, connection.to_portal])
+        else {
+            continue;
+        };
+
+        let mut radius: f32 = 0.0;
+        for (transform, parent) in [from, to] {
+            let size = transform.scale().xz().max_element();
+            radius = radius.max(size);
+
+            let portal = Sphere {
+                center: transform.translation_vec3a(),
+                radius: size,
+            };
+            cells
+                .portals
+                .entry(entity)
+                .or_default()
+                .push((portal, parent.get()));
+            cells
+                .portals
+                .entry(parent.get())
+                .or_default()
+                .push((portal, entity));
+        }
+
+        let segments = connection
+            .path
+            .windows(2)
+            .filter_map(|window| {
+                let (direction, length) = Dir3::new_and_length(window[1] - window[0]).ok()?;
+                Some((window[0], direction, length))
+            })
+            .collect();
+        let bounds = CellBounds::Tunnel {
+            segments,
+            radius: radius + CELL_MARGIN,
+        };
+        cells.bounds.insert(entity, bounds);
+    }
+}
+
+fn cull_chunks(
+    settings: Res<CaveCulling>,
+    current_room: Res<CurrentRoom>,
+    mut cells: ResMut<Cells>,
+    camera: Option<Single<(&GlobalTransform, &Frustum), With<PlayerCamera>>>,
+    mut chunks: Query<(Ref<Chunk>, &Transform, &mut Visibility)>,
+) {
+    let visible = match camera {
+        Some(camera) if settings.enabled => {
+            let (transform, frustum) = *camera;
+            let frustum = settings.frustum.then_some(frustum);
+            cells
+                .find(&current_room, transform.translation())
+                .map(|start| cells.visible_from(start, &settings, frustum))
+        }
+        _ => None,
+    };
+
+    // Only cull chunks that were just spawned, unless something changed
+    let all = cells.changed || settings.is_changed() || cells.visible != visible;
+    cells.visible = visible;
+    cells.changed = false;
+
+    for (chunk, transform, mut visibility) in chunks.iter_mut() {
+        if !all && !chunk.is_added() {
+            continue;
+        }
+
+        let half_size = Vec3A::splat(CHUNK_SIZE_F / 2.0);
+        let aabb = Aabb3d::new(Vec3A::from(transform.translation) + half_size, half_size);
+        visibility.set_if_neq(cells.chunk_visibility(&aabb));
+    }
+}
@@ -0,0 +1,88 @@
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use super::room::volume_contains;
+
+/// Magnitude of the physics engine's default gravity, which the game doesn't change.
+pub const STANDARD_GRAVITY: f32 = 9.81;
+
+/// Replaces gravity inside the volume. The volume is a unit cube, scaled by the transform.
+#[derive(Component)]
+pub struct GravityZone {
+    /// Acceleration inside the volume, relative to its rotation.
+    pub gravity: Vec3,
+}
+
+impl GravityZone {
+    pub fn world_gravity(&self, transform: &GlobalTransform) -> Vec3 {
+        transform.rotation() * self.gravity
+    }
+}
+
+/// Gravity anywhere in the world, including gravity zones. Use this for anything that simulates
+/// its own ballistics instead of relying on the physics engine.
+#[derive(SystemParam)]
+pub struct GravityZones<'w, 's> {
+    gravity: Res<'w, Gravity>,
+    zones: Query<'w, 's, (&'static GravityZone, &'static GlobalTransform)>,
+}
+
+impl GravityZones<'_, '_> {
+    /// Where zones overlap, the smallest one wins, so small zones can be nested in large ones.
+    pub fn gravity_at(&self, position: Vec3) -> Vec3 {
+        self.zones
+            .iter()
+            .filter(|(_, transform)| volume_contains(transform, position))
+            .min_by(|(_, a), (_, b)| {
+                let a = a.scale().element_product();
+                let b = b.scale().element_product();
+                a.total_cmp(&b)
+            })
+            .map(|(zone, transform)| zone.world_gravity(transform))
+            .unwrap_or(self.gravity.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+}
+
+pub struct GravityZonePlugin;
+
+impl Plugin for GravityZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_gravity_zones);
+    }
+}
+
+/// The physics engine only has global gravity, so bodies inside zones are accelerated by the
+/// difference. This includes the player, whose controller floats on top of it.
+fn apply_gravity_zones(
+    time: Res<Time>,
+    zones: GravityZones,
+    mut bodies: Query<
+        (
+            &RigidBody,
+            &GlobalTransform,
+            &mut LinearVelocity,
+            Option<&GravityScale>,
+        ),
+        Without<Sleeping>,
+    >,
+) {
+    if zones.is_empty() {
+        return;
+    }
+
+    for (body, transform, mut velocity, scale) in bodies.iter_mut() {
+        if !body.is_dynamic() {
+            continue;
+        }
+
+        let scale = scale.map_or(1.0, |scale| scale.0);
+        let difference = zones.gravity_at(transform.translation()) - zones.gravity.0;
+        if difference != Vec3::ZERO {
+            velocity.0 += difference * scale * time.delta_secs();
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use rand::Rng;
+
+use crate::player::PlayerCamera;
+
+use super::{cleanup::BelongsToRoom, room::Portal, tunnel::PortalConnection, LayoutState};
+
+/// Tunnel lights wander this far off the path, in meters, so they don't line up down the middle.
+const TUNNEL_LIGHT_WANDER: f32 = 2.0;
+const TUNNEL_LIGHT_RANGE: f32 = 12.0;
+const SPORE_SIZE: f32 = 0.08;
+/// Flickering lights waver this many times per second, roughly.
+const FLICKER_SPEED: f32 = 6.0;
+
+/// Light placed by the layout, either declared by a room asset or grown along a tunnel.
+#[derive(Resource)]
+pub struct CaveLighting {
+    /// Chance of a light in each stretch of tunnel.
+    pub tunnel_light_chance: f32,
+    /// Length of each stretch of tunnel, in meters.
+    pub tunnel_light_spacing: f32,
+    /// Tunnel lights are one of these bioluminescent colors.
+    pub tunnel_light_colors: Vec<Color>,
+    pub tunnel_light_intensity: (f32, f32),
+    pub tunnel_light_flicker: f32,
+    /// Only the lights closest to the camera are enabled, up to this many.
+    pub max_active: usize,
+    /// Lights further than this from the camera are disabled.
+    pub max_distance: f32,
+}
+
+impl Default for CaveLighting {
+    fn default() -> Self {
+        Self {
+            tunnel_light_chance: 0.3,
+            tunnel_light_spacing: 12.0,
+            tunnel_light_colors: vec![
+                Color::srgb(0.2, 0.9, 0.8),
+                Color::srgb(0.4, 1.0, 0.4),
+                Color::srgb(0.3, 0.5, 1.0),
+                Color::srgb(0.8, 0.4, 1.0),
+            ],
+            tunnel_light_intensity: (20_000.0, 60_000.0),
+            tunnel_light_flicker: 0.15,
+            max_active: 24,
+            max_distance: 60.0,
+        }
+    }
+}
+
+/// Point light managed by the layout. It's enabled when it's one of the closest to the camera,
+/// see [`CaveLighting`].
+#[derive(Component)]
+pub struct CaveLight {
+    /// Intensity of the light when it isn't flickering, in lumens.
+    pub intensity: f32,
+    /// How far the intensity dips when flickering, from 0 for a steady light to 1.
+    pub flicker: f32,
+}
+
+#[derive(Resource)]
+struct SporeAssets {
+    mesh: Handle<Mesh>,
+}
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CaveLighting>();
+        app.add_systems(Startup, setup_spore_assets);
+        app.add_systems(
+            Update,
+            (spawn_tunnel_lights, limit_lights, flicker_lights).chain(),
+        );
+    }
+}
+
+fn setup_spore_assets(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(SporeAssets {
+        mesh: meshes.add(Sphere::new(SPORE_SIZE).mesh().ico(1).unwrap()),
+    });
+}
+
+fn spawn_tunnel_lights(
+    mut commands: Commands,
+    state: Res<LayoutState>,
+    settings: Res<CaveLighting>,
+    spores: Option<Res<SporeAssets>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    connections: Query<&PortalConnection, Added<PortalConnection>>,
+    portals: Query<&Parent, With<Portal>>,
+) {
+    if settings.tunnel_light_colors.is_empty() || settings.tunnel_light_spacing <= 0.0 {
+        return;
+    }
+
+    for connection in connections.iter() {
+        let Ok(room) = portals.get(connection.from_portal) else {
+            continue;
+        };
+
+        // Each tunnel has its own RNG, so its lights don't depend on when it was spawned
+        let key = (
+            connection.sequence,
+            connection
+                .path
+                .first()
+                .map(|point| point.to_array().map(f32::to_bits)),
+        );
+        let mut rng = state.rng_for(key);
+
+        // Roll for a light at the end of each stretch along the path
+        let mut travelled = 0.0;
+        let mut next = settings.tunnel_light_spacing;
+        for segment in connection.path.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            let length = start.distance(end);
+            while next <= travelled + length {
+                let position = start.lerp(end, (next - travelled) / length);
+                next += settings.tunnel_light_spacing;
+                if !rng.gen_bool(settings.tunnel_light_chance.clamp(0.0, 1.0) as f64) {
+                    continue;
+                }
+
+                let wander = Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                ) * TUNNEL_LIGHT_WANDER;
+                let color = settings.tunnel_light_colors
+                    [rng.gen_range(0..settings.tunnel_light_colors.len())];
+                let (min_intensity, max_intensity) = settings.tunnel_light_intensity;
+                let intensity = rng.gen_range(min_intensity..=max_intensity.max(min_intensity));
+
+                let light = (
+                    CaveLight {
+                        intensity,
+                        flicker: settings.tunnel_light_flicker,
+                    },
+                    PointLight {
+                        color,
+                        intensity,
+                        range: TUNNEL_LIGHT_RANGE,
+                        ..default()
+                    },
+                    Visibility::Hidden,
+                );
+                let transform = Transform::from_translation(position + wander);
+
+                // A glowing spore marks where the light comes from, and stays visible when the
+                // light is disabled
+                let Some(spores) = spores.as_ref() else {
+                    commands.spawn((light, transform, BelongsToRoom(room.get())));
+                    continue;
+                };
+                let material = materials.add(StandardMaterial {
+                    base_color: color,
+                    emissive: LinearRgba::from(color) * 4.0,
+                    unlit: true,
+                    ..default()
+                });
+                commands
+                    .spawn((
+                        Mesh3d(spores.mesh.clone()),
+                        MeshMaterial3d(material),
+                        NotShadowCaster,
+                        transform,
+                        BelongsToRoom(room.get()),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(light);
+                    });
+            }
+            travelled += length;
+        }
+    }
+}
+
+/// Enables the lights closest to the camera and disables the rest, so there are never more than
+/// the renderer can handle.
+fn limit_lights(
+    settings: Res<CaveLighting>,
+    camera: Option<Single<&GlobalTransform, With<PlayerCamera>>>,
+    mut lights: Query<(&GlobalTransform, &mut Visibility), With<CaveLight>>,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let eye = camera.translation();
+
+    let mut by_distance = lights
+        .iter()
+        .enumerate()
+        .map(|(i, (transform, _))| (i, transform.translation().distance_squared(eye)))
+        .filter(|(_, distance)| *distance <= settings.max_distance.powi(2))
+        .collect::<Vec<_>>();
+    by_distance.sort_by(|a, b| a.1.total_cmp(&b.1));
+    by_distance.truncate(settings.max_active);
+
+    let mut enabled = vec![false; lights.iter().count()];
+    by_distance.into_iter().for_each(|(i, _)| enabled[i] = true);
+
+    for ((_, mut visibility), enabled) in lights.iter_mut().zip(enabled) {
+        visibility.set_if_neq(if enabled {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+fn flicker_lights(
+    time: Res<Time>,
+    mut lights: Query<(Entity, &CaveLight, &Visibility, &mut PointLight)>,
+) {
+    for (entity, light, visibility, mut point_light) in lights.iter_mut() {
+        if *visibility == Visibility::Hidden || light.flicker <= 0.0 {
+            continue;
+        }
+
+        // Layered waves, offset per light so they don't flicker in unison
+        let t = time.elapsed_secs() * FLICKER_SPEED + entity.index() as f32 * 1.618;
+        let wave = (t.sin() + (t * 2.3).sin() * 0.5 + (t * 5.7).sin() * 0.25) / 1.75;
+        let dip = light.flicker.min(1.0) * (wave * 0.5 + 0.5);
+        point_light.intensity = light.intensity * (1.0 - dip);
+    }
+}
@@ -0,0 +1,160 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use contact_query::intersection_test;
+use rand::Rng;
+
+use crate::worldgen::asset::{self, PortalDirection};
+
+use super::{
+    consts::{ROOM_SHYNESS, SEQUENCE_DISTANCE, TUNNEL_SHYNESS},
+    room::Portal,
+    utility::Arrangement,
+};
+
+/// Loops are only closed between portals that face each other at least this much. 0 allows
+/// portals at right angles, 1 only allows portals that face each other directly.
+const LOOP_MIN_FACING: f32 = 0.25;
+
+/// Controls how often the layout closes loops. A loop connects an exit of a new sequence back to
+/// an unconnected entrance of the previous one, so the caves form circular routes instead of a
+/// strictly linear path.
+#[derive(Resource)]
+pub struct LayoutLoops {
+    /// Chance that each sequence tries to close a loop, from 0 to 1.
+    pub chance: f64,
+    /// Loops are only closed between portals at most this far apart.
+    pub max_distance: f32,
+}
+
+impl Default for LayoutLoops {
+    fn default() -> Self {
+        Self {
+            chance: 0.25,
+            max_distance: SEQUENCE_DISTANCE * 1.5,
+        }
+    }
+}
+
+/// An unconnected entrance that a loop can connect back to.
+pub(super) struct LoopTarget {
+    pub portal: Entity,
+    pub room: Entity,
+    pub room_radius: f32,
+    pub transform: GlobalTransform,
+}
+
+/// A loop from an exit of a new room back to a target.
+pub(super) struct Loop {
+    pub room: usize,
+    /// Index of the exit within the room's portals.
+    pub exit: usize,
+    pub target: usize,
+}
+
+/// Picks a loop that's likely to be pathfindable. Both portals must face each other, be close
+/// enough, and have nothing between them except the rooms they belong to. At least one other
+/// exit is left unconnected so the layout can continue.
+pub(super) fn find_loop<R>(
+    loops: &LayoutLoops,
+    rooms: &[asset::Room],
+    arrangements: &[Arrangement],
+    targets: &[LoopTarget],
+    obstacles: &[(Option<Entity>, Arrangement)],
+    rng: &mut R,
+) -> Option<Loop>
+where
+    R: Rng + ?Sized,
+{
+    // Bidirectional portals may become entrances when the rooms spawn, so only exits are used
+    let exits = rooms
+        .iter()
+        .zip(arrangements)
+        .enumerate()
+        .flat_map(|(room_index, (room, arrangement))| {
+            let mut room_transform = arrangement.transform();
+            room_transform.translation += room.inverse_world_origin_offset();
+            room.portals
+                .iter()
+                .enumerate()
+                .filter(|(_, portal)| portal.direction == PortalDirection::Exit)
+                .map(move |(exit_index, portal)| {
+                    let transform = GlobalTransform::from(room_transform * portal.transform);
+                    (room_index, exit_index, room.radius(), transform)
+                })
+        })
+        .collect::<Vec<_>>();
+    if exits.len() < 2 {
+        return None;
+    }
+
+    let exit_portal = Portal {
+        direction: PortalDirection::Exit,
+        connection: None,
+    };
+    let entrance_portal = Portal {
+        direction: PortalDirection::Entrance,
+        connection: None,
+    };
+
+    let mut candidates = Vec::new();
+    for (room_index, exit_index, exit_radius, exit_transform) in exits.iter() {
+        for (target_index, target) in targets.iter().enumerate() {
+            let start = exit_transform.translation();
+            let end = target.transform.translation();
+            let Ok(direction) = Dir3::new(end - start) else {
+                continue;
+            };
+            if start.distance(end) > loops.max_distance {
+                continue;
+            }
+
+            let exit_outward = -exit_portal.inward(exit_transform);
+            let target_outward = -entrance_portal.inward(&target.transform);
+            if exit_outward.dot(*direction) < LOOP_MIN_FACING
+                || target_outward.dot(-*direction) < LOOP_MIN_FACING
+            {
+                continue;
+            }
+
+            // Same points the tunnel is pathfound between
+            let corridor = Collider::capsule_endpoints(
+                TUNNEL_SHYNESS,
+                start + exit_outward * (exit_radius + ROOM_SHYNESS),
+                end + target_outward * (target.room_radius + ROOM_SHYNESS),
+            );
+            let blocked = |arrangement: &Arrangement| {
+                intersection_test(
+                    &corridor,
+                    Position::default(),
+                    Rotation::default(),
+                    &arrangement.collider,
+                    arrangement.position,
+                    arrangement.rotation,
+                )
+                .unwrap_or(true)
+            };
+            let blocked_by_new_room = arrangements
+                .iter()
+                .enumerate()
+                .any(|(index, arrangement)| index != *room_index && blocked(arrangement));
+            let blocked_by_obstacle = obstacles
+                .iter()
+                .any(|(owner, arrangement)| *owner != Some(target.room) && blocked(arrangement));
+            if blocked_by_new_room || blocked_by_obstacle {
+                continue;
+            }
+
+            candidates.push(Loop {
+                room: *room_index,
+                exit: *exit_index,
+                target: target_index,
+            });
+        }
+    }
+
+    if candidates.is_empty() {
+        return None;
+    }
+    let index = rng.gen_range(0..candidates.len());
+    Some(candidates.swap_remove(index))
+}
@@ -1,71 +1,269 @@
-use std::{f32::consts::PI, fs::File, io::Read};
+use std::{
+    f32::consts::PI,
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+};
 
+use ambience::AmbiencePlugin;
+use atmosphere::AtmospherePlugin;
 use avian3d::prelude::{Collider, Collision};
 use bevy::{
     ecs::{system::SystemState, world::CommandQueue},
     prelude::*,
+    tasks::AsyncComputeTaskPool,
+    utils::HashMap,
 };
 use bevy_rand::{
     global::GlobalEntropy,
     prelude::{Entropy, WyRand},
     traits::ForkableRng,
 };
+use cleanup::CleanupPlugin;
 use consts::{ROOM_SHYNESS, SEQUENCE_DISTANCE};
-use rand::Rng;
-use room::{Portal, Room, SpawnRoomCommand};
+use culling::CullingPlugin;
+use lighting::LightingPlugin;
+use occupancy::OccupancyPlugin;
+use pacing::PacingPlugin;
+use progress::{set_stage, LayoutProgressPlugin, LayoutTask};
+use rand::{Rng, SeedableRng};
+use room::SpawnRoomCommand;
+use seal::SealPlugin;
+use signage::SignagePlugin;
+use skylight::SkylightPlugin;
 use tunnel::{connect_portals, LayoutTrigger, PortalConnection};
 use utility::{arrange_by_depenetration, Arrangement};
 
-use crate::player::IsPlayer;
+use crate::{
+    input::{ActionInput, InputAction, InputBindingsPlugin},
+    player::IsPlayer,
+    save::SaveGame,
+};
 
-use super::asset::{AssetCollection, PortalDirection, RoomFlags};
+use super::asset::{
+    self,
+    migration::Versioned,
+    pack::{self, AssetPacks},
+    AssetCollection, PortalDirection, RoomFlags, RoomQuery,
+};
 
-mod consts;
+mod ambience;
+mod atmosphere;
+mod cleanup;
+pub mod consts;
+mod culling;
+mod gravity;
+mod lighting;
+mod loops;
+mod occupancy;
+mod pacing;
+mod progress;
 mod room;
+mod save;
+mod seal;
+mod signage;
+mod skylight;
 mod tunnel;
 mod utility;
-pub use room::Spawnpoint;
+pub use ambience::{AudioZone, CurrentAudioZone, Reverb};
+pub use atmosphere::DefaultAtmosphere;
+pub use cleanup::{AudioFadeOut, BelongsToRoom};
+pub use culling::CaveCulling;
+pub use gravity::{GravityZone, GravityZonePlugin, GravityZones, STANDARD_GRAVITY};
+pub use lighting::{CaveLight, CaveLighting};
+pub use loops::LayoutLoops;
+pub use occupancy::{CurrentRoom, RoomChangedEvent, TrackCurrentRoom};
+pub use pacing::{ContentMix, PacingController, PacingCurve, PacingPoint};
+pub use progress::{LayoutProgress, LayoutProgressEvent, LayoutStage};
+pub use room::{
+    volume_contains, Annotation, PatrolPath, Portal, Room, RoomCavity, SpawnRoomAssetCommand,
+    Spawnpoint,
+};
+pub use save::{SavedConnection, SavedLayout, SavedRoom};
+pub use seal::{PortalSealing, Sealed};
+pub use signage::{Sign, SignageSettings};
+pub use skylight::{Skylight, SkylightSettings};
+pub use tunnel::{PendingPortalConnection, PortalConnection};
+
+/// Seed the world is generated with. The same seed always produces the same layout. Insert it
+/// before startup, otherwise the layout is seeded from global entropy.
+#[derive(Resource, Clone, Copy)]
+pub struct WorldSeed(pub u64);
+
+/// Where the asset collection and pacing curve are read from. Relative to the working directory
+/// unless it's replaced before startup, e.g. by tests.
+#[derive(Resource, Clone, Debug)]
+pub struct LayoutAssetDirectory(pub PathBuf);
+
+impl Default for LayoutAssetDirectory {
+    fn default() -> Self {
+        Self(PathBuf::from("./assets"))
+    }
+}
 
 #[derive(Resource)]
 pub struct LayoutState {
     pub rng: Entropy<WyRand>,
+    /// Seed of the layout, kept for RNGs that must not depend on what was generated before.
+    pub seed: u64,
     pub sequence: usize,
 }
 
+impl LayoutState {
+    /// RNG for one part of the layout, e.g. a tunnel. It only depends on the seed and the key, so
+    /// the part comes out the same whenever it's spawned, including after loading a save.
+    pub fn rng_for(&self, key: impl Hash) -> Entropy<WyRand> {
+        let mut hasher = DefaultHasher::new();
+        (self.seed, key).hash(&mut hasher);
+        Entropy::<WyRand>::seed_from_u64(hasher.finish())
+    }
+}
+
+/// Rooms the layout must include at specific sequences, e.g. a boss room every few sequences.
+/// The first room of the sequence is chosen with the query instead of [`RoomQuery::regular`].
+///
+/// A dead end ends the layout if nothing else in its sequence has an exit.
+#[derive(Resource, Default)]
+pub struct LayoutMilestones(pub HashMap<usize, RoomQuery>);
+
+/// Shapes the layout in three dimensions. By default, sequences stay level and rooms only turn
+/// around the vertical axis.
+#[derive(Resource, Default, Clone, Copy)]
+pub struct LayoutGradient {
+    /// Angle between successive sequences and the horizontal plane, in radians. Negative values
+    /// make the caves trend downward, positive values upward.
+    pub pitch: f32,
+    /// Rooms are tilted by up to this angle, in radians. Rooms with fluid or gravity zones are
+    /// authored upright, so keep this small.
+    pub max_tilt: f32,
+}
+
+impl LayoutGradient {
+    /// Direction to place the next sequence in, from the horizontal direction of travel.
+    pub fn direction(&self, horizontal: Vec3) -> Vec3 {
+        (horizontal * self.pitch.cos() + Vec3::Y * self.pitch.sin()).normalize_or(horizontal)
+    }
+
+    /// Random orientation for a room.
+    pub fn rotation<R>(&self, rng: &mut R) -> Quat
+    where
+        R: Rng + ?Sized,
+    {
+        let yaw = rng.gen_range(0.0..(2.0 * PI));
+        if self.max_tilt <= 0.0 {
+            return Quat::from_euler(EulerRot::YXZ, yaw, 0.0, 0.0);
+        }
+
+        let pitch = rng.gen_range(-self.max_tilt..=self.max_tilt);
+        let roll = rng.gen_range(-self.max_tilt..=self.max_tilt);
+        Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll)
+    }
+}
+
 pub struct InitLayoutCommand {
     pub after: CommandQueue,
+    /// Resume the layout from a save instead of generating a new one.
+    pub from_save: Option<PathBuf>,
 }
+/// Generates the next sequence of rooms. Rooms are arranged in the background, so they're
+/// spawned a few frames later. Follow along with [`LayoutProgressEvent`].
 pub struct StepLayoutCommand;
 
 pub struct LayoutPlugin;
 
 impl Plugin for LayoutPlugin {
     fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<InputBindingsPlugin>() {
+            app.add_plugins(InputBindingsPlugin);
+        }
+        app.add_plugins((
+            AmbiencePlugin,
+            AtmospherePlugin,
+            CleanupPlugin,
+            CullingPlugin,
+            GravityZonePlugin,
+            LayoutProgressPlugin,
+            LightingPlugin,
+            OccupancyPlugin,
+            PacingPlugin,
+            SealPlugin,
+            SignagePlugin,
+            SkylightPlugin,
+        ));
+        app.init_resource::<LayoutMilestones>();
+        app.init_resource::<LayoutLoops>();
+        app.init_resource::<LayoutGradient>();
+        app.init_resource::<LayoutAssetDirectory>();
         app.add_systems(Startup, (load_asset_collection, setup_state).chain());
         app.add_systems(Update, (debug, connect_portals, triggers));
     }
 }
 
-fn load_asset_collection(mut commands: Commands) {
-    let path = if cfg!(debug_assertions) {
-        "./assets/worldgen.staging.cbor"
+fn load_asset_collection(mut commands: Commands, directory: Res<LayoutAssetDirectory>) {
+    let path = directory.0.join(if cfg!(debug_assertions) {
+        "worldgen.staging.cbor"
     } else {
-        "./assets/worldgen.production.cbor"
+        "worldgen.production.cbor"
+    });
+
+    // Packs can still provide rooms and tunnels without the built-in collection
+    let mut assets = match read_asset_collection(&path) {
+        Ok((assets, migrations)) => {
+            for migration in migrations {
+                info!("migrated worldgen asset collection: {migration}");
+            }
+            assets
+        }
+        Err(err) => {
+            error!(
+                "failed to load worldgen asset collection {}: {err}",
+                path.display()
+            );
+            AssetCollection::default()
+        }
     };
 
-    let mut file = File::open(path).expect("worldgen asset collection does not exist");
-    let mut vec = Vec::new();
-    file.read_to_end(&mut vec)
-        .expect("failed to read worldgen asset collection");
-    let assets: AssetCollection =
-        cbor4ii::serde::from_slice(&vec).expect("failed to deserialize worldgen asset collection");
+    let packs = pack::load_packs(&mut assets, Path::new(pack::MODS_DIRECTORY));
+    for report in packs.iter() {
+        if !report.enabled {
+            info!("asset pack {} is disabled", report.name);
+            continue;
+        }
+        info!(
+            "loaded asset pack {} with {} rooms and {} tunnels",
+            report.name, report.rooms, report.tunnels
+        );
+        for problem in report.problems.iter() {
+            warn!("asset pack {}: {problem}", report.name);
+        }
+    }
 
     commands.insert_resource(assets);
+    commands.insert_resource(AssetPacks(packs));
 }
 
-pub fn setup_state(mut commands: Commands, mut rng: GlobalEntropy<WyRand>) {
+fn read_asset_collection(path: &Path) -> anyhow::Result<(AssetCollection, Vec<&'static str>)> {
+    let mut file = File::open(path)?;
+    let mut vec = Vec::new();
+    file.read_to_end(&mut vec)?;
+
+    AssetCollection::from_cbor(&vec)
+}
+
+pub fn setup_state(
+    mut commands: Commands,
+    seed: Option<Res<WorldSeed>>,
+    mut rng: GlobalEntropy<WyRand>,
+) {
+    // Seeding directly keeps the layout independent of anything else drawing from global entropy
+    let seed = match seed {
+        Some(seed) => seed.0,
+        None => rng.gen(),
+    };
     commands.insert_resource(LayoutState {
-        rng: rng.fork_rng(),
+        rng: Entropy::<WyRand>::from_seed(seed.to_le_bytes()),
+        seed,
         sequence: 0,
     });
 }
@@ -73,10 +271,10 @@ pub fn setup_state(mut commands: Commands, mut rng: GlobalEntropy<WyRand>) {
 fn debug(
     mut gizmos: Gizmos,
     mut commands: Commands,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    input: ActionInput,
     portals: Query<(&Portal, &GlobalTransform)>,
 ) {
-    if keyboard.just_released(KeyCode::KeyN) {
+    if input.just_released(InputAction::StepLayout) {
         commands.queue(StepLayoutCommand);
     }
 
@@ -199,6 +397,13 @@ fn walk_room(
             continue;
         };
 
+        // Loops lead back to rooms that are walked some other way, so they're unloaded along
+        // with this room instead of being walked through
+        if connection.1.looped {
+            entity_distances.push((connection_entity, depth));
+            continue;
+        }
+
         walk_connection(
             entity_distances,
             connections,
@@ -247,16 +452,34 @@ fn walk_connection(
 
 impl Command for InitLayoutCommand {
     fn apply(mut self, world: &mut World) {
-        let mut system_state: SystemState<(Commands, ResMut<LayoutState>, Res<AssetCollection>)> =
-            SystemState::new(world);
-        let (mut commands, mut state, assets) = system_state.get_mut(world);
-
-        if state.sequence != 0 {
+        if world.resource::<LayoutState>().sequence != 0 {
             panic!("layout is already initialized");
         }
 
+        if let Some(path) = self.from_save.take() {
+            let save = SaveGame::read(&path).expect("failed to read save");
+            save.restore(&path, world, self.after);
+            return;
+        }
+
+        let mut system_state: SystemState<(
+            Commands,
+            ResMut<LayoutState>,
+            Res<AssetCollection>,
+            Res<LayoutGradient>,
+            ResMut<LayoutProgress>,
+            EventWriter<LayoutProgressEvent>,
+        )> = SystemState::new(world);
+        let (mut commands, mut state, assets, gradient, mut progress, mut events) =
+            system_state.get_mut(world);
+
+        let query = RoomQuery {
+            flags: RoomFlags::Spawnable,
+            ..RoomQuery::regular()
+        };
         let room = assets
-            .random_room_with_flags(RoomFlags::Spawnable, &mut state.rng)
+            .random_room_matching(&query, &mut state.rng, |_| 1.0)
+            .expect("no spawnable rooms")
             .clone();
         commands.queue(SpawnRoomCommand {
             sequence: 0,
@@ -264,17 +487,13 @@ impl Command for InitLayoutCommand {
                 spherical: true,
                 collider: Collider::sphere(room.radius() + ROOM_SHYNESS),
                 position: (state.rng.gen::<Vec3>() - Vec3::splat(0.5)).into(),
-                rotation: Quat::from_euler(
-                    EulerRot::YXZ,
-                    state.rng.gen_range(0.0..(PI * 2.0)),
-                    0.0,
-                    0.0,
-                )
-                .into(),
+                rotation: gradient.rotation(&mut state.rng).into(),
             },
             room,
             connect_to_portals: default(),
+            close_loop: None,
         });
+        set_stage(&mut progress, &mut events, 0, LayoutStage::SpawningRooms);
 
         commands.append(&mut self.after);
         system_state.apply(world);
@@ -283,16 +502,41 @@ impl Command for InitLayoutCommand {
 
 impl Command for StepLayoutCommand {
     fn apply(self, world: &mut World) {
+        // The previous sequence's rooms need to exist to connect to them
+        let mut progress = world.resource_mut::<LayoutProgress>();
+        if progress.stage != LayoutStage::Done {
+            progress.queue_step();
+            return;
+        }
+
         let mut system_state: SystemState<(
             Commands,
             ResMut<LayoutState>,
+            ResMut<LayoutProgress>,
+            EventWriter<LayoutProgressEvent>,
             Res<AssetCollection>,
+            Res<LayoutMilestones>,
+            Res<LayoutGradient>,
+            Res<PacingCurve>,
+            ResMut<PacingController>,
             Query<&Arrangement>,
             Query<(&Room, &GlobalTransform)>,
             Query<(&Portal, Entity, &GlobalTransform)>,
         )> = SystemState::new(world);
-        let (mut commands, mut state, assets, arrangeables, rooms, portals) =
-            system_state.get_mut(world);
+        let (
+            mut commands,
+            mut state,
+            mut progress,
+            mut events,
+            assets,
+            milestones,
+            gradient,
+            pacing_curve,
+            mut pacing,
+            arrangeables,
+            rooms,
+            portals,
+        ) = system_state.get_mut(world);
 
         // Find available exit portals from the previous sequence.
         let prev_rooms = rooms
@@ -303,6 +547,15 @@ impl Command for StepLayoutCommand {
             .iter()
             .flat_map(|room| room.0.portals.clone())
             .collect::<Vec<_>>();
+        let unconnected_entrances = prev_portal_entities
+            .iter()
+            .filter(|portal| {
+                portals.get(**portal).is_ok_and(|portal| {
+                    portal.0.connection.is_none() && !portal.0.direction.is_exit()
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
         let mut prev_portals = prev_portal_entities
             .into_iter()
             .filter_map(|portal| {
@@ -315,6 +568,12 @@ impl Command for StepLayoutCommand {
             })
             .collect::<Vec<_>>();
 
+        // Dead ends can leave nothing to connect to, which ends the layout
+        if prev_portals.is_empty() {
+            info!("layout ended at sequence {}", state.sequence);
+            return;
+        }
+
         state.sequence += 1;
 
         // Choose next rooms.
@@ -323,9 +582,31 @@ impl Command for StepLayoutCommand {
             1 => 1,
             _ => state.rng.gen_range(1..=prev_portals.len()),
         };
+        let mut next_content = ContentMix::default();
+        let regular = RoomQuery::regular();
+        let milestone = milestones.0.get(&state.sequence);
         let next_rooms = (0..next_room_count)
-            .map(|_| assets.random_room(&mut state.rng).clone())
+            .map(|i| {
+                let sequence = state.sequence;
+                let bias = |room: &asset::Room| {
+                    pacing.bias(&pacing_curve, sequence, room.content, &next_content)
+                };
+                let milestone_room = milestone.filter(|_| i == 0).and_then(|query| {
+                    let room = assets.random_room_matching(query, &mut state.rng, bias);
+                    if room.is_none() {
+                        warn!("no rooms match the milestone for sequence {sequence}");
+                    }
+                    room
+                });
+                let room = milestone_room
+                    .or_else(|| assets.random_room_matching(&regular, &mut state.rng, bias))
+                    .expect("no regular rooms")
+                    .clone();
+                next_content.add(room.content, 1.0);
+                room
+            })
             .collect::<Vec<_>>();
+        pacing.record(&pacing_curve, next_content.normalized());
 
         // Arrange next rooms.
         let prev_room_positions = rooms
@@ -339,7 +620,7 @@ impl Command for StepLayoutCommand {
             .collect::<Vec<_>>();
         let avg_position =
             prev_room_positions.iter().sum::<Vec3>() / prev_room_positions.len() as f32;
-        let bias_direction = avg_position.cross(Vec3::Y).normalize();
+        let bias_direction = gradient.direction(avg_position.cross(Vec3::Y).normalize());
         let start_position = avg_position + bias_direction * SEQUENCE_DISTANCE;
         let mut next_room_arrangeables = next_rooms
             .iter()
@@ -349,13 +630,7 @@ impl Command for StepLayoutCommand {
                     spherical,
                     collider: Collider::sphere(room.radius() + ROOM_SHYNESS),
                     position: (start_position + state.rng.gen::<Vec3>() - Vec3::splat(0.5)).into(),
-                    rotation: Quat::from_euler(
-                        EulerRot::YXZ,
-                        state.rng.gen_range(0.0..(2.0 * PI)),
-                        0.0,
-                        0.0,
-                    )
-                    .into(),
+                    rotation: gradient.rotation(&mut state.rng).into(),
                 }
             })
             .collect::<Vec<Arrangement>>();
@@ -363,26 +638,46 @@ impl Command for StepLayoutCommand {
             .iter()
             .map(|arrangeable| arrangeable.clone())
             .collect();
-        arrange_by_depenetration(&mut next_room_arrangeables, static_arrangeables);
+        let mut arrangement_rng = state.rng.fork_rng();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            arrange_by_depenetration(
+                &mut next_room_arrangeables,
+                static_arrangeables,
+                &mut arrangement_rng,
+            );
+            next_room_arrangeables
+        });
 
-        next_rooms
-            .into_iter()
-            .zip(next_room_arrangeables)
-            .for_each(|(room, arrangement)| {
+        let from_portals = next_rooms
+            .iter()
+            .map(|_| {
                 let exit_index = match prev_portals.len() {
                     0 => panic!("no unconnected exits"),
                     1 => 0,
                     _ => state.rng.gen_range(0..prev_portals.len()),
                 };
-                let from_portal = prev_portals.remove(exit_index);
+                prev_portals.remove(exit_index).1
+            })
+            .collect();
 
-                commands.queue(SpawnRoomCommand {
-                    sequence: state.sequence,
-                    arrangement,
-                    room,
-                    connect_to_portals: vec![from_portal.1],
-                });
-            });
+        // Exits that weren't chosen will never be connected, so seal them. Entrances that were
+        // left over are sealed once the next rooms are arranged, unless a loop closes on one.
+        let seal_portals = prev_portals.iter().map(|portal| portal.1).collect();
+
+        commands.spawn(LayoutTask {
+            sequence: state.sequence,
+            task,
+            rooms: next_rooms,
+            from_portals,
+            seal_portals,
+            loop_targets: unconnected_entrances,
+        });
+        set_stage(
+            &mut progress,
+            &mut events,
+            state.sequence,
+            LayoutStage::Arranging,
+        );
 
         system_state.apply(world);
     }
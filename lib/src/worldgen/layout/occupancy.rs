@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+use crate::player::IsPlayer;
+
+use super::room::Room;
+
+/// Extra distance the player can stray outside of the current room before leaving it, so
+/// standing in a doorway doesn't flicker between rooms.
+const ROOM_EXIT_MARGIN: f32 = 4.0;
+
+/// The room that contains the player, if any.
+#[derive(Resource, Default, PartialEq)]
+pub struct CurrentRoom(pub Option<Entity>);
+
+/// Sent when the player moves between rooms, or between a room and a tunnel.
+#[derive(Event, Debug)]
+pub struct RoomChangedEvent {
+    pub previous: Option<Entity>,
+    pub current: Option<Entity>,
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TrackCurrentRoom;
+
+pub struct OccupancyPlugin;
+
+impl Plugin for OccupancyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentRoom>();
+        app.add_event::<RoomChangedEvent>();
+        app.add_systems(Update, track_current_room.in_set(TrackCurrentRoom));
+    }
+}
+
+fn track_current_room(
+    mut current_room: ResMut<CurrentRoom>,
+    mut changed: EventWriter<RoomChangedEvent>,
+    player: Option<Single<&GlobalTransform, With<IsPlayer>>>,
+    rooms: Query<(Entity, &Room, &GlobalTransform)>,
+) {
+    let Some(player) = player else {
+        return;
+    };
+    let position = player.translation();
+    let distance_to = |(room, transform): (&Room, &GlobalTransform)| {
+        transform.transform_point(room.center).distance(position)
+    };
+
+    // Stay in the current room until the player is clearly outside of it
+    let stays = current_room.0.is_some_and(|entity| {
+        rooms.get(entity).is_ok_and(|(_, room, transform)| {
+            distance_to((room, transform)) <= room.radius + ROOM_EXIT_MARGIN
+        })
+    });
+    if stays {
+        return;
+    }
+
+    let closest = rooms
+        .iter()
+        .filter_map(|(entity, room, transform)| {
+            let distance = distance_to((room, transform));
+            (distance <= room.radius).then_some((entity, distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity);
+
+    if current_room.0 != closest {
+        changed.send(RoomChangedEvent {
+            previous: current_room.0,
+            current: closest,
+        });
+        current_room.0 = closest;
+    }
+}
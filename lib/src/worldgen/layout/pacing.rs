@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::worldgen::asset::RoomContent;
+
+use super::LayoutAssetDirectory;
+
+/// Read from the [`LayoutAssetDirectory`].
+const PACING_CURVE_FILE_NAME: &str = "pacing.ron";
+
+/// Keeps content that's far from its target from being weighted into oblivion or taking over.
+const MIN_BIAS: f32 = 0.1;
+const MAX_BIAS: f32 = 10.0;
+/// Avoids dividing by zero when some content hasn't appeared recently.
+const BIAS_EPSILON: f32 = 0.05;
+
+/// Proportions of each kind of content.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ContentMix {
+    pub combat: f32,
+    pub loot: f32,
+    pub transit: f32,
+}
+
+impl ContentMix {
+    pub fn get(&self, content: RoomContent) -> f32 {
+        match content {
+            RoomContent::Combat => self.combat,
+            RoomContent::Loot => self.loot,
+            RoomContent::Transit => self.transit,
+        }
+    }
+
+    pub fn add(&mut self, content: RoomContent, amount: f32) {
+        match content {
+            RoomContent::Combat => self.combat += amount,
+            RoomContent::Loot => self.loot += amount,
+            RoomContent::Transit => self.transit += amount,
+        }
+    }
+
+    pub fn total(&self) -> f32 {
+        self.combat + self.loot + self.transit
+    }
+
+    pub fn normalized(&self) -> Self {
+        let total = self.total();
+        if total <= 0.0 {
+            return *self;
+        }
+
+        Self {
+            combat: self.combat / total,
+            loot: self.loot / total,
+            transit: self.transit / total,
+        }
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            combat: self.combat.lerp(other.combat, t),
+            loot: self.loot.lerp(other.loot, t),
+            transit: self.transit.lerp(other.transit, t),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PacingPoint {
+    pub sequence: usize,
+    pub mix: ContentMix,
+}
+
+/// Target content mix over the course of a run. Loaded from `assets/pacing.ron`.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct PacingCurve {
+    /// Number of previous sequences compared against the curve.
+    pub window: usize,
+    /// How strongly room selection is pushed toward the curve. Zero disables pacing.
+    pub strength: f32,
+    /// Sorted by sequence. The mix is interpolated between points, and held past the ends.
+    pub points: Vec<PacingPoint>,
+}
+
+impl Default for PacingCurve {
+    fn default() -> Self {
+        Self {
+            window: 4,
+            strength: 1.0,
+            points: vec![
+                PacingPoint {
+                    sequence: 0,
+                    mix: ContentMix {
+                        combat: 0.2,
+                        loot: 0.3,
+                        transit: 0.5,
+                    },
+                },
+                PacingPoint {
+                    sequence: 12,
+                    mix: ContentMix {
+                        combat: 0.45,
+                        loot: 0.25,
+                        transit: 0.3,
+                    },
+                },
+            ],
+        }
+    }
+}
+
+impl PacingCurve {
+    pub fn target(&self, sequence: usize) -> ContentMix {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return ContentMix::default();
+        };
+        if sequence <= first.sequence {
+            return first.mix.normalized();
+        }
+        if sequence >= last.sequence {
+            return last.mix.normalized();
+        }
+
+        let next = self
+            .points
+            .iter()
+            .position(|point| point.sequence > sequence)
+            .unwrap_or(self.points.len() - 1);
+        let (a, b) = (&self.points[next - 1], &self.points[next]);
+        let t = (sequence - a.sequence) as f32 / (b.sequence - a.sequence).max(1) as f32;
+
+        a.mix.normalized().lerp(&b.mix.normalized(), t)
+    }
+}
+
+/// Remembers the content of recent sequences to steer the next one toward the pacing curve.
+#[derive(Resource, Default)]
+pub struct PacingController {
+    history: VecDeque<ContentMix>,
+}
+
+impl PacingController {
+    pub fn record(&mut self, curve: &PacingCurve, sequence: ContentMix) {
+        self.history.push_back(sequence);
+        while self.history.len() > curve.window.max(1) {
+            self.history.pop_front();
+        }
+    }
+
+    /// Content of the last few sequences, plus content already chosen for the next one.
+    pub fn recent(&self, pending: &ContentMix) -> ContentMix {
+        let mut recent = *pending;
+        for sequence in self.history.iter() {
+            recent.combat += sequence.combat;
+            recent.loot += sequence.loot;
+            recent.transit += sequence.transit;
+        }
+
+        recent.normalized()
+    }
+
+    /// Multiplier for the weight of rooms, or spawner entries, that offer `content`. Content that
+    /// has been scarce compared to the curve is favored, and content that's been overdone is
+    /// avoided.
+    pub fn bias(
+        &self,
+        curve: &PacingCurve,
+        sequence: usize,
+        content: RoomContent,
+        pending: &ContentMix,
+    ) -> f32 {
+        if curve.strength <= 0.0 || (self.history.is_empty() && pending.total() <= 0.0) {
+            return 1.0;
+        }
+
+        let target = curve.target(sequence).get(content);
+        let recent = self.recent(pending).get(content);
+
+        ((target + BIAS_EPSILON) / (recent + BIAS_EPSILON))
+            .powf(curve.strength)
+            .clamp(MIN_BIAS, MAX_BIAS)
+    }
+}
+
+pub struct PacingPlugin;
+
+impl Plugin for PacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PacingController>();
+        app.add_systems(Startup, load_pacing_curve);
+    }
+}
+
+fn load_pacing_curve(mut commands: Commands, directory: Res<LayoutAssetDirectory>) {
+    let curve = match std::fs::read_to_string(directory.0.join(PACING_CURVE_FILE_NAME)) {
+        Ok(s) => ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("failed to parse pacing curve, using default: {err}");
+            PacingCurve::default()
+        }),
+        Err(_) => PacingCurve::default(),
+    };
+
+    commands.insert_resource(curve);
+}
@@ -0,0 +1,213 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, Task},
+};
+use rand::Rng;
+
+use crate::worldgen::asset;
+
+use super::{
+    loops::{find_loop, LayoutLoops, LoopTarget},
+    room::{Portal, Room, SpawnRoomCommand},
+    seal::SealPortalCommand,
+    tunnel::PendingPortalConnection,
+    utility::Arrangement,
+    LayoutState, StepLayoutCommand,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutStage {
+    /// Rooms are being arranged in the background.
+    Arranging,
+    SpawningRooms,
+    /// Waiting for tunnels to be pathfound between portals.
+    ConnectingPortals,
+    Done,
+}
+
+/// Sent whenever generating a sequence moves to the next stage, to drive loading screens.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct LayoutProgressEvent {
+    pub sequence: usize,
+    pub stage: LayoutStage,
+}
+
+/// Stage of the sequence that's currently generating, or the last one if it's done.
+#[derive(Resource, Debug)]
+pub struct LayoutProgress {
+    pub sequence: usize,
+    pub stage: LayoutStage,
+    /// Steps requested while a sequence was still generating. They run once it's done.
+    queued_steps: usize,
+}
+
+impl Default for LayoutProgress {
+    fn default() -> Self {
+        Self {
+            sequence: 0,
+            stage: LayoutStage::Done,
+            queued_steps: 0,
+        }
+    }
+}
+
+impl LayoutProgress {
+    pub fn is_done(&self) -> bool {
+        self.stage == LayoutStage::Done && self.queued_steps == 0
+    }
+
+    pub(super) fn queue_step(&mut self) {
+        self.queued_steps += 1;
+    }
+}
+
+/// Arranges the next sequence's rooms in the background, then spawns them.
+#[derive(Component)]
+pub(super) struct LayoutTask {
+    pub sequence: usize,
+    pub task: Task<Vec<Arrangement>>,
+    pub rooms: Vec<asset::Room>,
+    /// Exit each room connects to, in the same order as the rooms.
+    pub from_portals: Vec<Entity>,
+    pub seal_portals: Vec<Entity>,
+    /// Unconnected entrances from the previous sequence. One may be connected back to by a
+    /// loop, and the rest are sealed.
+    pub loop_targets: Vec<Entity>,
+}
+
+pub struct LayoutProgressPlugin;
+
+impl Plugin for LayoutProgressPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LayoutProgress>();
+        app.add_event::<LayoutProgressEvent>();
+        app.add_systems(Update, (receive_layout_tasks, advance_progress).chain());
+    }
+}
+
+/// Sets the stage and sends the event for it.
+pub(super) fn set_stage(
+    progress: &mut LayoutProgress,
+    events: &mut EventWriter<LayoutProgressEvent>,
+    sequence: usize,
+    stage: LayoutStage,
+) {
+    progress.sequence = sequence;
+    progress.stage = stage;
+    events.send(LayoutProgressEvent { sequence, stage });
+}
+
+fn receive_layout_tasks(
+    mut commands: Commands,
+    mut state: ResMut<LayoutState>,
+    mut progress: ResMut<LayoutProgress>,
+    mut events: EventWriter<LayoutProgressEvent>,
+    loops: Res<LayoutLoops>,
+    mut tasks: Query<(Entity, &mut LayoutTask)>,
+    portals: Query<(&GlobalTransform, &Parent), With<Portal>>,
+    rooms: Query<&Room>,
+    placed: Query<(&Arrangement, Option<&Parent>)>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let status = block_on(future::poll_once(&mut task.task));
+        let Some(arrangements) = status else {
+            continue;
+        };
+
+        let sequence = task.sequence;
+        let next_rooms = std::mem::take(&mut task.rooms);
+        let from_portals = std::mem::take(&mut task.from_portals);
+        let mut loop_targets = task
+            .loop_targets
+            .drain(..)
+            .filter_map(|portal| {
+                let (transform, parent) = portals.get(portal).ok()?;
+                Some(LoopTarget {
+                    portal,
+                    room: parent.get(),
+                    room_radius: rooms.get(parent.get()).ok()?.radius,
+                    transform: *transform,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Close a loop
+        let mut close_loop = None;
+        if !loop_targets.is_empty() && state.rng.gen_bool(loops.chance.clamp(0.0, 1.0)) {
+            let obstacles = placed
+                .iter()
+                .map(|(arrangement, parent)| (parent.map(Parent::get), arrangement.clone()))
+                .collect::<Vec<_>>();
+            close_loop = find_loop(
+                &loops,
+                &next_rooms,
+                &arrangements,
+                &loop_targets,
+                &obstacles,
+                &mut state.rng,
+            )
+            .map(|found| {
+                let target = loop_targets.swap_remove(found.target);
+                (found.room, (found.exit, target.portal))
+            });
+        }
+
+        next_rooms
+            .into_iter()
+            .zip(arrangements)
+            .zip(from_portals)
+            .enumerate()
+            .for_each(|(index, ((room, arrangement), from_portal))| {
+                commands.queue(SpawnRoomCommand {
+                    sequence,
+                    arrangement,
+                    room,
+                    connect_to_portals: vec![from_portal],
+                    close_loop: close_loop
+                        .filter(|(loop_room, _)| *loop_room == index)
+                        .map(|(_, exit)| exit),
+                });
+            });
+        task.seal_portals
+            .drain(..)
+            .chain(loop_targets.into_iter().map(|target| target.portal))
+            .for_each(|portal| commands.queue(SealPortalCommand { portal }));
+
+        commands.entity(entity).despawn();
+        set_stage(
+            &mut progress,
+            &mut events,
+            sequence,
+            LayoutStage::SpawningRooms,
+        );
+    }
+}
+
+fn advance_progress(
+    mut commands: Commands,
+    mut progress: ResMut<LayoutProgress>,
+    mut events: EventWriter<LayoutProgressEvent>,
+    pending: Query<&PendingPortalConnection>,
+) {
+    let sequence = progress.sequence;
+    let connecting = pending.iter().any(|pending| pending.sequence == sequence);
+
+    match progress.stage {
+        LayoutStage::SpawningRooms if connecting => {
+            set_stage(
+                &mut progress,
+                &mut events,
+                sequence,
+                LayoutStage::ConnectingPortals,
+            );
+        }
+        LayoutStage::SpawningRooms | LayoutStage::ConnectingPortals if !connecting => {
+            set_stage(&mut progress, &mut events, sequence, LayoutStage::Done);
+        }
+        LayoutStage::Done if progress.queued_steps > 0 => {
+            progress.queued_steps -= 1;
+            commands.queue(StepLayoutCommand);
+        }
+        _ => {}
+    }
+}
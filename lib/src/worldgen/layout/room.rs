@@ -1,19 +1,39 @@
 use bevy::{ecs::system::SystemState, prelude::*};
 use rand::Rng;
 
-use crate::worldgen::{
-    asset::{self, PortalDirection},
-    brush::TerrainBrush,
-    voxel::VoxelMaterial,
+use crate::{
+    meshgen::{AddDoorwayToEntity, AddSwitchToEntity, SwitchChannel, SwitchKind},
+    worldgen::{
+        asset::{self, AnnotationKind, PortalDirection, ReverbPreset, RoomAtmosphere, RoomFlags},
+        brush::{BrushOperation, TerrainBrush},
+        fluid::FluidVolume,
+        terrain::{PlaceOnTerrain, PlacementRetry, TerrainDetail},
+        voxel::VoxelMaterial,
+    },
 };
 
-use super::{tunnel::PendingPortalConnection, utility::Arrangement, LayoutState};
+use super::{
+    ambience::AudioZone,
+    consts::{ROOM_PLACEMENT_ATTEMPTS, ROOM_PLACEMENT_MAX_DROP},
+    gravity::GravityZone,
+    lighting::CaveLight,
+    tunnel::PendingPortalConnection,
+    utility::Arrangement,
+    LayoutState,
+};
 
 #[derive(Component)]
 pub struct Room {
+    /// Source of the room asset this was spawned from.
+    pub source: String,
     pub sequence: usize,
     pub portals: Vec<Entity>,
     pub radius: f32,
+    /// Center of the room's cavities, relative to the room entity.
+    pub center: Vec3,
+    pub atmosphere: RoomAtmosphere,
+    /// Estimated from the room's size if not set.
+    pub reverb: Option<ReverbPreset>,
 }
 
 #[derive(Component)]
@@ -33,11 +53,50 @@ impl Portal {
 #[derive(Component)]
 pub struct Spawnpoint;
 
+/// Brush carving out the space a room was authored in. Cavities are still placeholders, so they
+/// carve with [`VoxelMaterial::Invalid`].
+#[derive(Component)]
+pub struct RoomCavity;
+
+/// Marks an area of the room for enemies. The volume is a unit cube, scaled by the transform.
+#[derive(Component)]
+pub struct Annotation {
+    pub kind: AnnotationKind,
+}
+
+/// Whether the point is inside a unit cube, scaled by the transform. Annotations and other
+/// authored volumes are shaped like this.
+pub fn volume_contains(transform: &GlobalTransform, point: Vec3) -> bool {
+    let local = transform.affine().inverse().transform_point3(point);
+    local.abs().max_element() <= 0.5
+}
+
+/// Route for enemies to walk along, relative to the entity.
+#[derive(Component)]
+pub struct PatrolPath {
+    pub points: Vec<Vec3>,
+    pub looped: bool,
+}
+
+/// Spawns a room asset with its authored origin at the transform, along with its brushes,
+/// portals, doorways, switches, and everything else the room was authored with. Works without the
+/// layout plugin, e.g. to show a complete room in an example or test scene.
+pub struct SpawnRoomAssetCommand {
+    pub room: asset::Room,
+    pub transform: Transform,
+    /// Portals to connect to the room's entrances. The tunnels are only dug with the layout
+    /// plugin. Panics if there are more portals than entrances.
+    pub connect: Vec<Entity>,
+}
+
+/// Spawns a room that was arranged by the layout.
 pub struct SpawnRoomCommand {
     pub sequence: usize,
     pub arrangement: Arrangement,
     pub room: asset::Room,
     pub connect_to_portals: Vec<Entity>,
+    /// Connects the exit at this index back to a portal from an earlier sequence.
+    pub close_loop: Option<(usize, Entity)>,
 }
 
 fn position_and_angle_transform(position: Vec3, angle: f32) -> Transform {
@@ -45,89 +104,292 @@ fn position_and_angle_transform(position: Vec3, angle: f32) -> Transform {
     Transform::from_translation(position).with_rotation(rotation)
 }
 
-impl Command for SpawnRoomCommand {
+/// Settles room content onto the floor below where it was authored, without scattering it.
+fn place_on_room_floor() -> PlaceOnTerrain {
+    PlaceOnTerrain {
+        max_drop: ROOM_PLACEMENT_MAX_DROP,
+        retry: PlacementRetry {
+            attempts: ROOM_PLACEMENT_ATTEMPTS,
+            scatter: 0.0,
+            ..default()
+        },
+        ..default()
+    }
+}
+
+impl Command for SpawnRoomAssetCommand {
     fn apply(self, world: &mut World) {
-        let mut system_state: SystemState<(Commands, ResMut<LayoutState>)> =
-            SystemState::new(world);
-        let (mut commands, mut state) = system_state.get_mut(world);
+        spawn_room(world, self.room, self.transform, 0, self.connect);
+    }
+}
 
+impl Command for SpawnRoomCommand {
+    fn apply(self, world: &mut World) {
         let mut transform = self.arrangement.transform();
         transform.translation += self.room.inverse_world_origin_offset();
 
-        let mut room = Room {
-            sequence: self.sequence,
-            portals: default(),
-            radius: self.room.radius(),
-        };
-
-        commands
-            .spawn(transform)
-            .with_children(|parent| {
-                // Arrangement
-                parent.spawn(self.arrangement);
-
-                // Cavities
-                self.room.cavities.iter().for_each(|cavity| {
-                    parent.spawn(TerrainBrush::collider(
+        let room_entity = spawn_room(
+            world,
+            self.room,
+            transform,
+            self.sequence,
+            self.connect_to_portals,
+        );
+        let close_loop = self.close_loop.and_then(|(index, to_portal)| {
+            let room = world.get::<Room>(room_entity)?;
+            Some((*room.portals.get(index)?, to_portal))
+        });
+        world.entity_mut(room_entity).with_children(|parent| {
+            parent.spawn(self.arrangement);
+            if let Some((from_portal, to_portal)) = close_loop {
+                parent.spawn(PendingPortalConnection {
+                    sequence: self.sequence,
+                    from_portal,
+                    to_portal,
+                    path: None,
+                    looped: true,
+                });
+            }
+        });
+    }
+}
+
+/// Returns the room entity. Entrances are chosen with the layout's rng if there is one.
+fn spawn_room(
+    world: &mut World,
+    asset: asset::Room,
+    transform: Transform,
+    sequence: usize,
+    connect_to_portals: Vec<Entity>,
+) -> Entity {
+    let mut system_state: SystemState<(Commands, Option<ResMut<LayoutState>>)> =
+        SystemState::new(world);
+    let (mut commands, mut state) = system_state.get_mut(world);
+
+    let mut room = Room {
+        source: asset.source.clone(),
+        sequence,
+        portals: default(),
+        radius: asset.radius(),
+        center: -asset.inverse_world_origin_offset(),
+        atmosphere: asset.atmosphere,
+        reverb: asset.reverb,
+    };
+
+    let mut doorways = Vec::new();
+    let mut switches = Vec::new();
+
+    let room_entity = commands
+        .spawn(transform)
+        .with_children(|parent| {
+            // Cavities
+            let detail = if asset.flags.contains(RoomFlags::HighDetail) {
+                TerrainDetail::High
+            } else {
+                TerrainDetail::Standard
+            };
+            asset.cavities.iter().for_each(|cavity| {
+                parent.spawn((
+                    TerrainBrush::collider(
                         "",
-                        self.sequence,
+                        sequence,
                         VoxelMaterial::Invalid,
                         cavity.clone(),
                         transform,
-                    ));
-                });
-
-                // Portals
-                room.portals = self
-                    .room
-                    .portals
+                    ),
+                    detail,
+                    RoomCavity,
+                ));
+            });
+            asset.brushes.iter().for_each(|brush| {
+                parent.spawn((
+                    TerrainBrush::collider(
+                        "",
+                        sequence,
+                        brush.material,
+                        brush.collider.clone(),
+                        transform,
+                    )
+                    .with_operation(brush.operation),
+                    detail,
+                ));
+            });
+            if !asset.paint.is_empty() {
+                let strokes = asset
+                    .paint
                     .iter()
-                    .map(|portal| {
-                        parent
-                            .spawn((
-                                portal.transform,
-                                Portal {
-                                    direction: portal.direction,
-                                    connection: None,
-                                },
-                            ))
-                            .id()
-                    })
+                    .map(|stroke| stroke.transformed_by(transform))
                     .collect();
+                parent.spawn((TerrainBrush::paint("", sequence, strokes), detail));
+            }
 
-                // Pending connections
-                let mut entrances = room
-                    .portals
-                    .iter_mut()
-                    .zip(self.room.portals)
-                    .filter(|(_, portal)| portal.direction.is_entrance())
-                    .map(|(entity, _)| entity.clone())
-                    .collect::<Vec<_>>();
-                self.connect_to_portals.into_iter().for_each(|from_portal| {
-                    let entrance_index = match entrances.len() {
-                        0 => panic!("no unconnected entrances"),
-                        1 => 0,
-                        _ => state.rng.gen_range(0..entrances.len()),
-                    };
-                    let to_portal = entrances.remove(entrance_index);
-
-                    parent.spawn(PendingPortalConnection {
-                        sequence: self.sequence,
-                        from_portal,
-                        to_portal,
-                    });
-                });
+            // Portals
+            room.portals = asset
+                .portals
+                .iter()
+                .map(|portal| {
+                    parent
+                        .spawn((
+                            portal.transform,
+                            Portal {
+                                direction: portal.direction,
+                                connection: None,
+                            },
+                        ))
+                        .id()
+                })
+                .collect();
+
+            // Pending connections
+            let mut entrances = room
+                .portals
+                .iter_mut()
+                .zip(asset.portals)
+                .filter(|(_, portal)| portal.direction.is_entrance())
+                .map(|(entity, _)| entity.clone())
+                .collect::<Vec<_>>();
+            connect_to_portals.into_iter().for_each(|from_portal| {
+                let entrance_index = match (entrances.len(), state.as_mut()) {
+                    (0, _) => panic!("no unconnected entrances"),
+                    (1, _) | (_, None) => 0,
+                    (len, Some(state)) => state.rng.gen_range(0..len),
+                };
+                let to_portal = entrances.remove(entrance_index);
 
-                // Spawnpoints
-                self.room.spawnpoints.iter().for_each(|spawnpoint| {
-                    parent.spawn((
-                        position_and_angle_transform(spawnpoint.position, spawnpoint.angle),
-                        Spawnpoint,
-                    ));
+                parent.spawn(PendingPortalConnection {
+                    sequence,
+                    from_portal,
+                    to_portal,
+                    path: None,
+                    looped: false,
                 });
-            })
-            .insert(room);
+            });
 
-        system_state.apply(world);
-    }
+            // Spawnpoints
+            asset.spawnpoints.iter().for_each(|spawnpoint| {
+                parent.spawn((
+                    position_and_angle_transform(spawnpoint.position, spawnpoint.angle),
+                    Spawnpoint,
+                    place_on_room_floor(),
+                ));
+            });
+
+            // Annotations
+            asset.annotations.iter().for_each(|annotation| {
+                let mut entity = parent.spawn((
+                    annotation.transform,
+                    Annotation {
+                        kind: annotation.kind,
+                    },
+                ));
+                // Enemies spawn at nests and ambush points, safe zones are only regions
+                if annotation.kind != AnnotationKind::SafeZone {
+                    entity.insert(place_on_room_floor());
+                }
+            });
+            asset.patrol_paths.iter().for_each(|path| {
+                parent.spawn((
+                    Transform::default(),
+                    PatrolPath {
+                        points: path.points.clone(),
+                        looped: path.looped,
+                    },
+                ));
+            });
+
+            // Gravity zones
+            asset.gravity_zones.iter().for_each(|zone| {
+                parent.spawn((
+                    zone.transform,
+                    GravityZone {
+                        gravity: zone.gravity,
+                    },
+                ));
+            });
+
+            // Fluid volumes
+            asset.fluid_volumes.iter().for_each(|volume| {
+                parent.spawn((
+                    volume.transform,
+                    FluidVolume {
+                        level: volume.level,
+                    },
+                ));
+            });
+
+            // Audio zones
+            asset.audio_zones.iter().for_each(|zone| {
+                parent.spawn((
+                    zone.transform,
+                    AudioZone {
+                        ambience: zone.ambience.clone(),
+                        volume: zone.volume,
+                        reverb: zone.reverb,
+                    },
+                ));
+            });
+
+            // Lights
+            asset.lights.iter().for_each(|light| {
+                let [red, green, blue] = light.color;
+                parent.spawn((
+                    light.transform,
+                    CaveLight {
+                        intensity: light.intensity,
+                        flicker: light.flicker,
+                    },
+                    PointLight {
+                        color: Color::linear_rgb(red, green, blue),
+                        intensity: light.intensity,
+                        ..default()
+                    },
+                    Visibility::Hidden,
+                ));
+            });
+
+            // Doorways
+            doorways = asset
+                .doorways
+                .iter()
+                .map(|doorway| (parent.spawn(doorway.transform).id(), doorway))
+                .collect();
+
+            // Switches
+            switches = asset
+                .switches
+                .iter()
+                .map(|switch| {
+                    let mut entity = parent.spawn(switch.transform);
+                    // Buttons are mounted on walls, plates rest on the floor
+                    if switch.spec.kind != SwitchKind::Button {
+                        entity.insert(place_on_room_floor());
+                    }
+                    (entity.id(), switch.spec)
+                })
+                .collect();
+        })
+        .insert(room)
+        .id();
+
+    doorways.into_iter().for_each(|(entity, doorway)| {
+        commands.queue(AddDoorwayToEntity {
+            spec: doorway.spec,
+            entity,
+            channel: doorway.channel.map(|channel| SwitchChannel {
+                scope: room_entity,
+                channel,
+            }),
+        });
+    });
+    switches.into_iter().for_each(|(entity, spec)| {
+        commands.queue(AddSwitchToEntity {
+            spec,
+            entity,
+            scope: room_entity,
+        });
+    });
+
+    system_state.apply(world);
+
+    room_entity
 }
@@ -0,0 +1,203 @@
+use anyhow::Context;
+use avian3d::prelude::Collider;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rand::prelude::{Entropy, WyRand};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::worldgen::asset::AssetCollection;
+
+use super::{
+    consts::ROOM_SHYNESS,
+    room::{Room, SpawnRoomCommand},
+    seal::{PendingSeal, Sealed},
+    tunnel::{PendingPortalConnection, PortalConnection},
+    utility::Arrangement,
+    LayoutState,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedRoom {
+    /// Source of the room asset.
+    pub source: String,
+    pub sequence: usize,
+    pub position: Vec3,
+    pub rotation: Quat,
+    /// Indices of the portals that were sealed.
+    pub sealed: Vec<usize>,
+}
+
+/// A tunnel between two portals. Portals are identified by the index of their room in the save
+/// and their index within the room.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedConnection {
+    pub sequence: usize,
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub path: Vec<Vec3>,
+    #[serde(default)]
+    pub looped: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SavedLayout {
+    pub sequence: usize,
+    /// Seed of the layout, which tunnels and the like are generated from.
+    #[serde(default)]
+    pub seed: u64,
+    /// The layout RNG can't be serialized, so it is reseeded from this when saving and loading.
+    pub rng_seed: u64,
+    pub rooms: Vec<SavedRoom>,
+    pub connections: Vec<SavedConnection>,
+}
+
+impl SavedLayout {
+    /// Captures the rooms and connections that are currently spawned. The layout RNG is reseeded
+    /// so the running game continues the same way a loaded one will.
+    pub fn capture(world: &mut World) -> anyhow::Result<Self> {
+        let mut state = world
+            .get_resource_mut::<LayoutState>()
+            .context("layout is not initialized")?;
+        let rng_seed = state.rng.gen::<u64>();
+        state.rng = Entropy::<WyRand>::seed_from_u64(rng_seed);
+        let sequence = state.sequence;
+        let seed = state.seed;
+
+        let mut room_query = world.query::<(&Room, &Children)>();
+        let mut arrangement_query = world.query::<&Arrangement>();
+        let mut sealed_query = world.query_filtered::<(), With<Sealed>>();
+        let mut connection_query = world.query::<&PortalConnection>();
+
+        let mut rooms = Vec::new();
+        let mut portal_indices = HashMap::<Entity, (usize, usize)>::new();
+        for (room, children) in room_query.iter(world) {
+            let Some(arrangement) = children
+                .iter()
+                .find_map(|child| arrangement_query.get(world, *child).ok())
+            else {
+                continue;
+            };
+
+            let index = rooms.len();
+            portal_indices.extend(
+                room.portals
+                    .iter()
+                    .enumerate()
+                    .map(|(portal_index, portal)| (*portal, (index, portal_index))),
+            );
+            rooms.push(SavedRoom {
+                source: room.source.clone(),
+                sequence: room.sequence,
+                position: arrangement.position.0,
+                rotation: arrangement.rotation.0,
+                sealed: room
+                    .portals
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, portal)| sealed_query.get(world, **portal).is_ok())
+                    .map(|(portal_index, _)| portal_index)
+                    .collect(),
+            });
+        }
+
+        let connections = connection_query
+            .iter(world)
+            .filter_map(|connection| {
+                Some(SavedConnection {
+                    sequence: connection.sequence,
+                    from: *portal_indices.get(&connection.from_portal)?,
+                    to: *portal_indices.get(&connection.to_portal)?,
+                    path: connection.path.clone(),
+                    looped: connection.looped,
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            sequence,
+            seed,
+            rng_seed,
+            rooms,
+            connections,
+        })
+    }
+
+    /// Spawns the saved rooms and connections. Tunnels reuse their saved paths, and sealed
+    /// portals are sealed again once their transforms are ready.
+    pub fn restore(&self, world: &mut World) {
+        {
+            let mut state = world.resource_mut::<LayoutState>();
+            state.rng = Entropy::<WyRand>::seed_from_u64(self.rng_seed);
+            state.seed = self.seed;
+            state.sequence = self.sequence;
+        }
+
+        // Rooms
+        let mut room_query = world.query_filtered::<Entity, With<Room>>();
+        let mut room_entities = Vec::with_capacity(self.rooms.len());
+        for saved in self.rooms.iter() {
+            let asset = world
+                .resource::<AssetCollection>()
+                .rooms
+                .iter()
+                .find(|room| room.source == saved.source)
+                .cloned();
+            let Some(asset) = asset else {
+                warn!("saved room {} no longer exists", saved.source);
+                room_entities.push(None);
+                continue;
+            };
+
+            let existing = room_query.iter(world).collect::<HashSet<_>>();
+            SpawnRoomCommand {
+                sequence: saved.sequence,
+                arrangement: Arrangement {
+                    spherical: true,
+                    collider: Collider::sphere(asset.radius() + ROOM_SHYNESS),
+                    position: saved.position.into(),
+                    rotation: saved.rotation.into(),
+                },
+                room: asset,
+                connect_to_portals: default(),
+                close_loop: None,
+            }
+            .apply(world);
+            room_entities.push(room_query.iter(world).find(|room| !existing.contains(room)));
+        }
+
+        let portal = |world: &World, (room, index): (usize, usize)| {
+            let room = room_entities.get(room).copied().flatten()?;
+            Some((room, *world.get::<Room>(room)?.portals.get(index)?))
+        };
+
+        // Connections
+        for saved in self.connections.iter() {
+            let (Some((_, from_portal)), Some((to_room, to_portal))) =
+                (portal(world, saved.from), portal(world, saved.to))
+            else {
+                continue;
+            };
+            world.entity_mut(to_room).with_children(|parent| {
+                parent.spawn(PendingPortalConnection {
+                    sequence: saved.sequence,
+                    from_portal,
+                    to_portal,
+                    path: Some(saved.path.clone()),
+                    looped: saved.looped,
+                });
+            });
+        }
+
+        // Seals
+        for (room, saved) in self.rooms.iter().enumerate() {
+            for index in saved.sealed.iter() {
+                if let Some((_, portal)) = portal(world, (room, *index)) {
+                    world.entity_mut(portal).insert(PendingSeal);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::f32::consts::PI;
+
+use bevy::{ecs::system::SystemState, prelude::*};
+use rand::Rng;
+
+use crate::worldgen::{
+    brush::{BrushOperation, TerrainBrush},
+    voxel::VoxelMaterial,
+};
+
+use super::{
+    room::{Portal, Room},
+    LayoutState,
+};
+
+/// Material used to seal portals in rooms that don't have a valid material of their own.
+const FALLBACK_SEAL_MATERIAL: VoxelMaterial = VoxelMaterial::BrownRock;
+/// Size of the seal relative to the portal.
+const SEAL_RADIUS_SCALE: f32 = 1.25;
+const RUBBLE_COUNT: std::ops::RangeInclusive<usize> = 5..=9;
+
+#[derive(Resource)]
+pub struct PortalSealing {
+    /// Spawn a pile of rubble in front of sealed portals so they read as collapsed passages.
+    pub rubble: bool,
+}
+
+impl Default for PortalSealing {
+    fn default() -> Self {
+        Self { rubble: true }
+    }
+}
+
+#[derive(Resource)]
+struct RubbleAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+/// Marks a portal that was filled in because it was never connected.
+#[derive(Component)]
+pub struct Sealed;
+
+/// Seals a portal once its transform has propagated, for portals spawned in the same frame.
+#[derive(Component)]
+pub struct PendingSeal;
+
+pub struct SealPlugin;
+
+impl Plugin for SealPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PortalSealing>();
+        app.add_systems(Startup, setup_rubble_assets);
+        app.add_systems(
+            PostUpdate,
+            seal_pending_portals.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn setup_rubble_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(RubbleAssets {
+        mesh: meshes.add(Sphere::new(1.0).mesh().ico(1).unwrap()),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgb(0.32, 0.24, 0.18),
+            perceptual_roughness: 1.0,
+            ..default()
+        }),
+    });
+}
+
+fn seal_pending_portals(mut commands: Commands, pending: Query<Entity, With<PendingSeal>>) {
+    for portal in pending.iter() {
+        commands.entity(portal).remove::<PendingSeal>();
+        commands.queue(SealPortalCommand { portal });
+    }
+}
+
+pub struct SealPortalCommand {
+    pub portal: Entity,
+}
+
+impl Command for SealPortalCommand {
+    fn apply(self, world: &mut World) {
+        let mut system_state: SystemState<(
+            Commands,
+            Res<LayoutState>,
+            Res<PortalSealing>,
+            Option<Res<RubbleAssets>>,
+            Query<(&Portal, &GlobalTransform, &Parent), Without<Sealed>>,
+            Query<(&Room, &GlobalTransform, &Children)>,
+            Query<&TerrainBrush>,
+        )> = SystemState::new(world);
+        let (mut commands, state, sealing, rubble_assets, portals, rooms, brushes) =
+            system_state.get_mut(world);
+
+        let Ok((portal, portal_transform, portal_parent)) = portals.get(self.portal) else {
+            return;
+        };
+        if portal.connection.is_some() {
+            return;
+        }
+        let Ok((room, room_transform, room_children)) = rooms.get(portal_parent.get()) else {
+            return;
+        };
+
+        // Match the material of the room's cavities
+        let material = room_children
+            .iter()
+            .filter_map(|child| match brushes.get(*child) {
+                Ok(
+                    TerrainBrush::Collider {
+                        material,
+                        operation: BrushOperation::Carve,
+                        ..
+                    }
+                    | TerrainBrush::Primitive {
+                        material,
+                        operation: BrushOperation::Carve,
+                        ..
+                    },
+                ) => Some(*material),
+                _ => None,
+            })
+            .find(|material| *material != VoxelMaterial::Invalid)
+            .unwrap_or(FALLBACK_SEAL_MATERIAL);
+
+        let scale = portal_transform.scale();
+        let radius = scale.x.max(scale.y);
+        let inward = portal.inward(portal_transform);
+        let position = portal_transform.translation() - inward * radius * 0.5;
+
+        let seal_transform = GlobalTransform::from(
+            Transform::from_translation(portal_transform.translation()).looking_to(inward, Vec3::Y),
+        )
+        .reparented_to(room_transform);
+
+        let mut seal = commands.spawn(seal_transform);
+        seal.with_children(|parent| {
+            parent.spawn(TerrainBrush::fill(
+                "",
+                room.sequence,
+                material,
+                position,
+                radius * SEAL_RADIUS_SCALE,
+            ));
+        });
+
+        // Collapsed passage
+        if let (true, Some(rubble)) = (sealing.rubble, rubble_assets) {
+            // Seeded by the portal, so the rubble is the same when a save is restored
+            let key = (
+                room.sequence,
+                portal_transform.translation().to_array().map(f32::to_bits),
+            );
+            let mut rng = state.rng_for(key);
+            let count = rng.gen_range(RUBBLE_COUNT);
+            seal.with_children(|parent| {
+                for _ in 0..count {
+                    // Piled up against the bottom of the portal, on the room side
+                    let size = radius * rng.gen_range(0.2..0.45);
+                    let offset = Vec3::new(
+                        rng.gen_range(-radius..radius),
+                        -(radius - size).max(0.0) * rng.gen_range(0.5..1.0),
+                        -size * 0.5,
+                    );
+                    let rotation = Quat::from_euler(
+                        EulerRot::YXZ,
+                        rng.gen_range(0.0..(2.0 * PI)),
+                        rng.gen_range(0.0..PI),
+                        0.0,
+                    );
+
+                    parent.spawn((
+                        Mesh3d(rubble.mesh.clone()),
+                        MeshMaterial3d(rubble.material.clone()),
+                        Transform::from_translation(offset)
+                            .with_rotation(rotation)
+                            .with_scale(Vec3::new(size, size * 0.6, size * 0.8)),
+                    ));
+                }
+            });
+        }
+
+        let seal = seal.id();
+        commands.entity(portal_parent.get()).add_child(seal);
+        commands.entity(self.portal).insert(Sealed);
+
+        system_state.apply(world);
+    }
+}
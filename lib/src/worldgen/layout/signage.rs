@@ -0,0 +1,307 @@
+use avian3d::prelude::*;
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{physics::GameLayer, player::PlayerCamera, worldgen::terrain::TerrainPlacement};
+
+use super::{cleanup::BelongsToRoom, room::Portal, tunnel::PortalConnection};
+
+/// Distance into the room from the tunnel mouth where walls are searched for.
+const SIGN_INSET: f32 = 3.0;
+/// Furthest a wall can be from the tunnel mouth and still get a sign.
+const SIGN_MAX_WALL_DISTANCE: f32 = 12.0;
+/// Surfaces steeper than this, measured by the vertical part of their normal, aren't walls.
+const SIGN_MAX_WALL_SLOPE: f32 = 0.5;
+/// Distance between the sign and the wall, so the board doesn't clip into it.
+const SIGN_WALL_OFFSET: f32 = 0.1;
+const SIGN_SIZE: Vec2 = Vec2::new(1.2, 0.8);
+const SIGN_PLACEMENT_ATTEMPTS: usize = 8;
+/// Seconds between placement attempts. Terrain near the tunnel mouth may still be loading.
+const SIGN_PLACEMENT_INTERVAL: f32 = 0.5;
+
+/// Direction hints for players who get lost. Signs are always generated, so toggling them only
+/// changes whether they're shown.
+#[derive(Resource)]
+pub struct SignageSettings {
+    pub enabled: bool,
+    /// Labels are shown on signs within this distance of the camera.
+    pub label_distance: f32,
+}
+
+impl Default for SignageSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            label_distance: 12.0,
+        }
+    }
+}
+
+/// Placed on a wall near the mouth of a tunnel, with an arrow pointing toward the next sequence.
+#[derive(Component)]
+pub struct Sign {
+    /// Sequence the tunnel leads to.
+    pub depth: usize,
+    /// Direction of the tunnel from its mouth, in world space.
+    pub toward: Vec3,
+}
+
+/// Waiting for a wall to be found near the tunnel mouth.
+#[derive(Component)]
+struct PendingSign {
+    sign: Sign,
+    origin: Vec3,
+    attempts: usize,
+    next_attempt: f32,
+}
+
+#[derive(Resource)]
+struct SignAssets {
+    board_mesh: Handle<Mesh>,
+    board_material: Handle<StandardMaterial>,
+    arrow_shaft_mesh: Handle<Mesh>,
+    arrow_head_mesh: Handle<Mesh>,
+    arrow_material: Handle<StandardMaterial>,
+}
+
+pub struct SignagePlugin;
+
+impl Plugin for SignagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SignageSettings>();
+        app.add_systems(Startup, setup_sign_assets);
+        app.add_systems(
+            Update,
+            (
+                queue_signs,
+                place_signs,
+                toggle_signs.run_if(resource_changed::<SignageSettings>),
+                draw_labels.run_if(any_with_component::<PlayerCamera>),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn setup_sign_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(SignAssets {
+        board_mesh: meshes.add(Cuboid::new(SIGN_SIZE.x, SIGN_SIZE.y, 0.05)),
+        board_material: materials.add(StandardMaterial {
+            base_color: Color::srgb(0.35, 0.25, 0.15),
+            perceptual_roughness: 0.9,
+            ..default()
+        }),
+        arrow_shaft_mesh: meshes.add(Cuboid::new(0.1, 0.35, 0.02)),
+        arrow_head_mesh: meshes.add(Cone::new(0.18, 0.2).mesh().resolution(3)),
+        // Signs need to be readable in the dark
+        arrow_material: materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.8, 0.2),
+            emissive: LinearRgba::rgb(2.0, 1.4, 0.3),
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn queue_signs(
+    mut commands: Commands,
+    time: Res<Time>,
+    connections: Query<&PortalConnection, Added<PortalConnection>>,
+    portals: Query<(&Portal, &GlobalTransform, &Parent)>,
+) {
+    // Loops lead back to an earlier sequence, so their signs would point the wrong way
+    for connection in connections.iter().filter(|connection| !connection.looped) {
+        let Ok((portal, transform, room)) = portals.get(connection.from_portal) else {
+            continue;
+        };
+
+        let inward = portal.inward(transform);
+        let toward = match connection.path.as_slice() {
+            [start, next, ..] => (*next - *start).normalize_or(-inward),
+            _ => -inward,
+        };
+
+        commands.spawn((
+            PendingSign {
+                sign: Sign {
+                    depth: connection.sequence,
+                    toward,
+                },
+                origin: transform.translation() + inward * SIGN_INSET,
+                attempts: 0,
+                next_attempt: time.elapsed_secs(),
+            },
+            BelongsToRoom(room.get()),
+        ));
+    }
+}
+
+fn place_signs(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<SignageSettings>,
+    assets: Option<Res<SignAssets>>,
+    placement: TerrainPlacement,
+    mut pending: Query<(Entity, &mut PendingSign)>,
+) {
+    let Some(assets) = assets else {
+        return;
+    };
+    let now = time.elapsed_secs();
+
+    for (entity, mut pending) in pending.iter_mut() {
+        if now < pending.next_attempt {
+            continue;
+        }
+
+        // Search for the closest wall to either side of the tunnel mouth
+        let side = pending.sign.toward.cross(Vec3::Y);
+        let wall = [side, -side]
+            .into_iter()
+            .filter_map(|direction| Dir3::new(direction).ok())
+            .filter_map(|direction| {
+                placement.place_against_terrain(pending.origin, direction, SIGN_MAX_WALL_DISTANCE)
+            })
+            .filter(|surface| surface.normal.y.abs() < SIGN_MAX_WALL_SLOPE)
+            .min_by(|a, b| {
+                let a = a.position.distance_squared(pending.origin);
+                let b = b.position.distance_squared(pending.origin);
+                a.total_cmp(&b)
+            });
+
+        let Some(wall) = wall else {
+            pending.attempts += 1;
+            pending.next_attempt = now + SIGN_PLACEMENT_INTERVAL;
+            if pending.attempts >= SIGN_PLACEMENT_ATTEMPTS {
+                warn!("no wall found for sign near {}", pending.origin);
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+
+        // The board faces out of the wall, and stays upright even when the wall leans
+        let outward = wall.normal.with_y(0.0).normalize_or(wall.normal);
+        let transform = Transform::from_translation(wall.position + outward * SIGN_WALL_OFFSET)
+            .looking_to(-outward, Vec3::Y);
+
+        // Point the arrow along the tunnel, flattened onto the board
+        let local_toward = transform.rotation.inverse() * pending.sign.toward;
+        let arrow_angle = f32::atan2(-local_toward.x, local_toward.y);
+        let arrow_rotation = Quat::from_rotation_z(arrow_angle);
+
+        let sign = Sign {
+            depth: pending.sign.depth,
+            toward: pending.sign.toward,
+        };
+        let visibility = if settings.enabled {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+
+        let mut commands = commands.entity(entity);
+        commands.remove::<PendingSign>();
+        commands.insert((sign, transform, visibility));
+        commands.with_children(|parent| {
+            parent.spawn((
+                Mesh3d(assets.board_mesh.clone()),
+                MeshMaterial3d(assets.board_material.clone()),
+            ));
+            parent
+                .spawn((
+                    Transform::from_xyz(0.0, 0.0, 0.04).with_rotation(arrow_rotation),
+                    Visibility::default(),
+                ))
+                .with_children(|arrow| {
+                    arrow.spawn((
+                        Mesh3d(assets.arrow_shaft_mesh.clone()),
+                        MeshMaterial3d(assets.arrow_material.clone()),
+                        Transform::from_xyz(0.0, -0.1, 0.0),
+                        NotShadowCaster,
+                    ));
+                    arrow.spawn((
+                        Mesh3d(assets.arrow_head_mesh.clone()),
+                        MeshMaterial3d(assets.arrow_material.clone()),
+                        Transform::from_xyz(0.0, 0.17, 0.0),
+                        NotShadowCaster,
+                    ));
+                });
+        });
+    }
+}
+
+fn toggle_signs(settings: Res<SignageSettings>, mut signs: Query<&mut Visibility, With<Sign>>) {
+    for mut visibility in signs.iter_mut() {
+        *visibility = if settings.enabled {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Bevy can't render text in the world, so labels are drawn over signs that are in view.
+fn draw_labels(
+    mut contexts: EguiContexts,
+    settings: Res<SignageSettings>,
+    spatial_query: SpatialQuery,
+    camera: Single<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    signs: Query<(Entity, &Sign, &GlobalTransform)>,
+) {
+    let (camera, camera_transform) = *camera;
+    if !settings.enabled || !camera.is_active {
+        return;
+    }
+
+    let eye = camera_transform.translation();
+    let filter = SpatialQueryFilter::from_mask(GameLayer::World);
+    let ctx = contexts.ctx_mut();
+
+    for (entity, sign, transform) in signs.iter() {
+        let position = transform.translation();
+        let to_sign = position - eye;
+        let distance = to_sign.length();
+        if distance > settings.label_distance || to_sign.dot(*transform.back()) > 0.0 {
+            continue;
+        }
+
+        // Walls between the camera and the sign hide the label
+        let occluded = Dir3::new(to_sign).ok().is_some_and(|direction| {
+            spatial_query
+                .cast_ray(
+                    eye,
+                    direction,
+                    (distance - SIGN_WALL_OFFSET * 2.0).max(0.0),
+                    true,
+                    &filter,
+                )
+                .is_some()
+        });
+        if occluded {
+            continue;
+        }
+
+        let label_position = position + Vec3::Y * (SIGN_SIZE.y * 0.5 + 0.2);
+        let Ok(viewport_position) = camera.world_to_viewport(camera_transform, label_position)
+        else {
+            continue;
+        };
+
+        egui::Area::new(egui::Id::new("sign_label").with(entity))
+            .fixed_pos(egui::pos2(viewport_position.x, viewport_position.y))
+            .pivot(egui::Align2::CENTER_BOTTOM)
+            .order(egui::Order::Background)
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(format!("Depth {}", sign.depth))
+                        .strong()
+                        .color(egui::Color32::from_rgb(255, 204, 51)),
+                );
+            });
+    }
+}
@@ -0,0 +1,228 @@
+use std::f32::consts::TAU;
+
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use nalgebra::Point3;
+use rand::Rng;
+
+use crate::worldgen::{brush::TerrainBrush, voxel::VoxelMaterial};
+
+use super::{cleanup::BelongsToRoom, room::Room, LayoutState};
+
+const SHAFT_POINTS: usize = 5;
+/// Horizontal wander of the shaft, relative to its radius.
+const SHAFT_WANDER: f32 = 0.6;
+const DUST_COUNT: usize = 48;
+const DUST_SIZE: f32 = 0.04;
+/// Dust falls and sways this fast, in units per second.
+const DUST_FALL_SPEED: f32 = 0.15;
+const DUST_SWAY_SPEED: f32 = 0.3;
+
+#[derive(Resource)]
+pub struct SkylightSettings {
+    /// Chance for each eligible room to get a skylight.
+    pub chance: f32,
+    /// Only rooms in this sequence or earlier get skylights.
+    pub max_sequence: usize,
+    /// World height of the surface that shafts open up to.
+    pub surface_height: f32,
+    pub radius: (f32, f32),
+    pub light_color: Color,
+    pub light_intensity: f32,
+    pub dust: bool,
+}
+
+impl Default for SkylightSettings {
+    fn default() -> Self {
+        Self {
+            chance: 0.35,
+            max_sequence: 3,
+            surface_height: 160.0,
+            radius: (3.0, 6.0),
+            light_color: Color::srgb(1.0, 0.96, 0.85),
+            light_intensity: 40_000_000.0,
+            dust: true,
+        }
+    }
+}
+
+/// Vertical shaft from a room up to the surface. Positioned in world space, and despawned along
+/// with its room.
+#[derive(Component)]
+pub struct Skylight {
+    pub bottom: Vec3,
+    pub top: Vec3,
+    pub radius: f32,
+}
+
+#[derive(Component)]
+struct DustMote {
+    /// Height along the shaft, from 0 at the bottom to 1 at the top.
+    height: f32,
+    angle: f32,
+    distance: f32,
+}
+
+#[derive(Resource)]
+struct DustAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct SkylightPlugin;
+
+impl Plugin for SkylightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SkylightSettings>();
+        app.add_systems(Startup, setup_dust_assets);
+        app.add_systems(Update, (spawn_skylights, drift_dust));
+    }
+}
+
+fn setup_dust_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(DustAssets {
+        mesh: meshes.add(Sphere::new(DUST_SIZE).mesh().ico(0).unwrap()),
+        material: materials.add(StandardMaterial {
+            base_color: Color::srgba(1.0, 0.95, 0.85, 0.6),
+            emissive: LinearRgba::rgb(0.6, 0.55, 0.45),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn spawn_skylights(
+    mut commands: Commands,
+    state: Res<LayoutState>,
+    settings: Res<SkylightSettings>,
+    dust: Option<Res<DustAssets>>,
+    rooms: Query<(Entity, &Room, &Transform), Added<Room>>,
+) {
+    for (entity, room, transform) in rooms.iter() {
+        // Seeded by the room, so the shaft is the same when a save is restored
+        let key = (
+            &room.source,
+            room.sequence,
+            transform.translation.to_array().map(f32::to_bits),
+        );
+        let mut rng = state.rng_for(key);
+        if room.sequence > settings.max_sequence
+            || !rng.gen_bool(settings.chance.clamp(0.0, 1.0) as f64)
+        {
+            continue;
+        }
+
+        // Start from the top of the room
+        let center = transform.transform_point(room.center);
+        let bottom = center + Vec3::Y * room.radius * 0.5;
+        if bottom.y >= settings.surface_height {
+            continue;
+        }
+        let top = bottom.with_y(settings.surface_height);
+
+        let (min_radius, max_radius) = settings.radius;
+        let radius = rng.gen_range(min_radius..=max_radius.max(min_radius));
+        let points = (0..SHAFT_POINTS)
+            .map(|i| {
+                let t = i as f32 / (SHAFT_POINTS - 1) as f32;
+                // The ends stay put so the shaft meets the room and the surface head on
+                let wander = if i == 0 || i == SHAFT_POINTS - 1 {
+                    Vec3::ZERO
+                } else {
+                    let angle = rng.gen_range(0.0..TAU);
+                    Vec3::new(angle.cos(), 0.0, angle.sin()) * radius * SHAFT_WANDER
+                };
+                let point = bottom.lerp(top, t) + wander;
+                Point3::new(point.x, point.y, point.z)
+            })
+            .collect::<Vec<_>>();
+
+        let shaft_length = top.y - bottom.y;
+        let light_transform = Transform::from_translation(top).looking_to(Vec3::NEG_Y, Vec3::Z);
+
+        let mut skylight = commands.spawn((
+            Skylight {
+                bottom,
+                top,
+                radius,
+            },
+            BelongsToRoom(entity),
+            Transform::default(),
+            Visibility::default(),
+        ));
+        skylight.with_children(|parent| {
+            parent.spawn(TerrainBrush::curve(
+                "",
+                room.sequence,
+                VoxelMaterial::BrownRock,
+                &points,
+                radius,
+            ));
+
+            // Bevy doesn't have light cookies, so a narrow shadowed spot light shining down the
+            // shaft stands in for one
+            parent.spawn((
+                SpotLight {
+                    color: settings.light_color,
+                    intensity: settings.light_intensity,
+                    range: shaft_length + room.radius * 2.0,
+                    radius: radius * 0.5,
+                    outer_angle: (radius * 1.5 / shaft_length).atan(),
+                    inner_angle: (radius * 0.75 / shaft_length).atan(),
+                    shadows_enabled: true,
+                    ..default()
+                },
+                light_transform,
+            ));
+
+            // Dust drifting through the light
+            let Some(dust) = dust.as_ref().filter(|_| settings.dust) else {
+                return;
+            };
+            for _ in 0..DUST_COUNT {
+                let mote = DustMote {
+                    height: rng.gen_range(0.0..1.0),
+                    angle: rng.gen_range(0.0..TAU),
+                    distance: radius * rng.gen_range(0.0f32..1.0).sqrt() * 0.8,
+                };
+                parent.spawn((
+                    mote,
+                    Mesh3d(dust.mesh.clone()),
+                    MeshMaterial3d(dust.material.clone()),
+                    Transform::from_translation(bottom),
+                    NotShadowCaster,
+                ));
+            }
+        });
+    }
+}
+
+fn drift_dust(
+    time: Res<Time>,
+    skylights: Query<(&Skylight, &Children)>,
+    mut motes: Query<(&mut DustMote, &mut Transform)>,
+) {
+    let delta = time.delta_secs();
+
+    for (skylight, children) in skylights.iter() {
+        // Dust only needs to fill the lower part of the shaft, where it can be seen
+        let visible_height = (skylight.top.y - skylight.bottom.y).min(skylight.radius * 8.0);
+
+        for child in children.iter() {
+            let Ok((mut mote, mut transform)) = motes.get_mut(*child) else {
+                continue;
+            };
+
+            mote.height = (mote.height - DUST_FALL_SPEED * delta / visible_height).rem_euclid(1.0);
+            mote.angle = (mote.angle + DUST_SWAY_SPEED * delta / mote.distance.max(1.0)) % TAU;
+
+            let offset = Vec3::new(mote.angle.cos(), 0.0, mote.angle.sin()) * mote.distance;
+            transform.translation =
+                skylight.bottom + offset + Vec3::Y * mote.height * visible_height;
+        }
+    }
+}
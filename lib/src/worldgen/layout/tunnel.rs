@@ -7,14 +7,24 @@ use rand::Rng;
 use crate::{
     materials::LineMaterial,
     worldgen::{
-        brush::{curve::mesh_curve, TerrainBrush},
+        asset::AssetCollection,
+        brush::{
+            curve::mesh_curve, sweep::ProfileRamp, BrushOperation, TerrainBrush,
+            TerrainBrushRequest,
+        },
+        consts::TUNNEL_BLEND_RADIUS,
+        fluid::FluidVolume,
         voxel::VoxelMaterial,
     },
 };
 
 use super::{
-    consts::{ROOM_SHYNESS, TRIGGER_OFFSET, TUNNEL_SHYNESS},
+    consts::{
+        ROOM_SHYNESS, TRIGGER_OFFSET, TUNNEL_FLOOD_MARGIN, TUNNEL_FLOOD_MIN_DEPTH,
+        TUNNEL_FLOOD_PADDING, TUNNEL_KEYFRAME_SCALE, TUNNEL_KEYFRAME_SPACING, TUNNEL_SHYNESS,
+    },
     room::{Portal, Room},
+    seal::SealPortalCommand,
     utility::{find_path_between_portals, navigable_pointcloud, Arrangement},
     LayoutState,
 };
@@ -24,6 +34,11 @@ pub struct PendingPortalConnection {
     pub sequence: usize,
     pub from_portal: Entity,
     pub to_portal: Entity,
+    /// Reuse a path instead of pathfinding a new one, e.g. when loading a save.
+    pub path: Option<Vec<Vec3>>,
+    /// Connects back to an earlier sequence. Loops are optional, so both portals are sealed if
+    /// no path is found.
+    pub looped: bool,
 }
 
 #[derive(Component, Debug)]
@@ -31,6 +46,10 @@ pub struct PortalConnection {
     pub sequence: usize,
     pub from_portal: Entity,
     pub to_portal: Entity,
+    pub path: Vec<Vec3>,
+    /// Loops don't generate or unload sequences, since they lead back to rooms that are already
+    /// loaded.
+    pub looped: bool,
 }
 
 #[derive(Component)]
@@ -42,6 +61,7 @@ pub enum LayoutTrigger {
 pub fn connect_portals(
     mut commands: Commands,
     mut state: ResMut<LayoutState>,
+    assets: Option<Res<AssetCollection>>,
     mut portals: Query<(&mut Portal, &GlobalTransform, &Parent)>,
     rooms: Query<(&Room, &GlobalTransform)>,
     arrangements: Query<&Arrangement>,
@@ -68,40 +88,57 @@ pub fn connect_portals(
         let (from_room, from_room_transform) = from_room;
         let (to_room, to_room_transform) = to_room;
 
-        let path = 'pathfinding: {
-            let max_attempts = 3;
-            for attempt in 1..=max_attempts {
-                let navigation_cloud = navigable_pointcloud(
-                    (from_room_transform.translation(), from_room.radius),
-                    (to_room_transform.translation(), to_room.radius),
-                    attempt,
-                    &mut state.rng,
-                );
-                let real_start = from_portal_transform.translation();
-                let real_end = to_portal_transform.translation();
-                let start_offset = from_room.radius + ROOM_SHYNESS;
-                let end_offset = to_room.radius + ROOM_SHYNESS;
-                let pathfinding_start = (real_start
-                    - from_portal.inward(from_portal_transform) * start_offset)
-                    .as_ivec3();
-                let pathfinding_end =
-                    (real_end - to_portal.inward(to_portal_transform) * end_offset).as_ivec3();
-
-                let path = find_path_between_portals(
-                    attempt != max_attempts,
-                    real_start,
-                    real_end,
-                    pathfinding_start,
-                    pathfinding_end,
-                    navigation_cloud,
-                    &arrangements,
-                );
-
-                if let Some(path) = path {
-                    break 'pathfinding path;
+        let path = match pending.path.clone() {
+            Some(path) => path,
+            None => 'pathfinding: {
+                let max_attempts = 3;
+                for attempt in 1..=max_attempts {
+                    let navigation_cloud = navigable_pointcloud(
+                        (from_room_transform.translation(), from_room.radius),
+                        (to_room_transform.translation(), to_room.radius),
+                        attempt,
+                        &mut state.rng,
+                    );
+                    let real_start = from_portal_transform.translation();
+                    let real_end = to_portal_transform.translation();
+                    let start_offset = from_room.radius + ROOM_SHYNESS;
+                    let end_offset = to_room.radius + ROOM_SHYNESS;
+                    let pathfinding_start = (real_start
+                        - from_portal.inward(from_portal_transform) * start_offset)
+                        .as_ivec3();
+                    let pathfinding_end =
+                        (real_end - to_portal.inward(to_portal_transform) * end_offset).as_ivec3();
+
+                    let path = find_path_between_portals(
+                        attempt != max_attempts,
+                        real_start,
+                        real_end,
+                        pathfinding_start,
+                        pathfinding_end,
+                        navigation_cloud,
+                        &arrangements,
+                    );
+
+                    if let Some(path) = path {
+                        break 'pathfinding path;
+                    }
+                }
+                if !pending.looped {
+                    panic!("no viable path found after {max_attempts} attempts");
                 }
+
+                info!("no viable path found for loop, sealing its portals");
+                commands.queue(SealPortalCommand {
+                    portal: pending.from_portal,
+                });
+                commands.queue(SealPortalCommand {
+                    portal: pending.to_portal,
+                });
+                let mut commands = commands.entity(pending_entity);
+                commands.remove_parent();
+                commands.despawn();
+                return;
             }
-            panic!("no viable path found after {max_attempts} attempts");
         };
 
         let arrangement_colliders = path
@@ -115,6 +152,15 @@ pub fn connect_portals(
             })
             .collect();
 
+        let profile = tunnel_profile(
+            assets.as_deref(),
+            &mut state.rng,
+            &path,
+            from_portal_transform.scale().xz(),
+            to_portal_transform.scale().xz(),
+        );
+        let flood = flood_volume(&path);
+
         let color = Color::hsl(state.rng.gen_range(0.0..360.0), 1.0, 0.5);
         let connection = commands
             .spawn((
@@ -123,6 +169,8 @@ pub fn connect_portals(
                     sequence: pending.sequence,
                     from_portal: pending.from_portal,
                     to_portal: pending.to_portal,
+                    path: path.clone(),
+                    looped: pending.looped,
                 },
             ))
             .with_children(|parent| {
@@ -144,13 +192,31 @@ pub fn connect_portals(
                         alpha_mode: AlphaMode::Blend,
                     })),
                 ));
-                parent.spawn(TerrainBrush::curve(
-                    "",
-                    state.sequence,
-                    VoxelMaterial::BrownRock,
-                    &points,
-                    6.0,
-                ));
+                match profile {
+                    Some(profile) => parent.spawn(TerrainBrushRequest::Sweep {
+                        uuid: String::new(),
+                        sequence: state.sequence,
+                        material: VoxelMaterial::BrownRock,
+                        operation: BrushOperation::Carve,
+                        rail: points.clone(),
+                        profile,
+                        smoothness: TUNNEL_BLEND_RADIUS,
+                    }),
+                    None => parent.spawn(
+                        TerrainBrush::curve(
+                            "",
+                            state.sequence,
+                            VoxelMaterial::BrownRock,
+                            &points,
+                            6.0,
+                        )
+                        .with_smoothness(TUNNEL_BLEND_RADIUS),
+                    ),
+                };
+
+                if let Some(flood) = flood {
+                    parent.spawn(flood);
+                }
 
                 let arrangement = Arrangement {
                     spherical: false,
@@ -161,6 +227,10 @@ pub fn connect_portals(
                 arrangements.push(arrangement.clone());
                 parent.spawn(arrangement);
 
+                if pending.looped {
+                    return;
+                }
+
                 // Triggers
                 // TODO these need some work to make sure the player can't sneak past them
                 let scale = from_portal_transform.scale();
@@ -200,3 +270,58 @@ pub fn connect_portals(
         commands.despawn();
     });
 }
+
+/// Picks the profiles a tunnel is swept through. Long tunnels get intermediate profiles from
+/// other tunnel assets, so they change shape along the way and sometimes squeeze through narrow
+/// sections. Returns `None` without tunnel assets.
+fn tunnel_profile<R>(
+    assets: Option<&AssetCollection>,
+    rng: &mut R,
+    path: &[Vec3],
+    start_size: Vec2,
+    end_size: Vec2,
+) -> Option<ProfileRamp>
+where
+    R: Rng + ?Sized,
+{
+    let assets = assets.filter(|assets| !assets.tunnels.is_empty())?;
+    let length = path.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>();
+    let sections = (length / TUNNEL_KEYFRAME_SPACING).floor().max(1.0) as usize;
+
+    // Both mouths share a profile so they match the portals on either end
+    let mouth = assets.random_tunnel(rng);
+    let mut profile = ProfileRamp::start(mouth.profile(start_size)).end(mouth.profile(end_size));
+
+    let (min_scale, max_scale) = TUNNEL_KEYFRAME_SCALE;
+    for i in 1..sections {
+        let parameter = i as f32 / sections as f32;
+        let size = start_size.lerp(end_size, parameter) * rng.gen_range(min_scale..=max_scale);
+        let tunnel = assets.random_tunnel(rng);
+        profile = profile.keyframe(parameter, tunnel.profile(size));
+    }
+
+    Some(profile)
+}
+
+/// Floods the low part of a tunnel that dips well below both of its mouths, up to just under the
+/// lower mouth.
+fn flood_volume(path: &[Vec3]) -> Option<(Transform, FluidVolume)> {
+    let (first, last) = (path.first()?, path.last()?);
+    let surface = first.y.min(last.y) - TUNNEL_FLOOD_MARGIN;
+    let lowest = path.iter().map(|point| point.y).reduce(f32::min)?;
+    if surface - lowest < TUNNEL_FLOOD_MIN_DEPTH {
+        return None;
+    }
+
+    let (min, max) = path
+        .iter()
+        .filter(|point| point.y < surface)
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), point| {
+            (min.min(*point), max.max(*point))
+        });
+    let min = (min - TUNNEL_FLOOD_PADDING).with_y(lowest - TUNNEL_FLOOD_PADDING);
+    let max = (max + TUNNEL_FLOOD_PADDING).with_y(surface);
+    let transform = Transform::from_translation((min + max) / 2.0).with_scale(max - min);
+
+    Some((transform, FluidVolume { level: 1.0 }))
+}
@@ -8,7 +8,7 @@ use rand::Rng;
 
 use crate::worldgen::layout::consts::{DEPENETRATION_DISTANCE, SHORT_HOP};
 
-use super::consts::{HULL_DENSITY, ROOM_SHYNESS, TUNNEL_SHYNESS};
+use super::consts::{HULL_DENSITY, MAX_TUNNEL_PITCH, ROOM_SHYNESS, TUNNEL_SHYNESS};
 
 #[derive(Component, Clone)]
 pub struct Arrangement {
@@ -23,15 +23,24 @@ impl Arrangement {
     }
 }
 
-pub fn arrange_by_depenetration(
+/// The rng only breaks ties between colliders at the same position, so pass one derived from the
+/// layout rng to keep arrangements reproducible.
+pub fn arrange_by_depenetration<R>(
     dynamic_colliders: &mut [Arrangement],
     static_colliders: Vec<Arrangement>,
-) {
-    fn depenetrate(
+    rng: &mut R,
+) where
+    R: Rng + ?Sized,
+{
+    fn depenetrate<R>(
         static_collider: &Arrangement,
         dynamic_collider: &mut Arrangement,
         desperation: f32,
-    ) -> bool {
+        rng: &mut R,
+    ) -> bool
+    where
+        R: Rng + ?Sized,
+    {
         let Some(contact) = contact(
             &dynamic_collider.collider,
             dynamic_collider.position,
@@ -50,6 +59,12 @@ pub fn arrange_by_depenetration(
         } else {
             contact.normal1
         };
+        // Coincident centers have no direction, so jitter horizontally
+        let direction = if direction.is_finite() {
+            direction
+        } else {
+            Quat::from_rotation_y(rng.gen_range(0.0..(2.0 * PI))) * Vec3::Z
+        };
 
         // Prefer horizontal depenetration to minimize steep paths
         let y_scale = 0.01;
@@ -78,12 +93,13 @@ pub fn arrange_by_depenetration(
                     &dynamic_colliders[j].clone(),
                     &mut dynamic_colliders[i],
                     desperation,
+                    rng,
                 );
                 desperation *= acceleration;
             }
 
             for static_collider in static_colliders.iter() {
-                done &= depenetrate(static_collider, &mut dynamic_colliders[i], desperation);
+                done &= depenetrate(static_collider, &mut dynamic_colliders[i], desperation, rng);
                 desperation *= acceleration;
             }
         }
@@ -114,12 +130,21 @@ pub fn navigable_pointcloud<R>(
 where
     R: Rng + ?Sized,
 {
-    let navigable_shell_thickness = TUNNEL_SHYNESS * shells.max(1) as f32;
+    let distance = from_sphere.0.distance(to_sphere.0);
+
+    // A path that's too steep to go straight needs room to the side, enough for a detour as
+    // long as the shortest path at the steepest allowed pitch
+    let rise = (to_sphere.0.y - from_sphere.0.y).abs();
+    let min_length = rise / MAX_TUNNEL_PITCH.sin();
+    let detour = ((min_length / 2.0).powi(2) - (distance / 2.0).powi(2))
+        .max(0.0)
+        .sqrt();
+
+    let navigable_shell_thickness = TUNNEL_SHYNESS * shells.max(1) as f32 + detour;
     let exclusion_radius_0 = from_sphere.1 + ROOM_SHYNESS;
     let exclusion_radius_1 = from_sphere.1 + ROOM_SHYNESS;
     let navigable_radius_0 = exclusion_radius_0 + navigable_shell_thickness;
     let navigable_radius_1 = exclusion_radius_1 + navigable_shell_thickness;
-    let distance = from_sphere.0.distance(to_sphere.0);
 
     let volume = navigable_hull_volume(distance, navigable_radius_0, navigable_radius_1);
     let points = (volume * HULL_DENSITY) as usize;
@@ -257,11 +282,15 @@ pub fn penalize_sharp_angles(a: &Vec3, center: &Vec3, b: &Vec3) -> u32 {
 
 pub fn penalize_steep_angles(a: &Vec3, center: &Vec3) -> u32 {
     const STEEP_ANGLE_PENALTY: f32 = 4096.0;
+    const TOO_STEEP_PENALTY: u32 = 16384;
 
     let b = Vec3::new(a.x, center.y, a.z);
     let dir_a = (a - center).normalize();
     let dir_b = (b - center).normalize();
     let fault = 1.0 - ((dir_a.dot(dir_b) + 1.0) / 2.0);
 
-    penalize(fault, STEEP_ANGLE_PENALTY)
+    // Winding around is better than a hop that's too steep to walk
+    let too_steep = dir_a.y.abs() > MAX_TUNNEL_PITCH.sin();
+
+    penalize(fault, STEEP_ANGLE_PENALTY) + if too_steep { TOO_STEEP_PENALTY } else { 0 }
 }
@@ -1,7 +1,9 @@
 pub mod asset;
 pub mod brush;
 pub mod chunk;
+pub mod fluid;
 pub mod layout;
+pub mod sdf;
 pub mod terrain;
 pub mod voxel;
 
@@ -12,6 +14,9 @@ pub mod consts {
 
     pub const CHUNK_SAMPLE_RESOLUTION: f32 = 1.0 / 4.0; // RHS must be a power of 2
 
+    /// Resolution of chunks overlapping high detail rooms.
+    pub const CHUNK_HIGH_DETAIL_SAMPLE_RESOLUTION: f32 = 1.0 / 2.0; // RHS must be a power of 2
+
     pub const CHUNK_SAMPLE_SIZE: u32 = (CHUNK_SIZE_F * CHUNK_SAMPLE_RESOLUTION) as u32;
     pub const VOXEL_REAL_SIZE: f32 = (CHUNK_SIZE / CHUNK_SAMPLE_SIZE) as f32;
 
@@ -23,6 +28,9 @@ pub mod consts {
     pub const CHUNK_INTERNAL_GEOMETRY: bool = true;
     pub const WORLD_RENDER_ORIGIN: bool = false;
 
+    /// Blend radius where tunnels meet rooms and other tunnels.
+    pub const TUNNEL_BLEND_RADIUS: f32 = 3.0;
+
     pub const TUNNEL_VHACD_PARAMETERS: VhacdParameters = VhacdParameters {
         // Changed
         alpha: 0.025,
@@ -0,0 +1,133 @@
+//! Signed distance field math shared by brushes, destruction, and chunk generation.
+//!
+//! Distances are negative in open space and positive in solid terrain, so carving takes the
+//! minimum of two fields and filling takes the maximum. Everything here is a pure function of its
+//! arguments so it can be tested, and ported to the GPU, without a world.
+
+use bevy::prelude::*;
+
+use super::consts::VOXEL_REAL_SIZE;
+
+/// Shapes change samples up to one voxel outside their surface, since meshing interpolates
+/// between neighboring samples. Bounds of anything written into the SDF are grown by this much.
+pub const INFLATION: f32 = VOXEL_REAL_SIZE;
+
+/// Distance used for points inside a shape when the exact distance isn't known, or is too close
+/// to zero to have a reliable sign.
+pub const MIN_INSIDE_DISTANCE: f32 = 0.001;
+
+//
+// Shapes
+//
+
+pub fn sphere(point: Vec3, center: Vec3, radius: f32) -> f32 {
+    point.distance(center) - radius
+}
+
+/// Box centered on the origin.
+pub fn cuboid(point: Vec3, half_extents: Vec3) -> f32 {
+    let q = point.abs() - half_extents;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+}
+
+/// Capsule centered on the origin and aligned with the Y axis.
+pub fn capsule(point: Vec3, half_height: f32, radius: f32) -> f32 {
+    let segment = Vec3::Y * point.y.clamp(-half_height, half_height);
+    point.distance(segment) - radius
+}
+
+/// Signs an unsigned distance. Points inside are never closer than [`MIN_INSIDE_DISTANCE`] to the
+/// surface, so they can't be mistaken for points outside.
+pub fn signed(distance: f32, inside: bool) -> f32 {
+    if inside {
+        -distance.abs().max(MIN_INSIDE_DISTANCE)
+    } else {
+        distance
+    }
+}
+
+/// Radius of the region a shape with this radius can change.
+pub fn inflate(radius: f32) -> f32 {
+    radius + INFLATION
+}
+
+//
+// Merging
+//
+
+/// Polynomial smooth minimum. Identical to `min` when `k` is zero or the values are at least `k`
+/// apart.
+pub fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+/// Hollows out the inside of a shape, rounding off the edges within `smoothness`.
+pub fn carve(terrain: f32, shape: f32, smoothness: f32) -> f32 {
+    smooth_min(terrain, shape, smoothness)
+}
+
+/// Makes the inside of a shape solid, rounding off the edges within `smoothness`.
+pub fn fill(terrain: f32, shape: f32, smoothness: f32) -> f32 {
+    -smooth_min(-terrain, shape, smoothness)
+}
+
+//
+// Hardness
+//
+
+/// Fraction of a destruction's radius that reaches a material, from 0 to 1.
+pub fn strength(force: f32, resistance: f32) -> f32 {
+    (force / resistance).min(1.0)
+}
+
+/// Carves a sphere into a sample, shrinking the sphere's radius by `strength`. Returns the new
+/// distance, or `None` if the sample is already at least as open, so applying the same sphere
+/// twice changes nothing.
+pub fn carve_with_strength(terrain: f32, sphere: f32, radius: f32, strength: f32) -> Option<f32> {
+    let distance = sphere + radius * (1.0 - strength);
+    (distance < terrain).then_some(distance)
+}
+
+//
+// Painting
+//
+
+/// Pseudorandom value from 0 to 1 for the voxel containing a point. It only depends on the
+/// position, so dithered paint looks the same in every chunk and session.
+pub fn dither(point: Vec3) -> f32 {
+    let cell = (point / VOXEL_REAL_SIZE).round().as_ivec3();
+    let mut hash = (cell.x as u32).wrapping_mul(0x8da6_b343)
+        ^ (cell.y as u32).wrapping_mul(0xd816_3841)
+        ^ (cell.z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7feb_352d);
+    hash ^= hash >> 15;
+
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Paints the inside of a shape where `dither` is below the coverage, which is `strength` at the
+/// surface and doubles toward the center of a shape with this radius. Returns a negative distance
+/// where the point is painted.
+pub fn paint(shape: f32, radius: f32, strength: f32, dither: f32) -> f32 {
+    let depth = (-shape / radius.max(MIN_INSIDE_DISTANCE)).clamp(0.0, 1.0);
+    let coverage = (strength * (1.0 + depth)).min(1.0);
+    let painted = shape <= 0.0 && dither < coverage;
+
+    signed(shape.abs().max(MIN_INSIDE_DISTANCE), painted)
+}
+
+//
+// Postprocessing
+//
+
+/// Whether a sample is solid. Surface noise is only applied to solid samples, so open space
+/// stays as carved.
+pub fn is_solid(distance: f32) -> bool {
+    distance >= 0.0
+}
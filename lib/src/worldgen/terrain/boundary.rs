@@ -2,14 +2,22 @@ use avian3d::prelude::*;
 use bevy::{prelude::*, utils::hashbrown::HashSet};
 use bevy_tnua::TnuaToggle;
 
+use crate::player::IsPlayer;
+
 use super::CHUNK_SIZE_F;
 
+/// Distance from a loading chunk at which the player starts being pushed back.
+const SOFT_WALL_MARGIN: f32 = 8.0;
+/// Speed the player is steered away at, per unit of penetration into the soft wall.
+const SOFT_WALL_PUSH_SPEED: f32 = 1.5;
+
 #[allow(unused)]
 #[derive(Component)]
 pub struct IntersectsBoundary;
 
 #[derive(Component)]
 pub struct LoadingBoundary {
+    chunk_pos: IVec3,
     aabb: ColliderAabb,
 }
 
@@ -17,6 +25,7 @@ impl LoadingBoundary {
     pub fn new(chunk_pos: IVec3) -> Self {
         let world_pos = chunk_pos.as_vec3() * CHUNK_SIZE_F;
         Self {
+            chunk_pos,
             aabb: ColliderAabb {
                 min: world_pos,
                 max: world_pos + CHUNK_SIZE_F,
@@ -25,12 +34,33 @@ impl LoadingBoundary {
     }
 }
 
-#[allow(unused)]
+/// How close the player is to the loading frontier, from 0.0 (outside the soft wall) to 1.0
+/// (touching a loading chunk).
+#[derive(Resource, Default)]
+pub struct FrontierProximity {
+    pub intensity: f32,
+    /// Velocity the player's controls steer them at to leave the soft wall.
+    pub push: Vec3,
+    pub encounters: usize,
+    pub time_near: f32,
+}
+
+/// Sent when the player enters the soft wall around a loading chunk. Useful for tuning how far
+/// ahead of the player generation needs to run.
+#[derive(Event, Debug)]
+pub struct FrontierTelemetryEvent {
+    pub position: Vec3,
+    pub chunk_pos: IVec3,
+    pub penetration: f32,
+    pub pending_chunks: usize,
+}
+
 pub fn enforce_loading_chunk_boundaries(
     mut commands: Commands,
     spatial_query: SpatialQuery,
     boundaries: Query<&LoadingBoundary>,
     intersecting_prev: Query<Entity, With<IntersectsBoundary>>,
+    players: Query<(), With<IsPlayer>>,
 ) {
     let mut intersecting_curr = HashSet::<Entity>::new();
 
@@ -39,6 +69,11 @@ pub fn enforce_loading_chunk_boundaries(
             .aabb_intersections_with_aabb(boundary.aabb)
             .iter()
             .for_each(|entity| {
+                // The player is pushed back by the soft wall instead
+                if players.contains(*entity) {
+                    return;
+                }
+
                 intersecting_curr.insert(*entity);
 
                 if intersecting_prev.contains(*entity) {
@@ -65,3 +100,67 @@ pub fn enforce_loading_chunk_boundaries(
         commands.remove::<IntersectsBoundary>();
     });
 }
+
+pub fn push_player_from_frontier(
+    time: Res<Time>,
+    boundaries: Query<&LoadingBoundary>,
+    player: Option<Single<&Transform, With<IsPlayer>>>,
+    mut proximity: ResMut<FrontierProximity>,
+    mut telemetry: EventWriter<FrontierTelemetryEvent>,
+) {
+    let Some(transform) = player else {
+        proximity.intensity = 0.0;
+        proximity.push = Vec3::ZERO;
+        return;
+    };
+    let position = transform.translation;
+
+    let mut push = Vec3::ZERO;
+    let mut deepest: Option<(&LoadingBoundary, f32)> = None;
+
+    for boundary in boundaries.iter() {
+        let closest = position.clamp(boundary.aabb.min, boundary.aabb.max);
+        let offset = position - closest;
+        let distance = offset.length();
+        if distance >= SOFT_WALL_MARGIN {
+            continue;
+        }
+
+        let penetration = SOFT_WALL_MARGIN - distance;
+        let direction = if distance > f32::EPSILON {
+            offset / distance
+        } else {
+            (position - boundary.aabb.center()).normalize_or(Vec3::Y)
+        };
+        push += direction * penetration;
+
+        if deepest.is_none_or(|(_, deepest)| penetration > deepest) {
+            deepest = Some((boundary, penetration));
+        }
+    }
+
+    let was_near = proximity.intensity > 0.0;
+
+    let Some((boundary, penetration)) = deepest else {
+        proximity.intensity = 0.0;
+        proximity.push = Vec3::ZERO;
+        return;
+    };
+
+    proximity.push = push * SOFT_WALL_PUSH_SPEED;
+    proximity.intensity = (penetration / SOFT_WALL_MARGIN).clamp(0.0, 1.0);
+    proximity.time_near += time.delta_secs();
+
+    if !was_near {
+        proximity.encounters += 1;
+
+        let event = FrontierTelemetryEvent {
+            position,
+            chunk_pos: boundary.chunk_pos,
+            penetration,
+            pending_chunks: boundaries.iter().count(),
+        };
+        info!("player reached the loading frontier: {event:?}");
+        telemetry.send(event);
+    }
+}
@@ -4,11 +4,29 @@ use bevy::{prelude::*, utils::HashMap};
 
 use crate::worldgen::{brush::TerrainBrush, chunk::ChunksAABB};
 
-use super::{ChunkSpawnRequest, TerrainStateMutex};
+use super::{ChunkSpawnRequest, TerrainDetail, TerrainStateMutex};
 
 #[derive(Default, Clone)]
 pub struct TerrainSource {
     pub brushes: HashMap<Entity, TerrainBrush>,
+    /// Brushes with a detail other than [`TerrainDetail::Standard`].
+    pub details: HashMap<Entity, TerrainDetail>,
+}
+
+impl TerrainSource {
+    /// Highest detail of the brushes overlapping the chunk.
+    pub fn chunk_detail(&self, chunk_pos: &IVec3) -> TerrainDetail {
+        self.details
+            .iter()
+            .filter(|(entity, _)| {
+                self.brushes
+                    .get(*entity)
+                    .is_some_and(|brush| brush.chunks().chunks.contains(chunk_pos))
+            })
+            .map(|(_, detail)| *detail)
+            .max()
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Resource, Default)]
@@ -39,13 +57,13 @@ fn detect_brush_additions(
     mut commands: Commands,
     sources: Res<TerrainSourceArc>,
     mut changed_aabbs: ResMut<TerrainSourceChanges>,
-    changed_brushes: Query<(Entity, Ref<TerrainBrush>)>,
+    changed_brushes: Query<(Entity, Ref<TerrainBrush>, Option<&TerrainDetail>)>,
 ) {
-    let mut additions: Vec<(Entity, TerrainBrush)> = Vec::new();
+    let mut additions: Vec<(Entity, TerrainBrush, TerrainDetail)> = Vec::new();
 
-    changed_brushes.iter().for_each(|(entity, brush)| {
+    changed_brushes.iter().for_each(|(entity, brush, detail)| {
         if brush.is_added() {
-            additions.push((entity, brush.clone()));
+            additions.push((entity, brush.clone(), detail.copied().unwrap_or_default()));
         }
     });
 
@@ -55,9 +73,12 @@ fn detect_brush_additions(
 
     let mut sources = Arc::unwrap_or_clone(sources.0.clone());
 
-    additions.into_iter().for_each(|(entity, brush)| {
+    additions.into_iter().for_each(|(entity, brush, detail)| {
         changed_aabbs.0.push(brush.chunks().clone());
         sources.brushes.insert(entity, brush);
+        if detail != TerrainDetail::Standard {
+            sources.details.insert(entity, detail);
+        }
     });
 
     commands.insert_resource(TerrainSourceArc(Arc::new(sources)));
@@ -65,6 +86,7 @@ fn detect_brush_additions(
 
 fn detect_brush_removals(
     mut commands: Commands,
+    terrain_state: Res<TerrainStateMutex>,
     sources: Res<TerrainSourceArc>,
     mut changed_aabbs: ResMut<TerrainSourceChanges>,
     mut removed_brushes: RemovedComponents<TerrainBrush>,
@@ -80,11 +102,21 @@ fn detect_brush_removals(
     }
 
     let mut sources = Arc::unwrap_or_clone(sources.0.clone());
+    let store = terrain_state.lock().unwrap().store.clone();
 
     removals.into_iter().for_each(|entity| {
         if let Some(brush) = sources.brushes.remove(&entity) {
+            // Stored chunks were generated with the brush
+            if let Some(store) = &store {
+                for chunk_pos in brush.chunks().chunks.iter() {
+                    if let Err(err) = store.remove(*chunk_pos) {
+                        warn!("failed to remove stored chunk {chunk_pos}: {err:?}");
+                    }
+                }
+            }
             changed_aabbs.0.push(brush.chunks().clone());
         }
+        sources.details.remove(&entity);
     });
 
     commands.insert_resource(TerrainSourceArc(Arc::new(sources)));
@@ -108,12 +140,19 @@ fn handle_chunk_changes(
             if spawn.contains_key(&chunk_pos) {
                 continue;
             }
+
+            // Chunks generated again keep their destruction
+            let destruction = match terrain_state.chunk_data.get(&chunk_pos) {
+                Some((data, _)) => Some(data.destruction.clone()),
+                None => terrain_state.unloaded.remove(&chunk_pos),
+            };
+
             spawn.insert(
                 chunk_pos,
                 ChunkSpawnRequest {
                     chunk_pos,
                     copy_borders: false,
-                    destruction: None,
+                    destruction: destruction.filter(|destruction| !destruction.is_empty()),
                 },
             );
         }
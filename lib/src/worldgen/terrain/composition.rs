@@ -0,0 +1,58 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::worldgen::voxel::VoxelMaterial;
+
+use super::ChunkData;
+
+/// Solid voxels of each material in a chunk, updated whenever the chunk is meshed.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ChunkComposition {
+    counts: HashMap<VoxelMaterial, u32>,
+    total: u32,
+}
+
+impl ChunkComposition {
+    pub(super) fn new(data: &ChunkData) -> Self {
+        let mut composition = Self::default();
+        for (distance, material) in data.sdf.iter().zip(data.materials.iter()) {
+            // Negative distances are open space
+            if *distance <= 0.0 || !material.is_natural() {
+                continue;
+            }
+            *composition.counts.entry(*material).or_default() += 1;
+            composition.total += 1;
+        }
+
+        composition
+    }
+
+    pub fn count(&self, material: VoxelMaterial) -> u32 {
+        self.counts.get(&material).copied().unwrap_or_default()
+    }
+
+    /// Share of the solid voxels made of the material, from 0 to 1.
+    pub fn fraction(&self, material: VoxelMaterial) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.count(material) as f32 / self.total as f32
+    }
+
+    /// The most common material, if the chunk has any solid voxels.
+    pub fn dominant(&self) -> Option<VoxelMaterial> {
+        self.counts
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(material, _)| *material)
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (VoxelMaterial, u32)> + '_ {
+        self.counts
+            .iter()
+            .map(|(material, count)| (*material, *count))
+    }
+}
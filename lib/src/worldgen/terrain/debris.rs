@@ -0,0 +1,230 @@
+use avian3d::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rand::{global::GlobalEntropy, prelude::WyRand};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    physics::GameLayer,
+    worldgen::{sdf, voxel::VoxelMaterial},
+};
+
+use super::{DestroyTerrainEvent, VoxelMaterialQuery};
+
+/// Debris pieces are thrown outward at up to this speed, in meters per second.
+const DEBRIS_MAX_SPEED: f32 = 6.0;
+/// Dust drifts outward at up to this speed, in meters per second.
+const DUST_MAX_SPEED: f32 = 1.0;
+/// Dust grows to this many times its starting size.
+const DUST_GROWTH: f32 = 3.0;
+const DUST_ALPHA: f32 = 0.35;
+/// Share of a particle's lifetime spent shrinking away at the end.
+const FADE_FRACTION: f32 = 0.3;
+
+/// Debris thrown out when a material is destroyed, scaled by how much of the destruction gets
+/// through the material.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VoxelDebris {
+    /// Pieces per meter of destruction radius.
+    pub pieces: f32,
+    /// Size of each piece, in meters.
+    pub size: f32,
+    /// Dust puffs per meter of destruction radius.
+    pub dust: f32,
+}
+
+impl Default for VoxelDebris {
+    fn default() -> Self {
+        Self {
+            pieces: 4.0,
+            size: 0.15,
+            dust: 2.0,
+        }
+    }
+}
+
+/// Settings for the debris and dust spawned where terrain is destroyed.
+#[derive(Resource)]
+pub struct TerrainDebris {
+    pub enabled: bool,
+    /// Pieces and dust alive at once. Destruction past the limit doesn't spawn any.
+    pub max_particles: usize,
+    /// Seconds before debris pieces despawn.
+    pub debris_lifetime: f32,
+    /// Seconds before dust despawns.
+    pub dust_lifetime: f32,
+}
+
+impl Default for TerrainDebris {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_particles: 256,
+            debris_lifetime: 4.0,
+            dust_lifetime: 1.5,
+        }
+    }
+}
+
+#[derive(Component)]
+struct DebrisParticle {
+    spawned_secs: f32,
+    lifetime: f32,
+    size: f32,
+    /// Velocity of dust, which drifts and grows as it fades instead of falling. Pieces are moved
+    /// by physics.
+    dust: Option<Vec3>,
+}
+
+/// Meshes shared by every particle, and materials for each voxel material.
+#[derive(Resource)]
+struct DebrisAssets {
+    piece: Handle<Mesh>,
+    dust: Handle<Mesh>,
+    materials: HashMap<(VoxelMaterial, bool), Handle<StandardMaterial>>,
+}
+
+pub struct TerrainDebrisPlugin;
+
+impl Plugin for TerrainDebrisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TerrainDebris>();
+        app.add_systems(Startup, setup);
+        app.add_systems(Update, (spawn_debris, update_debris).chain());
+    }
+}
+
+fn setup(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(DebrisAssets {
+        piece: meshes.add(Cuboid::from_length(1.0)),
+        dust: meshes.add(Sphere::new(0.5).mesh().ico(1).unwrap()),
+        materials: HashMap::new(),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_debris(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<TerrainDebris>,
+    mut assets: ResMut<DebrisAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rng: GlobalEntropy<WyRand>,
+    terrain: VoxelMaterialQuery,
+    particles: Query<(), With<DebrisParticle>>,
+    mut events: EventReader<DestroyTerrainEvent>,
+) {
+    if !settings.enabled {
+        events.clear();
+        return;
+    }
+
+    let mut alive = particles.iter().count();
+    for event in events.read() {
+        let Some(material) = terrain.material_at(event.position) else {
+            continue;
+        };
+        let Some(properties) = terrain.properties(material) else {
+            continue;
+        };
+
+        let strength = sdf::strength(event.force, properties.resistance(event.damage));
+        let debris = &properties.debris;
+        let pieces = (debris.pieces * event.radius * strength).round() as usize;
+        let dust = (debris.dust * event.radius * strength).round() as usize;
+        let color = properties.particle_color();
+
+        for is_dust in (0..pieces + dust).map(|i| i >= pieces) {
+            if alive >= settings.max_particles {
+                return;
+            }
+            alive += 1;
+
+            let material = assets
+                .materials
+                .entry((material, is_dust))
+                .or_insert_with(|| {
+                    materials.add(if is_dust {
+                        StandardMaterial {
+                            base_color: color.with_alpha(DUST_ALPHA),
+                            alpha_mode: AlphaMode::Blend,
+                            unlit: true,
+                            ..default()
+                        }
+                    } else {
+                        StandardMaterial {
+                            base_color: color,
+                            perceptual_roughness: 0.9,
+                            ..default()
+                        }
+                    })
+                })
+                .clone();
+
+            let direction = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+            .normalize_or(Vec3::Y);
+            let offset = direction * rng.gen_range(0.0..event.radius * 0.5);
+            let size = debris.size * rng.gen_range(0.5..1.5);
+            let transform = Transform::from_translation(event.position + offset)
+                .with_rotation(Quat::from_scaled_axis(direction * rng.gen_range(0.0..3.0)))
+                .with_scale(Vec3::splat(size));
+
+            if is_dust {
+                commands.spawn((
+                    DebrisParticle {
+                        spawned_secs: time.elapsed_secs(),
+                        lifetime: settings.dust_lifetime,
+                        size,
+                        dust: Some(direction * rng.gen_range(0.0..DUST_MAX_SPEED)),
+                    },
+                    transform,
+                    Mesh3d(assets.dust.clone()),
+                    MeshMaterial3d(material),
+                ));
+            } else {
+                commands.spawn((
+                    DebrisParticle {
+                        spawned_secs: time.elapsed_secs(),
+                        lifetime: settings.debris_lifetime,
+                        size,
+                        dust: None,
+                    },
+                    transform,
+                    RigidBody::Dynamic,
+                    Collider::cuboid(1.0, 1.0, 1.0),
+                    CollisionLayers::new(GameLayer::Debris, GameLayer::World),
+                    LinearVelocity(direction * rng.gen_range(0.0..DEBRIS_MAX_SPEED)),
+                    Mesh3d(assets.piece.clone()),
+                    MeshMaterial3d(material),
+                ));
+            }
+        }
+    }
+}
+
+/// Moves dust, which has no rigid body, and shrinks particles away at the end of their lifetime.
+fn update_debris(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &DebrisParticle, &mut Transform)>,
+) {
+    for (entity, particle, mut transform) in particles.iter_mut() {
+        let age = (time.elapsed_secs() - particle.spawned_secs) / particle.lifetime;
+        if age >= 1.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let mut size = particle.size * ((1.0 - age) / FADE_FRACTION).min(1.0);
+        if let Some(velocity) = particle.dust {
+            size *= 1.0_f32.lerp(DUST_GROWTH, age);
+            transform.translation += velocity * time.delta_secs();
+        }
+        transform.scale = Vec3::splat(size);
+    }
+}
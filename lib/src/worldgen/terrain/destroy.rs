@@ -1,20 +1,40 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
 
-use bevy::{prelude::*, tasks::AsyncComputeTaskPool, utils::HashSet};
-use rayon::iter::ParallelIterator;
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+    tasks::AsyncComputeTaskPool,
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::worldgen::chunk::ChunksAABB;
+use crate::worldgen::{chunk::ChunksAABB, sdf, voxel::DamageType};
 
 use super::{
-    chunk_samples, merge_sdf_with_hardness, ChunkRemeshRequest, ChunkRemeshTask, ChunkSpawnRequest,
-    ChunkSpawnTask, TerrainState, TerrainStateMutex, VOXEL_REAL_SIZE,
+    carve_sample, fast_surface_nets::ndshape::Shape, ChunkData, ChunkRemeshRequest,
+    ChunkRemeshTask, ChunkSpawnRequest, ChunkSpawnTask, SampleBox, TerrainState, TerrainStateMutex,
+    VoxelMaterialRegistry, VoxelMaterials, VOXEL_REAL_SIZE,
 };
 
+pub const DESTROY_QUEUE_LENGTH: DiagnosticPath = DiagnosticPath::const_new("terrain/destroy_queue");
+pub const DESTROY_CARVED_VOXELS: DiagnosticPath =
+    DiagnosticPath::const_new("terrain/destroy_carved_voxels");
+pub const DESTROY_MERGED_EVENTS: DiagnosticPath =
+    DiagnosticPath::const_new("terrain/destroy_merged_events");
+
+/// An incoming destruction is merged into a queued one if it fits inside it after the queued
+/// radius is scaled by this factor.
+const MERGE_SLACK: f32 = 1.25;
+
 #[derive(Event, Clone, Copy)]
 pub struct DestroyTerrainEvent {
     pub position: Vec3,
     pub radius: f32,
     pub force: f32,
+    pub damage: DamageType,
 }
 
 impl DestroyTerrainEvent {
@@ -23,21 +43,57 @@ impl DestroyTerrainEvent {
             position: self.position,
             radius: self.radius,
             force: self.force,
+            damage: self.damage,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DestroyTerrain {
     pub position: Vec3,
     pub radius: f32,
+    /// Strength relative to material hardness. A force equal to the resistance of a material
+    /// carves it completely.
     pub force: f32,
+    #[serde(default)]
+    pub damage: DamageType,
 }
 
 impl DestroyTerrain {
+    /// Approximate number of voxel samples inside the destruction sphere.
+    pub fn estimated_voxels(&self) -> usize {
+        let volume = 4.0 / 3.0 * PI * self.radius.powi(3);
+        (volume / VOXEL_REAL_SIZE.powi(3)).ceil() as usize
+    }
+
+    /// Merges `other` into `self` if `other` is (nearly) contained by it and does the same type
+    /// of damage.
+    fn try_merge(&mut self, other: &DestroyTerrain) -> bool {
+        if self.damage != other.damage {
+            return false;
+        }
+
+        let (outer, inner) = if self.radius >= other.radius {
+            (*self, *other)
+        } else {
+            (*other, *self)
+        };
+        let distance = outer.position.distance(inner.position);
+        if distance + inner.radius > outer.radius * MERGE_SLACK {
+            return false;
+        }
+
+        *self = DestroyTerrain {
+            position: outer.position,
+            radius: outer.radius.max(distance + inner.radius),
+            force: outer.force.max(inner.force),
+            damage: outer.damage,
+        };
+        true
+    }
+
     fn world_extents(&self) -> (Vec3, Vec3) {
-        let inflate = VOXEL_REAL_SIZE; // World units, not chunks
-        let radius = Vec3::splat(self.radius + inflate);
+        let radius = Vec3::splat(sdf::inflate(self.radius));
         let min = self.position - radius;
         let max = self.position + radius;
 
@@ -45,16 +101,75 @@ impl DestroyTerrain {
     }
 }
 
+/// Destruction waiting to be applied, in the order it was requested.
+#[derive(Resource, Default)]
+pub struct DestroyTerrainQueue(pub Vec<DestroyTerrain>);
+
+/// Maximum number of voxels carved per batch. At least one destruction is always applied so
+/// large ones can't stall the queue.
+#[derive(Resource)]
+pub struct DestroyTerrainBudget {
+    pub voxels_per_batch: usize,
+}
+
+impl Default for DestroyTerrainBudget {
+    fn default() -> Self {
+        Self {
+            voxels_per_batch: 8192,
+        }
+    }
+}
+
+pub struct DestroyTerrainPlugin;
+
+impl Plugin for DestroyTerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DestroyTerrainEvent>()
+            .init_resource::<DestroyTerrainQueue>()
+            .init_resource::<DestroyTerrainBudget>()
+            .register_diagnostic(Diagnostic::new(DESTROY_QUEUE_LENGTH))
+            .register_diagnostic(Diagnostic::new(DESTROY_CARVED_VOXELS))
+            .register_diagnostic(Diagnostic::new(DESTROY_MERGED_EVENTS))
+            .add_systems(Update, queue_destroy_terrain.before(begin_destroy_terrain));
+    }
+}
+
 pub struct DestroyTerrainParams {
     pub state: Arc<Mutex<TerrainState>>,
+    pub materials: Arc<VoxelMaterialRegistry>,
     pub destruction: Vec<DestroyTerrain>,
 }
 
+fn queue_destroy_terrain(
+    mut diagnostics: Diagnostics,
+    mut events: EventReader<DestroyTerrainEvent>,
+    mut queue: ResMut<DestroyTerrainQueue>,
+) {
+    let mut merged = 0;
+
+    'events: for event in events.read() {
+        let destroy = event.unevent();
+        for queued in queue.0.iter_mut() {
+            if queued.try_merge(&destroy) {
+                merged += 1;
+                continue 'events;
+            }
+        }
+        queue.0.push(destroy);
+    }
+
+    diagnostics.add_measurement(&DESTROY_MERGED_EVENTS, || merged as f64);
+    diagnostics.add_measurement(&DESTROY_QUEUE_LENGTH, || queue.0.len() as f64);
+}
+
 pub fn begin_destroy_terrain(
-    mut event: EventReader<DestroyTerrainEvent>,
+    mut diagnostics: Diagnostics,
+    mut queue: ResMut<DestroyTerrainQueue>,
+    budget: Res<DestroyTerrainBudget>,
     spawn_tasks: Query<&ChunkSpawnTask>,
     remesh_tasks: Query<&ChunkRemeshTask>,
     state: Res<TerrainStateMutex>,
+    materials: Res<VoxelMaterials>,
 ) {
     // Wait until all other spawn/remesh tasks are finished
     {
@@ -68,7 +183,20 @@ pub fn begin_destroy_terrain(
         }
     }
 
-    let destruction: Vec<DestroyTerrain> = event.read().map(|e| e.unevent()).collect();
+    // Take as much as the budget allows, deferring the rest
+    let mut voxels = 0;
+    let mut take = 0;
+    for destroy in queue.0.iter() {
+        let estimate = destroy.estimated_voxels();
+        if take > 0 && voxels + estimate > budget.voxels_per_batch {
+            break;
+        }
+        voxels += estimate;
+        take += 1;
+    }
+    let destruction: Vec<DestroyTerrain> = queue.0.drain(0..take).collect();
+
+    diagnostics.add_measurement(&DESTROY_CARVED_VOXELS, || voxels as f64);
 
     if destruction.len() == 0 {
         return;
@@ -76,6 +204,7 @@ pub fn begin_destroy_terrain(
 
     let params = DestroyTerrainParams {
         state: state.clone(),
+        materials: materials.0.clone(),
         destruction,
     };
 
@@ -99,30 +228,75 @@ fn destroy_terrain(params: DestroyTerrainParams) {
 
     for chunk_pos in affected_chunks {
         let Some((data, chunk_entity)) = state.chunk_data.get_mut(&chunk_pos) else {
+            // Chunks unloaded by streaming keep their earlier destruction
+            let mut destruction = state.unloaded.remove(&chunk_pos).unwrap_or_default();
+            destruction.extend(params.destruction.iter().copied());
             spawn_requests.push(ChunkSpawnRequest {
                 chunk_pos,
                 copy_borders: true,
-                destruction: Some(params.destruction.clone()),
+                destruction: Some(destruction),
             });
             continue;
         };
 
-        let world_pos = data.world_pos();
+        // One request per chunk, covering every destruction that changed it
+        let mut dirty: Option<SampleBox> = None;
         for destroy in params.destruction.iter() {
-            let changed = merge_sdf_with_hardness(data, destroy.force, || {
-                chunk_samples(&world_pos)
-                    .map(|point| point.distance(destroy.position) - destroy.radius)
-                    .collect()
-            });
-            if changed {
-                remesh_requests.push(ChunkRemeshRequest {
-                    chunk_pos,
-                    chunk_entity: *chunk_entity,
-                });
+            if let Some(changed) = carve_chunk(data, destroy, &params.materials) {
+                data.destruction.push(*destroy);
+                dirty = Some(dirty.map_or(changed, |dirty| dirty.union(changed)));
             }
         }
+        if dirty.is_some() {
+            remesh_requests.push(ChunkRemeshRequest {
+                chunk_pos,
+                chunk_entity: *chunk_entity,
+                dirty,
+            });
+        }
     }
 
     state.spawn_requests.extend(spawn_requests);
     state.remesh_requests.extend(remesh_requests);
 }
+
+/// Carves a destruction sphere out of a loaded chunk, visiting only the samples within its
+/// bounds. Returns the samples that changed, if any.
+pub fn carve_chunk(
+    data: &mut ChunkData,
+    destroy: &DestroyTerrain,
+    materials: &VoxelMaterialRegistry,
+) -> Option<SampleBox> {
+    let resolution = data.detail.sample_resolution();
+    let center = data.to_sample_space(destroy.position);
+    let radius = sdf::inflate(destroy.radius) * resolution;
+    let min = (center - radius).floor().max(Vec3::ZERO);
+    let max = (center + radius)
+        .ceil()
+        .min(Vec3::splat(data.max_sample() as f32));
+    if min.cmpgt(max).any() {
+        return None;
+    }
+
+    let world_pos = data.world_pos();
+    let (min, max) = (min.as_uvec3(), max.as_uvec3());
+    let mut changed: Option<SampleBox> = None;
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let sample = UVec3::new(x, y, z);
+                let i = data.shape.linearize(sample.to_array()) as usize;
+                let point = sample.as_vec3() / resolution + world_pos;
+                let distance = sdf::sphere(point, destroy.position, destroy.radius);
+
+                if carve_sample(data, i, distance, destroy, materials) {
+                    let sample = SampleBox::point(sample);
+                    changed = Some(changed.map_or(sample, |changed| changed.union(sample)));
+                }
+            }
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,422 @@
+use std::sync::mpsc;
+
+use anyhow::{anyhow, bail};
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingType, BufferBindingType,
+            BufferDescriptor, BufferInitDescriptor, BufferUsages, CommandEncoderDescriptor,
+            ComputePassDescriptor, ComputePipeline, Maintain, MapMode, PipelineCompilationOptions,
+            PipelineLayoutDescriptor, RawComputePipelineDescriptor, ShaderModuleDescriptor,
+            ShaderSource, ShaderStages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        RenderApp,
+    },
+};
+use curvo::prelude::Tessellation;
+
+use crate::{
+    debug_console::{ConsoleCommand, RegisterConsoleCommand},
+    worldgen::{
+        brush::{primitive::BrushPrimitive, TerrainBrush},
+        voxel::VoxelMaterial,
+    },
+};
+
+use super::ChunkData;
+
+const WORKGROUP_SIZE: u32 = 64;
+const SHADER: &str = include_str!("sample_brushes.wgsl");
+
+const KIND_CURVE: u32 = 0;
+const KIND_BOX: u32 = 1;
+const KIND_SPHERE: u32 = 2;
+const KIND_CAPSULE: u32 = 3;
+
+/// Evaluates brushes with an exact distance function on the GPU when chunks are generated. Other
+/// brushes, and any run of brushes the GPU fails to sample, are still sampled on the CPU.
+#[derive(Resource, Default)]
+pub struct GpuSampling {
+    /// Off by default. Has no effect if compute shaders aren't supported.
+    pub enabled: bool,
+}
+
+/// Compute pipeline that merges brushes into a chunk's samples, shared with chunk tasks. Only
+/// exists if the renderer supports compute shaders.
+#[derive(Resource, Clone)]
+pub struct GpuSdfSampler {
+    device: RenderDevice,
+    queue: RenderQueue,
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl GpuSdfSampler {
+    fn new(device: RenderDevice, queue: RenderQueue) -> Self {
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(
+            "sample_brushes_layout",
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+            ],
+        );
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("sample_brushes"),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("sample_brushes_pipeline_layout"),
+            bind_group_layouts: &[&*layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&RawComputePipelineDescriptor {
+            label: Some("sample_brushes_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            device,
+            queue,
+            layout,
+            pipeline,
+        }
+    }
+
+    /// Whether the brush can be sampled on the GPU.
+    pub fn supports(brush: &TerrainBrush) -> bool {
+        matches!(
+            brush,
+            TerrainBrush::Curve { .. } | TerrainBrush::Primitive { .. } | TerrainBrush::Fill { .. }
+        )
+    }
+
+    /// Merges the brushes into the chunk in order, starting from its current samples. The chunk
+    /// is left as it was if sampling fails.
+    pub fn sample(&self, data: &mut ChunkData, brushes: &[&TerrainBrush]) -> anyhow::Result<()> {
+        let (brush_bytes, point_bytes) = encode_brushes(brushes)?;
+        let count = data.sdf.len() as u32;
+        let size = data.max_sample() + 1;
+
+        let mut params = Vec::with_capacity(32);
+        params.extend(data.world_pos().to_array().map(f32::to_le_bytes).concat());
+        params.extend((1.0 / data.detail.sample_resolution()).to_le_bytes());
+        params.extend(
+            [size, count, brushes.len() as u32, 0]
+                .map(u32::to_le_bytes)
+                .concat(),
+        );
+
+        let sdf = data
+            .sdf
+            .iter()
+            .flat_map(|distance| distance.to_le_bytes())
+            .collect::<Vec<_>>();
+        let materials = data
+            .materials
+            .iter()
+            .flat_map(|material| (material.0 as u32).to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let buffer = |label, contents: &[u8], usage| {
+            self.device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage,
+            })
+        };
+        let params_buffer = buffer("sample_brushes_params", &params, BufferUsages::UNIFORM);
+        let brush_buffer = buffer(
+            "sample_brushes_brushes",
+            &brush_bytes,
+            BufferUsages::STORAGE,
+        );
+        let point_buffer = buffer("sample_brushes_points", &point_bytes, BufferUsages::STORAGE);
+        let readable = BufferUsages::STORAGE | BufferUsages::COPY_SRC;
+        let sdf_buffer = buffer("sample_brushes_sdf", &sdf, readable);
+        let material_buffer = buffer("sample_brushes_materials", &materials, readable);
+
+        let output_size = (sdf.len() + materials.len()) as u64;
+        let output_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("sample_brushes_output"),
+            size: output_size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(
+            "sample_brushes_bind_group",
+            &self.layout,
+            &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: brush_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: point_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: sdf_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: material_buffer.as_entire_binding(),
+                },
+            ],
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("sample_brushes_encoder"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("sample_brushes_pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &*bind_group, &[]);
+            pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&sdf_buffer, 0, &output_buffer, 0, sdf.len() as u64);
+        encoder.copy_buffer_to_buffer(
+            &material_buffer,
+            0,
+            &output_buffer,
+            sdf.len() as u64,
+            materials.len() as u64,
+        );
+        self.queue.submit([encoder.finish()]);
+
+        // Chunks are generated in the background, so waiting on the GPU doesn't block a frame
+        let slice = output_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        self.device
+            .map_buffer(&slice, MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.device.poll(Maintain::Wait);
+        receiver.recv()??;
+
+        {
+            let output = slice.get_mapped_range();
+            let (sdf, materials) = output.split_at(sdf.len());
+            for (distance, bytes) in data.sdf.iter_mut().zip(sdf.chunks_exact(4)) {
+                *distance = f32::from_le_bytes(bytes.try_into()?);
+            }
+            for (material, bytes) in data.materials.iter_mut().zip(materials.chunks_exact(4)) {
+                let value = u32::from_le_bytes(bytes.try_into()?);
+                *material = VoxelMaterial(value as u8);
+            }
+        }
+        output_buffer.unmap();
+
+        Ok(())
+    }
+}
+
+/// Returns the brush and point buffers. Each brush is 80 bytes, see `Brush` in the shader.
+fn encode_brushes(brushes: &[&TerrainBrush]) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let mut brush_bytes = Vec::with_capacity(brushes.len() * 80);
+    let mut points = Vec::<Vec4>::new();
+
+    for brush in brushes {
+        let point_start = points.len() as u32;
+        let (kind, material, radius, transform, half_extents) = match brush {
+            TerrainBrush::Curve {
+                curve,
+                radius,
+                material,
+                ..
+            } => {
+                let samples = curve.tessellate(Some(1e-8));
+                points.extend(
+                    samples
+                        .iter()
+                        .map(|point| Vec4::new(point.x, point.y, point.z, 0.0)),
+                );
+                (
+                    KIND_CURVE,
+                    *material,
+                    *radius,
+                    Transform::default(),
+                    Vec3::ZERO,
+                )
+            }
+            TerrainBrush::Primitive {
+                primitive,
+                material,
+                transform,
+                ..
+            } => match *primitive {
+                BrushPrimitive::Box { half_extents } => {
+                    (KIND_BOX, *material, 0.0, *transform, half_extents)
+                }
+                BrushPrimitive::Sphere { radius } => {
+                    (KIND_SPHERE, *material, radius, *transform, Vec3::ZERO)
+                }
+                BrushPrimitive::Capsule {
+                    half_height,
+                    radius,
+                } => (
+                    KIND_CAPSULE,
+                    *material,
+                    radius,
+                    *transform,
+                    Vec3::Y * half_height,
+                ),
+            },
+            TerrainBrush::Fill {
+                position,
+                radius,
+                material,
+                ..
+            } => (
+                KIND_SPHERE,
+                *material,
+                *radius,
+                Transform::from_translation(*position),
+                Vec3::ZERO,
+            ),
+            _ => {
+                return Err(anyhow!(
+                    "brush {} can't be sampled on the GPU",
+                    brush.uuid()
+                ))
+            }
+        };
+        let point_count = points.len() as u32 - point_start;
+        if kind == KIND_CURVE && point_count == 0 {
+            return Err(anyhow!("curve {} has no points", brush.uuid()));
+        }
+
+        let header = [
+            kind,
+            brush.operation() as u32,
+            material.0 as u32,
+            point_start,
+            point_count,
+        ];
+        brush_bytes.extend(header.map(u32::to_le_bytes).concat());
+        brush_bytes.extend(
+            [brush.smoothness(), radius, 0.0]
+                .map(f32::to_le_bytes)
+                .concat(),
+        );
+        let vectors = [
+            transform.translation.extend(0.0),
+            Vec4::from(transform.rotation.inverse()),
+            half_extents.extend(0.0),
+        ];
+        for vector in vectors {
+            brush_bytes.extend(vector.to_array().map(f32::to_le_bytes).concat());
+        }
+    }
+
+    // Storage buffers can't be empty
+    if points.is_empty() {
+        points.push(Vec4::ZERO);
+    }
+    let point_bytes = points
+        .iter()
+        .flat_map(|point| point.to_array().map(f32::to_le_bytes).concat())
+        .collect();
+
+    Ok((brush_bytes, point_bytes))
+}
+
+struct GpuSamplingCommand;
+
+impl ConsoleCommand for GpuSamplingCommand {
+    fn name(&self) -> &'static str {
+        "gpu_sampling"
+    }
+
+    fn usage(&self) -> &'static str {
+        "gpu_sampling [on|off] - samples terrain brushes on the GPU when chunks are generated"
+    }
+
+    fn run(&self, args: &[&str], world: &mut World) -> anyhow::Result<String> {
+        let mut sampling = world.resource_mut::<GpuSampling>();
+        sampling.enabled = match args {
+            [] => !sampling.enabled,
+            ["on"] => true,
+            ["off"] => false,
+            _ => bail!("expected 'on' or 'off'"),
+        };
+
+        let state = if sampling.enabled { "on" } else { "off" };
+        if world.contains_resource::<GpuSdfSampler>() {
+            Ok(format!("gpu sampling {state}"))
+        } else {
+            Ok(format!(
+                "gpu sampling {state} (compute shaders aren't supported)"
+            ))
+        }
+    }
+}
+
+pub struct GpuSamplingPlugin;
+
+impl Plugin for GpuSamplingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GpuSampling>()
+            .register_console_command(GpuSamplingCommand);
+    }
+
+    /// The render device only exists once the renderer has finished building.
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app(RenderApp) else {
+            return;
+        };
+        let world = render_app.world();
+        let (Some(device), Some(queue)) = (
+            world.get_resource::<RenderDevice>(),
+            world.get_resource::<RenderQueue>(),
+        ) else {
+            return;
+        };
+        if device.limits().max_compute_workgroup_size_x < WORKGROUP_SIZE {
+            info!("compute shaders aren't supported, terrain is always sampled on the CPU");
+            return;
+        }
+
+        let sampler = GpuSdfSampler::new(device.clone(), queue.clone());
+        app.insert_resource(sampler);
+    }
+}
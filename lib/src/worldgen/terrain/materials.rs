@@ -0,0 +1,217 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    materials::is_drawn_voxel_type,
+    worldgen::voxel::{DamageType, VoxelHardness, VoxelMaterial},
+};
+
+use super::{
+    debris::VoxelDebris, Chunk, ChunkComposition, TerrainStateMutex, UpdateChunks, VOXEL_REAL_SIZE,
+};
+
+const VOXEL_MATERIALS_PATH: &str = "./assets/materials.ron";
+/// The materials shipped with the game. See [`VoxelMaterialRegistry::default`].
+const DEFAULT_VOXEL_MATERIALS: &str = include_str!("../../../../assets/materials.ron");
+/// How far behind a surface point its material is sampled, so the sample isn't taken from open
+/// space.
+const SURFACE_SAMPLE_DEPTH: f32 = VOXEL_REAL_SIZE * 0.5;
+
+/// How a voxel material behaves in the game, as opposed to how it's generated.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VoxelMaterialProperties {
+    /// Shown in the editor. Built-in materials have a name already.
+    pub name: Option<String>,
+    pub hardness: VoxelHardness,
+    /// Multiplies the hardness against each damage type. Damage types that aren't listed aren't
+    /// multiplied.
+    pub damage_multipliers: BTreeMap<DamageType, f32>,
+    pub friction: f32,
+    pub restitution: f32,
+    /// Voxel type drawn by the cave shader, so new materials can reuse the look of another.
+    pub texture: u8,
+    /// Sound played where weapons hit the material, if any.
+    pub impact_sfx: Option<String>,
+    /// Color of impact cues and debris, in sRGB.
+    pub particle_color: [f32; 3],
+    pub debris: VoxelDebris,
+    /// Sound set played when the player walks, lands, or slides on the material. Names a
+    /// directory in `assets/sfx/footsteps`.
+    pub footsteps: String,
+    /// Hitscan and projectiles bounce off deflective materials instead of stopping.
+    pub deflective: bool,
+    /// Cable anchors hold in these. Softer rock crumbles under the load.
+    pub holds_anchors: bool,
+    /// Worth prospecting for.
+    pub valuable: bool,
+}
+
+impl Default for VoxelMaterialProperties {
+    fn default() -> Self {
+        Self {
+            name: None,
+            hardness: VoxelHardness::Default,
+            damage_multipliers: BTreeMap::new(),
+            friction: 0.5,
+            restitution: 0.0,
+            texture: VoxelMaterial::Invalid.0,
+            impact_sfx: Some("sfx/impact/rock.ogg".to_owned()),
+            particle_color: [0.75, 0.6, 0.45],
+            debris: default(),
+            footsteps: "rock".to_owned(),
+            deflective: false,
+            holds_anchors: false,
+            valuable: false,
+        }
+    }
+}
+
+impl VoxelMaterialProperties {
+    /// Hardness against a damage type.
+    pub fn resistance(&self, damage: DamageType) -> f32 {
+        let multiplier = self.damage_multipliers.get(&damage).copied().unwrap_or(1.0);
+        self.hardness.multiplier() * multiplier
+    }
+
+    pub fn particle_color(&self) -> Color {
+        let [r, g, b] = self.particle_color;
+        Color::srgb(r, g, b)
+    }
+}
+
+/// Properties of each voxel material. Loaded from `assets/materials.ron`, where new materials are
+/// added by number, e.g. `Custom(4)`, and take the look of a built-in one through their texture.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoxelMaterialRegistry {
+    pub materials: BTreeMap<VoxelMaterial, VoxelMaterialProperties>,
+    /// Used by materials that aren't listed.
+    #[serde(default)]
+    pub fallback: VoxelMaterialProperties,
+}
+
+impl Default for VoxelMaterialRegistry {
+    /// The registry shipped in `assets/materials.ron`, so it's used wherever that file can't be
+    /// read.
+    fn default() -> Self {
+        ron::from_str(DEFAULT_VOXEL_MATERIALS).expect("built-in voxel materials should parse")
+    }
+}
+
+impl VoxelMaterialRegistry {
+    pub fn get(&self, material: VoxelMaterial) -> &VoxelMaterialProperties {
+        self.materials.get(&material).unwrap_or(&self.fallback)
+    }
+
+    pub fn name(&self, material: VoxelMaterial) -> String {
+        match self
+            .materials
+            .get(&material)
+            .and_then(|properties| properties.name.as_ref())
+        {
+            Some(name) => name.clone(),
+            None => material.to_string(),
+        }
+    }
+
+    /// Voxel types drawn by the cave shader for valuable materials, as a bitmask.
+    pub fn valuable_textures(&self) -> u32 {
+        self.materials
+            .values()
+            .filter(|properties| properties.valuable && properties.texture < 32)
+            .fold(0, |mask, properties| mask | 1 << properties.texture)
+    }
+}
+
+/// The registry shared with terrain tasks. Replace it to change the properties of materials.
+#[derive(Resource, Default, Deref)]
+pub struct VoxelMaterials(pub Arc<VoxelMaterialRegistry>);
+
+/// Looks up the materials of loaded terrain.
+#[derive(SystemParam)]
+pub struct VoxelMaterialQuery<'w> {
+    terrain: Option<Res<'w, TerrainStateMutex>>,
+    materials: Option<Res<'w, VoxelMaterials>>,
+}
+
+impl VoxelMaterialQuery<'_> {
+    /// Returns the material of the nearest voxel sample, if its chunk is loaded.
+    pub fn material_at(&self, position: Vec3) -> Option<VoxelMaterial> {
+        self.terrain.as_ref()?.material_at(position)
+    }
+
+    /// Returns the material at a point on the surface of the terrain and its properties, if its
+    /// chunk is loaded.
+    pub fn surface_at(
+        &self,
+        point: Vec3,
+        normal: Vec3,
+    ) -> Option<(VoxelMaterial, &VoxelMaterialProperties)> {
+        let material = self.material_at(point - normal * SURFACE_SAMPLE_DEPTH)?;
+        Some((material, self.materials.as_ref()?.get(material)))
+    }
+
+    pub fn properties(&self, material: VoxelMaterial) -> Option<&VoxelMaterialProperties> {
+        Some(self.materials.as_ref()?.get(material))
+    }
+}
+
+//
+// Plugin
+//
+
+pub struct VoxelMaterialsPlugin;
+
+impl Plugin for VoxelMaterialsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VoxelMaterials>();
+        app.add_systems(Startup, load_voxel_materials);
+        app.add_systems(Update, update_chunk_physics.after(UpdateChunks));
+    }
+}
+
+fn load_voxel_materials(mut commands: Commands) {
+    let registry = match std::fs::read_to_string(VOXEL_MATERIALS_PATH) {
+        Ok(s) => ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("failed to parse voxel materials, using default: {err}");
+            VoxelMaterialRegistry::default()
+        }),
+        Err(_) => VoxelMaterialRegistry::default(),
+    };
+    for (material, properties) in registry.materials.iter() {
+        if !is_drawn_voxel_type(properties.texture) {
+            warn!(
+                "voxel material {} uses texture {}, which is drawn as invalid",
+                registry.name(*material),
+                properties.texture
+            );
+        }
+    }
+
+    commands.insert_resource(VoxelMaterials(Arc::new(registry)));
+}
+
+/// Chunks take the friction and restitution of their most common material.
+fn update_chunk_physics(
+    mut commands: Commands,
+    materials: Res<VoxelMaterials>,
+    chunks: Query<(Entity, Ref<ChunkComposition>), With<Chunk>>,
+) {
+    for (entity, composition) in chunks.iter() {
+        if !composition.is_changed() && !materials.is_changed() {
+            continue;
+        }
+
+        let properties = match composition.dominant() {
+            Some(material) => materials.get(material),
+            None => &materials.fallback,
+        };
+        commands.entity(entity).try_insert((
+            Friction::new(properties.friction),
+            Restitution::new(properties.restitution),
+        ));
+    }
+}
@@ -5,34 +5,66 @@ use bevy::{
     prelude::*,
     utils::{HashMap, HashSet},
 };
-use fast_surface_nets::ndshape::{ConstShape, ConstShape3u32};
+use fast_surface_nets::ndshape::{RuntimeShape, Shape};
 
-use crate::materials::{CaveMaterial, CaveMaterialExtension};
+use crate::materials::{CaveMaterial, CaveMaterialExtension, CaveMaterialQuality};
 
 use super::{brush::TerrainBrushPlugin, chunk::ChunksAABB, consts::*, voxel::VoxelMaterial};
 
 mod boundary;
 mod change_detection;
+mod composition;
+mod debris;
 mod destroy;
 mod fast_surface_nets;
+mod gpu;
+mod materials;
+mod navigation;
+mod persistence;
+mod placement;
 mod remesh;
 mod spawn;
+mod streaming;
 mod utility;
 
+use boundary::*;
 use change_detection::TerrainChangeDetectionPlugin;
+use debris::TerrainDebrisPlugin;
 use destroy::*;
+use materials::VoxelMaterialsPlugin;
+use navigation::TerrainNavigationPlugin;
+use placement::TerrainPlacementPlugin;
 use remesh::*;
 use spawn::*;
+use streaming::TerrainStreamingPlugin;
 use utility::*;
 
-pub use destroy::DestroyTerrainEvent;
+pub use boundary::{FrontierProximity, FrontierTelemetryEvent};
+pub use composition::ChunkComposition;
+pub use debris::{TerrainDebris, VoxelDebris};
+pub use destroy::{
+    carve_chunk, DestroyTerrain, DestroyTerrainBudget, DestroyTerrainEvent, DestroyTerrainQueue,
+    DESTROY_CARVED_VOXELS, DESTROY_MERGED_EVENTS, DESTROY_QUEUE_LENGTH,
+};
+pub use gpu::{GpuSampling, GpuSamplingPlugin, GpuSdfSampler};
+pub use materials::{
+    VoxelMaterialProperties, VoxelMaterialQuery, VoxelMaterialRegistry, VoxelMaterials,
+};
+pub use navigation::{NavGrid, NAV_CELL_SIZE};
+pub use persistence::ChunkStore;
+pub use placement::{
+    PlaceOnTerrain, PlacementFailure, PlacementRetry, SurfacePlacement, TerrainPlacement,
+};
+pub use remesh::{SampleBox, PARTIAL_REMESH_MAX_FRACTION};
+pub use spawn::sample_brushes;
+pub use streaming::{ChunkLoader, TerrainStreamingSettings};
+pub use utility::{mesh_chunk, remesh_chunk_region, ChunkMeshes};
 
 //
 // Types & consts
 //
 
-type ChunkShape =
-    ConstShape3u32<{ CHUNK_SAMPLE_SIZE + 2 }, { CHUNK_SAMPLE_SIZE + 2 }, { CHUNK_SAMPLE_SIZE + 2 }>;
+type ChunkShape = RuntimeShape<u32, 3>;
 
 const CHUNK_BORDER_INSET: f32 = 0.0;
 
@@ -43,38 +75,201 @@ const CHUNK_BORDER_INSET: f32 = 0.0;
 #[derive(Component)]
 pub struct Chunk;
 
+/// Terrain systems that spawn, mesh, and despawn chunks. Systems ordered after this set see the
+/// components of chunks from the lifecycle events sent this frame, since commands are applied in
+/// between.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UpdateChunks;
+
+/// Sent when a chunk entity is spawned with its collider, mesh, and composition.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkSpawned {
+    pub chunk_pos: IVec3,
+    pub entity: Entity,
+}
+
+/// Sent after [`ChunkSpawned`], and again whenever the chunk's collider and mesh are replaced,
+/// e.g. after destruction.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkMeshed {
+    pub chunk_pos: IVec3,
+    pub entity: Entity,
+}
+
+/// Sent when a chunk is unloaded or replaced. The entity may no longer exist when this is read.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkDespawned {
+    pub chunk_pos: IVec3,
+    pub entity: Entity,
+}
+
+/// Sampling density of a chunk. Add this to a brush entity to raise the detail of every chunk
+/// it overlaps. Chunks use the highest detail of their brushes.
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TerrainDetail {
+    #[default]
+    Standard,
+    High,
+}
+
+impl TerrainDetail {
+    /// Samples per world unit.
+    pub fn sample_resolution(self) -> f32 {
+        match self {
+            TerrainDetail::Standard => CHUNK_SAMPLE_RESOLUTION,
+            TerrainDetail::High => CHUNK_HIGH_DETAIL_SAMPLE_RESOLUTION,
+        }
+    }
+
+    /// Samples along each axis, not including the border.
+    pub fn sample_size(self) -> u32 {
+        (CHUNK_SIZE_F * self.sample_resolution()) as u32
+    }
+
+    fn shape(self) -> ChunkShape {
+        ChunkShape::new([self.sample_size() + 2; 3])
+    }
+}
+
 pub struct ChunkData {
     chunk_pos: IVec3,
-    materials: [VoxelMaterial; ChunkShape::USIZE],
-    sdf: [f32; ChunkShape::USIZE],
+    detail: TerrainDetail,
+    shape: ChunkShape,
+    materials: Vec<VoxelMaterial>,
+    sdf: Vec<f32>,
+    /// Surface from the last remesh, used to remesh only the part of the chunk that changed.
+    surface: Option<ChunkSurface>,
+    /// Destruction applied since the chunk was generated, replayed if it is unloaded and loaded
+    /// again.
+    destruction: Vec<DestroyTerrain>,
 }
 
 impl ChunkData {
-    fn new(chunk_pos: IVec3) -> Self {
+    /// A chunk with no brushes merged into it yet.
+    pub fn new(chunk_pos: IVec3, detail: TerrainDetail) -> Self {
+        let shape = detail.shape();
+        let len = shape.size() as usize;
         Self {
             chunk_pos,
-            materials: [VoxelMaterial::Unset; ChunkShape::USIZE],
-            sdf: [f32::MAX; ChunkShape::USIZE],
+            detail,
+            shape,
+            materials: vec![VoxelMaterial::Unset; len],
+            sdf: vec![f32::MAX; len],
+            surface: None,
+            destruction: Vec::new(),
         }
     }
 
+    pub fn chunk_pos(&self) -> IVec3 {
+        self.chunk_pos
+    }
+
+    pub fn detail(&self) -> TerrainDetail {
+        self.detail
+    }
+
+    /// Destruction applied since the chunk was generated.
+    pub fn destruction(&self) -> &[DestroyTerrain] {
+        &self.destruction
+    }
+
+    /// Distance to the surface at each sample, including the border.
+    pub fn sdf(&self) -> &[f32] {
+        &self.sdf
+    }
+
+    pub fn materials(&self) -> &[VoxelMaterial] {
+        &self.materials
+    }
+
     pub fn world_pos(&self) -> Vec3 {
         self.chunk_pos.as_vec3() * CHUNK_SIZE_F
     }
+
+    /// Index of the last sample along each axis.
+    fn max_sample(&self) -> u32 {
+        self.detail.sample_size() + 1
+    }
+
+    /// Converts a world position to this chunk's sample space.
+    fn to_sample_space(&self, position: Vec3) -> Vec3 {
+        (position - self.world_pos()) * self.detail.sample_resolution()
+    }
+
+    /// Returns the material of the nearest sample. The point is in sample space.
+    fn nearest_material(&self, point: Vec3) -> VoxelMaterial {
+        let sample = point
+            .round()
+            .clamp(Vec3::ZERO, Vec3::splat(self.max_sample() as f32))
+            .as_uvec3();
+        self.materials[self.shape.linearize(sample.to_array()) as usize]
+    }
 }
 
 #[derive(Resource, Default, Deref)]
-struct TerrainStateMutex(pub Arc<Mutex<TerrainState>>);
+pub(crate) struct TerrainStateMutex(pub Arc<Mutex<TerrainState>>);
+
+impl TerrainStateMutex {
+    /// Returns the material of the nearest voxel sample, if its chunk is loaded.
+    pub fn material_at(&self, position: Vec3) -> Option<VoxelMaterial> {
+        let chunk_pos = (position / CHUNK_SIZE_F).floor().as_ivec3();
+        let state = self.lock().unwrap();
+        let (data, _) = state.chunk_data.get(&chunk_pos)?;
+
+        Some(data.nearest_material(data.to_sample_space(position)))
+    }
+
+    /// Returns the interpolated distance to the surface, if the chunk is loaded. Open space is
+    /// negative.
+    pub fn distance_at(&self, position: Vec3) -> Option<f32> {
+        self.lock().unwrap().distance_at(position)
+    }
+
+    /// Returns how much solid terrain is behind a surface point, measured inward along the normal
+    /// in steps, up to `max_depth`. Returns None if any of it isn't loaded.
+    pub fn thickness_at(
+        &self,
+        position: Vec3,
+        normal: Vec3,
+        step: f32,
+        max_depth: f32,
+    ) -> Option<f32> {
+        let state = self.lock().unwrap();
+        let mut depth = step;
+        while depth < max_depth {
+            if state.distance_at(position - normal * depth)? < 0.0 {
+                return Some(depth - step);
+            }
+            depth += step;
+        }
+
+        Some(max_depth)
+    }
+}
 
 #[derive(Default)]
-struct TerrainState {
+pub(crate) struct TerrainState {
     pub chunk_data: HashMap<IVec3, (ChunkData, Entity)>,
 
     pub spawn_requests: Vec<ChunkSpawnRequest>,
     pub remesh_requests: Vec<ChunkRemeshRequest>,
+
+    /// Destruction of chunks unloaded by streaming, applied again when they are loaded.
+    pub unloaded: HashMap<IVec3, Vec<DestroyTerrain>>,
+    /// Modified chunks of the world being played, if any. Chunks are written to it as they
+    /// unload, and read back when they load again.
+    pub store: Option<ChunkStore>,
 }
 
 impl TerrainState {
+    /// See [`TerrainStateMutex::distance_at`].
+    pub fn distance_at(&self, position: Vec3) -> Option<f32> {
+        let chunk_pos = (position / CHUNK_SIZE_F).floor().as_ivec3();
+        let (data, _) = self.chunk_data.get(&chunk_pos)?;
+
+        Some(sample_sdf(data, data.to_sample_space(position)))
+    }
+
     /// Returns up to 4 neighboring chunks
     pub fn neighbors(&self, chunk_pos: &IVec3) -> Vec<IVec3> {
         let directions: Vec<IVec3> = vec![
@@ -111,11 +306,33 @@ pub struct TerrainPlugin;
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<TerrainStateMutex>()
-            .add_event::<DestroyTerrainEvent>()
-            .add_plugins((TerrainChangeDetectionPlugin, TerrainBrushPlugin))
+            .init_resource::<FrontierProximity>()
+            .init_resource::<CaveMaterialQuality>()
+            .add_event::<FrontierTelemetryEvent>()
+            .add_event::<ChunkSpawned>()
+            .add_event::<ChunkMeshed>()
+            .add_event::<ChunkDespawned>()
+            .add_plugins((
+                TerrainChangeDetectionPlugin,
+                TerrainBrushPlugin,
+                DestroyTerrainPlugin,
+                TerrainDebrisPlugin,
+                GpuSamplingPlugin,
+                VoxelMaterialsPlugin,
+                TerrainPlacementPlugin,
+                TerrainStreamingPlugin,
+                TerrainNavigationPlugin,
+            ))
             .add_systems(Startup, (setup, setup_material))
             .add_systems(Update, draw_debug)
-            //.add_systems(Update, enforce_loading_chunk_boundaries)
+            .add_systems(
+                Update,
+                update_material_quality.run_if(resource_changed::<CaveMaterialQuality>),
+            )
+            .add_systems(
+                Update,
+                (enforce_loading_chunk_boundaries, push_player_from_frontier).chain(),
+            )
             .add_systems(
                 Update,
                 (
@@ -125,11 +342,26 @@ impl Plugin for TerrainPlugin {
                     receive_spawn_chunks,
                     begin_destroy_terrain,
                 )
-                    .chain(),
+                    .chain()
+                    .in_set(UpdateChunks),
             );
     }
 }
 
+/// True when no chunks are waiting to be spawned or remeshed.
+pub(crate) fn terrain_is_idle(
+    state: Res<TerrainStateMutex>,
+    spawn_tasks: Query<(), With<ChunkSpawnTask>>,
+    remesh_tasks: Query<(), With<ChunkRemeshTask>>,
+) -> bool {
+    let state = state.lock().unwrap();
+    spawn_tasks.is_empty()
+        && remesh_tasks.is_empty()
+        && state.spawn_requests.is_empty()
+        && state.remesh_requests.is_empty()
+        && !state.chunk_data.is_empty()
+}
+
 fn setup(state: Res<TerrainStateMutex>, aabb_query: Query<&ChunksAABB>) {
     let mut chunks = HashSet::<IVec3>::new();
 
@@ -161,6 +393,15 @@ fn setup_material(mut commands: Commands, mut materials: ResMut<Assets<CaveMater
     commands.insert_resource(CaveMaterialHandle(material));
 }
 
+fn update_material_quality(
+    quality: Res<CaveMaterialQuality>,
+    mut materials: ResMut<Assets<CaveMaterial>>,
+) {
+    for (_, material) in materials.iter_mut() {
+        material.extension.quality = *quality;
+    }
+}
+
 fn draw_debug(mut gizmos: Gizmos, chunk_query: Query<&Transform, With<Chunk>>) {
     if CHUNK_RENDER_BORDERS {
         for transform in chunk_query.iter() {
@@ -0,0 +1,341 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use pathfinding::prelude::astar;
+
+use super::{
+    ChunkDespawned, ChunkMeshed, DestroyTerrainEvent, TerrainState, TerrainStateMutex, UpdateChunks,
+};
+use crate::worldgen::consts::CHUNK_SIZE_F;
+
+/// Size of a navigation cell, in world units.
+pub const NAV_CELL_SIZE: f32 = 1.0;
+/// Open cells required above a floor for it to be walkable.
+const NAV_CLEARANCE_CELLS: i32 = 2;
+/// How far positions are snapped to the nearest walkable cell when pathfinding, in cells.
+const NAV_SNAP_CELLS: i32 = 2;
+const CELLS_PER_CHUNK: i32 = (CHUNK_SIZE_F / NAV_CELL_SIZE) as i32;
+/// Destruction only rebakes the tiles it touches, instead of whole chunks. Must divide
+/// [`CELLS_PER_CHUNK`].
+const CELLS_PER_TILE: i32 = 8;
+const TILES_PER_CHUNK: i32 = CELLS_PER_CHUNK / CELLS_PER_TILE;
+
+/// Path costs are integers, these are scaled by 10.
+const COST_STRAIGHT: u32 = 10;
+const COST_DIAGONAL: u32 = 14;
+const COST_STEP: u32 = 4;
+
+/// Walkable cells of every loaded chunk, for ground based AI. A cell is walkable if it's open,
+/// has solid terrain beneath it, and has enough headroom above it. Cells are in global
+/// coordinates, so paths cross chunk borders freely.
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    chunks: HashMap<IVec3, HashSet<IVec3>>,
+    /// Tiles touched by destruction, rebaked when their chunk or one above or below it is
+    /// remeshed.
+    dirty_tiles: HashSet<IVec3>,
+    /// Chunks meshed since the last bake started.
+    meshed: HashSet<IVec3>,
+    /// Chunks being baked for the first time.
+    baking: HashSet<IVec3>,
+}
+
+/// Walkable cells of tiles, baked in the background. Only one bake runs at a time, so they
+/// finish in order.
+#[derive(Component)]
+struct NavBakeTask(Task<Vec<(IVec3, HashSet<IVec3>)>>);
+
+impl NavGrid {
+    pub fn cell(position: Vec3) -> IVec3 {
+        (position / NAV_CELL_SIZE).floor().as_ivec3()
+    }
+
+    pub fn cell_center(cell: IVec3) -> Vec3 {
+        (cell.as_vec3() + 0.5) * NAV_CELL_SIZE
+    }
+
+    fn chunk(cell: IVec3) -> IVec3 {
+        cell.div_euclid(IVec3::splat(CELLS_PER_CHUNK))
+    }
+
+    fn tile(cell: IVec3) -> IVec3 {
+        cell.div_euclid(IVec3::splat(CELLS_PER_TILE))
+    }
+
+    /// Every tile of a chunk.
+    fn chunk_tiles(chunk_pos: IVec3) -> impl Iterator<Item = IVec3> {
+        let range = 0..TILES_PER_CHUNK;
+        range
+            .clone()
+            .flat_map(|x| range.clone().map(move |y| (x, y)))
+            .flat_map(move |(x, y)| range.clone().map(move |z| IVec3::new(x, y, z)))
+            .map(move |local| chunk_pos * TILES_PER_CHUNK + local)
+    }
+
+    /// Tiles of a chunk along its face in a direction.
+    fn face_tiles(chunk_pos: IVec3, direction: IVec3) -> impl Iterator<Item = IVec3> {
+        // Tiles on the face are as far from the chunk's center as possible along the direction
+        Self::chunk_tiles(chunk_pos).filter(move |tile| {
+            let local = *tile - chunk_pos * TILES_PER_CHUNK;
+            (local * 2 - (TILES_PER_CHUNK - 1)).dot(direction) == TILES_PER_CHUNK - 1
+        })
+    }
+
+    /// Marks the tiles overlapping a sphere as dirty, including the cells above it whose
+    /// headroom may change.
+    fn invalidate(&mut self, position: Vec3, radius: f32) {
+        let min = Self::tile(Self::cell(position - radius) - IVec3::Y * NAV_CLEARANCE_CELLS);
+        let max = Self::tile(Self::cell(position + radius) + IVec3::Y);
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.dirty_tiles.insert(IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// Replaces the walkable cells of a tile, if its chunk has been baked.
+    fn replace_tile(&mut self, tile: IVec3, walkable: HashSet<IVec3>) {
+        let min = tile * CELLS_PER_TILE;
+        let max = min + CELLS_PER_TILE;
+        let Some(cells) = self.chunks.get_mut(&Self::chunk(min)) else {
+            return;
+        };
+
+        cells.retain(|cell| cell.cmplt(min).any() || cell.cmpge(max).any());
+        cells.extend(walkable);
+    }
+
+    pub fn is_walkable(&self, cell: IVec3) -> bool {
+        self.chunks
+            .get(&Self::chunk(cell))
+            .is_some_and(|cells| cells.contains(&cell))
+    }
+
+    /// Returns true once the chunk has been baked.
+    pub fn is_baked(&self, chunk_pos: IVec3) -> bool {
+        self.chunks.contains_key(&chunk_pos)
+    }
+
+    /// The closest walkable cell within a couple cells of the position.
+    pub fn nearest_walkable(&self, position: Vec3) -> Option<IVec3> {
+        let origin = Self::cell(position);
+        let range = -NAV_SNAP_CELLS..=NAV_SNAP_CELLS;
+
+        range
+            .clone()
+            .flat_map(|x| range.clone().map(move |y| (x, y)))
+            .flat_map(|(x, y)| range.clone().map(move |z| origin + IVec3::new(x, y, z)))
+            .filter(|cell| self.is_walkable(*cell))
+            .min_by(|a, b| {
+                let a = Self::cell_center(*a).distance_squared(position);
+                let b = Self::cell_center(*b).distance_squared(position);
+                a.total_cmp(&b)
+            })
+    }
+
+    /// Finds a walking path between two positions, returned as the centers of the cells along it.
+    pub fn find_path(&self, from: Vec3, to: Vec3) -> Option<Vec<Vec3>> {
+        let start = self.nearest_walkable(from)?;
+        let goal = self.nearest_walkable(to)?;
+
+        let (cells, _) = astar(
+            &start,
+            |cell| self.successors(*cell),
+            |cell| {
+                // Octile distance, ignoring height
+                let offset = (*cell - goal).abs();
+                let (min, max) = (offset.x.min(offset.z), offset.x.max(offset.z));
+                (max - min) as u32 * COST_STRAIGHT + min as u32 * COST_DIAGONAL
+            },
+            |cell| *cell == goal,
+        )?;
+
+        Some(cells.into_iter().map(Self::cell_center).collect())
+    }
+
+    /// Neighboring walkable cells, including one step up or down.
+    fn successors(&self, cell: IVec3) -> Vec<(IVec3, u32)> {
+        let mut successors = Vec::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                if x == 0 && z == 0 {
+                    continue;
+                }
+                let cost = if x != 0 && z != 0 {
+                    COST_DIAGONAL
+                } else {
+                    COST_STRAIGHT
+                };
+
+                for y in -1..=1 {
+                    let next = cell + IVec3::new(x, y, z);
+                    if self.is_walkable(next) {
+                        successors.push((next, cost + y.unsigned_abs() * COST_STEP));
+                    }
+                }
+            }
+        }
+
+        successors
+    }
+}
+
+pub struct TerrainNavigationPlugin;
+
+impl Plugin for TerrainNavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavGrid>();
+        app.add_systems(
+            Update,
+            (
+                invalidate_navigation,
+                (begin_bake_navigation, receive_bake_navigation)
+                    .chain()
+                    .after(UpdateChunks),
+            ),
+        );
+    }
+}
+
+fn invalidate_navigation(mut grid: ResMut<NavGrid>, mut events: EventReader<DestroyTerrainEvent>) {
+    for event in events.read() {
+        grid.invalidate(event.position, event.radius);
+    }
+}
+
+fn begin_bake_navigation(
+    mut commands: Commands,
+    state: Res<TerrainStateMutex>,
+    mut grid: ResMut<NavGrid>,
+    mut meshed: EventReader<ChunkMeshed>,
+    mut despawned: EventReader<ChunkDespawned>,
+    tasks: Query<(), With<NavBakeTask>>,
+) {
+    let grid = grid.as_mut();
+    for event in despawned.read() {
+        grid.chunks.remove(&event.chunk_pos);
+        grid.baking.remove(&event.chunk_pos);
+        grid.meshed.remove(&event.chunk_pos);
+        grid.dirty_tiles
+            .retain(|tile| NavGrid::chunk(*tile * CELLS_PER_TILE) != event.chunk_pos);
+    }
+    grid.meshed
+        .extend(meshed.read().map(|event| event.chunk_pos));
+
+    if !tasks.is_empty() || grid.meshed.is_empty() {
+        return;
+    }
+
+    let mut tiles = HashSet::<IVec3>::new();
+    for chunk_pos in std::mem::take(&mut grid.meshed) {
+        // Remeshed chunks only rebake the tiles touched by destruction, including the floors
+        // across their top and bottom
+        if grid.is_baked(chunk_pos) {
+            let chunks = [-IVec3::Y, IVec3::ZERO, IVec3::Y].map(|y| chunk_pos + y);
+            tiles.extend(
+                grid.dirty_tiles
+                    .iter()
+                    .copied()
+                    .filter(|tile| chunks.contains(&NavGrid::chunk(*tile * CELLS_PER_TILE))),
+            );
+            continue;
+        }
+
+        grid.baking.insert(chunk_pos);
+        grid.dirty_tiles
+            .retain(|tile| NavGrid::chunk(*tile * CELLS_PER_TILE) != chunk_pos);
+        tiles.extend(NavGrid::chunk_tiles(chunk_pos));
+
+        // Neighbors were stitched to the new chunk, and floors along their top and bottom
+        // depend on it
+        for direction in [IVec3::X, IVec3::Y, IVec3::Z] {
+            for direction in [direction, -direction] {
+                let neighbor = chunk_pos + direction;
+                if grid.is_baked(neighbor) {
+                    tiles.extend(NavGrid::face_tiles(neighbor, -direction));
+                }
+            }
+        }
+    }
+    for tile in tiles.iter() {
+        grid.dirty_tiles.remove(tile);
+    }
+
+    let state = state.clone();
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        tiles
+            .into_iter()
+            .map(|tile| {
+                // Locked per tile, so chunks can still be spawned and queried meanwhile
+                let state = state.lock().unwrap();
+                (
+                    tile,
+                    bake_cells(&state, tile * CELLS_PER_TILE, CELLS_PER_TILE),
+                )
+            })
+            .collect()
+    });
+    commands.spawn(NavBakeTask(task));
+}
+
+fn receive_bake_navigation(
+    mut commands: Commands,
+    mut grid: ResMut<NavGrid>,
+    mut tasks: Query<(Entity, &mut NavBakeTask)>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let Some(tiles) = block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        commands.entity(entity).despawn();
+
+        // Chunks despawned during the bake are left out
+        let grid = grid.as_mut();
+        for chunk_pos in grid.baking.drain() {
+            grid.chunks.insert(chunk_pos, HashSet::new());
+        }
+        for (tile, walkable) in tiles {
+            grid.replace_tile(tile, walkable);
+        }
+    }
+}
+
+/// Walkable cells within a cube of cells.
+fn bake_cells(state: &TerrainState, min: IVec3, size: i32) -> HashSet<IVec3> {
+    let mut walkable = HashSet::new();
+
+    for x in 0..size {
+        for z in 0..size {
+            for y in 0..size {
+                let cell = min + IVec3::new(x, y, z);
+                if is_walkable(state, cell) {
+                    walkable.insert(cell);
+                }
+            }
+        }
+    }
+
+    walkable
+}
+
+/// Terrain that isn't loaded counts as neither open nor solid.
+fn is_walkable(state: &TerrainState, cell: IVec3) -> bool {
+    let center = NavGrid::cell_center(cell);
+    let solid_below = state
+        .distance_at(center - Vec3::Y * NAV_CELL_SIZE)
+        .is_some_and(|distance| distance >= 0.0);
+    if !solid_below {
+        return false;
+    }
+
+    (0..NAV_CLEARANCE_CELLS).all(|i| {
+        state
+            .distance_at(center + Vec3::Y * NAV_CELL_SIZE * i as f32)
+            .is_some_and(|distance| distance < 0.0)
+    })
+}
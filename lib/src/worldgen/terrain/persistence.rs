@@ -0,0 +1,187 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+
+use crate::worldgen::voxel::VoxelMaterial;
+
+use super::{ChunkData, TerrainDetail, TerrainState};
+
+const MAGIC: &[u8; 4] = b"CFCK";
+const VERSION: u8 = 1;
+/// Holds a store for each world seed.
+const WORLD_DIRECTORY: &str = "saves/worlds";
+
+/// Reads and writes modified chunks, keyed by chunk position. Each world seed has a store that
+/// chunks are written to as they unload, and each save game keeps a copy of it from when it was
+/// saved.
+///
+/// Each file holds a small header, the raw SDF, then the materials run-length encoded.
+#[derive(Clone)]
+pub struct ChunkStore {
+    directory: PathBuf,
+}
+
+impl ChunkStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// The store for the world generated with the seed.
+    pub fn for_seed(seed: u64) -> Self {
+        Self::new(PathBuf::from(WORLD_DIRECTORY).join(format!("{seed:016x}")))
+    }
+
+    fn path(&self, chunk_pos: IVec3) -> PathBuf {
+        let IVec3 { x, y, z } = chunk_pos;
+        self.directory.join(format!("{x}_{y}_{z}.chunk"))
+    }
+
+    /// Returns the saved SDF and materials of a chunk, if it was saved with the same detail.
+    pub fn read(
+        &self,
+        chunk_pos: IVec3,
+        detail: TerrainDetail,
+    ) -> anyhow::Result<Option<(Vec<f32>, Vec<VoxelMaterial>)>> {
+        let path = self.path(chunk_pos);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut bytes = Vec::new();
+        File::open(&path)
+            .context("failed to open saved chunk")?
+            .read_to_end(&mut bytes)?;
+
+        let mut reader = ByteReader(&bytes);
+        if reader.take(4)? != MAGIC || reader.u8()? != VERSION {
+            bail!("unrecognized chunk file {}", path.display());
+        }
+        if reader.u8()? != detail as u8 {
+            // The layout changed since this was saved
+            return Ok(None);
+        }
+
+        let len = reader.u32()? as usize;
+        let sdf = (0..len)
+            .map(|_| Ok(f32::from_le_bytes(reader.take(4)?.try_into()?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut materials = Vec::with_capacity(len);
+        while materials.len() < len {
+            let run = reader.u16()? as usize;
+            let material = VoxelMaterial(reader.u8()?);
+            materials.extend(std::iter::repeat(material).take(run));
+        }
+        if materials.len() != len {
+            bail!("corrupt chunk file {}", path.display());
+        }
+
+        Ok(Some((sdf, materials)))
+    }
+
+    pub fn write(&self, data: &ChunkData) -> anyhow::Result<()> {
+        let mut bytes = Vec::with_capacity(data.sdf.len() * 5);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(data.detail as u8);
+        bytes.extend_from_slice(&(data.sdf.len() as u32).to_le_bytes());
+        for distance in data.sdf.iter() {
+            bytes.extend_from_slice(&distance.to_le_bytes());
+        }
+        for run in data.materials.chunk_by(|a, b| a == b) {
+            for run in run.chunks(u16::MAX as usize) {
+                bytes.extend_from_slice(&(run.len() as u16).to_le_bytes());
+                bytes.push(run[0].0);
+            }
+        }
+
+        // Write to a temporary file first so a chunk loaded mid-write is never half saved
+        fs::create_dir_all(&self.directory).context("failed to create save directory")?;
+        let path = self.path(data.chunk_pos);
+        let temporary = path.with_extension("tmp");
+        File::create(&temporary)
+            .context("failed to create saved chunk")?
+            .write_all(&bytes)?;
+        fs::rename(&temporary, &path)?;
+
+        Ok(())
+    }
+
+    /// Forgets a chunk, e.g. when the brushes it was generated from changed.
+    pub fn remove(&self, chunk_pos: IVec3) -> anyhow::Result<()> {
+        let path = self.path(chunk_pos);
+        if path.exists() {
+            fs::remove_file(&path).context("failed to remove saved chunk")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the modified chunks that are loaded. Unloaded chunks were written as they unloaded.
+    pub(crate) fn write_all(&self, state: &TerrainState) -> anyhow::Result<()> {
+        for (data, _) in state.chunk_data.values() {
+            if !data.destruction.is_empty() {
+                self.write(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every chunk.
+    pub fn clear(&self) -> anyhow::Result<()> {
+        if self.directory.exists() {
+            fs::remove_dir_all(&self.directory).context("failed to clear saved chunks")?;
+        }
+        Ok(())
+    }
+
+    /// Replaces every chunk with the chunks of another store.
+    pub fn copy_from(&self, other: &ChunkStore) -> anyhow::Result<()> {
+        self.clear()?;
+        if !other.directory.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.directory).context("failed to create save directory")?;
+        for entry in fs::read_dir(&other.directory)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "chunk")
+            {
+                fs::copy(&path, self.directory.join(path.file_name().unwrap()))
+                    .context("failed to copy saved chunk")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("unexpected end of chunk file");
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
@@ -0,0 +1,303 @@
+use std::f32::consts::PI;
+
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::physics::GameLayer;
+
+use super::TerrainStateMutex;
+
+/// Smallest step taken when marching the SDF, so noisy distances can't stall the march.
+const MIN_MARCH_STEP: f32 = 0.25;
+const MAX_MARCH_STEPS: usize = 128;
+/// Distance used for central differences when estimating a normal from the SDF.
+const NORMAL_EPSILON: f32 = 0.5;
+/// Angle between successive retry offsets, which spreads them evenly around the origin.
+const GOLDEN_ANGLE: f32 = PI * (3.0 - 2.236_068);
+
+pub struct SurfacePlacement {
+    pub position: Vec3,
+    pub normal: Vec3,
+}
+
+impl SurfacePlacement {
+    /// Transform at the surface, facing `forward` as closely as possible. When `align` is true
+    /// the transform's up follows the surface normal, otherwise it stays upright.
+    pub fn transform(&self, forward: Vec3, align: bool) -> Transform {
+        let up = if align { self.normal } else { Vec3::Y };
+        let forward = forward
+            .reject_from(up)
+            .normalize_or(up.any_orthonormal_vector());
+        Transform::from_translation(self.position).looking_to(forward, up)
+    }
+}
+
+/// Finds resting places on the terrain for loot drops, props, and spawners.
+#[derive(SystemParam)]
+pub struct TerrainPlacement<'w, 's> {
+    spatial_query: SpatialQuery<'w, 's>,
+    terrain: Option<Res<'w, TerrainStateMutex>>,
+}
+
+impl TerrainPlacement<'_, '_> {
+    /// Finds the first surface below `origin`, no more than `max_drop` away. Chunk colliders are
+    /// checked first, then the SDF for chunks that are loaded but don't have colliders yet.
+    pub fn place_on_terrain(&self, origin: Vec3, max_drop: f32) -> Option<SurfacePlacement> {
+        let filter = SpatialQueryFilter::from_mask(GameLayer::World);
+        self.raycast(origin, Dir3::NEG_Y, max_drop, &filter)
+            .or_else(|| self.march(origin, Dir3::NEG_Y, max_drop))
+    }
+
+    /// Like [`Self::place_on_terrain`], but in any direction, e.g. to find walls.
+    pub fn place_against_terrain(
+        &self,
+        origin: Vec3,
+        direction: Dir3,
+        max_distance: f32,
+    ) -> Option<SurfacePlacement> {
+        let filter = SpatialQueryFilter::from_mask(GameLayer::World);
+        self.raycast(origin, direction, max_distance, &filter)
+            .or_else(|| self.march(origin, direction, max_distance))
+    }
+
+    /// Like [`Self::place_on_terrain`], but ignores the colliders of the entity being placed.
+    pub fn place_entity_on_terrain(
+        &self,
+        entity: Entity,
+        origin: Vec3,
+        max_drop: f32,
+    ) -> Option<SurfacePlacement> {
+        let filter =
+            SpatialQueryFilter::from_mask(GameLayer::World).with_excluded_entities([entity]);
+        self.raycast(origin, Dir3::NEG_Y, max_drop, &filter)
+            .or_else(|| self.march(origin, Dir3::NEG_Y, max_drop))
+    }
+
+    fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Dir3,
+        max_distance: f32,
+        filter: &SpatialQueryFilter,
+    ) -> Option<SurfacePlacement> {
+        let hit = self
+            .spatial_query
+            .cast_ray(origin, direction, max_distance, true, filter)?;
+
+        // Chunk meshes may be inside out, so make sure the normal faces the ray's origin
+        let normal = if hit.normal.dot(*direction) > 0.0 {
+            -hit.normal
+        } else {
+            hit.normal
+        };
+
+        Some(SurfacePlacement {
+            position: origin + direction * hit.distance,
+            normal,
+        })
+    }
+
+    fn march(&self, origin: Vec3, direction: Dir3, max_distance: f32) -> Option<SurfacePlacement> {
+        let terrain = self.terrain.as_ref()?;
+
+        // Starting inside solid terrain means the origin is embedded
+        let mut distance = terrain.distance_at(origin)?;
+        if distance >= 0.0 {
+            return None;
+        }
+
+        let mut travelled = 0.0;
+        for _ in 0..MAX_MARCH_STEPS {
+            let step = (-distance).max(MIN_MARCH_STEP);
+            if travelled + step > max_distance {
+                return None;
+            }
+
+            let next = origin + direction * (travelled + step);
+            let next_distance = terrain.distance_at(next)?;
+            if next_distance >= 0.0 {
+                // Interpolate between the last two samples to find the surface
+                let t = distance / (distance - next_distance);
+                let position = origin + direction * (travelled + step * t);
+                return Some(SurfacePlacement {
+                    position,
+                    normal: self.sdf_normal(terrain, position).unwrap_or(-*direction),
+                });
+            }
+
+            travelled += step;
+            distance = next_distance;
+        }
+
+        None
+    }
+
+    fn sdf_normal(&self, terrain: &TerrainStateMutex, position: Vec3) -> Option<Vec3> {
+        let gradient = |axis: Vec3| -> Option<f32> {
+            Some(
+                terrain.distance_at(position + axis * NORMAL_EPSILON)?
+                    - terrain.distance_at(position - axis * NORMAL_EPSILON)?,
+            )
+        };
+        let gradient = Vec3::new(gradient(Vec3::X)?, gradient(Vec3::Y)?, gradient(Vec3::Z)?);
+
+        // Open space is negative, so the normal points against the gradient
+        (-gradient).try_normalize()
+    }
+}
+
+//
+// Deferred placement
+//
+
+pub enum PlacementFailure {
+    Despawn,
+    /// Leave the entity where it was spawned.
+    Keep,
+}
+
+pub struct PlacementRetry {
+    pub attempts: usize,
+    /// Seconds between attempts. Terrain near the origin may still be loading.
+    pub interval: f32,
+    /// Later attempts are offset horizontally from the origin by up to this distance.
+    pub scatter: f32,
+    pub on_failure: PlacementFailure,
+}
+
+impl Default for PlacementRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 8,
+            interval: 0.5,
+            scatter: 4.0,
+            on_failure: PlacementFailure::Keep,
+        }
+    }
+}
+
+/// Moves the entity onto the terrain below it, retrying until a surface is found. Works for
+/// children too, e.g. room props and spawnpoints.
+#[derive(Component)]
+pub struct PlaceOnTerrain {
+    pub max_drop: f32,
+    /// Align the entity's up with the surface normal.
+    pub align: bool,
+    /// Distance above the surface to place the entity at. Defaults to how far the entity's
+    /// collider reaches below its origin, which is its half-height if it's centered.
+    pub offset: Option<f32>,
+    pub retry: PlacementRetry,
+}
+
+impl Default for PlaceOnTerrain {
+    fn default() -> Self {
+        Self {
+            max_drop: 64.0,
+            align: false,
+            offset: None,
+            retry: default(),
+        }
+    }
+}
+
+#[derive(Component)]
+struct PlacementAttempts {
+    origin: Vec3,
+    attempts: usize,
+    next_attempt: f32,
+}
+
+pub struct TerrainPlacementPlugin;
+
+impl Plugin for TerrainPlacementPlugin {
+    fn build(&self, app: &mut App) {
+        // Children are placed in world space, so their global transforms need to be up to date
+        app.add_systems(
+            PostUpdate,
+            place_pending.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+fn place_pending(
+    mut commands: Commands,
+    time: Res<Time>,
+    placement: TerrainPlacement,
+    mut pending: Query<(
+        Entity,
+        &PlaceOnTerrain,
+        &mut Transform,
+        &GlobalTransform,
+        Option<&Collider>,
+        Option<&mut PlacementAttempts>,
+    )>,
+) {
+    let now = time.elapsed_secs();
+
+    for (entity, place, mut transform, global_transform, collider, attempts) in pending.iter_mut() {
+        let (origin, attempt) = match &attempts {
+            Some(attempts) if now < attempts.next_attempt => continue,
+            Some(attempts) => (attempts.origin, attempts.attempts),
+            None => (global_transform.translation(), 0),
+        };
+
+        let offset = if attempt == 0 {
+            Vec2::ZERO
+        } else {
+            let fraction = (attempt as f32 / place.retry.attempts.max(1) as f32).sqrt();
+            Vec2::from_angle(attempt as f32 * GOLDEN_ANGLE) * place.retry.scatter * fraction
+        };
+        let candidate = origin + Vec3::new(offset.x, 0.0, offset.y);
+
+        if let Some(surface) = placement.place_entity_on_terrain(entity, candidate, place.max_drop)
+        {
+            let offset = place.offset.unwrap_or_else(|| {
+                collider.map_or(0.0, |collider| {
+                    -collider.aabb(Vec3::ZERO, Quat::IDENTITY).min.y
+                })
+            });
+            let mut placed = surface.transform(*global_transform.forward(), place.align);
+            placed.translation += surface.normal * offset;
+            placed.scale = global_transform.scale();
+
+            // Back into the parent's space, which is the global transform without the local one
+            let parent = GlobalTransform::from(
+                global_transform.affine() * transform.compute_affine().inverse(),
+            );
+            *transform = GlobalTransform::from(placed).reparented_to(&parent);
+
+            let mut commands = commands.entity(entity);
+            commands.remove::<PlaceOnTerrain>();
+            commands.remove::<PlacementAttempts>();
+            continue;
+        }
+
+        if attempt + 1 >= place.retry.attempts {
+            warn!(
+                "no surface found below {origin} after {} attempts",
+                attempt + 1
+            );
+            match place.retry.on_failure {
+                PlacementFailure::Despawn => commands.entity(entity).despawn_recursive(),
+                PlacementFailure::Keep => {
+                    let mut commands = commands.entity(entity);
+                    commands.remove::<PlaceOnTerrain>();
+                    commands.remove::<PlacementAttempts>();
+                }
+            }
+            continue;
+        }
+
+        let next = PlacementAttempts {
+            origin,
+            attempts: attempt + 1,
+            next_attempt: now + place.retry.interval,
+        };
+        match attempts {
+            Some(mut attempts) => *attempts = next,
+            None => {
+                commands.entity(entity).insert(next);
+            }
+        }
+    }
+}
@@ -6,39 +6,90 @@ use bevy::{
     tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
 
-use super::{utility::*, TerrainState, TerrainStateMutex};
+use super::{
+    fast_surface_nets::ndshape::Shape, utility::*, ChunkComposition, ChunkDespawned, ChunkMeshed,
+    TerrainState, TerrainStateMutex, VoxelMaterialRegistry, VoxelMaterials,
+};
+
+/// Dirty regions covering more than this fraction of a chunk are remeshed in full, since
+/// stitching would cost more than it saves.
+pub const PARTIAL_REMESH_MAX_FRACTION: f32 = 0.25;
+
+/// Inclusive box of samples in a chunk's sample space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleBox {
+    pub min: UVec3,
+    pub max: UVec3,
+}
+
+impl SampleBox {
+    pub fn point(sample: UVec3) -> Self {
+        Self {
+            min: sample,
+            max: sample,
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn contains(&self, sample: UVec3) -> bool {
+        sample.cmpge(self.min).all() && sample.cmple(self.max).all()
+    }
+
+    pub fn volume(&self) -> u32 {
+        (self.max - self.min + UVec3::ONE).element_product()
+    }
+}
 
 pub struct ChunkRemeshRequest {
     pub chunk_pos: IVec3,
     pub chunk_entity: Entity,
+    /// Samples changed since the chunk was last meshed. `None` remeshes the whole chunk.
+    pub dirty: Option<SampleBox>,
 }
 
 #[derive(Default, Clone)]
 struct ChunkRemeshParams {
     state: Arc<Mutex<TerrainState>>,
+    materials: Arc<VoxelMaterialRegistry>,
     chunk_pos: IVec3,
+    dirty: Option<SampleBox>,
 }
 
 impl ChunkRemeshParams {
-    fn new(state: Arc<Mutex<TerrainState>>) -> Self {
-        Self { state, ..default() }
+    fn new(state: Arc<Mutex<TerrainState>>, materials: Arc<VoxelMaterialRegistry>) -> Self {
+        Self {
+            state,
+            materials,
+            ..default()
+        }
     }
 
     fn with_request(&self, request: &ChunkRemeshRequest) -> Self {
         let mut clone = self.clone();
         clone.chunk_pos = request.chunk_pos.clone();
+        clone.dirty = request.dirty;
         clone
     }
 }
 
-struct ChunkRemeshResult(Mesh, Collider);
+struct ChunkRemeshResult(Mesh, Collider, ChunkComposition);
 
 #[derive(Component)]
-pub struct ChunkRemeshTask(Task<Option<ChunkRemeshResult>>, Entity);
+pub struct ChunkRemeshTask(Task<Option<ChunkRemeshResult>>, Entity, IVec3);
 
-pub fn begin_remesh_chunks(mut commands: Commands, state: Res<TerrainStateMutex>) {
+pub fn begin_remesh_chunks(
+    mut commands: Commands,
+    state: Res<TerrainStateMutex>,
+    materials: Res<VoxelMaterials>,
+) {
     let task_pool = AsyncComputeTaskPool::get();
-    let params = ChunkRemeshParams::new(state.clone());
+    let params = ChunkRemeshParams::new(state.clone(), materials.0.clone());
     let mut state = state.lock().unwrap();
 
     if state.remesh_requests.len() == 0 {
@@ -48,7 +99,11 @@ pub fn begin_remesh_chunks(mut commands: Commands, state: Res<TerrainStateMutex>
     state.remesh_requests.iter().for_each(|request| {
         let params = params.with_request(&request);
         let task = task_pool.spawn(async move { remesh_chunk(params) });
-        commands.spawn(ChunkRemeshTask(task, request.chunk_entity));
+        commands.spawn(ChunkRemeshTask(
+            task,
+            request.chunk_entity,
+            request.chunk_pos,
+        ));
     });
 
     state.remesh_requests.clear();
@@ -58,6 +113,8 @@ pub fn receive_remesh_chunks(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut remesh_tasks: Query<(Entity, &mut ChunkRemeshTask)>,
+    mut meshed_events: EventWriter<ChunkMeshed>,
+    mut despawned_events: EventWriter<ChunkDespawned>,
 ) {
     for (task_entity, mut task) in remesh_tasks.iter_mut() {
         let status = block_on(future::poll_once(&mut task.0));
@@ -66,14 +123,23 @@ pub fn receive_remesh_chunks(
             continue;
         };
 
-        if let Some(ChunkRemeshResult(mesh, collider)) = result {
+        if let Some(ChunkRemeshResult(mesh, collider, composition)) = result {
             let mut commands = commands.entity(task.1);
+            commands.insert(composition);
             commands.remove::<Collider>();
             commands.insert(collider);
             commands.remove::<Mesh3d>();
             commands.insert(Mesh3d(meshes.add(mesh)));
+            meshed_events.send(ChunkMeshed {
+                chunk_pos: task.2,
+                entity: task.1,
+            });
         } else {
             commands.entity(task.1).clear();
+            despawned_events.send(ChunkDespawned {
+                chunk_pos: task.2,
+                entity: task.1,
+            });
         }
 
         let mut commands = commands.entity(task_entity);
@@ -82,18 +148,30 @@ pub fn receive_remesh_chunks(
 }
 
 fn remesh_chunk(params: ChunkRemeshParams) -> Option<ChunkRemeshResult> {
-    let state = params.state.lock().unwrap();
+    let (meshes, composition) = {
+        let mut state = params.state.lock().unwrap();
+
+        let Some((data, _)) = state.chunk_data.get_mut(&params.chunk_pos) else {
+            if cfg!(debug_assertions) {
+                panic!("tried to remesh nonexistent chunk");
+            }
+            return None;
+        };
 
-    let Some((data, _)) = state.chunk_data.get(&params.chunk_pos) else {
-        if cfg!(debug_assertions) {
-            panic!("tried to remesh nonexistent chunk");
-        }
-        return None;
-    };
+        let chunk_volume = data.shape.size() as f32;
+        let partial = params
+            .dirty
+            .filter(|dirty| dirty.volume() as f32 <= chunk_volume * PARTIAL_REMESH_MAX_FRACTION);
 
-    let Some((mesh, collider)) = mesh_chunk(&data) else {
-        return None;
+        let meshed = match partial {
+            Some(dirty) => remesh_chunk_region(data, dirty, &params.materials),
+            None => mesh_chunk(data, &params.materials),
+        };
+        (meshed?, ChunkComposition::new(data))
     };
 
-    Some(ChunkRemeshResult(mesh, collider))
+    // Spawning and streaming wait on the terrain state, so build the collider after releasing it
+    let collider = meshes.collider();
+
+    Some(ChunkRemeshResult(meshes.render, collider, composition))
 }
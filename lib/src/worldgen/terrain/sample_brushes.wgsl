@@ -0,0 +1,130 @@
+// Merges analytic brushes into the samples of a chunk. Mirrors `TerrainBrush::sample` and
+// `BrushOperation::merge`, see gpu.rs for the layout of each buffer.
+
+const KIND_CURVE: u32 = 0u;
+const KIND_BOX: u32 = 1u;
+const KIND_SPHERE: u32 = 2u;
+const KIND_CAPSULE: u32 = 3u;
+
+const OPERATION_CARVE: u32 = 0u;
+const OPERATION_FILL: u32 = 1u;
+
+const MATERIAL_UNSET: u32 = 255u;
+
+struct Params {
+    // World position of the first sample
+    origin: vec3<f32>,
+    // World distance between samples
+    step: f32,
+    // Samples along each axis
+    size: u32,
+    count: u32,
+    brush_count: u32,
+    _padding: u32,
+}
+
+struct Brush {
+    kind: u32,
+    operation: u32,
+    material: u32,
+    point_start: u32,
+    point_count: u32,
+    smoothness: f32,
+    radius: f32,
+    _padding: f32,
+    translation: vec4<f32>,
+    inverse_rotation: vec4<f32>,
+    half_extents: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> brushes: array<Brush>;
+// Tessellated curves, one point per element
+@group(0) @binding(2) var<storage, read> points: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> sdf: array<f32>;
+@group(0) @binding(4) var<storage, read_write> materials: array<u32>;
+
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return min(a, b);
+    }
+
+    let h = max(k - abs(a - b), 0.0) / k;
+    return min(a, b) - h * h * k * 0.25;
+}
+
+fn segment_distance(p: vec3<f32>, a: vec3<f32>, b: vec3<f32>) -> f32 {
+    let ab = b - a;
+    let t = clamp(dot(p - a, ab) / max(dot(ab, ab), 1e-12), 0.0, 1.0);
+    return distance(p, a + ab * t);
+}
+
+fn curve_distance(brush: Brush, p: vec3<f32>) -> f32 {
+    var closest = distance(p, points[brush.point_start].xyz);
+    for (var i = 1u; i < brush.point_count; i++) {
+        let a = points[brush.point_start + i - 1u].xyz;
+        let b = points[brush.point_start + i].xyz;
+        closest = min(closest, segment_distance(p, a, b));
+    }
+    return closest - brush.radius;
+}
+
+fn brush_distance(brush: Brush, point: vec3<f32>) -> f32 {
+    if brush.kind == KIND_CURVE {
+        return curve_distance(brush, point);
+    }
+
+    let p = rotate(brush.inverse_rotation, point - brush.translation.xyz);
+    if brush.kind == KIND_BOX {
+        let q = abs(p) - brush.half_extents.xyz;
+        return length(max(q, vec3(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+    }
+    if brush.kind == KIND_CAPSULE {
+        let half_height = brush.half_extents.y;
+        let segment = vec3(0.0, clamp(p.y, -half_height, half_height), 0.0);
+        return distance(p, segment) - brush.radius;
+    }
+    return length(p) - brush.radius;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= params.count {
+        return;
+    }
+
+    let x = i % params.size;
+    let y = (i / params.size) % params.size;
+    let z = i / (params.size * params.size);
+    let point = params.origin + vec3(f32(x), f32(y), f32(z)) * params.step;
+
+    var terrain = sdf[i];
+    var material = materials[i];
+    for (var b = 0u; b < params.brush_count; b++) {
+        let brush = brushes[b];
+        let sample = brush_distance(brush, point);
+
+        if brush.operation == OPERATION_CARVE {
+            if sample < terrain || material == MATERIAL_UNSET {
+                material = brush.material;
+            }
+            terrain = smooth_min(terrain, sample, brush.smoothness);
+        } else if brush.operation == OPERATION_FILL {
+            if -sample > terrain {
+                material = brush.material;
+            }
+            terrain = -smooth_min(-terrain, sample, brush.smoothness);
+        } else if sample <= 0.0 {
+            material = brush.material;
+        }
+    }
+
+    sdf[i] = terrain;
+    materials[i] = material;
+}
@@ -12,11 +12,21 @@ use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelI
 use super::{
     boundary::LoadingBoundary,
     change_detection::{TerrainSource, TerrainSourceArc},
+    gpu::{GpuSampling, GpuSdfSampler},
     utility::*,
-    CaveMaterialHandle, Chunk, ChunkData, ChunkRemeshRequest, DestroyTerrain, TerrainState,
-    TerrainStateMutex, CHUNK_SAMPLE_RESOLUTION, CHUNK_SIZE_F,
+    CaveMaterialHandle, Chunk, ChunkComposition, ChunkData, ChunkDespawned, ChunkMeshed,
+    ChunkRemeshRequest, ChunkSpawned, DestroyTerrain, TerrainState, TerrainStateMutex,
+    VoxelMaterialRegistry, VoxelMaterials, CHUNK_SIZE_F,
 };
-use crate::{physics::GameLayer, player::IsPlayer, worldgen::voxel::VoxelMaterial};
+use crate::{
+    physics::GameLayer,
+    player::IsPlayer,
+    worldgen::{brush::TerrainBrush, sdf},
+};
+
+/// Finished chunks inserted per frame. Uploading meshes and adding colliders happens on the main
+/// thread, so a burst of chunks finishing together is spread over several frames.
+const MAX_RECEIVED_PER_FRAME: usize = 4;
 
 #[derive(Default, Clone)]
 pub struct ChunkSpawnRequest {
@@ -30,6 +40,8 @@ struct ChunkSpawnParams {
     state: Arc<Mutex<TerrainState>>,
     request: ChunkSpawnRequest,
     source: Arc<TerrainSource>,
+    materials: Arc<VoxelMaterialRegistry>,
+    gpu: Option<GpuSdfSampler>,
 }
 
 impl ChunkSpawnParams {
@@ -49,6 +61,7 @@ struct ChunkSpawnResult {
     data: ChunkData,
     mesh: Mesh,
     collider: Collider,
+    composition: ChunkComposition,
 }
 
 #[derive(Component)]
@@ -58,14 +71,25 @@ pub struct ChunkSpawnTask {
     boundary: Entity,
 }
 
+impl ChunkSpawnTask {
+    pub fn chunk_pos(&self) -> IVec3 {
+        self.chunk_pos
+    }
+}
+
 pub fn begin_spawn_chunks(
     mut commands: Commands,
     state: Res<TerrainStateMutex>,
     source: Res<TerrainSourceArc>,
+    materials: Res<VoxelMaterials>,
+    gpu: Option<Res<GpuSdfSampler>>,
+    gpu_sampling: Res<GpuSampling>,
     player: Option<Single<&Transform, With<IsPlayer>>>,
     spawn_tasks: Query<&ChunkSpawnTask>,
 ) {
-    let params = ChunkSpawnParams::new(state.clone());
+    let mut params = ChunkSpawnParams::new(state.clone());
+    params.materials = materials.0.clone();
+    params.gpu = gpu.filter(|_| gpu_sampling.enabled).map(|gpu| gpu.clone());
     let mut state = state.lock().unwrap();
 
     if state.spawn_requests.is_empty() {
@@ -116,27 +140,41 @@ pub fn receive_spawn_chunks(
     mut meshes: ResMut<Assets<Mesh>>,
     material: Res<CaveMaterialHandle>,
     mut spawn_tasks: Query<(Entity, &mut ChunkSpawnTask)>,
+    mut spawned_events: EventWriter<ChunkSpawned>,
+    mut meshed_events: EventWriter<ChunkMeshed>,
+    mut despawned_events: EventWriter<ChunkDespawned>,
 ) {
+    let mut received = 0;
     for (task_entity, mut task) in spawn_tasks.iter_mut() {
+        if received >= MAX_RECEIVED_PER_FRAME {
+            break;
+        }
+
         let status = block_on(future::poll_once(&mut task.task));
 
         let Some(result) = status else {
             continue;
         };
+        received += 1;
 
         let mut state = state.lock().unwrap();
 
         if let Some((_, entity)) = state.chunk_data.get(&task.chunk_pos) {
             commands.entity(*entity).clear();
+            despawned_events.send(ChunkDespawned {
+                chunk_pos: task.chunk_pos,
+                entity: *entity,
+            });
         }
 
         if let Some(generated) = result {
-            let scale = Vec3::splat(1.0 / CHUNK_SAMPLE_RESOLUTION);
+            let scale = Vec3::splat(1.0 / generated.data.detail.sample_resolution());
             let half_extents = Vec3A::splat(CHUNK_SIZE_F / 2.0);
             let world_pos = generated.data.world_pos();
 
             let commands = commands.spawn((
                 generated.collider,
+                generated.composition,
                 Chunk,
                 Aabb {
                     center: half_extents,
@@ -150,10 +188,11 @@ pub fn receive_spawn_chunks(
                 MeshMaterial3d(material.0.clone()),
             ));
             let entity = commands.id();
+            let chunk_pos = generated.data.chunk_pos;
 
-            state
-                .chunk_data
-                .insert(generated.data.chunk_pos, (generated.data, entity));
+            state.chunk_data.insert(chunk_pos, (generated.data, entity));
+            spawned_events.send(ChunkSpawned { chunk_pos, entity });
+            meshed_events.send(ChunkMeshed { chunk_pos, entity });
         }
 
         commands.entity(task.boundary).clear();
@@ -162,51 +201,94 @@ pub fn receive_spawn_chunks(
 }
 
 fn spawn_chunks(params: ChunkSpawnParams) -> Option<ChunkSpawnResult> {
-    let mut data = ChunkData::new(params.request.chunk_pos);
+    let detail = params.source.chunk_detail(&params.request.chunk_pos);
+    let mut data = ChunkData::new(params.request.chunk_pos, detail);
     let world_pos = data.world_pos();
 
-    let brushes = params
+    let mut brushes = params
         .source
         .brushes
         .values()
         .filter(|brush| brush.chunks().inflated(1).chunks.contains(&data.chunk_pos))
         .collect::<Vec<_>>();
 
-    data.sdf
-        .par_iter_mut()
-        .zip(&mut data.materials)
-        .enumerate()
-        .for_each(|(i, (distance, material))| {
-            let pos = delinearize_to_world_pos(world_pos, i as u32);
+    // Carve before filling and replacing materials, and blend smooth brushes into sharp ones
+    brushes.sort_by(|a, b| {
+        a.operation()
+            .cmp(&b.operation())
+            .then(a.smoothness().total_cmp(&b.smoothness()))
+    });
 
-            // Sample brushes
-            for brush in brushes.iter() {
-                let sample = brush.sample(pos);
-                if sample.distance < *distance {
-                    *distance = sample.distance;
-                    *material = sample.material;
-                } else if material == &VoxelMaterial::Unset {
-                    *material = sample.material;
-                }
+    // Restore chunks that were modified before they unloaded. Loaded chunks are only generated
+    // again when their brushes change, so what was stored for them is out of date.
+    let store = {
+        let state = params.state.lock().unwrap();
+        state
+            .store
+            .clone()
+            .filter(|_| !state.chunk_data.contains_key(&data.chunk_pos))
+    };
+    let saved = store.and_then(|store| {
+        store
+            .read(data.chunk_pos, detail)
+            .inspect_err(|error| warn!("failed to restore chunk {}: {error:?}", data.chunk_pos))
+            .ok()
+            .flatten()
+    });
+
+    if let Some((sdf, materials)) = saved.filter(|(sdf, _)| sdf.len() == data.sdf.len()) {
+        data.sdf = sdf;
+        data.materials = materials;
+    } else {
+        // Runs of brushes the GPU can sample are merged there, everything else is sampled here
+        let runs = match &params.gpu {
+            Some(_) => brushes
+                .chunk_by(|a, b| GpuSdfSampler::supports(a) == GpuSdfSampler::supports(b))
+                .collect(),
+            None => vec![brushes.as_slice()],
+        };
+        for run in runs {
+            let sampled_on_gpu = params
+                .gpu
+                .as_ref()
+                .filter(|_| GpuSdfSampler::supports(run[0]))
+                .is_some_and(|gpu| {
+                    gpu.sample(&mut data, run)
+                        .inspect_err(|error| {
+                            warn!("failed to sample brushes on the GPU: {error:?}")
+                        })
+                        .is_ok()
+                });
+            if !sampled_on_gpu {
+                sample_brushes(&mut data, run);
             }
+        }
 
-            // Apply material-specific noise
-            *distance += material.sdf_noise(&pos, distance);
-        });
+        // Apply material-specific noise
+        data.sdf
+            .par_iter_mut()
+            .zip(&data.materials)
+            .enumerate()
+            .for_each(|(i, (distance, material))| {
+                let pos = delinearize_to_world_pos(world_pos, detail, i as u32);
+                *distance += material.sdf_noise(&pos, distance);
+            });
+    }
 
     // Apply destruction
     if let Some(destruction) = params.request.destruction {
+        data.destruction = destruction.clone();
         for destroy in destruction.iter() {
-            merge_sdf_with_hardness(&mut data, destroy.force, || {
-                chunk_samples(&world_pos)
-                    .map(|point| point.distance(destroy.position) - destroy.radius)
+            merge_sdf_with_hardness(&mut data, destroy, &params.materials, || {
+                chunk_samples(&world_pos, detail)
+                    .map(|point| sdf::sphere(point, destroy.position, destroy.radius))
                     .collect()
             });
         }
     }
 
-    // Copy borders
-    if params.request.copy_borders {
+    // Copy borders, and stitch seams between chunks of differing detail
+    {
         let mut state = params.state.lock().unwrap();
         let mut remesh_requests = Vec::<ChunkRemeshRequest>::new();
         let neighbors = state.neighbors(&params.request.chunk_pos);
@@ -215,16 +297,28 @@ fn spawn_chunks(params: ChunkSpawnParams) -> Option<ChunkSpawnResult> {
             let Some((neighbor, entity)) = state.chunk_data.get_mut(&neighbor) else {
                 continue;
             };
+            if !params.request.copy_borders && neighbor.detail == data.detail {
+                continue;
+            }
 
-            // Copy borders FROM adjacent chunks
-            copy_borders(&mut data, neighbor);
+            let mut changed = false;
+            if params.request.copy_borders {
+                // Copy borders FROM adjacent chunks
+                copy_borders(&mut data, neighbor);
+
+                // Copy borders TO adjacent chunks
+                changed |= copy_borders(neighbor, &data);
+            }
+
+            // Only the finer chunk of the two is changed
+            stitch_borders(&mut data, neighbor);
+            changed |= stitch_borders(neighbor, &data);
 
-            // Copy borders TO adjacent chunks
-            let changed = copy_borders(neighbor, &data);
             if changed {
                 remesh_requests.push(ChunkRemeshRequest {
                     chunk_pos: neighbor.chunk_pos,
                     chunk_entity: *entity,
+                    dirty: None,
                 });
             }
         }
@@ -232,13 +326,34 @@ fn spawn_chunks(params: ChunkSpawnParams) -> Option<ChunkSpawnResult> {
         state.remesh_requests.extend(remesh_requests);
     }
 
-    let Some((mesh, collider)) = mesh_chunk(&data) else {
+    let Some(meshes) = mesh_chunk(&mut data, &params.materials) else {
         return None;
     };
 
     Some(ChunkSpawnResult {
+        composition: ChunkComposition::new(&data),
         data,
-        mesh,
-        collider,
+        collider: meshes.collider(),
+        mesh: meshes.render,
     })
 }
+
+/// Merges the brushes into every sample of the chunk, in order, on the CPU.
+pub fn sample_brushes(data: &mut ChunkData, brushes: &[&TerrainBrush]) {
+    let world_pos = data.world_pos();
+    let detail = data.detail;
+
+    data.sdf
+        .par_iter_mut()
+        .zip(&mut data.materials)
+        .enumerate()
+        .for_each(|(i, (distance, material))| {
+            let pos = delinearize_to_world_pos(world_pos, detail, i as u32);
+            for brush in brushes.iter() {
+                let sample = brush.sample(pos);
+                brush
+                    .operation()
+                    .merge(sample, brush.smoothness(), distance, material);
+            }
+        });
+}
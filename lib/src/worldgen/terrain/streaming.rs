@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{player::IsPlayer, worldgen::chunk::ChunksAABB};
+
+use super::{
+    change_detection::TerrainSourceArc, ChunkDespawned, ChunkRemeshTask, ChunkSpawnRequest,
+    ChunkSpawnTask, TerrainStateMutex, UpdateChunks, CHUNK_SIZE_F,
+};
+
+/// Keeps terrain loaded around this entity. The player is always a loader, using the radius
+/// from [`TerrainStreamingSettings`].
+#[derive(Component, Clone, Copy)]
+pub struct ChunkLoader {
+    /// In chunks.
+    pub radius: f32,
+}
+
+impl Default for ChunkLoader {
+    fn default() -> Self {
+        Self {
+            radius: TerrainStreamingSettings::default().load_radius,
+        }
+    }
+}
+
+#[derive(Resource, Clone)]
+pub struct TerrainStreamingSettings {
+    /// Chunks closer than this to a loader are loaded, in chunks.
+    pub load_radius: f32,
+    /// Chunks are unloaded once they are this much further than the load radius, so chunks at
+    /// the edge don't flicker in and out.
+    pub unload_margin: f32,
+    /// Time between streaming updates.
+    pub interval: Duration,
+}
+
+impl Default for TerrainStreamingSettings {
+    fn default() -> Self {
+        Self {
+            load_radius: 6.0,
+            unload_margin: 2.0,
+            interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Every chunk overlapped by a brush, i.e. every chunk that could be loaded.
+#[derive(Resource, Default)]
+struct StreamableChunks(HashSet<IVec3>);
+
+pub struct TerrainStreamingPlugin;
+
+impl Plugin for TerrainStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TerrainStreamingSettings>()
+            .init_resource::<StreamableChunks>()
+            .add_systems(
+                Update,
+                (
+                    index_streamable_chunks.run_if(resource_changed::<TerrainSourceArc>),
+                    stream_chunks,
+                )
+                    .chain()
+                    .in_set(UpdateChunks),
+            );
+    }
+}
+
+fn index_streamable_chunks(
+    source: Res<TerrainSourceArc>,
+    mut streamable: ResMut<StreamableChunks>,
+) {
+    streamable.0 = source
+        .0
+        .brushes
+        .values()
+        .flat_map(|brush| brush.chunks().chunks.iter().copied())
+        .collect();
+}
+
+fn chunk_center(chunk_pos: IVec3) -> Vec3 {
+    chunk_pos.as_vec3() + 0.5
+}
+
+fn stream_chunks(
+    mut commands: Commands,
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+    settings: Res<TerrainStreamingSettings>,
+    streamable: Res<StreamableChunks>,
+    state: Res<TerrainStateMutex>,
+    player: Query<&Transform, With<IsPlayer>>,
+    loaders: Query<(&Transform, &ChunkLoader)>,
+    spawn_tasks: Query<&ChunkSpawnTask>,
+    remesh_tasks: Query<(), With<ChunkRemeshTask>>,
+    mut despawned_events: EventWriter<ChunkDespawned>,
+) {
+    timer.set_duration(settings.interval);
+    timer.set_mode(TimerMode::Repeating);
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    // Loader positions and radii, in chunks
+    let loaders: Vec<(Vec3, f32)> = player
+        .iter()
+        .map(|transform| (transform, settings.load_radius))
+        .chain(
+            loaders
+                .iter()
+                .map(|(transform, loader)| (transform, loader.radius)),
+        )
+        .map(|(transform, radius)| (transform.translation / CHUNK_SIZE_F, radius))
+        .collect();
+
+    // Without loaders everything stays loaded, e.g. in the editor
+    if loaders.is_empty() {
+        return;
+    }
+
+    let within = |chunk_pos: IVec3, margin: f32| {
+        loaders.iter().any(|(position, radius)| {
+            chunk_center(chunk_pos).distance(*position) <= radius + margin
+        })
+    };
+
+    let mut state = state.lock().unwrap();
+
+    // Drop requests that are out of range, keeping their destruction for when they load
+    let (requests, dropped): (Vec<_>, Vec<_>) = std::mem::take(&mut state.spawn_requests)
+        .into_iter()
+        .partition(|request| within(request.chunk_pos, 0.0));
+    state.spawn_requests = requests;
+    for request in dropped {
+        if let Some(destruction) = request.destruction {
+            let archived = state.unloaded.entry(request.chunk_pos).or_default();
+            archived.extend(destruction);
+        }
+    }
+
+    // Load chunks that came into range
+    let spawning: HashSet<IVec3> = spawn_tasks
+        .iter()
+        .map(|task| task.chunk_pos())
+        .chain(state.spawn_requests.iter().map(|request| request.chunk_pos))
+        .collect();
+    let mut load = HashSet::<IVec3>::new();
+    for (position, radius) in loaders.iter() {
+        let min = (*position - *radius).floor().as_ivec3();
+        let max = (*position + *radius).ceil().as_ivec3();
+        for chunk_pos in ChunksAABB::chunks(min, max) {
+            if streamable.0.contains(&chunk_pos)
+                && !state.chunk_data.contains_key(&chunk_pos)
+                && !spawning.contains(&chunk_pos)
+                && within(chunk_pos, 0.0)
+            {
+                load.insert(chunk_pos);
+            }
+        }
+    }
+    for chunk_pos in load {
+        let destruction = state.unloaded.remove(&chunk_pos);
+        state.spawn_requests.push(ChunkSpawnRequest {
+            chunk_pos,
+            copy_borders: false,
+            destruction,
+        });
+    }
+
+    // Chunks are only unloaded while nothing is being meshed, since those tasks refer to them
+    if !spawn_tasks.is_empty() || !remesh_tasks.is_empty() || !state.remesh_requests.is_empty() {
+        return;
+    }
+
+    let unload: Vec<IVec3> = state
+        .chunk_data
+        .keys()
+        .filter(|chunk_pos| !within(**chunk_pos, settings.unload_margin))
+        .copied()
+        .collect();
+    for chunk_pos in unload {
+        let Some((data, entity)) = state.chunk_data.remove(&chunk_pos) else {
+            continue;
+        };
+        if !data.destruction.is_empty() {
+            if let Some(store) = &state.store {
+                if let Err(err) = store.write(&data) {
+                    warn!("failed to store chunk {chunk_pos}: {err:?}");
+                }
+            }
+            state.unloaded.insert(chunk_pos, data.destruction.clone());
+        }
+        commands.entity(entity).despawn_recursive();
+        despawned_events.send(ChunkDespawned { chunk_pos, entity });
+    }
+}
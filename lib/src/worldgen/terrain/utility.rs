@@ -3,14 +3,25 @@ use bevy::{
     asset::RenderAssetUsages,
     prelude::*,
     render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+    utils::HashMap,
+};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    IntoParallelRefMutIterator, ParallelIterator,
 };
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::materials::{ATTRIBUTE_VOXEL_RATIO, ATTRIBUTE_VOXEL_TYPE};
+use crate::{
+    materials::{
+        voxel_weights, ATTRIBUTE_SPECIAL_VOXEL_WEIGHTS, ATTRIBUTE_VOXEL_OCCLUSION,
+        ATTRIBUTE_VOXEL_WEIGHTS,
+    },
+    worldgen::sdf,
+};
 
 use super::{
-    fast_surface_nets::{ndshape::ConstShape, surface_nets, SurfaceNetsBuffer},
-    ChunkData, ChunkShape, CHUNK_INTERNAL_GEOMETRY, CHUNK_SAMPLE_RESOLUTION, CHUNK_SAMPLE_SIZE,
+    fast_surface_nets::{ndshape::Shape, surface_nets, SurfaceNetsBuffer, NULL_VERTEX},
+    ChunkData, DestroyTerrain, SampleBox, TerrainDetail, VoxelMaterialRegistry,
+    CHUNK_INTERNAL_GEOMETRY,
 };
 
 pub fn copy_sdf_plane(
@@ -22,7 +33,7 @@ pub fn copy_sdf_plane(
     offset1: u32,
 ) -> bool {
     let mut changed = false;
-    let max = CHUNK_SAMPLE_SIZE + 1;
+    let max = a.max_sample();
 
     for axis_point_0 in 0..=max {
         for axis_point_1 in 0..=max {
@@ -33,8 +44,8 @@ pub fn copy_sdf_plane(
             point1[axis0] = axis_point_0;
             point1[axis1] = axis_point_1;
 
-            let i = ChunkShape::linearize(point0) as usize;
-            let j = ChunkShape::linearize(point1) as usize;
+            let i = a.shape.linearize(point0) as usize;
+            let j = b.shape.linearize(point1) as usize;
 
             if !changed && (a.sdf[i] != b.sdf[j] || a.materials[i] != b.materials[j]) {
                 changed = true;
@@ -48,11 +59,62 @@ pub fn copy_sdf_plane(
     changed
 }
 
+/// Resamples layers of `a` from `b` when the chunks have different resolutions. `layers` are
+/// indices along `axis`.
+fn resample_sdf_layers(
+    a: &mut ChunkData,
+    b: &ChunkData,
+    axis: usize,
+    layers: impl Iterator<Item = u32>,
+) -> bool {
+    let mut changed = false;
+    let max = a.max_sample();
+    let (axis0, axis1) = ((axis + 1) % 3, (axis + 2) % 3);
+    let offset = a.world_pos() - b.world_pos();
+    let scale = b.detail.sample_resolution() / a.detail.sample_resolution();
+
+    for layer in layers {
+        for axis_point_0 in 0..=max {
+            for axis_point_1 in 0..=max {
+                let mut point = [layer; 3];
+                point[axis0] = axis_point_0;
+                point[axis1] = axis_point_1;
+
+                let i = a.shape.linearize(point) as usize;
+                let b_point = UVec3::from_array(point).as_vec3() * scale
+                    + offset * b.detail.sample_resolution();
+                let distance = sample_sdf(b, b_point);
+                let material = b.nearest_material(b_point);
+
+                if !changed && (a.sdf[i] != distance || a.materials[i] != material) {
+                    changed = true;
+                }
+
+                a.sdf[i] = distance;
+                a.materials[i] = material;
+            }
+        }
+    }
+
+    changed
+}
+
 /// Returns true if chunks are adjacent
 pub fn copy_borders(a: &mut ChunkData, b: &ChunkData) -> bool {
     let dir = a.chunk_pos - b.chunk_pos;
     let min = 0;
-    let max = CHUNK_SAMPLE_SIZE + 1;
+    let max = a.max_sample();
+
+    if a.detail != b.detail {
+        let Some(axis) = (0..3).find(|axis| dir[*axis] != 0) else {
+            return false;
+        };
+        if dir.abs().element_sum() != 1 {
+            return false;
+        }
+        let layer = if dir[axis] < 0 { max } else { min };
+        return resample_sdf_layers(a, b, axis, std::iter::once(layer));
+    }
 
     match dir {
         IVec3 { x: -1, y: 0, z: 0 } => copy_sdf_plane(a, &b, 1, 2, max, min + 1),
@@ -65,26 +127,55 @@ pub fn copy_borders(a: &mut ChunkData, b: &ChunkData) -> bool {
     }
 }
 
-pub fn delinearize_to_world_pos(chunk_world_pos: Vec3, sample: u32) -> Vec3 {
-    let [x, y, z] = ChunkShape::delinearize(sample);
+/// Conforms the edge of `a` to a coarser neighbor `b`. Meshes of adjacent chunks overlap by one
+/// cell, so the samples of `a` inside that overlap are replaced by the coarse field. This keeps
+/// both surfaces on the same interpolated shape and hides the seam. Returns true if `a` changed.
+pub fn stitch_borders(a: &mut ChunkData, b: &ChunkData) -> bool {
+    if a.detail <= b.detail {
+        return false;
+    }
+
+    let dir = a.chunk_pos - b.chunk_pos;
+    if dir.abs().element_sum() != 1 {
+        return false;
+    }
+    let Some(axis) = (0..3).find(|axis| dir[*axis] != 0) else {
+        return false;
+    };
+
+    let max = a.max_sample();
+    let ratio = (a.detail.sample_resolution() / b.detail.sample_resolution()) as u32;
+    if dir[axis] < 0 {
+        // `b` starts where the interior of `a` ends
+        resample_sdf_layers(a, b, axis, max - 1..=max)
+    } else {
+        // The overlapping cell of `b` extends past the start of `a`
+        resample_sdf_layers(a, b, axis, 0..=ratio)
+    }
+}
+
+pub fn delinearize_to_world_pos(chunk_world_pos: Vec3, detail: TerrainDetail, sample: u32) -> Vec3 {
+    let [x, y, z] = detail.shape().delinearize(sample);
     let point = Vec3::new(x as f32, y as f32, z as f32);
-    point / CHUNK_SAMPLE_RESOLUTION + chunk_world_pos
+    point / detail.sample_resolution() + chunk_world_pos
 }
 
 pub fn chunk_samples(
     chunk_world_pos: &Vec3,
+    detail: TerrainDetail,
 ) -> rayon::iter::Map<rayon::range::Iter<u32>, impl Fn(u32) -> Vec3> {
     let chunk_world_pos = chunk_world_pos.clone();
-    (0u32..ChunkShape::SIZE)
+    (0u32..detail.shape().size())
         .into_par_iter()
-        .map(move |i| delinearize_to_world_pos(chunk_world_pos, i))
+        .map(move |i| delinearize_to_world_pos(chunk_world_pos, detail, i))
 }
 
 // TODO ensure this can't result in non-manifold geometry
 // TODO consider hardness of the hit material to prevent destroying soft materials behind hard materials
 pub fn merge_sdf_with_hardness<F>(
     data: &mut ChunkData,
-    #[allow(unused)] force: f32,
+    destroy: &DestroyTerrain,
+    materials: &VoxelMaterialRegistry,
     sampler: F,
 ) -> bool
 where
@@ -94,40 +185,294 @@ where
     let new_sdf = sampler();
 
     for (i, distance) in new_sdf.into_iter().enumerate() {
-        if distance < data.sdf[i] {
-            // TODO fix hardness
-            // let hardness = data.materials[i].hardness().multiplier();
-            // let difference = data.sdf[i] - distance;
-            // data.sdf[i] -= difference * force / hardness;
+        changed |= carve_sample(data, i, distance, destroy, materials);
+    }
 
-            data.sdf[i] = distance;
+    changed
+}
 
-            changed = true;
-        }
+/// Carves the destruction into sample `i`, where `distance` is from the sample to the surface of
+/// the destruction. Materials that resist the damage shrink its radius, so applying the same
+/// destruction twice changes nothing. Returns true if the sample changed.
+pub fn carve_sample(
+    data: &mut ChunkData,
+    i: usize,
+    distance: f32,
+    destroy: &DestroyTerrain,
+    materials: &VoxelMaterialRegistry,
+) -> bool {
+    let resistance = materials.get(data.materials[i]).resistance(destroy.damage);
+    let strength = sdf::strength(destroy.force, resistance);
+    let Some(distance) = sdf::carve_with_strength(data.sdf[i], distance, destroy.radius, strength)
+    else {
+        return false;
+    };
+
+    data.sdf[i] = distance;
+
+    true
+}
+
+//
+// Ambient occlusion
+//
+
+/// Number of steps taken away from the surface when estimating occlusion.
+const OCCLUSION_STEPS: usize = 5;
+/// Distance between occlusion steps, in world units.
+const OCCLUSION_STEP_SIZE: f32 = 6.0;
+const OCCLUSION_STRENGTH: f32 = 0.6;
+
+/// Samples the SDF with trilinear interpolation. The point is in sample space.
+pub fn sample_sdf(data: &ChunkData, point: Vec3) -> f32 {
+    let max = data.max_sample() as f32;
+    let point = point.clamp(Vec3::ZERO, Vec3::splat(max));
+    let min = point.floor().min(Vec3::splat(max - 1.0));
+    let t = point - min;
+    let min = min.as_uvec3();
+
+    let corner = |x: u32, y: u32, z: u32| {
+        data.sdf[data.shape.linearize([min.x + x, min.y + y, min.z + z]) as usize]
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), t.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), t.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), t.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), t.x);
+    lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+}
+
+/// Direction from the surface into open space. The point is in sample space.
+fn open_direction(data: &ChunkData, point: Vec3) -> Vec3 {
+    let gradient = Vec3::new(
+        sample_sdf(data, point + Vec3::X) - sample_sdf(data, point - Vec3::X),
+        sample_sdf(data, point + Vec3::Y) - sample_sdf(data, point - Vec3::Y),
+        sample_sdf(data, point + Vec3::Z) - sample_sdf(data, point - Vec3::Z),
+    );
+
+    // Open space is negative
+    -gradient.normalize_or_zero()
+}
+
+/// Estimates how occluded a point on the surface is by marching into open space and comparing
+/// the distance travelled to the distance from the nearest surface. Returns 1.0 when unoccluded.
+fn sdf_occlusion(data: &ChunkData, point: Vec3) -> f32 {
+    let direction = open_direction(data, point);
+    if direction == Vec3::ZERO {
+        return 1.0;
     }
 
-    changed
+    let resolution = data.detail.sample_resolution();
+    let mut occlusion = 0.0;
+    let mut weight = 1.0;
+    for step in 1..=OCCLUSION_STEPS {
+        let travelled = step as f32 * OCCLUSION_STEP_SIZE;
+        let clearance = -sample_sdf(data, point + direction * travelled * resolution);
+        occlusion += (travelled - clearance).max(0.0) / travelled * weight;
+        weight *= 0.5;
+    }
+
+    (1.0 - occlusion * OCCLUSION_STRENGTH).clamp(0.0, 1.0)
 }
 
-pub fn mesh_chunk(data: &ChunkData) -> Option<(Mesh, Collider)> {
-    let mut sdf = data.sdf.clone();
+//
+// Meshing
+//
 
+/// Surface nets output kept with each chunk, so that small edits only remesh the cells they touch.
+#[derive(Default, Clone)]
+pub struct ChunkSurface {
+    positions: Vec<[f32; 3]>,
+    /// Cell that produced each vertex, by its minimum corner.
+    cells: Vec<UVec3>,
+    occlusion: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl ChunkSurface {
+    fn push_vertex(&mut self, position: [f32; 3], cell: UVec3, occlusion: f32) -> u32 {
+        self.positions.push(position);
+        self.cells.push(cell);
+        self.occlusion.push(occlusion);
+        self.positions.len() as u32 - 1
+    }
+}
+
+/// Meshes built from a chunk's surface. The collider is built separately with
+/// [`ChunkMeshes::collider`], so remeshing can release the terrain state first.
+pub struct ChunkMeshes {
+    pub render: Mesh,
+    pub physics: Mesh,
+}
+
+impl ChunkMeshes {
+    pub fn collider(&self) -> Collider {
+        Collider::trimesh_from_mesh(&self.physics).unwrap()
+    }
+}
+
+/// Cell whose edge produced a triangle. Surface nets builds each quad from the cell at an edge
+/// and its neighbors on the negative side, so this is the maximum of the vertex cells.
+fn triangle_cell(cells: impl Iterator<Item = UVec3>) -> UVec3 {
+    cells.fold(UVec3::ZERO, UVec3::max)
+}
+
+fn surface_sdf(data: &ChunkData) -> Vec<f32> {
     if CHUNK_INTERNAL_GEOMETRY {
-        for i in 0..ChunkShape::USIZE {
-            sdf[i] = -sdf[i];
-        }
+        data.sdf.iter().map(|distance| -distance).collect()
+    } else {
+        data.sdf.clone()
     }
+}
+
+/// Meshes the whole chunk and caches its surface for later partial remeshes.
+pub fn mesh_chunk(data: &mut ChunkData, materials: &VoxelMaterialRegistry) -> Option<ChunkMeshes> {
+    let sdf = surface_sdf(data);
 
     let mut buffer = SurfaceNetsBuffer::default();
     surface_nets(
         &sdf,
-        &ChunkShape {},
+        &data.shape,
         [0; 3],
-        [CHUNK_SAMPLE_SIZE + 1; 3],
+        [data.max_sample(); 3],
+        &mut buffer,
+    );
+
+    let occlusion = buffer
+        .positions
+        .par_iter()
+        .map(|position| sdf_occlusion(data, Vec3::from_array(*position)))
+        .collect::<Vec<_>>();
+
+    let surface = ChunkSurface {
+        cells: buffer
+            .surface_points
+            .iter()
+            .map(|p| UVec3::from_array(*p))
+            .collect(),
+        positions: buffer.positions,
+        occlusion,
+        indices: buffer.indices,
+    };
+    let meshed = build_chunk_mesh(data, &surface, materials);
+    data.surface = Some(surface);
+
+    meshed
+}
+
+/// Remeshes only the cells affected by the `dirty` samples and stitches them into the cached
+/// surface. Falls back to a full remesh if the chunk has no cached surface.
+pub fn remesh_chunk_region(
+    data: &mut ChunkData,
+    dirty: SampleBox,
+    materials: &VoxelMaterialRegistry,
+) -> Option<ChunkMeshes> {
+    let Some(old) = data.surface.take() else {
+        return mesh_chunk(data, materials);
+    };
+
+    let last_cell = UVec3::splat(data.max_sample() - 1);
+    // Cells with a dirty corner, whose vertices moved
+    let cells = SampleBox {
+        min: dirty.min.saturating_sub(UVec3::ONE),
+        max: dirty.max.min(last_cell),
+    };
+    // Cells whose quads use a dirty edge or one of the moved vertices
+    let quads = SampleBox {
+        min: cells.min,
+        max: (dirty.max + UVec3::ONE).min(last_cell),
+    };
+
+    // Surface nets needs one extra cell on each side to build the quads at the edge of the region
+    let sdf = surface_sdf(data);
+    let mut buffer = SurfaceNetsBuffer::default();
+    surface_nets(
+        &sdf,
+        &data.shape,
+        quads.min.saturating_sub(UVec3::ONE).to_array(),
+        (quads.max + UVec3::splat(2))
+            .min(UVec3::splat(data.max_sample()))
+            .to_array(),
         &mut buffer,
     );
 
-    if buffer.positions.len() < 3 || buffer.indices.len() < 3 {
+    // Keep everything the edit didn't touch
+    let mut surface = ChunkSurface::default();
+    let mut kept = vec![NULL_VERTEX; old.positions.len()];
+    let mut kept_cells = HashMap::<UVec3, u32>::new();
+    for (i, &cell) in old.cells.iter().enumerate() {
+        if cells.contains(cell) {
+            continue;
+        }
+        kept[i] = surface.push_vertex(old.positions[i], cell, old.occlusion[i]);
+        kept_cells.insert(cell, kept[i]);
+    }
+    for triangle in old.indices.chunks_exact(3) {
+        if quads.contains(triangle_cell(
+            triangle.iter().map(|i| old.cells[*i as usize]),
+        )) {
+            continue;
+        }
+        surface
+            .indices
+            .extend(triangle.iter().map(|i| kept[*i as usize]));
+    }
+
+    // Stitch in the new vertices and quads, reusing kept vertices at the border of the region
+    let fresh = buffer
+        .surface_points
+        .iter()
+        .zip(&buffer.positions)
+        .map(|(point, position)| {
+            let cell = UVec3::from_array(*point);
+            match kept_cells.get(&cell) {
+                Some(index) if !cells.contains(cell) => *index,
+                _ => surface.push_vertex(*position, cell, 1.0),
+            }
+        })
+        .collect::<Vec<_>>();
+    for triangle in buffer.indices.chunks_exact(3) {
+        let cell = triangle_cell(
+            triangle
+                .iter()
+                .map(|i| UVec3::from_array(buffer.surface_points[*i as usize])),
+        );
+        if !quads.contains(cell) {
+            continue;
+        }
+        surface
+            .indices
+            .extend(triangle.iter().map(|i| fresh[*i as usize]));
+    }
+
+    // Occlusion reaches past the edit, so refresh every vertex within marching distance
+    let reach =
+        OCCLUSION_STEPS as f32 * OCCLUSION_STEP_SIZE * data.detail.sample_resolution() + 1.0;
+    let (min, max) = (dirty.min.as_vec3() - reach, dirty.max.as_vec3() + reach);
+    surface
+        .occlusion
+        .par_iter_mut()
+        .zip(&surface.positions)
+        .for_each(|(occlusion, position)| {
+            let position = Vec3::from_array(*position);
+            if position.cmpge(min).all() && position.cmple(max).all() {
+                *occlusion = sdf_occlusion(data, position);
+            }
+        });
+
+    let meshed = build_chunk_mesh(data, &surface, materials);
+    data.surface = Some(surface);
+
+    meshed
+}
+
+fn build_chunk_mesh(
+    data: &ChunkData,
+    surface: &ChunkSurface,
+    materials: &VoxelMaterialRegistry,
+) -> Option<ChunkMeshes> {
+    if surface.positions.len() < 3 || surface.indices.len() < 3 {
         return None;
     }
 
@@ -135,54 +480,40 @@ pub fn mesh_chunk(data: &ChunkData) -> Option<(Mesh, Collider)> {
         PrimitiveTopology::TriangleList,
         RenderAssetUsages::MAIN_WORLD,
     );
-    physics_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, buffer.positions);
-    physics_mesh.insert_indices(Indices::U32(buffer.indices));
+    physics_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, surface.positions.clone());
+    physics_mesh.insert_indices(Indices::U32(surface.indices.clone()));
 
-    let collider = Collider::trimesh_from_mesh(&physics_mesh).unwrap();
-
-    // Unconnected triangles are required to blend voxel types
+    // Vertices stay shared between triangles. Voxel types are blended by interpolating per-vertex
+    // weights, and the shader flat shades each triangle, so neither needs duplicate vertices.
     let mut render_mesh = physics_mesh.clone();
     render_mesh.asset_usage = RenderAssetUsages::all();
-    render_mesh.duplicate_vertices();
-    render_mesh.compute_flat_normals();
-
-    let positions = render_mesh
-        .attribute(Mesh::ATTRIBUTE_POSITION)
-        .unwrap()
-        .as_float3()
-        .unwrap();
-    let voxel_types: Vec<u8> = positions
+    render_mesh.compute_smooth_normals();
+
+    let (voxel_weights, special_voxel_weights): (Vec<_>, Vec<_>) = surface
+        .positions
         .iter()
         .map(|pos| {
-            let index = ChunkShape::linearize([
+            let index = data.shape.linearize([
                 pos[0].floor() as u32,
                 pos[1].floor() as u32,
                 pos[2].floor() as u32,
             ]);
-            data.materials[index as usize] as u8
-        })
-        .collect();
-    let voxel_types: Vec<[u8; 4]> = (0..(positions.len() / 3))
-        .flat_map(|i| {
-            let a = voxel_types[i * 3];
-            let b = voxel_types[i * 3 + 1];
-            let c = voxel_types[i * 3 + 2];
-            vec![[a, b, c, 0], [a, b, c, 0], [a, b, c, 0]]
+            voxel_weights(materials.get(data.materials[index as usize]).texture)
         })
-        .collect();
-    let voxel_ratios: Vec<[f32; 3]> = (0..positions.len())
-        .map(|i| match i % 3 {
-            0 => [1.0, 0.0, 0.0],
-            1 => [0.0, 1.0, 0.0],
-            _ => [0.0, 0.0, 1.0],
-        })
-        .collect();
+        .unzip();
 
-    render_mesh.insert_attribute(ATTRIBUTE_VOXEL_RATIO, voxel_ratios);
+    render_mesh.insert_attribute(ATTRIBUTE_VOXEL_OCCLUSION, surface.occlusion.clone());
+    render_mesh.insert_attribute(
+        ATTRIBUTE_VOXEL_WEIGHTS,
+        VertexAttributeValues::Unorm8x4(voxel_weights),
+    );
     render_mesh.insert_attribute(
-        ATTRIBUTE_VOXEL_TYPE,
-        VertexAttributeValues::Uint8x4(voxel_types),
+        ATTRIBUTE_SPECIAL_VOXEL_WEIGHTS,
+        VertexAttributeValues::Unorm8x4(special_voxel_weights),
     );
 
-    Some((render_mesh, collider))
+    Some(ChunkMeshes {
+        render: render_mesh,
+        physics: physics_mesh,
+    })
 }
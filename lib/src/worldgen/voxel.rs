@@ -1,8 +1,8 @@
 use bevy::prelude::*;
 use noisy_bevy::simplex_noise_3d;
 use serde::{Deserialize, Serialize};
-use strum::EnumProperty;
-use strum_macros::FromRepr;
+
+use super::sdf;
 
 #[derive(Clone, Copy, Debug)]
 pub struct VoxelSample {
@@ -10,7 +10,7 @@ pub struct VoxelSample {
     pub distance: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum VoxelHardness {
     Default,
     Value(f32),
@@ -27,50 +27,199 @@ impl VoxelHardness {
     }
 }
 
+/// How terrain is being destroyed. Materials resist each type differently.
 #[derive(
-    FromRepr, EnumProperty, Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Hash,
+    Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
 )]
-#[repr(u8)]
-pub enum VoxelMaterial {
+pub enum DamageType {
+    Explosive,
     #[default]
-    #[strum(props(Name = "Unset"))]
-    Unset = 255,
+    Kinetic,
+    Drill,
+}
 
-    #[strum(props(Name = "Invalid"))]
-    Invalid = 254,
+/// Identifies a voxel material. The built-in materials are associated constants, and any other
+/// value is a material added through the
+/// [`VoxelMaterialRegistry`](crate::worldgen::terrain::VoxelMaterialRegistry).
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct VoxelMaterial(pub u8);
 
-    #[strum(props(Name = "Boundary"))]
-    Boundary = 253,
+const BUILTIN_MATERIAL_COUNT: usize = 8;
+/// Value, identifier, and display name of each built-in material, in serialization order.
+static BUILTIN_MATERIALS: [(VoxelMaterial, &str, &str); BUILTIN_MATERIAL_COUNT] = [
+    (VoxelMaterial::Unset, "Unset", "Unset"),
+    (VoxelMaterial::Invalid, "Invalid", "Invalid"),
+    (VoxelMaterial::Boundary, "Boundary", "Boundary"),
+    (VoxelMaterial::FakeBoundary, "FakeBoundary", "Fake Boundary"),
+    (VoxelMaterial::BrownRock, "BrownRock", "Brown Rock"),
+    (
+        VoxelMaterial::YellowRock,
+        "YellowRock",
+        "Smooth Yellow Rock",
+    ),
+    (
+        VoxelMaterial::ShinyGreenRock,
+        "ShinyGreenRock",
+        "Shiny Green Rock",
+    ),
+    (VoxelMaterial::Crystal, "Crystal", "Crystal"),
+];
+/// Serialized variant of materials that aren't built in.
+const CUSTOM_MATERIAL_VARIANT: &str = "Custom";
+/// Every serialized variant, by index.
+static MATERIAL_VARIANTS: [&str; BUILTIN_MATERIAL_COUNT + 1] = {
+    let mut variants = [CUSTOM_MATERIAL_VARIANT; BUILTIN_MATERIAL_COUNT + 1];
+    let mut i = 0;
+    while i < BUILTIN_MATERIAL_COUNT {
+        variants[i] = BUILTIN_MATERIALS[i].1;
+        i += 1;
+    }
+    variants
+};
 
-    #[strum(props(Name = "Fake Boundary"))]
-    FakeBoundary = 252,
+#[allow(non_upper_case_globals)]
+impl VoxelMaterial {
+    pub const Unset: Self = Self(255);
+    pub const Invalid: Self = Self(254);
+    pub const Boundary: Self = Self(253);
+    pub const FakeBoundary: Self = Self(252);
+    pub const BrownRock: Self = Self(0);
+    pub const YellowRock: Self = Self(1);
+    pub const ShinyGreenRock: Self = Self(2);
+    pub const Crystal: Self = Self(3);
+}
 
-    #[strum(props(Name = "Brown Rock"))]
-    BrownRock = 0,
+impl Default for VoxelMaterial {
+    fn default() -> Self {
+        Self::Unset
+    }
+}
 
-    #[strum(props(Name = "Smooth Yellow Rock"))]
-    YellowRock = 1,
+impl std::fmt::Display for VoxelMaterial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.builtin() {
+            Some((_, _, name)) => f.write_str(name),
+            None => write!(f, "Material {}", self.0),
+        }
+    }
+}
 
-    #[strum(props(Name = "Shiny Green Rock"))]
-    ShinyGreenRock = 2,
+impl Serialize for VoxelMaterial {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match BUILTIN_MATERIALS
+            .iter()
+            .position(|(material, ..)| material == self)
+        {
+            Some(index) => serializer.serialize_unit_variant(
+                "VoxelMaterial",
+                index as u32,
+                BUILTIN_MATERIALS[index].1,
+            ),
+            None => serializer.serialize_newtype_variant(
+                "VoxelMaterial",
+                BUILTIN_MATERIALS.len() as u32,
+                CUSTOM_MATERIAL_VARIANT,
+                &self.0,
+            ),
+        }
+    }
 }
 
-impl VoxelMaterial {
-    pub fn hardness(&self) -> VoxelHardness {
-        match self {
-            VoxelMaterial::Boundary => VoxelHardness::Unbreakable,
-            VoxelMaterial::FakeBoundary => VoxelHardness::Value(5.0),
-            VoxelMaterial::BrownRock => VoxelHardness::Value(1.5),
-            VoxelMaterial::ShinyGreenRock => VoxelHardness::Value(4.0),
-            _ => VoxelHardness::Default,
+impl<'de> Deserialize<'de> for VoxelMaterial {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{self, EnumAccess, VariantAccess};
+
+        /// A built-in material, or `None` for a custom one.
+        struct Variant(Option<VoxelMaterial>);
+
+        impl<'de> Deserialize<'de> for Variant {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct VariantVisitor;
+
+                impl de::Visitor<'_> for VariantVisitor {
+                    type Value = Variant;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str("a voxel material")
+                    }
+
+                    fn visit_u64<E: de::Error>(self, index: u64) -> Result<Variant, E> {
+                        match BUILTIN_MATERIALS.get(index as usize) {
+                            Some((material, ..)) => Ok(Variant(Some(*material))),
+                            None if index as usize == BUILTIN_MATERIALS.len() => Ok(Variant(None)),
+                            None => Err(E::invalid_value(de::Unexpected::Unsigned(index), &self)),
+                        }
+                    }
+
+                    fn visit_str<E: de::Error>(self, ident: &str) -> Result<Variant, E> {
+                        if ident == CUSTOM_MATERIAL_VARIANT {
+                            return Ok(Variant(None));
+                        }
+                        BUILTIN_MATERIALS
+                            .iter()
+                            .find(|(_, builtin, _)| *builtin == ident)
+                            .map(|(material, ..)| Variant(Some(*material)))
+                            .ok_or_else(|| E::unknown_variant(ident, &MATERIAL_VARIANTS))
+                    }
+
+                    fn visit_bytes<E: de::Error>(self, ident: &[u8]) -> Result<Variant, E> {
+                        let ident = std::str::from_utf8(ident)
+                            .map_err(|_| E::invalid_value(de::Unexpected::Bytes(ident), &self))?;
+                        self.visit_str(ident)
+                    }
+                }
+
+                deserializer.deserialize_identifier(VariantVisitor)
+            }
+        }
+
+        struct MaterialVisitor;
+
+        impl<'de> de::Visitor<'de> for MaterialVisitor {
+            type Value = VoxelMaterial;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a voxel material")
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<VoxelMaterial, A::Error> {
+                match data.variant()? {
+                    (Variant(Some(material)), variant) => {
+                        variant.unit_variant()?;
+                        Ok(material)
+                    }
+                    (Variant(None), variant) => variant.newtype_variant().map(VoxelMaterial),
+                }
+            }
         }
+
+        deserializer.deserialize_enum("VoxelMaterial", &MATERIAL_VARIANTS, MaterialVisitor)
+    }
+}
+
+impl VoxelMaterial {
+    /// Materials that make up rock, as opposed to markers like [`VoxelMaterial::Boundary`].
+    pub fn is_natural(&self) -> bool {
+        self.0 < VoxelMaterial::FakeBoundary.0
+    }
+
+    /// Whether this is one of the associated constants, rather than a material added through the
+    /// registry.
+    pub fn is_builtin(&self) -> bool {
+        self.builtin().is_some()
+    }
+
+    fn builtin(&self) -> Option<&'static (VoxelMaterial, &'static str, &'static str)> {
+        BUILTIN_MATERIALS
+            .iter()
+            .find(|(material, ..)| material == self)
     }
 
     pub fn sdf_noise(&self, point: &Vec3, distance: &f32) -> f32 {
-        let external = *distance >= 0.0;
+        let external = sdf::is_solid(*distance);
         let mut noise = 0.0;
 
-        match self {
+        match *self {
             VoxelMaterial::BrownRock => {
                 if external {
                     noise += simplex_noise_3d(point / 2.0) * 0.25;
@@ -0,0 +1,132 @@
+//! Checks that brushes sampled on the GPU match the same brushes sampled on the CPU.
+//!
+//! This needs a GPU, so it's ignored by default:
+//!
+//!     cargo test -p lib --test gpu -- --ignored
+
+use bevy::{prelude::*, render::RenderPlugin, window::ExitCondition, winit::WinitPlugin};
+use nalgebra::Point3;
+
+use lib::worldgen::{
+    brush::{primitive::BrushPrimitive, BrushOperation, TerrainBrush},
+    terrain::{sample_brushes, ChunkData, GpuSamplingPlugin, GpuSdfSampler, TerrainDetail},
+    voxel::VoxelMaterial,
+};
+
+/// Largest difference between two distances for them to be considered equal.
+const SDF_TOLERANCE: f32 = 1e-3;
+/// Fraction of samples allowed to have a different material, for samples right on a boundary.
+const MATERIAL_TOLERANCE: f32 = 0.001;
+
+fn sampler() -> GpuSdfSampler {
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            })
+            .set(RenderPlugin {
+                synchronous_pipeline_compilation: true,
+                ..default()
+            })
+            .disable::<WinitPlugin>(),
+        GpuSamplingPlugin,
+    ));
+    app.finish();
+    app.cleanup();
+
+    app.world()
+        .get_resource::<GpuSdfSampler>()
+        .expect("compute shaders aren't supported")
+        .clone()
+}
+
+/// One brush of every kind the GPU supports, carving then filling then replacing.
+fn brushes() -> Vec<TerrainBrush> {
+    let points = [
+        Point3::new(4.0, 16.0, 4.0),
+        Point3::new(16.0, 12.0, 16.0),
+        Point3::new(28.0, 16.0, 28.0),
+    ];
+    let primitive = |sequence, material, primitive, translation| {
+        TerrainBrush::primitive(
+            "primitive",
+            sequence,
+            material,
+            primitive,
+            Transform::from_translation(translation).with_rotation(Quat::from_rotation_y(0.5)),
+        )
+    };
+
+    vec![
+        TerrainBrush::curve("curve", 0, VoxelMaterial::BrownRock, &points, 4.0),
+        primitive(
+            1,
+            VoxelMaterial::YellowRock,
+            BrushPrimitive::Box {
+                half_extents: Vec3::new(6.0, 3.0, 4.0),
+            },
+            Vec3::new(16.0, 20.0, 10.0),
+        )
+        .with_smoothness(2.0),
+        primitive(
+            2,
+            VoxelMaterial::ShinyGreenRock,
+            BrushPrimitive::Capsule {
+                half_height: 3.0,
+                radius: 2.0,
+            },
+            Vec3::new(16.0, 14.0, 16.0),
+        )
+        .with_operation(BrushOperation::Fill),
+        TerrainBrush::fill(
+            "fill",
+            3,
+            VoxelMaterial::BrownRock,
+            Vec3::new(6.0, 16.0, 6.0),
+            2.0,
+        ),
+        primitive(
+            4,
+            VoxelMaterial::Crystal,
+            BrushPrimitive::Sphere { radius: 5.0 },
+            Vec3::new(24.0, 16.0, 24.0),
+        )
+        .with_operation(BrushOperation::ReplaceMaterial),
+    ]
+}
+
+#[test]
+#[ignore = "requires a GPU"]
+fn gpu_sampling_matches_cpu() {
+    let sampler = sampler();
+    let brushes = brushes();
+    let brushes: Vec<_> = brushes.iter().collect();
+    assert!(brushes.iter().all(|brush| GpuSdfSampler::supports(brush)));
+
+    let mut cpu = ChunkData::new(IVec3::ZERO, TerrainDetail::Standard);
+    sample_brushes(&mut cpu, &brushes);
+    let mut gpu = ChunkData::new(IVec3::ZERO, TerrainDetail::Standard);
+    sampler.sample(&mut gpu, &brushes).unwrap();
+
+    for (i, (cpu, gpu)) in cpu.sdf().iter().zip(gpu.sdf()).enumerate() {
+        assert!(
+            (cpu - gpu).abs() < SDF_TOLERANCE,
+            "sample {i}: expected {cpu} from the CPU, got {gpu} from the GPU"
+        );
+    }
+
+    let mismatched = cpu
+        .materials()
+        .iter()
+        .zip(gpu.materials())
+        .filter(|(cpu, gpu)| cpu != gpu)
+        .count();
+    let fraction = mismatched as f32 / cpu.materials().len() as f32;
+    assert!(
+        fraction <= MATERIAL_TOLERANCE,
+        "{mismatched} samples have a different material on the GPU"
+    );
+}
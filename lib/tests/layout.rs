@@ -0,0 +1,284 @@
+//! Generates several sequences of rooms headlessly and checks layout invariants.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use avian3d::prelude::*;
+use bevy::{
+    ecs::world::CommandQueue,
+    gizmos::GizmoPlugin,
+    input::InputPlugin,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rand::{plugin::EntropyPlugin, prelude::WyRand};
+
+use lib::{
+    materials::LineMaterial,
+    worldgen::{
+        brush::{
+            TerrainBrush, TerrainBrushPlugin, TerrainBrushRequest, TerrainBrushTask,
+            TerrainBrushTaskEvent,
+        },
+        layout::{
+            consts::ROOM_SHYNESS, InitLayoutCommand, LayoutAssetDirectory, LayoutPlugin,
+            LayoutProgress, LayoutStage, PendingPortalConnection, Portal, PortalConnection, Room,
+            RoomCavity, Sealed, StepLayoutCommand, WorldSeed,
+        },
+        voxel::VoxelMaterial,
+    },
+};
+
+const SEEDS: [u64; 4] = [0, 1, 42, 1337];
+const SEQUENCES: usize = 4;
+const MAX_UPDATES_PER_STEP: usize = 32;
+/// How long each step may wait on rooms being arranged and brushes being processed.
+const BACKGROUND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Uuids of brush tasks that failed and fell back to a placeholder brush.
+#[derive(Resource, Default)]
+struct BrushFallbacks(Vec<String>);
+
+fn app(seed: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        InputPlugin,
+        GizmoPlugin,
+        PhysicsPlugins::default(),
+        EntropyPlugin::<WyRand>::with_seed(seed.to_le_bytes()),
+        LayoutPlugin,
+        TerrainBrushPlugin,
+    ));
+    app.insert_resource(LayoutAssetDirectory(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets"),
+    ));
+    app.init_resource::<BrushFallbacks>();
+    app.add_systems(Update, record_brush_fallbacks);
+    app.init_asset::<Mesh>();
+    app.init_asset::<LineMaterial>();
+    app.init_asset::<StandardMaterial>();
+    app.insert_resource(AmbientLight::default());
+    app.insert_resource(WorldSeed(seed));
+    app.finish();
+    app.cleanup();
+    app.update();
+
+    app
+}
+
+fn record_brush_fallbacks(
+    mut events: EventReader<TerrainBrushTaskEvent>,
+    mut fallbacks: ResMut<BrushFallbacks>,
+) {
+    for event in events.read() {
+        if let TerrainBrushTaskEvent::Finished {
+            uuid,
+            fallback: true,
+            ..
+        } = event
+        {
+            fallbacks.0.push(uuid.clone());
+        }
+    }
+}
+
+fn brushes_in_progress(world: &mut World) -> usize {
+    world
+        .query_filtered::<(), Or<(With<TerrainBrushRequest>, With<TerrainBrushTask>)>>()
+        .iter(world)
+        .count()
+}
+
+fn settle(app: &mut App) {
+    let deadline = Instant::now() + BACKGROUND_TIMEOUT;
+    let mut updates = 0;
+
+    while updates < MAX_UPDATES_PER_STEP {
+        app.update();
+
+        let world = app.world_mut();
+        let arranging = world.resource::<LayoutProgress>().stage == LayoutStage::Arranging;
+
+        // Rooms are arranged and brushes are processed in the background, so updates spent
+        // waiting on them don't count
+        if arranging || brushes_in_progress(world) > 0 {
+            assert!(
+                Instant::now() < deadline,
+                "rooms were not arranged and their brushes processed in time"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        updates += 1;
+
+        let pending = world
+            .query::<&PendingPortalConnection>()
+            .iter(world)
+            .count();
+        if pending == 0 && world.resource::<LayoutProgress>().is_done() {
+            return;
+        }
+    }
+
+    panic!("portal connections did not settle");
+}
+
+fn generate(seed: u64) -> App {
+    let mut app = app(seed);
+
+    app.world_mut().commands().queue(InitLayoutCommand {
+        after: CommandQueue::default(),
+        from_save: None,
+    });
+    app.world_mut().flush();
+    settle(&mut app);
+
+    for _ in 0..SEQUENCES {
+        app.world_mut().commands().queue(StepLayoutCommand);
+        app.world_mut().flush();
+        settle(&mut app);
+    }
+
+    app
+}
+
+fn assert_rooms_reachable(world: &mut World, seed: u64) {
+    let rooms = world
+        .query::<(Entity, &Room)>()
+        .iter(world)
+        .map(|(entity, room)| (entity, room.sequence, room.portals.clone()))
+        .collect::<Vec<_>>();
+    let portal_parents = world
+        .query::<(Entity, &Parent, &Portal)>()
+        .iter(world)
+        .map(|(entity, parent, portal)| (entity, (parent.get(), portal.connection)))
+        .collect::<HashMap<_, _>>();
+    let connections = world
+        .query::<(Entity, &PortalConnection)>()
+        .iter(world)
+        .map(|(entity, connection)| (entity, (connection.from_portal, connection.to_portal)))
+        .collect::<HashMap<_, _>>();
+
+    let start = rooms
+        .iter()
+        .find(|(_, sequence, _)| *sequence == 0)
+        .expect("no initial room")
+        .0;
+    let mut visited = HashSet::from([start]);
+    let mut stack = vec![start];
+
+    while let Some(room) = stack.pop() {
+        let (_, _, portals) = rooms.iter().find(|(entity, ..)| *entity == room).unwrap();
+        for portal in portals {
+            let Some((_, Some(connection))) = portal_parents.get(portal) else {
+                continue;
+            };
+            let (from, to) = connections[connection];
+            for other in [from, to] {
+                let (other_room, _) = portal_parents[&other];
+                if visited.insert(other_room) {
+                    stack.push(other_room);
+                }
+            }
+        }
+    }
+
+    for (entity, sequence, _) in rooms.iter() {
+        assert!(
+            visited.contains(entity),
+            "seed {seed}: room in sequence {sequence} is unreachable"
+        );
+    }
+}
+
+fn assert_rooms_separated(world: &mut World, seed: u64) {
+    let rooms = world
+        .query::<(&Room, &GlobalTransform)>()
+        .iter(world)
+        .map(|(room, transform)| (transform.transform_point(room.center), room.radius))
+        .collect::<Vec<_>>();
+
+    for (i, (a, a_radius)) in rooms.iter().enumerate() {
+        for (b, b_radius) in rooms.iter().skip(i + 1) {
+            let overlap = a_radius + b_radius - a.distance(*b);
+            assert!(
+                overlap <= ROOM_SHYNESS,
+                "seed {seed}: rooms at {a} and {b} overlap by {overlap}"
+            );
+        }
+    }
+}
+
+fn assert_portals_resolved(world: &mut World, seed: u64) {
+    let rooms = world
+        .query::<&Room>()
+        .iter(world)
+        .map(|room| (room.sequence, room.portals.clone()))
+        .collect::<Vec<_>>();
+    let latest = rooms.iter().map(|(sequence, _)| *sequence).max().unwrap();
+
+    for (sequence, portals) in rooms {
+        for portal in portals {
+            let sealed = world.get::<Sealed>(portal).is_some();
+            let portal = world.get::<Portal>(portal).expect("nonexistent portal");
+            if portal.connection.is_some() {
+                assert!(!sealed, "seed {seed}: connected portal was sealed");
+                continue;
+            }
+
+            // Only exits of the latest sequence may still be open, the rest should be sealed.
+            assert!(
+                sealed || (portal.direction.is_exit() && sequence == latest),
+                "seed {seed}: unsealed portal in sequence {sequence}"
+            );
+        }
+    }
+}
+
+fn assert_brushes_valid(world: &mut World, seed: u64) {
+    let fallbacks = &world.resource::<BrushFallbacks>().0;
+    assert!(
+        fallbacks.is_empty(),
+        "seed {seed}: brushes fell back to an invalid placeholder: {fallbacks:?}"
+    );
+
+    // Room cavities are still placeholder brushes, so they're the only ones left invalid
+    for brush in world
+        .query_filtered::<&TerrainBrush, Without<RoomCavity>>()
+        .iter(world)
+    {
+        let invalid = match brush {
+            TerrainBrush::Curve { material, .. }
+            | TerrainBrush::Collider { material, .. }
+            | TerrainBrush::Primitive { material, .. }
+            | TerrainBrush::Fill { material, .. } => *material == VoxelMaterial::Invalid,
+            TerrainBrush::Paint { strokes, .. } => strokes
+                .iter()
+                .any(|stroke| stroke.material == VoxelMaterial::Invalid),
+        };
+        assert!(
+            !invalid,
+            "seed {seed}: brush in sequence {} has an invalid material",
+            brush.sequence()
+        );
+    }
+}
+
+#[test]
+fn layout_invariants() {
+    for seed in SEEDS {
+        let mut app = generate(seed);
+        let world = app.world_mut();
+
+        assert_rooms_reachable(world, seed);
+        assert_rooms_separated(world, seed);
+        assert_portals_resolved(world, seed);
+        assert_brushes_valid(world, seed);
+    }
+}
@@ -0,0 +1,306 @@
+//! Renders generated meshes with a fixed camera and compares them against golden images.
+//!
+//! This needs a GPU, so it's ignored by default:
+//!
+//!     cargo test -p lib --test meshgen -- --ignored
+//!
+//! Missing goldens fail the test, as do renders that don't match. Mismatched renders are written
+//! to the test's temporary directory for inspection. After an intentional change, rebless the
+//! goldens with `BLESS_GOLDENS=1` and commit them.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    core_pipeline::tonemapping::Tonemapping,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+        RenderPlugin,
+    },
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+
+use lib::meshgen::{generate_door_meshes, DoorwaySpec};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+const WARMUP_FRAMES: usize = 8;
+const MAX_CAPTURE_FRAMES: usize = 64;
+
+/// Largest per-channel difference for two pixels to be considered equal.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed to differ before a render is considered a mismatch.
+const PIXEL_TOLERANCE: f32 = 0.005;
+
+//
+// Variants
+//
+
+fn doorway(frame: Vec2, door: Vec2, door_offset: Vec2) -> DoorwaySpec {
+    DoorwaySpec {
+        frame: Rect {
+            min: Vec2::new(-frame.x / 2.0, 0.0),
+            max: Vec2::new(frame.x / 2.0, frame.y),
+        },
+        door: Rect {
+            min: Vec2::new(-door.x / 2.0, 0.0) + door_offset,
+            max: Vec2::new(door.x / 2.0, door.y) + door_offset,
+        },
+        frame_depth: 0.4,
+        door_depth: 0.075,
+        frame_uv_scale: 4.0,
+        door_uv_scale: 4.0,
+    }
+}
+
+/// Meshes to render for each golden, with their translations.
+fn variants() -> Vec<(&'static str, Vec<(Mesh, Vec3)>)> {
+    let doorways = [
+        (
+            "doorway_centered",
+            doorway(vec2(6.0, 4.0), vec2(2.75, 2.25), Vec2::ZERO),
+        ),
+        (
+            "doorway_offset",
+            doorway(vec2(6.0, 4.0), vec2(2.75, 2.25), vec2(0.6, 0.15)),
+        ),
+        (
+            "doorway_narrow",
+            doorway(vec2(3.0, 4.5), vec2(1.25, 3.5), Vec2::ZERO),
+        ),
+        (
+            "doorway_wide",
+            doorway(vec2(8.0, 3.5), vec2(6.0, 2.5), vec2(0.0, 0.25)),
+        ),
+    ];
+
+    doorways
+        .into_iter()
+        .map(|(name, spec)| {
+            let meshes = generate_door_meshes(spec);
+            let mut parts = vec![(meshes.frame_mesh, Vec3::ZERO)];
+            parts.extend(meshes.door_meshes);
+            (name, parts)
+        })
+        .collect()
+}
+
+//
+// Rendering
+//
+
+#[derive(Resource)]
+struct RenderTargetImage(Handle<Image>);
+
+#[derive(Resource, Default)]
+struct Captured(Option<Image>);
+
+#[derive(Component)]
+struct Subject;
+
+fn app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..default()
+            })
+            .set(RenderPlugin {
+                synchronous_pipeline_compilation: true,
+                ..default()
+            })
+            .disable::<WinitPlugin>(),
+        ScheduleRunnerPlugin::default(),
+    ));
+    app.init_resource::<Captured>();
+    app.add_systems(Startup, setup);
+    app.finish();
+    app.cleanup();
+    app.update();
+    app
+}
+
+fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            ..default()
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        default(),
+    );
+    image.texture_descriptor.usage |=
+        TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING;
+    let image = images.add(image);
+
+    commands.spawn((
+        Camera3d::default(),
+        Camera {
+            target: RenderTarget::Image(image.clone()),
+            clear_color: ClearColorConfig::Custom(Color::BLACK),
+            ..default()
+        },
+        Msaa::Off,
+        Tonemapping::None,
+        Transform::from_xyz(5.0, 4.0, 9.0).looking_at(Vec3::new(0.0, 2.0, 0.0), Vec3::Y),
+    ));
+    commands.spawn((
+        DirectionalLight {
+            illuminance: 4000.0,
+            shadows_enabled: false,
+            ..default()
+        },
+        Transform::from_xyz(2.0, 3.0, 4.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+    commands.insert_resource(AmbientLight {
+        color: Color::WHITE,
+        brightness: 400.0,
+    });
+    commands.insert_resource(RenderTargetImage(image));
+}
+
+fn render(app: &mut App, parts: Vec<(Mesh, Vec3)>) -> Image {
+    let world = app.world_mut();
+
+    let subjects = world
+        .query_filtered::<Entity, With<Subject>>()
+        .iter(world)
+        .collect::<Vec<_>>();
+    subjects.into_iter().for_each(|entity| {
+        world.despawn(entity);
+    });
+
+    let material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(StandardMaterial {
+            base_color: Color::srgb(0.8, 0.8, 0.8),
+            perceptual_roughness: 1.0,
+            ..default()
+        });
+    for (mesh, translation) in parts {
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(mesh);
+        world.spawn((
+            Subject,
+            Mesh3d(mesh),
+            MeshMaterial3d(material.clone()),
+            Transform::from_translation(translation),
+        ));
+    }
+
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+
+    let target = app.world().resource::<RenderTargetImage>().0.clone();
+    app.world_mut().spawn(Screenshot::image(target)).observe(
+        |trigger: Trigger<ScreenshotCaptured>, mut captured: ResMut<Captured>| {
+            captured.0 = Some(trigger.event().0.clone());
+        },
+    );
+
+    for _ in 0..MAX_CAPTURE_FRAMES {
+        app.update();
+        if let Some(image) = app.world_mut().resource_mut::<Captured>().0.take() {
+            return image;
+        }
+    }
+
+    panic!("screenshot was not captured");
+}
+
+//
+// Comparison
+//
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/goldens/meshgen")
+        .join(format!("{name}.png"))
+}
+
+fn save(path: &Path, data: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    image::save_buffer(path, data, WIDTH, HEIGHT, image::ExtendedColorType::Rgba8).unwrap();
+}
+
+/// Returns the fraction of pixels that differ by more than the channel tolerance.
+fn difference(a: &[u8], b: &[u8]) -> f32 {
+    let mismatched = a
+        .chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .filter(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    mismatched as f32 / (WIDTH * HEIGHT) as f32
+}
+
+#[test]
+#[ignore = "requires a GPU"]
+fn meshgen_goldens() {
+    let bless = env::var("BLESS_GOLDENS").is_ok_and(|v| v == "1");
+    let mut app = app();
+    let mut failures = Vec::new();
+
+    for (name, parts) in variants() {
+        let actual = render(&mut app, parts);
+        let path = golden_path(name);
+
+        if bless {
+            save(&path, &actual.data);
+            continue;
+        }
+
+        let actual_path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("meshgen")
+            .join(format!("{name}.png"));
+
+        if !path.exists() {
+            save(&actual_path, &actual.data);
+            failures.push(format!(
+                "{name}: missing golden {}, see {}",
+                path.display(),
+                actual_path.display()
+            ));
+            continue;
+        }
+        let golden = match image::open(&path) {
+            Ok(golden) => golden,
+            Err(err) => {
+                failures.push(format!("{name}: failed to open golden: {err}"));
+                continue;
+            }
+        };
+        let golden = golden.to_rgba8();
+        if golden.dimensions() != (WIDTH, HEIGHT) {
+            failures.push(format!("{name}: golden is {:?}", golden.dimensions()));
+            continue;
+        }
+
+        let difference = difference(golden.as_raw(), &actual.data);
+        if difference > PIXEL_TOLERANCE {
+            save(&actual_path, &actual.data);
+            failures.push(format!(
+                "{name}: {:.2}% of pixels differ, see {}",
+                difference * 100.0,
+                actual_path.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,181 @@
+//! Checks that assets saved with older versions are read with their own types.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+
+use lib::worldgen::asset::migration::Versioned;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Shape {
+    Point,
+    Circle(f32),
+    Box { size: (f32, f32) },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Asset {
+    #[serde(default)]
+    version: u32,
+    name: String,
+    shapes: Vec<Shape>,
+    parent: Option<Box<Shape>>,
+    tags: BTreeMap<String, u8>,
+}
+
+impl Versioned for Asset {
+    const VERSION: u32 = 2;
+    const CHANGES: &'static [&'static str] = &["shapes are a list", "renamed title to name"];
+
+    fn deserialize_legacy<'de, D>(version: u32, deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match version {
+            0 => AssetV0::deserialize(deserializer).map(Self::from),
+            1 => AssetV1::deserialize(deserializer).map(Self::from),
+            _ => Self::deserialize(deserializer),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AssetV0 {
+    title: String,
+    #[serde(deserialize_with = "deserialize_shape_tuple")]
+    shapes: Vec<Shape>,
+    parent: Option<Box<Shape>>,
+    tags: BTreeMap<String, u8>,
+}
+
+impl From<AssetV0> for Asset {
+    fn from(asset: AssetV0) -> Self {
+        Self {
+            version: Self::VERSION,
+            name: asset.title,
+            shapes: asset.shapes,
+            parent: asset.parent,
+            tags: asset.tags,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AssetV1 {
+    title: String,
+    shapes: Vec<Shape>,
+    parent: Option<Box<Shape>>,
+    tags: BTreeMap<String, u8>,
+}
+
+impl From<AssetV1> for Asset {
+    fn from(asset: AssetV1) -> Self {
+        Self {
+            version: Self::VERSION,
+            name: asset.title,
+            shapes: asset.shapes,
+            parent: asset.parent,
+            tags: asset.tags,
+        }
+    }
+}
+
+fn deserialize_shape_tuple<'de, D>(deserializer: D) -> Result<Vec<Shape>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ShapeTupleVisitor;
+
+    impl<'de> Visitor<'de> for ShapeTupleVisitor {
+        type Value = Vec<Shape>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a tuple of shapes")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut shapes = Vec::new();
+            while let Some(shape) = seq.next_element()? {
+                shapes.push(shape);
+            }
+            Ok(shapes)
+        }
+    }
+
+    deserializer.deserialize_tuple(usize::MAX, ShapeTupleVisitor)
+}
+
+fn expected() -> Asset {
+    Asset {
+        version: Asset::VERSION,
+        name: "rock \"one\"".to_string(),
+        shapes: vec![
+            Shape::Point,
+            Shape::Circle(1.5),
+            Shape::Box { size: (2.0, -3.0) },
+        ],
+        parent: Some(Box::new(Shape::Circle(2.0))),
+        tags: BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 0x10)]),
+    }
+}
+
+#[test]
+fn reads_unversioned_ron_with_its_own_type() {
+    let ron = r#"
+        #![enable(implicit_some)]
+        // Saved before versioning
+        (
+            title: "rock \"one\"",
+            shapes: (Point, Circle(1.5), Box(size: (2.0, -3.0))), /* a tuple */
+            parent: Circle(2),
+            tags: {"a": 1, "b": 16,},
+        )
+    "#;
+
+    let (asset, changes) = Asset::from_ron(ron).unwrap();
+    assert_eq!(asset, expected());
+    assert_eq!(changes, ["shapes are a list", "renamed title to name"]);
+}
+
+#[test]
+fn reads_older_versions_with_their_own_types() {
+    let ron = r#"
+        Asset(
+            version: 1,
+            title: "rock \"one\"",
+            shapes: [Point, Circle(1.5), Box(size: (2.0, -3.0))],
+            parent: Some(Circle(2)),
+            tags: {"a": 1, "b": 16},
+        )
+    "#;
+
+    let (asset, changes) = Asset::from_ron(ron).unwrap();
+    assert_eq!(asset, expected());
+    assert_eq!(changes, ["renamed title to name"]);
+}
+
+#[test]
+fn reads_current_ron_and_cbor_unchanged() {
+    let asset = expected();
+
+    let ron = ron::ser::to_string_pretty(&asset, ron::ser::PrettyConfig::default()).unwrap();
+    let (from_ron, changes) = Asset::from_ron(&ron).unwrap();
+    assert_eq!(from_ron, asset);
+    assert!(changes.is_empty());
+
+    let cbor = cbor4ii::serde::to_vec(Vec::new(), &asset).unwrap();
+    let (from_cbor, changes) = Asset::from_cbor(&cbor).unwrap();
+    assert_eq!(from_cbor, asset);
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn rejects_newer_versions() {
+    assert!(Asset::from_ron("(version: 3, name: \"\", shapes: [], tags: {})").is_err());
+}
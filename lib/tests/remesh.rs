@@ -0,0 +1,189 @@
+//! Checks that remeshing only the part of a chunk that was destroyed builds the same mesh as
+//! remeshing all of it.
+
+use std::cmp::Ordering;
+
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+use nalgebra::Point3;
+
+use lib::worldgen::{
+    brush::TerrainBrush,
+    terrain::{
+        carve_chunk, mesh_chunk, remesh_chunk_region, sample_brushes, ChunkData, ChunkMeshes,
+        DestroyTerrain, SampleBox, TerrainDetail, VoxelMaterialRegistry,
+        PARTIAL_REMESH_MAX_FRACTION,
+    },
+    voxel::VoxelMaterial,
+};
+
+/// Largest difference between two normals for them to be considered equal. Normals are summed
+/// from the faces around each vertex, which may be visited in a different order.
+const NORMAL_TOLERANCE: f32 = 1e-4;
+
+type Vertex = ([f32; 3], [f32; 3]);
+
+/// A chunk with a tunnel through its middle, meshed in full.
+fn tunneled_chunk(materials: &VoxelMaterialRegistry) -> ChunkData {
+    let points = [
+        Point3::new(-8.0, 16.0, 16.0),
+        Point3::new(16.0, 14.0, 16.0),
+        Point3::new(40.0, 16.0, 16.0),
+    ];
+    let tunnel = TerrainBrush::curve("tunnel", 0, VoxelMaterial::BrownRock, &points, 8.0);
+
+    let mut data = ChunkData::new(IVec3::ZERO, TerrainDetail::Standard);
+    sample_brushes(&mut data, &[&tunnel]);
+    mesh_chunk(&mut data, materials).expect("the tunnel should have a surface");
+    data
+}
+
+/// Every triangle of the mesh, starting from its smallest vertex so the winding is kept, and
+/// sorted so meshes can be compared regardless of vertex and index order.
+fn triangles(meshes: &ChunkMeshes) -> Vec<[Vertex; 3]> {
+    let mesh = &meshes.render;
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        panic!("chunk meshes should have positions");
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        panic!("chunk meshes should have normals");
+    };
+    let Some(Indices::U32(indices)) = mesh.indices() else {
+        panic!("chunk meshes should have u32 indices");
+    };
+
+    let compare = |a: &Vertex, b: &Vertex| {
+        a.0.iter()
+            .zip(&b.0)
+            .map(|(a, b)| a.total_cmp(b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    };
+
+    let mut triangles = indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let mut vertices = [0, 1, 2].map(|i| {
+                (
+                    positions[triangle[i] as usize],
+                    normals[triangle[i] as usize],
+                )
+            });
+            let first = (0..3)
+                .min_by(|a, b| compare(&vertices[*a], &vertices[*b]))
+                .unwrap();
+            vertices.rotate_left(first);
+            vertices
+        })
+        .collect::<Vec<_>>();
+    triangles.sort_by(|a, b| {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    triangles
+}
+
+fn assert_same_triangles(partial: &ChunkMeshes, full: &ChunkMeshes) {
+    let (partial, full) = (triangles(partial), triangles(full));
+    assert_eq!(
+        partial.len(),
+        full.len(),
+        "the partial remesh has a different number of triangles"
+    );
+
+    for (i, (partial, full)) in partial.iter().zip(&full).enumerate() {
+        for ((partial_position, partial_normal), (full_position, full_normal)) in
+            partial.iter().zip(full)
+        {
+            assert_eq!(
+                partial_position, full_position,
+                "triangle {i}: expected {full:?}, got {partial:?}"
+            );
+            let difference = Vec3::from_array(*partial_normal) - Vec3::from_array(*full_normal);
+            assert!(
+                difference.abs().max_element() < NORMAL_TOLERANCE,
+                "triangle {i}: expected normal {full_normal:?}, got {partial_normal:?}"
+            );
+        }
+    }
+}
+
+/// Carves the destruction into two copies of the same chunk, then remeshes one partially and the
+/// other in full. Returns the dirty region so callers can check which side of the partial remesh
+/// limit it's on.
+fn remesh_both(destroy: DestroyTerrain) -> SampleBox {
+    let materials = VoxelMaterialRegistry::default();
+
+    let mut partial = tunneled_chunk(&materials);
+    let dirty = carve_chunk(&mut partial, &destroy, &materials)
+        .expect("the destruction should carve the chunk");
+    let partial = remesh_chunk_region(&mut partial, dirty, &materials)
+        .expect("the partial remesh should have a surface");
+
+    let mut full = tunneled_chunk(&materials);
+    carve_chunk(&mut full, &destroy, &materials);
+    let full = mesh_chunk(&mut full, &materials).expect("the full remesh should have a surface");
+
+    assert_same_triangles(&partial, &full);
+    dirty
+}
+
+fn chunk_volume() -> f32 {
+    ChunkData::new(IVec3::ZERO, TerrainDetail::Standard)
+        .sdf()
+        .len() as f32
+}
+
+#[test]
+fn small_destruction_at_a_corner_matches_full_remesh() {
+    let dirty = remesh_both(DestroyTerrain {
+        position: Vec3::splat(2.0),
+        radius: 5.0,
+        force: 10.0,
+        damage: default(),
+    });
+
+    assert!(
+        dirty.volume() as f32 <= chunk_volume() * PARTIAL_REMESH_MAX_FRACTION,
+        "the destruction should be small enough to be remeshed partially"
+    );
+}
+
+#[test]
+fn small_destruction_at_a_face_matches_full_remesh() {
+    let dirty = remesh_both(DestroyTerrain {
+        position: Vec3::new(16.0, 16.0, 30.0),
+        radius: 4.0,
+        force: 10.0,
+        damage: default(),
+    });
+
+    assert!(
+        dirty.volume() as f32 <= chunk_volume() * PARTIAL_REMESH_MAX_FRACTION,
+        "the destruction should be small enough to be remeshed partially"
+    );
+}
+
+#[test]
+fn large_destruction_at_a_face_matches_full_remesh() {
+    // Larger than the game remeshes partially, but stitching should still be exact
+    let dirty = remesh_both(DestroyTerrain {
+        position: Vec3::new(16.0, 16.0, 32.0),
+        radius: 24.0,
+        force: 10.0,
+        damage: default(),
+    });
+
+    assert!(
+        dirty.volume() as f32 > chunk_volume() * PARTIAL_REMESH_MAX_FRACTION,
+        "the destruction should be too large to be remeshed partially"
+    );
+}
@@ -0,0 +1,113 @@
+//! Checks the semantics of the SDF math shared by brushes, destruction, and chunk generation.
+
+use bevy::prelude::*;
+
+use lib::worldgen::sdf;
+
+const EPSILON: f32 = 1e-5;
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < EPSILON, "expected {b}, got {a}");
+}
+
+#[test]
+fn shapes_are_negative_inside() {
+    assert_close(sdf::sphere(Vec3::ZERO, Vec3::ZERO, 2.0), -2.0);
+    assert_close(sdf::sphere(Vec3::X * 3.0, Vec3::ZERO, 2.0), 1.0);
+    assert_close(
+        sdf::sphere(Vec3::new(1.0, 2.0, 0.0), Vec3::Y * 2.0, 1.0),
+        0.0,
+    );
+
+    assert_close(sdf::cuboid(Vec3::ZERO, Vec3::ONE), -1.0);
+    assert_close(sdf::cuboid(Vec3::X * 3.0, Vec3::ONE), 2.0);
+    assert_close(sdf::cuboid(Vec3::splat(2.0), Vec3::ONE), 3f32.sqrt());
+
+    // The flat sides of a capsule are a cylinder, and the ends are hemispheres
+    assert_close(sdf::capsule(Vec3::ZERO, 1.0, 0.5), -0.5);
+    assert_close(sdf::capsule(Vec3::new(1.0, 0.5, 0.0), 1.0, 0.5), 0.5);
+    assert_close(sdf::capsule(Vec3::Y * 2.0, 1.0, 0.5), 0.5);
+}
+
+#[test]
+fn signed_distances_never_touch_the_surface_inside() {
+    assert_close(sdf::signed(2.0, false), 2.0);
+    assert_close(sdf::signed(2.0, true), -2.0);
+    assert_close(sdf::signed(0.0, true), -sdf::MIN_INSIDE_DISTANCE);
+    assert!(sdf::signed(0.0, true) < 0.0);
+}
+
+#[test]
+fn inflation_grows_by_one_voxel() {
+    assert_close(sdf::inflate(2.0), 2.0 + sdf::INFLATION);
+    assert!(sdf::INFLATION > 0.0);
+}
+
+#[test]
+fn smooth_min_matches_min_outside_smoothness() {
+    assert_close(sdf::smooth_min(1.0, 2.0, 0.0), 1.0);
+    assert_close(sdf::smooth_min(1.0, 5.0, 2.0), 1.0);
+    assert_close(sdf::smooth_min(5.0, 1.0, 2.0), 1.0);
+
+    // Close values are pulled below both
+    let blended = sdf::smooth_min(1.0, 1.0, 2.0);
+    assert_close(blended, 0.5);
+    assert!(blended < 1.0);
+}
+
+#[test]
+fn carving_opens_and_filling_closes() {
+    // Carving takes the most open of the two
+    assert_close(sdf::carve(3.0, -1.0, 0.0), -1.0);
+    assert_close(sdf::carve(-2.0, 1.0, 0.0), -2.0);
+
+    // Filling makes the inside of the shape solid
+    assert_close(sdf::fill(-2.0, -1.0, 0.0), 1.0);
+    assert_close(sdf::fill(3.0, 1.0, 0.0), 3.0);
+
+    // Smoothing only ever carves more or fills more
+    assert!(sdf::carve(1.0, 1.0, 2.0) < 1.0);
+    assert!(sdf::fill(-1.0, 1.0, 2.0) > -1.0);
+}
+
+#[test]
+fn hardness_shrinks_destruction() {
+    assert_close(sdf::strength(2.0, 1.0), 1.0);
+    assert_close(sdf::strength(1.0, 4.0), 0.25);
+
+    // Full strength carves the sphere as is
+    assert_eq!(sdf::carve_with_strength(1.0, -2.0, 3.0, 1.0), Some(-2.0));
+
+    // Half strength shrinks the radius by half
+    assert_eq!(sdf::carve_with_strength(1.0, -2.0, 3.0, 0.5), Some(-0.5));
+
+    // Samples that are already more open don't change, so carving is idempotent
+    assert_eq!(sdf::carve_with_strength(-2.0, -2.0, 3.0, 1.0), None);
+    assert_eq!(sdf::carve_with_strength(-3.0, -2.0, 3.0, 1.0), None);
+}
+
+#[test]
+fn noise_is_only_applied_to_solid_samples() {
+    assert!(sdf::is_solid(0.0));
+    assert!(sdf::is_solid(1.0));
+    assert!(!sdf::is_solid(-0.5));
+}
+
+#[test]
+fn paint_thins_out_with_strength() {
+    // Full strength paints everything inside, and nothing is painted outside
+    assert!(sdf::paint(-0.1, 2.0, 1.0, 0.99) < 0.0);
+    assert!(sdf::paint(0.5, 2.0, 1.0, 0.0) > 0.0);
+
+    // No strength paints nothing
+    assert!(sdf::paint(-2.0, 2.0, 0.0, 0.0) > 0.0);
+
+    // Coverage doubles from the edge to the center
+    assert!(sdf::paint(-0.01, 2.0, 0.4, 0.5) > 0.0);
+    assert!(sdf::paint(-2.0, 2.0, 0.4, 0.5) < 0.0);
+
+    // Dither is stable and in range
+    let point = Vec3::new(1.3, -4.2, 7.9);
+    assert_eq!(sdf::dither(point), sdf::dither(point));
+    assert!((0.0..1.0).contains(&sdf::dither(point)));
+}